@@ -0,0 +1,53 @@
+//! Per-message latency of acoustic decoding in a streaming receiver.
+//!
+//! `cargo bench --features audio-core --bench acoustic_decode`. A receiver
+//! keeps one decoder for the whole stream; a stateless caller (like the
+//! WASM binding) builds a decoder per message. With `audio-fixed` as well,
+//! the integer-only decoder is timed on the same messages as 16-bit PCM.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use aill::audio::{AcousticDecoder, AcousticEncoder};
+use aill::testing::generate_utterance;
+
+const MESSAGES: usize = 40;
+
+fn report(label: &str, elapsed: Duration) {
+    println!("{:<28} {:>8.3} ms/message", label, elapsed.as_secs_f64() * 1e3 / MESSAGES as f64);
+}
+
+fn main() {
+    let encoder = AcousticEncoder::new();
+    let messages: Vec<Vec<f32>> = (0..MESSAGES as u64)
+        .map(|seed| encoder.encode(&generate_utterance(seed, 1)).unwrap().samples)
+        .collect();
+
+    let decoder = AcousticDecoder::new();
+    decoder.decode(&messages[0]).unwrap();
+    let start = Instant::now();
+    for samples in &messages {
+        black_box(decoder.decode(black_box(samples)).unwrap());
+    }
+    report("one decoder per stream", start.elapsed());
+
+    let start = Instant::now();
+    for samples in &messages {
+        black_box(AcousticDecoder::new().decode(black_box(samples)).unwrap());
+    }
+    report("one decoder per message", start.elapsed());
+
+    #[cfg(feature = "audio-fixed")]
+    {
+        let pcm: Vec<Vec<i16>> = messages
+            .iter()
+            .map(|m| m.iter().map(|&s| (s.clamp(-1.0, 1.0) * 32767.0).round() as i16).collect())
+            .collect();
+        let decoder = aill::audio::Q15Decoder::new();
+        let start = Instant::now();
+        for samples in &pcm {
+            black_box(decoder.decode(black_box(samples)).unwrap());
+        }
+        report("integer-only decoder", start.elapsed());
+    }
+}
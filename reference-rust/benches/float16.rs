@@ -0,0 +1,54 @@
+//! Batched against one-at-a-time float16 conversion.
+//!
+//! `cargo bench --bench float16`; figures are nanoseconds per value.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use aill::{decode_f16_slice, decode_float16, encode_f16_slice, encode_float16};
+
+const VALUES: usize = 4096;
+const ROUNDS: u32 = 2000;
+
+fn time(label: &str, mut f: impl FnMut()) {
+    f();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    let per_value = start.elapsed().as_nanos() as f64 / (ROUNDS as f64 * VALUES as f64);
+    println!("{:<24} {:>8.3} ns/value", label, per_value);
+}
+
+fn main() {
+    let values: Vec<f32> = (0..VALUES).map(|i| (i as f32 * 0.618).sin() * 100.0).collect();
+    let mut wire = Vec::with_capacity(VALUES * 2);
+    encode_f16_slice(&values, &mut wire);
+    let mut bytes = Vec::with_capacity(VALUES * 2);
+    let mut decoded = Vec::with_capacity(VALUES);
+
+    time("encode one at a time", || {
+        bytes.clear();
+        for &v in black_box(&values) {
+            bytes.extend_from_slice(&encode_float16(v));
+        }
+        black_box(&bytes);
+    });
+    time("encode_f16_slice", || {
+        bytes.clear();
+        encode_f16_slice(black_box(&values), &mut bytes);
+        black_box(&bytes);
+    });
+    time("decode one at a time", || {
+        decoded.clear();
+        for pair in black_box(&wire).chunks_exact(2) {
+            decoded.push(decode_float16([pair[0], pair[1]]));
+        }
+        black_box(&decoded);
+    });
+    time("decode_f16_slice", || {
+        decoded.clear();
+        decode_f16_slice(black_box(&wire), &mut decoded).unwrap();
+        black_box(&decoded);
+    });
+}
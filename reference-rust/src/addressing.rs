@@ -0,0 +1,218 @@
+//! Multicast and broadcast destinations (COMM-1 MULTICAST / BROADCAST).
+//!
+//! DEST_AGENT names a single recipient. Larger audiences are tagged at the
+//! start of the body:
+//!
+//! ```text
+//! ESCAPE_L1 MULTICAST STRUCT{0x0000: LIST[n](BYTES(16) ...)}
+//! ESCAPE_L1 BROADCAST
+//! ```
+//!
+//! The decoder leaves these tags in the body. [`lift_tags`] copies them
+//! into [`MetaHeader::dest_agents`] and [`MetaHeader::broadcast`];
+//! [`is_addressed_to`] reads them from the body directly.
+
+use crate::agent::AgentId;
+use crate::ast::{AstNode, LiteralValue, MetaHeader};
+use crate::codebook::base::esc;
+use crate::codebook::comm::COMM1_REGISTRY_ID;
+use crate::decoder::AILLDecoder;
+use crate::encoder::AILLEncoder;
+
+pub const MULTICAST: u16 = 0x0021;
+pub const BROADCAST: u16 = 0x0022;
+
+impl AILLEncoder {
+    /// Address the utterance to several agents via a MULTICAST tag. Call
+    /// directly after `start_utterance*`.
+    pub fn dest_agents(&mut self, agents: &[AgentId]) -> &mut Self {
+        self.l1_ref(MULTICAST).begin_struct().field(0x0000).begin_list(agents.len() as u16);
+        for id in agents {
+            self.bytes(id.as_bytes());
+        }
+        self.end_list().end_struct()
+    }
+
+    /// Address the utterance to every agent in range via a BROADCAST tag.
+    /// Call directly after `start_utterance*`.
+    pub fn broadcast(&mut self) -> &mut Self {
+        self.l1_ref(BROADCAST)
+    }
+}
+
+impl MetaHeader {
+    /// Whether `agent` is a recipient. Utterances without any addressing
+    /// are treated as broadcasts.
+    pub fn is_addressed_to(&self, agent: &AgentId) -> bool {
+        if self.broadcast || (self.dest_agent.is_none() && self.dest_agents.is_empty()) {
+            return true;
+        }
+        self.dest_agent.as_ref() == Some(agent) || self.dest_agents.contains(agent)
+    }
+}
+
+/// Whether a decoded utterance is addressed to `agent`, counting its
+/// MULTICAST and BROADCAST tags whether or not they were lifted.
+pub fn is_addressed_to(ast: &AstNode, agent: &AgentId) -> bool {
+    match ast {
+        AstNode::Utterance { meta, body } => {
+            let mut meta = meta.clone();
+            lift(&mut meta, body);
+            meta.is_addressed_to(agent)
+        }
+        _ => false,
+    }
+}
+
+/// Keep only the utterances addressed to `agent`.
+pub fn filter_addressed_to<'a, I>(utterances: I, agent: &'a AgentId) -> impl Iterator<Item = &'a AstNode>
+where
+    I: IntoIterator<Item = &'a AstNode>,
+{
+    utterances.into_iter().filter(move |ast| is_addressed_to(ast, agent))
+}
+
+/// Whether the utterance starting with `prefix` is addressed to `agent`,
+/// judged from DEST_AGENT and a leading BROADCAST tag without decoding the
+/// body. `None` if the prefix is too short to tell or the utterance may name
+/// its recipients in a MULTICAST tag.
+pub fn peek_addressed_to(prefix: &[u8], agent: &AgentId) -> Option<bool> {
+    let (meta, len) = AILLDecoder::new().decode_meta_prefix(prefix).ok()?;
+    // A cut-off header could continue with DEST_AGENT, so look past it.
+    let body = prefix.get(len..len + 3)?;
+    if meta.dest_agent.as_ref() == Some(agent) || body == [esc::ESCAPE_L1, 0x00, BROADCAST as u8] {
+        return Some(true);
+    }
+    if body[0] == esc::ESCAPE_L1 {
+        // Some other leading tag; MULTICAST or BROADCAST may follow it.
+        return None;
+    }
+    Some(meta.dest_agent.is_none())
+}
+
+/// Copy the COMM-1 MULTICAST / BROADCAST tags of a decoded utterance into
+/// its meta header.
+pub fn lift_tags(ast: &mut AstNode) {
+    if let AstNode::Utterance { meta, body } = ast {
+        lift(meta, body);
+    }
+}
+
+/// Copy COMM-1 MULTICAST / BROADCAST tags from the leading body items into
+/// `meta`.
+fn lift(meta: &mut MetaHeader, body: &[AstNode]) {
+    let mut i = 0;
+    while let Some(AstNode::DomainRef { level: 1, domain_code, registry }) = body.get(i) {
+        let comm1 = registry.is_none_or(|r| r == COMM1_REGISTRY_ID);
+        match (*domain_code, body.get(i + 1)) {
+            (BROADCAST, _) if comm1 => {
+                meta.broadcast = true;
+                i += 1;
+            }
+            (MULTICAST, Some(AstNode::Struct { fields })) if comm1 => {
+                if let Some(AstNode::List { elements, .. }) = fields.get(&0x0000) {
+                    meta.dest_agents = elements
+                        .iter()
+                        .filter_map(|e| match e {
+                            AstNode::Literal { value: LiteralValue::Bytes(b), .. } => AgentId::try_from(b.as_slice()).ok(),
+                            _ => None,
+                        })
+                        .collect();
+                }
+                i += 2;
+            }
+            // Skip other leading tags (MSG_ID, MESH_ROUTE, ...) and their value.
+            (_, Some(AstNode::DomainRef { .. })) | (_, None) => i += 1,
+            _ => i += 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLDecoder;
+
+    const A: AgentId = AgentId::new([0xA; 16]);
+    const B: AgentId = AgentId::new([0xB; 16]);
+    const C: AgentId = AgentId::new([0xC; 16]);
+
+    fn decode(e: &mut AILLEncoder) -> AstNode {
+        let mut ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        lift_tags(&mut ast);
+        ast
+    }
+
+    #[test]
+    fn multicast_lifted_into_meta() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().msg_id(1).dest_agents(&[A, B]).command().l1_ref(0x0000);
+        let wire = e.end_utterance();
+        let mut ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        lift_tags(&mut ast);
+        let AstNode::Utterance { meta, .. } = &ast else { panic!() };
+        assert_eq!(meta.dest_agents, vec![A, B]);
+        assert!(!meta.broadcast);
+        assert!(is_addressed_to(&ast, &A));
+        assert!(!is_addressed_to(&ast, &C));
+
+        // Tags are read from the body when they were not lifted
+        let unlifted = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        let AstNode::Utterance { meta, .. } = &unlifted else { panic!() };
+        assert!(meta.dest_agents.is_empty());
+        assert!(!is_addressed_to(&unlifted, &C));
+    }
+
+    #[test]
+    fn broadcast_and_unaddressed_reach_everyone() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().broadcast().warn().l1_ref(0x0000);
+        let bcast = decode(&mut e);
+        let AstNode::Utterance { meta, .. } = &bcast else { panic!() };
+        assert!(meta.broadcast);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().float32(1.0);
+        let plain = decode(&mut e);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(B), None).assert_().float32(1.0);
+        let directed = decode(&mut e);
+
+        let all = [bcast, plain, directed];
+        assert_eq!(filter_addressed_to(&all, &A).count(), 2);
+        assert_eq!(filter_addressed_to(&all, &B).count(), 3);
+    }
+
+    #[test]
+    fn destination_peeked_from_prefix() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(B), None).assert_().float32(1.0);
+        let directed = e.end_utterance();
+        assert_eq!(peek_addressed_to(&directed, &A), Some(false));
+        assert_eq!(peek_addressed_to(&directed, &B), Some(true));
+        assert_eq!(peek_addressed_to(&directed[..32], &A), None);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(B), None).broadcast().assert_().float32(1.0);
+        assert_eq!(peek_addressed_to(&e.end_utterance(), &A), Some(true));
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().dest_agents(&[B]).assert_().float32(1.0);
+        assert_eq!(peek_addressed_to(&e.end_utterance(), &A), None);
+    }
+
+    #[test]
+    fn other_registries_codes_are_not_tags() {
+        use crate::codebook::DIAG1;
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().broadcast().dest_agents(&[B]).assert_().float32(1.0);
+        let diag = AILLDecoder::with_config(crate::DecoderConfig::new().bind_escape(1, DIAG1.registry_id));
+        let mut ast = diag.decode_utterance(&e.end_utterance()).unwrap();
+        lift_tags(&mut ast);
+        let AstNode::Utterance { meta, .. } = ast else { panic!() };
+        assert!(!meta.broadcast);
+        assert!(meta.dest_agents.is_empty());
+    }
+}
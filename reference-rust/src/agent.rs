@@ -0,0 +1,175 @@
+//! 16-byte agent identifiers.
+//!
+//! SOURCE_AGENT, DEST_AGENT and the COMM-1 addressing tags all carry a
+//! 16-byte UUID. [`AgentId`] wraps those bytes and formats them as a
+//! hyphenated UUID:
+//!
+//! ```
+//! use aill::AgentId;
+//!
+//! let id: AgentId = "6ba7b810-9dad-11d1-80b4-00c04fd430c8".parse().unwrap();
+//! assert_eq!(id.short(), "6ba7b810");
+//! assert_eq!(id.to_string(), "6ba7b810-9dad-11d1-80b4-00c04fd430c8");
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::AILLError;
+
+/// A 16-byte agent UUID. Serializes as its raw bytes.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "ast-serde", serde(transparent))]
+pub struct AgentId([u8; 16]);
+
+impl AgentId {
+    /// The all-zero id.
+    pub const NIL: AgentId = AgentId([0; 16]);
+
+    pub const fn new(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    /// A random version 4 UUID.
+    #[cfg(feature = "rand")]
+    pub fn new_v4() -> Self {
+        let mut bytes: [u8; 16] = rand::random();
+        bytes[6] = (bytes[6] & 0x0F) | 0x40;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+        Self(bytes)
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == [0; 16]
+    }
+
+    /// All 32 hex digits, without hyphens.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// The first 8 hex digits, for logs and compact displays.
+    pub fn short(&self) -> String {
+        self.0[..4].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl From<[u8; 16]> for AgentId {
+    fn from(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8; 16]> for AgentId {
+    fn from(bytes: &[u8; 16]) -> Self {
+        Self(*bytes)
+    }
+}
+
+impl From<AgentId> for [u8; 16] {
+    fn from(id: AgentId) -> Self {
+        id.0
+    }
+}
+
+impl TryFrom<&[u8]> for AgentId {
+    type Error = AILLError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, AILLError> {
+        bytes
+            .try_into()
+            .map(Self)
+            .map_err(|_| AILLError::InvalidStructure(format!("agent id must be 16 bytes, got {}", bytes.len())))
+    }
+}
+
+impl AsRef<[u8]> for AgentId {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq<[u8; 16]> for AgentId {
+    fn eq(&self, other: &[u8; 16]) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for AgentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AgentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AgentId({})", self)
+    }
+}
+
+/// Parses 32 hex digits, with or without the usual hyphens.
+impl FromStr for AgentId {
+    type Err = AILLError;
+
+    fn from_str(s: &str) -> Result<Self, AILLError> {
+        let err = || AILLError::InvalidStructure(format!("invalid agent id {:?}", s));
+        let digits: Vec<u8> = s.bytes().filter(|&c| c != b'-').collect();
+        if digits.len() != 32 {
+            return Err(err());
+        }
+        let mut bytes = [0u8; 16];
+        for (i, pair) in digits.chunks(2).enumerate() {
+            let pair = std::str::from_utf8(pair).map_err(|_| err())?;
+            bytes[i] = u8::from_str_radix(pair, 16).map_err(|_| err())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_and_parse_roundtrip() {
+        let id = AgentId::new([
+            0x6b, 0xa7, 0xb8, 0x10, 0x9d, 0xad, 0x11, 0xd1, 0x80, 0xb4, 0x00, 0xc0, 0x4f, 0xd4, 0x30, 0xc8,
+        ]);
+        assert_eq!(id.to_string(), "6ba7b810-9dad-11d1-80b4-00c04fd430c8");
+        assert_eq!(id.to_hex(), "6ba7b8109dad11d180b400c04fd430c8");
+        assert_eq!(id.to_string().parse::<AgentId>().unwrap(), id);
+        assert_eq!(id.to_hex().parse::<AgentId>().unwrap(), id);
+        assert!("6ba7b810".parse::<AgentId>().is_err());
+        assert!("zz".repeat(16).parse::<AgentId>().is_err());
+        assert!(AgentId::try_from(&[1u8; 15][..]).is_err());
+    }
+
+    #[cfg(feature = "ast-serde")]
+    #[test]
+    fn serializes_as_raw_bytes() {
+        assert_eq!(serde_json::to_string(&AgentId::new([1; 16])).unwrap(), format!("[{}1]", "1,".repeat(15)));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn v4_sets_version_and_variant() {
+        let id = AgentId::new_v4();
+        assert_eq!(id.as_bytes()[6] >> 4, 4);
+        assert_eq!(id.as_bytes()[8] >> 6, 0b10);
+        assert_ne!(id, AgentId::new_v4());
+    }
+}
@@ -0,0 +1,188 @@
+//! Opcode coverage of captured traffic.
+//!
+//! [`coverage`] decodes a capture of back-to-back utterances and counts
+//! which base-codebook opcodes and which domain entries it uses. Coverage
+//! from many captures can be merged, which makes it easy to find opcodes
+//! no message exercises, or to check that a test corpus is complete.
+
+use std::collections::BTreeMap;
+
+use crate::ast::AstNode;
+use crate::codebook::base::BASE_CODEBOOK;
+use crate::codebook::{DomainCodebook, DomainEntry};
+use crate::decoder::{AILLDecoder, DecodeObserver};
+
+/// Opcode and domain-reference counts over one or more captures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodeCoverage {
+    /// Utterances decoded successfully.
+    pub messages: usize,
+    /// Captures whose decoding stopped early on malformed input.
+    pub errors: usize,
+    opcodes: [u64; 256],
+    domain_refs: BTreeMap<(u8, u16), u64>,
+}
+
+impl Default for OpcodeCoverage {
+    fn default() -> Self {
+        Self { messages: 0, errors: 0, opcodes: [0; 256], domain_refs: BTreeMap::new() }
+    }
+}
+
+/// Coverage of a single capture.
+pub fn coverage(capture: &[u8]) -> OpcodeCoverage {
+    let mut cov = OpcodeCoverage::new();
+    cov.add_capture(capture);
+    cov
+}
+
+/// Aggregate coverage over many captures.
+pub fn coverage_all<'a, I>(captures: I) -> OpcodeCoverage
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut cov = OpcodeCoverage::new();
+    for capture in captures {
+        cov.add_capture(capture);
+    }
+    cov
+}
+
+struct Recorder<'c> {
+    cov: &'c mut OpcodeCoverage,
+    end: usize,
+}
+
+impl DecodeObserver for Recorder<'_> {
+    fn on_opcode(&mut self, _offset: usize, code: u8) {
+        self.cov.opcodes[code as usize] += 1;
+    }
+
+    fn on_node_end(&mut self, _start: usize, end: usize, node: &AstNode) {
+        match node {
+            AstNode::DomainRef { level, domain_code, .. } => {
+                *self.cov.domain_refs.entry((*level, *domain_code)).or_insert(0) += 1;
+            }
+            AstNode::Utterance { .. } => self.end = end,
+            _ => {}
+        }
+    }
+}
+
+impl OpcodeCoverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode every utterance in `capture`, stopping at the first one that
+    /// fails to decode.
+    pub fn add_capture(&mut self, capture: &[u8]) {
+        let mut offset = 0;
+        while offset < capture.len() {
+            let mut rec = Recorder { cov: self, end: 0 };
+            match AILLDecoder::new().decode_utterance_with_observer(&capture[offset..], &mut rec) {
+                Ok(_) if rec.end > 0 => {
+                    offset += rec.end;
+                    self.messages += 1;
+                }
+                _ => {
+                    self.errors += 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Fold `other` into this coverage.
+    pub fn merge(&mut self, other: &OpcodeCoverage) {
+        self.messages += other.messages;
+        self.errors += other.errors;
+        for (mine, theirs) in self.opcodes.iter_mut().zip(other.opcodes.iter()) {
+            *mine += theirs;
+        }
+        for (key, n) in &other.domain_refs {
+            *self.domain_refs.entry(*key).or_insert(0) += n;
+        }
+    }
+
+    /// Times `code` appeared as an opcode (payload bytes are not counted).
+    pub fn opcode_count(&self, code: u8) -> u64 {
+        self.opcodes[code as usize]
+    }
+
+    /// Opcodes seen at least once, ascending.
+    pub fn seen_opcodes(&self) -> Vec<u8> {
+        (0..=255u8).filter(|&c| self.opcodes[c as usize] > 0).collect()
+    }
+
+    /// Defined (non-reserved) opcodes never seen.
+    pub fn unseen_opcodes(&self) -> Vec<u8> {
+        (0..=255u8)
+            .filter(|&c| self.opcodes[c as usize] == 0)
+            .filter(|&c| {
+                let mnemonic = BASE_CODEBOOK[c as usize].mnemonic;
+                mnemonic != "UNKNOWN" && !mnemonic.starts_with("RESERVED")
+            })
+            .collect()
+    }
+
+    /// Opcode counts per base-codebook category (`"type_marker"`, `"modality"`, ...).
+    pub fn category_counts(&self) -> BTreeMap<&'static str, u64> {
+        let mut counts = BTreeMap::new();
+        for (code, &n) in self.opcodes.iter().enumerate() {
+            if n > 0 {
+                *counts.entry(BASE_CODEBOOK[code].category).or_insert(0) += n;
+            }
+        }
+        counts
+    }
+
+    /// Counts per `(escape level, domain code)`.
+    pub fn domain_refs(&self) -> &BTreeMap<(u8, u16), u64> {
+        &self.domain_refs
+    }
+
+    /// Entries of `codebook` referenced via ESCAPE_L1, and those that were
+    /// not. L1 codes carry no registry id, so this assumes the capture was
+    /// spoken in `codebook`'s domain.
+    pub fn domain_coverage<'d>(&self, codebook: &'d DomainCodebook) -> (Vec<&'d DomainEntry>, Vec<&'d DomainEntry>) {
+        codebook.entries().iter().partition(|entry| self.domain_refs.contains_key(&(1, entry.code)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::base::{fc, pragma};
+    use crate::codebook::DIAG1;
+    use crate::reliability::{encode_link_report, LinkStats, AILL_BER};
+    use crate::testing::generate_corpus;
+
+    #[test]
+    fn counts_opcodes_across_a_capture() {
+        let capture: Vec<u8> = encode_link_report(&LinkStats::default()).concat();
+        let cov = coverage(&capture);
+        assert_eq!((cov.messages, cov.errors), (3, 0));
+        assert_eq!(cov.opcode_count(fc::START_UTTERANCE), 3);
+        assert_eq!(cov.opcode_count(pragma::ASSERT), 3);
+        assert_eq!(cov.domain_refs()[&(1, AILL_BER)], 1);
+
+        let (used, unused) = cov.domain_coverage(&DIAG1);
+        assert_eq!(used.len(), 3);
+        assert_eq!(used.len() + unused.len(), DIAG1.len());
+        assert!(cov.unseen_opcodes().contains(&pragma::QUERY));
+        assert_eq!(cov.category_counts()["pragmatic"], 3);
+    }
+
+    #[test]
+    fn aggregates_and_reports_errors() {
+        let corpus = generate_corpus(1, 50, 3);
+        let mut cov = coverage_all(corpus.iter().map(Vec::as_slice));
+        assert_eq!((cov.messages, cov.errors), (50, 0));
+        let seen = cov.seen_opcodes().len();
+
+        cov.merge(&coverage(&[0x42]));
+        assert_eq!((cov.messages, cov.errors), (50, 1));
+        assert!(cov.seen_opcodes().len() >= seen);
+    }
+}
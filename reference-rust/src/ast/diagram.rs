@@ -0,0 +1,230 @@
+use super::AstNode;
+use crate::codebook::base::BASE_CODEBOOK;
+use crate::codebook::{get_domain_codebook, DomainCodebook};
+use crate::format::literal_text;
+
+/// Render a decoded AST as a Graphviz DOT digraph.
+pub fn to_dot(node: &AstNode) -> String {
+    Graph::build(node, None).dot()
+}
+
+/// Like [`to_dot`], labelling domain references and struct field edges with
+/// mnemonics from `domain`.
+pub fn to_dot_with_domain(node: &AstNode, domain: &DomainCodebook) -> String {
+    Graph::build(node, Some(domain)).dot()
+}
+
+/// Render a decoded AST as a Mermaid flowchart.
+pub fn to_mermaid(node: &AstNode) -> String {
+    Graph::build(node, None).mermaid()
+}
+
+/// Like [`to_mermaid`], labelling domain references and struct field edges
+/// with mnemonics from `domain`.
+pub fn to_mermaid_with_domain(node: &AstNode, domain: &DomainCodebook) -> String {
+    Graph::build(node, Some(domain)).mermaid()
+}
+
+struct GraphNode {
+    label: String,
+    category: &'static str,
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    label: Option<String>,
+}
+
+struct Graph<'d> {
+    domain: Option<&'d DomainCodebook>,
+    nodes: Vec<GraphNode>,
+    edges: Vec<Edge>,
+}
+
+impl<'d> Graph<'d> {
+    fn build(root: &AstNode, domain: Option<&'d DomainCodebook>) -> Self {
+        let mut g = Graph { domain, nodes: Vec::new(), edges: Vec::new() };
+        g.visit(root);
+        g
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: Option<String>) {
+        self.edges.push(Edge { from, to, label });
+    }
+
+    /// Add `node` and its subtree, returning the id of `node`.
+    fn visit(&mut self, node: &AstNode) -> usize {
+        let (label, category) = self.label(node);
+        let id = self.nodes.len();
+        self.nodes.push(GraphNode { label, category });
+
+        match node {
+            AstNode::Utterance { body, .. } => {
+                for (i, expr) in body.iter().enumerate() {
+                    let child = self.visit(expr);
+                    self.edge(id, child, Some(i.to_string()));
+                }
+            }
+            AstNode::Struct { fields } => {
+                for (fid, val) in fields {
+                    let child = self.visit(val);
+                    let label = match self.domain.and_then(|d| d.lookup(*fid)) {
+                        Some(e) => format!("0x{:04X} {}", fid, e.mnemonic),
+                        None => format!("0x{:04X}", fid),
+                    };
+                    self.edge(id, child, Some(label));
+                }
+            }
+            AstNode::List { elements, .. } => {
+                for (i, elem) in elements.iter().enumerate() {
+                    let child = self.visit(elem);
+                    self.edge(id, child, Some(format!("[{}]", i)));
+                }
+            }
+            AstNode::Map { pairs, .. } => {
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    let key = self.visit(k);
+                    self.edge(id, key, Some(format!("key {}", i)));
+                    let val = self.visit(v);
+                    self.edge(id, val, Some(format!("val {}", i)));
+                }
+            }
+            AstNode::Pragmatic { expression, .. }
+            | AstNode::Modal { expression, .. }
+            | AstNode::Temporal { expression, .. }
+            | AstNode::SchemaRef { expression, .. } => {
+                let child = self.visit(expression);
+                self.edge(id, child, None);
+            }
+            _ => {}
+        }
+        id
+    }
+
+    fn label(&self, node: &AstNode) -> (String, &'static str) {
+        match node {
+            AstNode::Utterance { meta, .. } => (
+                format!(
+                    "UTTERANCE\nconfidence={:.2} priority={}",
+                    meta.confidence, meta.priority
+                ),
+                "frame_control",
+            ),
+            AstNode::Literal { value_type, value } => {
+                (format!("{}: {}", value_type, literal_text(value)), "type_marker")
+            }
+            AstNode::Struct { .. } => ("STRUCT".into(), "structure"),
+            AstNode::List { count, .. } => (format!("LIST[{}]", count), "structure"),
+            AstNode::Map { count, .. } => (format!("MAP[{}]", count), "structure"),
+            AstNode::Pragmatic { act, .. } => (act.clone(), "pragmatic"),
+            AstNode::Modal { modality, extra, .. } => match extra {
+                Some(v) => (format!("{}\nhorizon={}ms", modality, v), "modality"),
+                None => (modality.clone(), "modality"),
+            },
+            AstNode::Temporal { modifier, .. } => (modifier.clone(), "temporal"),
+            AstNode::SchemaRef { schema_id, .. } => (format!("SCHEMA_REF\n0x{:04X}", schema_id), "structure"),
+            AstNode::DomainRef { level, domain_code, registry } => {
+                let domain = registry.and_then(get_domain_codebook).or(self.domain);
+                let target = match domain.and_then(|d| d.lookup(*domain_code).map(|e| (d.name, e))) {
+                    Some((name, e)) => format!("{}/{}", name, e.mnemonic),
+                    None => format!("0x{:04X}", domain_code),
+                };
+                (format!("REF L{}\n{}", level, target), "escape")
+            }
+            AstNode::CodebookRef { level, registry_id } => {
+                let name = get_domain_codebook(*registry_id)
+                    .map(|d| d.name.to_string())
+                    .unwrap_or_else(|| format!("0x{:02X}", registry_id));
+                (format!("CODEBOOK_REF\nL{} = {}", level, name), "escape")
+            }
+            AstNode::ContextRef { sct_index } => (format!("SCT_REF[{}]", sct_index), "meta"),
+            AstNode::HashRef { hash } => (format!("HASH_REF\n0x{:08X}", hash), "meta"),
+            AstNode::Code { code, mnemonic } => (mnemonic.clone(), BASE_CODEBOOK[*code as usize].category),
+            AstNode::FrameControl { mnemonic, operands, .. } => {
+                let operands: Vec<String> = operands.iter().map(|o| o.to_string()).collect();
+                (format!("{}\n{}", mnemonic, operands.join(", ")).trim_end().to_string(), "frame_control")
+            }
+            AstNode::Annotated { mnemonic, .. } => (mnemonic.clone(), "meta"),
+            AstNode::Extension { code, mnemonic, .. } => (format!("{}\n0x{:02X}", mnemonic, code), "reserved"),
+            AstNode::Raw { bytes } => (format!("RAW\n{} bytes", bytes.len()), "reserved"),
+            AstNode::Placeholder { name, value_type } => (format!("{}: ?{}", value_type, name), "type_marker"),
+        }
+    }
+
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph aill {\n  node [shape=box, fontname=\"monospace\"];\n");
+        for (id, n) in self.nodes.iter().enumerate() {
+            out.push_str(&format!(
+                "  n{} [label=\"{}\", class=\"{}\"];\n",
+                id,
+                dot_escape(&n.label),
+                n.category
+            ));
+        }
+        for e in &self.edges {
+            match &e.label {
+                Some(l) => out.push_str(&format!("  n{} -> n{} [label=\"{}\"];\n", e.from, e.to, dot_escape(l))),
+                None => out.push_str(&format!("  n{} -> n{};\n", e.from, e.to)),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        for (id, n) in self.nodes.iter().enumerate() {
+            out.push_str(&format!("  n{}[\"{}\"]:::{}\n", id, mermaid_escape(&n.label), n.category));
+        }
+        for e in &self.edges {
+            match &e.label {
+                Some(l) => out.push_str(&format!("  n{} -->|\"{}\"| n{}\n", e.from, mermaid_escape(l), e.to)),
+                None => out.push_str(&format!("  n{} --> n{}\n", e.from, e.to)),
+            }
+        }
+        out
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::MANIP1;
+    use crate::{AILLDecoder, AILLEncoder};
+
+    fn sample() -> AstNode {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().command().l1_ref(0x0000);
+        e.begin_struct().field(0x0000).uint8(2).field(0x0042).string("say \"hi\"").end_struct();
+        AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+    }
+
+    #[test]
+    fn dot_has_nodes_and_labelled_edges() {
+        let dot = to_dot_with_domain(&sample(), &MANIP1);
+        assert!(dot.starts_with("digraph aill {"));
+        assert!(dot.contains("label=\"COMMAND\""));
+        assert!(dot.contains("REF L1\\nMANIP-1/GRIPPER_STATE"));
+        assert!(dot.contains("[label=\"0x0000 GRIPPER_STATE\"]"));
+        assert!(dot.contains("say \\\"hi\\\""));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn mermaid_edges_without_domain_use_raw_ids() {
+        let mm = to_mermaid(&sample());
+        assert!(mm.starts_with("flowchart TD\n"));
+        assert!(mm.contains("-->|\"0x0042\"|"));
+        assert!(mm.contains("#quot;hi#quot;"));
+        assert_eq!(mm.matches("-->").count(), mm.lines().filter(|l| l.contains("-->")).count());
+    }
+}
@@ -0,0 +1,333 @@
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::agent::AgentId;
+
+pub mod diagram;
+
+pub use diagram::{to_dot, to_dot_with_domain, to_mermaid, to_mermaid_with_domain};
+
+/// Literal value types.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "ast-serde", serde(tag = "type", content = "value"))]
+pub enum LiteralValue {
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Float16(f32),
+    Float32(f32),
+    Float64(f64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    Timestamp(i64),
+    Null,
+    /// A value with its standard deviation, carried as the well-known
+    /// [`MEASUREMENT_SCHEMA_ID`] schema.
+    Measurement { value: f32, stddev: f32 },
+}
+
+/// Well-known schema of a measurement: `STRUCT{FLOAT32 value, FLOAT32 stddev}`.
+pub const MEASUREMENT_SCHEMA_ID: u16 = 0x0001;
+
+/// The measurement a [`MEASUREMENT_SCHEMA_ID`] payload holds, if it has
+/// that shape.
+pub fn measurement(payload: &AstNode) -> Option<LiteralValue> {
+    let AstNode::Struct { fields } = payload else {
+        return None;
+    };
+    let float = |i: u16| match fields.get(&i) {
+        Some(AstNode::Literal { value: LiteralValue::Float32(v), .. }) => Some(*v),
+        _ => None,
+    };
+    match fields.len() {
+        2 => Some(LiteralValue::Measurement { value: float(0)?, stddev: float(1)? }),
+        _ => None,
+    }
+}
+
+/// AST node types for decoded AILL expressions.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "ast-serde", serde(tag = "node_type"))]
+pub enum AstNode {
+    Utterance {
+        meta: MetaHeader,
+        body: Vec<AstNode>,
+    },
+    Literal {
+        value_type: String,
+        value: LiteralValue,
+    },
+    /// Fields by id. [`AILLEncoder`](crate::AILLEncoder) writes them in
+    /// ascending id order unless told otherwise, see
+    /// [`with_canonical_fields`](crate::AILLEncoder::with_canonical_fields);
+    /// [`FixedEncoder`](crate::fixed::FixedEncoder) writes them as called.
+    Struct {
+        #[cfg_attr(feature = "ast-serde", serde(deserialize_with = "field_ids::deserialize"))]
+        fields: BTreeMap<u16, AstNode>,
+    },
+    List {
+        count: u16,
+        elements: Vec<AstNode>,
+    },
+    /// Pairs in wire order. Keys may be any expression, so neither the
+    /// encoders nor the decoder sort them.
+    Map {
+        count: u16,
+        pairs: Vec<(AstNode, AstNode)>,
+    },
+    Pragmatic {
+        act: String,
+        expression: Box<AstNode>,
+    },
+    Modal {
+        modality: String,
+        expression: Box<AstNode>,
+        #[cfg_attr(feature = "ast-serde", serde(skip_serializing_if = "Option::is_none"))]
+        extra: Option<f64>,
+    },
+    Temporal {
+        modifier: String,
+        expression: Box<AstNode>,
+    },
+    /// SCHEMA_REF: `expression`, normally a struct of positional fields,
+    /// follows schema `schema_id`; see [`schema`](crate::schema).
+    SchemaRef {
+        schema_id: u16,
+        expression: Box<AstNode>,
+    },
+    DomainRef {
+        level: u8,
+        domain_code: u16,
+        /// Registry the escape level was bound to when decoded, see
+        /// [`DecoderConfig`](crate::DecoderConfig).
+        #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "Option::is_none"))]
+        registry: Option<u8>,
+    },
+    /// CODEBOOK_REF: binds escape `level` to `registry_id` for the rest of
+    /// the utterance.
+    CodebookRef {
+        level: u8,
+        registry_id: u8,
+    },
+    ContextRef {
+        sct_index: u32,
+    },
+    /// Content-addressed reference (first 32 bits of SHA-256).
+    HashRef {
+        hash: u32,
+    },
+    Code {
+        code: u8,
+        mnemonic: String,
+    },
+    /// Frame-control opcode inside a body, with its operands in wire order
+    /// (see [`fc::operand_widths`](crate::codebook::base::fc::operand_widths)):
+    /// an epoch sequence number and NACK reason, a SYNC_MARK timestamp, a
+    /// fragment count, index or CRC, or an echo nonce.
+    FrameControl {
+        code: u8,
+        mnemonic: String,
+        #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+        operands: Vec<u32>,
+    },
+    Annotated {
+        code: u8,
+        mnemonic: String,
+    },
+    /// Opcode from the reserved range 0xC0-0xEF, see
+    /// [`extension`](crate::extension). `payload` is empty for opcodes the
+    /// decoder has not registered.
+    Extension {
+        code: u8,
+        mnemonic: String,
+        #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+        payload: Vec<u8>,
+    },
+    /// Undecoded wire bytes of a struct field value whose id the decoder
+    /// does not know, see [`DecoderConfig::with_known_fields`](crate::DecoderConfig::with_known_fields).
+    /// Re-encoded verbatim.
+    Raw {
+        bytes: Vec<u8>,
+    },
+    /// Named substitution slot for a literal of `value_type` in a
+    /// [`Template`](crate::templates::Template). Never produced by the decoder.
+    Placeholder {
+        name: String,
+        value_type: String,
+    },
+}
+
+impl AstNode {
+    /// The value of a map entry whose key is the string literal `key`.
+    /// `None` if this is not a map or has no such entry.
+    pub fn get_str_key(&self, key: &str) -> Option<&AstNode> {
+        let AstNode::Map { pairs, .. } = self else {
+            return None;
+        };
+        pairs.iter().find_map(|(k, v)| match k {
+            AstNode::Literal { value: LiteralValue::String(s), .. } if s == key => Some(v),
+            _ => None,
+        })
+    }
+}
+
+/// Struct field maps keyed by id. JSON prints the ids as strings, and
+/// the buffering behind `#[serde(tag)]` hands them back as strings too, so
+/// accept either form.
+#[cfg(feature = "ast-serde")]
+mod field_ids {
+    use super::AstNode;
+    use serde::de::{self, Deserializer, MapAccess, Visitor};
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    struct FieldId(u16);
+
+    impl<'de> serde::Deserialize<'de> for FieldId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct IdVisitor;
+
+            impl Visitor<'_> for IdVisitor {
+                type Value = FieldId;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a u16 field id, as a number or a string")
+                }
+
+                fn visit_u64<E: de::Error>(self, v: u64) -> Result<FieldId, E> {
+                    u16::try_from(v).map(FieldId).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+                }
+
+                fn visit_i64<E: de::Error>(self, v: i64) -> Result<FieldId, E> {
+                    u16::try_from(v).map(FieldId).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+                }
+
+                fn visit_str<E: de::Error>(self, v: &str) -> Result<FieldId, E> {
+                    v.parse().map(FieldId).map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+                }
+            }
+
+            deserializer.deserialize_any(IdVisitor)
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<u16, AstNode>, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = BTreeMap<u16, AstNode>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of field id to AST node")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = BTreeMap::new();
+                while let Some((FieldId(id), node)) = map.next_entry::<FieldId, AstNode>()? {
+                    fields.insert(id, node);
+                }
+                Ok(fields)
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+/// Decoded meta header.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct MetaHeader {
+    pub confidence: f32,
+    pub priority: u8,
+    pub timestamp_us: i64,
+    #[cfg_attr(feature = "ast-serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub source_agent: Option<AgentId>,
+    #[cfg_attr(feature = "ast-serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub dest_agent: Option<AgentId>,
+    #[cfg_attr(feature = "ast-serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub seqnum: Option<u32>,
+    /// Recipients named by a body-level COMM-1 MULTICAST tag, once
+    /// [`lift_tags`](crate::addressing::lift_tags) has copied them here.
+    #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub dest_agents: Vec<AgentId>,
+    /// Set by [`lift_tags`](crate::addressing::lift_tags) for a body-level
+    /// COMM-1 BROADCAST tag.
+    #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    pub broadcast: bool,
+    /// Conversation thread named by a body-level COMM-1 THREAD_ID tag, once
+    /// [`lift_thread_id`](crate::thread::lift_thread_id) has copied it here.
+    #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub thread_id: Option<u64>,
+    #[cfg_attr(feature = "ast-serde", serde(default, skip_serializing_if = "BTreeMap::is_empty"))]
+    pub annotations: BTreeMap<String, AnnotationValue>,
+}
+
+impl Default for MetaHeader {
+    fn default() -> Self {
+        Self {
+            confidence: 1.0,
+            priority: 3,
+            timestamp_us: 0,
+            source_agent: None,
+            dest_agent: None,
+            seqnum: None,
+            dest_agents: Vec::new(),
+            broadcast: false,
+            thread_id: None,
+            annotations: BTreeMap::new(),
+        }
+    }
+}
+
+impl MetaHeader {
+    /// The TRACE_ID annotation, if present.
+    pub fn trace_id(&self) -> Option<u64> {
+        match self.annotations.get("trace_id") {
+            Some(AnnotationValue::U64(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// The COST annotation, if present.
+    pub fn cost(&self) -> Option<f32> {
+        match self.annotations.get("cost") {
+            Some(AnnotationValue::F32(c)) => Some(*c),
+            _ => None,
+        }
+    }
+}
+
+/// Values that can appear in meta annotations.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "ast-serde", serde(untagged))]
+pub enum AnnotationValue {
+    U16(u16),
+    U64(u64),
+    Pair(u16, u16),
+    F32(f32),
+}
+
+/// A decoded epoch with verified CRC.
+#[derive(Debug, Clone)]
+pub struct DecodedEpoch {
+    pub seq_num: u16,
+    pub payload: Vec<u8>,
+    /// Whether the epoch's check value, CRC-8 or another integrity scheme,
+    /// matched.
+    pub crc_ok: bool,
+    /// Flags byte of an extended header, `None` for a plain 5-byte header.
+    pub flags: Option<u8>,
+    /// Timestamp (µs) of an extended header with the TIMESTAMP flag.
+    pub timestamp_us: Option<i64>,
+}
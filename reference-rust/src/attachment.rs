@@ -0,0 +1,280 @@
+//! Binary attachments split across utterances.
+//!
+//! Blobs such as camera crops or segmentation masks are too large for one
+//! BYTES value, and should not hold up a link for the whole transfer. A
+//! blob is split into chunks, each sent as an utterance of its own that is
+//! marked with the fragmentation opcodes:
+//!
+//! ```text
+//! FRAGMENT_START <u16 total>  ASSERT BYTES <chunk 0>
+//! FRAGMENT_CONT  <u16 index>  ASSERT BYTES <chunk index>
+//! FRAGMENT_CONT  <u16 last>   ASSERT BYTES <last chunk>  FRAGMENT_END <u32 crc>
+//! ```
+//!
+//! A single-chunk blob carries both FRAGMENT_START and FRAGMENT_END. The
+//! check value is the [`crc32()`] of the whole blob, as for
+//! [`AILLEncoder::fragment_end`], and is verified on reassembly.
+//! Fragments of one attachment are sent back to back, so a receiver
+//! assembles one attachment at a time and a new FRAGMENT_START abandons an
+//! unfinished one.
+//!
+//! [`EpochWriter::send_attachment`] sends each fragment of a blob in an
+//! epoch of its own; an [`AttachmentAssembler`] is fed decoded
+//! utterances and hands completed blobs to its
+//! [`on_attachment`](AttachmentAssembler::on_attachment) callback.
+
+use std::io::Write;
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::base::fc;
+use crate::encoder::{AILLEncoder, EpochWriter};
+use crate::error::AILLError;
+use crate::wire::crc32;
+
+/// Largest number of bytes a fragment utterance adds around its chunk.
+pub const FRAGMENT_OVERHEAD: usize = 31;
+
+/// Default limit on the size of a reassembled attachment.
+pub const DEFAULT_MAX_ATTACHMENT: usize = 1 << 20;
+
+/// A reassembled blob.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub data: Vec<u8>,
+    /// [`crc32()`] of `data`, as announced by the sender.
+    pub crc32: u32,
+}
+
+/// Split `data` into fragment utterances of at most `chunk_size` data bytes.
+pub fn fragments(data: &[u8], chunk_size: usize) -> Result<Vec<Vec<u8>>, AILLError> {
+    let chunk_size = chunk_size.clamp(1, u16::MAX as usize);
+    let chunks: Vec<&[u8]> = match data.is_empty() {
+        true => vec![&[]],
+        false => data.chunks(chunk_size).collect(),
+    };
+    let total = u16::try_from(chunks.len()).map_err(|_| {
+        AILLError::EncoderError(format!("{} bytes need more than {} fragments", data.len(), u16::MAX))
+    })?;
+    let crc = crc32(data);
+    let utterances = chunks.iter().enumerate().map(|(i, chunk)| {
+        let mut e = AILLEncoder::new();
+        e.start_utterance();
+        match i {
+            0 => e.fragment_start(total),
+            _ => e.fragment_cont(i as u16),
+        };
+        e.assert_().bytes(chunk);
+        if i + 1 == chunks.len() {
+            e.fragment_end(crc);
+        }
+        e.end_utterance()
+    });
+    Ok(utterances.collect())
+}
+
+impl<W: Write> EpochWriter<W> {
+    /// Send `data` as fragment utterances, each in an epoch of its own.
+    /// Earlier partial payload is flushed first. Returns the blob's [`crc32()`].
+    pub fn send_attachment(&mut self, data: &[u8]) -> Result<u32, AILLError> {
+        let chunk_size = self.max_payload().saturating_sub(FRAGMENT_OVERHEAD);
+        self.flush()?;
+        for fragment in fragments(data, chunk_size)? {
+            self.write(&fragment)?;
+            self.flush()?;
+        }
+        Ok(crc32(data))
+    }
+}
+
+struct Partial {
+    total: u16,
+    next: u16,
+    data: Vec<u8>,
+}
+
+/// Reassembles attachments from decoded utterances.
+pub struct AttachmentAssembler {
+    max_len: usize,
+    partial: Option<Partial>,
+    completed: Vec<Attachment>,
+    on_attachment: Option<Box<dyn FnMut(Attachment) + Send>>,
+}
+
+impl Default for AttachmentAssembler {
+    fn default() -> Self {
+        Self { max_len: DEFAULT_MAX_ATTACHMENT, partial: None, completed: Vec::new(), on_attachment: None }
+    }
+}
+
+impl AttachmentAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse attachments larger than `max_len` bytes.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Call `callback` with each completed attachment instead of keeping
+    /// it for [`take_attachments`](Self::take_attachments).
+    pub fn on_attachment(mut self, callback: impl FnMut(Attachment) + Send + 'static) -> Self {
+        self.on_attachment = Some(Box::new(callback));
+        self
+    }
+
+    /// Whether an attachment is partly received.
+    pub fn is_receiving(&self) -> bool {
+        self.partial.is_some()
+    }
+
+    /// Take in a decoded utterance. Returns `false` if it is not a
+    /// fragment. A fragment out of sequence, an oversized attachment or a
+    /// CRC mismatch discards the attachment and is reported as an error.
+    pub fn push(&mut self, utterance: &AstNode) -> Result<bool, AILLError> {
+        let Some((marker, index, chunk, crc)) = fragment_parts(utterance) else {
+            return Ok(false);
+        };
+        if marker == fc::FRAGMENT_START {
+            self.partial = Some(Partial { total: index, next: 0, data: Vec::new() });
+        }
+        let Some(mut partial) = self.partial.take() else {
+            return Err(AILLError::InvalidStructure(format!("attachment fragment {} without a start", index)));
+        };
+        let index = if marker == fc::FRAGMENT_START { 0 } else { index };
+        if index != partial.next || index >= partial.total {
+            return Err(AILLError::InvalidStructure(format!(
+                "attachment fragment {} of {}, expected {}",
+                index, partial.total, partial.next
+            )));
+        }
+        if partial.data.len() + chunk.len() > self.max_len {
+            return Err(AILLError::InvalidStructure(format!("attachment exceeds {} bytes", self.max_len)));
+        }
+        partial.data.extend_from_slice(chunk);
+        partial.next += 1;
+        match crc {
+            None if partial.next < partial.total => self.partial = Some(partial),
+            None => return Err(AILLError::InvalidStructure("last attachment fragment has no CRC".into())),
+            Some(_) if partial.next < partial.total => {
+                return Err(AILLError::InvalidStructure(format!(
+                    "attachment ends after {} of {} fragments",
+                    partial.next, partial.total
+                )))
+            }
+            Some(crc) => {
+                if crc32(&partial.data) != crc {
+                    return Err(AILLError::InvalidStructure(format!("attachment CRC mismatch, expected {:08x}", crc)));
+                }
+                let attachment = Attachment { data: partial.data, crc32: crc };
+                match self.on_attachment.as_mut() {
+                    Some(callback) => callback(attachment),
+                    None => self.completed.push(attachment),
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Attachments completed since the last call, when no callback is set.
+    pub fn take_attachments(&mut self) -> Vec<Attachment> {
+        std::mem::take(&mut self.completed)
+    }
+}
+
+/// Marker opcode, its operand, the chunk and the CRC of a fragment utterance.
+fn fragment_parts(utterance: &AstNode) -> Option<(u8, u16, &[u8], Option<u32>)> {
+    let AstNode::Utterance { body, .. } = utterance else { return None };
+    let (marker, index) = match body.first()? {
+        AstNode::FrameControl { code: code @ (fc::FRAGMENT_START | fc::FRAGMENT_CONT), operands, .. } => {
+            (*code, *operands.first()? as u16)
+        }
+        _ => return None,
+    };
+    let chunk = match body.get(1)? {
+        AstNode::Pragmatic { expression, .. } => match expression.as_ref() {
+            AstNode::Literal { value: LiteralValue::Bytes(b), .. } => b.as_slice(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let crc = match body.get(2) {
+        Some(AstNode::FrameControl { code: fc::FRAGMENT_END, operands, .. }) => Some(*operands.first()?),
+        _ => None,
+    };
+    Some((marker, index, chunk, crc))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::decoder::{decode_epoch, AILLDecoder};
+    use crate::stream::StreamDecoder;
+
+    #[test]
+    fn attachment_round_trips_through_epochs() {
+        let blob: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let mut writer = EpochWriter::new(Vec::new()).with_max_payload(128).with_max_in_flight(usize::MAX);
+        let crc = writer.send_attachment(&blob).unwrap();
+        let wire = writer.into_inner();
+
+        let got = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&got);
+        let mut assembler = AttachmentAssembler::new().on_attachment(move |a| sink.lock().unwrap().push(a));
+        let mut stream = StreamDecoder::new();
+        let (mut pos, mut epochs) = (0, 0);
+        while pos < wire.len() {
+            let (epoch, used) = decode_epoch(&wire, pos).unwrap();
+            // One fragment per epoch
+            assert!(epoch.payload.len() <= 128);
+            for node in stream.push(&epoch.payload).unwrap() {
+                assert!(assembler.push(&node).unwrap());
+            }
+            pos += used;
+            epochs += 1;
+        }
+        assert_eq!(epochs, 1000usize.div_ceil(128 - FRAGMENT_OVERHEAD));
+        assert!(!assembler.is_receiving());
+        assert_eq!(crc, crc32(&blob));
+        assert_eq!(*got.lock().unwrap(), vec![Attachment { data: blob, crc32: crc }]);
+    }
+
+    #[test]
+    fn rejects_corrupt_and_out_of_order_fragments() {
+        let decoder = AILLDecoder::new();
+        let decode = |wire: &[u8]| decoder.decode_utterance(wire).unwrap();
+        let mut assembler = AttachmentAssembler::new();
+        let mut other = AILLEncoder::new();
+        other.start_utterance().assert_().uint8(1);
+        assert!(!assembler.push(&decode(&other.end_utterance())).unwrap());
+
+        let frags = fragments(b"hello, attachment", 5).unwrap();
+        assert_eq!(frags.len(), 4);
+        // FRAGMENT_END carries the CRC-32 of the whole blob
+        let AstNode::Utterance { body, .. } = decode(&frags[3]) else { panic!() };
+        let end = [crc32(b"hello, attachment")];
+        assert!(matches!(&body[2], AstNode::FrameControl { code: fc::FRAGMENT_END, operands, .. } if operands[..] == end));
+        assert!(assembler.push(&decode(&frags[0])).is_ok());
+        assert!(assembler.push(&decode(&frags[2])).is_err());
+        assert!(assembler.push(&decode(&frags[3])).is_err());
+
+        // A flipped data byte fails the CRC check
+        let mut bad = frags.clone();
+        let at = bad[1].len() - 2;
+        bad[1][at] ^= 0x01;
+        let results: Vec<_> = bad.iter().map(|f| assembler.push(&decode(f))).collect();
+        assert!(results.last().unwrap().is_err());
+        assert!(assembler.take_attachments().is_empty());
+
+        for f in &frags {
+            assembler.push(&decode(f)).unwrap();
+        }
+        assert_eq!(assembler.take_attachments()[0].data, b"hello, attachment");
+        let mut small = AttachmentAssembler::new().with_max_len(8);
+        assert!(frags.iter().any(|f| small.push(&decode(f)).is_err()));
+        assert_eq!(fragments(&[], 16).unwrap().len(), 1);
+    }
+}
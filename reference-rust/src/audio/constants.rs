@@ -62,6 +62,10 @@ pub const TONE_RELEASE: f32 = 0.003;
 /// Master gain applied to data tones.
 pub const MASTER_GAIN: f32 = 0.15;
 
+/// Largest per-carrier gain used to compensate a speaker's frequency
+/// response; four carriers at this gain still fit in [-1, 1].
+pub const MAX_CARRIER_GAIN: f32 = 2.0;
+
 // ── FFT / decoder ──
 
 pub const FFT_SIZE: usize = 4096;
@@ -115,6 +119,19 @@ pub const MAX_SILENCE_MS: f32 = 250.0;
 /// Minimum symbols for a valid reception.
 pub const MIN_SYMBOLS: usize = 4;
 
+// ── Station-ID tail ──
+
+/// Silence between the end chirp and the station-ID tail (seconds).
+pub const STATION_GAP: f32 = 0.05;
+
+/// Tail symbols carry a whole byte on all carriers, at half the data
+/// symbol rate so that distant listeners can still read them.
+pub const STATION_SYMBOL_DURATION: f32 = 0.1;
+pub const STATION_FRAME_TIME: f32 = 0.12;
+
+/// Agent UUID followed by its CRC-8.
+pub const STATION_ID_BYTES: usize = 17;
+
 // ── Encoder limits ──
 
 /// Maximum number of wire bytes the encoder will accept.
@@ -1,21 +1,103 @@
+use std::collections::HashMap;
 use std::f32::consts::PI;
+use std::sync::{Arc, Mutex, OnceLock, TryLockError};
 
-use rustfft::{num_complex::Complex, FftPlanner};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
 
+use crate::addressing::peek_addressed_to;
+use crate::agent::AgentId;
 use crate::error::AILLError;
+use crate::wire::crc8::crc8;
 
 use super::constants::*;
+use super::encode::AcousticEncoder;
+use super::latency::cross_correlate_with;
+use super::noise::{NoiseProfile, NOISE_SIGMA};
+
+/// Wire bytes decoded first to check the destination of a transmission;
+/// enough for a meta header with SOURCE_AGENT, DEST_AGENT and SEQNUM.
+pub const ADDRESS_PREFIX_BYTES: usize = 64;
+
+/// Symbol confidence below which a byte is reported as an erasure.
+pub const DEFAULT_CONFIDENCE_FLOOR: f32 = 0.3;
+
+/// Lowest normalized correlation accepted as finding a chirp.
+const CHIRP_MATCH_SCORE: f32 = 0.5;
 
 /// Decodes PCM audio back into AILL wire-format bytes.
+///
+/// The FFT plan is shared by all decoders at one sample rate, and each
+/// decoder reuses its FFT buffers from frame to frame, so a streaming
+/// receiver should keep one decoder rather than build one per message.
 pub struct AcousticDecoder {
     sample_rate: u32,
+    /// Skip transmissions not addressed to this agent.
+    address: Option<AgentId>,
+    promiscuous: bool,
+    noise: Option<NoiseProfile>,
+    /// Tone threshold multiplier for each carrier.
+    carrier_weights: [f32; NUM_CARRIERS],
+    confidence_floor: f32,
+    plan: Arc<Plan>,
+    scratch: Mutex<Scratch>,
+}
+
+/// What decoding at one sample rate needs that does not depend on the
+/// audio: the Hann window and FFT plan for spectra, the reference chirps
+/// and the FFT plans used to find them.
+struct Plan {
+    window: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    /// Empty if the encoder does not support the sample rate.
+    sync_chirp: Vec<f32>,
+    end_chirp: Vec<f32>,
+    /// Keeps the cross-correlation plans by size.
+    planner: Mutex<FftPlanner<f32>>,
+}
+
+impl Plan {
+    fn new(sample_rate: u32) -> Self {
+        let mut planner = FftPlanner::new();
+        let encoder = AcousticEncoder::with_sample_rate(sample_rate).ok();
+        Self {
+            window: hann_window(),
+            fft: planner.plan_fft_forward(FFT_SIZE),
+            sync_chirp: encoder.as_ref().map(|e| e.sync_chirp()).unwrap_or_default(),
+            end_chirp: encoder.as_ref().map(|e| e.end_chirp()).unwrap_or_default(),
+            planner: Mutex::new(planner),
+        }
+    }
+
+    /// [`locate_chirp`] with the cached cross-correlation plans.
+    fn locate(&self, reference: &[f32], samples: &[f32], min_gain: Option<f32>) -> Option<(usize, f32)> {
+        locate_chirp(reference, samples, min_gain, |n| {
+            let mut planner = self.planner.lock().unwrap_or_else(|e| e.into_inner());
+            (planner.plan_fft_forward(n), planner.plan_fft_inverse(n))
+        })
+    }
+}
+
+/// The plan for `sample_rate`, built on first use.
+fn cached_plan(sample_rate: u32) -> Arc<Plan> {
+    static PLANS: OnceLock<Mutex<HashMap<u32, Arc<Plan>>>> = OnceLock::new();
+    let mut plans = PLANS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    plans.entry(sample_rate).or_insert_with(|| Arc::new(Plan::new(sample_rate))).clone()
 }
 
-/// A detected symbol: which half (hi/lo) and what nibble value.
+/// FFT input and working buffers, kept between frames.
+#[derive(Default)]
+struct Scratch {
+    buffer: Vec<Complex<f32>>,
+    fft: Vec<Complex<f32>>,
+}
+
+/// A detected symbol: which half (hi/lo), what nibble value, and how
+/// clearly it was told apart (0..=1).
 #[derive(Debug, Clone, Copy)]
 struct Symbol {
     half: Half,
     value: u8,
+    confidence: f32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -24,10 +106,48 @@ enum Half {
     Lo,
 }
 
+/// One transmission found by [`AcousticDecoder::decode_all`] or
+/// [`AcousticDecoder::decode_detailed`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeResult {
+    /// Sample offset of the start of the sync chirp.
+    pub start: usize,
+    /// Sample offset just past the end chirp, or past the station-ID tail
+    /// if there is one.
+    pub end: usize,
+    /// Mean ratio of active carrier magnitude to the tone threshold over
+    /// the detected symbols; higher is cleaner, below 2 is marginal.
+    pub quality: f32,
+    pub bytes: Result<Vec<u8>, AILLError>,
+    /// Confidence (0..=1) of each decoded byte: the lower of its two
+    /// symbols' carrier margin and band exclusivity.
+    pub confidence: Vec<f32>,
+    /// Indices of bytes below the decoder's confidence floor, for an
+    /// erasure-correcting FEC layer to fill in.
+    pub erasures: Vec<usize>,
+    /// Sender named by a station-ID tail after the end chirp.
+    pub station: Option<AgentId>,
+}
+
+/// Symbols read from one transmission.
+struct Scan {
+    symbols: Vec<Symbol>,
+    /// Frames examined before the end chirp or the end of the audio.
+    frames: usize,
+    quality: f32,
+}
+
 impl AcousticDecoder {
     pub fn new() -> Self {
         Self {
             sample_rate: DEFAULT_SAMPLE_RATE,
+            address: None,
+            promiscuous: false,
+            noise: None,
+            carrier_weights: [1.0; NUM_CARRIERS],
+            confidence_floor: DEFAULT_CONFIDENCE_FLOOR,
+            plan: cached_plan(DEFAULT_SAMPLE_RATE),
+            scratch: Mutex::default(),
         }
     }
 
@@ -38,55 +158,283 @@ impl AcousticDecoder {
                 sample_rate, MIN_SAMPLE_RATE
             )));
         }
-        Ok(Self { sample_rate })
+        Ok(Self { sample_rate, plan: cached_plan(sample_rate), ..Self::new() })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Use a noise profile learned earlier, e.g. loaded with
+    /// [`NoiseProfile::load`].
+    pub fn with_noise_profile(mut self, profile: NoiseProfile) -> Result<Self, AILLError> {
+        if profile.sample_rate != self.sample_rate || profile.mean.len() != FFT_SIZE / 2 {
+            return Err(AILLError::InvalidStructure(
+                "Noise profile does not match decoder sample rate or FFT size".into(),
+            ));
+        }
+        self.noise = Some(profile);
+        Ok(self)
+    }
+
+    pub fn noise_profile(&self) -> Option<&NoiseProfile> {
+        self.noise.as_ref()
+    }
+
+    pub fn clear_noise(&mut self) {
+        self.noise = None;
+    }
+
+    /// Learn the background noise from ambient audio recorded while nobody
+    /// transmits, adding to what was learned before. From then on each
+    /// carrier's noise floor is subtracted and tones must clear the noise
+    /// by [`NOISE_SIGMA`] deviations, so a steady hum on one carrier no
+    /// longer reads as a tone.
+    pub fn learn_noise(&mut self, samples: &[f32]) -> Result<(), AILLError> {
+        if samples.len() < FFT_SIZE {
+            return Err(AILLError::InvalidStructure(
+                "Audio too short for FFT analysis".into(),
+            ));
+        }
+        let spectra: Vec<Vec<f32>> = (0..=samples.len() - FFT_SIZE)
+            .step_by(FFT_SIZE / 2)
+            .map(|pos| self.compute_magnitudes(&samples[pos..pos + FFT_SIZE]))
+            .collect();
+        let learned = NoiseProfile::from_spectra(self.sample_rate, &spectra);
+        match &mut self.noise {
+            Some(profile) => profile.merge(&learned),
+            None => {
+                self.noise = Some(learned);
+                Ok(())
+            }
+        }
+    }
+
+    /// Only decode transmissions addressed to `agent` or to everyone. The
+    /// destination is read from the first [`ADDRESS_PREFIX_BYTES`] bytes;
+    /// transmissions for other agents fail with
+    /// [`AILLError::NotAddressed`] without decoding the rest.
+    pub fn with_address(mut self, agent: AgentId) -> Self {
+        self.address = Some(agent);
+        self
+    }
+
+    /// Decode every transmission regardless of [`with_address`](Self::with_address),
+    /// e.g. for a sniffer.
+    pub fn with_promiscuous(mut self, promiscuous: bool) -> Self {
+        self.promiscuous = promiscuous;
+        self
+    }
+
+    pub fn set_promiscuous(&mut self, promiscuous: bool) {
+        self.promiscuous = promiscuous;
+    }
+
+    /// Scale the tone threshold of each carrier by its weight (clamped to
+    /// `1 / MAX_CARRIER_GAIN..=MAX_CARRIER_GAIN`), so that carriers the
+    /// microphone hears quieter still register, e.g. with
+    /// [`LatencyProfile::carrier_weights`](super::LatencyProfile::carrier_weights)
+    /// from calibration.
+    pub fn with_carrier_weights(mut self, weights: [f32; NUM_CARRIERS]) -> Self {
+        self.carrier_weights = weights.map(|w| w.clamp(1.0 / MAX_CARRIER_GAIN, MAX_CARRIER_GAIN));
+        self
+    }
+
+    /// Symbol confidence (0..=1) below which bytes are reported in
+    /// [`DecodeResult::erasures`].
+    pub fn with_confidence_floor(mut self, floor: f32) -> Self {
+        self.confidence_floor = floor.clamp(0.0, 1.0);
+        self
     }
 
     /// Decode PCM f32 samples into wire bytes.
     pub fn decode(&self, samples: &[f32]) -> Result<Vec<u8>, AILLError> {
+        self.decode_detailed(samples)?.bytes
+    }
+
+    /// Decode the first transmission in `samples`, with its offsets,
+    /// quality and per-byte confidence.
+    pub fn decode_detailed(&self, samples: &[f32]) -> Result<DecodeResult, AILLError> {
         if samples.len() < FFT_SIZE {
             return Err(AILLError::InvalidStructure(
                 "Audio too short for FFT analysis".into(),
             ));
         }
 
-        // Precompute Hann window and FFT plan
-        let window: Vec<f32> = (0..FFT_SIZE)
-            .map(|i| 0.5 * (1.0 - (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
-            .collect();
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(FFT_SIZE);
+        self.decode_first(samples)
+    }
 
+    /// Decode every transmission in a long capture, in order. Each one is
+    /// synced and thresholded on its own; one that fails to decode is
+    /// reported with its error and the search goes on after it.
+    pub fn decode_all(&self, samples: &[f32]) -> Vec<DecodeResult> {
+        let mut results = Vec::new();
+        let mut pos = 0;
+        while samples.len() - pos >= FFT_SIZE {
+            let Ok(mut found) = self.decode_first(&samples[pos..]) else {
+                break;
+            };
+            found.start += pos;
+            found.end += pos;
+            pos = found.end;
+            // Sync-like noise with no tones after it is not a transmission
+            if found.bytes.is_ok() || found.quality > 0.0 {
+                results.push(found);
+            }
+        }
+        results
+    }
+
+    /// Decode the first transmission in `samples`. Fails only if no sync
+    /// chirp is found; decoding errors are left in the result.
+    fn decode_first(&self, samples: &[f32]) -> Result<DecodeResult, AILLError> {
         // Phase 1: Find sync chirp — returns the sample offset where data begins
-        let data_start_sample = self.find_sync(samples, &window, &fft)?;
+        let (chirp_start, data_start_sample, gain) = self.find_sync(samples)?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(chirp_start, data_start_sample, gain, "sync chirp detected");
+
+        // A located end chirp bounds the data, so a following transmission
+        // is not read as part of this one
+        let end_samples = (END_DURATION * self.sample_rate as f32).round() as usize;
+        let chirp_end = self
+            .find_end_chirp(samples, data_start_sample, gain)
+            .map(|pos| (pos + end_samples).min(samples.len()));
+        let station = chirp_end.and_then(|end| self.read_station(samples, end).ok());
+        let samples = &samples[..chirp_end.unwrap_or(samples.len())];
+        let end_of =
+            |frames: usize| chirp_end.unwrap_or(data_start_sample + self.frames_to_end(frames));
+        let result = |end: usize, quality: f32, bytes, confidence: Vec<f32>| DecodeResult {
+            start: chirp_start,
+            end: station.map_or(end.min(samples.len()).max(data_start_sample), |(_, tail_end)| tail_end),
+            quality,
+            bytes,
+            erasures: (0..confidence.len())
+                .filter(|&i| confidence[i] < self.confidence_floor)
+                .collect(),
+            confidence,
+            station: station.map(|(id, _)| id),
+        };
 
         // Phase 2: Compute adaptive threshold by scanning the data region
-        let tone_threshold = self.compute_tone_threshold(
-            samples, data_start_sample, &window, &fft,
-        );
+        let tone_threshold = self.compute_tone_threshold(samples, data_start_sample);
+
+        // Optional: drop transmissions for other agents after the header
+        if let (Some(agent), false) = (&self.address, self.promiscuous) {
+            let prefix = self.decode_symbols_fixed(
+                samples, data_start_sample, tone_threshold, 2 * ADDRESS_PREFIX_BYTES,
+            );
+            // A bare utterance, or one behind a 4-byte epoch header
+            let (bytes, _) = reassemble_bytes(&prefix.symbols);
+            let verdict = peek_addressed_to(&bytes, agent)
+                .or_else(|| peek_addressed_to(bytes.get(4..)?, agent));
+            if verdict == Some(false) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(chirp_start, "transmission addressed to another agent");
+                let end = end_of(prefix.frames);
+                return Ok(result(end, prefix.quality, Err(AILLError::NotAddressed), Vec::new()));
+            }
+        }
 
         // Phase 3: Decode symbols at exact frame intervals from sync point
-        let symbols = self.decode_symbols_fixed(
-            samples, data_start_sample, tone_threshold, &window, &fft,
+        let scan = self.decode_symbols_fixed(
+            samples, data_start_sample, tone_threshold, MAX_DECODE_FRAMES,
         );
+        let end = end_of(scan.frames);
 
         // Phase 4: Reassemble bytes
-        let bytes = reassemble_bytes(&symbols);
+        let (bytes, confidence) = reassemble_bytes(&scan.symbols);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            bytes = bytes.len(),
+            frames = scan.frames,
+            quality = scan.quality,
+            erasures = confidence.iter().filter(|&&c| c < self.confidence_floor).count(),
+            "symbols decoded"
+        );
         if bytes.is_empty() {
-            return Err(AILLError::InvalidStructure(
-                "No bytes recovered from audio".into(),
-            ));
+            let error = AILLError::InvalidStructure("No bytes recovered from audio".into());
+            return Ok(result(end, scan.quality, Err(error), confidence));
         }
 
-        Ok(bytes)
+        Ok(result(end, scan.quality, Ok(bytes), confidence))
     }
 
-    /// Find the sync chirp and return the sample offset where data begins.
-    fn find_sync(
-        &self,
-        samples: &[f32],
-        window: &[f32],
-        fft: &std::sync::Arc<dyn rustfft::Fft<f32>>,
-    ) -> Result<usize, AILLError> {
+    /// The sender named by the station-ID tail of the first transmission in
+    /// `samples`, read without decoding the payload, e.g. to attribute
+    /// traffic while listening passively.
+    pub fn station_id(&self, samples: &[f32]) -> Result<AgentId, AILLError> {
+        let end_samples = (END_DURATION * self.sample_rate as f32).round() as usize;
+        let (pos, _) = self
+            .plan
+            .locate(&self.plan.end_chirp, samples, None)
+            .ok_or_else(|| AILLError::InvalidStructure("End chirp not found".into()))?;
+        self.read_station(samples, pos + end_samples).map(|(id, _)| id)
+    }
+
+    /// Read a station-ID tail following an end chirp that stops at `from`,
+    /// returning the agent and the sample offset past the tail.
+    fn read_station(&self, samples: &[f32], from: usize) -> Result<(AgentId, usize), AILLError> {
+        let sr = self.sample_rate as f32;
+        let start = from + (STATION_GAP * sr).round() as usize;
+        let frame_samples = (STATION_FRAME_TIME * sr).round() as usize;
+        let sym_center_offset = (STATION_SYMBOL_DURATION * sr / 2.0).round() as usize;
+        let missing = || AILLError::InvalidStructure("No station-ID tail".into());
+
+        let symbols = (0..STATION_ID_BYTES)
+            .map(|n| {
+                let begin = (start + n * frame_samples + sym_center_offset).checked_sub(FFT_SIZE / 2)?;
+                let frame = samples.get(begin..begin + FFT_SIZE)?;
+                Some(self.carrier_mags(&self.compute_magnitudes(frame)))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(missing)?;
+        let loudest = symbols.iter().flatten().copied().fold(0.0f32, f32::max);
+        if loudest < ABS_THRESHOLD {
+            return Err(missing());
+        }
+
+        // Every carrier carries one bit, lo band in the low nibble
+        let thresholds = self.carrier_weights.map(|w| (0.4 * loudest).max(ABS_THRESHOLD) * w);
+        let tail: Vec<u8> = symbols
+            .iter()
+            .map(|mags| (0..NUM_CARRIERS).filter(|&c| mags[c] > thresholds[c]).fold(0, |b, c| b | 1 << c))
+            .collect();
+        let mut id = [0u8; 16];
+        id.copy_from_slice(&tail[..16]);
+        let (expected, actual) = (tail[16], crc8(&id));
+        if expected != actual {
+            return Err(AILLError::CrcMismatch { expected, actual });
+        }
+        Ok((AgentId::new(id), start + STATION_ID_BYTES * frame_samples))
+    }
+
+    /// Signal level on the channel in the last `FFT_SIZE` samples: the
+    /// strongest carrier or the stronger sync chirp band, whichever is
+    /// higher, as a linear magnitude comparable to [`ABS_THRESHOLD`].
+    /// Shorter input is zero-padded.
+    pub fn channel_energy(&self, samples: &[f32]) -> f32 {
+        let sr = self.sample_rate as f32;
+        let mut frame = vec![0.0f32; FFT_SIZE];
+        let tail = &samples[samples.len().saturating_sub(FFT_SIZE)..];
+        frame[..tail.len()].copy_from_slice(tail);
+
+        let magnitudes = self.compute_magnitudes(&frame);
+
+        let carriers = self.carrier_mags(&magnitudes).into_iter().fold(0.0f32, f32::max);
+        let lo = band_energy(&magnitudes, SYNC_LO_BAND.0, SYNC_LO_BAND.1, sr);
+        let hi = band_energy(&magnitudes, SYNC_HI_BAND.0, SYNC_HI_BAND.1, sr);
+        carriers.max(lo).max(hi)
+    }
+
+    /// Whether the last `FFT_SIZE` samples carry energy above `threshold`
+    /// (e.g. [`ABS_THRESHOLD`]) on the AILL carriers or chirp bands.
+    pub fn channel_busy(&self, samples: &[f32], threshold: f32) -> bool {
+        self.channel_energy(samples) > threshold
+    }
+
+    /// Find the sync chirp and return the sample offsets where it starts
+    /// and where data begins, and its gain when it was matched exactly.
+    fn find_sync(&self, samples: &[f32]) -> Result<(usize, usize, Option<f32>), AILLError> {
         let sr = self.sample_rate as f32;
         let hop = (0.008 * sr).round() as usize; // 8ms hop for finer sync resolution
 
@@ -96,7 +444,7 @@ impl AcousticDecoder {
 
         let mut pos = 0;
         while pos + FFT_SIZE <= samples.len() {
-            let magnitudes = self.compute_magnitudes(&samples[pos..pos + FFT_SIZE], window, fft);
+            let magnitudes = self.compute_magnitudes(&samples[pos..pos + FFT_SIZE]);
             let lo = band_energy(&magnitudes, SYNC_LO_BAND.0, SYNC_LO_BAND.1, sr);
             let hi = band_energy(&magnitudes, SYNC_HI_BAND.0, SYNC_HI_BAND.1, sr);
             lo_energies.push((pos, lo));
@@ -152,21 +500,26 @@ impl AcousticDecoder {
         // which is near the end of the chirp. Add a small margin for the chirp
         // to finish and the guard silence before the first data symbol.
         let chirp_end_pos = hi_energies[chirp_end_idx].0 + FFT_SIZE / 2;
-        let sync_based = chirp_start_pos + (SYNC_DURATION * sr).round() as usize;
+        let sync_samples = (SYNC_DURATION * sr).round() as usize;
+
+        // The energy windows only place the chirp to within a window length;
+        // a matched filter on the chirp itself pins it to the sample
+        let search_end = samples.len().min(chirp_end_pos + FFT_SIZE + sync_samples);
+        let region = &samples[chirp_start_pos..search_end];
+        if let Some((pos, gain)) = self.plan.locate(&self.plan.sync_chirp, region, None) {
+            let start = chirp_start_pos + pos;
+            return Ok((start, start + sync_samples, Some(gain)));
+        }
+
+        let sync_based = chirp_start_pos + sync_samples;
         // Use the later of the two estimates to avoid overlapping with the chirp tail
         let data_start = sync_based.max(chirp_end_pos);
 
-        Ok(data_start)
+        Ok((chirp_start_pos, data_start, None))
     }
 
     /// Compute an adaptive tone detection threshold by scanning data region.
-    fn compute_tone_threshold(
-        &self,
-        samples: &[f32],
-        data_start: usize,
-        window: &[f32],
-        fft: &std::sync::Arc<dyn rustfft::Fft<f32>>,
-    ) -> f32 {
+    fn compute_tone_threshold(&self, samples: &[f32], data_start: usize) -> f32 {
         let sr = self.sample_rate as f32;
         let frame_samples = (FRAME_TIME * sr).round() as usize;
         let sym_center_offset = (SYMBOL_DURATION * sr / 2.0).round() as usize;
@@ -181,10 +534,8 @@ impl AcousticDecoder {
                 break;
             }
 
-            let magnitudes = self.compute_magnitudes(&samples[start..start + FFT_SIZE], window, fft);
-            for &freq in &CARRIER_FREQS {
-                all_mags.push(get_bin_mag(&magnitudes, freq, sr));
-            }
+            let magnitudes = self.compute_magnitudes(&samples[start..start + FFT_SIZE]);
+            all_mags.extend(self.carrier_mags(&magnitudes));
         }
 
         if all_mags.is_empty() {
@@ -215,7 +566,8 @@ impl AcousticDecoder {
         .max(ABS_THRESHOLD)
     }
 
-    /// Decode data symbols at fixed frame intervals from the sync point.
+    /// Decode data symbols at fixed frame intervals from the sync point,
+    /// looking at no more than `max_frames` frames.
     ///
     /// Two-pass approach:
     /// 1. Scan all frames, recording detected tones and marking silent slots
@@ -232,31 +584,36 @@ impl AcousticDecoder {
         samples: &[f32],
         data_start: usize,
         threshold: f32,
-        window: &[f32],
-        fft: &std::sync::Arc<dyn rustfft::Fft<f32>>,
-    ) -> Vec<Symbol> {
+        max_frames: usize,
+    ) -> Scan {
         let sr = self.sample_rate as f32;
         let frame_samples = (FRAME_TIME * sr).round() as usize;
         let sym_center_offset = (SYMBOL_DURATION * sr / 2.0).round() as usize;
 
+        // Tones must also stand clear of the learned noise on their carrier
+        let mut thresholds = self.carrier_weights.map(|w| threshold * w);
+        if let Some(noise) = &self.noise {
+            for (t, &freq) in thresholds.iter_mut().zip(&CARRIER_FREQS) {
+                *t = t.max(NOISE_SIGMA * get_bin_mag(&noise.deviation, freq, sr));
+            }
+        }
+
         // Pass 1: Analyze all frame positions, detect tones and end chirp
         let mut frame_results: Vec<Option<Symbol>> = Vec::new();
+        let mut silence_confidence: Vec<f32> = Vec::new();
+        let (mut margin_sum, mut margin_count) = (0.0f32, 0usize);
 
-        for n in 0..MAX_DECODE_FRAMES {
+        for n in 0..max_frames.min(MAX_DECODE_FRAMES) {
             let center = data_start + n * frame_samples + sym_center_offset;
             let start = center.saturating_sub(FFT_SIZE / 2);
             if start + FFT_SIZE > samples.len() {
                 break;
             }
 
-            let magnitudes =
-                self.compute_magnitudes(&samples[start..start + FFT_SIZE], window, fft);
+            let magnitudes = self.compute_magnitudes(&samples[start..start + FFT_SIZE]);
             let hi_band = band_energy(&magnitudes, SYNC_HI_BAND.0, SYNC_HI_BAND.1, sr);
 
-            let mut carrier_mags = [0.0f32; NUM_CARRIERS];
-            for i in 0..NUM_CARRIERS {
-                carrier_mags[i] = get_bin_mag(&magnitudes, CARRIER_FREQS[i], sr);
-            }
+            let carrier_mags = self.carrier_mags(&magnitudes);
 
             // End chirp detection: broadband hi-band energy without strong carrier tones
             if frame_results.len() > 2 {
@@ -269,7 +626,17 @@ impl AcousticDecoder {
                 }
             }
 
-            frame_results.push(decode_tone_symbol(&carrier_mags, threshold));
+            // Hi and lo nibbles alternate from the sync point
+            let slot = if n % 2 == 0 { Half::Hi } else { Half::Lo };
+            let symbol = decode_tone_symbol(&carrier_mags, &thresholds, slot);
+            if symbol.is_some() {
+                let active = carrier_mags.iter().zip(&thresholds).filter(|(m, t)| m > t);
+                let (sum, count) = active.fold((0.0, 0), |(s, c), (m, t)| (s + m / t, c + 1));
+                margin_sum += sum / count as f32;
+                margin_count += 1;
+            }
+            frame_results.push(symbol);
+            silence_confidence.push(quiet_confidence(&carrier_mags, &thresholds));
         }
 
         // Pass 2: Find the last frame that has a detected tone.
@@ -302,28 +669,66 @@ impl AcousticDecoder {
                 None => {
                     // Silent slot = nibble value 0, half determined by position
                     let half = if n % 2 == 0 { Half::Hi } else { Half::Lo };
-                    symbols.push(Symbol { half, value: 0 });
+                    symbols.push(Symbol { half, value: 0, confidence: silence_confidence[n] });
                 }
             }
         }
 
-        symbols
+        Scan {
+            symbols,
+            frames: frame_results.len(),
+            quality: if margin_count > 0 { margin_sum / margin_count as f32 } else { 0.0 },
+        }
     }
 
-    /// Run FFT on a windowed frame and return magnitude spectrum.
-    fn compute_magnitudes(
+    /// Start of the first end chirp after `from` that arrives at half or
+    /// more of the sync chirp's gain.
+    fn find_end_chirp(
         &self,
-        frame: &[f32],
-        window: &[f32],
-        fft: &std::sync::Arc<dyn rustfft::Fft<f32>>,
-    ) -> Vec<f32> {
-        let mut buffer: Vec<Complex<f32>> = frame
-            .iter()
-            .zip(window.iter())
-            .map(|(&s, &w)| Complex::new(s * w, 0.0))
-            .collect();
+        samples: &[f32],
+        from: usize,
+        sync_gain: Option<f32>,
+    ) -> Option<usize> {
+        let reference = &self.plan.end_chirp;
+        let frame_samples = (FRAME_TIME * self.sample_rate as f32).round() as usize;
+        let limit = samples.len().min(from + MAX_DECODE_FRAMES * frame_samples + reference.len());
+        let min_gain = sync_gain.map_or(0.0, |g| 0.5 * g);
+        self.plan.locate(reference, samples.get(from..limit)?, Some(min_gain))
+            .map(|(pos, _)| from + pos)
+    }
+
+    /// Samples from the start of the data to the end of the end chirp,
+    /// after `frames` data frames, when the chirp itself was not found.
+    fn frames_to_end(&self, frames: usize) -> usize {
+        let sr = self.sample_rate as f32;
+        frames * (FRAME_TIME * sr).round() as usize + (END_DURATION * sr).round() as usize
+    }
 
-        fft.process(&mut buffer);
+    /// Magnitude at each carrier, less the learned noise floor.
+    fn carrier_mags(&self, magnitudes: &[f32]) -> [f32; NUM_CARRIERS] {
+        let sr = self.sample_rate as f32;
+        let mut mags = [0.0f32; NUM_CARRIERS];
+        for (m, &freq) in mags.iter_mut().zip(&CARRIER_FREQS) {
+            let floor = self.noise.as_ref().map_or(0.0, |n| get_bin_mag(&n.mean, freq, sr));
+            *m = (get_bin_mag(magnitudes, freq, sr) - floor).max(0.0);
+        }
+        mags
+    }
+
+    /// Run FFT on a windowed frame and return magnitude spectrum.
+    fn compute_magnitudes(&self, frame: &[f32]) -> Vec<f32> {
+        // Another thread decoding with this decoder holds the shared buffers
+        let mut own = Scratch::default();
+        let mut shared = match self.scratch.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        };
+        let Scratch { buffer, fft } = shared.as_deref_mut().unwrap_or(&mut own);
+        buffer.clear();
+        buffer.extend(frame.iter().zip(&self.plan.window).map(|(&s, &w)| Complex::new(s * w, 0.0)));
+        fft.resize(self.plan.fft.get_inplace_scratch_len(), Complex::new(0.0, 0.0));
+        self.plan.fft.process_with_scratch(buffer, fft);
 
         let n = FFT_SIZE / 2;
         let scale = 2.0 / FFT_SIZE as f32;
@@ -340,6 +745,13 @@ impl Default for AcousticDecoder {
     }
 }
 
+/// Hann window of `FFT_SIZE` samples.
+fn hann_window() -> Vec<f32> {
+    (0..FFT_SIZE)
+        .map(|i| 0.5 * (1.0 - (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
+        .collect()
+}
+
 /// Convert Hz to FFT bin index.
 fn freq_to_bin(freq: f32, sample_rate: f32) -> usize {
     (freq * FFT_SIZE as f32 / sample_rate).round() as usize
@@ -358,6 +770,47 @@ fn band_energy(magnitudes: &[f32], lo_hz: f32, hi_hz: f32, sample_rate: f32) ->
     sum / (b - a + 1) as f32
 }
 
+/// Start and gain of the first occurrence of `reference` in `samples`,
+/// found by normalized cross-correlation. A match scores at least
+/// [`CHIRP_MATCH_SCORE`] at a gain of at least `min_gain`, by default half
+/// the strongest correlation in `samples`; the gain keeps near-silent
+/// stretches from matching. Returns the strongest correlation within one
+/// chirp length of the first match. `plans` supplies the FFTs, as for
+/// [`cross_correlate_with`].
+fn locate_chirp(
+    reference: &[f32],
+    samples: &[f32],
+    min_gain: Option<f32>,
+    plans: impl FnOnce(usize) -> (Arc<dyn Fft<f32>>, Arc<dyn Fft<f32>>),
+) -> Option<(usize, f32)> {
+    if reference.is_empty() || samples.len() < reference.len() {
+        return None;
+    }
+    let corr = cross_correlate_with(reference, samples, plans);
+    let ref_energy: f32 = reference.iter().map(|s| s * s).sum();
+    let min_corr = match min_gain {
+        Some(gain) => gain * ref_energy,
+        None => 0.5 * corr.iter().copied().fold(0.0f32, f32::max),
+    };
+    let mut seg_energy: f32 = samples[..reference.len()].iter().map(|s| s * s).sum();
+    let (mut first, mut best) = (None, (0, f32::MIN));
+    for (k, &c) in corr.iter().enumerate() {
+        if k > 0 {
+            seg_energy += samples[k + reference.len() - 1].powi(2) - samples[k - 1].powi(2);
+        }
+        let score = c / (ref_energy * seg_energy.max(1e-12)).sqrt();
+        if first.is_none() && score >= CHIRP_MATCH_SCORE && c >= min_corr {
+            first = Some(k);
+        }
+        match first {
+            Some(f) if k >= f + reference.len() => break,
+            Some(_) if c > best.1 => best = (k, c),
+            _ => {}
+        }
+    }
+    first.map(|_| (best.0, best.1 / ref_energy))
+}
+
 /// Get peak magnitude at a carrier frequency (target bin + neighbors).
 fn get_bin_mag(magnitudes: &[f32], freq: f32, sample_rate: f32) -> f32 {
     let bin = freq_to_bin(freq, sample_rate);
@@ -380,52 +833,64 @@ fn extract_nibble(active: u8, carrier_offset: usize) -> u8 {
     n
 }
 
-/// Detect which carriers are active and return a Symbol, or None if silence.
-fn decode_tone_symbol(carrier_mags: &[f32; NUM_CARRIERS], threshold: f32) -> Option<Symbol> {
+/// Detect which carriers are active and return the Symbol for a frame in
+/// the `slot` half, or None if silence. Tones in the other band, e.g. from
+/// chirp leakage, lower the symbol's confidence instead of changing its half.
+fn decode_tone_symbol(
+    carrier_mags: &[f32; NUM_CARRIERS],
+    thresholds: &[f32; NUM_CARRIERS],
+    slot: Half,
+) -> Option<Symbol> {
     let mut active: u8 = 0;
-    let mut lo_any = false;
-    let mut hi_any = false;
-
     for i in 0..NUM_CARRIERS {
-        if carrier_mags[i] > threshold {
+        if carrier_mags[i] > thresholds[i] {
             active |= 1 << i;
-            if i < 4 {
-                lo_any = true;
-            } else {
-                hi_any = true;
-            }
         }
     }
 
-    if !lo_any && !hi_any {
+    if active == 0 {
         return None;
     }
 
-    let (half, nibble) = if hi_any && !lo_any {
-        (Half::Hi, extract_nibble(active, HI_CARRIER_OFFSET))
-    } else if lo_any && !hi_any {
-        (Half::Lo, extract_nibble(active, LO_CARRIER_OFFSET))
-    } else {
-        // Both bands active — pick the stronger one
-        let lo_strength: f32 = carrier_mags[..4].iter().sum();
-        let hi_strength: f32 = carrier_mags[4..].iter().sum();
-
-        if hi_strength > lo_strength {
-            (Half::Hi, extract_nibble(active, HI_CARRIER_OFFSET))
-        } else {
-            (Half::Lo, extract_nibble(active, LO_CARRIER_OFFSET))
-        }
+    let (offset, other_offset) = match slot {
+        Half::Hi => (HI_CARRIER_OFFSET, LO_CARRIER_OFFSET),
+        Half::Lo => (LO_CARRIER_OFFSET, HI_CARRIER_OFFSET),
     };
 
+    // Margin: how far the closest on/off call in the band is from its threshold
+    let margin = (offset..offset + 4)
+        .map(|i| {
+            let ratio = carrier_mags[i] / thresholds[i];
+            if ratio > 1.0 { 1.0 - 1.0 / ratio } else { 1.0 - ratio }
+        })
+        .fold(1.0f32, f32::min);
+    // Exclusivity: how little energy the other band carries
+    let own: f32 = carrier_mags[offset..offset + 4].iter().sum();
+    let other: f32 = carrier_mags[other_offset..other_offset + 4].iter().sum();
+    let exclusivity = (1.0 - other / own).clamp(0.0, 1.0);
+
     Some(Symbol {
-        half,
-        value: nibble,
+        half: slot,
+        value: extract_nibble(active, offset),
+        confidence: margin.min(exclusivity),
     })
 }
 
-/// Reassemble paired symbols into bytes.
-fn reassemble_bytes(symbols: &[Symbol]) -> Vec<u8> {
+/// Confidence that a frame with no carrier over its threshold is silent.
+fn quiet_confidence(carrier_mags: &[f32; NUM_CARRIERS], thresholds: &[f32; NUM_CARRIERS]) -> f32 {
+    let loudest = carrier_mags
+        .iter()
+        .zip(thresholds)
+        .map(|(m, t)| m / t)
+        .fold(0.0f32, f32::max);
+    (1.0 - loudest).clamp(0.0, 1.0)
+}
+
+/// Reassemble paired symbols into bytes, each with the lower confidence
+/// of its two symbols.
+fn reassemble_bytes(symbols: &[Symbol]) -> (Vec<u8>, Vec<f32>) {
     let mut bytes = Vec::new();
+    let mut confidence = Vec::new();
     let mut i = 0;
 
     while i + 1 < symbols.len() {
@@ -440,10 +905,12 @@ fn reassemble_bytes(symbols: &[Symbol]) -> Vec<u8> {
             i += 2;
         } else {
             i += 1; // skip mismatched symbol
+            continue;
         }
+        confidence.push(s1.confidence.min(s2.confidence));
     }
 
-    bytes
+    (bytes, confidence)
 }
 
 #[cfg(test)]
@@ -460,31 +927,60 @@ mod tests {
     #[test]
     fn test_reassemble_normal_order() {
         let symbols = vec![
-            Symbol { half: Half::Hi, value: 0x4 },
-            Symbol { half: Half::Lo, value: 0x2 },
+            Symbol { half: Half::Hi, value: 0x4, confidence: 1.0 },
+            Symbol { half: Half::Lo, value: 0x2, confidence: 1.0 },
         ];
-        let bytes = reassemble_bytes(&symbols);
+        let (bytes, _) = reassemble_bytes(&symbols);
         assert_eq!(bytes, vec![0x42]);
     }
 
     #[test]
     fn test_reassemble_reversed_order() {
         let symbols = vec![
-            Symbol { half: Half::Lo, value: 0x2 },
-            Symbol { half: Half::Hi, value: 0x4 },
+            Symbol { half: Half::Lo, value: 0x2, confidence: 1.0 },
+            Symbol { half: Half::Hi, value: 0x4, confidence: 1.0 },
         ];
-        let bytes = reassemble_bytes(&symbols);
+        let (bytes, _) = reassemble_bytes(&symbols);
         assert_eq!(bytes, vec![0x42]);
     }
 
     #[test]
     fn test_reassemble_skip_mismatch() {
         let symbols = vec![
-            Symbol { half: Half::Hi, value: 0xA },
-            Symbol { half: Half::Hi, value: 0xB },
-            Symbol { half: Half::Lo, value: 0x3 },
+            Symbol { half: Half::Hi, value: 0xA, confidence: 1.0 },
+            Symbol { half: Half::Hi, value: 0xB, confidence: 1.0 },
+            Symbol { half: Half::Lo, value: 0x3, confidence: 1.0 },
         ];
-        let bytes = reassemble_bytes(&symbols);
+        let (bytes, _) = reassemble_bytes(&symbols);
         assert_eq!(bytes, vec![0xB3]);
     }
+
+    #[test]
+    fn shared_decoder_decodes_on_many_threads() {
+        let wire = [0x00, 0x90, 0x3C, 0x00, 0x91, 0x03, 0x42, 0x01];
+        let samples = AcousticEncoder::new().encode(&wire).unwrap().samples;
+        let decoder = AcousticDecoder::new();
+        assert!(Arc::ptr_eq(&decoder.plan, &AcousticDecoder::with_sample_rate(DEFAULT_SAMPLE_RATE).unwrap().plan));
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4).map(|_| scope.spawn(|| decoder.decode(&samples))).collect();
+            for thread in threads {
+                assert_eq!(thread.join().unwrap().unwrap(), wire);
+            }
+        });
+    }
+
+    #[test]
+    fn test_other_band_lowers_confidence_not_half() {
+        let thresholds = [1.0; NUM_CARRIERS];
+        let mut mags = [0.1; NUM_CARRIERS];
+        mags[HI_CARRIER_OFFSET] = 8.0;
+        let clean = decode_tone_symbol(&mags, &thresholds, Half::Hi).unwrap();
+        assert_eq!((clean.half, clean.value), (Half::Hi, 0x1));
+        assert!(clean.confidence > 0.8);
+
+        mags[LO_CARRIER_OFFSET + 2] = 12.0;
+        let leaked = decode_tone_symbol(&mags, &thresholds, Half::Hi).unwrap();
+        assert_eq!((leaked.half, leaked.value), (Half::Hi, 0x1));
+        assert!(leaked.confidence < DEFAULT_CONFIDENCE_FLOOR);
+    }
 }
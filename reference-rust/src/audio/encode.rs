@@ -1,6 +1,11 @@
 use std::f32::consts::PI;
 
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::agent::AgentId;
 use crate::error::AILLError;
+use crate::wire::crc8::crc8;
 
 use super::constants::*;
 
@@ -14,15 +19,75 @@ pub struct EncodedAudio {
     pub duration: f32,
 }
 
+/// Carrier phase offsets within a nibble's band when shaping symbols
+/// (Newman phases, πk²/4), so that the carriers do not peak together.
+const SHAPED_PHASES: [f32; BITS_PER_NIBBLE] = [0.0, PI / 4.0, PI, 9.0 * PI / 4.0];
+
+/// Transmission options for an [`AcousticEncoder`] that stay the same
+/// from message to message; see [`AcousticEncoder::with_profile`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct AcousticProfile {
+    /// Follow each transmission with a station-ID tail carrying this agent,
+    /// so passive listeners can tell who sent it without decoding the
+    /// payload; see [`AcousticDecoder::station_id`](super::AcousticDecoder::station_id).
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub station_id: Option<AgentId>,
+}
+
 /// Encodes AILL wire-format bytes into acoustic PCM audio.
 pub struct AcousticEncoder {
     sample_rate: u32,
+    shaping: Option<Shaping>,
+    carrier_gains: [f32; NUM_CARRIERS],
+    profile: AcousticProfile,
+}
+
+/// Output level set by [`AcousticEncoder::with_peak_level`].
+#[derive(Debug, Clone, Copy)]
+struct Shaping {
+    peak: f32,
+    /// Carrier gain for each nibble value, lo band then hi band, that
+    /// brings the symbol's loudest sample to `peak`.
+    gains: [[f32; 16]; 2],
+}
+
+impl Shaping {
+    fn new(sample_rate: u32, peak: f32, carrier_gains: &[f32; NUM_CARRIERS]) -> Self {
+        let sr = sample_rate as f32;
+        let sym_samples = (SYMBOL_DURATION * sr).round() as usize;
+        let mut gains = [[0.0; 16]; 2];
+        for (band, offset) in [LO_CARRIER_OFFSET, HI_CARRIER_OFFSET].into_iter().enumerate() {
+            for nibble in 1..16u8 {
+                // Loudest point of the carrier sum, at the samples actually played
+                let loudest = (0..sym_samples)
+                    .map(|i| {
+                        let t = i as f32 / sr;
+                        (0..BITS_PER_NIBBLE)
+                            .filter(|bit| nibble & (1 << bit) != 0)
+                            .map(|bit| {
+                                let phase = 2.0 * PI * CARRIER_FREQS[offset + bit] * t + SHAPED_PHASES[bit];
+                                phase.sin() * carrier_gains[offset + bit]
+                            })
+                            .sum::<f32>()
+                            .abs()
+                    })
+                    .fold(0.0f32, f32::max);
+                // Less a hair, so rounding cannot carry a sample past the peak
+                gains[band][nibble as usize] = peak * 0.9999 / (TONE_AMPLITUDE * loudest.max(1e-3));
+            }
+        }
+        Self { peak, gains }
+    }
 }
 
 impl AcousticEncoder {
     pub fn new() -> Self {
         Self {
             sample_rate: DEFAULT_SAMPLE_RATE,
+            shaping: None,
+            carrier_gains: [1.0; NUM_CARRIERS],
+            profile: AcousticProfile::default(),
         }
     }
 
@@ -33,11 +98,62 @@ impl AcousticEncoder {
                 sample_rate, MIN_SAMPLE_RATE
             )));
         }
-        Ok(Self { sample_rate })
+        Ok(Self { sample_rate, ..Self::new() })
+    }
+
+    /// Shape each symbol so its loudest sample reaches `peak` (clamped to
+    /// 0..=1), instead of playing every carrier at [`MASTER_GAIN`]; chirps
+    /// play at `peak` too. Carriers are also phase-offset so that they sum
+    /// to a lower peak. This raises the average output power several times
+    /// over without clipping; decoders are unaffected, as they adapt their
+    /// thresholds to the signal level.
+    pub fn with_peak_level(mut self, peak: f32) -> Self {
+        self.shaping = Some(Shaping::new(self.sample_rate, peak.clamp(0.0, 1.0), &self.carrier_gains));
+        self
+    }
+
+    /// Scale each carrier by its gain (clamped to 0..=[`MAX_CARRIER_GAIN`])
+    /// to make up for a speaker that plays some carriers quieter than
+    /// others, e.g. with [`LatencyProfile::carrier_gains`] from calibration.
+    ///
+    /// [`LatencyProfile::carrier_gains`]: super::LatencyProfile::carrier_gains
+    pub fn with_carrier_gains(mut self, gains: [f32; NUM_CARRIERS]) -> Self {
+        self.carrier_gains = gains.map(|g| g.clamp(0.0, MAX_CARRIER_GAIN));
+        if let Some(shaping) = self.shaping {
+            self.shaping = Some(Shaping::new(self.sample_rate, shaping.peak, &self.carrier_gains));
+        }
+        self
+    }
+
+    /// Apply the options in `profile`, such as a station-ID tail.
+    pub fn with_profile(mut self, profile: AcousticProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn profile(&self) -> &AcousticProfile {
+        &self.profile
     }
 
     /// Encode wire bytes into PCM audio.
     pub fn encode(&self, wire_bytes: &[u8]) -> Result<EncodedAudio, AILLError> {
+        let stream = self.encode_streaming(wire_bytes)?;
+        let duration = stream.duration();
+        let samples: Vec<f32> = stream.collect();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(bytes = wire_bytes.len(), samples = samples.len(), duration, "audio encoded");
+
+        Ok(EncodedAudio {
+            samples,
+            sample_rate: self.sample_rate,
+            duration,
+        })
+    }
+
+    /// Encode wire bytes into PCM audio synthesized as it is read, e.g. from
+    /// an output device callback. Gives the same samples as
+    /// [`encode`](Self::encode) without holding them all in memory.
+    pub fn encode_streaming(&self, wire_bytes: &[u8]) -> Result<EncodedStream, AILLError> {
         if wire_bytes.is_empty() {
             return Err(AILLError::EncoderError("Empty input".into()));
         }
@@ -50,128 +166,238 @@ impl AcousticEncoder {
         }
 
         let sr = self.sample_rate as f32;
-        let duration = SYNC_DURATION + (wire_bytes.len() as f32 * 2.0 * FRAME_TIME) + END_DURATION;
-        let total_samples = (duration * sr).ceil() as usize;
-        let mut samples = vec![0.0f32; total_samples];
-
-        let mut offset = 0usize;
-
-        // 1. Sync chirp (rising: 300 → 1800 Hz)
-        offset = self.write_chirp(
-            &mut samples,
-            offset,
-            SYNC_FREQ_START,
-            SYNC_FREQ_END,
-            SYNC_DURATION,
-        );
-
-        // 2. Data symbols: each byte → hi nibble then lo nibble
-        for &byte in wire_bytes {
-            let hi = (byte >> 4) & 0x0F;
-            let lo = byte & 0x0F;
-            offset = self.write_symbol(&mut samples, offset, hi, HI_CARRIER_OFFSET);
-            offset = self.write_symbol(&mut samples, offset, lo, LO_CARRIER_OFFSET);
-        }
-
-        // 3. End chirp (falling: 1800 → 300 Hz)
-        self.write_chirp(
-            &mut samples,
-            offset,
-            END_FREQ_START,
-            END_FREQ_END,
-            END_DURATION,
-        );
-
-        Ok(EncodedAudio {
-            samples,
+        let station = self.profile.station_id.map(|id| {
+            let mut tail = [0u8; STATION_ID_BYTES];
+            tail[..16].copy_from_slice(id.as_bytes());
+            tail[16] = crc8(id.as_bytes());
+            tail
+        });
+        let mut duration = SYNC_DURATION + (wire_bytes.len() as f32 * 2.0 * FRAME_TIME) + END_DURATION;
+        if station.is_some() {
+            duration += STATION_GAP + STATION_ID_BYTES as f32 * STATION_FRAME_TIME;
+        }
+        Ok(EncodedStream {
             sample_rate: self.sample_rate,
+            bytes: wire_bytes.to_vec(),
             duration,
+            pos: 0,
+            len: (duration * sr).ceil() as usize,
+            sync_samples: (SYNC_DURATION * sr).round() as usize,
+            frame_samples: (FRAME_TIME * sr).round() as usize,
+            end_samples: (END_DURATION * sr).round() as usize,
+            station,
+            gap_samples: (STATION_GAP * sr).round() as usize,
+            station_frame_samples: (STATION_FRAME_TIME * sr).round() as usize,
+            shaping: self.shaping,
+            carrier_gains: self.carrier_gains,
         })
     }
 
-    /// Write a linear frequency sweep (chirp) with linear attack/release envelope.
-    /// Returns the sample offset after the chirp.
-    fn write_chirp(
-        &self,
-        samples: &mut [f32],
-        start: usize,
-        f0: f32,
-        f1: f32,
-        duration: f32,
-    ) -> usize {
-        let sr = self.sample_rate as f32;
-        let num_samples = (duration * sr).round() as usize;
-        let attack_samples = ((CHIRP_ATTACK * sr).round() as usize).max(1);
-        let release_samples = ((CHIRP_RELEASE * sr).round() as usize).max(1);
+    /// The sync chirp on its own, e.g. as a reference signal for
+    /// latency calibration.
+    pub fn sync_chirp(&self) -> Vec<f32> {
+        let n = (SYNC_DURATION * self.sample_rate as f32).round() as usize;
+        let gain = chirp_gain(self.shaping.as_ref());
+        (0..n).map(|i| chirp_sample(self.sample_rate, i, n, SYNC_FREQ_START, SYNC_FREQ_END, SYNC_DURATION, gain)).collect()
+    }
 
-        for i in 0..num_samples {
-            if start + i >= samples.len() {
-                break;
-            }
-            let t = i as f32 / sr;
-
-            // Phase-correct linear chirp: φ(t) = 2π(f₀t + (f₁-f₀)t²/(2d))
-            let phase = 2.0 * PI * (f0 * t + (f1 - f0) * t * t / (2.0 * duration));
-            let signal = phase.sin();
-
-            // Envelope: linear attack/release
-            let env = if i < attack_samples {
-                i as f32 / attack_samples as f32
-            } else if i >= num_samples - release_samples {
-                (num_samples - 1 - i) as f32 / release_samples as f32
-            } else {
-                1.0
+    /// The end chirp on its own, e.g. to find where a transmission ends.
+    pub fn end_chirp(&self) -> Vec<f32> {
+        let n = (END_DURATION * self.sample_rate as f32).round() as usize;
+        let gain = chirp_gain(self.shaping.as_ref());
+        (0..n).map(|i| chirp_sample(self.sample_rate, i, n, END_FREQ_START, END_FREQ_END, END_DURATION, gain)).collect()
+    }
+}
+
+/// PCM samples of one transmission, synthesized on demand; see
+/// [`AcousticEncoder::encode_streaming`].
+#[derive(Debug, Clone)]
+pub struct EncodedStream {
+    sample_rate: u32,
+    bytes: Vec<u8>,
+    duration: f32,
+    pos: usize,
+    len: usize,
+    sync_samples: usize,
+    frame_samples: usize,
+    end_samples: usize,
+    /// Station-ID tail: agent UUID and CRC-8.
+    station: Option<[u8; STATION_ID_BYTES]>,
+    gap_samples: usize,
+    station_frame_samples: usize,
+    shaping: Option<Shaping>,
+    carrier_gains: [f32; NUM_CARRIERS],
+}
+
+impl EncodedStream {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Total duration in seconds.
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Samples not yet read.
+    pub fn remaining(&self) -> usize {
+        self.len - self.pos
+    }
+
+    /// Write the next samples into `out`, returning how many there were;
+    /// fewer than `out.len()` once the transmission ends.
+    pub fn fill(&mut self, out: &mut [f32]) -> usize {
+        let n = out.len().min(self.remaining());
+        for slot in &mut out[..n] {
+            *slot = self.sample(self.pos);
+            self.pos += 1;
+        }
+        n
+    }
+
+    /// Sample `i` of the transmission: sync chirp, data symbols (each byte
+    /// as hi nibble then lo nibble), end chirp, then the station-ID tail if
+    /// any, then silence.
+    fn sample(&self, i: usize) -> f32 {
+        let sr = self.sample_rate;
+        let shaping = self.shaping.as_ref();
+        let data_samples = self.bytes.len() * 2 * self.frame_samples;
+        if i < self.sync_samples {
+            let gain = chirp_gain(shaping);
+            return chirp_sample(sr, i, self.sync_samples, SYNC_FREQ_START, SYNC_FREQ_END, SYNC_DURATION, gain);
+        }
+        let i = i - self.sync_samples;
+        if i < data_samples {
+            let (symbol, at) = (i / self.frame_samples, i % self.frame_samples);
+            let byte = self.bytes[symbol / 2];
+            return match symbol % 2 {
+                0 => symbol_sample(sr, at, byte >> 4, HI_CARRIER_OFFSET, shaping, &self.carrier_gains),
+                _ => symbol_sample(sr, at, byte & 0x0F, LO_CARRIER_OFFSET, shaping, &self.carrier_gains),
             };
+        }
+        let i = i - data_samples;
+        if i < self.end_samples {
+            return chirp_sample(sr, i, self.end_samples, END_FREQ_START, END_FREQ_END, END_DURATION, chirp_gain(shaping));
+        }
+        let i = i - self.end_samples;
+        match &self.station {
+            Some(tail) if i >= self.gap_samples => {
+                let i = i - self.gap_samples;
+                let byte = tail.get(i / self.station_frame_samples).copied().unwrap_or(0);
+                station_sample(sr, i % self.station_frame_samples, byte, shaping)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl Iterator for EncodedStream {
+    type Item = f32;
 
-            samples[start + i] += signal * env * MASTER_GAIN;
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.len {
+            return None;
         }
+        self.pos += 1;
+        Some(self.sample(self.pos - 1))
+    }
 
-        start + num_samples
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining(), Some(self.remaining()))
     }
+}
 
-    /// Write a data symbol: activate carriers for set bits in the nibble.
-    /// `carrier_offset` is 0 for lo-nibble (600-900Hz) or 4 for hi-nibble (1000-1300Hz).
-    /// Returns the sample offset after the full frame (symbol + guard).
-    fn write_symbol(
-        &self,
-        samples: &mut [f32],
-        start: usize,
-        nibble: u8,
-        carrier_offset: usize,
-    ) -> usize {
-        let sr = self.sample_rate as f32;
-        let sym_samples = (SYMBOL_DURATION * sr).round() as usize;
-        let frame_samples = (FRAME_TIME * sr).round() as usize;
-        let attack_samples = ((TONE_ATTACK * sr).round() as usize).max(1);
-        let release_samples = ((TONE_RELEASE * sr).round() as usize).max(1);
+impl ExactSizeIterator for EncodedStream {}
 
-        for bit in 0..BITS_PER_NIBBLE {
-            if nibble & (1 << bit) == 0 {
-                continue;
-            }
-            let freq = CARRIER_FREQS[carrier_offset + bit];
+fn chirp_gain(shaping: Option<&Shaping>) -> f32 {
+    shaping.map_or(MASTER_GAIN, |s| s.peak)
+}
 
-            for i in 0..sym_samples {
-                if start + i >= samples.len() {
-                    break;
-                }
-                let t = i as f32 / sr;
-                let signal = (2.0 * PI * freq * t).sin();
-
-                // Envelope: 3ms attack to 0.8, hold, 3ms release
-                let env = if i < attack_samples {
-                    TONE_AMPLITUDE * (i as f32 / attack_samples as f32)
-                } else if i >= sym_samples - release_samples {
-                    TONE_AMPLITUDE * ((sym_samples - 1 - i) as f32 / release_samples as f32)
-                } else {
-                    TONE_AMPLITUDE
-                };
-
-                samples[start + i] += signal * env * MASTER_GAIN;
-            }
+/// Sample `i` of an `num_samples`-long linear frequency sweep (chirp) with
+/// linear attack/release envelope.
+fn chirp_sample(sample_rate: u32, i: usize, num_samples: usize, f0: f32, f1: f32, duration: f32, gain: f32) -> f32 {
+    let sr = sample_rate as f32;
+    let attack_samples = ((CHIRP_ATTACK * sr).round() as usize).max(1);
+    let release_samples = ((CHIRP_RELEASE * sr).round() as usize).max(1);
+    let t = i as f32 / sr;
+
+    // Phase-correct linear chirp: φ(t) = 2π(f₀t + (f₁-f₀)t²/(2d))
+    let phase = 2.0 * PI * (f0 * t + (f1 - f0) * t * t / (2.0 * duration));
+    let signal = phase.sin();
+
+    // Envelope: linear attack/release
+    let env = if i < attack_samples {
+        i as f32 / attack_samples as f32
+    } else if i >= num_samples - release_samples {
+        (num_samples - 1 - i) as f32 / release_samples as f32
+    } else {
+        1.0
+    };
+
+    signal * env * gain
+}
+
+/// Sample `i` of a data symbol's frame (symbol + guard): carriers for the
+/// set bits of the nibble, each scaled by its gain in `carrier_gains`.
+/// `carrier_offset` is 0 for lo-nibble (600-900Hz) or 4 for hi-nibble
+/// (1000-1300Hz).
+fn symbol_sample(
+    sample_rate: u32,
+    i: usize,
+    nibble: u8,
+    carrier_offset: usize,
+    shaping: Option<&Shaping>,
+    carrier_gains: &[f32; NUM_CARRIERS],
+) -> f32 {
+    let sr = sample_rate as f32;
+    let sym_samples = (SYMBOL_DURATION * sr).round() as usize;
+    if i >= sym_samples {
+        return 0.0;
+    }
+    let env = tone_envelope(sr, i, sym_samples);
+    let t = i as f32 / sr;
+    let gain = shaping.map_or(MASTER_GAIN, |s| s.gains[carrier_offset / BITS_PER_NIBBLE][nibble as usize]);
+    let mut sample = 0.0;
+    for bit in 0..BITS_PER_NIBBLE {
+        if nibble & (1 << bit) != 0 {
+            let freq = CARRIER_FREQS[carrier_offset + bit];
+            let phase = if shaping.is_some() { SHAPED_PHASES[bit] } else { 0.0 };
+            sample += (2.0 * PI * freq * t + phase).sin() * env * gain * carrier_gains[carrier_offset + bit];
         }
+    }
+    sample
+}
+
+/// Sample `i` of a station-ID frame: every carrier for the set bits of
+/// `byte`, hi nibble on the hi band, at the data tone level or, shaped, so
+/// that all eight together stay within the peak.
+fn station_sample(sample_rate: u32, i: usize, byte: u8, shaping: Option<&Shaping>) -> f32 {
+    let sr = sample_rate as f32;
+    let sym_samples = (STATION_SYMBOL_DURATION * sr).round() as usize;
+    if i >= sym_samples {
+        return 0.0;
+    }
+    let env = tone_envelope(sr, i, sym_samples);
+    let gain = shaping.map_or(MASTER_GAIN, |s| s.peak / (TONE_AMPLITUDE * NUM_CARRIERS as f32));
+    let t = i as f32 / sr;
+    let active = (byte & 0x0F) as usize | ((byte >> 4) as usize) << HI_CARRIER_OFFSET;
+    (0..NUM_CARRIERS)
+        .filter(|c| active & (1 << c) != 0)
+        .map(|c| (2.0 * PI * CARRIER_FREQS[c] * t).sin() * env * gain)
+        .sum()
+}
 
-        start + frame_samples
+/// Tone envelope at sample `i` of a `sym_samples`-long symbol: attack to
+/// [`TONE_AMPLITUDE`], hold, release.
+fn tone_envelope(sr: f32, i: usize, sym_samples: usize) -> f32 {
+    let attack_samples = ((TONE_ATTACK * sr).round() as usize).max(1);
+    let release_samples = ((TONE_RELEASE * sr).round() as usize).max(1);
+    if i < attack_samples {
+        TONE_AMPLITUDE * (i as f32 / attack_samples as f32)
+    } else if i >= sym_samples - release_samples {
+        TONE_AMPLITUDE * ((sym_samples - 1 - i) as f32 / release_samples as f32)
+    } else {
+        TONE_AMPLITUDE
     }
 }
 
@@ -196,6 +422,64 @@ mod tests {
         assert_eq!(audio.sample_rate, DEFAULT_SAMPLE_RATE);
     }
 
+    #[test]
+    fn test_streaming_in_blocks_matches_encode() {
+        let encoder = AcousticEncoder::with_sample_rate(22050).unwrap();
+        let data = [0x00, 0x90, 0xA5, 0x01];
+        let audio = encoder.encode(&data).unwrap();
+
+        let mut stream = encoder.encode_streaming(&data).unwrap();
+        assert_eq!((stream.len(), stream.duration()), (audio.samples.len(), audio.duration));
+        let mut streamed = Vec::new();
+        let mut block = [0.0f32; 256];
+        loop {
+            let n = stream.fill(&mut block);
+            streamed.extend_from_slice(&block[..n]);
+            if n < block.len() {
+                break;
+            }
+        }
+        assert_eq!(streamed, audio.samples);
+        assert_eq!((stream.remaining(), stream.next()), (0, None));
+        assert!(encoder.encode_streaming(&[]).is_err());
+    }
+
+    #[test]
+    fn test_shaped_symbols_fill_the_range_without_clipping() {
+        let data: Vec<u8> = (0..=255).collect();
+        for sample_rate in [DEFAULT_SAMPLE_RATE, 22050] {
+            let plain = AcousticEncoder::with_sample_rate(sample_rate).unwrap().encode(&data).unwrap();
+            let shaped = AcousticEncoder::with_sample_rate(sample_rate).unwrap().with_peak_level(1.0).encode(&data).unwrap();
+            let max_abs = shaped.samples.iter().map(|s| s.abs()).fold(0.0, f32::max);
+            assert!((0.99..=1.0).contains(&max_abs), "peak {}", max_abs);
+
+            let power = |s: &[f32]| s.iter().map(|x| x * x).sum::<f32>() / s.len() as f32;
+            let ratio = power(&shaped.samples) / power(&plain.samples);
+            assert!(ratio > 8.0, "power up {}x at {} Hz", ratio, sample_rate);
+        }
+        let quiet = AcousticEncoder::new().with_peak_level(0.5).encode(&[0x0F, 0xF1]).unwrap();
+        assert!(quiet.samples.iter().all(|s| s.abs() <= 0.5));
+    }
+
+    #[test]
+    fn test_carrier_gains_scale_each_carrier() {
+        // Byte 0x31 plays carriers 4 and 5, then carrier 0
+        let plain = AcousticEncoder::new().encode(&[0x31]).unwrap().samples;
+        let mut gains = [1.0; NUM_CARRIERS];
+        gains[0] = 1.5;
+        let boosted = AcousticEncoder::new().with_carrier_gains(gains).encode(&[0x31]).unwrap().samples;
+        let sr = DEFAULT_SAMPLE_RATE as f32;
+        let lo = (((SYNC_DURATION + FRAME_TIME) * sr).round() as usize)..(((SYNC_DURATION + 2.0 * FRAME_TIME) * sr).round() as usize);
+        for i in 0..plain.len() {
+            let expected = if lo.contains(&i) { 1.5 * plain[i] } else { plain[i] };
+            assert!((boosted[i] - expected).abs() < 1e-6, "sample {}", i);
+        }
+
+        gains[4] = 10.0;
+        let shaped = AcousticEncoder::new().with_peak_level(1.0).with_carrier_gains(gains);
+        assert!(shaped.encode(&[0x31, 0xFF]).unwrap().samples.iter().all(|s| s.abs() <= 1.0));
+    }
+
     #[test]
     fn test_encode_empty_fails() {
         let encoder = AcousticEncoder::new();
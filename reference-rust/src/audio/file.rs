@@ -0,0 +1,96 @@
+//! Reading compressed captures (FLAC, Ogg Vorbis) via symphonia.
+//!
+//! Field recordings often come from phones in these formats and at rates
+//! other than the decoder's. [`read_audio`] decodes any supported file to
+//! mono f32 samples; [`AcousticDecoder::decode_file`] also resamples them
+//! to the decoder's rate and decodes every transmission found.
+
+use std::fs::File;
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::error::AILLError;
+
+use super::decode::{AcousticDecoder, DecodeResult};
+use super::resample::resample;
+
+/// Read the first audio track of a FLAC, Ogg Vorbis or WAV file as mono
+/// f32 PCM, averaging channels. Returns (samples, sample_rate).
+pub fn read_audio<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, u32), AILLError> {
+    let path = path.as_ref();
+    let read_err = |e: SymphoniaError| {
+        AILLError::InvalidStructure(format!("Audio read error in {}: {}", path.display(), e))
+    };
+
+    let file = File::open(path).map_err(|e| {
+        AILLError::InvalidStructure(format!("Audio read error in {}: {}", path.display(), e))
+    })?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(read_err)?
+        .format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| AILLError::InvalidStructure("No audio track in file".into()))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| AILLError::InvalidStructure("Audio track has no sample rate".into()))?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(read_err)?;
+
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(read_err(e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet loses its samples, not the whole file
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(read_err(e)),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buf.copy_interleaved_ref(decoded);
+        let mono = buf.samples().chunks(channels).map(|f| f.iter().sum::<f32>() / channels as f32);
+        samples.extend(mono);
+    }
+
+    Ok((samples, sample_rate))
+}
+
+impl AcousticDecoder {
+    /// Decode every transmission in an audio file, resampled to this
+    /// decoder's rate. See [`read_audio`] for the formats read.
+    pub fn decode_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DecodeResult>, AILLError> {
+        let (samples, sample_rate) = read_audio(path)?;
+        let samples = resample(&samples, sample_rate, self.sample_rate());
+        Ok(self.decode_all(&samples))
+    }
+}
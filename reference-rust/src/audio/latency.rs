@@ -0,0 +1,256 @@
+//! Output→input latency of the local audio devices.
+//!
+//! Calibration plays the sync chirp while recording and finds it in the
+//! recording by cross-correlation. The resulting [`LatencyProfile`] tells
+//! a full-duplex caller how long the input stream takes to start, how late
+//! played audio shows up in the recording, and how much it is attenuated.
+//! As the chirp sweeps across every carrier, the profile also records the
+//! speaker-to-mic response at each one, from which encoders and decoders
+//! compensate for carriers the hardware plays or hears quieter.
+//! Profiles are stored as JSON so later runs can skip calibration.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AILLError;
+
+use super::constants::{CARRIER_FREQS, MAX_CARRIER_GAIN, NUM_CARRIERS, TONE_SPACING};
+
+/// Lowest normalized correlation accepted as finding the chirp.
+pub const MIN_CALIBRATION_SCORE: f32 = 0.5;
+
+/// Measured timing and gain of an output→input audio path.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyProfile {
+    pub sample_rate: u32,
+    /// From starting playback to the audio arriving at the input.
+    pub latency_ms: f32,
+    /// From starting a recording to the first captured sample.
+    pub input_startup_ms: f32,
+    /// Amplitude of the recorded chirp relative to the played one.
+    pub gain: f32,
+    /// Recorded relative to played amplitude around each carrier.
+    /// Profiles saved without it read as a flat response.
+    #[serde(default = "flat_response")]
+    pub carrier_response: [f32; NUM_CARRIERS],
+}
+
+impl LatencyProfile {
+    /// Locate `reference` in `recording`. Playback of `reference` started
+    /// `play_delay_secs` after a recording of `record_secs` was started;
+    /// samples missing from the recording are taken as input startup time.
+    pub fn measure(
+        reference: &[f32],
+        recording: &[f32],
+        sample_rate: u32,
+        play_delay_secs: f32,
+        record_secs: f32,
+    ) -> Result<Self, AILLError> {
+        if reference.is_empty() || recording.len() < reference.len() {
+            return Err(AILLError::InvalidStructure(
+                "Recording shorter than the calibration signal".into(),
+            ));
+        }
+        let corr = cross_correlate(reference, recording);
+        let (offset, peak) = corr
+            .iter()
+            .copied()
+            .enumerate()
+            .fold((0, f32::MIN), |best, (i, c)| if c > best.1 { (i, c) } else { best });
+
+        let ref_energy: f32 = reference.iter().map(|s| s * s).sum();
+        let seg_energy: f32 = recording[offset..offset + reference.len()].iter().map(|s| s * s).sum();
+        let score = peak / (ref_energy * seg_energy).sqrt();
+        if score.is_nan() || score < MIN_CALIBRATION_SCORE {
+            return Err(AILLError::InvalidStructure(format!(
+                "Calibration chirp not found in recording (score {:.2})",
+                score
+            )));
+        }
+
+        let sr = sample_rate as f32;
+        let expected = (record_secs * sr).round() as usize;
+        let startup = expected.saturating_sub(recording.len()) as f32 / sr;
+        let latency = (startup + offset as f32 / sr - play_delay_secs).max(0.0);
+        Ok(Self {
+            sample_rate,
+            latency_ms: latency * 1000.0,
+            input_startup_ms: startup * 1000.0,
+            gain: peak / ref_energy,
+            carrier_response: carrier_response(reference, &recording[offset..offset + reference.len()], sample_rate),
+        })
+    }
+
+    /// Encoder gains that even out [`carrier_response`](Self::carrier_response):
+    /// inversely proportional to it, scaled so that none exceeds
+    /// [`MAX_CARRIER_GAIN`] or boosts the best-heard carrier.
+    pub fn carrier_gains(&self) -> [f32; NUM_CARRIERS] {
+        let best = self.carrier_response.iter().copied().fold(0.0f32, f32::max);
+        if best <= 0.0 {
+            return flat_response();
+        }
+        let boosts = self.carrier_response.map(|r| best / r.max(best * 1e-3));
+        let scale = (MAX_CARRIER_GAIN / boosts.iter().copied().fold(1.0f32, f32::max)).min(1.0);
+        boosts.map(|b| b * scale)
+    }
+
+    /// Decoder threshold weights for audio through this path: each
+    /// carrier's response relative to the best-heard one, no lower than
+    /// `1 / MAX_CARRIER_GAIN`.
+    pub fn carrier_weights(&self) -> [f32; NUM_CARRIERS] {
+        let best = self.carrier_response.iter().copied().fold(0.0f32, f32::max);
+        if best <= 0.0 {
+            return flat_response();
+        }
+        self.carrier_response.map(|r| (r / best).clamp(1.0 / MAX_CARRIER_GAIN, 1.0))
+    }
+
+    /// Where profiles are kept by default: `aill/latency.json` under
+    /// `$XDG_CONFIG_HOME` or `$HOME/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        config_path("latency.json")
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AILLError> {
+        save_json(self, path.as_ref())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AILLError> {
+        load_json(path.as_ref())
+    }
+}
+
+fn flat_response() -> [f32; NUM_CARRIERS] {
+    [1.0; NUM_CARRIERS]
+}
+
+/// Amplitude of `recorded` relative to `played` within half a tone spacing
+/// of each carrier, from the energy of their spectra in that band.
+fn carrier_response(played: &[f32], recorded: &[f32], sample_rate: u32) -> [f32; NUM_CARRIERS] {
+    let n = played.len().next_power_of_two();
+    let fft = FftPlanner::new().plan_fft_forward(n);
+    let spectrum = |x: &[f32]| {
+        let mut buf: Vec<Complex<f32>> = x.iter().map(|&s| Complex::new(s, 0.0)).collect();
+        buf.resize(n, Complex::new(0.0, 0.0));
+        fft.process(&mut buf);
+        buf
+    };
+    let (played, recorded) = (spectrum(played), spectrum(recorded));
+    let bin_hz = sample_rate as f32 / n as f32;
+    CARRIER_FREQS.map(|freq| {
+        let lo = ((freq - TONE_SPACING / 2.0) / bin_hz).ceil() as usize;
+        let hi = ((freq + TONE_SPACING / 2.0) / bin_hz).floor() as usize;
+        let energy = |s: &[Complex<f32>]| s[lo..=hi].iter().map(|c| c.norm_sqr()).sum::<f32>();
+        let reference = energy(&played);
+        if reference > 0.0 { (energy(&recorded) / reference).sqrt() } else { 0.0 }
+    })
+}
+
+/// `aill/<file>` under `$XDG_CONFIG_HOME` or `$HOME/.config`.
+pub(crate) fn config_path(file: &str) -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("aill").join(file))
+}
+
+/// Write `value` as JSON to `path`, creating missing directories.
+pub(crate) fn save_json<T: Serialize>(value: &T, path: &Path) -> Result<(), AILLError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| AILLError::EncoderError(format!("Failed to create {}: {}", dir.display(), e)))?;
+    }
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| AILLError::EncoderError(format!("Failed to serialize profile: {}", e)))?;
+    std::fs::write(path, json)
+        .map_err(|e| AILLError::EncoderError(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Read a value saved with [`save_json`].
+pub(crate) fn load_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, AILLError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| AILLError::EncoderError(format!("Failed to read {}: {}", path.display(), e)))?;
+    serde_json::from_str(&json)
+        .map_err(|e| AILLError::InvalidStructure(format!("Invalid profile {}: {}", path.display(), e)))
+}
+
+/// `r[k] = Σ signal[k + i] · reference[i]` for every offset `k` at which
+/// `reference` fits inside `signal`, computed via FFT.
+pub(crate) fn cross_correlate(reference: &[f32], signal: &[f32]) -> Vec<f32> {
+    let mut planner = FftPlanner::new();
+    cross_correlate_with(reference, signal, |n| (planner.plan_fft_forward(n), planner.plan_fft_inverse(n)))
+}
+
+/// [`cross_correlate`] with the forward and inverse FFTs of size `n` that
+/// `plans(n)` returns.
+pub(crate) fn cross_correlate_with(
+    reference: &[f32],
+    signal: &[f32],
+    plans: impl FnOnce(usize) -> (Arc<dyn Fft<f32>>, Arc<dyn Fft<f32>>),
+) -> Vec<f32> {
+    let n = (signal.len() + reference.len()).next_power_of_two();
+    let (forward, inverse) = plans(n);
+
+    let padded = |x: &[f32]| {
+        let mut buf: Vec<Complex<f32>> = x.iter().map(|&s| Complex::new(s, 0.0)).collect();
+        buf.resize(n, Complex::new(0.0, 0.0));
+        buf
+    };
+    let mut a = padded(signal);
+    let mut b = padded(reference);
+    forward.process(&mut a);
+    forward.process(&mut b);
+    for (x, y) in a.iter_mut().zip(&b) {
+        *x *= y.conj();
+    }
+    inverse.process(&mut a);
+    a[..=signal.len() - reference.len()].iter().map(|c| c.re / n as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::AcousticEncoder;
+
+    #[test]
+    fn finds_delayed_and_attenuated_chirp() {
+        let chirp = AcousticEncoder::new().sync_chirp();
+        let sr = 48_000;
+        // Recording lost its first 50ms; the chirp, played 200ms in,
+        // arrives 300ms into what was captured at half amplitude.
+        let mut recording: Vec<f32> = (0..sr as usize).map(|i| 0.01 * (i as f32 * 0.37).sin()).collect();
+        for (i, s) in chirp.iter().enumerate() {
+            recording[14_400 + i] += 0.5 * s;
+        }
+        let profile = LatencyProfile::measure(&chirp, &recording, sr, 0.2, 1.05).unwrap();
+        assert!((profile.input_startup_ms - 50.0).abs() < 0.1);
+        assert!((profile.latency_ms - 150.0).abs() < 0.1, "{:?}", profile);
+        assert!((profile.gain - 0.5).abs() < 0.05);
+        assert!(profile.carrier_response.iter().all(|r| (r - 0.5).abs() < 0.05), "{:?}", profile);
+
+        let path = std::env::temp_dir().join("aill_latency_profile_test.json");
+        profile.save(&path).unwrap();
+        assert_eq!(LatencyProfile::load(&path).unwrap(), profile);
+        std::fs::remove_file(path).ok();
+
+        assert!(LatencyProfile::measure(&chirp, &vec![0.0; sr as usize], sr, 0.2, 1.0).is_err());
+    }
+
+    #[test]
+    fn compensation_evens_out_carrier_response() {
+        let json = r#"{"sample_rate":48000,"latency_ms":20.0,"input_startup_ms":5.0,"gain":0.5}"#;
+        let mut profile: LatencyProfile = serde_json::from_str(json).unwrap();
+        assert_eq!(profile.carrier_gains(), [1.0; NUM_CARRIERS]);
+        assert_eq!(profile.carrier_weights(), [1.0; NUM_CARRIERS]);
+
+        profile.carrier_response = [0.1, 0.4, 0.8, 0.8, 0.8, 0.8, 0.8, 0.8];
+        let gains = profile.carrier_gains();
+        assert_eq!(gains[0], MAX_CARRIER_GAIN);
+        assert_eq!((gains[1], gains[7]), (0.5, 0.25));
+        let weights = profile.carrier_weights();
+        assert_eq!((weights[0], weights[1], weights[7]), (1.0 / MAX_CARRIER_GAIN, 0.5, 1.0));
+    }
+}
@@ -4,6 +4,14 @@ use std::sync::{Arc, Mutex};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
 use crate::error::AILLError;
+use crate::timestamp::now_micros;
+
+use super::constants::{DEFAULT_SAMPLE_RATE, FFT_SIZE, SYNC_DURATION};
+use super::decode::AcousticDecoder;
+use super::encode::AcousticEncoder;
+use super::latency::LatencyProfile;
+use super::pacer::Pacer;
+use super::tdma::TdmaClient;
 
 /// Polling interval (ms) while waiting for playback to finish.
 const POLL_INTERVAL_MS: u64 = 10;
@@ -14,6 +22,16 @@ const DRAIN_DELAY_MS: u64 = 50;
 /// Maximum recording duration (seconds) to prevent runaway allocations.
 const MAX_RECORD_DURATION_SECS: f32 = 300.0;
 
+/// Time (ms) between starting the calibration recording and playing the chirp.
+const CALIBRATION_LEAD_MS: u64 = 500;
+
+/// Recording time (seconds) after the calibration chirp; bounds the latency
+/// `calibrate` can measure.
+const CALIBRATION_TAIL_SECS: f32 = 1.0;
+
+/// How long `play_when_clear` listens before each attempt (seconds).
+pub const LISTEN_DURATION_SECS: f32 = 0.2;
+
 /// Build a mono f32 stream config at the given sample rate.
 fn stream_config(sample_rate: u32) -> cpal::StreamConfig {
     cpal::StreamConfig {
@@ -164,3 +182,90 @@ pub fn record_audio(duration_secs: f32, sample_rate: u32) -> Result<Vec<f32>, AI
     let samples = std::mem::take(&mut *lock_or_recover(&buffer));
     Ok(samples)
 }
+
+/// Play everything queued in `client`, each transmission inside one of the
+/// agent's TDMA slots, sleeping between slots. Uses the system clock, which
+/// should be roughly synchronized with the coordinator's.
+///
+/// Returns an error if queued audio has no slot long enough for it.
+pub fn play_scheduled(client: &mut TdmaClient) -> Result<(), AILLError> {
+    while !client.is_idle() {
+        let now = now_micros();
+        if let Some(samples) = client.poll(now) {
+            play_audio(&samples, client.sample_rate())?;
+            continue;
+        }
+        let start = client
+            .next_start(now)
+            .ok_or_else(|| AILLError::EncoderError("No TDMA slot for queued audio".into()))?;
+        let wait_us = (start - now).max(0) as u64;
+        std::thread::sleep(std::time::Duration::from_micros(wait_us));
+    }
+    Ok(())
+}
+
+/// Listen on the default input device for `duration_secs` and report
+/// whether the channel stayed clear, i.e. no `FFT_SIZE` window of the
+/// recording carried energy above `threshold` on the AILL carriers or
+/// chirp bands. Use [`super::ABS_THRESHOLD`] unless the room is noisy.
+pub fn channel_clear(duration_secs: f32, threshold: f32) -> Result<bool, AILLError> {
+    let samples = record_audio(duration_secs, DEFAULT_SAMPLE_RATE)?;
+    let decoder = AcousticDecoder::new();
+    Ok(!samples.chunks(FFT_SIZE).any(|window| decoder.channel_busy(window, threshold)))
+}
+
+/// Listen before talking: play `samples` once the channel is clear, backing
+/// off for the randomized gap `pacer` picks after a busy channel frees up.
+///
+/// Returns an error if the channel is still busy after `max_attempts`
+/// listening periods.
+pub fn play_when_clear(
+    samples: &[f32],
+    sample_rate: u32,
+    pacer: &mut Pacer,
+    threshold: f32,
+    max_attempts: u32,
+) -> Result<(), AILLError> {
+    for _ in 0..max_attempts {
+        let clear = channel_clear(LISTEN_DURATION_SECS, threshold)?;
+        let now = now_micros();
+        pacer.observe(!clear, now);
+        match pacer.earliest_start(now) {
+            Some(start) if start <= now => {
+                play_audio(samples, sample_rate)?;
+                let duration_us = samples.len() as i64 * 1_000_000 / sample_rate as i64;
+                pacer.transmitted(now, duration_us);
+                return Ok(());
+            }
+            Some(start) => std::thread::sleep(std::time::Duration::from_micros((start - now) as u64)),
+            None => {}
+        }
+    }
+    Err(AILLError::EncoderError(format!(
+        "Channel still busy after {} attempts",
+        max_attempts
+    )))
+}
+
+/// Measure the latency and gain from the default output to the default
+/// input device by playing the sync chirp while recording. Save the result
+/// with [`LatencyProfile::save`] to reuse it.
+///
+/// Returns an error if either device fails or the chirp cannot be found
+/// in the recording, e.g. because the speaker is muted.
+pub fn calibrate() -> Result<LatencyProfile, AILLError> {
+    let sample_rate = DEFAULT_SAMPLE_RATE;
+    let chirp = AcousticEncoder::new().sync_chirp();
+    let record_secs = CALIBRATION_LEAD_MS as f32 / 1000.0 + SYNC_DURATION + CALIBRATION_TAIL_SECS;
+
+    let started = std::time::Instant::now();
+    let rx = std::thread::spawn(move || record_audio(record_secs, sample_rate));
+    std::thread::sleep(std::time::Duration::from_millis(CALIBRATION_LEAD_MS));
+    let play_delay_secs = started.elapsed().as_secs_f32();
+    play_audio(&chirp, sample_rate)?;
+
+    let recording = rx
+        .join()
+        .map_err(|_| AILLError::EncoderError("Recording thread panicked".into()))??;
+    LatencyProfile::measure(&chirp, &recording, sample_rate, play_delay_secs, record_secs)
+}
@@ -1,19 +1,55 @@
 pub mod constants;
-pub mod decode;
 pub mod encode;
 
+#[cfg(feature = "audio-core")]
+pub mod decode;
+#[cfg(feature = "audio-core")]
+pub mod latency;
+#[cfg(feature = "audio-core")]
+pub mod noise;
+#[cfg(feature = "audio-core")]
+pub mod pacer;
+#[cfg(feature = "audio-core")]
+pub mod resample;
+#[cfg(feature = "audio-core")]
+pub mod tdma;
+
+#[cfg(feature = "audio-fixed")]
+pub mod q15;
+
 #[cfg(feature = "audio")]
 pub mod wav;
 
+#[cfg(feature = "audio-formats")]
+pub mod file;
+
 #[cfg(all(feature = "audio-live", not(target_family = "wasm")))]
 pub mod live;
 
 pub use constants::*;
-pub use decode::AcousticDecoder;
-pub use encode::{AcousticEncoder, EncodedAudio};
+pub use encode::{AcousticEncoder, AcousticProfile, EncodedAudio, EncodedStream};
+
+#[cfg(feature = "audio-core")]
+pub use decode::{AcousticDecoder, DecodeResult};
+#[cfg(feature = "audio-core")]
+pub use latency::LatencyProfile;
+#[cfg(feature = "audio-core")]
+pub use noise::NoiseProfile;
+#[cfg(feature = "audio-core")]
+pub use pacer::{ChannelState, Pacer};
+#[cfg(feature = "audio-core")]
+pub use resample::resample;
+#[cfg(feature = "audio-core")]
+pub use tdma::{TdmaClient, TdmaCoordinator, TdmaMessage};
+
+#[cfg(feature = "audio-fixed")]
+pub use q15::Q15Decoder;
 
 #[cfg(feature = "audio")]
-pub use wav::{read_wav, write_wav};
+pub use wav::{read_wav, write_wav, write_wav_with_metadata, WavMetadata};
+
+#[cfg(feature = "audio-formats")]
+pub use file::read_audio;
 
 #[cfg(all(feature = "audio-live", not(target_family = "wasm")))]
-pub use live::{play_audio, record_audio};
+pub use live::{calibrate, play_audio, record_audio};
@@ -0,0 +1,106 @@
+//! Background noise profiles for the acoustic decoder.
+//!
+//! Fans, motors and mains hum put steady tones into the room that can sit
+//! right on an AILL carrier. A [`NoiseProfile`] records the mean and spread
+//! of every FFT bin while the channel is idle; see
+//! [`AcousticDecoder::learn_noise`](super::AcousticDecoder::learn_noise).
+//! The decoder subtracts the mean from each carrier and requires tones to
+//! stand [`NOISE_SIGMA`] deviations above what is left.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::AILLError;
+
+use super::latency::{config_path, load_json, save_json};
+
+/// Deviations above the learned noise a carrier needs to count as a tone.
+pub const NOISE_SIGMA: f32 = 4.0;
+
+/// Per-bin magnitude statistics of ambient audio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoiseProfile {
+    pub sample_rate: u32,
+    /// Number of FFT frames the statistics cover.
+    pub frames: u32,
+    /// Mean magnitude of each FFT bin.
+    pub mean: Vec<f32>,
+    /// Standard deviation of each bin's magnitude.
+    pub deviation: Vec<f32>,
+}
+
+impl NoiseProfile {
+    /// Statistics of a set of magnitude spectra of equal length.
+    pub fn from_spectra(sample_rate: u32, spectra: &[Vec<f32>]) -> Self {
+        let bins = spectra.first().map_or(0, Vec::len);
+        let n = spectra.len().max(1) as f32;
+        let mut mean = vec![0.0f32; bins];
+        let mut square = vec![0.0f32; bins];
+        for spectrum in spectra {
+            for (i, &m) in spectrum.iter().enumerate().take(bins) {
+                mean[i] += m / n;
+                square[i] += m * m / n;
+            }
+        }
+        let deviation = mean.iter().zip(&square).map(|(m, q)| (q - m * m).max(0.0).sqrt()).collect();
+        Self { sample_rate, frames: spectra.len() as u32, mean, deviation }
+    }
+
+    /// Fold in the statistics of `other`, weighted by frame counts.
+    pub fn merge(&mut self, other: &NoiseProfile) -> Result<(), AILLError> {
+        if other.sample_rate != self.sample_rate || other.mean.len() != self.mean.len() {
+            return Err(AILLError::InvalidStructure(
+                "Noise profiles differ in sample rate or FFT size".into(),
+            ));
+        }
+        let total = self.frames + other.frames;
+        if total == 0 {
+            return Ok(());
+        }
+        let (a, b) = (self.frames as f32 / total as f32, other.frames as f32 / total as f32);
+        for i in 0..self.mean.len() {
+            let (m1, m2) = (self.mean[i], other.mean[i]);
+            let q1 = self.deviation[i].powi(2) + m1 * m1;
+            let q2 = other.deviation[i].powi(2) + m2 * m2;
+            let mean = a * m1 + b * m2;
+            self.mean[i] = mean;
+            self.deviation[i] = (a * q1 + b * q2 - mean * mean).max(0.0).sqrt();
+        }
+        self.frames = total;
+        Ok(())
+    }
+
+    /// Where profiles are kept by default: `aill/noise.json` under
+    /// `$XDG_CONFIG_HOME` or `$HOME/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        config_path("noise.json")
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AILLError> {
+        save_json(self, path.as_ref())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AILLError> {
+        load_json(path.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merging_matches_learning_at_once() {
+        let spectra: Vec<Vec<f32>> = (0..6).map(|i| vec![i as f32, 1.0, (i % 2) as f32]).collect();
+        let all = NoiseProfile::from_spectra(48_000, &spectra);
+        let mut merged = NoiseProfile::from_spectra(48_000, &spectra[..2]);
+        merged.merge(&NoiseProfile::from_spectra(48_000, &spectra[2..])).unwrap();
+        assert_eq!(merged.frames, 6);
+        for (x, y) in merged.mean.iter().chain(&merged.deviation).zip(all.mean.iter().chain(&all.deviation)) {
+            assert!((x - y).abs() < 1e-5);
+        }
+        assert_eq!(all.deviation[1], 0.0);
+        assert!(merged.merge(&NoiseProfile::from_spectra(44_100, &spectra)).is_err());
+    }
+}
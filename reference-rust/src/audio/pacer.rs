@@ -0,0 +1,196 @@
+//! Congestion-aware spacing of acoustic transmissions.
+//!
+//! Agents sharing one acoustic channel listen before talking. A [`Pacer`]
+//! is fed carrier-sense observations (usually from
+//! [`AcousticDecoder::channel_busy`] on the latest input samples) and keeps
+//! a smoothed estimate of how occupied the channel is. It holds
+//! transmissions while the channel is busy and, once it clears or after our
+//! own transmission, waits a randomized gap that grows with the occupancy,
+//! so that agents waiting on the same channel do not all start at once.
+//! Time is passed in explicitly as microseconds.
+
+use crate::encoder::AILLEncoder;
+
+use super::decode::AcousticDecoder;
+
+/// COMM-1 CHANNEL_BUSY entry code.
+pub const CHANNEL_BUSY: u16 = 0x0040;
+/// COMM-1 CHANNEL_CLEAR entry code.
+pub const CHANNEL_CLEAR: u16 = 0x0041;
+
+/// Default weight of the newest observation in the occupancy estimate.
+pub const DEFAULT_OCCUPANCY_SMOOTHING: f32 = 0.1;
+
+/// Carrier-sense state of the channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    Busy,
+    Clear,
+}
+
+impl ChannelState {
+    /// An `ASSERT OBSERVED comm::CHANNEL_BUSY` (or `CHANNEL_CLEAR`)
+    /// utterance announcing this state to peers.
+    pub fn encode(&self) -> Vec<u8> {
+        let code = match self {
+            ChannelState::Busy => CHANNEL_BUSY,
+            ChannelState::Clear => CHANNEL_CLEAR,
+        };
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().observed().l1_ref(code);
+        e.end_utterance()
+    }
+}
+
+/// Decides when this agent may start its next transmission.
+pub struct Pacer {
+    min_gap_us: i64,
+    smoothing: f32,
+    occupancy: f32,
+    state: ChannelState,
+    /// Earliest start of the next transmission.
+    not_before_us: i64,
+    rng: u64,
+}
+
+impl Pacer {
+    /// Pacer that leaves at least `min_gap_us` between the channel becoming
+    /// free and our next transmission.
+    pub fn new(min_gap_us: i64) -> Self {
+        Self {
+            min_gap_us,
+            smoothing: DEFAULT_OCCUPANCY_SMOOTHING,
+            occupancy: 0.0,
+            state: ChannelState::Clear,
+            not_before_us: i64::MIN,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Weight (0..=1) of each new observation in the occupancy estimate.
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Seed the backoff jitter; agents on one channel should use different
+    /// seeds, e.g. derived from their agent id.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = seed | 1;
+        self
+    }
+
+    /// Smoothed fraction of observations that found the channel busy.
+    pub fn occupancy(&self) -> f32 {
+        self.occupancy
+    }
+
+    pub fn state(&self) -> ChannelState {
+        self.state
+    }
+
+    /// Record a carrier-sense observation. Returns the new state when it
+    /// changed, e.g. to announce it with [`ChannelState::encode`].
+    pub fn observe(&mut self, busy: bool, now_us: i64) -> Option<ChannelState> {
+        let sample = if busy { 1.0 } else { 0.0 };
+        self.occupancy += self.smoothing * (sample - self.occupancy);
+        let state = if busy {
+            ChannelState::Busy
+        } else {
+            ChannelState::Clear
+        };
+        if state == self.state {
+            return None;
+        }
+        self.state = state;
+        if state == ChannelState::Clear {
+            self.back_off(now_us);
+        }
+        Some(state)
+    }
+
+    /// Sense the channel from the latest input samples.
+    pub fn sense(
+        &mut self,
+        decoder: &AcousticDecoder,
+        samples: &[f32],
+        threshold: f32,
+        now_us: i64,
+    ) -> Option<ChannelState> {
+        self.observe(decoder.channel_busy(samples, threshold), now_us)
+    }
+
+    /// When the next transmission may start, or `None` while the channel
+    /// is busy.
+    pub fn earliest_start(&self, now_us: i64) -> Option<i64> {
+        match self.state {
+            ChannelState::Busy => None,
+            ChannelState::Clear => Some(self.not_before_us.max(now_us)),
+        }
+    }
+
+    pub fn may_transmit(&self, now_us: i64) -> bool {
+        self.earliest_start(now_us) == Some(now_us)
+    }
+
+    /// Record our own transmission of `duration_us` starting at `now_us`.
+    pub fn transmitted(&mut self, now_us: i64, duration_us: i64) {
+        self.back_off(now_us + duration_us);
+    }
+
+    /// Wait a gap from `from_us` that grows with the occupancy, with up
+    /// to one gap of random jitter on top.
+    fn back_off(&mut self, from_us: i64) {
+        let gap = self.min_gap_us as f32 * (1.0 + 4.0 * self.occupancy);
+        let jitter = self.next_unit() * gap;
+        self.not_before_us = from_us + (gap + jitter) as i64;
+    }
+
+    /// Uniform in [0, 1) from an xorshift generator.
+    fn next_unit(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AcousticEncoder, ABS_THRESHOLD};
+
+    #[test]
+    fn senses_aill_audio_as_busy() {
+        let decoder = AcousticDecoder::new();
+        let audio = AcousticEncoder::new().encode(&[0xFF, 0x0F]).unwrap();
+        let mid = audio.samples.len() / 2;
+        assert!(decoder.channel_busy(&audio.samples[..mid], ABS_THRESHOLD));
+        assert!(!decoder.channel_busy(&vec![0.0; 8000], ABS_THRESHOLD));
+
+        let mut pacer = Pacer::new(10_000);
+        assert_eq!(
+            pacer.sense(&decoder, &audio.samples[..mid], ABS_THRESHOLD, 0),
+            Some(ChannelState::Busy)
+        );
+        assert_eq!(pacer.earliest_start(5), None);
+        assert!(ChannelState::Busy.encode().ends_with(&[0xF0, 0x00, 0x40, 0x01]));
+    }
+
+    #[test]
+    fn gaps_grow_with_occupancy() {
+        let mut pacer = Pacer::new(10_000).with_seed(7);
+        assert!(pacer.may_transmit(0));
+        pacer.transmitted(0, 50_000);
+        let quiet = pacer.earliest_start(0).unwrap() - 50_000;
+        assert!((10_000..20_000).contains(&quiet));
+
+        for t in 0..20 {
+            pacer.observe(t % 4 != 3, t * 1_000);
+        }
+        assert!(pacer.occupancy() > 0.5);
+        pacer.transmitted(100_000, 50_000);
+        let congested = pacer.earliest_start(100_000).unwrap() - 150_000;
+        assert!(congested > 30_000, "{}", congested);
+    }
+}
@@ -0,0 +1,322 @@
+//! Integer-only acoustic demodulation (`audio-fixed` feature).
+//!
+//! [`Q15Decoder`] demodulates the standard acoustic profile from 16-bit
+//! PCM with integer arithmetic only, for receivers such as Cortex-M0+
+//! parts that have no FPU. Instead of FFTs it runs a Goertzel filter with
+//! Q15 coefficients on each carrier, over a Q15 Hann window across the
+//! middle of each symbol, and finds the sync chirp with two more filters
+//! tuned to frequencies the chirp sweeps through 120 ms apart. The window
+//! and coefficient tables are worked out once, when the decoder is built.
+//!
+//! It decodes the first transmission only, without the noise profiles,
+//! addressing or per-byte confidence of the floating-point decoder.
+
+use std::f32::consts::PI;
+
+use crate::error::AILLError;
+
+use super::constants::*;
+
+/// [`ABS_THRESHOLD`] as a Q15 tone amplitude: an FFT magnitude of a
+/// Hann-windowed tone is half the tone's amplitude.
+pub const ABS_THRESHOLD_Q15: u32 = 328;
+
+/// Frequencies the sync chirp passes early and late, away from the carriers.
+const SYNC_PROBE_LO: f32 = 450.0;
+const SYNC_PROBE_HI: f32 = 1650.0;
+
+/// Between the top carrier and the sync band; a frame loud here is the
+/// end chirp, which passes it mid-frame.
+const END_PROBE: f32 = 1425.0;
+
+/// A Goertzel filter's window and Q15 cosine of its frequency.
+struct Tone<'a> {
+    window: &'a [i16],
+    window_sum: u64,
+    cos: i16,
+}
+
+impl Tone<'_> {
+    /// Q15 amplitude of the filter's frequency at the start of `samples`.
+    fn amplitude(&self, samples: &[i16]) -> u32 {
+        let c = self.cos as i64;
+        let (mut s1, mut s2) = (0i32, 0i32);
+        for (&x, &w) in samples.iter().zip(self.window) {
+            // Q15 × Q15 down to Q13, leaving headroom for the filter's gain
+            let x = (x as i32 * w as i32) >> 17;
+            let s0 = x + ((c * s1 as i64) >> 14) as i32 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        let (s1, s2) = (s1 as i64, s2 as i64);
+        let power = s1 * s1 + s2 * s2 - ((c * s1) >> 14) * s2;
+        // A tone of amplitude A gives |X| = A·Σw/2; Q13 back to Q15
+        ((power.max(0) as u64).isqrt() * 8 * 32768 / self.window_sum.max(1)) as u32
+    }
+}
+
+/// Demodulates 16-bit PCM without floating point; see the [module docs](self).
+pub struct Q15Decoder {
+    sample_rate: u32,
+    symbol: usize,
+    frame: usize,
+    sync: usize,
+    /// Hann window over the middle three fifths of a symbol.
+    window: Vec<i16>,
+    window_sum: u64,
+    carriers: [i16; NUM_CARRIERS],
+    end_probe: i16,
+    /// Shorter Hann window for following the sync chirp.
+    probe_window: Vec<i16>,
+    probe_sum: u64,
+    sync_lo: i16,
+    sync_hi: i16,
+    /// Samples from the start of the sync chirp to where it passes each probe.
+    sync_lo_at: usize,
+    sync_hi_at: usize,
+}
+
+impl Q15Decoder {
+    pub fn new() -> Self {
+        Self::build(DEFAULT_SAMPLE_RATE)
+    }
+
+    pub fn with_sample_rate(sample_rate: u32) -> Result<Self, AILLError> {
+        if sample_rate < MIN_SAMPLE_RATE {
+            return Err(AILLError::EncoderError(format!(
+                "Sample rate {} too low (minimum {}): Nyquist must exceed highest carrier",
+                sample_rate, MIN_SAMPLE_RATE
+            )));
+        }
+        Ok(Self::build(sample_rate))
+    }
+
+    fn build(sample_rate: u32) -> Self {
+        let sr = sample_rate as f32;
+        let samples = |seconds: f32| (seconds * sr).round() as usize;
+        let symbol = samples(SYMBOL_DURATION);
+        let sync = samples(SYNC_DURATION);
+        let cos = |freq: f32| q15((2.0 * PI * freq / sr).cos());
+        let passes = |freq: f32| (sync as f32 * (freq - SYNC_FREQ_START) / (SYNC_FREQ_END - SYNC_FREQ_START)) as usize;
+        let window = hann(symbol * 3 / 5);
+        let probe_window = hann(samples(0.02));
+        Self {
+            sample_rate,
+            symbol,
+            frame: samples(FRAME_TIME),
+            sync,
+            window_sum: window.iter().map(|&w| w as u64).sum(),
+            window,
+            carriers: CARRIER_FREQS.map(cos),
+            end_probe: cos(END_PROBE),
+            probe_sum: probe_window.iter().map(|&w| w as u64).sum(),
+            probe_window,
+            sync_lo: cos(SYNC_PROBE_LO),
+            sync_hi: cos(SYNC_PROBE_HI),
+            sync_lo_at: passes(SYNC_PROBE_LO),
+            sync_hi_at: passes(SYNC_PROBE_HI),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Decode the first transmission in 16-bit PCM samples into wire bytes.
+    pub fn decode(&self, samples: &[i16]) -> Result<Vec<u8>, AILLError> {
+        let chirp = self
+            .find_sync(samples)
+            .ok_or_else(|| AILLError::InvalidStructure("Could not detect sync chirp".into()))?;
+        let data_start = chirp + self.sync;
+        let threshold = self.tone_threshold(samples, data_start);
+
+        let mut frames: Vec<u8> = Vec::new();
+        for n in 0..MAX_DECODE_FRAMES {
+            let Some(frame) = self.frame_samples(samples, data_start, n) else { break };
+            let mags = self.carrier_mags(frame);
+            let loudest = mags.iter().copied().max().unwrap_or(0);
+            let end = self.tone(self.end_probe).amplitude(frame);
+            if n > 2 && end > threshold / 2 && end * 2 > loudest {
+                break;
+            }
+            let active = (0..NUM_CARRIERS).filter(|&i| mags[i] > threshold).fold(0u8, |a, i| a | 1 << i);
+            frames.push(active);
+        }
+
+        // Hi and lo nibbles alternate from the sync point; trailing silence
+        // is dropped, except the lo nibble after a final hi one
+        let last_tone = frames.iter().rposition(|&a| a != 0).unwrap_or(0);
+        let data_end = (last_tone + 2 - last_tone % 2).min(frames.len());
+        let bytes: Vec<u8> = frames[..data_end]
+            .chunks_exact(2)
+            .map(|pair| (pair[0] & 0xF0) | (pair[1] & 0x0F))
+            .collect();
+        if bytes.is_empty() {
+            return Err(AILLError::InvalidStructure("No bytes recovered from audio".into()));
+        }
+        Ok(bytes)
+    }
+
+    /// Start of the sync chirp: where the low probe's peak is followed by
+    /// the high probe's at the spacing the chirp's sweep gives them.
+    fn find_sync(&self, samples: &[i16]) -> Option<usize> {
+        let len = self.probe_window.len();
+        let hop = (self.sample_rate as usize / 500).max(1);
+        let (lo, hi) = (self.probe(self.sync_lo), self.probe(self.sync_hi));
+        let blocks: Vec<(u32, u32)> = (0..)
+            .map(|k| k * hop)
+            .take_while(|&pos| pos + len <= samples.len())
+            .map(|pos| (lo.amplitude(&samples[pos..]), hi.amplitude(&samples[pos..])))
+            .collect();
+        let max_lo = blocks.iter().map(|b| b.0).max()?;
+        let max_hi = blocks.iter().map(|b| b.1).max()?;
+        if max_lo < ABS_THRESHOLD_Q15 || max_hi < ABS_THRESHOLD_Q15 {
+            return None;
+        }
+        let (lo_thresh, hi_thresh) = (max_lo * 3 / 10, max_hi * 3 / 10);
+        let gap = (self.sync_hi_at - self.sync_lo_at) / hop;
+        let tolerance = self.sample_rate as usize / 100 / hop;
+        let centre = |k: usize| k * hop + len / 2;
+
+        let mut k = 0;
+        while k < blocks.len() {
+            if blocks[k].1 < hi_thresh {
+                k += 1;
+                continue;
+            }
+            let run_end = k + blocks[k..].iter().take_while(|b| b.1 >= hi_thresh).count();
+            let hi_peak = (k..run_end).max_by_key(|&i| blocks[i].1)?;
+            if hi_peak >= gap {
+                let around = hi_peak.saturating_sub(gap + tolerance)..(hi_peak - gap + tolerance + 1).min(blocks.len());
+                if let Some(lo_peak) = around.max_by_key(|&i| blocks[i].0) {
+                    if blocks[lo_peak].0 >= lo_thresh {
+                        // Block centres sit where the chirp passes each probe
+                        let from_lo = centre(lo_peak).saturating_sub(self.sync_lo_at);
+                        let from_hi = centre(hi_peak).saturating_sub(self.sync_hi_at);
+                        return Some((from_lo + from_hi) / 2);
+                    }
+                }
+            }
+            k = run_end;
+        }
+        None
+    }
+
+    /// Tone threshold from the carrier levels of the first symbols, as the
+    /// floating-point decoder works it out.
+    fn tone_threshold(&self, samples: &[i16], data_start: usize) -> u32 {
+        let mut mags: Vec<u32> = (0..20)
+            .map_while(|n| self.frame_samples(samples, data_start, n))
+            .flat_map(|frame| self.carrier_mags(frame))
+            .collect();
+        if mags.is_empty() {
+            return ABS_THRESHOLD_Q15;
+        }
+        mags.sort_unstable();
+        let len = mags.len();
+        let median = mags[len / 2];
+        let p85 = mags[(len * 85 / 100).min(len - 1)];
+        if p85 > median * 3 && median > 0 {
+            (median * 2 + p85) / 4
+        } else if p85 > ABS_THRESHOLD_Q15 * 2 {
+            p85 * 2 / 5
+        } else {
+            ABS_THRESHOLD_Q15
+        }
+        .max(ABS_THRESHOLD_Q15)
+    }
+
+    /// The analysed middle of data frame `n`, if the samples reach it.
+    fn frame_samples<'a>(&self, samples: &'a [i16], data_start: usize, n: usize) -> Option<&'a [i16]> {
+        let start = data_start + n * self.frame + (self.symbol - self.window.len()) / 2;
+        samples.get(start..start + self.window.len())
+    }
+
+    fn carrier_mags(&self, frame: &[i16]) -> [u32; NUM_CARRIERS] {
+        self.carriers.map(|cos| self.tone(cos).amplitude(frame))
+    }
+
+    fn tone(&self, cos: i16) -> Tone<'_> {
+        Tone { window: &self.window, window_sum: self.window_sum, cos }
+    }
+
+    fn probe(&self, cos: i16) -> Tone<'_> {
+        Tone { window: &self.probe_window, window_sum: self.probe_sum, cos }
+    }
+}
+
+impl Default for Q15Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn q15(x: f32) -> i16 {
+    (x * 32767.0).round() as i16
+}
+
+/// Hann window of `len` samples in Q15.
+fn hann(len: usize) -> Vec<i16> {
+    (0..len)
+        .map(|i| q15(0.5 - 0.5 * (2.0 * PI * (i as f32 + 0.5) / len as f32).cos()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::AcousticEncoder;
+    use crate::testing::generate_utterance;
+
+    fn pcm(samples: &[f32]) -> Vec<i16> {
+        samples.iter().map(|&s| q15(s.clamp(-1.0, 1.0))).collect()
+    }
+
+    #[test]
+    fn goertzel_reads_tone_amplitude() {
+        let decoder = Q15Decoder::new();
+        let sr = DEFAULT_SAMPLE_RATE as f32;
+        let tone: Vec<f32> = (0..decoder.window.len()).map(|i| 0.25 * (2.0 * PI * 800.0 * i as f32 / sr).sin()).collect();
+        let mags = decoder.carrier_mags(&pcm(&tone));
+        assert!((mags[2] as i32 - 8192).abs() < 100, "{:?}", mags);
+        assert!(mags.iter().enumerate().all(|(i, &m)| i == 2 || m < ABS_THRESHOLD_Q15), "{:?}", mags);
+    }
+
+    #[test]
+    fn decodes_what_the_encoder_sends() {
+        for (sample_rate, seed) in [(DEFAULT_SAMPLE_RATE, 1), (8000, 2), (16000, 3)] {
+            let wire = generate_utterance(seed, 2);
+            let audio = AcousticEncoder::with_sample_rate(sample_rate).unwrap().encode(&wire).unwrap();
+            // Some quiet lead-in and low-level hiss, as from a microphone
+            let mut noise = 0x2545_F491u32;
+            let mut samples = vec![0.0; sample_rate as usize / 5];
+            samples.extend(audio.samples);
+            samples.extend(vec![0.0; sample_rate as usize / 10]);
+            for s in &mut samples {
+                noise ^= noise << 13;
+                noise ^= noise >> 17;
+                noise ^= noise << 5;
+                *s += (noise % 1000) as f32 / 1000.0 * 0.004 - 0.002;
+            }
+            let decoder = Q15Decoder::with_sample_rate(sample_rate).unwrap();
+            let samples = pcm(&samples);
+            let sync = decoder.find_sync(&samples).unwrap();
+            assert!(sync.abs_diff(sample_rate as usize / 5) <= sample_rate as usize / 500, "sync at {}", sync);
+            assert_eq!(decoder.decode(&samples).unwrap(), wire, "at {} Hz", sample_rate);
+        }
+    }
+
+    #[test]
+    fn decodes_shaped_audio() {
+        let wire = generate_utterance(4, 2);
+        let audio = AcousticEncoder::with_sample_rate(8000).unwrap().with_peak_level(1.0).encode(&wire).unwrap();
+        let decoder = Q15Decoder::with_sample_rate(8000).unwrap();
+        assert_eq!(decoder.decode(&pcm(&audio.samples)).unwrap(), wire);
+    }
+
+    #[test]
+    fn silence_has_no_sync() {
+        assert!(Q15Decoder::new().decode(&vec![0; 48_000]).is_err());
+        assert!(Q15Decoder::with_sample_rate(2000).is_err());
+    }
+}
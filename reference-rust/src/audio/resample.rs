@@ -0,0 +1,82 @@
+//! Sample-rate conversion for captures recorded at a rate other than the
+//! decoder's, e.g. 44.1 kHz phone recordings.
+//!
+//! Uses band-limited (windowed-sinc) interpolation, with the cutoff at the
+//! lower of the two Nyquist frequencies so downsampling does not alias.
+
+use std::f32::consts::PI;
+
+/// Input samples on each side of an output sample.
+const HALF_TAPS: usize = 16;
+
+/// Convert `samples` from `from_rate` to `to_rate`.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() || from_rate == 0 || to_rate == 0 {
+        return samples.to_vec();
+    }
+    let step = from_rate as f64 / to_rate as f64;
+    // Cutoff relative to the input Nyquist frequency
+    let cutoff = (to_rate as f32 / from_rate as f32).min(1.0);
+    // Widen the kernel when downsampling so it still spans HALF_TAPS zero crossings
+    let half_width = (HALF_TAPS as f32 / cutoff).ceil() as isize;
+    let out_len = (samples.len() as f64 / step).floor() as usize;
+
+    (0..out_len)
+        .map(|n| {
+            let pos = n as f64 * step;
+            let center = pos.floor() as isize;
+            let frac = (pos - center as f64) as f32;
+            let mut acc = 0.0f32;
+            for k in -half_width + 1..=half_width {
+                let Some(&s) = usize::try_from(center + k).ok().and_then(|i| samples.get(i)) else {
+                    continue;
+                };
+                let x = k as f32 - frac;
+                acc += s * cutoff * sinc(cutoff * x) * hann(x / half_width as f32);
+            }
+            acc
+        })
+        .collect()
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Hann window over `x` in [-1, 1].
+fn hann(x: f32) -> f32 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.5 + 0.5 * (PI * x).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_tone_frequency_and_level() {
+        let tone = |rate: u32, len: usize| -> Vec<f32> {
+            let step = 2.0 * PI * 1000.0 / rate as f32;
+            (0..len).map(|i| 0.5 * (step * i as f32).sin()).collect()
+        };
+        let converted = resample(&tone(44_100, 44_100), 44_100, 48_000);
+        assert_eq!(converted.len(), 48_000);
+        let expected = tone(48_000, 48_000);
+        // Skip the edges, where the kernel runs off the input
+        for i in 100..47_900 {
+            assert!((converted[i] - expected[i]).abs() < 0.01, "sample {}", i);
+        }
+
+        let down = resample(&expected, 48_000, 16_000);
+        assert_eq!(down.len(), 16_000);
+        let target = tone(16_000, 16_000);
+        assert!((100..15_900).all(|i| (down[i] - target[i]).abs() < 0.01));
+    }
+}
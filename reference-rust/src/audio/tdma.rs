@@ -0,0 +1,481 @@
+//! Time-division access to a shared acoustic channel (COMM-1 TDMA).
+//!
+//! With more than two agents in one room, carrier sense alone collides
+//! too often. A [`TdmaCoordinator`] hands out slots in a repeating cycle:
+//! agents ask for airtime with TX_REQUEST, get a TX_GRANT for their next
+//! slot (or a TX_DENY), and every change is announced as a TDMA_SCHEDULE.
+//! A [`TdmaClient`] queues outgoing audio and releases it only inside the
+//! agent's slot.
+//!
+//! ```text
+//! REQUEST ESCAPE_L1 TX_REQUEST    STRUCT{0x0000: UINT32 duration_ms}
+//! ASSERT  ESCAPE_L1 TX_GRANT      STRUCT{0x0000: TIMESTAMP slot_start, 0x0001: UINT32 duration_ms}
+//! REJECT  ESCAPE_L1 TX_DENY       STRUCT{0x0000: UINT8 reason}
+//! ASSERT  ESCAPE_L1 TDMA_SCHEDULE LIST[n](STRUCT{0x0000: BYTES(16) agent, 0x0001: UINT32 slot_ms, 0x0002: UINT32 duration_ms})
+//! ```
+//!
+//! Requests carry SOURCE_AGENT; grants and denials are sent to DEST_AGENT.
+//! A schedule's cycle starts at the utterance timestamp and repeats after
+//! the end of its last slot; the coordinator leaves a guard gap before
+//! every slot. Times are absolute microseconds, so agents need roughly
+//! synchronized clocks.
+
+use std::collections::VecDeque;
+
+use crate::agent::AgentId;
+use crate::ast::{AstNode, LiteralValue};
+use crate::encoder::AILLEncoder;
+use crate::reliability::RejectReason;
+
+/// COMM-1 TX_REQUEST entry code.
+pub const TX_REQUEST: u16 = 0x0042;
+/// COMM-1 TX_GRANT entry code.
+pub const TX_GRANT: u16 = 0x0043;
+/// COMM-1 TX_DENY entry code.
+pub const TX_DENY: u16 = 0x0044;
+/// COMM-1 TDMA_SCHEDULE entry code.
+pub const TDMA_SCHEDULE: u16 = 0x0045;
+
+/// Default silence before each slot, covering playback drain and latency.
+pub const DEFAULT_GUARD_US: i64 = 100_000;
+/// Default longest slot the coordinator grants.
+pub const DEFAULT_MAX_SLOT_US: i64 = 10_000_000;
+
+/// One agent's slot in a TDMA cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub agent: AgentId,
+    /// Start of the slot relative to the start of the cycle.
+    pub offset_us: i64,
+    pub duration_us: i64,
+}
+
+/// A COMM-1 channel access message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TdmaMessage {
+    Request { duration_us: i64 },
+    Grant { start_us: i64, duration_us: i64 },
+    Deny { reason: RejectReason },
+    Schedule { cycle_start_us: i64, slots: Vec<Slot> },
+}
+
+impl TdmaMessage {
+    /// Encode as an utterance from `source`, optionally sent to `dest`.
+    pub fn encode(&self, source: AgentId, dest: Option<AgentId>) -> Vec<u8> {
+        let timestamp = match self {
+            TdmaMessage::Schedule { cycle_start_us, .. } => Some(*cycle_start_us),
+            _ => None,
+        };
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, timestamp, dest, None).source_agent(source);
+        match self {
+            TdmaMessage::Request { duration_us } => {
+                e.request().l1_ref(TX_REQUEST).begin_struct();
+                e.field(0x0000).uint32(to_ms(*duration_us)).end_struct();
+            }
+            TdmaMessage::Grant { start_us, duration_us } => {
+                e.assert_().l1_ref(TX_GRANT).begin_struct();
+                e.field(0x0000).timestamp(*start_us);
+                e.field(0x0001).uint32(to_ms(*duration_us)).end_struct();
+            }
+            TdmaMessage::Deny { reason } => {
+                e.reject().l1_ref(TX_DENY).begin_struct();
+                e.field(0x0000).uint8(reason.code()).end_struct();
+            }
+            TdmaMessage::Schedule { slots, .. } => {
+                e.assert_().l1_ref(TDMA_SCHEDULE).begin_list(slots.len() as u16);
+                for slot in slots {
+                    e.begin_struct();
+                    e.field(0x0000).bytes(slot.agent.as_bytes());
+                    e.field(0x0001).uint32(to_ms(slot.offset_us));
+                    e.field(0x0002).uint32(to_ms(slot.duration_us));
+                    e.end_struct();
+                }
+                e.end_list();
+            }
+        }
+        e.end_utterance()
+    }
+
+    /// The channel access message in a decoded utterance, if any.
+    pub fn decode(ast: &AstNode) -> Option<TdmaMessage> {
+        let AstNode::Utterance { meta, body } = ast else {
+            return None;
+        };
+        body.windows(2).find_map(|pair| {
+            let [AstNode::Pragmatic { expression, .. }, value] = pair else {
+                return None;
+            };
+            let AstNode::DomainRef { level: 1, domain_code, .. } = expression.as_ref() else {
+                return None;
+            };
+            match (*domain_code, value) {
+                (TX_REQUEST, AstNode::Struct { fields }) => {
+                    Some(TdmaMessage::Request { duration_us: ms_field(fields.get(&0x0000))? })
+                }
+                (TX_GRANT, AstNode::Struct { fields }) => {
+                    let start_us = match fields.get(&0x0000)? {
+                        AstNode::Literal { value: LiteralValue::Timestamp(t), .. } => *t,
+                        _ => return None,
+                    };
+                    Some(TdmaMessage::Grant { start_us, duration_us: ms_field(fields.get(&0x0001))? })
+                }
+                (TX_DENY, AstNode::Struct { fields }) => match fields.get(&0x0000)? {
+                    AstNode::Literal { value: LiteralValue::Uint8(code), .. } => {
+                        Some(TdmaMessage::Deny { reason: RejectReason::from_code(*code) })
+                    }
+                    _ => None,
+                },
+                (TDMA_SCHEDULE, AstNode::List { elements, .. }) => {
+                    let slots = elements
+                        .iter()
+                        .map(|e| {
+                            let AstNode::Struct { fields } = e else { return None };
+                            let agent = match fields.get(&0x0000)? {
+                                AstNode::Literal { value: LiteralValue::Bytes(b), .. } => AgentId::try_from(b.as_slice()).ok()?,
+                                _ => return None,
+                            };
+                            Some(Slot {
+                                agent,
+                                offset_us: ms_field(fields.get(&0x0001))?,
+                                duration_us: ms_field(fields.get(&0x0002))?,
+                            })
+                        })
+                        .collect::<Option<Vec<_>>>()?;
+                    Some(TdmaMessage::Schedule { cycle_start_us: meta.timestamp_us, slots })
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Whole milliseconds, rounded up so a slot always covers its audio.
+fn to_ms(us: i64) -> u32 {
+    (us.max(0) as u64).div_ceil(1_000).min(u32::MAX as u64) as u32
+}
+
+fn ms_field(node: Option<&AstNode>) -> Option<i64> {
+    match node? {
+        AstNode::Literal { value: LiteralValue::Uint32(ms), .. } => Some(*ms as i64 * 1_000),
+        _ => None,
+    }
+}
+
+/// Start of the first occurrence of a slot in a repeating cycle that has
+/// not ended by `now_us`, or `None` for an empty cycle.
+fn next_occurrence(cycle_start_us: i64, period_us: i64, slot: &Slot, now_us: i64) -> Option<i64> {
+    if period_us <= 0 {
+        return None;
+    }
+    let first = cycle_start_us + slot.offset_us;
+    let behind = now_us - (first + slot.duration_us);
+    let cycles = if behind < 0 { 0 } else { behind / period_us + 1 };
+    Some(first + cycles * period_us)
+}
+
+/// Assigns TDMA slots to the agents sharing a channel.
+pub struct TdmaCoordinator {
+    agent: AgentId,
+    guard_us: i64,
+    max_slot_us: i64,
+    /// Requested airtime per agent, in slot order.
+    slots: Vec<(AgentId, i64)>,
+    cycle_start_us: i64,
+}
+
+impl TdmaCoordinator {
+    /// Coordinator sending its messages as `agent`.
+    pub fn new(agent: AgentId) -> Self {
+        Self {
+            agent,
+            guard_us: DEFAULT_GUARD_US,
+            max_slot_us: DEFAULT_MAX_SLOT_US,
+            slots: Vec::new(),
+            cycle_start_us: 0,
+        }
+    }
+
+    /// Silence left before every slot.
+    pub fn with_guard(mut self, guard_us: i64) -> Self {
+        self.guard_us = guard_us.max(0);
+        self
+    }
+
+    /// Deny requests for more than `max_slot_us` of airtime.
+    pub fn with_max_slot(mut self, max_slot_us: i64) -> Self {
+        self.max_slot_us = max_slot_us;
+        self
+    }
+
+    /// Length of one cycle, guards included.
+    pub fn cycle_us(&self) -> i64 {
+        self.slots.iter().map(|(_, d)| self.guard_us + to_ms(*d) as i64 * 1_000).sum()
+    }
+
+    /// The current slot assignment.
+    pub fn slots(&self) -> Vec<Slot> {
+        let mut offset_us = 0;
+        self.slots
+            .iter()
+            .map(|&(agent, d)| {
+                // Round as on the wire so every agent sees the same cycle.
+                let duration_us = to_ms(d) as i64 * 1_000;
+                offset_us += self.guard_us;
+                let slot = Slot { agent, offset_us, duration_us };
+                offset_us += duration_us;
+                slot
+            })
+            .collect()
+    }
+
+    /// The current schedule as a TDMA_SCHEDULE message.
+    pub fn schedule(&self) -> TdmaMessage {
+        TdmaMessage::Schedule { cycle_start_us: self.cycle_start_us, slots: self.slots() }
+    }
+
+    /// Give `agent` a slot of `duration_us` in every cycle, replacing any
+    /// slot it had. The new schedule takes effect at the end of the
+    /// running cycle.
+    pub fn request(&mut self, agent: AgentId, duration_us: i64, now_us: i64) -> TdmaMessage {
+        if duration_us <= 0 || duration_us > self.max_slot_us {
+            return TdmaMessage::Deny { reason: RejectReason::Policy };
+        }
+        self.restart_cycle(now_us);
+        match self.slots.iter_mut().find(|(a, _)| *a == agent) {
+            Some(slot) => slot.1 = duration_us,
+            None => self.slots.push((agent, duration_us)),
+        }
+        self.grant(&agent, now_us).unwrap_or(TdmaMessage::Deny { reason: RejectReason::Policy })
+    }
+
+    /// Take `agent`'s slot out of the schedule from the next cycle on.
+    pub fn release(&mut self, agent: &AgentId, now_us: i64) {
+        if self.slots.iter().any(|(a, _)| a == agent) {
+            self.restart_cycle(now_us);
+            self.slots.retain(|(a, _)| a != agent);
+        }
+    }
+
+    /// Start the next cycle where the running one ends.
+    fn restart_cycle(&mut self, now_us: i64) {
+        let period = self.cycle_us();
+        self.cycle_start_us = if period > 0 && now_us > self.cycle_start_us {
+            self.cycle_start_us + ((now_us - self.cycle_start_us) / period + 1) * period
+        } else {
+            self.cycle_start_us.max(now_us)
+        };
+    }
+
+    /// A TX_GRANT for the next occurrence of `agent`'s slot.
+    pub fn grant(&self, agent: &AgentId, now_us: i64) -> Option<TdmaMessage> {
+        let slot = self.slots().into_iter().find(|s| s.agent == *agent)?;
+        let start_us = next_occurrence(self.cycle_start_us, self.cycle_us(), &slot, now_us)?;
+        Some(TdmaMessage::Grant { start_us, duration_us: slot.duration_us })
+    }
+
+    /// Answer a TX_REQUEST utterance: the grant or denial addressed to the
+    /// requester, followed by the new schedule for everyone. A request for
+    /// zero airtime releases the requester's slot and only yields the
+    /// schedule. Requests without SOURCE_AGENT are ignored.
+    pub fn handle(&mut self, ast: &AstNode, now_us: i64) -> Vec<Vec<u8>> {
+        let AstNode::Utterance { meta, .. } = ast else {
+            return Vec::new();
+        };
+        let (Some(TdmaMessage::Request { duration_us }), Some(from)) = (TdmaMessage::decode(ast), meta.source_agent)
+        else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        if duration_us == 0 {
+            self.release(&from, now_us);
+        } else {
+            let reply = self.request(from, duration_us, now_us);
+            let denied = matches!(reply, TdmaMessage::Deny { .. });
+            out.push(reply.encode(self.agent, Some(from)));
+            if denied {
+                return out;
+            }
+        }
+        out.push(self.schedule().encode(self.agent, None));
+        out
+    }
+}
+
+/// The recurring slot of one agent.
+#[derive(Debug, Clone, Copy)]
+struct Cycle {
+    start_us: i64,
+    period_us: i64,
+    slot: Slot,
+}
+
+/// Queues an agent's outgoing audio until its TDMA slot.
+pub struct TdmaClient {
+    agent: AgentId,
+    sample_rate: u32,
+    cycle: Option<Cycle>,
+    /// One-off slots from TX_GRANT as `(start, end)`.
+    grants: Vec<(i64, i64)>,
+    queue: VecDeque<Vec<f32>>,
+}
+
+impl TdmaClient {
+    pub fn new(agent: AgentId, sample_rate: u32) -> Self {
+        Self { agent, sample_rate, cycle: None, grants: Vec::new(), queue: VecDeque::new() }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// A TX_REQUEST for `duration_us` of airtime per cycle.
+    pub fn request(&self, duration_us: i64) -> Vec<u8> {
+        TdmaMessage::Request { duration_us }.encode(self.agent, None)
+    }
+
+    /// A TX_REQUEST for the longest queued transmission.
+    pub fn request_for_queue(&self) -> Vec<u8> {
+        let longest = self.queue.iter().map(|s| self.duration_us(s)).max().unwrap_or(0);
+        self.request(longest)
+    }
+
+    /// Take in a grant, denial or schedule from the coordinator. Returns the
+    /// message if it concerns this agent.
+    pub fn handle(&mut self, ast: &AstNode) -> Option<TdmaMessage> {
+        let AstNode::Utterance { meta, .. } = ast else {
+            return None;
+        };
+        let msg = TdmaMessage::decode(ast)?;
+        match &msg {
+            TdmaMessage::Grant { start_us, duration_us } if meta.dest_agent == Some(self.agent) => {
+                self.grants.push((*start_us, start_us + duration_us));
+            }
+            TdmaMessage::Deny { .. } if meta.dest_agent == Some(self.agent) => {}
+            TdmaMessage::Schedule { cycle_start_us, slots } => {
+                let period_us = slots.iter().map(|s| s.offset_us + s.duration_us).max().unwrap_or(0);
+                self.cycle = slots
+                    .iter()
+                    .find(|s| s.agent == self.agent)
+                    .map(|slot| Cycle { start_us: *cycle_start_us, period_us, slot: *slot });
+            }
+            _ => return None,
+        }
+        Some(msg)
+    }
+
+    /// Queue audio for the next slot it fits in.
+    pub fn enqueue(&mut self, samples: Vec<f32>) {
+        self.queue.push_back(samples);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// The next queued audio if it may be played now.
+    pub fn poll(&mut self, now_us: i64) -> Option<Vec<f32>> {
+        let start = self.next_start(now_us)?;
+        if start > now_us {
+            return None;
+        }
+        self.queue.pop_front()
+    }
+
+    /// When the next queued audio may start, or `None` if nothing is queued
+    /// or no known slot is long enough for it.
+    pub fn next_start(&mut self, now_us: i64) -> Option<i64> {
+        let needed = self.duration_us(self.queue.front()?);
+        self.grants.retain(|&(_, end)| end > now_us);
+        let grant = self
+            .grants
+            .iter()
+            .map(|&(start, end)| (start.max(now_us), end))
+            .filter(|&(start, end)| end - start >= needed)
+            .map(|(start, _)| start)
+            .min();
+        let cycle = self.cycle.and_then(|c| {
+            if c.slot.duration_us < needed {
+                return None;
+            }
+            let start = next_occurrence(c.start_us, c.period_us, &c.slot, now_us)?;
+            if start + c.slot.duration_us - start.max(now_us) >= needed {
+                Some(start.max(now_us))
+            } else {
+                Some(start + c.period_us)
+            }
+        });
+        match (grant, cycle) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn duration_us(&self, samples: &[f32]) -> i64 {
+        (samples.len() as u64 * 1_000_000).div_ceil(self.sample_rate.max(1) as u64) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLDecoder;
+
+    const HUB: AgentId = AgentId::new([0xC; 16]);
+    const A: AgentId = AgentId::new([0xA; 16]);
+    const B: AgentId = AgentId::new([0xB; 16]);
+
+    fn decode(wire: &[u8]) -> AstNode {
+        AILLDecoder::new().decode_utterance(wire).unwrap()
+    }
+
+    #[test]
+    fn coordinator_assigns_consecutive_slots() {
+        let mut hub = TdmaCoordinator::new(HUB).with_guard(100_000).with_max_slot(2_000_000);
+        let a = TdmaClient::new(A, 8_000);
+        let out = hub.handle(&decode(&a.request(500_000)), 0);
+        assert_eq!(out.len(), 2);
+        assert_eq!(TdmaMessage::decode(&decode(&out[0])), Some(TdmaMessage::Grant { start_us: 100_000, duration_us: 500_000 }));
+
+        // B joins at the start of A's next cycle
+        let out = hub.handle(&decode(&TdmaClient::new(B, 8_000).request(300_000)), 200_000);
+        assert_eq!(TdmaMessage::decode(&decode(&out[0])), Some(TdmaMessage::Grant { start_us: 1_300_000, duration_us: 300_000 }));
+        let schedule = TdmaMessage::decode(&decode(&out[1])).unwrap();
+        assert_eq!(
+            schedule,
+            TdmaMessage::Schedule {
+                cycle_start_us: 600_000,
+                slots: vec![
+                    Slot { agent: A, offset_us: 100_000, duration_us: 500_000 },
+                    Slot { agent: B, offset_us: 700_000, duration_us: 300_000 },
+                ],
+            }
+        );
+        assert_eq!(hub.cycle_us(), 1_000_000);
+
+        let deny = hub.handle(&decode(&a.request(3_000_000)), 0);
+        assert_eq!(deny.len(), 1);
+        assert_eq!(TdmaMessage::decode(&decode(&deny[0])), Some(TdmaMessage::Deny { reason: RejectReason::Policy }));
+    }
+
+    #[test]
+    fn client_holds_audio_until_its_slot() {
+        let mut hub = TdmaCoordinator::new(HUB).with_guard(100_000);
+        hub.request(A, 500_000, 0);
+        hub.request(B, 250_000, 0);
+        let mut b = TdmaClient::new(B, 8_000);
+        let wire = hub.schedule().encode(HUB, None);
+        assert!(matches!(b.handle(&decode(&wire)), Some(TdmaMessage::Schedule { .. })));
+
+        // B's slot is 700..950ms, then every 950ms
+        b.enqueue(vec![0.0; 1_600]);
+        b.enqueue(vec![0.0; 1_600]);
+        assert_eq!(b.next_start(0), Some(700_000));
+        assert!(b.poll(650_000).is_none());
+        assert_eq!(b.poll(700_000).map(|s| s.len()), Some(1_600));
+        // 200ms of audio no longer fits in what is left of the slot
+        assert_eq!(b.next_start(800_000), Some(1_650_000));
+        assert!(b.poll(1_650_000).is_some());
+        assert!(b.is_idle());
+    }
+}
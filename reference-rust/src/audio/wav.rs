@@ -1,8 +1,89 @@
+use std::fs::OpenOptions;
+use std::io::{Cursor, Seek, SeekFrom, Write};
 use std::path::Path;
 
 use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
 
+use crate::conversation::utterance_hash;
 use crate::error::AILLError;
+use crate::wire::crc16::crc16;
+
+use super::constants::*;
+
+/// Marks the INFO comment of a WAV written with [`write_wav_with_metadata`].
+const METADATA_TAG: &str = "aill";
+
+/// Wire payload and acoustic profile of a WAV capture, carried in a
+/// `LIST`/`INFO` chunk so archived audio can be checked against what was
+/// sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavMetadata {
+    /// Length of the encoded wire bytes.
+    pub payload_len: usize,
+    /// CRC-16/CCITT-FALSE of the wire bytes.
+    pub crc16: u16,
+    /// HASH_REF of the wire bytes; see [`utterance_hash`].
+    pub hash: u32,
+    /// Acoustic parameters the audio was synthesized with, as
+    /// `(key, value)` pairs such as `("base_hz", "600")`.
+    pub profile: Vec<(String, String)>,
+}
+
+impl WavMetadata {
+    /// Metadata for audio encoding `wire_bytes` with the current profile.
+    pub fn for_payload(wire_bytes: &[u8]) -> Self {
+        let profile = [
+            ("base_hz", BASE_FREQ.to_string()),
+            ("spacing_hz", TONE_SPACING.to_string()),
+            ("carriers", NUM_CARRIERS.to_string()),
+            ("symbol_ms", (SYMBOL_DURATION * 1000.0).to_string()),
+            ("frame_ms", (FRAME_TIME * 1000.0).to_string()),
+        ];
+        Self {
+            payload_len: wire_bytes.len(),
+            crc16: crc16(wire_bytes),
+            hash: utterance_hash(wire_bytes),
+            profile: profile.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+        }
+    }
+
+    /// Whether `decoded` matches the payload this metadata describes.
+    pub fn verify(&self, decoded: &[u8]) -> bool {
+        decoded.len() == self.payload_len
+            && crc16(decoded) == self.crc16
+            && utterance_hash(decoded) == self.hash
+    }
+
+    /// `aill payload_len=… crc16=… hash=… key=value…`
+    fn to_comment(&self) -> String {
+        let mut comment = format!(
+            "{} payload_len={} crc16={:04x} hash={:08x}",
+            METADATA_TAG, self.payload_len, self.crc16, self.hash
+        );
+        for (key, value) in &self.profile {
+            comment.push_str(&format!(" {}={}", key, value));
+        }
+        comment
+    }
+
+    fn from_comment(comment: &str) -> Option<Self> {
+        let mut words = comment.split_whitespace();
+        if words.next()? != METADATA_TAG {
+            return None;
+        }
+        let (mut payload_len, mut crc, mut hash) = (None, None, None);
+        let mut profile = Vec::new();
+        for (key, value) in words.filter_map(|w| w.split_once('=')) {
+            match key {
+                "payload_len" => payload_len = value.parse().ok(),
+                "crc16" => crc = u16::from_str_radix(value, 16).ok(),
+                "hash" => hash = u32::from_str_radix(value, 16).ok(),
+                _ => profile.push((key.to_string(), value.to_string())),
+            }
+        }
+        Some(Self { payload_len: payload_len?, crc16: crc?, hash: hash?, profile })
+    }
+}
 
 /// Write mono f32 PCM samples to a WAV file.
 pub fn write_wav<P: AsRef<Path>>(
@@ -33,10 +114,40 @@ pub fn write_wav<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Write mono f32 PCM samples to a WAV file followed by a `LIST`/`INFO`
+/// chunk holding `metadata`.
+pub fn write_wav_with_metadata<P: AsRef<Path>>(
+    path: P,
+    samples: &[f32],
+    sample_rate: u32,
+    metadata: &WavMetadata,
+) -> Result<(), AILLError> {
+    let path = path.as_ref();
+    write_wav(path, samples, sample_rate)?;
+
+    let io_err = |e: std::io::Error| AILLError::EncoderError(format!("WAV metadata write error: {}", e));
+    let mut file = OpenOptions::new().read(true).write(true).open(path).map_err(io_err)?;
+    let riff_len = file.seek(SeekFrom::End(0)).map_err(io_err)?;
+    let chunk = info_chunk(&[(b"ISFT", METADATA_TAG), (b"ICMT", &metadata.to_comment())]);
+    file.write_all(&chunk).map_err(io_err)?;
+
+    // The RIFF size covers everything after its own 8-byte header
+    let riff_size = u32::try_from(riff_len + chunk.len() as u64 - 8)
+        .map_err(|_| AILLError::EncoderError("WAV file too large for metadata".into()))?;
+    file.seek(SeekFrom::Start(4)).map_err(io_err)?;
+    file.write_all(&riff_size.to_le_bytes()).map_err(io_err)?;
+    Ok(())
+}
+
 /// Read mono f32 PCM samples from a WAV file.
-/// Returns (samples, sample_rate).
-pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, u32), AILLError> {
-    let reader = WavReader::open(path)
+/// Returns (samples, sample_rate, metadata), with the metadata of a file
+/// written by [`write_wav_with_metadata`].
+pub fn read_wav<P: AsRef<Path>>(
+    path: P,
+) -> Result<(Vec<f32>, u32, Option<WavMetadata>), AILLError> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| AILLError::InvalidStructure(format!("WAV read error: {}", e)))?;
+    let reader = WavReader::new(Cursor::new(&bytes))
         .map_err(|e| AILLError::InvalidStructure(format!("WAV read error: {}", e)))?;
 
     let spec = reader.spec();
@@ -72,7 +183,61 @@ pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, u32), AILLError> {
         }
     };
 
-    Ok((samples, sample_rate))
+    let metadata = info_entries(&bytes)
+        .into_iter()
+        .find(|(id, _)| id == b"ICMT")
+        .and_then(|(_, comment)| WavMetadata::from_comment(&comment));
+
+    Ok((samples, sample_rate, metadata))
+}
+
+/// A `LIST` chunk of type `INFO` holding NUL-terminated text entries.
+fn info_chunk(entries: &[(&[u8; 4], &str)]) -> Vec<u8> {
+    let mut body = b"INFO".to_vec();
+    for (id, text) in entries {
+        let mut value = text.as_bytes().to_vec();
+        value.push(0);
+        body.extend_from_slice(*id);
+        body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        body.extend_from_slice(&value);
+        if value.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut chunk = b"LIST".to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(&body);
+    chunk
+}
+
+/// Text entries of every `LIST`/`INFO` chunk in a RIFF file.
+fn info_entries(riff: &[u8]) -> Vec<([u8; 4], String)> {
+    let mut entries = Vec::new();
+    for (id, body) in chunks(riff.get(12..).unwrap_or_default()) {
+        if id == *b"LIST" && body.starts_with(b"INFO") {
+            for (id, value) in chunks(&body[4..]) {
+                let text = value.split(|&b| b == 0).next().unwrap_or_default();
+                entries.push((id, String::from_utf8_lossy(text).into_owned()));
+            }
+        }
+    }
+    entries
+}
+
+/// `(id, body)` of each chunk in a run of RIFF chunks, stopping at the
+/// first truncated one.
+fn chunks(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut found = Vec::new();
+    while data.len() >= 8 {
+        let id = [data[0], data[1], data[2], data[3]];
+        let size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let Some(body) = data.get(8..8 + size) else {
+            break;
+        };
+        found.push((id, body));
+        data = data.get(8 + size + size % 2..).unwrap_or_default();
+    }
+    found
 }
 
 #[cfg(test)]
@@ -87,9 +252,10 @@ mod tests {
         let sr = 48000;
 
         write_wav(path, &samples, sr).unwrap();
-        let (read_samples, read_sr) = read_wav(path).unwrap();
+        let (read_samples, read_sr, metadata) = read_wav(path).unwrap();
 
         assert_eq!(read_sr, sr);
+        assert_eq!(metadata, None);
         assert_eq!(read_samples.len(), samples.len());
         for (a, b) in samples.iter().zip(read_samples.iter()) {
             assert!((a - b).abs() < 1e-6, "Sample mismatch: {} vs {}", a, b);
@@ -97,4 +263,23 @@ mod tests {
 
         fs::remove_file(path).ok();
     }
+
+    #[test]
+    fn test_metadata_chunk_roundtrip() {
+        let path = "/tmp/aill_test_wav_metadata.wav";
+        let samples: Vec<f32> = (0..999).map(|i| (i as f32 * 0.01).sin()).collect();
+        let wire = [0x00, 0x90, 0x3C, 0x00, 0x01];
+        let metadata = WavMetadata::for_payload(&wire);
+
+        write_wav_with_metadata(path, &samples, 48000, &metadata).unwrap();
+        let (read_samples, _, read_metadata) = read_wav(path).unwrap();
+        fs::remove_file(path).ok();
+
+        assert_eq!(read_samples.len(), samples.len());
+        let read_metadata = read_metadata.unwrap();
+        assert_eq!(read_metadata, metadata);
+        assert!(read_metadata.verify(&wire));
+        assert!(!read_metadata.verify(&wire[..4]));
+        assert!(read_metadata.profile.contains(&("base_hz".into(), "600".into())));
+    }
 }
@@ -0,0 +1,223 @@
+//! Batching of small utterances into shared epochs.
+//!
+//! Status reports and acknowledgements are often only a few dozen bytes,
+//! and sending each in an epoch of its own pays the epoch header and CRC,
+//! and on an acoustic link the preamble, every time. An
+//! [`UtteranceBatcher`] packs complete utterances back to back into one
+//! epoch payload until it is full or the oldest utterance has waited
+//! `max_delay_us`. The receiver needs nothing special: a
+//! [`StreamDecoder`](crate::stream::StreamDecoder) already returns every
+//! utterance in a payload, and [`split_utterances`] cuts a payload into
+//! the wire bytes of each, e.g. for forwarding.
+//!
+//! An utterance larger than an epoch is refused: expressions must not be
+//! split across epochs, so send large data in smaller utterances.
+
+use std::ops::Range;
+
+use crate::decoder::AILLDecoder;
+use crate::encoder::{frame_epoch, MAX_EPOCH_PAYLOAD};
+use crate::error::AILLError;
+
+/// How long an utterance waits for company by default, in microseconds.
+pub const DEFAULT_BATCH_DELAY_US: i64 = 250_000;
+
+/// Counters for judging what batching saves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub utterances: u64,
+    pub epochs: u64,
+    /// Utterance bytes sent.
+    pub payload_bytes: u64,
+    /// Epoch bytes sent, headers and CRC included.
+    pub wire_bytes: u64,
+}
+
+impl BatchStats {
+    /// Fraction of the wire bytes spent on epoch framing.
+    pub fn framing_overhead(&self) -> f64 {
+        match self.wire_bytes {
+            0 => 0.0,
+            wire => (wire - self.payload_bytes) as f64 / wire as f64,
+        }
+    }
+}
+
+/// Packs utterances into epochs; see the [module docs](self).
+pub struct UtteranceBatcher {
+    seq: u16,
+    max_payload: usize,
+    max_delay_us: i64,
+    payload: Vec<u8>,
+    /// When the oldest utterance in `payload` was queued.
+    oldest_us: Option<i64>,
+    stats: BatchStats,
+}
+
+impl Default for UtteranceBatcher {
+    fn default() -> Self {
+        Self {
+            seq: 0,
+            max_payload: MAX_EPOCH_PAYLOAD,
+            max_delay_us: DEFAULT_BATCH_DELAY_US,
+            payload: Vec::new(),
+            oldest_us: None,
+            stats: BatchStats::default(),
+        }
+    }
+}
+
+impl UtteranceBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap epoch payloads at `max_payload` bytes (at most [`MAX_EPOCH_PAYLOAD`]).
+    pub fn with_max_payload(mut self, max_payload: usize) -> Self {
+        self.max_payload = max_payload.clamp(1, MAX_EPOCH_PAYLOAD);
+        self
+    }
+
+    /// Send a batch once its oldest utterance has waited `max_delay_us`.
+    pub fn with_max_delay_us(mut self, max_delay_us: i64) -> Self {
+        self.max_delay_us = max_delay_us;
+        self
+    }
+
+    /// Continue numbering from `seq`, e.g. after restoring a session.
+    pub fn with_seq(mut self, seq: u16) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// Sequence number of the next epoch.
+    pub fn next_seq(&self) -> u16 {
+        self.seq
+    }
+
+    /// Bytes waiting for the next epoch.
+    pub fn pending(&self) -> usize {
+        self.payload.len()
+    }
+
+    pub fn stats(&self) -> BatchStats {
+        self.stats
+    }
+
+    /// Queue one complete utterance at `now_us`, returning the epochs that
+    /// are ready to send. Fails, queueing nothing, if the utterance is
+    /// longer than an epoch payload.
+    pub fn push(&mut self, utterance: &[u8], now_us: i64) -> Result<Vec<Vec<u8>>, AILLError> {
+        if utterance.len() > self.max_payload {
+            return Err(AILLError::EncoderError(format!(
+                "utterance of {} bytes does not fit a {}-byte epoch",
+                utterance.len(),
+                self.max_payload
+            )));
+        }
+        let mut ready = Vec::new();
+        if self.payload.len() + utterance.len() > self.max_payload {
+            ready.extend(self.flush());
+        }
+        self.stats.utterances += 1;
+        self.payload.extend_from_slice(utterance);
+        self.oldest_us.get_or_insert(now_us);
+        ready.extend(self.poll(now_us));
+        Ok(ready)
+    }
+
+    /// The pending batch, if its oldest utterance has waited long enough
+    /// or it cannot take another byte.
+    pub fn poll(&mut self, now_us: i64) -> Option<Vec<u8>> {
+        let due = self.oldest_us.is_some_and(|t| now_us.saturating_sub(t) >= self.max_delay_us);
+        match due || self.payload.len() >= self.max_payload {
+            true => self.flush(),
+            false => None,
+        }
+    }
+
+    /// The pending batch, regardless of its age.
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        self.oldest_us = None;
+        if self.payload.is_empty() {
+            return None;
+        }
+        let payload = std::mem::take(&mut self.payload);
+        Some(self.frame(&payload))
+    }
+
+    fn frame(&mut self, payload: &[u8]) -> Vec<u8> {
+        let epoch = frame_epoch(self.seq, payload);
+        self.seq = self.seq.wrapping_add(1);
+        self.stats.epochs += 1;
+        self.stats.payload_bytes += payload.len() as u64;
+        self.stats.wire_bytes += epoch.len() as u64;
+        epoch
+    }
+}
+
+/// Byte ranges of the utterances packed in an epoch payload.
+pub fn split_utterances(payload: &[u8]) -> Result<Vec<Range<usize>>, AILLError> {
+    AILLDecoder::new().utterances(payload).map(|r| r.map(|(_, range)| range)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_epoch;
+    use crate::encoder::AILLEncoder;
+    use crate::stream::StreamDecoder;
+
+    fn ack(n: u8) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().acknowledge().uint8(n);
+        e.end_utterance()
+    }
+
+    #[test]
+    fn batching_cuts_framing_overhead() {
+        let acks: Vec<_> = (0..40).map(ack).collect();
+        let mut single = UtteranceBatcher::new().with_max_delay_us(0);
+        let mut batched = UtteranceBatcher::new().with_max_payload(256);
+        let mut epochs = Vec::new();
+        for (i, wire) in acks.iter().enumerate() {
+            single.push(wire, i as i64 * 10_000).unwrap();
+            epochs.extend(batched.push(wire, i as i64 * 10_000).unwrap());
+        }
+        epochs.extend(batched.flush());
+
+        let (single, batched) = (single.stats(), batched.stats());
+        assert_eq!(single.epochs, 40);
+        assert_eq!(single.payload_bytes, batched.payload_bytes);
+        assert!(batched.epochs <= 8, "{:?}", batched);
+        assert!(batched.framing_overhead() * 4.0 < single.framing_overhead(), "{:?} vs {:?}", batched, single);
+
+        // Each payload splits back into whole utterances
+        let mut stream = StreamDecoder::new();
+        let mut received = Vec::new();
+        for epoch in &epochs {
+            let (epoch, _) = decode_epoch(epoch, 0).unwrap();
+            let ranges = split_utterances(&epoch.payload).unwrap();
+            received.extend(ranges.iter().map(|r| epoch.payload[r.clone()].to_vec()));
+            assert_eq!(stream.push(&epoch.payload).unwrap().len(), ranges.len());
+        }
+        assert_eq!(received, acks);
+    }
+
+    #[test]
+    fn flushes_on_delay_and_refuses_large_utterances() {
+        let mut batcher = UtteranceBatcher::new().with_max_payload(64).with_max_delay_us(1_000);
+        assert!(batcher.push(&ack(1), 0).unwrap().is_empty());
+        assert!(batcher.poll(999).is_none());
+        assert!(batcher.poll(1_000).is_some());
+        assert_eq!(batcher.pending(), 0);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().bytes(&[7; 100]);
+        let large = e.end_utterance();
+        batcher.push(&ack(2), 2_000).unwrap();
+        assert!(matches!(batcher.push(&large, 2_000), Err(AILLError::EncoderError(_))));
+        assert_eq!((batcher.pending(), batcher.stats().utterances), (ack(2).len(), 2));
+        assert_eq!(batcher.flush().map(|epoch| decode_epoch(&epoch, 0).unwrap().0.payload), Some(ack(2)));
+    }
+}
@@ -0,0 +1,162 @@
+//! The link-independent half of `aill-bridge`: relaying utterances between
+//! the acoustic link and a datagram link.
+//!
+//! Over the air, frames are bare utterances, as `aill-live tx` plays them.
+//! On the network side every utterance heard is framed into an epoch and
+//! sent through the session's reliable sender, and the peer's epochs are
+//! acknowledged, put back in order and reassembled before the complete
+//! utterances are queued for the speaker. Epochs and ACK/NACK frames share
+//! the datagram link and are told apart by length. The gateway only deals
+//! in frames, so the same code runs over UDP in `main.rs` and over an
+//! in-memory channel in `tests/bridge.rs`.
+
+use std::collections::VecDeque;
+
+use aill::estimate::EPOCH_OVERHEAD;
+use aill::reliability::{abort_reason, LinkStats, DEFAULT_MAX_RETRIES};
+use aill::reorder::{EpochReorderBuffer, ReorderEvent};
+use aill::session::SessionState;
+use aill::stream::StreamDecoder;
+use aill::AILLError;
+
+/// How long an epoch may go unacknowledged before it is sent again. Covers
+/// a listening window of the bridge at the other end of the network.
+pub const RETRANSMIT_TIMEOUT_US: i64 = 10_000_000;
+
+/// Relays utterances between the acoustic and the datagram link.
+pub struct Gateway {
+    session: SessionState,
+    reorder: EpochReorderBuffer,
+    stream: StreamDecoder,
+    /// Bytes of the utterance being reassembled from the network.
+    partial: Vec<u8>,
+    to_net: VecDeque<Vec<u8>>,
+    to_air: VecDeque<Vec<u8>>,
+    /// Frames heard over the air that were not whole utterances.
+    rejected: u64,
+}
+
+impl Gateway {
+    pub fn new() -> Self {
+        let session = SessionState::new(RETRANSMIT_TIMEOUT_US);
+        // Skip a missing epoch only once the sender has given up on it
+        let reorder_timeout = RETRANSMIT_TIMEOUT_US * (DEFAULT_MAX_RETRIES as i64 + 1);
+        Self {
+            reorder: EpochReorderBuffer::new(reorder_timeout).with_next_seq(0),
+            stream: StreamDecoder::with_decoder(session.decoder()),
+            session,
+            partial: Vec::new(),
+            to_net: VecDeque::new(),
+            to_air: VecDeque::new(),
+            rejected: 0,
+        }
+    }
+
+    /// Forward a frame decoded from the acoustic link to the network. Fails
+    /// without forwarding anything unless the frame holds whole utterances,
+    /// so that misheard audio stays off the network.
+    pub fn heard(&mut self, frame: &[u8], now_us: i64) -> Result<(), AILLError> {
+        let mut check = StreamDecoder::with_decoder(self.session.decoder());
+        let whole = check.push(frame).map(|utterances| !utterances.is_empty() && check.pending() == 0);
+        if !matches!(whole, Ok(true)) {
+            self.rejected += 1;
+            whole?;
+            return Err(AILLError::InvalidStructure("frame is not a whole utterance".into()));
+        }
+
+        let mut builder = self.session.epoch_builder();
+        builder.write(frame);
+        for epoch in builder.get_epochs() {
+            if let Some(datagram) = self.session.sender.send(epoch, now_us)? {
+                self.to_net.push_back(datagram);
+            }
+        }
+        self.session.next_seq = builder.next_seq();
+        Ok(())
+    }
+
+    /// Handle a datagram from the network: an epoch or a control frame.
+    pub fn received(&mut self, datagram: &[u8], now_us: i64) -> Result<(), AILLError> {
+        if datagram.len() < EPOCH_OVERHEAD {
+            let resend = self.session.sender.handle_control(datagram, now_us)?;
+            self.to_net.extend(resend);
+            return Ok(());
+        }
+        let delivery = self.session.receiver.receive(datagram, now_us)?;
+        if !delivery.reply.is_empty() {
+            self.to_net.push_back(delivery.reply);
+        }
+        if let Some(payload) = delivery.payload {
+            let seq = u16::from_be_bytes([datagram[0], datagram[1]]);
+            let events = self.reorder.insert(seq, payload, now_us);
+            self.deliver(events)?;
+        }
+        Ok(())
+    }
+
+    /// Datagrams to send now: replies, new epochs and retransmissions of
+    /// epochs whose timeout has expired.
+    pub fn poll_net(&mut self, now_us: i64) -> Result<Vec<Vec<u8>>, AILLError> {
+        let events = self.reorder.poll(now_us);
+        self.deliver(events)?;
+        let resend = self.session.sender.poll(now_us);
+        self.to_net.extend(resend);
+        Ok(self.to_net.drain(..).collect())
+    }
+
+    /// Utterances from the network to play, oldest first.
+    pub fn poll_air(&mut self) -> Vec<Vec<u8>> {
+        self.to_air.drain(..).collect()
+    }
+
+    /// Nothing left to send either way and every epoch sent has been
+    /// acknowledged or given up on.
+    pub fn is_idle(&self) -> bool {
+        self.to_net.is_empty() && self.to_air.is_empty() && self.session.sender.in_flight() == 0
+    }
+
+    /// Counters of the network side: epochs sent to, and received from,
+    /// the peer.
+    pub fn link_stats(&self) -> (LinkStats, LinkStats) {
+        (self.session.sender.stats(), self.session.receiver.stats())
+    }
+
+    /// Frames heard over the air and not forwarded.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    fn deliver(&mut self, events: Vec<ReorderEvent>) -> Result<(), AILLError> {
+        for event in events {
+            match event {
+                ReorderEvent::Deliver { payload, .. } => {
+                    self.partial.extend_from_slice(&payload);
+                    let done = match self.stream.push(&payload) {
+                        Ok(utterances) => !utterances.is_empty(),
+                        Err(e) => {
+                            self.partial.clear();
+                            return Err(e);
+                        }
+                    };
+                    // Play what is complete; keep the rest for later epochs
+                    if done {
+                        let rest = self.partial.split_off(self.partial.len() - self.stream.pending());
+                        self.to_air.push_back(std::mem::replace(&mut self.partial, rest));
+                    }
+                }
+                // The partial utterance can no longer be completed
+                ReorderEvent::Gap { .. } => {
+                    self.stream.abort(abort_reason::CHANNEL_DEGRADED);
+                    self.partial.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+//! Gateway between the acoustic link and a UDP peer.
+//!
+//! Run `aill-bridge <bind-addr> <peer-addr>` on a machine within earshot
+//! of an air-gapped agent. Utterances heard from the microphone are sent
+//! reliably to the peer over UDP; utterances the peer sends are played on
+//! the speaker once the channel is clear. The acoustic side is half
+//! duplex: the bridge listens for a fixed window, forwards what it heard,
+//! then handles the datagrams that arrived meanwhile.
+
+mod gateway;
+
+use std::env;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::process;
+
+use aill::audio::constants::{ABS_THRESHOLD, DEFAULT_SAMPLE_RATE};
+use aill::audio::{live, AcousticDecoder, AcousticEncoder, Pacer};
+use aill::timestamp::now_micros;
+
+use gateway::Gateway;
+
+/// How long each listening window lasts (seconds).
+const LISTEN_SECS: f32 = 4.0;
+
+/// Listening periods to wait for a busy channel before each utterance.
+const MAX_CHANNEL_ATTEMPTS: u32 = 50;
+
+/// Minimum gap (microseconds) left after the channel frees up.
+const MIN_TX_GAP_US: i64 = 100_000;
+
+/// Largest datagram accepted from the peer.
+const MAX_DATAGRAM: usize = 65_536;
+
+fn usage() -> ! {
+    eprintln!("Usage:");
+    eprintln!("  aill-bridge <bind-addr> <peer-addr>   Relay between the mic/speaker and a UDP peer");
+    eprintln!();
+    eprintln!("Example: aill-bridge 0.0.0.0:7400 10.0.0.5:7400");
+    process::exit(1);
+}
+
+fn run(bind: &str, peer: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let socket = UdpSocket::bind(bind).map_err(|e| format!("Cannot bind {}: {}", bind, e))?;
+    socket.connect(peer).map_err(|e| format!("Cannot reach {}: {}", peer, e))?;
+    socket.set_nonblocking(true)?;
+    println!("Bridging audio and {} <-> {}", socket.local_addr()?, peer);
+
+    let encoder = AcousticEncoder::new();
+    let decoder = AcousticDecoder::new();
+    let mut pacer = Pacer::new(MIN_TX_GAP_US).with_seed(now_micros() as u64);
+    let mut gateway = Gateway::new();
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+
+    loop {
+        // Datagrams queued by the OS while we were listening
+        loop {
+            match socket.recv(&mut buf) {
+                Ok(n) => {
+                    if let Err(e) = gateway.received(&buf[..n], now_micros()) {
+                        eprintln!("Dropped datagram: {}", e);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // A peer that is not up yet refuses our datagrams
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        for datagram in gateway.poll_net(now_micros())? {
+            if let Err(e) = socket.send(&datagram) {
+                eprintln!("Send to {} failed: {}", peer, e);
+            }
+        }
+
+        for utterance in gateway.poll_air() {
+            println!("net -> air: {} bytes", utterance.len());
+            let audio = encoder.encode(&utterance)?;
+            live::play_when_clear(&audio.samples, audio.sample_rate, &mut pacer, ABS_THRESHOLD, MAX_CHANNEL_ATTEMPTS)?;
+        }
+
+        let samples = live::record_audio(LISTEN_SECS, DEFAULT_SAMPLE_RATE)?;
+        for result in decoder.decode_all(&samples) {
+            match result.bytes {
+                Ok(frame) => match gateway.heard(&frame, now_micros()) {
+                    Ok(()) => println!("air -> net: {} bytes", frame.len()),
+                    Err(e) => eprintln!("Not forwarded: {}", e),
+                },
+                Err(e) => eprintln!("Undecodable frame: {}", e),
+            }
+        }
+        if !gateway.is_idle() {
+            let (sent, received) = gateway.link_stats();
+            println!(
+                "net: {} epochs sent, {} retransmitted, {} received; {} frames not forwarded",
+                sent.epochs_sent, sent.retransmits, received.epochs_received, gateway.rejected()
+            );
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        usage();
+    }
+    if let Err(e) = run(&args[1], &args[2]) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,70 @@
+use std::env;
+use std::process;
+
+use aill::conformance::{load_vectors, run};
+use aill::AILLDecoder;
+
+/// Suite name in JUnit reports.
+const SUITE_NAME: &str = "aill-conformance";
+
+fn usage() -> ! {
+    eprintln!("Usage:");
+    eprintln!("  aill-conformance <dir> [--format json|junit] [--out <file>]");
+    eprintln!();
+    eprintln!("Checks every <name>.hex / <name>.json vector pair in <dir> against the");
+    eprintln!("reference decoder and encoder. Prints the report to stdout unless --out");
+    eprintln!("is given; exits non-zero if any check fails.");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut dir = None;
+    let mut format = "json".to_string();
+    let mut out = None;
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--format" => format = rest.next().cloned().unwrap_or_else(|| usage()),
+            "--out" => out = Some(rest.next().cloned().unwrap_or_else(|| usage())),
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+    let Some(dir) = dir else { usage() };
+
+    let vectors = match load_vectors(&dir) {
+        Ok(vectors) => vectors,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let report = run(&vectors, &AILLDecoder::new());
+    let rendered = match format.as_str() {
+        "json" => report.to_json(),
+        "junit" => report.to_junit(SUITE_NAME),
+        other => {
+            eprintln!("Unknown format: {}", other);
+            usage();
+        }
+    };
+
+    match &out {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, rendered) {
+                eprintln!("Error: failed to write {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", rendered),
+    }
+
+    for failure in report.failures() {
+        eprintln!("FAIL {} ({:?}): {}", failure.vector, failure.check, failure.failure.as_deref().unwrap_or_default());
+    }
+    eprintln!("{} vectors, {} checks passed, {} failed", vectors.len(), report.passed(), report.failed());
+    if report.failed() > 0 {
+        process::exit(1);
+    }
+}
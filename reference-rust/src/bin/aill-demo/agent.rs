@@ -0,0 +1,259 @@
+//! The link-independent half of `aill-demo`: an agent negotiating a COMM-1
+//! SILENCE_PERIOD with one peer.
+//!
+//! Each utterance is split into epochs of at most [`FRAGMENT_BYTES`] and
+//! sent through the session's reliable sender; the peer acknowledges every
+//! epoch, puts them back in order and reassembles the utterance. The agent
+//! only deals in wire frames, so the same code runs over the acoustic link
+//! in `main.rs` and over an in-memory channel in `tests/demo_agent.rs`.
+
+use std::collections::VecDeque;
+
+use aill::codebook::base::pragma;
+use aill::estimate::EPOCH_OVERHEAD;
+use aill::reliability::{abort_reason, LinkStats, DEFAULT_MAX_RETRIES};
+use aill::reorder::{EpochReorderBuffer, ReorderEvent};
+use aill::session::SessionState;
+use aill::stream::StreamDecoder;
+use aill::thread::{new_thread_id, thread_id, ThreadStore};
+use aill::{pretty_print, AILLEncoder, AILLError, AgentId, AstNode, LiteralValue};
+
+/// COMM-1 SILENCE_PERIOD, `STRUCT{start, duration}`.
+pub const SILENCE_PERIOD: u16 = 0x0048;
+
+/// Largest utterance fragment carried by one epoch. Short epochs keep each
+/// acoustic frame brief, so a burst of noise costs one retransmission
+/// rather than the whole utterance.
+pub const FRAGMENT_BYTES: usize = 32;
+
+/// How long an epoch may go unacknowledged before it is sent again. Covers
+/// the peer's listening window plus the airtime of its reply.
+pub const RETRANSMIT_TIMEOUT_US: i64 = 30_000_000;
+
+/// Proposals, counter-proposals included, an agent makes before giving up.
+pub const MAX_ROUNDS: u32 = 4;
+
+/// The negotiated term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilencePeriod {
+    pub start_us: i64,
+    pub duration_ms: u32,
+}
+
+/// How a negotiation ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Agreed(SilencePeriod),
+    Refused,
+}
+
+/// One side of a SILENCE_PERIOD negotiation.
+pub struct DemoAgent {
+    id: AgentId,
+    /// Silence durations this agent accepts.
+    min_ms: u32,
+    max_ms: u32,
+    session: SessionState,
+    reorder: EpochReorderBuffer,
+    stream: StreamDecoder,
+    threads: ThreadStore,
+    thread: Option<u64>,
+    peer: Option<AgentId>,
+    rounds: u32,
+    outbox: VecDeque<Vec<u8>>,
+    outcome: Option<Outcome>,
+}
+
+impl DemoAgent {
+    /// Agent `id` accepting silence periods of `min_ms..=max_ms`.
+    pub fn new(id: AgentId, min_ms: u32, max_ms: u32) -> Self {
+        let session = SessionState::new(RETRANSMIT_TIMEOUT_US);
+        // Skip a missing epoch only once the sender has given up on it
+        let reorder_timeout = RETRANSMIT_TIMEOUT_US * (DEFAULT_MAX_RETRIES as i64 + 1);
+        Self {
+            id,
+            min_ms: min_ms.min(max_ms),
+            max_ms,
+            reorder: EpochReorderBuffer::new(reorder_timeout).with_next_seq(0),
+            stream: StreamDecoder::with_decoder(session.decoder()),
+            session,
+            threads: ThreadStore::new(1),
+            thread: None,
+            peer: None,
+            rounds: 0,
+            outbox: VecDeque::new(),
+            outcome: None,
+        }
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    /// Nothing left to send and every epoch sent has been acknowledged or
+    /// given up on.
+    pub fn is_idle(&self) -> bool {
+        self.outbox.is_empty() && self.session.sender.in_flight() == 0
+    }
+
+    /// Counters of the sending end of the link.
+    pub fn link_stats(&self) -> LinkStats {
+        self.session.sender.stats()
+    }
+
+    /// Open a negotiation by proposing `period`.
+    pub fn propose(&mut self, period: SilencePeriod, now_us: i64) -> Result<(), AILLError> {
+        self.thread = Some(new_thread_id());
+        self.say(pragma::PROPOSE, period, now_us)
+    }
+
+    /// Handle a frame received from the link: an epoch or a control frame.
+    pub fn receive(&mut self, frame: &[u8], now_us: i64) -> Result<(), AILLError> {
+        if frame.len() < EPOCH_OVERHEAD {
+            let resend = self.session.sender.handle_control(frame, now_us)?;
+            self.outbox.extend(resend);
+            return Ok(());
+        }
+        let delivery = self.session.receiver.receive(frame, now_us)?;
+        self.outbox.push_back(delivery.reply);
+        if let Some(payload) = delivery.payload {
+            let seq = u16::from_be_bytes([frame[0], frame[1]]);
+            let events = self.reorder.insert(seq, payload, now_us);
+            self.deliver(events, now_us)?;
+        }
+        Ok(())
+    }
+
+    /// Frames to put on the link now: replies, new epochs and
+    /// retransmissions of epochs whose timeout has expired.
+    pub fn poll(&mut self, now_us: i64) -> Result<Vec<Vec<u8>>, AILLError> {
+        let events = self.reorder.poll(now_us);
+        self.deliver(events, now_us)?;
+        let resend = self.session.sender.poll(now_us);
+        self.outbox.extend(resend);
+        Ok(self.outbox.drain(..).collect())
+    }
+
+    /// The negotiation so far, both sides' utterances in order.
+    pub fn transcript(&self) -> Vec<String> {
+        let Some(thread) = self.thread.and_then(|t| self.threads.get(t)) else {
+            return Vec::new();
+        };
+        thread.utterances().iter().map(|u| pretty_print(u, 0)).collect()
+    }
+
+    fn deliver(&mut self, events: Vec<ReorderEvent>, now_us: i64) -> Result<(), AILLError> {
+        for event in events {
+            match event {
+                ReorderEvent::Deliver { payload, .. } => {
+                    for utterance in self.stream.push(&payload)? {
+                        self.on_utterance(utterance, now_us)?;
+                    }
+                }
+                // The partial utterance can no longer be completed
+                ReorderEvent::Gap { .. } => {
+                    self.stream.abort(abort_reason::CHANNEL_DEGRADED);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_utterance(&mut self, utterance: AstNode, now_us: i64) -> Result<(), AILLError> {
+        let AstNode::Utterance { meta, .. } = &utterance else {
+            return Ok(());
+        };
+        if meta.source_agent == Some(self.id) || meta.dest_agent.is_some_and(|d| d != self.id) {
+            return Ok(());
+        }
+        let Some((act, period)) = silence_period(&utterance) else {
+            return Ok(());
+        };
+        match (self.thread, thread_id(&utterance)) {
+            (Some(ours), Some(theirs)) if ours == theirs => {}
+            (None, Some(theirs)) => self.thread = Some(theirs),
+            _ => return Ok(()),
+        }
+        self.peer = meta.source_agent.or(self.peer);
+        let act = act.to_string();
+        self.threads.insert(utterance);
+        if self.outcome.is_some() {
+            return Ok(());
+        }
+
+        match act.as_str() {
+            "PROPOSE" => {
+                let acceptable = period.duration_ms.clamp(self.min_ms, self.max_ms);
+                if acceptable == period.duration_ms {
+                    self.say(pragma::ACCEPT, period, now_us)?;
+                    self.outcome = Some(Outcome::Agreed(period));
+                } else if self.rounds < MAX_ROUNDS {
+                    self.say(pragma::PROPOSE, SilencePeriod { duration_ms: acceptable, ..period }, now_us)?;
+                } else {
+                    self.say(pragma::REJECT, period, now_us)?;
+                    self.outcome = Some(Outcome::Refused);
+                }
+            }
+            "ACCEPT" => self.outcome = Some(Outcome::Agreed(period)),
+            "REJECT" => self.outcome = Some(Outcome::Refused),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Send `act` SILENCE_PERIOD `period` to the peer, fragmented into
+    /// epochs.
+    fn say(&mut self, act: u8, period: SilencePeriod, now_us: i64) -> Result<(), AILLError> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, Some(now_us), self.peer, None).source_agent(self.id);
+        if let Some(thread) = self.thread {
+            e.thread_id(thread);
+        }
+        e.pragma(act).l1_ref(SILENCE_PERIOD).begin_struct();
+        e.field(0x0000).timestamp(period.start_us);
+        e.field(0x0001).uint32(period.duration_ms).end_struct();
+        let wire = e.end_utterance();
+        self.threads.insert(self.session.decoder().decode_utterance(&wire)?);
+        if act == pragma::PROPOSE {
+            self.rounds += 1;
+        }
+
+        let mut builder = self.session.epoch_builder();
+        for fragment in wire.chunks(FRAGMENT_BYTES) {
+            builder.write(fragment);
+            builder.flush();
+        }
+        for epoch in builder.get_epochs() {
+            if let Some(frame) = self.session.sender.send(epoch, now_us)? {
+                self.outbox.push_back(frame);
+            }
+        }
+        self.session.next_seq = builder.next_seq();
+        Ok(())
+    }
+}
+
+/// The act and SILENCE_PERIOD term of a decoded utterance, if it carries
+/// one.
+fn silence_period(utterance: &AstNode) -> Option<(&str, SilencePeriod)> {
+    let AstNode::Utterance { body, .. } = utterance else {
+        return None;
+    };
+    body.windows(2).find_map(|pair| {
+        let [AstNode::Pragmatic { act, expression }, AstNode::Struct { fields }] = pair else {
+            return None;
+        };
+        let AstNode::DomainRef { level: 1, domain_code: SILENCE_PERIOD, .. } = expression.as_ref() else {
+            return None;
+        };
+        let start_us = match fields.get(&0x0000)? {
+            AstNode::Literal { value: LiteralValue::Timestamp(t), .. } => *t,
+            _ => return None,
+        };
+        let duration_ms = match fields.get(&0x0001)? {
+            AstNode::Literal { value: LiteralValue::Uint32(ms), .. } => *ms,
+            _ => return None,
+        };
+        Some((act.as_str(), SilencePeriod { start_us, duration_ms }))
+    })
+}
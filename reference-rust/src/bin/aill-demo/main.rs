@@ -0,0 +1,165 @@
+//! Two agents negotiating a silence period over the acoustic link.
+//!
+//! Run `aill-demo respond` on one machine and `aill-demo propose` on
+//! another within earshot. The link is half duplex: each agent listens for
+//! a fixed window, decodes every frame it heard, then plays its replies
+//! once the channel is clear. Lost frames are recovered by retransmission.
+
+mod agent;
+
+use std::env;
+use std::process;
+
+use aill::audio::constants::{ABS_THRESHOLD, DEFAULT_SAMPLE_RATE};
+use aill::audio::{live, AcousticDecoder, AcousticEncoder, Pacer};
+use aill::thread::new_thread_id;
+use aill::timestamp::now_micros;
+use aill::AgentId;
+
+use agent::{DemoAgent, Outcome, SilencePeriod};
+
+/// How long each listening window lasts (seconds). Long enough to hear a
+/// whole burst of frames from the peer.
+const LISTEN_SECS: f32 = 8.0;
+
+/// Listening windows to keep answering the peer after the negotiation
+/// ends, in case our last ACK was lost.
+const LINGER_WINDOWS: u32 = 2;
+
+/// Give up on a negotiation that has not ended after this long (seconds).
+const MAX_SESSION_SECS: i64 = 300;
+
+/// Listening periods to wait for a busy channel before each frame.
+const MAX_CHANNEL_ATTEMPTS: u32 = 50;
+
+/// Minimum gap (microseconds) left after the channel frees up.
+const MIN_TX_GAP_US: i64 = 100_000;
+
+/// Silence periods accepted when no range is given (milliseconds).
+const DEFAULT_MIN_MS: u32 = 500;
+const DEFAULT_MAX_MS: u32 = 5_000;
+
+/// Delay between agreeing and the start of a proposed silence period.
+const SILENCE_LEAD_US: i64 = 60_000_000;
+
+fn usage() -> ! {
+    eprintln!("Usage:");
+    eprintln!("  aill-demo propose <ms> [<min-ms> <max-ms>]   Propose a silence period of <ms>");
+    eprintln!("  aill-demo respond [<min-ms> <max-ms>]        Answer a proposal");
+    process::exit(1);
+}
+
+fn parse_ms(arg: Option<&String>, default: u32) -> Result<u32, String> {
+    match arg {
+        Some(s) => s.parse().map_err(|e| format!("Invalid duration '{}': {}", s, e)),
+        None => Ok(default),
+    }
+}
+
+/// A random agent id for this run.
+fn random_agent_id() -> AgentId {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&new_thread_id().to_be_bytes());
+    bytes[8..].copy_from_slice(&new_thread_id().to_be_bytes());
+    AgentId::new(bytes)
+}
+
+fn run(mut agent: DemoAgent) -> Result<(), Box<dyn std::error::Error>> {
+    let encoder = AcousticEncoder::new();
+    let decoder = AcousticDecoder::new();
+    let mut pacer = Pacer::new(MIN_TX_GAP_US).with_seed(now_micros() as u64);
+    let deadline = now_micros() + MAX_SESSION_SECS * 1_000_000;
+    let mut lingering = 0;
+
+    loop {
+        for frame in agent.poll(now_micros())? {
+            println!("-> {} bytes", frame.len());
+            let audio = encoder.encode(&frame)?;
+            live::play_when_clear(&audio.samples, audio.sample_rate, &mut pacer, ABS_THRESHOLD, MAX_CHANNEL_ATTEMPTS)?;
+        }
+
+        if agent.outcome().is_some() && agent.is_idle() {
+            if lingering == LINGER_WINDOWS {
+                break;
+            }
+            lingering += 1;
+        }
+        if now_micros() > deadline {
+            return Err("Negotiation timed out".into());
+        }
+
+        println!("Listening {:.0}s...", LISTEN_SECS);
+        let samples = live::record_audio(LISTEN_SECS, DEFAULT_SAMPLE_RATE)?;
+        for result in decoder.decode_all(&samples) {
+            match result.bytes {
+                Ok(frame) => {
+                    println!("<- {} bytes", frame.len());
+                    if let Err(e) = agent.receive(&frame, now_micros()) {
+                        eprintln!("Dropped frame: {}", e);
+                    }
+                    // Keep lingering while the peer is still talking
+                    lingering = 0;
+                }
+                Err(e) => eprintln!("Undecodable frame: {}", e),
+            }
+        }
+    }
+
+    println!();
+    for utterance in agent.transcript() {
+        println!("{}", utterance);
+    }
+    let stats = agent.link_stats();
+    println!(
+        "{} epochs sent, {} retransmitted, {} abandoned",
+        stats.epochs_sent, stats.retransmits, stats.failures
+    );
+    match agent.outcome() {
+        Some(Outcome::Agreed(period)) => {
+            println!("Agreed: {} ms of silence from {} us", period.duration_ms, period.start_us);
+            Ok(())
+        }
+        _ => Err("Proposal refused".into()),
+    }
+}
+
+fn cmd_propose(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let ms = parse_ms(Some(args.first().ok_or("Missing duration")?), 0)?;
+    let min_ms = parse_ms(args.get(1), DEFAULT_MIN_MS)?;
+    let max_ms = parse_ms(args.get(2), DEFAULT_MAX_MS)?;
+    let id = random_agent_id();
+    let mut agent = DemoAgent::new(id, min_ms, max_ms);
+    println!("Agent {} proposing {} ms of silence", id.short(), ms);
+    let now = now_micros();
+    agent.propose(SilencePeriod { start_us: now + SILENCE_LEAD_US, duration_ms: ms }, now)?;
+    run(agent)
+}
+
+fn cmd_respond(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let min_ms = parse_ms(args.first(), DEFAULT_MIN_MS)?;
+    let max_ms = parse_ms(args.get(1), DEFAULT_MAX_MS)?;
+    let id = random_agent_id();
+    println!("Agent {} accepting {}-{} ms of silence", id.short(), min_ms, max_ms);
+    run(DemoAgent::new(id, min_ms, max_ms))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        usage();
+    }
+
+    let result = match args[1].as_str() {
+        "propose" => cmd_propose(&args[2..]),
+        "respond" => cmd_respond(&args[2..]),
+        _ => {
+            eprintln!("Unknown command: {}", args[1]);
+            usage();
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
@@ -1,8 +1,9 @@
 use std::env;
+use std::path::PathBuf;
 use std::process;
 
-use aill::audio::{AcousticDecoder, AcousticEncoder};
-use aill::audio::constants::DEFAULT_SAMPLE_RATE;
+use aill::audio::{AcousticDecoder, AcousticEncoder, LatencyProfile, NoiseProfile, Pacer};
+use aill::audio::constants::{ABS_THRESHOLD, DEFAULT_SAMPLE_RATE};
 use aill::audio::live;
 
 /// Maximum recording duration the CLI will accept (seconds).
@@ -12,14 +13,26 @@ const MAX_RECORD_DURATION_SECS: f32 = 60.0;
 const ROUNDTRIP_LATENCY_MARGIN_SECS: f32 = 1.0;
 
 /// Delay before playback starts in roundtrip mode, letting the recording
-/// stream fully initialize (milliseconds).
+/// stream fully initialize (milliseconds). Used when no latency profile
+/// has been saved by `calibrate`.
 const RECORDING_INIT_DELAY_MS: u64 = 200;
 
+/// Margin added to the calibrated input startup time (milliseconds).
+const CALIBRATED_INIT_MARGIN_MS: u64 = 50;
+
+/// Listening periods `tx` waits for a busy channel before giving up.
+const MAX_CHANNEL_ATTEMPTS: u32 = 50;
+
+/// Minimum gap (microseconds) `tx` leaves after the channel frees up.
+const MIN_TX_GAP_US: i64 = 100_000;
+
 fn usage() -> ! {
     eprintln!("Usage:");
-    eprintln!("  aill-live tx <hex-bytes>       Encode hex data and play through speaker");
+    eprintln!("  aill-live tx <hex-bytes>       Encode hex data and play once the channel is clear");
     eprintln!("  aill-live rx <seconds>         Record from mic, decode, and print hex");
     eprintln!("  aill-live roundtrip <hex>      Transmit then receive, verify match");
+    eprintln!("  aill-live calibrate [path]     Measure speaker-to-mic latency and save it");
+    eprintln!("  aill-live noise <seconds>      Learn background noise and save it for rx");
     process::exit(1);
 }
 
@@ -54,8 +67,15 @@ fn cmd_tx(hex: &str) -> Result<(), Box<dyn std::error::Error>> {
         encoded.sample_rate
     );
 
-    println!("Playing...");
-    live::play_audio(&encoded.samples, encoded.sample_rate)?;
+    println!("Waiting for a clear channel...");
+    let mut pacer = Pacer::new(MIN_TX_GAP_US).with_seed(aill::timestamp::now_micros() as u64);
+    live::play_when_clear(
+        &encoded.samples,
+        encoded.sample_rate,
+        &mut pacer,
+        ABS_THRESHOLD,
+        MAX_CHANNEL_ATTEMPTS,
+    )?;
     println!("Done.");
     Ok(())
 }
@@ -77,12 +97,23 @@ fn cmd_rx(seconds_str: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("Captured {} samples.", samples.len());
 
     println!("Decoding...");
-    let decoder = AcousticDecoder::new();
-    let bytes = decoder.decode(&samples)?;
+    let bytes = saved_noise_decoder()?.decode(&samples)?;
     println!("Decoded {} bytes: {}", bytes.len(), hex_string(&bytes));
     Ok(())
 }
 
+/// A decoder using the noise profile saved by `noise`, if any.
+fn saved_noise_decoder() -> Result<AcousticDecoder, Box<dyn std::error::Error>> {
+    let decoder = AcousticDecoder::new();
+    match NoiseProfile::default_path().and_then(|p| NoiseProfile::load(p).ok()) {
+        Some(profile) => {
+            println!("Using noise profile from {} frames", profile.frames);
+            Ok(decoder.with_noise_profile(profile)?)
+        }
+        None => Ok(decoder),
+    }
+}
+
 fn cmd_roundtrip(hex: &str) -> Result<(), Box<dyn std::error::Error>> {
     let wire_bytes = parse_hex(hex)?;
     println!("Roundtrip test: {} bytes: {}", wire_bytes.len(), hex_string(&wire_bytes));
@@ -90,8 +121,21 @@ fn cmd_roundtrip(hex: &str) -> Result<(), Box<dyn std::error::Error>> {
     let encoder = AcousticEncoder::new();
     let encoded = encoder.encode(&wire_bytes)?;
 
-    // Calculate recording duration: audio duration + margin for latency
-    let rx_duration = encoded.duration + ROUNDTRIP_LATENCY_MARGIN_SECS;
+    // Compensate with the calibrated latency profile when there is one
+    let profile = LatencyProfile::default_path().and_then(|p| LatencyProfile::load(p).ok());
+    let (init_delay_ms, latency_secs) = match profile {
+        Some(p) => {
+            println!(
+                "Using latency profile: {:.0} ms latency, {:.0} ms input startup",
+                p.latency_ms, p.input_startup_ms
+            );
+            (p.input_startup_ms.ceil() as u64 + CALIBRATED_INIT_MARGIN_MS, p.latency_ms / 1000.0)
+        }
+        None => (RECORDING_INIT_DELAY_MS, 0.0),
+    };
+
+    // Calculate recording duration: audio duration + latency + margin
+    let rx_duration = encoded.duration + latency_secs + ROUNDTRIP_LATENCY_MARGIN_SECS;
 
     // Start recording in a background thread before playing
     let rx_handle = std::thread::spawn(move || {
@@ -99,7 +143,7 @@ fn cmd_roundtrip(hex: &str) -> Result<(), Box<dyn std::error::Error>> {
     });
 
     // Small delay to let the recording stream initialize
-    std::thread::sleep(std::time::Duration::from_millis(RECORDING_INIT_DELAY_MS));
+    std::thread::sleep(std::time::Duration::from_millis(init_delay_ms));
 
     println!("Playing...");
     live::play_audio(&encoded.samples, encoded.sample_rate)?;
@@ -112,7 +156,10 @@ fn cmd_roundtrip(hex: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("Captured {} samples.", samples.len());
 
     println!("Decoding...");
-    let decoder = AcousticDecoder::new();
+    let mut decoder = saved_noise_decoder()?;
+    if let Some(p) = profile {
+        decoder = decoder.with_carrier_weights(p.carrier_weights());
+    }
     let decoded = decoder.decode(&samples)?;
     println!("Decoded {} bytes: {}", decoded.len(), hex_string(&decoded));
     if decoded == wire_bytes {
@@ -128,9 +175,50 @@ fn cmd_roundtrip(hex: &str) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn cmd_calibrate(path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = match path {
+        Some(p) => PathBuf::from(p),
+        None => LatencyProfile::default_path().ok_or("No config directory; pass a path")?,
+    };
+
+    println!("Playing calibration chirp while recording...");
+    let profile = live::calibrate()?;
+    println!(
+        "Latency {:.1} ms, input startup {:.1} ms, gain {:.3}",
+        profile.latency_ms, profile.input_startup_ms, profile.gain
+    );
+    println!("Carrier response {:.2?}", profile.carrier_response);
+    profile.save(&path)?;
+    println!("Saved to {}", path.display());
+    Ok(())
+}
+
+fn cmd_noise(seconds_str: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let seconds: f32 = seconds_str
+        .parse()
+        .map_err(|e| format!("Invalid duration '{}': {}", seconds_str, e))?;
+    if seconds <= 0.0 || seconds > MAX_RECORD_DURATION_SECS {
+        return Err(format!(
+            "Duration must be greater than 0 and at most {} seconds",
+            MAX_RECORD_DURATION_SECS
+        )
+        .into());
+    }
+    let path = NoiseProfile::default_path().ok_or("No config directory for the noise profile")?;
+
+    println!("Recording {:.1}s of background noise; keep the channel quiet...", seconds);
+    let samples = live::record_audio(seconds, DEFAULT_SAMPLE_RATE)?;
+    let mut decoder = AcousticDecoder::new();
+    decoder.learn_noise(&samples)?;
+    let profile = decoder.noise_profile().ok_or("No noise learned")?;
+    profile.save(&path)?;
+    println!("Learned {} frames, saved to {}", profile.frames, path.display());
+    Ok(())
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
+    if args.len() < 2 || (args.len() < 3 && args[1] != "calibrate") {
         usage();
     }
 
@@ -138,6 +226,8 @@ fn main() {
         "tx" => cmd_tx(&args[2]),
         "rx" => cmd_rx(&args[2]),
         "roundtrip" => cmd_roundtrip(&args[2]),
+        "calibrate" => cmd_calibrate(args.get(2).map(String::as_str)),
+        "noise" => cmd_noise(&args[2]),
         _ => {
             eprintln!("Unknown command: {}", args[1]);
             usage();
@@ -0,0 +1,329 @@
+mod corpus;
+mod pipe;
+mod repl;
+
+use std::env;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+
+use aill::codebook::{search, DOMAIN_REGISTRY};
+use aill::lint::{Linter, Severity};
+use aill::{AILLDecoder, DecoderConfig};
+
+use pipe::{parse_hex, Framing};
+use repl::{Output, Repl};
+
+const PROMPT: &str = "aill> ";
+
+/// Matches `aill lookup` prints.
+const LOOKUP_LIMIT: usize = 20;
+
+fn usage() -> ! {
+    eprintln!("Usage:");
+    eprintln!("  aill repl                      Compose and decode messages interactively");
+    eprintln!("  aill lint <hex|file> [--bind <level>=<registry>]...");
+    eprintln!("                                 Check a wire message before sending it");
+    eprintln!("  aill gen-corpus --out <dir> [--count N] [--seed S] [--complexity C]");
+    eprintln!("                                 Write seeded random messages as .bin, .hex and .json");
+    eprintln!("  aill pipe decode|encode [--hex]");
+    eprintln!("                                 Epochs on stdin to JSON lines on stdout, or back");
+    eprintln!("  aill lookup <words>...         Search codebook mnemonics and descriptions");
+    process::exit(1);
+}
+
+/// Puts the terminal in unbuffered, no-echo mode for as long as it lives,
+/// so Tab reaches the REPL. Uses `stty`; `None` if that is unavailable.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> Option<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(Self { saved: saved.trim().to_string() })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Read a line from a raw-mode terminal, completing the last word on Tab.
+/// Returns `None` on Ctrl-D or Ctrl-C.
+fn read_line_completing(repl: &Repl) -> io::Result<Option<String>> {
+    let mut stdout = io::stdout();
+    let mut line: Vec<u8> = Vec::new();
+    let mut bytes = io::stdin().lock().bytes();
+    write!(stdout, "{}", PROMPT)?;
+    stdout.flush()?;
+    while let Some(byte) = bytes.next() {
+        match byte? {
+            b'\r' | b'\n' => {
+                writeln!(stdout)?;
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+            0x03 | 0x04 if line.is_empty() => {
+                writeln!(stdout)?;
+                return Ok(None);
+            }
+            0x03 => {
+                line.clear();
+                write!(stdout, "\n{}", PROMPT)?;
+            }
+            0x7F | 0x08 => {
+                // Drop a whole UTF-8 character
+                while line.pop().is_some_and(|b| b & 0xC0 == 0x80) {}
+                write!(stdout, "\x08 \x08")?;
+            }
+            b'\t' => {
+                let text = String::from_utf8_lossy(&line).into_owned();
+                let candidates = repl.complete(&text);
+                let word_len = text.len() - text.trim_end_matches(|c: char| !c.is_whitespace()).len();
+                let common = common_prefix(&candidates);
+                let word = &text[text.len() - word_len..];
+                let completes = common.get(..word_len).is_some_and(|c| c.eq_ignore_ascii_case(word));
+                if !completes && candidates.len() == 1 {
+                    // A misspelling: replace the word
+                    line.truncate(line.len() - word_len);
+                    line.extend_from_slice(format!("{} ", candidates[0]).as_bytes());
+                    write!(stdout, "{}{} ", "\x08 \x08".repeat(word.chars().count()), candidates[0])?;
+                } else if completes && common.len() > word_len {
+                    let rest = &common[word_len..];
+                    let rest = if candidates.len() == 1 { format!("{} ", rest) } else { rest.to_string() };
+                    line.extend_from_slice(rest.as_bytes());
+                    write!(stdout, "{}", rest)?;
+                } else if candidates.len() > 1 {
+                    write!(stdout, "\n{}\n{}{}", candidates.join("  "), PROMPT, text)?;
+                }
+            }
+            // Arrow keys and other escape sequences are not supported
+            0x1B => {
+                bytes.next();
+                bytes.next();
+            }
+            b if b >= 0x20 => {
+                line.push(b);
+                stdout.write_all(&[b])?;
+            }
+            _ => {}
+        }
+        stdout.flush()?;
+    }
+    Ok(None)
+}
+
+fn read_plain_line() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    Ok((io::stdin().read_line(&mut line)? > 0).then_some(line))
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else {
+        return String::new();
+    };
+    let mut len = first.len();
+    for word in &words[1..] {
+        len = len.min(first.bytes().zip(word.bytes()).take_while(|(a, b)| a == b).count());
+    }
+    first[..len].to_string()
+}
+
+#[cfg(feature = "audio-live")]
+fn play(wire: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let audio = aill::audio::AcousticEncoder::new().encode(wire)?;
+    aill::audio::live::play_audio(&audio.samples, audio.sample_rate)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "audio-live"))]
+fn play(_wire: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    Err("built without the audio-live feature".into())
+}
+
+fn cmd_repl() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new();
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        println!("AILL REPL; Tab completes, `help` lists commands.");
+    }
+    loop {
+        let raw = if interactive { RawMode::enter() } else { None };
+        let line = match raw {
+            Some(_) => read_line_completing(&repl)?,
+            None => {
+                if interactive {
+                    print!("{}", PROMPT);
+                    io::stdout().flush()?;
+                }
+                read_plain_line()?
+            }
+        };
+        drop(raw);
+        let Some(line) = line else {
+            return Ok(());
+        };
+        match repl.execute(&line) {
+            Ok(Output::None) => {}
+            Ok(Output::Text(text)) => println!("{}", text),
+            Ok(Output::Play(wire)) => {
+                if let Err(e) = play(&wire) {
+                    eprintln!("Error: {}", e);
+                }
+            }
+            Ok(Output::Quit) => return Ok(()),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+}
+
+/// The message named on the command line: a file of raw bytes or hex
+/// text, or hex given inline.
+fn read_message(arg: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let bytes = match std::fs::read(arg) {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(parse_hex(arg).ok_or_else(|| format!("{} is neither a file nor hex", arg))?),
+    };
+    Ok(std::str::from_utf8(&bytes).ok().and_then(parse_hex).unwrap_or(bytes))
+}
+
+/// `<level>=<registry>`, the registry as an id or a codebook name.
+fn parse_binding(arg: &str) -> Option<(u8, u8)> {
+    let (level, registry) = arg.split_once('=')?;
+    let level: u8 = level.parse().ok().filter(|l| (1..=3).contains(l))?;
+    let registry = match registry.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok()?,
+        None => registry.parse().ok().or_else(|| {
+            DOMAIN_REGISTRY.iter().find(|cb| cb.name.eq_ignore_ascii_case(registry)).map(|cb| cb.registry_id)
+        })?,
+    };
+    Some((level, registry))
+}
+
+fn cmd_lint(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = DecoderConfig::default();
+    let mut message = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--bind" => {
+                let binding = rest.next().ok_or("--bind needs <level>=<registry>")?;
+                let (level, registry) =
+                    parse_binding(binding).ok_or_else(|| format!("Invalid binding: {}", binding))?;
+                config = config.bind_escape(level, registry);
+            }
+            _ if message.is_none() => message = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(message) = message else { usage() };
+    let wire = read_message(message)?;
+
+    let lints = Linter::new().with_decoder(AILLDecoder::with_config(config)).lint(&wire);
+    for lint in &lints {
+        println!("{}", lint);
+    }
+    let errors = lints.iter().filter(|l| l.severity == Severity::Error).count();
+    eprintln!("{} bytes, {} errors, {} warnings", wire.len(), errors, lints.len() - errors);
+    if errors > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Writes `<name>.bin` (raw wire, for fuzzer seeds) for `count` utterances
+/// from consecutive seeds, and the `<name>.hex` / `<name>.json` vector pair
+/// that `aill-conformance` reads for those that re-encode exactly.
+fn cmd_gen_corpus(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = None;
+    let mut count = 100usize;
+    let mut seed = 0u64;
+    let mut complexity = 3u8;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--count" => count = value()?.parse()?,
+            "--seed" => seed = value()?.parse()?,
+            "--complexity" => complexity = value()?.parse()?,
+            _ => usage(),
+        }
+    }
+    let Some(out) = out else { usage() };
+    let stats = corpus::write_corpus(&out, seed, count, complexity)?;
+    eprintln!("Wrote {} messages to {}, {} as conformance vectors", stats.messages, out.display(), stats.vectors);
+    Ok(())
+}
+
+fn cmd_pipe(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let framing = match args.get(1).map(String::as_str) {
+        None => Framing::Raw,
+        Some("--hex") if args.len() == 2 => Framing::Hex,
+        _ => usage(),
+    };
+    let (stdin, stdout, stderr) = (io::stdin().lock(), io::stdout().lock(), io::stderr().lock());
+    let stats = match args.first().map(String::as_str) {
+        Some("decode") => pipe::decode(stdin, stdout, stderr, framing)?,
+        Some("encode") => pipe::encode(stdin, stdout, stderr, framing)?,
+        _ => usage(),
+    };
+    if stats.errors > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn cmd_lookup(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.is_empty() {
+        usage();
+    }
+    let query = args.join(" ");
+    let matches = search(&query);
+    if matches.is_empty() {
+        return Err(format!("Nothing in the codebooks matches '{}'", query).into());
+    }
+    for m in matches.iter().take(LOOKUP_LIMIT) {
+        let code = match m.registry_id {
+            None => format!("0x{:02X}", m.code),
+            Some(_) => format!("0x{:04X}", m.code),
+        };
+        println!("{:<9} {:<6} {:<28} {}", m.table, code, m.mnemonic, m.description);
+    }
+    if matches.len() > LOOKUP_LIMIT {
+        eprintln!("... and {} weaker matches", matches.len() - LOOKUP_LIMIT);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        usage();
+    }
+
+    let result = match args[1].as_str() {
+        "repl" => cmd_repl(),
+        "lint" => cmd_lint(&args[2..]),
+        "gen-corpus" => cmd_gen_corpus(&args[2..]),
+        "pipe" => cmd_pipe(&args[2..]),
+        "lookup" => cmd_lookup(&args[2..]),
+        _ => {
+            eprintln!("Unknown command: {}", args[1]);
+            usage();
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,155 @@
+//! `aill pipe`: epochs in and JSON out, or the reverse, for shell pipelines.
+//!
+//! Decoding reads a stream of raw epochs, or hex text with one or more
+//! epochs per line, joins their payloads in the order they arrive and
+//! writes each utterance they complete as one line of JSON. Encoding reads
+//! one JSON utterance per line, as decoding writes them, and writes each
+//! as an epoch. Output is flushed line by line, so a process in any
+//! language can talk AILL through a pipe. Epochs failing their CRC and
+//! lines that do not parse are reported on the error stream and skipped.
+
+use std::io::{self, BufRead, Write};
+
+use aill::stream::StreamDecoder;
+use aill::wire::integrity::{scheme_id, unkeyed};
+use aill::wire::{epoch_flags, EXTENDED_EPOCH};
+use aill::{decode_epoch, AstNode, EpochBuilder};
+
+/// How epochs are written on the byte side of the pipe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    Raw,
+    /// Hex text, one epoch per line on output.
+    Hex,
+}
+
+/// What a pipe run got through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipeStats {
+    /// Utterances written.
+    pub messages: usize,
+    /// Epochs or lines skipped.
+    pub errors: usize,
+}
+
+/// Decode epochs from `input` into JSON lines on `output`.
+pub fn decode(mut input: impl BufRead, mut output: impl Write, mut errors: impl Write, framing: Framing) -> io::Result<PipeStats> {
+    let mut stats = PipeStats::default();
+    let mut stream = StreamDecoder::new();
+    let mut buf = Vec::new();
+    let mut line = String::new();
+    loop {
+        match framing {
+            Framing::Raw => {
+                let chunk = input.fill_buf()?;
+                if chunk.is_empty() {
+                    break;
+                }
+                buf.extend_from_slice(chunk);
+                let n = chunk.len();
+                input.consume(n);
+            }
+            Framing::Hex => {
+                line.clear();
+                if input.read_line(&mut line)? == 0 {
+                    break;
+                }
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_hex(&line) {
+                    Some(bytes) => buf.extend(bytes),
+                    None => {
+                        writeln!(errors, "not hex: {}", line.trim())?;
+                        stats.errors += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        while let Some(len) = epoch_len(&buf).filter(|&len| len <= buf.len()) {
+            let epoch = buf.drain(..len).collect::<Vec<u8>>();
+            let utterances = match decode_epoch(&epoch, 0) {
+                Ok((epoch, _)) if !epoch.crc_ok => Err(format!("epoch {}: CRC mismatch", epoch.seq_num)),
+                Ok((epoch, _)) => stream.push(&epoch.payload).map_err(|e| format!("epoch {}: {}", epoch.seq_num, e)),
+                Err(e) => Err(e.to_string()),
+            };
+            match utterances {
+                Ok(utterances) => {
+                    for utterance in utterances {
+                        writeln!(output, "{}", serde_json::to_string(&utterance)?)?;
+                        stats.messages += 1;
+                    }
+                    output.flush()?;
+                }
+                Err(e) => {
+                    writeln!(errors, "{}", e)?;
+                    stats.errors += 1;
+                }
+            }
+        }
+    }
+    if !buf.is_empty() || stream.pending() > 0 {
+        writeln!(errors, "input ends inside an epoch or utterance")?;
+        stats.errors += 1;
+    }
+    Ok(stats)
+}
+
+/// Encode JSON utterances, one per line of `input`, into epochs on
+/// `output`, numbered from 0.
+pub fn encode(input: impl BufRead, mut output: impl Write, mut errors: impl Write, framing: Framing) -> io::Result<PipeStats> {
+    let mut stats = PipeStats::default();
+    let mut seq = 0;
+    for (n, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut builder = EpochBuilder::new().with_seq(seq);
+        let framed = serde_json::from_str::<AstNode>(&line)
+            .map_err(|e| e.to_string())
+            .and_then(|ast| builder.write_ast(&ast).map_err(|e| e.to_string()));
+        if let Err(e) = framed {
+            writeln!(errors, "line {}: {}", n + 1, e)?;
+            stats.errors += 1;
+            continue;
+        }
+        for epoch in builder.get_epochs() {
+            match framing {
+                Framing::Raw => output.write_all(&epoch)?,
+                Framing::Hex => writeln!(output, "{}", epoch.iter().map(|b| format!("{:02x}", b)).collect::<String>())?,
+            }
+        }
+        output.flush()?;
+        seq = builder.next_seq();
+        stats.messages += 1;
+    }
+    Ok(stats)
+}
+
+/// Hex digits, optionally prefixed with `0x` and split by whitespace.
+pub fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.split_whitespace().collect();
+    let digits = digits.trim_start_matches("0x");
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok()).collect()
+}
+
+/// Length of the epoch at the start of `data`, once enough of its header
+/// is there to tell.
+fn epoch_len(data: &[u8]) -> Option<usize> {
+    let len_word = u16::from_be_bytes([*data.get(2)?, *data.get(3)?]);
+    let (header, check) = if len_word & EXTENDED_EPOCH == 0 {
+        (4, 1)
+    } else {
+        let flags = *data.get(4)?;
+        // Keyed schemes are left to decode_epoch to reject
+        let check = unkeyed(scheme_id(flags)).map_or(1, |scheme| scheme.tag_len());
+        (if flags & epoch_flags::TIMESTAMP == 0 { 5 } else { 13 }, check)
+    };
+    Some(header + (len_word & !EXTENDED_EPOCH) as usize + check)
+}
@@ -0,0 +1,304 @@
+//! `aill repl`: compose an utterance one expression per line.
+//!
+//! Each line appends to the utterance being built; `show` decodes what has
+//! been written so far, `hex` prints its wire bytes. Lines are kept as
+//! [`Step`]s and replayed into a fresh encoder whenever the utterance is
+//! needed, so `undo` is just dropping the last step.
+
+use aill::codebook::base::{modal, BASE_CODEBOOK};
+use aill::codebook::search;
+use aill::{pretty_print, AILLDecoder, AILLEncoder};
+
+/// Categories of base codes that are written as a bare opcode.
+const BARE_CATEGORIES: [&str; 7] =
+    ["pragmatic", "modality", "temporal", "quantifier", "logic", "relational", "arithmetic"];
+
+/// Commands other than bare opcodes.
+const COMMANDS: [&str; 33] = [
+    "new", "struct", "list", "map", "end", "field", "i8", "i16", "i32", "i64", "u8", "u16", "u32",
+    "u64", "f16", "f32", "f64", "bool", "str", "bytes", "ts", "null", "l1", "l2", "l3",
+    "predicted", "undo", "reset", "show", "hex", "play", "help", "quit",
+];
+
+const HELP: &str = "\
+Acts and operators by mnemonic: assert, query, propose, certain, forall, add, ...
+  new [confidence] [priority]   start over with a new meta header
+  struct | list <n> | map <n>   open a container; `end` closes the innermost
+  field <id>                    struct field id (decimal or 0x hex)
+  i8 .. i64, u8 .. u64 <v>      integer literal
+  f16 | f32 | f64 <v>           float literal
+  bool <true|false>, str <text>, bytes <hex>, ts <micros>, null
+  l1 | l2 | l3 <code>           domain reference at escape level 1-3
+  predicted <ms>                PREDICTED modality with its horizon
+  undo | reset                  drop the last line | everything
+  show | hex                    decoded tree | wire bytes
+  play                          transmit over the speaker
+  quit";
+
+/// One line of the utterance being composed.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Code(u8),
+    Struct,
+    List(u16),
+    Map(u16),
+    /// Closes the innermost container, of the given kind.
+    End(Container),
+    Field(u16),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Float16(f32),
+    Float32(f32),
+    Float64(f64),
+    Bool(bool),
+    Str(String),
+    Bytes(Vec<u8>),
+    Timestamp(i64),
+    Null,
+    DomainRef(u8, u16),
+    Predicted(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Struct,
+    List,
+    Map,
+}
+
+/// What the caller should do with a line's result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    None,
+    Text(String),
+    /// Transmit these wire bytes.
+    Play(Vec<u8>),
+    Quit,
+}
+
+/// State of an interactive session.
+pub struct Repl {
+    confidence: f32,
+    priority: u8,
+    steps: Vec<Step>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self { confidence: 1.0, priority: 3, steps: Vec::new() }
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run one line. Errors are messages for the user; the utterance is
+    /// left as it was.
+    pub fn execute(&mut self, line: &str) -> Result<Output, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(Output::None);
+        };
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize| args.get(i).copied().ok_or_else(|| format!("{} needs an argument", command));
+
+        let step = match command.to_ascii_lowercase().as_str() {
+            "new" => {
+                let confidence = args.first().map(|a| parse(a)).transpose()?.unwrap_or(1.0);
+                let priority = args.get(1).map(|a| parse(a)).transpose()?.unwrap_or(3);
+                *self = Self { confidence, priority, steps: Vec::new() };
+                return Ok(Output::None);
+            }
+            "undo" => {
+                self.steps.pop().ok_or("Nothing to undo")?;
+                return Ok(Output::None);
+            }
+            "reset" => {
+                self.steps.clear();
+                return Ok(Output::None);
+            }
+            "show" => {
+                let ast = AILLDecoder::new().decode_utterance(&self.wire()?).map_err(|e| e.to_string())?;
+                return Ok(Output::Text(pretty_print(&ast, 0)));
+            }
+            "hex" => return Ok(Output::Text(self.wire()?.iter().map(|b| format!("{:02X}", b)).collect())),
+            "play" => return Ok(Output::Play(self.wire()?)),
+            "help" => return Ok(Output::Text(HELP.to_string())),
+            "quit" | "exit" => return Ok(Output::Quit),
+
+            "struct" => Step::Struct,
+            "list" => Step::List(parse_code(arg(0)?)?),
+            "map" => Step::Map(parse_code(arg(0)?)?),
+            "end" => Step::End(self.open().last().copied().ok_or("No open container")?),
+            "field" => Step::Field(parse_code(arg(0)?)?),
+            "i8" => Step::Int8(parse(arg(0)?)?),
+            "i16" => Step::Int16(parse(arg(0)?)?),
+            "i32" => Step::Int32(parse(arg(0)?)?),
+            "i64" => Step::Int64(parse(arg(0)?)?),
+            "u8" => Step::Uint8(parse(arg(0)?)?),
+            "u16" => Step::Uint16(parse(arg(0)?)?),
+            "u32" => Step::Uint32(parse(arg(0)?)?),
+            "u64" => Step::Uint64(parse(arg(0)?)?),
+            "f16" => Step::Float16(parse(arg(0)?)?),
+            "f32" => Step::Float32(parse(arg(0)?)?),
+            "f64" => Step::Float64(parse(arg(0)?)?),
+            "bool" => Step::Bool(parse(arg(0)?)?),
+            "str" => Step::Str(line.trim_start()[command.len()..].trim().to_string()),
+            "bytes" => Step::Bytes(parse_hex(&args.concat())?),
+            "ts" => Step::Timestamp(parse(arg(0)?)?),
+            "null" => Step::Null,
+            "l1" => Step::DomainRef(1, parse_code(arg(0)?)?),
+            "l2" => Step::DomainRef(2, parse_code(arg(0)?)?),
+            "l3" => Step::DomainRef(3, parse_code(arg(0)?)?),
+            "predicted" => Step::Predicted(parse(arg(0)?)?),
+            other => Step::Code(bare_code(other).ok_or_else(|| format!("Unknown command '{}'; try help", other))?),
+        };
+        self.steps.push(step);
+        Ok(Output::None)
+    }
+
+    /// Completions of the last word of `line`. A first word that starts no
+    /// command or mnemonic gets the mnemonics it may be a misspelling of.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        if line.ends_with(char::is_whitespace) || words.is_empty() {
+            words.push("");
+        }
+        let prefix = words[words.len() - 1].to_ascii_lowercase();
+        let mut candidates: Vec<String> = match words.len() {
+            1 => COMMANDS
+                .iter()
+                .map(|c| c.to_string())
+                .chain(bare_mnemonics().map(|m| m.to_ascii_lowercase()))
+                .collect(),
+            2 if words[0] == "bool" => vec!["true".into(), "false".into()],
+            _ => Vec::new(),
+        };
+        candidates.retain(|c| c.starts_with(&prefix));
+        if candidates.is_empty() && words.len() == 1 && !prefix.is_empty() {
+            candidates = search(&prefix)
+                .into_iter()
+                .filter(|m| m.registry_id.is_none() && bare_mnemonics().any(|b| b == m.mnemonic))
+                .map(|m| m.mnemonic.to_ascii_lowercase())
+                .collect();
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    /// Containers opened and not yet closed, innermost last.
+    fn open(&self) -> Vec<Container> {
+        let mut open = Vec::new();
+        for step in &self.steps {
+            match step {
+                Step::Struct => open.push(Container::Struct),
+                Step::List(_) => open.push(Container::List),
+                Step::Map(_) => open.push(Container::Map),
+                Step::End(_) => {
+                    open.pop();
+                }
+                _ => {}
+            }
+        }
+        open
+    }
+
+    /// Wire bytes of the utterance so far.
+    fn wire(&self) -> Result<Vec<u8>, String> {
+        let open = self.open().len();
+        if open > 0 {
+            return Err(format!("{} container(s) still open; close them with end", open));
+        }
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(self.confidence, self.priority, None, None, None);
+        for step in &self.steps {
+            emit(&mut e, step);
+        }
+        e.try_end_utterance().map_err(|e| e.to_string())
+    }
+}
+
+fn emit(e: &mut AILLEncoder, step: &Step) {
+    match step {
+        Step::Code(code) => e.op(*code),
+        Step::Struct => e.begin_struct(),
+        Step::List(n) => e.begin_list(*n),
+        Step::Map(n) => e.begin_map(*n),
+        Step::End(Container::Struct) => e.end_struct(),
+        Step::End(Container::List) => e.end_list(),
+        Step::End(Container::Map) => e.end_map(),
+        Step::Field(id) => e.field(*id),
+        Step::Int8(v) => e.int8(*v),
+        Step::Int16(v) => e.int16(*v),
+        Step::Int32(v) => e.int32(*v),
+        Step::Int64(v) => e.int64(*v),
+        Step::Uint8(v) => e.uint8(*v),
+        Step::Uint16(v) => e.uint16(*v),
+        Step::Uint32(v) => e.uint32(*v),
+        Step::Uint64(v) => e.uint64(*v),
+        Step::Float16(v) => e.float16(*v),
+        Step::Float32(v) => e.float32(*v),
+        Step::Float64(v) => e.float64(*v),
+        Step::Bool(v) => e.bool_(*v),
+        Step::Str(s) => e.string(s),
+        Step::Bytes(b) => e.bytes(b),
+        Step::Timestamp(t) => e.timestamp(*t),
+        Step::Null => e.null(),
+        Step::DomainRef(1, code) => e.l1_ref(*code),
+        Step::DomainRef(2, code) => e.l2_ref(*code),
+        Step::DomainRef(_, code) => e.l3_ref(*code),
+        Step::Predicted(ms) => e.predicted(*ms),
+    };
+}
+
+/// Mnemonics written as a bare opcode. PREDICTED and REPORTED carry
+/// operands and are left out; PREDICTED has its own command.
+fn bare_mnemonics() -> impl Iterator<Item = &'static str> {
+    BASE_CODEBOOK
+        .iter()
+        .filter(|entry| BARE_CATEGORIES.contains(&entry.category))
+        .filter(|entry| entry.code != modal::PREDICTED && entry.code != modal::REPORTED)
+        .map(|entry| entry.mnemonic)
+        .filter(|m| !m.starts_with("RESERVED"))
+}
+
+fn bare_code(word: &str) -> Option<u8> {
+    let word = word.to_ascii_uppercase();
+    let mnemonic = bare_mnemonics().find(|m| *m == word)?;
+    BASE_CODEBOOK.iter().find(|entry| entry.mnemonic == mnemonic).map(|entry| entry.code)
+}
+
+fn parse<T: std::str::FromStr>(word: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    word.parse().map_err(|e| format!("Invalid value '{}': {}", word, e))
+}
+
+/// A decimal or `0x` hex u16.
+fn parse_code(word: &str) -> Result<u16, String> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid value '{}': {}", word, e)),
+        None => parse(word),
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim_start_matches("0x");
+    if !text.len().is_multiple_of(2) {
+        return Err("Hex needs an even number of digits".into());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|e| format!("Invalid hex: {}", e)))
+        .collect()
+}
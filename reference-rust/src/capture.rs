@@ -0,0 +1,420 @@
+//! Human and JSON views of received epochs and captures.
+//!
+//! A capture is a run of back-to-back epochs as read off the link.
+//! [`Capture::decode`] checks each epoch's CRC and sequence number and
+//! reassembles the utterances the epochs carry, recording which epochs
+//! each one was split across. A sequence gap or a failed CRC breaks the
+//! utterance being received; decoding picks up again at the next
+//! utterance boundary.
+//!
+//! [`pretty_print_capture`] renders the result as text,
+//! [`Capture::to_json`] as JSON, and [`pretty_print_epoch`] shows a single
+//! epoch on its own.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+#[cfg(feature = "ast-serde")]
+use serde::Serialize;
+
+use crate::ast::{AstNode, DecodedEpoch};
+use crate::codebook::base::fc;
+use crate::decoder::{decode_epoch, AILLDecoder, Prefix};
+use crate::format::pretty_print;
+use crate::wire::epoch_flags;
+
+/// Header fields of one epoch in a capture.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
+pub struct EpochSummary {
+    /// Byte offset of the epoch in the capture.
+    pub offset: usize,
+    pub seq_num: u16,
+    pub payload_len: usize,
+    pub flags: Option<u8>,
+    pub timestamp_us: Option<i64>,
+    pub crc_ok: bool,
+    /// Sequence numbers skipped since the previous epoch.
+    pub missing: u16,
+}
+
+/// What became of an utterance in a capture.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
+#[cfg_attr(feature = "ast-serde", serde(rename_all = "snake_case"))]
+pub enum Outcome {
+    Decoded(AstNode),
+    /// Abandoned by the sender with ABORT.
+    Aborted,
+    /// Cut off by a lost or corrupt epoch or the end of the capture.
+    Incomplete,
+    Failed(String),
+}
+
+/// An utterance reassembled from a capture.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
+pub struct CapturedUtterance {
+    /// Sequence numbers of the epochs it was carried in, in order.
+    pub epochs: Vec<u16>,
+    /// Index in [`Capture::epochs`] of the epoch it ends in.
+    pub ends_in: usize,
+    pub len: usize,
+    pub outcome: Outcome,
+}
+
+/// A decoded capture; see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
+pub struct Capture {
+    pub epochs: Vec<EpochSummary>,
+    pub utterances: Vec<CapturedUtterance>,
+    /// Bytes after the last epoch that could be decoded.
+    pub trailing: usize,
+    /// Why decoding stopped before the end of the capture.
+    pub error: Option<String>,
+}
+
+/// Utterance bytes waiting for the rest of their epochs.
+#[derive(Default)]
+struct Reassembly {
+    buf: Vec<u8>,
+    /// Sequence number and byte count of each epoch's share of `buf`.
+    shares: VecDeque<(u16, usize)>,
+    /// Between a break and the next utterance boundary.
+    resync: bool,
+}
+
+impl Reassembly {
+    /// Sequence numbers of the epochs holding the next `n` bytes, which
+    /// are dropped from the buffer.
+    fn consume(&mut self, n: usize) -> Vec<u16> {
+        self.buf.drain(..n);
+        let mut epochs = Vec::new();
+        let mut left = n;
+        while left > 0 {
+            let Some((seq, len)) = self.shares.front_mut() else { break };
+            epochs.push(*seq);
+            let take = left.min(*len);
+            *len -= take;
+            left -= take;
+            if *len == 0 {
+                self.shares.pop_front();
+            }
+        }
+        epochs
+    }
+}
+
+impl Capture {
+    /// Decode a capture of back-to-back epochs.
+    pub fn decode(data: &[u8]) -> Self {
+        Self::decode_with(&AILLDecoder::new(), data)
+    }
+
+    /// Like [`decode`](Self::decode), with `decoder`'s configuration.
+    pub fn decode_with(decoder: &AILLDecoder, data: &[u8]) -> Self {
+        let mut capture = Capture::default();
+        let mut stream = Reassembly { resync: true, ..Reassembly::default() };
+        let mut offset = 0;
+        while offset < data.len() {
+            let (epoch, used) = match decode_epoch(data, offset) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    capture.trailing = data.len() - offset;
+                    capture.error = Some(e.to_string());
+                    break;
+                }
+            };
+            let missing = match capture.epochs.last() {
+                Some(prev) => epoch.seq_num.wrapping_sub(prev.seq_num).wrapping_sub(1),
+                None => 0,
+            };
+            capture.epochs.push(EpochSummary {
+                offset,
+                seq_num: epoch.seq_num,
+                payload_len: epoch.payload.len(),
+                flags: epoch.flags,
+                timestamp_us: epoch.timestamp_us,
+                crc_ok: epoch.crc_ok,
+                missing,
+            });
+            offset += used;
+
+            if missing > 0 || !epoch.crc_ok {
+                capture.break_stream(&mut stream);
+            }
+            if epoch.crc_ok {
+                capture.receive(decoder, &mut stream, epoch.seq_num, &epoch.payload);
+            }
+        }
+        capture.break_stream(&mut stream);
+        capture
+    }
+
+    /// Record the partial utterance, if any, as incomplete.
+    fn break_stream(&mut self, stream: &mut Reassembly) {
+        if !stream.buf.is_empty() {
+            let len = stream.buf.len();
+            self.push(stream.consume(len), len, Outcome::Incomplete);
+        }
+        stream.resync = true;
+    }
+
+    fn push(&mut self, epochs: Vec<u16>, len: usize, outcome: Outcome) {
+        let ends_in = self.epochs.len().saturating_sub(1);
+        self.utterances.push(CapturedUtterance { epochs, ends_in, len, outcome });
+    }
+
+    fn receive(&mut self, decoder: &AILLDecoder, stream: &mut Reassembly, seq: u16, payload: &[u8]) {
+        let mut payload = payload;
+        if stream.resync {
+            // The tail of an utterance whose start was lost
+            let start = resync_point(decoder, payload).unwrap_or(payload.len());
+            if start > 0 {
+                self.push(vec![seq], start, Outcome::Incomplete);
+            }
+            payload = &payload[start..];
+            stream.resync = payload.is_empty();
+        }
+        if payload.is_empty() {
+            return;
+        }
+        stream.buf.extend_from_slice(payload);
+        stream.shares.push_back((seq, payload.len()));
+
+        while let Some(&code) = stream.buf.first() {
+            if matches!(code, fc::PAUSE | fc::RESUME) {
+                stream.consume(1);
+                continue;
+            }
+            if code == fc::ABORT {
+                // Wait for the reason code
+                if stream.buf.len() < 2 {
+                    break;
+                }
+                stream.consume(2);
+                continue;
+            }
+            match decoder.decode_prefix(&stream.buf) {
+                Ok(Prefix::Complete(node, used)) => self.push(stream.consume(used), used, Outcome::Decoded(node)),
+                Ok(Prefix::Aborted(_, used)) => self.push(stream.consume(used), used, Outcome::Aborted),
+                Ok(Prefix::Truncated) => break,
+                Err(e) => {
+                    let len = stream.buf.len();
+                    self.push(stream.consume(len), len, Outcome::Failed(e.to_string()));
+                    stream.resync = true;
+                }
+            }
+        }
+    }
+
+    /// Utterances that decoded.
+    pub fn decoded(&self) -> impl Iterator<Item = &AstNode> {
+        self.utterances.iter().filter_map(|u| match &u.outcome {
+            Outcome::Decoded(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    #[cfg(feature = "ast-serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The text view; see [`pretty_print_capture`].
+    pub fn pretty_print(&self) -> String {
+        let mut out = String::new();
+        let mut utterances = self.utterances.iter().enumerate().peekable();
+        for (i, epoch) in self.epochs.iter().enumerate() {
+            let mut line = epoch_line(epoch.seq_num, epoch.payload_len, epoch.flags, epoch.timestamp_us, epoch.crc_ok);
+            if epoch.missing > 0 {
+                let _ = write!(line, " missing={}", epoch.missing);
+            }
+            out.push_str(&line);
+            out.push('\n');
+            while let Some((n, utt)) = utterances.next_if(|(_, u)| u.ends_in == i) {
+                let epochs: Vec<String> = utt.epochs.iter().map(|s| s.to_string()).collect();
+                let _ = write!(out, "  utterance {}: {} bytes from epoch {}", n, utt.len, epochs.join(", "));
+                match &utt.outcome {
+                    Outcome::Decoded(node) => {
+                        out.push('\n');
+                        out.push_str(&pretty_print(node, 2));
+                    }
+                    Outcome::Aborted => out.push_str(": aborted"),
+                    Outcome::Incomplete => out.push_str(": incomplete"),
+                    Outcome::Failed(e) => {
+                        let _ = write!(out, ": {}", e);
+                    }
+                }
+                out.push('\n');
+            }
+        }
+        if let Some(e) = &self.error {
+            let _ = writeln!(out, "TRAILING {} bytes: {}", self.trailing, e);
+        }
+        out
+    }
+}
+
+/// Offset of the first utterance boundary in a payload that may begin
+/// partway through an utterance: a START_UTTERANCE at the start or after
+/// an END_UTTERANCE from which an utterance decodes.
+fn resync_point(decoder: &AILLDecoder, payload: &[u8]) -> Option<usize> {
+    let after_end = payload.windows(2).enumerate().filter(|(_, w)| w[0] == fc::END_UTTERANCE).map(|(p, _)| p + 1);
+    std::iter::once(0)
+        .chain(after_end)
+        .filter(|&p| payload.get(p) == Some(&fc::START_UTTERANCE))
+        .find(|&p| decoder.decode_prefix(&payload[p..]).is_ok())
+}
+
+fn epoch_line(seq: u16, len: usize, flags: Option<u8>, timestamp_us: Option<i64>, crc_ok: bool) -> String {
+    let mut line = format!("EPOCH seq={} len={}", seq, len);
+    if let Some(flags) = flags {
+        const NAMES: [(u8, &str); 5] = [
+            (epoch_flags::COMPRESSED, "COMPRESSED"),
+            (epoch_flags::FEC, "FEC"),
+            (epoch_flags::ENCRYPTED, "ENCRYPTED"),
+            (epoch_flags::PRIORITY, "PRIORITY"),
+            (epoch_flags::TIMESTAMP, "TIMESTAMP"),
+        ];
+        let names: Vec<&str> = NAMES.iter().filter(|(bit, _)| flags & bit != 0).map(|(_, name)| *name).collect();
+        let _ = write!(line, " flags=0x{:02X}", flags);
+        if !names.is_empty() {
+            let _ = write!(line, " ({})", names.join("|"));
+        }
+    }
+    if let Some(ts) = timestamp_us {
+        let _ = write!(line, " timestamp={}", ts);
+    }
+    line.push_str(if crc_ok { " crc=ok" } else { " crc=BAD" });
+    line
+}
+
+/// Show an epoch's header, CRC status and the utterances in its payload,
+/// including pieces of utterances that began in an earlier epoch or
+/// continue in a later one.
+pub fn pretty_print_epoch(epoch: &DecodedEpoch) -> String {
+    let decoder = AILLDecoder::new();
+    let payload = &epoch.payload;
+    let mut out = epoch_line(epoch.seq_num, payload.len(), epoch.flags, epoch.timestamp_us, epoch.crc_ok);
+    out.push('\n');
+    let mut rest = &payload[..];
+    let start = resync_point(&decoder, rest).unwrap_or(rest.len());
+    if start > 0 {
+        let _ = writeln!(out, "  {} bytes continuing an utterance from an earlier epoch", start);
+        rest = &rest[start..];
+    }
+    while let Some(&code) = rest.first() {
+        if matches!(code, fc::PAUSE | fc::RESUME | fc::ABORT) {
+            // ABORT carries a reason code
+            rest = rest.get(1 + (code == fc::ABORT) as usize..).unwrap_or_default();
+            continue;
+        }
+        match decoder.decode_prefix(rest) {
+            Ok(Prefix::Complete(node, used)) => {
+                out.push_str(&pretty_print(&node, 1));
+                out.push('\n');
+                rest = &rest[used..];
+            }
+            Ok(Prefix::Aborted(_, used)) => {
+                let _ = writeln!(out, "  {} bytes of an aborted utterance", used);
+                rest = &rest[used..];
+            }
+            Ok(Prefix::Truncated) => {
+                let _ = writeln!(out, "  {} bytes of an utterance continued in a later epoch", rest.len());
+                break;
+            }
+            Err(e) => {
+                let _ = writeln!(out, "  {} undecodable bytes: {}", rest.len(), e);
+                break;
+            }
+        }
+    }
+    out
+}
+
+/// Show every epoch of a capture followed by the utterances that end in
+/// it; see [`Capture`].
+pub fn pretty_print_capture(data: &[u8]) -> String {
+    Capture::decode(data).pretty_print()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{frame_epoch, AILLEncoder};
+
+    fn utterance(value: i32) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().int32(value);
+        e.end_utterance()
+    }
+
+    /// Three 22-byte utterances in 16-byte epochs.
+    fn epochs() -> Vec<Vec<u8>> {
+        let stream: Vec<u8> = (1..=3).flat_map(utterance).collect();
+        stream.chunks(16).enumerate().map(|(seq, chunk)| frame_epoch(seq as u16, chunk)).collect()
+    }
+
+    #[test]
+    fn capture_tracks_utterances_across_epochs() {
+        let capture = Capture::decode(&epochs().concat());
+        assert_eq!(capture.epochs.len(), 5);
+        assert!(capture.epochs.iter().all(|e| e.crc_ok && e.missing == 0));
+        let spans: Vec<_> = capture.utterances.iter().map(|u| (u.epochs.clone(), u.ends_in)).collect();
+        assert_eq!(spans, vec![(vec![0, 1], 1), (vec![1, 2], 2), (vec![2, 3, 4], 4)]);
+        assert_eq!(capture.decoded().count(), 3);
+        assert!(capture.error.is_none());
+
+        let text = capture.pretty_print();
+        assert!(text.starts_with("EPOCH seq=0 len=16 crc=ok\nEPOCH seq=1 len=16 crc=ok\n"), "{}", text);
+        assert!(text.contains("  utterance 0: 22 bytes from epoch 0, 1\n    UTTERANCE:"), "{}", text);
+        #[cfg(feature = "ast-serde")]
+        assert!(capture.to_json().contains("\"seq_num\": 4"));
+    }
+
+    #[test]
+    fn lost_and_corrupt_epochs_break_the_utterance() {
+        let mut epochs = epochs();
+        epochs.remove(1);
+        let last = epochs[2].len() - 1;
+        epochs[2][last] ^= 0xFF;
+        let mut wire = epochs.concat();
+        wire.extend([0, 9, 0]);
+
+        let capture = Capture::decode(&wire);
+        assert_eq!(capture.epochs[1].missing, 1);
+        assert!(!capture.epochs[2].crc_ok);
+        let outcomes: Vec<_> = capture.utterances.iter().map(|u| (u.epochs.clone(), &u.outcome)).collect();
+        assert!(outcomes.iter().all(|(_, o)| **o == Outcome::Incomplete), "{:?}", outcomes);
+        let epochs: Vec<_> = outcomes.into_iter().map(|(e, _)| e).collect();
+        assert_eq!(epochs, vec![vec![0], vec![2], vec![2], vec![4]]);
+        assert_eq!(capture.trailing, 3);
+        let text = capture.pretty_print();
+        assert!(text.contains("missing=1") && text.contains("crc=BAD") && text.contains("TRAILING 3 bytes"), "{}", text);
+    }
+
+    #[test]
+    fn empty_epoch_is_shown_without_utterances() {
+        let mut wire = frame_epoch(0, &[]);
+        wire.extend(frame_epoch(1, &utterance(7)));
+        let capture = Capture::decode(&wire);
+        assert_eq!(capture.epochs.len(), 2);
+        assert!(capture.epochs[0].crc_ok);
+        assert_eq!(capture.decoded().count(), 1);
+        assert!(capture.pretty_print().starts_with("EPOCH seq=0 len=0 crc=ok\n"));
+
+        let (epoch, _) = decode_epoch(&frame_epoch(0, &[]), 0).unwrap();
+        assert!(pretty_print_epoch(&epoch).starts_with("EPOCH seq=0 len=0 crc=ok"));
+    }
+
+    #[test]
+    fn epoch_view_shows_pieces_of_split_utterances() {
+        let (epoch, _) = decode_epoch(&epochs()[1], 0).unwrap();
+        let text = pretty_print_epoch(&epoch);
+        assert!(text.starts_with("EPOCH seq=1 len=16 crc=ok\n"), "{}", text);
+        assert!(text.contains("  6 bytes continuing an utterance from an earlier epoch\n"), "{}", text);
+        assert!(text.contains("  10 bytes of an utterance continued in a later epoch\n"), "{}", text);
+    }
+}
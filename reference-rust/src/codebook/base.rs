@@ -1,5 +1,9 @@
+#[cfg(feature = "ast-serde")]
+use serde::Serialize;
+
 /// Base codebook entry metadata.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
 pub struct CodeEntry {
     pub code: u8,
     pub mnemonic: &'static str,
@@ -27,6 +31,20 @@ pub mod fc {
     pub const ECHO_REPLY: u8 = 0x0D;
     pub const RESERVED_0E: u8 = 0x0E;
     pub const RESERVED_0F: u8 = 0x0F;
+
+    /// Operand widths in bytes of a frame-control code other than
+    /// START/END_UTTERANCE and the reserved codes. ABORT's reason code
+    /// ends the utterance rather than appearing in a body.
+    pub fn operand_widths(code: u8) -> Option<&'static [usize]> {
+        Some(match code {
+            PAUSE | RESUME => &[],
+            ABORT => &[1],
+            RETRANSMIT | ACK_EPOCH | FRAGMENT_START | FRAGMENT_CONT => &[2],
+            NACK_EPOCH => &[2, 1],
+            SYNC_MARK | FRAGMENT_END | ECHO_REQUEST | ECHO_REPLY => &[4],
+            _ => return None,
+        })
+    }
 }
 
 // ═══════════════════════════════════════════════════════════════════════
@@ -0,0 +1,139 @@
+//! Which domain codebooks two peers can use with each other, from their
+//! [`AgentCapabilities`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use super::get_domain_codebook;
+use crate::comm::AgentCapabilities;
+use crate::error::AILLError;
+
+/// What a session does with a domain reference its peer does not
+/// support, see [`SessionState::encode_ast`](crate::session::SessionState::encode_ast).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum Degradation {
+    /// Refuse the utterance.
+    #[default]
+    Refuse,
+    /// Send a string literal naming the entry instead, see
+    /// [`fallback_label`].
+    Label,
+    /// Send the label wrapped in CLARIFY, inviting the peer to ask what it
+    /// means.
+    Clarify,
+}
+
+/// Outcome of [`compatibility`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct CompatReport {
+    /// Registries both peers support, with the lower of their versions.
+    pub shared: BTreeMap<u8, u16>,
+    /// Shared registries the peers hold at different versions, ours first.
+    /// Entries added after the older version may not be understood.
+    pub version_mismatches: BTreeMap<u8, (u16, u16)>,
+    /// Registries we support and the peer does not; their codes must be
+    /// avoided.
+    pub unsupported: BTreeSet<u8>,
+    /// Registries only the peer supports.
+    pub peer_only: BTreeSet<u8>,
+    /// Largest utterance both peers accept.
+    pub max_payload: u32,
+}
+
+impl CompatReport {
+    /// Whether codes of registry `registry_id` may be sent to the peer.
+    pub fn allows(&self, registry_id: u8) -> bool {
+        self.shared.contains_key(&registry_id)
+    }
+
+    /// Registry and code of every built-in entry in an
+    /// [`unsupported`](Self::unsupported) registry.
+    pub fn avoided_codes(&self) -> Vec<(u8, u16)> {
+        self.unsupported
+            .iter()
+            .filter_map(|&id| get_domain_codebook(id))
+            .flat_map(|codebook| codebook.entries().iter().map(|e| (codebook.registry_id, e.code)))
+            .collect()
+    }
+
+    /// Fail unless a reference to `code` in `registry` may be sent. A
+    /// reference on an unbound escape level names no registry and passes.
+    pub fn check(&self, registry: Option<u8>, code: u16) -> Result<(), AILLError> {
+        match registry {
+            Some(id) if !self.allows(id) => Err(AILLError::EncoderError(format!(
+                "code 0x{:04X} of registry 0x{:02X} is not supported by the peer",
+                code, id
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Text standing in for a reference the peer cannot decode: the codebook
+/// and mnemonic, e.g. `SAFETY-1.ALL_STOP`, or the raw registry and
+/// code for entries not built in.
+pub fn fallback_label(registry_id: u8, code: u16) -> String {
+    match get_domain_codebook(registry_id).and_then(|codebook| Some((codebook.name, codebook.lookup(code)?))) {
+        Some((name, entry)) => format!("{}.{}", name, entry.mnemonic),
+        None => format!("0x{:02X}.0x{:04X}", registry_id, code),
+    }
+}
+
+/// Compare our capabilities with a peer's.
+pub fn compatibility(ours: &AgentCapabilities, theirs: &AgentCapabilities) -> CompatReport {
+    let mut report = CompatReport { max_payload: ours.max_payload.min(theirs.max_payload), ..CompatReport::default() };
+    for (&id, &version) in &ours.domains_supported {
+        match theirs.domains_supported.get(&id) {
+            Some(&theirs) => {
+                report.shared.insert(id, version.min(theirs));
+                if version != theirs {
+                    report.version_mismatches.insert(id, (version, theirs));
+                }
+            }
+            None => {
+                report.unsupported.insert(id);
+            }
+        }
+    }
+    report.peer_only =
+        theirs.domains_supported.keys().filter(|id| !ours.domains_supported.contains_key(id)).copied().collect();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::{DIAG1, NAV1, SAFETY1};
+
+    #[test]
+    fn shared_registries_and_avoided_codes() {
+        let ours = AgentCapabilities::new(0, 0)
+            .with_domain(NAV1.registry_id, 1)
+            .with_domain(SAFETY1.registry_id, 1)
+            .with_domain(0x40, 3);
+        let theirs = AgentCapabilities::new(1, 2)
+            .with_domain(NAV1.registry_id, 1)
+            .with_domain(DIAG1.registry_id, 1)
+            .with_domain(0x40, 2)
+            .with_max_payload(256);
+        let report = compatibility(&ours, &theirs);
+        assert_eq!(report.shared, BTreeMap::from([(NAV1.registry_id, 1), (0x40, 2)]));
+        assert_eq!(report.version_mismatches, BTreeMap::from([(0x40, (3, 2))]));
+        assert_eq!(report.unsupported, BTreeSet::from([SAFETY1.registry_id]));
+        assert_eq!(report.peer_only, BTreeSet::from([DIAG1.registry_id]));
+        assert_eq!(report.max_payload, 256);
+
+        assert_eq!(report.avoided_codes().len(), SAFETY1.len());
+        assert!(report.check(Some(NAV1.registry_id), 0x0000).is_ok());
+        assert!(report.check(None, 0x0000).is_ok());
+        assert!(report.check(Some(SAFETY1.registry_id), 0x0000).is_err());
+        assert!(report.check(Some(DIAG1.registry_id), 0x0000).is_err());
+
+        assert_eq!(fallback_label(DIAG1.registry_id, 0x0000), "DIAG-1.BATTERY_LEVEL");
+        assert_eq!(fallback_label(0x40, 0x0102), "0x40.0x0102");
+    }
+}
@@ -0,0 +1,395 @@
+//! Session-negotiated (Level 2) codebooks and their incremental updates.
+//!
+//! A [`DynamicCodebook`] holds `(code, type, label)` triples defined at
+//! runtime, with a version number. A sender evolves its copy and sends the
+//! [`diff`](DynamicCodebook::diff) against what the peer last acknowledged
+//! as a CODEBOOK_DEF frame, outside utterances:
+//!
+//! ```text
+//! CODEBOOK_DEF  <u8 registry> <u16 base_version> <u16 version> <u16 n> change*
+//!   0x01 <u16 code> <string type> <string label>    add
+//!   0x02 <u16 code>                                 remove
+//!   0x03 <u16 code> <string type> <string label>    modify
+//! CODEBOOK_ACK  <u16 hash>     CRC-16 of the updated codebook
+//! CODEBOOK_NACK <u8 reason>
+//! ```
+//!
+//! A full definition is a diff from the empty codebook at version 0. The
+//! receiver applies a diff only on top of `base_version`, all or nothing.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::codebook::base::esc;
+use crate::codebook::types::TypeExpr;
+use crate::error::AILLError;
+use crate::wire::{crc16, ByteReader, ByteWriter};
+
+const ADD: u8 = 0x01;
+const REMOVE: u8 = 0x02;
+const MODIFY: u8 = 0x03;
+
+/// An entry of a [`DynamicCodebook`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct DynamicEntry {
+    pub code: u16,
+    /// Type signature in [`TypeExpr`] syntax.
+    pub value_type: String,
+    pub label: String,
+}
+
+impl DynamicEntry {
+    pub fn new(code: u16, value_type: &str, label: &str) -> Self {
+        Self { code, value_type: value_type.to_string(), label: label.to_string() }
+    }
+}
+
+/// A codebook defined at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct DynamicCodebook {
+    pub registry_id: u8,
+    version: u16,
+    entries: BTreeMap<u16, DynamicEntry>,
+}
+
+impl DynamicCodebook {
+    /// Empty codebook at version 0.
+    pub fn new(registry_id: u8) -> Self {
+        Self { registry_id, version: 0, entries: BTreeMap::new() }
+    }
+
+    pub fn with_entry(mut self, code: u16, value_type: &str, label: &str) -> Self {
+        self.insert(DynamicEntry::new(code, value_type, label));
+        self
+    }
+
+    pub fn with_version(mut self, version: u16) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: u16) {
+        self.version = version;
+    }
+
+    /// Add or replace an entry, returning the one it replaced.
+    pub fn insert(&mut self, entry: DynamicEntry) -> Option<DynamicEntry> {
+        self.entries.insert(entry.code, entry)
+    }
+
+    pub fn remove(&mut self, code: u16) -> Option<DynamicEntry> {
+        self.entries.remove(&code)
+    }
+
+    pub fn get(&self, code: u16) -> Option<&DynamicEntry> {
+        self.entries.get(&code)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DynamicEntry> {
+        self.entries.values()
+    }
+
+    /// CRC-16 over the entries in code order, as carried by CODEBOOK_ACK.
+    pub fn hash(&self) -> u16 {
+        let mut w = ByteWriter::new();
+        for e in self.entries.values() {
+            w.write_u16_be(e.code).write_string(&e.value_type).write_string(&e.label);
+        }
+        crc16(&w.into_bytes())
+    }
+
+    /// Changes that turn this codebook into `newer`.
+    pub fn diff(&self, newer: &DynamicCodebook) -> CodebookDiff {
+        let mut changes = Vec::new();
+        for (code, entry) in &newer.entries {
+            match self.entries.get(code) {
+                None => changes.push(CodebookChange::Add(entry.clone())),
+                Some(old) if old != entry => changes.push(CodebookChange::Modify(entry.clone())),
+                Some(_) => {}
+            }
+        }
+        for code in self.entries.keys() {
+            if !newer.entries.contains_key(code) {
+                changes.push(CodebookChange::Remove(*code));
+            }
+        }
+        CodebookDiff { registry_id: newer.registry_id, base_version: self.version, version: newer.version, changes }
+    }
+
+    /// Apply `diff` if it was made against this version, leaving the
+    /// codebook unchanged if any change is rejected.
+    pub fn apply(&mut self, diff: &CodebookDiff) -> Result<(), CodebookNack> {
+        if diff.registry_id != self.registry_id {
+            return Err(CodebookNack::UnknownCodebook);
+        }
+        if diff.base_version != self.version {
+            return Err(CodebookNack::VersionMismatch);
+        }
+        let mut entries = self.entries.clone();
+        for change in &diff.changes {
+            match change {
+                CodebookChange::Add(e) | CodebookChange::Modify(e) => {
+                    if TypeExpr::parse(&e.value_type).is_err() {
+                        return Err(CodebookNack::UnsupportedType);
+                    }
+                    let exists = entries.contains_key(&e.code);
+                    if matches!(change, CodebookChange::Add(_)) && exists {
+                        return Err(CodebookNack::Collision);
+                    }
+                    if matches!(change, CodebookChange::Modify(_)) && !exists {
+                        return Err(CodebookNack::UnknownEntry);
+                    }
+                    entries.insert(e.code, e.clone());
+                }
+                CodebookChange::Remove(code) => {
+                    entries.remove(code).ok_or(CodebookNack::UnknownEntry)?;
+                }
+            }
+        }
+        self.entries = entries;
+        self.version = diff.version;
+        Ok(())
+    }
+
+    /// Receiving side: apply a CODEBOOK_DEF frame and produce the reply.
+    pub fn handle_def(&mut self, wire: &[u8]) -> CodebookReply {
+        let result = CodebookDiff::decode(wire)
+            .map_err(|_| CodebookNack::Malformed)
+            .and_then(|diff| self.apply(&diff));
+        match result {
+            Ok(()) => CodebookReply::Ack(self.hash()),
+            Err(reason) => CodebookReply::Nack(reason),
+        }
+    }
+}
+
+/// One change in a [`CodebookDiff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum CodebookChange {
+    Add(DynamicEntry),
+    Remove(u16),
+    Modify(DynamicEntry),
+}
+
+/// The payload of a CODEBOOK_DEF frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct CodebookDiff {
+    pub registry_id: u8,
+    /// Version the changes apply to.
+    pub base_version: u16,
+    /// Version after applying them.
+    pub version: u16,
+    pub changes: Vec<CodebookChange>,
+}
+
+impl CodebookDiff {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = ByteWriter::new();
+        w.write_u8(esc::CODEBOOK_DEF).write_u8(self.registry_id);
+        w.write_u16_be(self.base_version).write_u16_be(self.version);
+        w.write_u16_be(self.changes.len() as u16);
+        for change in &self.changes {
+            let (op, entry) = match change {
+                CodebookChange::Add(e) => (ADD, e),
+                CodebookChange::Modify(e) => (MODIFY, e),
+                CodebookChange::Remove(code) => {
+                    w.write_u8(REMOVE).write_u16_be(*code);
+                    continue;
+                }
+            };
+            w.write_u8(op).write_u16_be(entry.code).write_string(&entry.value_type).write_string(&entry.label);
+        }
+        w.into_bytes()
+    }
+
+    pub fn decode(wire: &[u8]) -> Result<Self, AILLError> {
+        let mut r = ByteReader::new(wire);
+        let code = r.read_u8()?;
+        if code != esc::CODEBOOK_DEF {
+            return Err(AILLError::InvalidOpCode(code));
+        }
+        let registry_id = r.read_u8()?;
+        let base_version = r.read_u16_be()?;
+        let version = r.read_u16_be()?;
+        let n = r.read_u16_be()?;
+        let mut changes = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            let op = r.read_u8()?;
+            let code = r.read_u16_be()?;
+            let change = match op {
+                REMOVE => CodebookChange::Remove(code),
+                ADD | MODIFY => {
+                    let entry = DynamicEntry { code, value_type: r.read_string()?, label: r.read_string()? };
+                    if op == ADD { CodebookChange::Add(entry) } else { CodebookChange::Modify(entry) }
+                }
+                other => {
+                    return Err(AILLError::InvalidStructure(format!("unknown CODEBOOK_DEF change 0x{:02X}", other)))
+                }
+            };
+            changes.push(change);
+        }
+        if !r.is_empty() {
+            return Err(AILLError::InvalidStructure("trailing bytes after CODEBOOK_DEF".into()));
+        }
+        Ok(Self { registry_id, base_version, version, changes })
+    }
+}
+
+/// Why a CODEBOOK_DEF was rejected. The first three codes are shared with
+/// EXT_NACK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum CodebookNack {
+    /// An added code is already defined.
+    Collision,
+    /// A type signature does not parse.
+    UnsupportedType,
+    ResourceExhaustion,
+    /// The diff was made against a different version.
+    VersionMismatch,
+    /// A removed or modified code is not defined.
+    UnknownEntry,
+    /// The receiver has no codebook with that registry id.
+    UnknownCodebook,
+    /// The frame could not be parsed.
+    Malformed,
+    Other(u8),
+}
+
+impl CodebookNack {
+    pub fn code(self) -> u8 {
+        match self {
+            CodebookNack::Collision => 0x00,
+            CodebookNack::UnsupportedType => 0x01,
+            CodebookNack::ResourceExhaustion => 0x02,
+            CodebookNack::VersionMismatch => 0x03,
+            CodebookNack::UnknownEntry => 0x04,
+            CodebookNack::UnknownCodebook => 0x05,
+            CodebookNack::Malformed => 0x06,
+            CodebookNack::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => CodebookNack::Collision,
+            0x01 => CodebookNack::UnsupportedType,
+            0x02 => CodebookNack::ResourceExhaustion,
+            0x03 => CodebookNack::VersionMismatch,
+            0x04 => CodebookNack::UnknownEntry,
+            0x05 => CodebookNack::UnknownCodebook,
+            0x06 => CodebookNack::Malformed,
+            other => CodebookNack::Other(other),
+        }
+    }
+}
+
+/// A CODEBOOK_ACK or CODEBOOK_NACK frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum CodebookReply {
+    /// Hash of the receiver's codebook after the update; the sender compares
+    /// it with [`DynamicCodebook::hash`] of its own copy.
+    Ack(u16),
+    Nack(CodebookNack),
+}
+
+impl CodebookReply {
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            CodebookReply::Ack(hash) => {
+                let [hi, lo] = hash.to_be_bytes();
+                vec![esc::CODEBOOK_ACK, hi, lo]
+            }
+            CodebookReply::Nack(reason) => vec![esc::CODEBOOK_NACK, reason.code()],
+        }
+    }
+
+    pub fn decode(wire: &[u8]) -> Result<Self, AILLError> {
+        match *wire {
+            [esc::CODEBOOK_ACK, hi, lo] => Ok(CodebookReply::Ack(u16::from_be_bytes([hi, lo]))),
+            [esc::CODEBOOK_NACK, reason] => Ok(CodebookReply::Nack(CodebookNack::from_code(reason))),
+            [code, ..] if code != esc::CODEBOOK_ACK && code != esc::CODEBOOK_NACK => Err(AILLError::InvalidOpCode(code)),
+            _ => Err(AILLError::InvalidStructure(format!("malformed codebook reply of {} bytes", wire.len()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v1() -> DynamicCodebook {
+        DynamicCodebook::new(0x40)
+            .with_entry(0x0000, "FLOAT32", "soil_moisture")
+            .with_entry(0x0001, "UINT8", "battery_pct")
+            .with_entry(0x0002, "ARRAY<FLOAT32,2>", "gps")
+            .with_version(1)
+    }
+
+    #[test]
+    fn full_definition_then_incremental_update() {
+        let mut peer = DynamicCodebook::new(0x40);
+        let def = DynamicCodebook::new(0x40).diff(&v1()).encode();
+        assert_eq!(peer.handle_def(&def), CodebookReply::Ack(v1().hash()));
+        assert_eq!(peer, v1());
+
+        let mut v2 = v1().with_entry(0x0003, "STRING", "firmware").with_version(2);
+        v2.remove(0x0001);
+        v2.insert(DynamicEntry::new(0x0002, "ARRAY<FLOAT32,3>", "gps"));
+        let diff = v1().diff(&v2);
+        assert_eq!(diff.changes.len(), 3);
+        assert_eq!(CodebookDiff::decode(&diff.encode()).unwrap(), diff);
+
+        let reply = CodebookReply::decode(&peer.handle_def(&diff.encode()).encode()).unwrap();
+        assert_eq!(reply, CodebookReply::Ack(v2.hash()));
+        assert_eq!(peer.version(), 2);
+        assert_ne!(v1().hash(), v2.hash());
+    }
+
+    #[test]
+    fn rejected_updates_leave_the_codebook_unchanged() {
+        let mut peer = v1();
+        let stale = DynamicCodebook::new(0x40).diff(&v1()).encode();
+        assert_eq!(peer.handle_def(&stale), CodebookReply::Nack(CodebookNack::VersionMismatch));
+
+        let mut bad = v1().diff(&v1().with_entry(0x0009, "UINT8", "x").with_version(2));
+        bad.changes.push(CodebookChange::Remove(0x0042));
+        assert_eq!(peer.handle_def(&bad.encode()), CodebookReply::Nack(CodebookNack::UnknownEntry));
+        assert_eq!(peer, v1());
+
+        let typo = v1().diff(&v1().with_entry(0x0009, "LIST<FLOAT32", "x").with_version(2));
+        assert_eq!(peer.apply(&typo), Err(CodebookNack::UnsupportedType));
+        assert_eq!(peer.handle_def(&[esc::CODEBOOK_DEF, 0x40]), CodebookReply::Nack(CodebookNack::Malformed));
+        assert_eq!(CodebookReply::decode(&[esc::CODEBOOK_NACK, 0x77]).unwrap(), CodebookReply::Nack(CodebookNack::Other(0x77)));
+    }
+
+    #[test]
+    fn hostile_type_strings_are_refused() {
+        let deep = format!("{}UINT8{}", "LIST<".repeat(10_000), ">".repeat(10_000));
+        for hostile in [deep.as_str(), "\u{A0}", "LIST<\u{2003}", "ARRAY<\u{3000}\u{A0}"] {
+            let mut peer = v1();
+            let def = v1().diff(&v1().with_entry(0x0009, hostile, "x").with_version(2)).encode();
+            assert_eq!(peer.handle_def(&def), CodebookReply::Nack(CodebookNack::UnsupportedType));
+            assert_eq!(peer, v1());
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::types::TypeExpr;
+use super::DomainEntry;
+
+/// One named value of an enumerated domain entry (e.g. `3=danger`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnumVariant {
+    pub value: u8,
+    /// Variant name without any trailing qualifier, e.g. `safe` for `0=safe (>2m)`.
+    pub name: &'static str,
+}
+
+/// Parse `N=name` enumerations out of a codebook description string.
+///
+/// Descriptions look like `"0=clear, 1=caution, 2=warning"`, optionally
+/// preceded by prose (`"Sensor fault: 0=degraded, 1=failed"`) and with
+/// parenthesised qualifiers after a name. Returns an empty vector when the
+/// description does not enumerate any values.
+pub fn parse_enum_description(description: &'static str) -> Vec<EnumVariant> {
+    let body = match description.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => &description[start..],
+        None => return Vec::new(),
+    };
+
+    let mut variants = Vec::new();
+    for item in body.split(',') {
+        let Some((value, name)) = item.split_once('=') else {
+            return Vec::new();
+        };
+        let Ok(value) = value.trim().parse::<u8>() else {
+            return Vec::new();
+        };
+        let name = name.trim();
+        let name = name.split_whitespace().next().unwrap_or(name);
+        if name.is_empty() {
+            return Vec::new();
+        }
+        variants.push(EnumVariant { value, name });
+    }
+    variants
+}
+
+impl DomainEntry {
+    /// Enumerated values of a `UINT8` state-code entry, parsed from its
+    /// description. Returns `None` for entries that are not enumerations.
+    pub fn enum_variants(&self) -> Option<Vec<EnumVariant>> {
+        if self.value_type != "UINT8" {
+            return None;
+        }
+        let variants = parse_enum_description(self.description);
+        if variants.is_empty() {
+            None
+        } else {
+            Some(variants)
+        }
+    }
+
+    /// Name of the enumerated value `value`, if this entry is an enumeration
+    /// and defines it.
+    pub fn enum_label(&self, value: u8) -> Option<&'static str> {
+        self.enum_variants()?
+            .into_iter()
+            .find(|v| v.value == value)
+            .map(|v| v.name)
+    }
+
+    /// The enumeration as a [`TypeExpr::Enum`], when the values are the
+    /// contiguous range `0..n`.
+    pub fn enum_type(&self) -> Option<TypeExpr> {
+        let variants = self.enum_variants()?;
+        if variants.iter().enumerate().all(|(i, v)| v.value as usize == i) {
+            Some(TypeExpr::Enum(variants.iter().map(|v| v.name.to_string()).collect()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::{MANIP1, SAFETY1};
+
+    #[test]
+    fn emergency_level_labels() {
+        let entry = SAFETY1.lookup(0x0000).unwrap();
+        assert_eq!(entry.enum_label(3), Some("danger"));
+        assert_eq!(entry.enum_label(5), Some("catastrophic"));
+        assert_eq!(entry.enum_label(6), None);
+    }
+
+    #[test]
+    fn qualifiers_are_stripped() {
+        let zone = SAFETY1.lookup(0x0023).unwrap();
+        assert_eq!(zone.enum_label(0), Some("safe"));
+        assert_eq!(zone.enum_label(3), Some("danger"));
+    }
+
+    #[test]
+    fn gripper_state_enum_type() {
+        let gripper = MANIP1.lookup(0x0000).unwrap();
+        match gripper.enum_type() {
+            Some(TypeExpr::Enum(names)) => assert_eq!(names[4], "holding"),
+            other => panic!("expected enum, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_enum_descriptions() {
+        assert!(parse_enum_description("Coord frame ID").is_empty());
+        assert!(parse_enum_description("Task priority 0-7").is_empty());
+        assert!(SAFETY1.lookup(0x0021).unwrap().enum_variants().is_none());
+    }
+}
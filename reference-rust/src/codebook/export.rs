@@ -0,0 +1,145 @@
+#[cfg(feature = "ast-serde")]
+use serde::Serialize;
+
+use super::{BASE_CODEBOOK, DOMAIN_REGISTRY};
+#[cfg(feature = "ast-serde")]
+use super::{CodeEntry, DomainEntry};
+
+/// Codebook version reported in exports.
+pub const CODEBOOK_VERSION: &str = "1.1";
+
+/// Column header used by [`export_csv`].
+pub const CSV_HEADER: &str = "table,registry_id,code,mnemonic,category,value_type,unit,description";
+
+#[cfg(feature = "ast-serde")]
+#[derive(Serialize)]
+struct DomainExport {
+    registry_id: u8,
+    name: &'static str,
+    entries: &'static [DomainEntry],
+}
+
+#[cfg(feature = "ast-serde")]
+#[derive(Serialize)]
+struct CodebookExport {
+    version: &'static str,
+    base: &'static [CodeEntry],
+    domains: Vec<DomainExport>,
+}
+
+/// Export the base codebook and every registered domain codebook as JSON.
+///
+/// The document has the shape
+/// `{ "version", "base": [{code, mnemonic, category}], "domains": [{registry_id, name, entries}] }`
+/// where domain entries carry `code`, `mnemonic`, `value_type`, `unit` and `description`.
+#[cfg(feature = "ast-serde")]
+pub fn export_json() -> String {
+    let doc = CodebookExport {
+        version: CODEBOOK_VERSION,
+        base: &BASE_CODEBOOK,
+        domains: DOMAIN_REGISTRY
+            .iter()
+            .map(|cb| DomainExport {
+                registry_id: cb.registry_id,
+                name: cb.name,
+                entries: cb.entries,
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&doc).expect("codebook tables are always serializable")
+}
+
+/// Export the base codebook and every registered domain codebook as CSV.
+///
+/// Base rows use table `BASE` with an empty registry id; domain rows use the
+/// domain name (e.g. `NAV-1`) and leave `category` empty. Codes are written
+/// as hex (`0x1A` for base, `0x001A` for domains).
+pub fn export_csv() -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+
+    for entry in BASE_CODEBOOK.iter() {
+        push_row(&mut out, &[
+            "BASE",
+            "",
+            &format!("0x{:02X}", entry.code),
+            entry.mnemonic,
+            entry.category,
+            "",
+            "",
+            "",
+        ]);
+    }
+
+    for cb in DOMAIN_REGISTRY {
+        let registry_id = format!("0x{:02X}", cb.registry_id);
+        for entry in cb.entries() {
+            push_row(&mut out, &[
+                cb.name,
+                &registry_id,
+                &format!("0x{:04X}", entry.code),
+                entry.mnemonic,
+                "",
+                entry.value_type,
+                entry.unit,
+                entry.description,
+            ]);
+        }
+    }
+
+    out
+}
+
+fn push_row(out: &mut String, cells: &[&str]) {
+    let row: Vec<String> = cells.iter().map(|c| csv_escape(c)).collect();
+    out.push_str(&row.join(","));
+    out.push('\n');
+}
+
+/// Quote a CSV cell if it contains a delimiter, quote, or line break.
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "ast-serde")]
+    #[test]
+    fn json_contains_all_tables() {
+        let doc: serde_json::Value = serde_json::from_str(&export_json()).unwrap();
+        assert_eq!(doc["base"].as_array().unwrap().len(), 256);
+        assert_eq!(doc["base"][0x81]["mnemonic"], "ASSERT");
+        let domains = doc["domains"].as_array().unwrap();
+        assert_eq!(domains.len(), DOMAIN_REGISTRY.len());
+        assert_eq!(domains[0]["name"], "NAV-1");
+        assert_eq!(domains[0]["entries"][0]["value_type"], "ARRAY<FLOAT32,3>");
+    }
+
+    #[test]
+    fn csv_row_count_and_quoting() {
+        let csv = export_csv();
+        let domain_rows: usize = DOMAIN_REGISTRY.iter().map(|cb| cb.len()).sum();
+        assert_eq!(csv.lines().count(), 1 + 256 + domain_rows);
+        assert!(csv.starts_with(CSV_HEADER));
+        assert!(csv.contains("NAV-1,0x01,0x0000,POSITION_3D,,\"ARRAY<FLOAT32,3>\",m,\"3D position (x, y, z)\""));
+    }
+
+    #[test]
+    fn csv_quotes_line_breaks() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape("two\r\nlines"), "\"two\r\nlines\"");
+        assert_eq!(csv_escape("carriage\rreturn"), "\"carriage\rreturn\"");
+    }
+
+    #[test]
+    fn csv_escape_doubles_quotes() {
+        assert_eq!(csv_escape("a\"b"), "\"a\"\"b\"");
+        assert_eq!(csv_escape("plain"), "plain");
+    }
+}
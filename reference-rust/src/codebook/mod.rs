@@ -6,11 +6,28 @@ pub mod comm;
 pub mod diag;
 pub mod plan;
 pub mod safety;
+pub mod export;
+pub mod types;
+pub mod enums;
+pub mod dynamic;
+pub mod search;
+pub mod compat;
 
 pub use base::*;
+pub use export::export_csv;
+#[cfg(feature = "ast-serde")]
+pub use export::export_json;
+pub use types::{ScalarType, TypeExpr};
+pub use enums::{parse_enum_description, EnumVariant};
+pub use search::{search, Match, MatchKind};
+pub use compat::{compatibility, fallback_label, CompatReport, Degradation};
+
+#[cfg(feature = "ast-serde")]
+use serde::Serialize;
 
 /// A domain codebook entry.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
 pub struct DomainEntry {
     pub code: u16,
     pub mnemonic: &'static str,
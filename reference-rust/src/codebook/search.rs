@@ -0,0 +1,143 @@
+use super::{BASE_CODEBOOK, DOMAIN_REGISTRY};
+
+/// How a [`Match`] fits the query, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// The mnemonic is the query.
+    Exact,
+    /// The mnemonic starts with the query.
+    Prefix,
+    /// The mnemonic contains the query.
+    Substring,
+    /// The description contains the query.
+    Description,
+    /// The query's letters appear in order in the mnemonic, or the query is
+    /// one typo away from a word of the mnemonic or description.
+    Fuzzy,
+}
+
+/// A codebook entry found by [`search`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    /// Registry id of the domain codebook; `None` for the base codebook.
+    pub registry_id: Option<u8>,
+    /// `BASE` or the domain codebook's name, e.g. `DIAG-1`.
+    pub table: &'static str,
+    pub code: u16,
+    pub mnemonic: &'static str,
+    /// The domain entry's description, or the base entry's category.
+    pub description: &'static str,
+    pub kind: MatchKind,
+}
+
+/// Find entries of the base codebook and every registered domain codebook
+/// matching `query`, ignoring case. Spaces in the query match the
+/// underscores of mnemonics. Best matches come first, ties in codebook
+/// order.
+pub fn search(query: &str) -> Vec<Match> {
+    let query = query.trim().to_ascii_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let base = BASE_CODEBOOK
+        .iter()
+        .filter(|e| !e.mnemonic.is_empty() && !e.mnemonic.starts_with("RESERVED"))
+        .map(|e| (None, "BASE", e.code as u16, e.mnemonic, e.category));
+    let domains = DOMAIN_REGISTRY.iter().flat_map(|cb| {
+        cb.entries().iter().map(|e| (Some(cb.registry_id), cb.name, e.code, e.mnemonic, e.description))
+    });
+    let mut matches: Vec<Match> = base
+        .chain(domains)
+        .filter_map(|(registry_id, table, code, mnemonic, description)| {
+            let kind = match_kind(&query, mnemonic, description)?;
+            Some(Match { registry_id, table, code, mnemonic, description, kind })
+        })
+        .collect();
+    // Stable, so equally good matches stay in codebook order
+    matches.sort_by_key(|m| m.kind);
+    matches
+}
+
+fn match_kind(query: &str, mnemonic: &str, description: &str) -> Option<MatchKind> {
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let description = description.to_ascii_lowercase();
+    let underscored = query.replace(' ', "_");
+    if mnemonic == underscored {
+        Some(MatchKind::Exact)
+    } else if mnemonic.starts_with(&underscored) {
+        Some(MatchKind::Prefix)
+    } else if mnemonic.contains(&underscored) {
+        Some(MatchKind::Substring)
+    } else if description.contains(query) {
+        Some(MatchKind::Description)
+    } else if is_fuzzy(query, &mnemonic, &description) {
+        Some(MatchKind::Fuzzy)
+    } else {
+        None
+    }
+}
+
+fn is_fuzzy(query: &str, mnemonic: &str, description: &str) -> bool {
+    let letters: Vec<char> = query.chars().filter(char::is_ascii_alphanumeric).collect();
+    if letters.len() < 3 {
+        return false;
+    }
+    let mut rest = mnemonic.chars();
+    if letters.iter().all(|&c| rest.any(|m| m == c)) {
+        return true;
+    }
+    // Short words are too easily one edit from something
+    letters.len() >= 4
+        && mnemonic
+            .split('_')
+            .chain(description.split(|c: char| !c.is_ascii_alphanumeric()))
+            .any(|word| within_one_edit(&letters, &word.chars().collect::<Vec<_>>()))
+}
+
+/// One letter inserted, dropped or replaced, or two neighbours swapped.
+fn within_one_edit(a: &[char], b: &[char]) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if short.is_empty() || long.len() - short.len() > 1 {
+        return false;
+    }
+    let same = short.iter().zip(long).take_while(|(x, y)| x == y).count();
+    if same == short.len() {
+        return true;
+    }
+    if short.len() < long.len() {
+        return short[same..] == long[same + 1..];
+    }
+    let swapped = same + 1 < short.len()
+        && short[same] == long[same + 1]
+        && short[same + 1] == long[same]
+        && short[same + 2..] == long[same + 2..];
+    swapped || short[same + 1..] == long[same + 1..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::DIAG1;
+
+    #[test]
+    fn best_matches_come_first() {
+        let found = search("Assert");
+        assert_eq!((found[0].table, found[0].code, found[0].kind), ("BASE", 0x81, MatchKind::Exact));
+
+        let found = search("battery");
+        assert!(found.len() >= 5 && found.iter().all(|m| m.mnemonic.starts_with("BATTERY") || m.kind > MatchKind::Substring));
+        assert_eq!((found[0].registry_id, found[0].mnemonic), (Some(DIAG1.registry_id), "BATTERY_LEVEL"));
+        assert_eq!(search("battery level")[0].kind, MatchKind::Exact);
+        assert!(search("state of charge").iter().any(|m| m.mnemonic == "BATTERY_LEVEL" && m.kind == MatchKind::Description));
+        assert!(search("  ").is_empty());
+    }
+
+    #[test]
+    fn typos_still_find_the_entry() {
+        for typo in ["batery", "battrey", "batteyr", "bxttery"] {
+            let found = search(typo);
+            assert!(found.iter().any(|m| m.mnemonic == "BATTERY_LEVEL" && m.kind == MatchKind::Fuzzy), "{}", typo);
+        }
+        assert!(search("zzzzqx").is_empty());
+    }
+}
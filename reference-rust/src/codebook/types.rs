@@ -0,0 +1,374 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::base::ty;
+use super::DomainEntry;
+use crate::error::AILLError;
+
+/// Deepest nesting of ARRAY and LIST in a type [`TypeExpr::parse`] accepts.
+/// Type strings arrive from peers in CODEBOOK_DEF frames, so the parser's
+/// recursion must stay bounded.
+pub const MAX_TYPE_DEPTH: usize = 32;
+
+/// Scalar value types named in domain codebook `value_type` strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScalarType {
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Uint128,
+    Float16,
+    Float32,
+    Float64,
+    Bool,
+    String,
+    Timestamp,
+}
+
+impl ScalarType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "INT8" => ScalarType::Int8,
+            "INT16" => ScalarType::Int16,
+            "INT32" => ScalarType::Int32,
+            "INT64" => ScalarType::Int64,
+            "UINT8" => ScalarType::Uint8,
+            "UINT16" => ScalarType::Uint16,
+            "UINT32" => ScalarType::Uint32,
+            "UINT64" => ScalarType::Uint64,
+            "UINT128" => ScalarType::Uint128,
+            "FLOAT16" => ScalarType::Float16,
+            "FLOAT32" => ScalarType::Float32,
+            "FLOAT64" => ScalarType::Float64,
+            "BOOL" => ScalarType::Bool,
+            "STRING" => ScalarType::String,
+            "TIMESTAMP" => ScalarType::Timestamp,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScalarType::Int8 => "INT8",
+            ScalarType::Int16 => "INT16",
+            ScalarType::Int32 => "INT32",
+            ScalarType::Int64 => "INT64",
+            ScalarType::Uint8 => "UINT8",
+            ScalarType::Uint16 => "UINT16",
+            ScalarType::Uint32 => "UINT32",
+            ScalarType::Uint64 => "UINT64",
+            ScalarType::Uint128 => "UINT128",
+            ScalarType::Float16 => "FLOAT16",
+            ScalarType::Float32 => "FLOAT32",
+            ScalarType::Float64 => "FLOAT64",
+            ScalarType::Bool => "BOOL",
+            ScalarType::String => "STRING",
+            ScalarType::Timestamp => "TIMESTAMP",
+        }
+    }
+
+    /// The base-codebook type marker carrying this scalar on the wire.
+    /// UINT128 has no dedicated marker (it travels as 16 raw bytes).
+    pub fn type_marker(&self) -> Option<u8> {
+        Some(match self {
+            ScalarType::Int8 => ty::TYPE_INT8,
+            ScalarType::Int16 => ty::TYPE_INT16,
+            ScalarType::Int32 => ty::TYPE_INT32,
+            ScalarType::Int64 => ty::TYPE_INT64,
+            ScalarType::Uint8 => ty::TYPE_UINT8,
+            ScalarType::Uint16 => ty::TYPE_UINT16,
+            ScalarType::Uint32 => ty::TYPE_UINT32,
+            ScalarType::Uint64 => ty::TYPE_UINT64,
+            ScalarType::Uint128 => return None,
+            ScalarType::Float16 => ty::TYPE_FLOAT16,
+            ScalarType::Float32 => ty::TYPE_FLOAT32,
+            ScalarType::Float64 => ty::TYPE_FLOAT64,
+            ScalarType::Bool => ty::TYPE_BOOL,
+            ScalarType::String => ty::TYPE_STRING,
+            ScalarType::Timestamp => ty::TYPE_TIMESTAMP,
+        })
+    }
+}
+
+/// Structured form of a domain entry's `value_type` string.
+///
+/// Grammar (whitespace is not significant):
+///
+/// ```text
+/// type   := "NONE" | scalar | bytes | array | list | struct | enum | NAME
+/// bytes  := "BYTES" [ "(" INT ")" ]
+/// array  := "ARRAY<" type "," ( INT | "N" ) ">"
+/// list   := "LIST<" type ">"
+/// struct := "STRUCT" [ "{" NAME { "," NAME } "}" ]
+/// enum   := "ENUM{" NAME { "," NAME } "}"
+/// ```
+///
+/// A bare upper-case `NAME` that is not a scalar refers to another domain
+/// entry by mnemonic (e.g. `LIST<WAYPOINT>`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeExpr {
+    /// No payload (`NONE`).
+    None,
+    Scalar(ScalarType),
+    /// Byte string, optionally of fixed length (`BYTES(16)`).
+    Bytes(Option<usize>),
+    /// Homogeneous array; `len` is `None` for variable length (`N`).
+    Array { element: Box<TypeExpr>, len: Option<usize> },
+    List(Box<TypeExpr>),
+    /// Struct with named fields in declaration order. An opaque `STRUCT`
+    /// has no field names.
+    Struct(Vec<String>),
+    /// Enumeration with variant names in code order (0, 1, 2, ...).
+    Enum(Vec<String>),
+    /// Reference to another entry's type by mnemonic.
+    Named(String),
+}
+
+impl TypeExpr {
+    /// Parse a `value_type` string.
+    pub fn parse(s: &str) -> Result<TypeExpr, AILLError> {
+        let mut p = Parser { src: s, pos: 0, depth: 0 };
+        let expr = p.parse_type()?;
+        p.skip_ws();
+        if p.pos != s.len() {
+            return Err(p.error("trailing characters"));
+        }
+        Ok(expr)
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, TypeExpr::Scalar(_))
+    }
+}
+
+impl FromStr for TypeExpr {
+    type Err = AILLError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TypeExpr::parse(s)
+    }
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::None => write!(f, "NONE"),
+            TypeExpr::Scalar(s) => write!(f, "{}", s.name()),
+            TypeExpr::Bytes(None) => write!(f, "BYTES"),
+            TypeExpr::Bytes(Some(n)) => write!(f, "BYTES({})", n),
+            TypeExpr::Array { element, len: Some(n) } => write!(f, "ARRAY<{},{}>", element, n),
+            TypeExpr::Array { element, len: None } => write!(f, "ARRAY<{},N>", element),
+            TypeExpr::List(element) => write!(f, "LIST<{}>", element),
+            TypeExpr::Struct(fields) if fields.is_empty() => write!(f, "STRUCT"),
+            TypeExpr::Struct(fields) => write!(f, "STRUCT{{{}}}", fields.join(",")),
+            TypeExpr::Enum(variants) => write!(f, "ENUM{{{}}}", variants.join(",")),
+            TypeExpr::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl DomainEntry {
+    /// Parse this entry's `value_type` into a [`TypeExpr`].
+    pub fn type_expr(&self) -> Result<TypeExpr, AILLError> {
+        TypeExpr::parse(self.value_type)
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// ARRAY and LIST types being parsed.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> AILLError {
+        AILLError::InvalidStructure(format!(
+            "Invalid value type '{}' at column {}: {}",
+            self.src, self.pos, msg
+        ))
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.src[self.pos..].chars().next().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.src[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), AILLError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<&str, AILLError> {
+        self.skip_ws();
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected identifier"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn number(&mut self) -> Result<usize, AILLError> {
+        let ident = self.ident()?;
+        ident.parse().map_err(|_| self.error("expected integer"))
+    }
+
+    fn name_list(&mut self) -> Result<Vec<String>, AILLError> {
+        self.expect('{')?;
+        let mut names = vec![self.ident()?.to_string()];
+        while self.eat(',') {
+            names.push(self.ident()?.to_string());
+        }
+        self.expect('}')?;
+        Ok(names)
+    }
+
+    fn parse_type(&mut self) -> Result<TypeExpr, AILLError> {
+        let name = self.ident()?;
+        if let Some(scalar) = ScalarType::from_name(name) {
+            return Ok(TypeExpr::Scalar(scalar));
+        }
+        match name {
+            "NONE" => Ok(TypeExpr::None),
+            "BYTES" => {
+                if self.eat('(') {
+                    let n = self.number()?;
+                    self.expect(')')?;
+                    Ok(TypeExpr::Bytes(Some(n)))
+                } else {
+                    Ok(TypeExpr::Bytes(None))
+                }
+            }
+            "ARRAY" => {
+                self.expect('<')?;
+                let element = Box::new(self.parse_element()?);
+                self.expect(',')?;
+                let len = match self.ident()? {
+                    "N" => None,
+                    n => Some(n.parse().map_err(|_| self.error("expected array length"))?),
+                };
+                self.expect('>')?;
+                Ok(TypeExpr::Array { element, len })
+            }
+            "LIST" => {
+                self.expect('<')?;
+                let element = Box::new(self.parse_element()?);
+                self.expect('>')?;
+                Ok(TypeExpr::List(element))
+            }
+            "STRUCT" => {
+                self.skip_ws();
+                if self.src[self.pos..].starts_with('{') {
+                    Ok(TypeExpr::Struct(self.name_list()?))
+                } else {
+                    Ok(TypeExpr::Struct(Vec::new()))
+                }
+            }
+            "ENUM" => Ok(TypeExpr::Enum(self.name_list()?)),
+            other if other.starts_with(|c: char| c.is_ascii_uppercase()) => {
+                Ok(TypeExpr::Named(other.to_string()))
+            }
+            _ => Err(self.error("unknown type name")),
+        }
+    }
+
+    /// The element type of an ARRAY or LIST, one level deeper.
+    fn parse_element(&mut self) -> Result<TypeExpr, AILLError> {
+        if self.depth == MAX_TYPE_DEPTH {
+            return Err(self.error(&format!("nested deeper than {} levels", MAX_TYPE_DEPTH)));
+        }
+        self.depth += 1;
+        let element = self.parse_type();
+        self.depth -= 1;
+        element
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::DOMAIN_REGISTRY;
+
+    #[test]
+    fn every_domain_entry_parses_and_roundtrips() {
+        for cb in DOMAIN_REGISTRY {
+            for entry in cb.entries() {
+                let expr = entry.type_expr().unwrap_or_else(|e| {
+                    panic!("{} {}: {}", cb.name, entry.mnemonic, e)
+                });
+                assert_eq!(expr.to_string(), entry.value_type, "{} {}", cb.name, entry.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn nested_list_of_struct() {
+        let expr = TypeExpr::parse("LIST<STRUCT{time,positions}>").unwrap();
+        assert_eq!(
+            expr,
+            TypeExpr::List(Box::new(TypeExpr::Struct(vec!["time".into(), "positions".into()])))
+        );
+    }
+
+    #[test]
+    fn arrays_bytes_and_named() {
+        assert_eq!(
+            TypeExpr::parse("ARRAY<FLOAT16,N>").unwrap(),
+            TypeExpr::Array { element: Box::new(TypeExpr::Scalar(ScalarType::Float16)), len: None }
+        );
+        assert_eq!(TypeExpr::parse("BYTES(16)").unwrap(), TypeExpr::Bytes(Some(16)));
+        assert_eq!(TypeExpr::parse("POSITION_3D").unwrap(), TypeExpr::Named("POSITION_3D".into()));
+        assert_eq!(
+            TypeExpr::parse("ENUM{off, on}").unwrap(),
+            TypeExpr::Enum(vec!["off".into(), "on".into()])
+        );
+    }
+
+    #[test]
+    fn malformed_inputs_error() {
+        for bad in ["", "LIST<FLOAT32", "ARRAY<FLOAT32>", "STRUCT{a,}", "FLOAT32 junk", "lower"] {
+            assert!(TypeExpr::parse(bad).is_err(), "{:?} should fail", bad);
+        }
+    }
+
+    #[test]
+    fn unicode_whitespace_is_skipped() {
+        assert_eq!(
+            TypeExpr::parse("\u{A0}LIST<\u{2003}UINT8 >\u{3000}").unwrap(),
+            TypeExpr::List(Box::new(TypeExpr::Scalar(ScalarType::Uint8)))
+        );
+        assert!(TypeExpr::parse("LIST\u{2003}").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_errors_instead_of_overflowing() {
+        let nested = |depth: usize| format!("{}UINT8{}", "LIST<".repeat(depth), ">".repeat(depth));
+        assert!(TypeExpr::parse(&nested(MAX_TYPE_DEPTH)).is_ok());
+        assert!(TypeExpr::parse(&nested(MAX_TYPE_DEPTH + 1)).is_err());
+        assert!(TypeExpr::parse(&nested(10_000)).is_err());
+        assert!(TypeExpr::parse(&"ARRAY<".repeat(10_000)).is_err());
+    }
+}
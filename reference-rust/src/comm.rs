@@ -0,0 +1,271 @@
+//! COMM-1 discovery beacons and DIAG-1 capability reports.
+//!
+//! Agents announce themselves with a DISCOVERY_BEACON and answer queries
+//! with a CAPABILITIES_REPORT. Both carry the same capabilities struct, so
+//! a peer learns from either what the sender can decode:
+//!
+//! ```text
+//! ASSERT ESCAPE_L1 DISCOVERY_BEACON STRUCT{0x0000: BYTES(16) uuid, 0x0001: UINT8 type, 0x0002: caps}
+//! CODEBOOK_REF 1 DIAG-1
+//! ASSERT ESCAPE_L1 CAPABILITIES_REPORT caps
+//!
+//! caps = STRUCT{0x0000: UINT8 type, 0x0001: UINT8 role,
+//!               0x0002: LIST[n](STRUCT{0x0000: UINT8 registry, 0x0001: UINT16 version}),
+//!               0x0003: UINT32 max_payload, 0x0004: LIST[n](STRING profile)}
+//! ```
+//!
+//! CAPABILITIES_REPORT is a DIAG-1 entry, so the report binds ESCAPE_L1 to
+//! DIAG-1 first; its code would otherwise read as COMM-1 THANK. Both
+//! messages carry SOURCE_AGENT.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::agent::AgentId;
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::comm::COMM1_REGISTRY_ID;
+use crate::codebook::diag::DIAG1_REGISTRY_ID;
+use crate::codebook::DOMAIN_REGISTRY;
+use crate::encoder::{AILLEncoder, MAX_EPOCH_PAYLOAD};
+
+/// COMM-1 DISCOVERY_BEACON entry code.
+pub const DISCOVERY_BEACON: u16 = 0x0006;
+/// DIAG-1 CAPABILITIES_REPORT entry code.
+pub const CAPABILITIES_REPORT: u16 = 0x0067;
+
+/// Version reported for the built-in domain codebooks.
+pub const BUILTIN_CODEBOOK_VERSION: u16 = 1;
+
+/// What an agent is and what it can decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct AgentCapabilities {
+    /// COMM-1 AGENT_TYPE state, e.g. 1 for aerial.
+    pub agent_type: u8,
+    /// COMM-1 AGENT_ROLE state, e.g. 2 for scout.
+    pub role: u8,
+    /// Codebook version per supported registry id.
+    pub domains_supported: BTreeMap<u8, u16>,
+    /// Largest utterance, in bytes, the agent accepts.
+    pub max_payload: u32,
+    /// Names of the acoustic profiles the agent can receive.
+    pub audio_profiles: Vec<String>,
+}
+
+impl AgentCapabilities {
+    /// An agent of `agent_type` and `role` supporting no domains, accepting
+    /// one full epoch.
+    pub fn new(agent_type: u8, role: u8) -> Self {
+        Self {
+            agent_type,
+            role,
+            domains_supported: BTreeMap::new(),
+            max_payload: MAX_EPOCH_PAYLOAD as u32,
+            audio_profiles: Vec::new(),
+        }
+    }
+
+    /// Support every built-in domain codebook.
+    pub fn with_builtin_domains(mut self) -> Self {
+        for codebook in DOMAIN_REGISTRY {
+            self.domains_supported.insert(codebook.registry_id, BUILTIN_CODEBOOK_VERSION);
+        }
+        self
+    }
+
+    pub fn with_domain(mut self, registry_id: u8, version: u16) -> Self {
+        self.domains_supported.insert(registry_id, version);
+        self
+    }
+
+    pub fn with_max_payload(mut self, max_payload: u32) -> Self {
+        self.max_payload = max_payload;
+        self
+    }
+
+    pub fn with_audio_profile(mut self, name: &str) -> Self {
+        self.audio_profiles.push(name.to_string());
+        self
+    }
+
+    /// Whether the agent supports registry `registry_id`, in any version.
+    pub fn supports(&self, registry_id: u8) -> bool {
+        self.domains_supported.contains_key(&registry_id)
+    }
+
+    fn write(&self, e: &mut AILLEncoder) {
+        e.begin_struct();
+        e.field(0x0000).uint8(self.agent_type);
+        e.field(0x0001).uint8(self.role);
+        e.field(0x0002).begin_list(self.domains_supported.len() as u16);
+        for (registry, version) in &self.domains_supported {
+            e.begin_struct().field(0x0000).uint8(*registry).field(0x0001).uint16(*version).end_struct();
+        }
+        e.end_list();
+        e.field(0x0003).uint32(self.max_payload);
+        e.field(0x0004).begin_list(self.audio_profiles.len() as u16);
+        for name in &self.audio_profiles {
+            e.string(name);
+        }
+        e.end_list().end_struct();
+    }
+
+    fn read(node: &AstNode) -> Option<Self> {
+        let AstNode::Struct { fields } = node else {
+            return None;
+        };
+        let domains_supported = list(fields.get(&0x0002)?)?
+            .iter()
+            .map(|domain| {
+                let AstNode::Struct { fields } = domain else { return None };
+                match (literal(fields.get(&0x0000))?, literal(fields.get(&0x0001))?) {
+                    (LiteralValue::Uint8(registry), LiteralValue::Uint16(version)) => Some((*registry, *version)),
+                    _ => None,
+                }
+            })
+            .collect::<Option<_>>()?;
+        let audio_profiles = list(fields.get(&0x0004)?)?
+            .iter()
+            .map(|profile| match literal(Some(profile))? {
+                LiteralValue::String(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        match (literal(fields.get(&0x0000))?, literal(fields.get(&0x0001))?, literal(fields.get(&0x0003))?) {
+            (LiteralValue::Uint8(agent_type), LiteralValue::Uint8(role), LiteralValue::Uint32(max_payload)) => Some(Self {
+                agent_type: *agent_type,
+                role: *role,
+                domains_supported,
+                max_payload: *max_payload,
+                audio_profiles,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An agent's capabilities, as announced in a beacon or report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityReport {
+    pub agent: AgentId,
+    pub capabilities: AgentCapabilities,
+    /// Sent as a DISCOVERY_BEACON rather than a CAPABILITIES_REPORT.
+    pub beacon: bool,
+}
+
+impl CapabilityReport {
+    /// A DISCOVERY_BEACON announcing `agent`.
+    pub fn beacon(agent: AgentId, capabilities: AgentCapabilities) -> Self {
+        Self { agent, capabilities, beacon: true }
+    }
+
+    /// A CAPABILITIES_REPORT from `agent`.
+    pub fn report(agent: AgentId, capabilities: AgentCapabilities) -> Self {
+        Self { agent, capabilities, beacon: false }
+    }
+
+    /// Encode as an utterance, optionally sent to `dest`.
+    pub fn encode(&self, dest: Option<AgentId>) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, dest, None).source_agent(self.agent);
+        if self.beacon {
+            e.assert_().l1_ref(DISCOVERY_BEACON).begin_struct();
+            e.field(0x0000).bytes(self.agent.as_bytes());
+            e.field(0x0001).uint8(self.capabilities.agent_type);
+            e.field(0x0002);
+            self.capabilities.write(&mut e);
+            e.end_struct();
+        } else {
+            e.codebook_ref(1, DIAG1_REGISTRY_ID).assert_().l1_ref(CAPABILITIES_REPORT);
+            self.capabilities.write(&mut e);
+        }
+        e.end_utterance()
+    }
+
+    /// The beacon or report in a decoded utterance, if any. A report's
+    /// agent is its SOURCE_AGENT.
+    pub fn decode(ast: &AstNode) -> Option<CapabilityReport> {
+        let AstNode::Utterance { meta, body } = ast else {
+            return None;
+        };
+        body.windows(2).find_map(|pair| {
+            let [AstNode::Pragmatic { expression, .. }, value] = pair else {
+                return None;
+            };
+            match expression.as_ref() {
+                AstNode::DomainRef { level: 1, domain_code: DISCOVERY_BEACON, registry }
+                    if registry.is_none_or(|r| r == COMM1_REGISTRY_ID) =>
+                {
+                    let AstNode::Struct { fields } = value else { return None };
+                    let agent = match literal(fields.get(&0x0000))? {
+                        LiteralValue::Bytes(b) => AgentId::try_from(b.as_slice()).ok()?,
+                        _ => return None,
+                    };
+                    Some(Self::beacon(agent, AgentCapabilities::read(fields.get(&0x0002)?)?))
+                }
+                AstNode::DomainRef { level: 1, domain_code: CAPABILITIES_REPORT, registry: Some(DIAG1_REGISTRY_ID) } => {
+                    Some(Self::report(meta.source_agent?, AgentCapabilities::read(value)?))
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+fn literal(node: Option<&AstNode>) -> Option<&LiteralValue> {
+    match node? {
+        AstNode::Literal { value, .. } => Some(value),
+        _ => None,
+    }
+}
+
+fn list(node: &AstNode) -> Option<&[AstNode]> {
+    match node {
+        AstNode::List { elements, .. } => Some(elements),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::{DIAG1, NAV1};
+    use crate::AILLDecoder;
+
+    const A: AgentId = AgentId::new([0xA; 16]);
+    const B: AgentId = AgentId::new([0xB; 16]);
+
+    #[test]
+    fn beacons_and_reports_round_trip() {
+        let caps = AgentCapabilities::new(1, 2)
+            .with_builtin_domains()
+            .with_domain(0x40, 3)
+            .with_max_payload(512)
+            .with_audio_profile("acoustic-48k");
+        assert!(caps.supports(NAV1.registry_id) && caps.supports(0x40) && !caps.supports(0x41));
+
+        let decoder = AILLDecoder::new();
+        for sent in [CapabilityReport::beacon(A, caps.clone()), CapabilityReport::report(A, caps.clone())] {
+            let ast = decoder.decode_utterance(&sent.encode(Some(B))).unwrap();
+            assert_eq!(CapabilityReport::decode(&ast), Some(sent));
+        }
+        let empty = CapabilityReport::report(A, AgentCapabilities::new(0, 0));
+        assert_eq!(CapabilityReport::decode(&decoder.decode_utterance(&empty.encode(None)).unwrap()), Some(empty));
+    }
+
+    #[test]
+    fn other_codes_are_not_reports() {
+        // CAPABILITIES_REPORT's code without the DIAG-1 binding is COMM-1 THANK
+        let mut e = AILLEncoder::new();
+        e.start_utterance().source_agent(A).assert_().l1_ref(CAPABILITIES_REPORT);
+        AgentCapabilities::new(0, 0).write(&mut e);
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        assert_eq!(CapabilityReport::decode(&ast), None);
+
+        let report = CapabilityReport::report(A, AgentCapabilities::new(0, 0).with_domain(DIAG1.registry_id, 1));
+        let bound = AILLDecoder::with_config(crate::DecoderConfig::new().bind_escape(1, DIAG1.registry_id));
+        assert!(CapabilityReport::decode(&bound.decode_utterance(&report.encode(None)).unwrap()).is_some());
+    }
+}
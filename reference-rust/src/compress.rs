@@ -0,0 +1,350 @@
+//! Meta header compression.
+//!
+//! Consecutive utterances from one agent repeat most of their meta header:
+//! confidence, priority, source agent, topic. Once both ends agree, a
+//! [`HeaderCompressor`] sends a header in full once, stored as a numbered
+//! context, and afterwards only the context number and the fields that
+//! changed:
+//!
+//! ```text
+//! CONTEXT_REF <varint ctx> 0xFF <utterance>                       store header as ctx
+//! CONTEXT_REF <varint ctx> <u8 n> (<meta code> <operand>)*n <crc8> <body> END_UTTERANCE
+//! ```
+//!
+//! Fields are identified by their meta opcode; a context matches a header
+//! with the same fields in the same order. The CRC-8 covers the
+//! reconstructed START_UTTERANCE and header, so a [`HeaderDecompressor`]
+//! whose context went out of step reports a mismatch instead of decoding a
+//! wrong header. Headers that repeat a field are sent uncompressed, and
+//! plain utterances may be mixed in freely.
+//!
+//! Compressed utterances travel in epochs flagged
+//! [`COMPRESSED`](crate::wire::epoch_flags::COMPRESSED) that hold whole
+//! utterances. That flag is the negotiation: a receiver that does not
+//! support it NACKs such epochs with
+//! [`UnsupportedVersion`](crate::reliability::RejectReason::UnsupportedVersion),
+//! and the sender falls back to plain utterances. Both halves serialize
+//! with the [`SessionState`](crate::session::SessionState), since their
+//! contexts are shared state of the link.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::codebook::base::{fc, meta};
+use crate::decoder::{AILLDecoder, Prefix};
+use crate::error::AILLError;
+use crate::wire::{crc8, ByteReader, ByteWriter};
+
+/// Contexts kept by default, the shared context table size of a level 1
+/// session.
+pub const DEFAULT_MAX_CONTEXTS: usize = 64;
+
+/// Mode byte announcing a full utterance whose header becomes the context.
+const FULL_HEADER: u8 = 0xFF;
+
+/// One meta field: opcode and operand bytes.
+type Field = (u8, Vec<u8>);
+
+/// Operand width of a meta opcode that can appear in a header.
+fn operand_width(code: u8) -> Option<usize> {
+    Some(match code {
+        meta::CONFIDENCE | meta::TOPIC | meta::TTL | meta::COST => 2,
+        meta::PRIORITY => 1,
+        meta::SOURCE_AGENT | meta::DEST_AGENT => 16,
+        meta::TIMESTAMP_META | meta::TRACE_ID => 8,
+        meta::SEQNUM | meta::VERSION_TAG => 4,
+        _ => return None,
+    })
+}
+
+/// Split an utterance into its header fields and the offset of its body.
+fn parse_header(utterance: &[u8]) -> Result<(Vec<Field>, usize), AILLError> {
+    let mut r = ByteReader::new(utterance);
+    let code = r.read_u8()?;
+    if code != fc::START_UTTERANCE {
+        return Err(AILLError::InvalidStructure(format!("Expected START_UTTERANCE (0x00), got 0x{:02X}", code)));
+    }
+    let mut fields = Vec::new();
+    // The mandatory fields come first, then optional ones from 0x92 up
+    for expected in [meta::CONFIDENCE, meta::PRIORITY, meta::TIMESTAMP_META] {
+        if r.peek()? != expected {
+            return Err(AILLError::InvalidStructure(format!("Expected meta field 0x{:02X}", expected)));
+        }
+        fields.push(read_field(&mut r)?);
+    }
+    while let Ok(code) = r.peek() {
+        if code < meta::SOURCE_AGENT || operand_width(code).is_none() {
+            break;
+        }
+        fields.push(read_field(&mut r)?);
+    }
+    Ok((fields, r.pos()))
+}
+
+fn read_field(r: &mut ByteReader) -> Result<Field, AILLError> {
+    let code = r.read_u8()?;
+    let width = operand_width(code)
+        .ok_or_else(|| AILLError::InvalidStructure(format!("0x{:02X} is not a header field", code)))?;
+    Ok((code, r.read_n_bytes(width)?))
+}
+
+fn write_header(fields: &[Field]) -> Vec<u8> {
+    let mut w = ByteWriter::new();
+    w.write_u8(fc::START_UTTERANCE);
+    for (code, operand) in fields {
+        w.write_u8(*code).write_raw(operand);
+    }
+    w.into_bytes()
+}
+
+/// Sending half of header compression; see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct HeaderCompressor {
+    max_contexts: usize,
+    contexts: Vec<Vec<Field>>,
+    /// Slot to reuse once the table is full.
+    next: usize,
+}
+
+impl Default for HeaderCompressor {
+    fn default() -> Self {
+        Self { max_contexts: DEFAULT_MAX_CONTEXTS, contexts: Vec::new(), next: 0 }
+    }
+}
+
+impl HeaderCompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep at most `max_contexts` contexts (at least one); must not
+    /// exceed the peer's.
+    pub fn with_max_contexts(mut self, max_contexts: usize) -> Self {
+        self.max_contexts = max_contexts.max(1);
+        self
+    }
+
+    /// Forget every context, so each header is next sent in full, e.g.
+    /// after the peer's decompressor reported a CRC mismatch.
+    pub fn reset(&mut self) {
+        self.contexts.clear();
+        self.next = 0;
+    }
+
+    /// Compress one complete utterance.
+    pub fn compress(&mut self, utterance: &[u8]) -> Result<Vec<u8>, AILLError> {
+        let (fields, body) = parse_header(utterance)?;
+        let mut codes: Vec<u8> = fields.iter().map(|(code, _)| *code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        if codes.len() < fields.len() {
+            return Ok(utterance.to_vec());
+        }
+
+        let best = self
+            .contexts
+            .iter()
+            .enumerate()
+            .filter(|(_, ctx)| ctx.len() == fields.len() && ctx.iter().zip(&fields).all(|(a, b)| a.0 == b.0))
+            .map(|(i, ctx)| (i, ctx.iter().zip(&fields).filter(|(a, b)| a != b).count()))
+            .min_by_key(|&(_, changed)| changed);
+        let mut w = ByteWriter::new();
+        w.write_u8(meta::CONTEXT_REF);
+        let Some((ctx, changed)) = best else {
+            let ctx = match self.contexts.len() < self.max_contexts {
+                true => {
+                    self.contexts.push(fields);
+                    self.contexts.len() - 1
+                }
+                false => {
+                    let ctx = self.next;
+                    self.next = (self.next + 1) % self.max_contexts;
+                    self.contexts[ctx] = fields;
+                    ctx
+                }
+            };
+            w.write_varint(ctx as u32).write_u8(FULL_HEADER).write_raw(utterance);
+            return Ok(w.into_bytes());
+        };
+        w.write_varint(ctx as u32).write_u8(changed as u8);
+        for (old, new) in self.contexts[ctx].iter_mut().zip(&fields) {
+            if old != new {
+                w.write_u8(new.0).write_raw(&new.1);
+                old.1.clone_from(&new.1);
+            }
+        }
+        w.write_u8(crc8(&utterance[..body])).write_raw(&utterance[body..]);
+        Ok(w.into_bytes())
+    }
+}
+
+/// Receiving half of header compression; see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct HeaderDecompressor {
+    max_contexts: usize,
+    contexts: BTreeMap<u32, Vec<Field>>,
+}
+
+impl Default for HeaderDecompressor {
+    fn default() -> Self {
+        Self { max_contexts: DEFAULT_MAX_CONTEXTS, contexts: BTreeMap::new() }
+    }
+}
+
+impl HeaderDecompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept context numbers below `max_contexts`.
+    pub fn with_max_contexts(mut self, max_contexts: usize) -> Self {
+        self.max_contexts = max_contexts.max(1);
+        self
+    }
+
+    /// Expand the payload of a COMPRESSED epoch into plain utterances,
+    /// decoding bodies with `decoder`'s configuration. A context that
+    /// fails its CRC is dropped.
+    pub fn decompress(&mut self, decoder: &AILLDecoder, payload: &[u8]) -> Result<Vec<u8>, AILLError> {
+        let mut out = Vec::with_capacity(payload.len() * 2);
+        let mut pos = 0;
+        while pos < payload.len() {
+            if payload[pos] == fc::START_UTTERANCE {
+                let len = utterance_len(decoder, &payload[pos..])?;
+                out.extend_from_slice(&payload[pos..pos + len]);
+                pos += len;
+                continue;
+            }
+            let mut r = ByteReader::new(&payload[pos..]);
+            let code = r.read_u8()?;
+            if code != meta::CONTEXT_REF {
+                return Err(AILLError::InvalidStructure(format!(
+                    "Expected START_UTTERANCE or CONTEXT_REF, got 0x{:02X}",
+                    code
+                )));
+            }
+            let ctx = r.read_varint()?;
+            if ctx as usize >= self.max_contexts {
+                return Err(AILLError::InvalidStructure(format!("header context {} out of range", ctx)));
+            }
+            let mode = r.read_u8()?;
+            pos += r.pos();
+            if mode == FULL_HEADER {
+                let len = utterance_len(decoder, &payload[pos..])?;
+                let (fields, _) = parse_header(&payload[pos..pos + len])?;
+                self.contexts.insert(ctx, fields);
+                out.extend_from_slice(&payload[pos..pos + len]);
+                pos += len;
+                continue;
+            }
+
+            let mut fields = self
+                .contexts
+                .get(&ctx)
+                .cloned()
+                .ok_or_else(|| AILLError::InvalidStructure(format!("unknown header context {}", ctx)))?;
+            let mut r = ByteReader::new(&payload[pos..]);
+            for _ in 0..mode {
+                let (code, operand) = read_field(&mut r)?;
+                let field = fields.iter_mut().find(|(c, _)| *c == code).ok_or_else(|| {
+                    AILLError::InvalidStructure(format!("header context {} has no field 0x{:02X}", ctx, code))
+                })?;
+                field.1 = operand;
+            }
+            let header = write_header(&fields);
+            let expected = r.read_u8()?;
+            let actual = crc8(&header);
+            if expected != actual {
+                self.contexts.remove(&ctx);
+                return Err(AILLError::CrcMismatch { expected, actual });
+            }
+            pos += r.pos();
+            let mut utterance = header;
+            let header_len = utterance.len();
+            utterance.extend_from_slice(&payload[pos..]);
+            let len = utterance_len(decoder, &utterance)?;
+            utterance.truncate(len);
+            out.extend(utterance);
+            pos += len - header_len;
+            self.contexts.insert(ctx, fields);
+        }
+        Ok(out)
+    }
+}
+
+/// Length of the complete utterance at the start of `data`.
+fn utterance_len(decoder: &AILLDecoder, data: &[u8]) -> Result<usize, AILLError> {
+    match decoder.decode_prefix(data)? {
+        Prefix::Complete(_, used) | Prefix::Aborted(_, used) => Ok(used),
+        Prefix::Truncated => Err(AILLError::InvalidStructure("compressed epoch ends inside an utterance".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentId;
+    use crate::encoder::AILLEncoder;
+
+    fn report(seq: u32, value: u8) -> Vec<u8> {
+        let mut e = AILLEncoder::with_uuid(AgentId::new([7; 16]));
+        e.start_utterance_with(0.9, 3, Some(1_000_000 + seq as i64 * 500_000), None, Some(seq));
+        e.source_agent(AgentId::new([7; 16])).topic(0x0200);
+        e.assert_().uint8(value);
+        e.end_utterance()
+    }
+
+    #[test]
+    fn repeated_headers_shrink_and_expand_exactly() {
+        let mut tx = HeaderCompressor::new();
+        let mut rx = HeaderDecompressor::new();
+        let decoder = AILLDecoder::new();
+        let plain: Vec<_> = (0..10).map(|i| report(i, i as u8)).collect();
+        let compressed: Vec<_> = plain.iter().map(|u| tx.compress(u).unwrap()).collect();
+        assert_eq!(compressed[0].len(), plain[0].len() + 3);
+        // Only TIMESTAMP and SEQNUM change: 18 bytes of header instead of 40
+        assert_eq!(compressed[1].len() + 22, plain[1].len());
+
+        // Several utterances per payload, plain ones mixed in
+        let mut payload = compressed.concat();
+        payload.extend(&plain[0]);
+        let mut expected = plain.concat();
+        expected.extend(&plain[0]);
+        assert_eq!(rx.decompress(&decoder, &payload).unwrap(), expected);
+
+        // The contexts survive a session save
+        #[cfg(feature = "ast-serde")]
+        let (mut tx, mut rx): (HeaderCompressor, HeaderDecompressor) =
+            serde_json::from_str(&serde_json::to_string(&(&tx, &rx)).unwrap()).unwrap();
+        let next = tx.compress(&report(10, 10)).unwrap();
+        assert_eq!(rx.decompress(&decoder, &next).unwrap(), report(10, 10));
+    }
+
+    #[test]
+    fn out_of_step_context_fails_its_crc() {
+        let mut tx = HeaderCompressor::new().with_max_contexts(1);
+        let mut rx = HeaderDecompressor::new();
+        let decoder = AILLDecoder::new();
+        rx.decompress(&decoder, &tx.compress(&report(0, 0)).unwrap()).unwrap();
+        // Lost in transit
+        tx.compress(&report(1, 1)).unwrap();
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(0.9, 3, Some(1_500_000), None, Some(1));
+        e.source_agent(AgentId::new([7; 16])).topic(0x0200).assert_().uint8(2);
+        let third = tx.compress(&e.end_utterance()).unwrap();
+        assert!(matches!(rx.decompress(&decoder, &third), Err(AILLError::CrcMismatch { .. })));
+        assert!(rx.decompress(&decoder, &third).is_err());
+
+        // A different header shape takes over the only slot
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().uint8(3);
+        let other = e.end_utterance();
+        let full = tx.compress(&other).unwrap();
+        assert_eq!(full[..3], [meta::CONTEXT_REF, 0, FULL_HEADER]);
+        assert_eq!(rx.decompress(&decoder, &full).unwrap(), other);
+    }
+}
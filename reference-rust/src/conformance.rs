@@ -0,0 +1,309 @@
+//! Test vectors shared with other AILL implementations.
+//!
+//! A vector is a pair of files in one directory: `<name>.hex` holds the
+//! wire bytes as hex (whitespace ignored) and `<name>.json` what decoding
+//! them must give, in the AST's serde JSON form. A vector the decoder must
+//! reject has `{"error": true}` as its JSON instead.
+//!
+//! Each vector is checked both ways: decoding the bytes must give the
+//! expected AST, and encoding the expected AST with [`encode_ast`] must
+//! give the bytes back. Numbers are compared with a small relative
+//! tolerance, since implementations print floats with differing precision.
+//! A [`Report`] renders the results as JSON or JUnit XML; see the
+//! `aill-conformance` binary.
+
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ast::AstNode;
+use crate::decoder::AILLDecoder;
+use crate::error::AILLError;
+use crate::templates::encode_ast;
+
+/// Relative difference below which two JSON numbers count as equal.
+const NUMBER_TOLERANCE: f64 = 1e-6;
+
+/// What a vector's wire bytes must decode to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    /// The AST, as serde JSON.
+    Ast(Value),
+    /// The decoder must reject the bytes.
+    Error,
+}
+
+/// One wire message and its expected decoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+    pub name: String,
+    pub wire: Vec<u8>,
+    pub expected: Expected,
+}
+
+/// Which direction a check exercised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Check {
+    Decode,
+    Encode,
+}
+
+/// Outcome of one check of one vector.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaseResult {
+    pub vector: String,
+    pub check: Check,
+    /// Why the check failed; `None` if it passed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+/// Results of a conformance run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub results: Vec<CaseResult>,
+}
+
+/// Load every vector in `dir`, sorted by name. Fails on a `.hex` file
+/// without its `.json`, or on malformed hex or JSON.
+pub fn load_vectors(dir: impl AsRef<Path>) -> Result<Vec<TestVector>, AILLError> {
+    let dir = dir.as_ref();
+    let read_err = |path: &Path, e: std::io::Error| {
+        AILLError::InvalidStructure(format!("Failed to read {}: {}", path.display(), e))
+    };
+    let mut vectors = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| read_err(dir, e))? {
+        let hex_path = entry.map_err(|e| read_err(dir, e))?.path();
+        if hex_path.extension().and_then(|e| e.to_str()) != Some("hex") {
+            continue;
+        }
+        let name = hex_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let hex = std::fs::read_to_string(&hex_path).map_err(|e| read_err(&hex_path, e))?;
+        let wire = parse_hex(&hex)
+            .map_err(|e| AILLError::InvalidStructure(format!("{}: {}", hex_path.display(), e)))?;
+
+        let json_path = hex_path.with_extension("json");
+        let json = std::fs::read_to_string(&json_path).map_err(|e| read_err(&json_path, e))?;
+        let value: Value = serde_json::from_str(&json)
+            .map_err(|e| AILLError::InvalidStructure(format!("{}: {}", json_path.display(), e)))?;
+        let expected = match value.get("error") {
+            Some(Value::Bool(true)) => Expected::Error,
+            _ => Expected::Ast(value),
+        };
+        vectors.push(TestVector { name, wire, expected });
+    }
+    vectors.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(vectors)
+}
+
+/// Check `vectors` against `decoder` and the reference encoder.
+pub fn run(vectors: &[TestVector], decoder: &AILLDecoder) -> Report {
+    Report { results: vectors.iter().flat_map(|v| run_vector(v, decoder)).collect() }
+}
+
+/// Check one vector: a decode check, plus an encode check unless the
+/// bytes are meant to be rejected.
+pub fn run_vector(vector: &TestVector, decoder: &AILLDecoder) -> Vec<CaseResult> {
+    let result = |check, failure| CaseResult { vector: vector.name.clone(), check, failure };
+    let decoded = decoder.decode_utterance(&vector.wire);
+    let expected = match &vector.expected {
+        Expected::Error => {
+            let failure = decoded.ok().map(|_| "decoded bytes that must be rejected".to_string());
+            return vec![result(Check::Decode, failure)];
+        }
+        Expected::Ast(expected) => expected,
+    };
+
+    let decode_failure = match decoded {
+        Ok(ast) => match serde_json::to_value(&ast) {
+            Ok(actual) => first_difference(&actual, expected, "$"),
+            Err(e) => Some(format!("AST does not serialize: {}", e)),
+        },
+        Err(e) => Some(format!("decode failed: {}", e)),
+    };
+    let encode_failure = match serde_json::from_value::<AstNode>(expected.clone()) {
+        Ok(ast) => match encode_ast(&ast) {
+            Ok(wire) if wire == vector.wire => None,
+            Ok(wire) => Some(format!("encoded {}, expected {}", to_hex(&wire), to_hex(&vector.wire))),
+            Err(e) => Some(format!("encode failed: {}", e)),
+        },
+        Err(e) => Some(format!("expected JSON is not an AST: {}", e)),
+    };
+    vec![result(Check::Decode, decode_failure), result(Check::Encode, encode_failure)]
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.failure.is_none()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results.iter().filter(|r| r.failure.is_some())
+    }
+
+    /// `{"total", "passed", "failed", "results": [...]}`
+    pub fn to_json(&self) -> String {
+        let report = serde_json::json!({
+            "total": self.results.len(),
+            "passed": self.passed(),
+            "failed": self.failed(),
+            "results": self.results,
+        });
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    }
+
+    /// A JUnit XML `<testsuite>` named `suite`, one `<testcase>` per check.
+    pub fn to_junit(&self, suite: &str) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+            xml_escape(suite),
+            self.results.len(),
+            self.failed()
+        ));
+        for r in &self.results {
+            let check = match r.check {
+                Check::Decode => "decode",
+                Check::Encode => "encode",
+            };
+            let open = format!(
+                "  <testcase classname=\"{}.{}\" name=\"{}\"",
+                xml_escape(suite),
+                check,
+                xml_escape(&r.vector)
+            );
+            match &r.failure {
+                None => xml.push_str(&format!("{}/>\n", open)),
+                Some(why) => xml.push_str(&format!(
+                    "{}>\n    <failure message=\"{}\"/>\n  </testcase>\n",
+                    open,
+                    xml_escape(why)
+                )),
+            }
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+}
+
+/// Where `actual` first differs from `expected`, as a JSON path. An absent
+/// key matches `null`.
+fn first_difference(actual: &Value, expected: &Value, path: &str) -> Option<String> {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => {
+            let same = match (a.as_i64(), b.as_i64()) {
+                (Some(x), Some(y)) => x == y,
+                _ => match (a.as_f64(), b.as_f64()) {
+                    (Some(x), Some(y)) => (x - y).abs() <= NUMBER_TOLERANCE * x.abs().max(y.abs()).max(1.0),
+                    _ => a == b,
+                },
+            };
+            (!same).then(|| format!("{}: expected {}, got {}", path, b, a))
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            if a.len() != b.len() {
+                return Some(format!("{}: expected {} elements, got {}", path, b.len(), a.len()));
+            }
+            a.iter().zip(b).enumerate().find_map(|(i, (x, y))| first_difference(x, y, &format!("{}[{}]", path, i)))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let x = a.get(key).unwrap_or(&Value::Null);
+                let y = b.get(key).unwrap_or(&Value::Null);
+                first_difference(x, y, &format!("{}.{}", path, key))
+            })
+        }
+        _ => (actual != expected).then(|| format!("{}: expected {}, got {}", path, expected, actual)),
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.split_whitespace().collect();
+    let digits = digits.trim_start_matches("0x").trim_start_matches("0X");
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", digits.len()));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| format!("invalid hex at {}: {}", i, e))
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::AILLEncoder;
+
+    #[test]
+    fn vectors_checked_both_ways() {
+        let dir = std::env::temp_dir().join("aill_conformance_vectors");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().float32(1.1);
+        let wire = e.end_utterance();
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        // Written as another implementation would print the float
+        let json = serde_json::to_string(&ast).unwrap().replace("1.100000023841858", "1.1");
+        std::fs::write(dir.join("a_float.hex"), to_hex(&wire)).unwrap();
+        std::fs::write(dir.join("a_float.json"), json).unwrap();
+        std::fs::write(dir.join("b_truncated.hex"), to_hex(&wire[..wire.len() - 2])).unwrap();
+        std::fs::write(dir.join("b_truncated.json"), r#"{"error": true}"#).unwrap();
+        let mut changed = serde_json::to_value(&ast).unwrap();
+        changed["meta"]["priority"] = 5.into();
+        std::fs::write(dir.join("c_wrong.hex"), to_hex(&wire)).unwrap();
+        std::fs::write(dir.join("c_wrong.json"), changed.to_string()).unwrap();
+
+        let vectors = load_vectors(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(vectors.len(), 3);
+
+        let report = run(&vectors, &AILLDecoder::new());
+        let failures: Vec<_> = report.failures().map(|r| (r.vector.as_str(), r.check)).collect();
+        assert_eq!(failures, [("c_wrong", Check::Decode), ("c_wrong", Check::Encode)]);
+        let why = report.failures().next().unwrap().failure.as_deref().unwrap();
+        assert!(why.starts_with("$.meta.priority: expected 5, got 3"), "{}", why);
+
+        let junit = report.to_junit("aill");
+        assert!(junit.contains("<testsuite name=\"aill\" tests=\"5\" failures=\"2\">"));
+        let json: Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["passed"], 3);
+    }
+
+    #[test]
+    fn struct_vector_encodes_from_json() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().begin_struct().field(1).int32(7).field(300).string("x").end_struct();
+        let wire = e.end_utterance();
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        let json = serde_json::to_value(&ast).unwrap();
+        assert!(json.to_string().contains(r#""fields":{"1":"#), "{}", json);
+        let vector = TestVector { name: "a_struct".into(), wire, expected: Expected::Ast(json) };
+
+        let report = run(&[vector], &AILLDecoder::new());
+        assert_eq!(report.failures().count(), 0, "{:?}", report.results);
+        assert_eq!(report.passed(), 2);
+    }
+}
@@ -0,0 +1,317 @@
+//! CORRECT / CLARIFY act semantics.
+//!
+//! Both acts wrap a two-field struct that points back at an earlier
+//! utterance:
+//!
+//! ```text
+//! CORRECT STRUCT { 0x0000: target, 0x0001: replacement }
+//! CLARIFY STRUCT { 0x0000: target, 0x0001: question }
+//! ```
+//!
+//! The target is `HASH_REF <uint32>` (first 32 bits of the SHA-256 of the
+//! utterance's wire bytes) or a `uint32` literal holding its SEQNUM. A
+//! CORRECT replacement is either a patch map as built by
+//! [`AILLEncoder::correct_values`](crate::AILLEncoder::correct_values),
+//! which replaces individual literals, or any other expression, which
+//! replaces the target's whole body.
+
+use std::collections::VecDeque;
+
+use sha2::{Digest, Sha256};
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::base::pragma;
+use crate::decoder::AILLDecoder;
+use crate::delta::{apply_patch, parse_patch_map};
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+
+/// Struct field carrying the referenced utterance.
+pub const TARGET_FIELD: u16 = 0x0000;
+/// Struct field carrying the correction or question.
+pub const PAYLOAD_FIELD: u16 = 0x0001;
+
+/// Reference to a previously sent utterance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UtteranceRef {
+    /// Content hash, see [`utterance_hash`].
+    Hash(u32),
+    /// The utterance's SEQNUM meta field.
+    Seqnum(u32),
+}
+
+/// HASH_REF value for an utterance: the first 32 bits of the SHA-256 of its
+/// wire bytes, big-endian.
+pub fn utterance_hash(wire: &[u8]) -> u32 {
+    let digest = Sha256::digest(wire);
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// A CORRECT or CLARIFY act found in an utterance body.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReferenceAct<'a> {
+    /// `"CORRECT"` or `"CLARIFY"`.
+    pub act: &'a str,
+    pub target: UtteranceRef,
+    pub payload: &'a AstNode,
+}
+
+/// Recognise a body consisting of a single CORRECT or CLARIFY act in the
+/// structured form described in the module docs.
+pub fn parse_reference_act(body: &[AstNode]) -> Option<ReferenceAct<'_>> {
+    let [AstNode::Pragmatic { act, expression }] = body else {
+        return None;
+    };
+    if act != "CORRECT" && act != "CLARIFY" {
+        return None;
+    }
+    let AstNode::Struct { fields } = expression.as_ref() else {
+        return None;
+    };
+    let target = match fields.get(&TARGET_FIELD)? {
+        AstNode::HashRef { hash } => UtteranceRef::Hash(*hash),
+        AstNode::Literal { value: LiteralValue::Uint32(n), .. } => UtteranceRef::Seqnum(*n),
+        _ => return None,
+    };
+    let payload = fields.get(&PAYLOAD_FIELD)?;
+    Some(ReferenceAct { act, target, payload })
+}
+
+/// Outcome of [`Conversation::receive`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Received {
+    /// An ordinary utterance, now stored under `hash`.
+    Utterance { hash: u32 },
+    /// A stored utterance was corrected in place.
+    Corrected { target: UtteranceRef },
+    /// The peer asked for clarification of `target`.
+    Clarify { target: UtteranceRef, question: AstNode },
+}
+
+struct Entry {
+    hash: u32,
+    ast: AstNode,
+}
+
+/// Decode-side history of recent utterances that applies incoming
+/// corrections to the stored copies.
+pub struct Conversation {
+    history: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl Conversation {
+    /// Keep at most `capacity` utterances; the oldest are dropped first.
+    pub fn new(capacity: usize) -> Self {
+        Self { history: VecDeque::new(), capacity }
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// The stored (possibly corrected) utterance for `target`.
+    pub fn get(&self, target: UtteranceRef) -> Option<&AstNode> {
+        self.find(target).map(|i| &self.history[i].ast)
+    }
+
+    /// Decode an utterance and update the history.
+    pub fn receive(&mut self, wire: &[u8]) -> Result<Received, AILLError> {
+        let ast = AILLDecoder::new().decode_utterance(wire)?;
+        let AstNode::Utterance { body, .. } = &ast else {
+            unreachable!("decode_utterance always returns an utterance");
+        };
+
+        match parse_reference_act(body) {
+            Some(ReferenceAct { act: "CLARIFY", target, payload }) => Ok(Received::Clarify {
+                target,
+                question: payload.clone(),
+            }),
+            Some(ReferenceAct { target, payload, .. }) => {
+                self.apply_correction(target, payload)?;
+                Ok(Received::Corrected { target })
+            }
+            None => {
+                let hash = utterance_hash(wire);
+                if self.capacity > 0 && self.history.len() == self.capacity {
+                    self.history.pop_front();
+                }
+                if self.capacity > 0 {
+                    self.history.push_back(Entry { hash, ast });
+                }
+                Ok(Received::Utterance { hash })
+            }
+        }
+    }
+
+    fn find(&self, target: UtteranceRef) -> Option<usize> {
+        self.history.iter().rposition(|e| match target {
+            UtteranceRef::Hash(h) => e.hash == h,
+            UtteranceRef::Seqnum(n) => {
+                matches!(&e.ast, AstNode::Utterance { meta, .. } if meta.seqnum == Some(n))
+            }
+        })
+    }
+
+    fn apply_correction(&mut self, target: UtteranceRef, payload: &AstNode) -> Result<(), AILLError> {
+        let idx = self.find(target).ok_or_else(|| {
+            AILLError::InvalidStructure(format!("CORRECT references unknown utterance {:?}", target))
+        })?;
+        let AstNode::Utterance { body, .. } = &mut self.history[idx].ast else {
+            unreachable!();
+        };
+        match parse_patch_map(payload)? {
+            Some(patches) => {
+                // All or nothing: a bad patch leaves the utterance as it was
+                let mut patched = body.clone();
+                for (path, value) in patches {
+                    apply_patch(&mut patched, &path, value)?;
+                }
+                *body = patched;
+            }
+            None => *body = vec![payload.clone()],
+        }
+        Ok(())
+    }
+}
+
+impl AILLEncoder {
+    /// Emit a reference to a prior utterance: HASH_REF for a content hash,
+    /// a UINT32 literal for a SEQNUM.
+    pub fn utterance_ref(&mut self, target: UtteranceRef) -> &mut Self {
+        match target {
+            UtteranceRef::Hash(h) => self.hash_ref(h),
+            UtteranceRef::Seqnum(n) => self.uint32(n),
+        }
+    }
+
+    /// Begin CORRECT { target, payload }. Encode the replacement expression
+    /// next, then close with `end_struct()`.
+    pub fn correct(&mut self, target: UtteranceRef) -> &mut Self {
+        self.op(pragma::CORRECT);
+        self.begin_struct().field(TARGET_FIELD).utterance_ref(target).field(PAYLOAD_FIELD)
+    }
+
+    /// Begin CLARIFY { target, question }. Encode the question next, then
+    /// close with `end_struct()`.
+    pub fn clarify(&mut self, target: UtteranceRef) -> &mut Self {
+        self.op(pragma::CLARIFY);
+        self.begin_struct().field(TARGET_FIELD).utterance_ref(target).field(PAYLOAD_FIELD)
+    }
+
+    /// Emit a complete CORRECT whose payload replaces literals in the target
+    /// utterance. Each path starts with the body index, followed by struct
+    /// field ids or list/map indices.
+    pub fn correct_values(&mut self, target: UtteranceRef, patches: &[(&[u16], LiteralValue)]) -> &mut Self {
+        self.correct(target);
+        self.begin_map(patches.len() as u16);
+        for (path, value) in patches {
+            self.begin_list(path.len() as u16);
+            for &step in path.iter() {
+                self.uint16(step);
+            }
+            self.end_list();
+            self.literal(value);
+        }
+        self.end_map().end_struct()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLEncoder;
+
+    fn reading(seq: u32, temp: f32) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, None, Some(seq)).assert_();
+        e.begin_struct().field(0x0010).float32(temp).end_struct();
+        e.end_utterance()
+    }
+
+    fn temp_of(ast: &AstNode) -> &AstNode {
+        let AstNode::Utterance { body, .. } = ast else { panic!() };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        let AstNode::Struct { fields } = expression.as_ref() else { panic!() };
+        &fields[&0x0010]
+    }
+
+    #[test]
+    fn correct_by_hash_patches_literal() {
+        let mut conv = Conversation::new(8);
+        let wire = reading(1, 20.0);
+        let Received::Utterance { hash } = conv.receive(&wire).unwrap() else { panic!() };
+        assert_eq!(hash, utterance_hash(&wire));
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance()
+            .correct_values(UtteranceRef::Hash(hash), &[(&[0, 0x0010], LiteralValue::Float32(21.5))]);
+        let target = UtteranceRef::Hash(hash);
+        assert_eq!(conv.receive(&e.end_utterance()).unwrap(), Received::Corrected { target });
+        assert_eq!(
+            temp_of(conv.get(target).unwrap()),
+            &AstNode::Literal { value_type: "float32".into(), value: LiteralValue::Float32(21.5) }
+        );
+    }
+
+    #[test]
+    fn failed_correction_leaves_the_utterance_alone() {
+        let mut conv = Conversation::new(8);
+        let Received::Utterance { hash } = conv.receive(&reading(1, 20.0)).unwrap() else { panic!() };
+        let target = UtteranceRef::Hash(hash);
+        let before = conv.get(target).unwrap().clone();
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().correct_values(
+            target,
+            &[(&[0, 0x0010], LiteralValue::Float32(21.5)), (&[0, 0x0099], LiteralValue::Float32(0.0))],
+        );
+        assert!(conv.receive(&e.end_utterance()).is_err());
+        assert_eq!(conv.get(target), Some(&before));
+    }
+
+    #[test]
+    fn correct_by_seqnum_replaces_body() {
+        let mut conv = Conversation::new(8);
+        conv.receive(&reading(7, 20.0)).unwrap();
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().correct(UtteranceRef::Seqnum(7)).string("sensor offline").end_struct();
+        conv.receive(&e.end_utterance()).unwrap();
+
+        let AstNode::Utterance { body, .. } = conv.get(UtteranceRef::Seqnum(7)).unwrap() else { panic!() };
+        assert_eq!(
+            body,
+            &vec![AstNode::Literal { value_type: "string".into(), value: LiteralValue::String("sensor offline".into()) }]
+        );
+    }
+
+    #[test]
+    fn clarify_is_reported_not_stored() {
+        let mut conv = Conversation::new(8);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().clarify(UtteranceRef::Hash(0xDEADBEEF)).string("which sensor?").end_struct();
+        match conv.receive(&e.end_utterance()).unwrap() {
+            Received::Clarify { target, .. } => assert_eq!(target, UtteranceRef::Hash(0xDEADBEEF)),
+            other => panic!("expected clarify, got {:?}", other),
+        }
+        assert!(conv.is_empty());
+    }
+
+    #[test]
+    fn unknown_target_and_eviction() {
+        let mut conv = Conversation::new(1);
+        conv.receive(&reading(1, 1.0)).unwrap();
+        conv.receive(&reading(2, 2.0)).unwrap();
+        assert_eq!(conv.len(), 1);
+        assert!(conv.get(UtteranceRef::Seqnum(1)).is_none());
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().correct(UtteranceRef::Seqnum(1)).null().end_struct();
+        assert!(conv.receive(&e.end_utterance()).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! Request/response correlation via COMM-1 MSG_ID / REPLY_TO.
+//!
+//! Requests carry `ESCAPE_L1 MSG_ID TYPE_UINT64 <id>` and replies carry
+//! `ESCAPE_L1 REPLY_TO TYPE_UINT64 <id>` at body level, ahead of the
+//! pragmatic act:
+//!
+//! ```
+//! use aill::AILLEncoder;
+//! use aill::correlator::Correlator;
+//!
+//! let mut corr = Correlator::new();
+//! let id = corr.register(0, 500_000);
+//! let mut e = AILLEncoder::new();
+//! e.start_utterance().msg_id(id).query().l1_ref(0x0000);
+//! let _request = e.end_utterance();
+//! ```
+//!
+//! Time is passed in explicitly as microseconds.
+
+use std::collections::HashMap;
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::comm::COMM1_REGISTRY_ID;
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+
+/// COMM-1 MSG_ID entry code.
+pub const MSG_ID: u16 = 0x0027;
+/// COMM-1 REPLY_TO entry code.
+pub const REPLY_TO: u16 = 0x0028;
+
+/// Result delivered to a reply handler.
+pub type ReplyResult = Result<AstNode, AILLError>;
+
+type Callback = Box<dyn FnOnce(ReplyResult) + Send>;
+
+enum Handler {
+    /// Reply is returned from [`Correlator::handle_incoming`].
+    Poll,
+    Callback(Callback),
+    #[cfg(feature = "async")]
+    Future(std::sync::Arc<std::sync::Mutex<reply_future::Shared>>),
+}
+
+impl Handler {
+    fn complete(self, result: ReplyResult) {
+        match self {
+            Handler::Poll => {}
+            Handler::Callback(cb) => cb(result),
+            #[cfg(feature = "async")]
+            Handler::Future(shared) => reply_future::complete(&shared, result),
+        }
+    }
+}
+
+struct Pending {
+    deadline_us: i64,
+    handler: Handler,
+}
+
+/// Outcome of [`Correlator::handle_incoming`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming {
+    /// A reply to a pending request. For callback and future requests the
+    /// reply has already been delivered and `reply` is `None`.
+    Reply { id: u64, reply: Option<AstNode> },
+    /// Not a reply to anything pending (a new request, a late reply, or an
+    /// unrelated utterance).
+    Unmatched(AstNode),
+}
+
+/// Tracks outstanding requests and matches replies to them.
+pub struct Correlator {
+    next_id: u64,
+    pending: HashMap<u64, Pending>,
+}
+
+impl Correlator {
+    pub fn new() -> Self {
+        Self { next_id: 1, pending: HashMap::new() }
+    }
+
+    /// Number of requests still awaiting a reply.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn insert(&mut self, now_us: i64, timeout_us: i64, handler: Handler) -> u64 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.pending.insert(id, Pending { deadline_us: now_us.saturating_add(timeout_us), handler });
+        id
+    }
+
+    /// Assign a MSG_ID for an outgoing request. The reply is returned by
+    /// [`handle_incoming`](Self::handle_incoming).
+    pub fn register(&mut self, now_us: i64, timeout_us: i64) -> u64 {
+        self.insert(now_us, timeout_us, Handler::Poll)
+    }
+
+    /// Assign a MSG_ID and invoke `callback` with the reply, or with
+    /// [`AILLError::Timeout`] once the deadline passes.
+    pub fn register_callback<F>(&mut self, now_us: i64, timeout_us: i64, callback: F) -> u64
+    where
+        F: FnOnce(ReplyResult) + Send + 'static,
+    {
+        self.insert(now_us, timeout_us, Handler::Callback(Box::new(callback)))
+    }
+
+    /// Assign a MSG_ID and return a future resolving to the reply. Encode
+    /// the request with [`ReplyFuture::id`], send it, then `.await`.
+    #[cfg(feature = "async")]
+    pub fn request(&mut self, now_us: i64, timeout_us: i64) -> ReplyFuture {
+        let shared = std::sync::Arc::new(std::sync::Mutex::new(reply_future::Shared::default()));
+        let id = self.insert(now_us, timeout_us, Handler::Future(shared.clone()));
+        ReplyFuture { id, shared }
+    }
+
+    /// Match an incoming utterance against pending requests by REPLY_TO.
+    pub fn handle_incoming(&mut self, ast: AstNode) -> Incoming {
+        let Some(id) = reply_to(&ast) else {
+            return Incoming::Unmatched(ast);
+        };
+        match self.pending.remove(&id) {
+            Some(Pending { handler: Handler::Poll, .. }) => Incoming::Reply { id, reply: Some(ast) },
+            Some(Pending { handler, .. }) => {
+                handler.complete(Ok(ast));
+                Incoming::Reply { id, reply: None }
+            }
+            None => Incoming::Unmatched(ast),
+        }
+    }
+
+    /// Expire requests whose deadline is at or before `now_us`, returning
+    /// their ids. Callbacks and futures receive [`AILLError::Timeout`].
+    pub fn poll_timeouts(&mut self, now_us: i64) -> Vec<u64> {
+        let mut expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| p.deadline_us <= now_us)
+            .map(|(id, _)| *id)
+            .collect();
+        expired.sort_unstable();
+        for id in &expired {
+            if let Some(p) = self.pending.remove(id) {
+                p.handler.complete(Err(AILLError::Timeout(format!("no reply to message {}", id))));
+            }
+        }
+        expired
+    }
+}
+
+impl Default for Correlator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AILLEncoder {
+    /// Emit COMM-1 MSG_ID as `ESCAPE_L1 0x0027 TYPE_UINT64 <id>`.
+    pub fn msg_id(&mut self, id: u64) -> &mut Self {
+        self.l1_ref(MSG_ID).uint64(id)
+    }
+
+    /// Emit COMM-1 REPLY_TO as `ESCAPE_L1 0x0028 TYPE_UINT64 <id>`.
+    pub fn reply_to(&mut self, id: u64) -> &mut Self {
+        self.l1_ref(REPLY_TO).uint64(id)
+    }
+}
+
+/// The MSG_ID tag of an utterance, if present.
+pub fn msg_id(ast: &AstNode) -> Option<u64> {
+    body_tag(ast, MSG_ID)
+}
+
+/// The REPLY_TO tag of an utterance, if present.
+pub fn reply_to(ast: &AstNode) -> Option<u64> {
+    body_tag(ast, REPLY_TO)
+}
+
+fn body_tag(ast: &AstNode, code: u16) -> Option<u64> {
+    match ast {
+        AstNode::Utterance { body, .. } => uint64_tag(body, code),
+        _ => None,
+    }
+}
+
+/// The value of a COMM-1 `ESCAPE_L1 code TYPE_UINT64 <value>` tag among
+/// the leading body items.
+pub(crate) fn uint64_tag(body: &[AstNode], code: u16) -> Option<u64> {
+    let mut i = 0;
+    while let Some(AstNode::DomainRef { level: 1, domain_code, registry }) = body.get(i) {
+        match body.get(i + 1) {
+            Some(AstNode::Literal { value: LiteralValue::Uint64(id), .. })
+                if *domain_code == code && registry.is_none_or(|r| r == COMM1_REGISTRY_ID) =>
+            {
+                return Some(*id);
+            }
+            Some(AstNode::DomainRef { .. }) | None => i += 1,
+            _ => i += 2,
+        }
+    }
+    None
+}
+
+#[cfg(feature = "async")]
+pub use reply_future::ReplyFuture;
+
+#[cfg(feature = "async")]
+mod reply_future {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+
+    use super::ReplyResult;
+
+    #[derive(Default)]
+    pub(super) struct Shared {
+        result: Option<ReplyResult>,
+        waker: Option<Waker>,
+    }
+
+    pub(super) fn complete(shared: &Mutex<Shared>, result: ReplyResult) {
+        let mut s = shared.lock().unwrap();
+        s.result = Some(result);
+        if let Some(w) = s.waker.take() {
+            w.wake();
+        }
+    }
+
+    /// Future returned by [`Correlator::request`](super::Correlator::request).
+    pub struct ReplyFuture {
+        pub(super) id: u64,
+        pub(super) shared: Arc<Mutex<Shared>>,
+    }
+
+    impl ReplyFuture {
+        /// MSG_ID to put on the outgoing request.
+        pub fn id(&self) -> u64 {
+            self.id
+        }
+    }
+
+    impl Future for ReplyFuture {
+        type Output = ReplyResult;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ReplyResult> {
+            let mut s = self.shared.lock().unwrap();
+            match s.result.take() {
+                Some(r) => Poll::Ready(r),
+                None => {
+                    s.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLDecoder;
+    use std::sync::{Arc, Mutex};
+
+    fn reply(id: u64) -> AstNode {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().reply_to(id).assert_().float32(0.8);
+        AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+    }
+
+    #[test]
+    fn tags_roundtrip_through_decoder() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().msg_id(42).query().l1_ref(0x0000);
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        assert_eq!(msg_id(&ast), Some(42));
+        assert_eq!(reply_to(&ast), None);
+        assert_eq!(reply_to(&reply(42)), Some(42));
+    }
+
+    #[test]
+    fn only_leading_comm1_tags_count() {
+        use crate::codebook::DIAG1;
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().msg_id(42).query().l1_ref(0x0000);
+        let diag = AILLDecoder::with_config(crate::DecoderConfig::new().bind_escape(1, DIAG1.registry_id));
+        assert_eq!(msg_id(&diag.decode_utterance(&e.end_utterance()).unwrap()), None);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().l1_ref(0x0001).begin_list(2).l1_ref(MSG_ID).uint64(42).end_list();
+        e.l1_ref(MSG_ID).uint64(43);
+        assert_eq!(msg_id(&AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()), None);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().l1_ref(0x0001).msg_id(42).query().l1_ref(0x0000);
+        assert_eq!(msg_id(&AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()), Some(42));
+    }
+
+    #[test]
+    fn polled_reply_matches_once() {
+        let mut corr = Correlator::new();
+        let id = corr.register(0, 1_000);
+        assert!(matches!(corr.handle_incoming(reply(id)), Incoming::Reply { reply: Some(_), .. }));
+        assert_eq!(corr.pending_count(), 0);
+        assert!(matches!(corr.handle_incoming(reply(id)), Incoming::Unmatched(_)));
+    }
+
+    #[test]
+    fn callbacks_receive_reply_or_timeout() {
+        let mut corr = Correlator::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let l = log.clone();
+        let answered = corr.register_callback(0, 1_000, move |r| l.lock().unwrap().push(r.is_ok()));
+        let l = log.clone();
+        let expired = corr.register_callback(0, 500, move |r| l.lock().unwrap().push(r.is_ok()));
+
+        assert_eq!(corr.poll_timeouts(499), Vec::<u64>::new());
+        assert_eq!(corr.poll_timeouts(500), vec![expired]);
+        corr.handle_incoming(reply(answered));
+        assert_eq!(*log.lock().unwrap(), vec![false, true]);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn future_resolves_on_reply() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        let mut corr = Correlator::new();
+        let mut fut = Box::pin(corr.request(0, 1_000));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+
+        corr.handle_incoming(reply(fut.id()));
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(ast)) => assert_eq!(reply_to(&ast), Some(fut.id())),
+            _ => panic!("expected reply"),
+        }
+    }
+}
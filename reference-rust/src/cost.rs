@@ -0,0 +1,162 @@
+//! Cost accounting from COST meta annotations.
+//!
+//! Agents attach `COST <float16>` to bids and status reports. A
+//! [`CostLedger`] aggregates those reports per (task, agent) pair so a
+//! PLAN-1 auctioneer can compare bids and track spend.
+
+use std::collections::HashMap;
+
+use crate::agent::AgentId;
+use crate::ast::AstNode;
+
+/// Aggregate of the costs one agent reported for one task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostStats {
+    pub count: u32,
+    pub total: f64,
+    pub min: f32,
+    pub max: f32,
+    /// Most recently reported cost.
+    pub last: f32,
+}
+
+impl CostStats {
+    fn new(cost: f32) -> Self {
+        Self { count: 1, total: cost as f64, min: cost, max: cost, last: cost }
+    }
+
+    fn add(&mut self, cost: f32) {
+        self.count += 1;
+        self.total += cost as f64;
+        self.min = self.min.min(cost);
+        self.max = self.max.max(cost);
+        self.last = cost;
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.total / self.count as f64
+    }
+}
+
+/// Per-task, per-agent cost totals.
+#[derive(Debug, Clone, Default)]
+pub struct CostLedger {
+    entries: HashMap<(u32, AgentId), CostStats>,
+}
+
+impl CostLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record a cost reported by `agent` for `task_id`.
+    pub fn record(&mut self, task_id: u32, agent: impl Into<AgentId>, cost: f32) {
+        self.entries
+            .entry((task_id, agent.into()))
+            .and_modify(|s| s.add(cost))
+            .or_insert_with(|| CostStats::new(cost));
+    }
+
+    /// Record the COST of a decoded utterance against `task_id`, attributed
+    /// to its SOURCE_AGENT. Returns the recorded cost, or `None` if the
+    /// utterance has no COST or no SOURCE_AGENT.
+    pub fn record_utterance(&mut self, task_id: u32, ast: &AstNode) -> Option<f32> {
+        let AstNode::Utterance { meta, .. } = ast else {
+            return None;
+        };
+        let cost = meta.cost()?;
+        let agent = meta.source_agent?;
+        self.record(task_id, agent, cost);
+        Some(cost)
+    }
+
+    pub fn stats(&self, task_id: u32, agent: &AgentId) -> Option<&CostStats> {
+        self.entries.get(&(task_id, *agent))
+    }
+
+    /// Sum of all costs reported for `task_id`.
+    pub fn task_total(&self, task_id: u32) -> f64 {
+        self.entries
+            .iter()
+            .filter(|((t, _), _)| *t == task_id)
+            .map(|(_, s)| s.total)
+            .sum()
+    }
+
+    /// Sum of all costs reported by `agent`.
+    pub fn agent_total(&self, agent: &AgentId) -> f64 {
+        self.entries
+            .iter()
+            .filter(|((_, a), _)| a == agent)
+            .map(|(_, s)| s.total)
+            .sum()
+    }
+
+    /// The agent whose latest cost for `task_id` is lowest, for auction
+    /// awards. Ties go to the lexicographically smallest agent id.
+    pub fn lowest_bidder(&self, task_id: u32) -> Option<(AgentId, f32)> {
+        self.entries
+            .iter()
+            .filter(|((t, _), _)| *t == task_id)
+            .map(|((_, a), s)| (*a, s.last))
+            .min_by(|x, y| x.1.total_cmp(&y.1).then(x.0.cmp(&y.0)))
+    }
+
+    /// Forget everything recorded for `task_id` (e.g. after an award).
+    pub fn clear_task(&mut self, task_id: u32) {
+        self.entries.retain(|(t, _), _| *t != task_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AILLDecoder, AILLEncoder};
+
+    fn bid(agent: u8, cost: f32) -> AstNode {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().source_agent([agent; 16]).cost(cost).propose().l1_ref(0x0012);
+        AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+    }
+
+    #[test]
+    fn cost_meta_roundtrips_without_breaking_header() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().cost(2.5).trace_id(9).assert_().float32(1.0);
+        let AstNode::Utterance { meta, body } = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(meta.cost(), Some(2.5));
+        assert_eq!(meta.trace_id(), Some(9));
+        assert_eq!(body.len(), 1);
+    }
+
+    #[test]
+    fn ledger_aggregates_and_picks_lowest_bid() {
+        let mut ledger = CostLedger::new();
+        assert_eq!(ledger.record_utterance(7, &bid(1, 4.0)), Some(4.0));
+        ledger.record_utterance(7, &bid(2, 3.0));
+        ledger.record_utterance(7, &bid(1, 2.0));
+        ledger.record_utterance(8, &bid(2, 10.0));
+
+        let s = ledger.stats(7, &AgentId::new([1; 16])).unwrap();
+        assert_eq!((s.count, s.min, s.max, s.last), (2, 2.0, 4.0, 2.0));
+        assert_eq!(s.mean(), 3.0);
+        assert_eq!(ledger.task_total(7), 9.0);
+        assert_eq!(ledger.agent_total(&AgentId::new([2; 16])), 13.0);
+        assert_eq!(ledger.lowest_bidder(7), Some((AgentId::new([1; 16]), 2.0)));
+
+        ledger.clear_task(7);
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(ledger.lowest_bidder(7), None);
+    }
+}
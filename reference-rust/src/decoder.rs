@@ -1,536 +1,1159 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
 
-use crate::ast::{AstNode, MetaHeader, LiteralValue, AnnotationValue, DecodedEpoch};
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::agent::AgentId;
+use crate::ast::{measurement, AstNode, MetaHeader, LiteralValue, AnnotationValue, DecodedEpoch, MEASUREMENT_SCHEMA_ID};
 use crate::codebook::base::{fc, ty, st, meta, modal, esc, BASE_CODEBOOK};
+use crate::codebook::dynamic::DynamicCodebook;
+use crate::codebook::get_domain_codebook;
 use crate::error::AILLError;
-use crate::wire::ByteReader;
-use crate::wire::crc8::crc8;
+use crate::extension::{ExtensionRegistry, EXTENSION_RANGE};
+use crate::wire::{epoch_flags, ByteReader, EXTENDED_EPOCH, LONG_STRINGS_VERSION};
+use crate::wire::integrity::{self, IntegrityScheme};
+
+/// Default for [`DecoderConfig::max_depth`].
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Kind of AST node about to be decoded, reported to [`DecodeObserver::on_node_start`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeKind {
+    Utterance,
+    /// The utterance meta header. It has no AST node of its own, so no
+    /// matching `on_node_end` follows; it is folded into the utterance.
+    MetaHeader,
+    Literal,
+    Struct,
+    List,
+    Map,
+    Pragmatic,
+    Modal,
+    Temporal,
+    SchemaRef,
+    DomainRef,
+    CodebookRef,
+    ContextRef,
+    HashRef,
+    Code,
+    FrameControl,
+    Annotated,
+    Extension,
+}
+
+/// Callbacks invoked by the decoder while it parses, with byte offsets
+/// relative to the start of the decoded buffer.
+///
+/// All methods have empty defaults, so implementors only override what they
+/// need. Wire analyzers and disassemblers can be built on this instead of a
+/// parallel parser.
+pub trait DecodeObserver {
+    /// An opcode byte was consumed at `offset`.
+    fn on_opcode(&mut self, _offset: usize, _code: u8) {}
+
+    /// Decoding of a node of `kind` begins at `offset`.
+    fn on_node_start(&mut self, _offset: usize, _kind: NodeKind) {}
+
+    /// A node spanning `start..end` was decoded. Not called for the meta
+    /// header or for codes that produce no AST output (NOP, COMMENT).
+    fn on_node_end(&mut self, _start: usize, _end: usize, _node: &AstNode) {}
+}
+
+/// What the decoder does with a domain reference it cannot resolve: one
+/// on an unbound escape level, or whose code is not in the codebook its
+/// level is bound to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum UnknownDomainPolicy {
+    /// Decode it as a bare [`AstNode::DomainRef`] without looking it up.
+    #[default]
+    Allow,
+    /// Decode it and record an [`UnknownDomainRef`], see
+    /// [`AILLDecoder::decode_utterance_with_warnings`].
+    Warn,
+    /// Refuse the utterance with [`AILLError::UnknownDomainRef`].
+    Error,
+}
+
+/// A domain reference the decoder could not resolve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownDomainRef {
+    /// Offset of the escape opcode.
+    pub offset: usize,
+    pub level: u8,
+    pub domain_code: u16,
+    /// Registry the level was bound to, `None` if unbound.
+    pub registry: Option<u8>,
+}
+
+impl UnknownDomainRef {
+    fn into_error(self) -> AILLError {
+        let UnknownDomainRef { offset, level, domain_code, registry } = self;
+        AILLError::UnknownDomainRef { offset, level, domain_code, registry }
+    }
+}
+
+/// Decoder settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct DecoderConfig {
+    /// Extension opcodes whose payloads the decoder reads.
+    pub extensions: ExtensionRegistry,
+    /// Registry id bound to ESCAPE_L1, L2 and L3 when decoding starts.
+    /// Domain references on a bound level carry the registry id in the AST;
+    /// references on an unbound level decode with `registry: None`.
+    /// CODEBOOK_REF rebinds a level from that point to the end of the
+    /// utterance; see [`apply_codebook_refs`](Self::apply_codebook_refs) to
+    /// carry bindings over to later utterances.
+    pub escape_bindings: [Option<u8>; 3],
+    /// Struct field ids the application understands. When set, values of
+    /// other FIELD_IDs are kept as [`AstNode::Raw`] wire bytes rather than
+    /// decoded, so they pass through unchanged to newer peers.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub known_fields: Option<BTreeSet<u16>>,
+    /// Deepest nesting of expressions inside the body; deeper input fails
+    /// with [`AILLError::TooDeep`] before the decoder recurses into it.
+    /// [`decode_events`](AILLDecoder::decode_events) enforces the same
+    /// limit on its own stack.
+    #[cfg_attr(feature = "ast-serde", serde(default = "default_max_depth"))]
+    pub max_depth: usize,
+    /// Handling of domain references that do not resolve against
+    /// [`codebooks`](Self::codebooks) or the built-in domain codebooks.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub unknown_domains: UnknownDomainPolicy,
+    /// Codebooks by registry id that domain references resolve against
+    /// before the built-in ones, e.g. private or session-negotiated
+    /// vocabularies.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub codebooks: BTreeMap<u8, DynamicCodebook>,
+}
+
+#[cfg(feature = "ast-serde")]
+fn default_max_depth() -> usize {
+    DEFAULT_MAX_DEPTH
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        Self {
+            extensions: ExtensionRegistry::default(),
+            escape_bindings: [None; 3],
+            known_fields: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            unknown_domains: UnknownDomainPolicy::Allow,
+            codebooks: BTreeMap::new(),
+        }
+    }
+}
+
+impl DecoderConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_extensions(mut self, extensions: ExtensionRegistry) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    pub fn with_known_fields(mut self, ids: impl IntoIterator<Item = u16>) -> Self {
+        self.known_fields = Some(ids.into_iter().collect());
+        self
+    }
+
+    pub fn with_unknown_domains(mut self, policy: UnknownDomainPolicy) -> Self {
+        self.unknown_domains = policy;
+        self
+    }
+
+    /// Resolve references to `codebook.registry_id` against `codebook`,
+    /// replacing any built-in codebook with that id.
+    pub fn with_codebook(mut self, codebook: DynamicCodebook) -> Self {
+        self.codebooks.insert(codebook.registry_id, codebook);
+        self
+    }
+
+    /// Whether `code` is defined in registry `registry`, custom codebooks
+    /// first.
+    pub fn resolves(&self, registry: u8, code: u16) -> bool {
+        match self.codebooks.get(&registry) {
+            Some(codebook) => codebook.get(code).is_some(),
+            None => get_domain_codebook(registry).is_some_and(|codebook| codebook.lookup(code).is_some()),
+        }
+    }
+
+    /// Whether values of field `id` are decoded.
+    pub fn is_known_field(&self, id: u16) -> bool {
+        self.known_fields.as_ref().is_none_or(|known| known.contains(&id))
+    }
+
+    /// Resolve escape `level` (1-3) against registry `registry_id`.
+    ///
+    /// # Panics
+    /// If `level` is not 1, 2 or 3.
+    pub fn bind_escape(mut self, level: u8, registry_id: u8) -> Self {
+        assert!((1..=3).contains(&level), "escape level must be 1-3, got {}", level);
+        self.escape_bindings[level as usize - 1] = Some(registry_id);
+        self
+    }
+
+    /// The registry bound to escape `level`, if any.
+    pub fn escape_binding(&self, level: u8) -> Option<u8> {
+        let idx = (level as usize).checked_sub(1)?;
+        self.escape_bindings.get(idx).copied().flatten()
+    }
+
+    /// Adopt the bindings of every CODEBOOK_REF in `node`, in order, so
+    /// they stay in effect for subsequent utterances of a session.
+    pub fn apply_codebook_refs(&mut self, node: &AstNode) {
+        match node {
+            AstNode::CodebookRef { level, registry_id } => {
+                if let Some(slot) = (*level as usize).checked_sub(1).and_then(|i| self.escape_bindings.get_mut(i)) {
+                    *slot = Some(*registry_id);
+                }
+            }
+            AstNode::Utterance { body, .. } => body.iter().for_each(|n| self.apply_codebook_refs(n)),
+            AstNode::Struct { fields } => fields.iter().for_each(|(_, n)| self.apply_codebook_refs(n)),
+            AstNode::List { elements, .. } => elements.iter().for_each(|n| self.apply_codebook_refs(n)),
+            AstNode::Map { pairs, .. } => pairs.iter().for_each(|(k, v)| {
+                self.apply_codebook_refs(k);
+                self.apply_codebook_refs(v);
+            }),
+            AstNode::Pragmatic { expression, .. }
+            | AstNode::Modal { expression, .. }
+            | AstNode::Temporal { expression, .. }
+            | AstNode::SchemaRef { expression, .. } => self.apply_codebook_refs(expression),
+            _ => {}
+        }
+    }
+}
 
 /// Decodes AILL wire-format bytes into an AST.
-pub struct AILLDecoder;
+pub struct AILLDecoder {
+    config: DecoderConfig,
+}
 
 impl AILLDecoder {
     pub fn new() -> Self {
-        Self
+        Self::with_config(DecoderConfig::default())
+    }
+
+    pub fn with_config(config: DecoderConfig) -> Self {
+        Self { config }
+    }
+
+    /// A decoder that reads the payloads of the given extension opcodes.
+    pub fn with_extensions(extensions: ExtensionRegistry) -> Self {
+        Self::with_config(DecoderConfig::new().with_extensions(extensions))
+    }
+
+    pub fn config(&self) -> &DecoderConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut DecoderConfig {
+        &mut self.config
     }
 
     /// Decode a complete AILL utterance from wire bytes.
     pub fn decode_utterance(&self, data: &[u8]) -> Result<AstNode, AILLError> {
-        let mut reader = ByteReader::new(data);
+        Session::new(data, &self.config, None).decode_utterance()
+    }
 
-        // Expect START_UTTERANCE
-        let code = reader.read_u8()?;
+    /// Decode a complete utterance, also returning the domain references
+    /// that did not resolve when the policy is
+    /// [`Warn`](UnknownDomainPolicy::Warn).
+    pub fn decode_utterance_with_warnings(&self, data: &[u8]) -> Result<(AstNode, Vec<UnknownDomainRef>), AILLError> {
+        let mut session = Session::new(data, &self.config, None);
+        let node = session.decode_utterance()?;
+        Ok((node, session.warnings))
+    }
+
+    /// Decode a complete utterance, reporting parse progress to `observer`.
+    pub fn decode_utterance_with_observer(
+        &self,
+        data: &[u8],
+        observer: &mut dyn DecodeObserver,
+    ) -> Result<AstNode, AILLError> {
+        Session::new(data, &self.config, Some(observer)).decode_utterance()
+    }
+
+    /// Decode every utterance in a buffer of back-to-back utterances, with
+    /// the byte range each occupies. Fails on the first malformed one.
+    pub fn decode_all(&self, data: &[u8]) -> Result<Vec<(AstNode, Range<usize>)>, AILLError> {
+        self.utterances(data).collect()
+    }
+
+    /// Iterator form of [`decode_all`](Self::decode_all). It stops after
+    /// yielding the first error.
+    pub fn utterances<'d>(&'d self, data: &'d [u8]) -> Utterances<'d> {
+        Utterances { decoder: self, data, offset: 0 }
+    }
+
+    /// Decode the utterance at the start of `data`, reporting whether it was
+    /// complete, cut short by the end of `data`, or abandoned with ABORT.
+    pub(crate) fn decode_prefix(&self, data: &[u8]) -> Result<Prefix, AILLError> {
+        let mut session = Session::new(data, &self.config, None);
+        match session.utterance() {
+            Ok(node) if session.terminated => Ok(Prefix::Complete(node, session.reader.pos())),
+            Ok(_) | Err(AILLError::UnexpectedEof { .. }) => Ok(Prefix::Truncated),
+            Err(e) => match session.aborted {
+                Some(reason) => Ok(Prefix::Aborted(reason, session.reader.pos())),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Decode START_UTTERANCE and the meta header at the start of `data`,
+    /// returning the header and the number of bytes it took.
+    pub(crate) fn decode_meta_prefix(&self, data: &[u8]) -> Result<(MetaHeader, usize), AILLError> {
+        let mut session = Session::new(data, &self.config, None);
+        let code = session.opcode()?;
         if code != fc::START_UTTERANCE {
             return Err(AILLError::InvalidStructure(format!(
                 "Expected START_UTTERANCE (0x00), got 0x{:02X}",
                 code
             )));
         }
+        let meta = session.decode_meta_header()?;
+        Ok((meta, session.reader.pos()))
+    }
+}
 
-        // Decode meta header
-        let meta_header = decode_meta_header(&mut reader)?;
+/// Iterator over the utterances in a buffer, see [`AILLDecoder::utterances`].
+pub struct Utterances<'d> {
+    decoder: &'d AILLDecoder,
+    data: &'d [u8],
+    offset: usize,
+}
 
-        // Decode body expressions until END_UTTERANCE
-        let mut body = Vec::new();
-        while !reader.is_empty() {
-            if reader.peek()? == fc::END_UTTERANCE {
-                reader.read_u8()?; // consume
-                break;
+impl Iterator for Utterances<'_> {
+    type Item = Result<(AstNode, Range<usize>), AILLError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        let start = self.offset;
+        let mut session = Session::new(&self.data[start..], &self.decoder.config, None);
+        match session.utterance() {
+            Ok(node) => {
+                self.offset += session.reader.pos();
+                Some(Ok((node, start..self.offset)))
             }
-            if let Some(expr) = decode_expression(&mut reader)? {
-                body.push(expr);
+            Err(e) => {
+                self.offset = self.data.len();
+                Some(Err(e))
             }
         }
-
-        Ok(AstNode::Utterance {
-            meta: meta_header,
-            body,
-        })
     }
 }
 
+/// Outcome of [`AILLDecoder::decode_prefix`]; offsets are bytes consumed.
+pub(crate) enum Prefix {
+    Complete(AstNode, usize),
+    Truncated,
+    /// Abandoned with ABORT and its reason code.
+    Aborted(u8, usize),
+}
+
 impl Default for AILLDecoder {
     fn default() -> Self {
         Self::new()
     }
 }
 
-fn decode_meta_header(reader: &mut ByteReader) -> Result<MetaHeader, AILLError> {
-    let mut hdr = MetaHeader::default();
+/// END_UTTERANCE or a container closer.
+fn is_terminator(code: u8) -> bool {
+    matches!(code, fc::END_UTTERANCE | st::END_STRUCT | st::END_LIST | st::END_MAP)
+}
 
-    // CONFIDENCE (mandatory)
-    let code = reader.read_u8()?;
-    if code != meta::CONFIDENCE {
-        return Err(AILLError::InvalidStructure(format!(
-            "Expected CONFIDENCE (0x90), got 0x{:02X}", code
-        )));
+/// State for a single decode call.
+pub(crate) struct Session<'a, 'o> {
+    pub(crate) reader: ByteReader<'a>,
+    extensions: &'a ExtensionRegistry,
+    known_fields: Option<&'a BTreeSet<u16>>,
+    config: &'a DecoderConfig,
+    unknown_domains: UnknownDomainPolicy,
+    /// Unresolved domain references, under [`UnknownDomainPolicy::Warn`].
+    pub(crate) warnings: Vec<UnknownDomainRef>,
+    /// Registry per escape level; starts from the config and may be
+    /// rebound during the session.
+    pub(crate) bindings: [Option<u8>; 3],
+    /// END_UTTERANCE was reached.
+    terminated: bool,
+    /// Reason code of an ABORT that abandoned the utterance.
+    aborted: Option<u8>,
+    /// Expressions being decoded, innermost included.
+    depth: usize,
+    max_depth: usize,
+    /// Scanning ahead for [`count_extra_elements`](Self::count_extra_elements),
+    /// which nested containers skip so the scan stays linear.
+    lookahead: bool,
+    /// Where a sibling scan of [`count_extra_elements`](Self::count_extra_elements)
+    /// passing each `(offset, depth)` ended: at the terminator it names, or
+    /// `None` at the end of the utterance or a malformed expression. Later
+    /// scans through the same siblings stop there, so scanning stays linear.
+    scanned: HashMap<(usize, usize), Option<u8>>,
+    observer: Option<&'o mut dyn DecodeObserver>,
+}
+
+impl<'a, 'o> Session<'a, 'o> {
+    pub(crate) fn new(
+        data: &'a [u8],
+        config: &'a DecoderConfig,
+        observer: Option<&'o mut dyn DecodeObserver>,
+    ) -> Self {
+        Self {
+            reader: ByteReader::new(data),
+            extensions: &config.extensions,
+            known_fields: config.known_fields.as_ref(),
+            config,
+            unknown_domains: config.unknown_domains,
+            warnings: Vec::new(),
+            bindings: config.escape_bindings,
+            terminated: false,
+            aborted: None,
+            depth: 0,
+            max_depth: config.max_depth,
+            lookahead: false,
+            scanned: HashMap::new(),
+            observer,
+        }
     }
-    hdr.confidence = reader.read_f16_be()?;
 
-    // PRIORITY (mandatory)
-    let code = reader.read_u8()?;
-    if code != meta::PRIORITY {
-        return Err(AILLError::InvalidStructure(format!(
-            "Expected PRIORITY (0x91), got 0x{:02X}", code
-        )));
+    /// Consume one opcode byte, notifying the observer.
+    pub(crate) fn opcode(&mut self) -> Result<u8, AILLError> {
+        let offset = self.reader.pos();
+        let code = self.reader.read_u8()?;
+        if let Some(obs) = self.observer.as_mut() {
+            obs.on_opcode(offset, code);
+        }
+        Ok(code)
     }
-    hdr.priority = reader.read_u8()?;
 
-    // TIMESTAMP (mandatory)
-    let code = reader.read_u8()?;
-    if code != meta::TIMESTAMP_META {
-        return Err(AILLError::InvalidStructure(format!(
-            "Expected TIMESTAMP_META (0x94), got 0x{:02X}", code
-        )));
+    fn node_start(&mut self, kind: NodeKind) -> usize {
+        let offset = self.reader.pos();
+        if let Some(obs) = self.observer.as_mut() {
+            obs.on_node_start(offset, kind);
+        }
+        offset
     }
-    hdr.timestamp_us = reader.read_i64_be()?;
 
-    // Optional meta annotations (0x92-0x9F range)
-    while !reader.is_empty() {
-        let peek = reader.peek()?;
-        if !(0x92..=0x9F).contains(&peek) {
-            break;
+    fn node_end(&mut self, start: usize, node: AstNode) -> AstNode {
+        let end = self.reader.pos();
+        if let Some(obs) = self.observer.as_mut() {
+            obs.on_node_end(start, end, &node);
         }
-        let ann_code = reader.read_u8()?;
-        match ann_code {
-            meta::SOURCE_AGENT => {
-                hdr.source_agent = Some(reader.read_uuid()?.to_vec());
-            }
-            meta::DEST_AGENT => {
-                hdr.dest_agent = Some(reader.read_uuid()?.to_vec());
-            }
-            meta::SEQNUM => {
-                hdr.seqnum = Some(reader.read_u32_be()?);
-            }
-            meta::TRACE_ID => {
-                hdr.annotations.insert("trace_id".into(), AnnotationValue::U64(reader.read_u64_be()?));
-            }
-            meta::TTL => {
-                hdr.annotations.insert("ttl".into(), AnnotationValue::U16(reader.read_u16_be()?));
-            }
-            meta::TOPIC => {
-                hdr.annotations.insert("topic".into(), AnnotationValue::U16(reader.read_u16_be()?));
+        node
+    }
+
+    fn decode_utterance(&mut self) -> Result<AstNode, AILLError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("aill.decode", bytes = self.reader.remaining()).entered();
+        let result = self.utterance();
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            tracing::debug!(offset = self.reader.pos(), error = %e, "utterance rejected");
+        }
+        result
+    }
+
+    fn utterance(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Utterance);
+
+        // Expect START_UTTERANCE
+        let code = self.opcode()?;
+        if code != fc::START_UTTERANCE {
+            return Err(AILLError::InvalidStructure(format!(
+                "Expected START_UTTERANCE (0x00), got 0x{:02X}",
+                code
+            )));
+        }
+
+        // Decode meta header
+        let meta_header = self.decode_meta_header()?;
+
+        // Decode body expressions until END_UTTERANCE
+        let mut body = Vec::new();
+        while !self.reader.is_empty() {
+            if self.reader.peek()? == fc::END_UTTERANCE {
+                self.opcode()?; // consume
+                self.terminated = true;
+                break;
             }
-            meta::VERSION_TAG => {
-                let major = reader.read_u16_be()?;
-                let minor = reader.read_u16_be()?;
-                hdr.annotations.insert("version".into(), AnnotationValue::Pair(major, minor));
+            if let Some(expr) = self.decode_expression()? {
+                body.push(expr);
             }
-            _ => break,
         }
+
+        Ok(self.node_end(start, AstNode::Utterance {
+            meta: meta_header,
+            body,
+        }))
     }
 
-    Ok(hdr)
-}
+    pub(crate) fn decode_meta_header(&mut self) -> Result<MetaHeader, AILLError> {
+        self.node_start(NodeKind::MetaHeader);
+        let mut hdr = MetaHeader::default();
 
-fn decode_expression(reader: &mut ByteReader) -> Result<Option<AstNode>, AILLError> {
-    if reader.is_empty() {
-        return Ok(None);
-    }
+        // CONFIDENCE (mandatory)
+        let code = self.opcode()?;
+        if code != meta::CONFIDENCE {
+            return Err(AILLError::InvalidStructure(format!(
+                "Expected CONFIDENCE (0x90), got 0x{:02X}", code
+            )));
+        }
+        hdr.confidence = self.reader.read_f16_be()?;
 
-    let code = reader.peek()?;
+        // PRIORITY (mandatory)
+        let code = self.opcode()?;
+        if code != meta::PRIORITY {
+            return Err(AILLError::InvalidStructure(format!(
+                "Expected PRIORITY (0x91), got 0x{:02X}", code
+            )));
+        }
+        hdr.priority = self.reader.read_u8()?;
 
-    // Pragmatic acts (0x80-0x8F)
-    if (0x80..=0x8F).contains(&code) {
-        return Ok(Some(decode_pragmatic(reader)?));
-    }
+        // TIMESTAMP (mandatory)
+        let code = self.opcode()?;
+        if code != meta::TIMESTAMP_META {
+            return Err(AILLError::InvalidStructure(format!(
+                "Expected TIMESTAMP_META (0x94), got 0x{:02X}", code
+            )));
+        }
+        hdr.timestamp_us = self.reader.read_i64_be()?;
 
-    // Modality (0x70-0x7F)
-    if (0x70..=0x7F).contains(&code) {
-        return Ok(Some(decode_modal(reader)?));
-    }
+        // Optional meta annotations (0x92-0x9F range)
+        while !self.reader.is_empty() {
+            let peek = self.reader.peek()?;
+            if !(0x92..=0x9F).contains(&peek) {
+                break;
+            }
+            let ann_code = self.opcode()?;
+            let reader = &mut self.reader;
+            match ann_code {
+                meta::SOURCE_AGENT => {
+                    hdr.source_agent = Some(AgentId::new(reader.read_uuid()?));
+                }
+                meta::DEST_AGENT => {
+                    hdr.dest_agent = Some(AgentId::new(reader.read_uuid()?));
+                }
+                meta::SEQNUM => {
+                    hdr.seqnum = Some(reader.read_u32_be()?);
+                }
+                meta::TRACE_ID => {
+                    hdr.annotations.insert("trace_id".into(), AnnotationValue::U64(reader.read_u64_be()?));
+                }
+                meta::TTL => {
+                    hdr.annotations.insert("ttl".into(), AnnotationValue::U16(reader.read_u16_be()?));
+                }
+                meta::TOPIC => {
+                    hdr.annotations.insert("topic".into(), AnnotationValue::U16(reader.read_u16_be()?));
+                }
+                meta::COST => {
+                    hdr.annotations.insert("cost".into(), AnnotationValue::F32(reader.read_f16_be()?));
+                }
+                meta::VERSION_TAG => {
+                    let major = reader.read_u16_be()?;
+                    let minor = reader.read_u16_be()?;
+                    reader.set_long_strings((major, minor) >= LONG_STRINGS_VERSION);
+                    hdr.annotations.insert("version".into(), AnnotationValue::Pair(major, minor));
+                }
+                _ => break,
+            }
+        }
 
-    // Temporal (0x60-0x6F)
-    if (0x60..=0x6F).contains(&code) {
-        return Ok(Some(decode_temporal(reader)?));
+        Ok(hdr)
     }
 
-    // Meta annotations inline
-    if code == meta::CONFIDENCE || code == meta::LABEL {
-        return Ok(Some(decode_annotation(reader)?));
+    /// Fail unless an expression may start inside `depth` open ones.
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), AILLError> {
+        if depth >= self.max_depth {
+            return Err(AILLError::TooDeep { offset: self.reader.pos(), max_depth: self.max_depth });
+        }
+        Ok(())
     }
 
-    // Type markers (literals)
-    if (0x10..=0x1F).contains(&code) {
-        return Ok(Some(decode_literal(reader)?));
+    pub(crate) fn decode_expression(&mut self) -> Result<Option<AstNode>, AILLError> {
+        if self.reader.is_empty() {
+            return Ok(None);
+        }
+        self.check_depth(self.depth)?;
+        self.depth += 1;
+        let result = self.expression();
+        self.depth -= 1;
+        result
     }
 
-    // Structure codes
-    if code == st::BEGIN_STRUCT {
-        return Ok(Some(decode_struct(reader)?));
-    }
-    if code == st::BEGIN_LIST {
-        return Ok(Some(decode_list(reader)?));
+    fn expression(&mut self) -> Result<Option<AstNode>, AILLError> {
+        let code = self.reader.peek()?;
+
+        // ABORT abandons the utterance wherever an expression could start
+        if code == fc::ABORT {
+            let offset = self.reader.pos();
+            self.opcode()?;
+            let reason = self.reader.read_u8()?;
+            self.aborted = Some(reason);
+            return Err(AILLError::InvalidStructure(format!(
+                "utterance aborted at offset {} with reason 0x{:02X}",
+                offset, reason
+            )));
+        }
+
+        // Pragmatic acts (0x80-0x8F)
+        if (0x80..=0x8F).contains(&code) {
+            return Ok(Some(self.decode_pragmatic()?));
+        }
+
+        // Modality (0x70-0x7F)
+        if (0x70..=0x7F).contains(&code) {
+            return Ok(Some(self.decode_modal()?));
+        }
+
+        // Temporal (0x60-0x6F)
+        if (0x60..=0x6F).contains(&code) {
+            return Ok(Some(self.decode_temporal()?));
+        }
+
+        // Meta annotations inline
+        if code == meta::CONFIDENCE || code == meta::LABEL {
+            return Ok(Some(self.decode_annotation()?));
+        }
+
+        // Type markers (literals)
+        if (0x10..=0x1F).contains(&code) {
+            return Ok(Some(self.decode_literal()?));
+        }
+
+        // Structure codes
+        if code == st::BEGIN_STRUCT {
+            return Ok(Some(self.decode_struct()?));
+        }
+        if code == st::BEGIN_LIST {
+            return Ok(Some(self.decode_list()?));
+        }
+        if code == st::BEGIN_MAP {
+            return Ok(Some(self.decode_map()?));
+        }
+
+        // Schema-typed struct
+        if code == st::SCHEMA_REF {
+            return Ok(Some(self.decode_schema_ref()?));
+        }
+
+        // Escape/domain refs
+        if code == esc::ESCAPE_L1 || code == esc::ESCAPE_L2 || code == esc::ESCAPE_L3 {
+            return Ok(Some(self.decode_domain_ref()?));
+        }
+
+        // Codebook binding
+        if code == esc::CODEBOOK_REF {
+            return Ok(Some(self.decode_codebook_ref()?));
+        }
+
+        // Context ref
+        if code == meta::CONTEXT_REF {
+            let start = self.node_start(NodeKind::ContextRef);
+            self.opcode()?;
+            let idx = self.reader.read_varint()?;
+            return Ok(Some(self.node_end(start, AstNode::ContextRef { sct_index: idx })));
+        }
+
+        // Hash ref
+        if code == meta::HASH_REF {
+            let start = self.node_start(NodeKind::HashRef);
+            self.opcode()?;
+            let hash = self.reader.read_u32_be()?;
+            return Ok(Some(self.node_end(start, AstNode::HashRef { hash })));
+        }
+
+        // NOP
+        if code == esc::NOP {
+            self.opcode()?;
+            return Ok(None);
+        }
+
+        // COMMENT
+        if code == esc::COMMENT {
+            self.opcode()?;
+            let _comment = self.reader.read_string()?;
+            return Ok(None);
+        }
+
+        // Extension opcodes (0xC0-0xEF)
+        if EXTENSION_RANGE.contains(&code) {
+            let start = self.node_start(NodeKind::Extension);
+            self.opcode()?;
+            let (mnemonic, payload) = match self.extensions.get(code) {
+                Some(ext) => (ext.mnemonic.clone(), self.reader.read_n_bytes(ext.payload_len)?),
+                None => (BASE_CODEBOOK[code as usize].mnemonic.to_string(), Vec::new()),
+            };
+            return Ok(Some(self.node_end(start, AstNode::Extension { code, mnemonic, payload })));
+        }
+
+        // Frame control with its operands
+        if let Some(widths) = fc::operand_widths(code) {
+            let start = self.node_start(NodeKind::FrameControl);
+            self.opcode()?;
+            let mut operands = Vec::with_capacity(widths.len());
+            for width in widths {
+                operands.push(match width {
+                    1 => self.reader.read_u8()? as u32,
+                    2 => self.reader.read_u16_be()? as u32,
+                    _ => self.reader.read_u32_be()?,
+                });
+            }
+            let mnemonic = BASE_CODEBOOK[code as usize].mnemonic.to_string();
+            return Ok(Some(self.node_end(start, AstNode::FrameControl { code, mnemonic, operands })));
+        }
+
+        // Operators and other codes - emit as-is
+        let start = self.node_start(NodeKind::Code);
+        self.opcode()?;
+        let mnemonic = BASE_CODEBOOK[code as usize].mnemonic.to_string();
+        Ok(Some(self.node_end(start, AstNode::Code { code, mnemonic })))
     }
-    if code == st::BEGIN_MAP {
-        return Ok(Some(decode_map(reader)?));
+
+    /// Whether the reader is at the end of the utterance or of any container,
+    /// where a list or map stops even if its count is not used up.
+    pub(crate) fn at_container_end(&self) -> bool {
+        self.reader.peek().is_ok_and(is_terminator) || self.reader.is_empty()
     }
 
-    // Escape/domain refs
-    if code == esc::ESCAPE_L1 || code == esc::ESCAPE_L2 || code == esc::ESCAPE_L3 {
-        return Ok(Some(decode_domain_ref(reader)?));
+    /// Elements between the reader and the `closer` of a container whose
+    /// declared count is used up, or `None` if the container is not closed
+    /// explicitly before an enclosing closer or the end of the utterance.
+    pub(crate) fn count_extra_elements(&mut self, closer: u8) -> Option<usize> {
+        let mut ahead = Session {
+            reader: self.reader.clone(),
+            extensions: self.extensions,
+            known_fields: self.known_fields,
+            config: self.config,
+            // The real pass reports these
+            unknown_domains: UnknownDomainPolicy::Allow,
+            warnings: Vec::new(),
+            bindings: self.bindings,
+            terminated: false,
+            aborted: None,
+            depth: self.depth,
+            max_depth: self.max_depth,
+            lookahead: true,
+            scanned: HashMap::new(),
+            observer: None,
+        };
+        let mut passed = Vec::new();
+        let mut extra = 0;
+        let end = loop {
+            let pos = ahead.reader.pos();
+            match self.scanned.get(&(pos, self.depth)) {
+                Some(&end) if end != Some(closer) => break end,
+                _ => passed.push(pos),
+            }
+            match ahead.reader.peek() {
+                Ok(code) if code == closer => return Some(extra),
+                Ok(code) if is_terminator(code) => break Some(code),
+                Ok(_) => {}
+                Err(_) => break None,
+            }
+            match ahead.decode_expression() {
+                Ok(Some(_)) => extra += 1,
+                Ok(None) => {}
+                Err(_) => break None,
+            }
+        };
+        for pos in passed {
+            self.scanned.insert((pos, self.depth), end);
+        }
+        None
     }
 
-    // Context ref
-    if code == meta::CONTEXT_REF {
-        reader.read_u8()?;
-        let idx = reader.read_varint()?;
-        return Ok(Some(AstNode::ContextRef { sct_index: idx }));
+    /// Close a list or map of `declared` elements after `actual` were read,
+    /// reporting a count that disagrees with an explicit closer.
+    /// A container cut short by an enclosing closer or the end of the
+    /// utterance is a mismatch too.
+    pub(crate) fn close_container(&mut self, closer: u8, declared: u16, actual: usize) -> Result<(), AILLError> {
+        if self.reader.is_empty() {
+            if actual < declared as usize && !self.lookahead {
+                return Err(AILLError::CountMismatch { declared, actual });
+            }
+            return Ok(());
+        }
+        let next = self.reader.peek()?;
+        if next == closer {
+            self.opcode()?;
+            if actual < declared as usize && !self.lookahead {
+                return Err(AILLError::CountMismatch { declared, actual });
+            }
+            return Ok(());
+        }
+        if self.lookahead {
+            return Ok(());
+        }
+        if is_terminator(next) && actual < declared as usize {
+            return Err(AILLError::CountMismatch { declared, actual });
+        }
+        match self.count_extra_elements(closer) {
+            Some(extra) => {
+                let extra = if closer == st::END_MAP { extra.div_ceil(2) } else { extra };
+                Err(AILLError::CountMismatch { declared, actual: actual + extra })
+            }
+            None => Ok(()),
+        }
     }
 
-    // NOP
-    if code == esc::NOP {
-        reader.read_u8()?;
-        return Ok(None);
+    pub(crate) fn is_known_field(&self, id: u16) -> bool {
+        self.known_fields.is_none_or(|known| known.contains(&id))
     }
 
-    // COMMENT
-    if code == esc::COMMENT {
-        reader.read_u8()?;
-        let _comment = reader.read_string()?;
-        return Ok(None);
+    /// Skip over an expression, returning its wire bytes.
+    pub(crate) fn raw_expression(&mut self) -> Result<Option<Vec<u8>>, AILLError> {
+        let start = self.reader.pos();
+        Ok(self.decode_expression()?.map(|_| self.reader.since(start).to_vec()))
     }
 
-    // Operators and other codes - emit as-is
-    reader.read_u8()?;
-    let mnemonic = BASE_CODEBOOK[code as usize].mnemonic.to_string();
-    Ok(Some(AstNode::Code { code, mnemonic }))
-}
+    fn decode_literal(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Literal);
+        let code = self.opcode()?;
+        let reader = &mut self.reader;
 
-fn decode_literal(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    let code = reader.read_u8()?;
-
-    let (value_type, value) = match code {
-        ty::TYPE_INT8 => ("int8", LiteralValue::Int8(reader.read_i8()?)),
-        ty::TYPE_INT16 => ("int16", LiteralValue::Int16(reader.read_i16_be()?)),
-        ty::TYPE_INT32 => ("int32", LiteralValue::Int32(reader.read_i32_be()?)),
-        ty::TYPE_INT64 => ("int64", LiteralValue::Int64(reader.read_i64_be()?)),
-        ty::TYPE_UINT8 => ("uint8", LiteralValue::Uint8(reader.read_u8()?)),
-        ty::TYPE_UINT16 => ("uint16", LiteralValue::Uint16(reader.read_u16_be()?)),
-        ty::TYPE_UINT32 => ("uint32", LiteralValue::Uint32(reader.read_u32_be()?)),
-        ty::TYPE_UINT64 => ("uint64", LiteralValue::Uint64(reader.read_u64_be()?)),
-        ty::TYPE_FLOAT16 => ("float16", LiteralValue::Float16(reader.read_f16_be()?)),
-        ty::TYPE_FLOAT32 => ("float32", LiteralValue::Float32(reader.read_f32_be()?)),
-        ty::TYPE_FLOAT64 => ("float64", LiteralValue::Float64(reader.read_f64_be()?)),
-        ty::TYPE_BOOL => ("bool", LiteralValue::Bool(reader.read_u8()? != 0)),
-        ty::TYPE_STRING => ("string", LiteralValue::String(reader.read_string()?)),
-        ty::TYPE_BYTES => {
-            let length = reader.read_u16_be()? as usize;
-            ("bytes", LiteralValue::Bytes(reader.read_n_bytes(length)?))
-        }
-        ty::TYPE_TIMESTAMP => ("timestamp", LiteralValue::Timestamp(reader.read_i64_be()?)),
-        ty::TYPE_NULL => ("null", LiteralValue::Null),
-        _ => return Err(AILLError::InvalidOpCode(code)),
-    };
+        let (value_type, value) = match code {
+            ty::TYPE_INT8 => ("int8", LiteralValue::Int8(reader.read_i8()?)),
+            ty::TYPE_INT16 => ("int16", LiteralValue::Int16(reader.read_i16_be()?)),
+            ty::TYPE_INT32 => ("int32", LiteralValue::Int32(reader.read_i32_be()?)),
+            ty::TYPE_INT64 => ("int64", LiteralValue::Int64(reader.read_i64_be()?)),
+            ty::TYPE_UINT8 => ("uint8", LiteralValue::Uint8(reader.read_u8()?)),
+            ty::TYPE_UINT16 => ("uint16", LiteralValue::Uint16(reader.read_u16_be()?)),
+            ty::TYPE_UINT32 => ("uint32", LiteralValue::Uint32(reader.read_u32_be()?)),
+            ty::TYPE_UINT64 => ("uint64", LiteralValue::Uint64(reader.read_u64_be()?)),
+            ty::TYPE_FLOAT16 => ("float16", LiteralValue::Float16(reader.read_f16_be()?)),
+            ty::TYPE_FLOAT32 => ("float32", LiteralValue::Float32(reader.read_f32_be()?)),
+            ty::TYPE_FLOAT64 => ("float64", LiteralValue::Float64(reader.read_f64_be()?)),
+            ty::TYPE_BOOL => ("bool", LiteralValue::Bool(reader.read_u8()? != 0)),
+            ty::TYPE_STRING => ("string", LiteralValue::String(reader.read_string()?)),
+            ty::TYPE_BYTES => {
+                let length = reader.read_u16_be()? as usize;
+                ("bytes", LiteralValue::Bytes(reader.read_n_bytes(length)?))
+            }
+            ty::TYPE_TIMESTAMP => ("timestamp", LiteralValue::Timestamp(reader.read_i64_be()?)),
+            ty::TYPE_NULL => ("null", LiteralValue::Null),
+            _ => return Err(AILLError::InvalidOpCode(code)),
+        };
 
-    Ok(AstNode::Literal {
-        value_type: value_type.to_string(),
-        value,
-    })
-}
+        Ok(self.node_end(start, AstNode::Literal {
+            value_type: value_type.to_string(),
+            value,
+        }))
+    }
+
+    fn decode_struct(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Struct);
+        self.opcode()?; // consume BEGIN_STRUCT
+        let mut fields = BTreeMap::new();
+        let mut positional_idx: u16 = 0;
 
-fn decode_struct(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    reader.read_u8()?; // consume BEGIN_STRUCT
-    let mut fields = BTreeMap::new();
-    let mut positional_idx: u16 = 0;
-
-    while !reader.is_empty() && reader.peek()? != st::END_STRUCT {
-        if reader.peek()? == st::FIELD_SEP {
-            reader.read_u8()?;
-            continue;
-        }
-        if reader.peek()? == st::FIELD_ID {
-            reader.read_u8()?;
-            let field_code = reader.read_u16_be()?;
-            if let Some(value) = decode_expression(reader)? {
-                fields.insert(field_code, value);
+        while !self.reader.is_empty() && self.reader.peek()? != st::END_STRUCT {
+            if self.reader.peek()? == st::FIELD_SEP {
+                self.opcode()?;
+                continue;
             }
-        } else {
-            // Unnamed (positional) field
-            if let Some(expr) = decode_expression(reader)? {
-                fields.insert(positional_idx, expr);
-                positional_idx += 1;
+            if self.reader.peek()? == st::FIELD_ID {
+                self.opcode()?;
+                let field_code = self.reader.read_u16_be()?;
+                if !self.is_known_field(field_code) {
+                    if let Some(bytes) = self.raw_expression()? {
+                        fields.insert(field_code, AstNode::Raw { bytes });
+                    }
+                } else if let Some(value) = self.decode_expression()? {
+                    fields.insert(field_code, value);
+                }
+            } else {
+                // Unnamed (positional) field
+                if let Some(expr) = self.decode_expression()? {
+                    fields.insert(positional_idx, expr);
+                    positional_idx += 1;
+                }
             }
         }
-    }
-    if !reader.is_empty() {
-        reader.read_u8()?; // consume END_STRUCT
-    }
+        if !self.reader.is_empty() {
+            self.opcode()?; // consume END_STRUCT
+        }
 
-    Ok(AstNode::Struct { fields })
-}
+        Ok(self.node_end(start, AstNode::Struct { fields }))
+    }
 
-fn decode_list(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    reader.read_u8()?; // consume BEGIN_LIST
-    let count = reader.read_u16_be()?;
-    let mut elements = Vec::new();
+    fn decode_list(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::List);
+        self.opcode()?; // consume BEGIN_LIST
+        let count = self.reader.read_u16_be()?;
+        let mut elements = Vec::new();
 
-    for _ in 0..count {
-        if reader.is_empty() || reader.peek()? == st::END_LIST {
-            break;
-        }
-        if let Some(elem) = decode_expression(reader)? {
-            elements.push(elem);
+        while elements.len() < count as usize {
+            if self.at_container_end() {
+                break;
+            }
+            if let Some(elem) = self.decode_expression()? {
+                elements.push(elem);
+            }
         }
-    }
-    if !reader.is_empty() && reader.peek()? == st::END_LIST {
-        reader.read_u8()?; // consume END_LIST
-    }
+        self.close_container(st::END_LIST, count, elements.len())?;
 
-    Ok(AstNode::List { count, elements })
-}
+        Ok(self.node_end(start, AstNode::List { count, elements }))
+    }
 
-fn decode_map(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    reader.read_u8()?; // consume BEGIN_MAP
-    let count = reader.read_u16_be()?;
-    let mut pairs = Vec::new();
+    fn decode_map(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Map);
+        self.opcode()?; // consume BEGIN_MAP
+        let count = self.reader.read_u16_be()?;
+        let mut pairs = Vec::new();
 
-    for _ in 0..count {
-        if reader.is_empty() || reader.peek()? == st::END_MAP {
-            break;
+        for _ in 0..count {
+            if self.at_container_end() {
+                break;
+            }
+            let key = self.decode_expression()?.unwrap_or(AstNode::Literal {
+                value_type: "null".into(),
+                value: LiteralValue::Null,
+            });
+            if self.at_container_end() {
+                break;
+            }
+            let val = self.decode_expression()?.unwrap_or(AstNode::Literal {
+                value_type: "null".into(),
+                value: LiteralValue::Null,
+            });
+            pairs.push((key, val));
         }
-        let key = decode_expression(reader)?.unwrap_or(AstNode::Literal {
+        self.close_container(st::END_MAP, count, pairs.len())?;
+
+        Ok(self.node_end(start, AstNode::Map { count, pairs }))
+    }
+
+    fn decode_pragmatic(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Pragmatic);
+        let code = self.opcode()?;
+        let act_name = BASE_CODEBOOK[code as usize].mnemonic.to_string();
+        let expr = self.decode_expression()?.unwrap_or(AstNode::Literal {
             value_type: "null".into(),
             value: LiteralValue::Null,
         });
-        let val = decode_expression(reader)?.unwrap_or(AstNode::Literal {
+        Ok(self.node_end(start, AstNode::Pragmatic {
+            act: act_name,
+            expression: Box::new(expr),
+        }))
+    }
+
+    fn decode_modal(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Modal);
+        let code = self.opcode()?;
+        let mod_name = BASE_CODEBOOK[code as usize].mnemonic.to_string();
+        let extra = match code {
+            modal::PREDICTED => Some(self.reader.read_f16_be()? as f64),
+            modal::REPORTED => {
+                let _uuid = self.reader.read_uuid()?;
+                None // UUID handled separately; matching Python which stores it as extra
+            }
+            _ => None,
+        };
+        let expr = self.decode_expression()?.unwrap_or(AstNode::Literal {
             value_type: "null".into(),
             value: LiteralValue::Null,
         });
-        pairs.push((key, val));
+        Ok(self.node_end(start, AstNode::Modal {
+            modality: mod_name,
+            expression: Box::new(expr),
+            extra,
+        }))
     }
-    if !reader.is_empty() && reader.peek()? == st::END_MAP {
-        reader.read_u8()?;
+
+    fn decode_temporal(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Temporal);
+        let code = self.opcode()?;
+        let mod_name = BASE_CODEBOOK[code as usize].mnemonic.to_string();
+        let expr = self.decode_expression()?.unwrap_or(AstNode::Literal {
+            value_type: "null".into(),
+            value: LiteralValue::Null,
+        });
+        Ok(self.node_end(start, AstNode::Temporal {
+            modifier: mod_name,
+            expression: Box::new(expr),
+        }))
     }
 
-    Ok(AstNode::Map { count, pairs })
-}
+    fn decode_schema_ref(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::SchemaRef);
+        self.opcode()?;
+        let schema_id = self.reader.read_u16_be()?;
+        let expr = self.decode_expression()?.unwrap_or(AstNode::Literal {
+            value_type: "null".into(),
+            value: LiteralValue::Null,
+        });
+        if schema_id == MEASUREMENT_SCHEMA_ID {
+            if let Some(value) = measurement(&expr) {
+                return Ok(self.node_end(start, AstNode::Literal { value_type: "measurement".into(), value }));
+            }
+        }
+        Ok(self.node_end(start, AstNode::SchemaRef {
+            schema_id,
+            expression: Box::new(expr),
+        }))
+    }
 
-fn decode_pragmatic(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    let code = reader.read_u8()?;
-    let act_name = BASE_CODEBOOK[code as usize].mnemonic.to_string();
-    let expr = decode_expression(reader)?.unwrap_or(AstNode::Literal {
-        value_type: "null".into(),
-        value: LiteralValue::Null,
-    });
-    Ok(AstNode::Pragmatic {
-        act: act_name,
-        expression: Box::new(expr),
-    })
-}
+    fn decode_annotation(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::Annotated);
+        let code = self.opcode()?;
+        let mnemonic = if code == meta::CONFIDENCE {
+            let conf = self.reader.read_f16_be()?;
+            let _expr = self.decode_expression()?;
+            format!("CONFIDENCE({:.2})", conf)
+        } else if code == meta::LABEL {
+            let label = self.reader.read_string()?;
+            let _expr = self.decode_expression()?;
+            format!("LABEL({})", label)
+        } else {
+            format!("ANNOTATION_0x{:02X}", code)
+        };
+
+        Ok(self.node_end(start, AstNode::Annotated { code, mnemonic }))
+    }
 
-fn decode_modal(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    let code = reader.read_u8()?;
-    let mod_name = BASE_CODEBOOK[code as usize].mnemonic.to_string();
-    let extra = match code {
-        modal::PREDICTED => Some(reader.read_f16_be()? as f64),
-        modal::REPORTED => {
-            let _uuid = reader.read_uuid()?;
-            None // UUID handled separately; matching Python which stores it as extra
+    fn decode_domain_ref(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::DomainRef);
+        let code = self.opcode()?;
+        let level = match code {
+            esc::ESCAPE_L1 => 1,
+            esc::ESCAPE_L2 => 2,
+            esc::ESCAPE_L3 => 3,
+            _ => return Err(AILLError::InvalidOpCode(code)),
+        };
+        let domain_code = self.reader.read_u16_be()?;
+        let registry = self.bindings[level as usize - 1];
+        if self.unknown_domains != UnknownDomainPolicy::Allow
+            && !registry.is_some_and(|registry| self.config.resolves(registry, domain_code))
+        {
+            let unknown = UnknownDomainRef { offset: start, level, domain_code, registry };
+            if self.unknown_domains == UnknownDomainPolicy::Error {
+                return Err(unknown.into_error());
+            }
+            #[cfg(feature = "tracing")]
+            tracing::warn!(offset = start, level, domain_code, ?registry, "unknown domain reference");
+            self.warnings.push(unknown);
         }
-        _ => None,
-    };
-    let expr = decode_expression(reader)?.unwrap_or(AstNode::Literal {
-        value_type: "null".into(),
-        value: LiteralValue::Null,
-    });
-    Ok(AstNode::Modal {
-        modality: mod_name,
-        expression: Box::new(expr),
-        extra,
-    })
-}
+        Ok(self.node_end(start, AstNode::DomainRef { level, domain_code, registry }))
+    }
 
-fn decode_temporal(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    let code = reader.read_u8()?;
-    let mod_name = BASE_CODEBOOK[code as usize].mnemonic.to_string();
-    let expr = decode_expression(reader)?.unwrap_or(AstNode::Literal {
-        value_type: "null".into(),
-        value: LiteralValue::Null,
-    });
-    Ok(AstNode::Temporal {
-        modifier: mod_name,
-        expression: Box::new(expr),
-    })
+    fn decode_codebook_ref(&mut self) -> Result<AstNode, AILLError> {
+        let start = self.node_start(NodeKind::CodebookRef);
+        self.opcode()?;
+        let level = self.reader.read_u8()?;
+        if !(1..=3).contains(&level) {
+            return Err(AILLError::InvalidStructure(format!("CODEBOOK_REF to invalid escape level {}", level)));
+        }
+        let registry_id = u8::try_from(self.reader.read_u16_be()?)
+            .map_err(|_| AILLError::InvalidStructure("CODEBOOK_REF registry id out of range".into()))?;
+        self.bindings[level as usize - 1] = Some(registry_id);
+        Ok(self.node_end(start, AstNode::CodebookRef { level, registry_id }))
+    }
 }
 
-fn decode_annotation(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    let code = reader.read_u8()?;
-    let mnemonic = if code == meta::CONFIDENCE {
-        let conf = reader.read_f16_be()?;
-        let _expr = decode_expression(reader)?;
-        format!("CONFIDENCE({:.2})", conf)
-    } else if code == meta::LABEL {
-        let label = reader.read_string()?;
-        let _expr = decode_expression(reader)?;
-        format!("LABEL({})", label)
-    } else {
-        format!("ANNOTATION_0x{:02X}", code)
-    };
-
-    Ok(AstNode::Annotated { code, mnemonic })
+/// Decode a single epoch from wire bytes, in the plain or the extended
+/// header format (see [`EXTENDED_EPOCH`]), checking it with the unkeyed
+/// integrity scheme its header names. Epochs under a keyed scheme need
+/// [`decode_epoch_with`].
+/// Returns (DecodedEpoch, bytes_consumed).
+pub fn decode_epoch(data: &[u8], offset: usize) -> Result<(DecodedEpoch, usize), AILLError> {
+    decode_epoch_checked(data, offset, None)
 }
 
-fn decode_domain_ref(reader: &mut ByteReader) -> Result<AstNode, AILLError> {
-    let code = reader.read_u8()?;
-    let level = match code {
-        esc::ESCAPE_L1 => 1,
-        esc::ESCAPE_L2 => 2,
-        esc::ESCAPE_L3 => 3,
-        _ => return Err(AILLError::InvalidOpCode(code)),
-    };
-    let domain_code = reader.read_u16_be()?;
-    Ok(AstNode::DomainRef { level, domain_code })
+/// [`decode_epoch`] for epochs closed with `scheme`. An epoch naming any
+/// other scheme fails, so that a receiver expecting an HMAC cannot be fed
+/// epochs with a mere CRC.
+pub fn decode_epoch_with(
+    data: &[u8],
+    offset: usize,
+    scheme: &dyn IntegrityScheme,
+) -> Result<(DecodedEpoch, usize), AILLError> {
+    decode_epoch_checked(data, offset, Some(scheme))
 }
 
-/// Decode a single epoch from wire bytes.
-/// Returns (DecodedEpoch, bytes_consumed).
-pub fn decode_epoch(data: &[u8], offset: usize) -> Result<(DecodedEpoch, usize), AILLError> {
-    if data.len() - offset < 5 {
+fn decode_epoch_checked(
+    data: &[u8],
+    offset: usize,
+    expected: Option<&dyn IntegrityScheme>,
+) -> Result<(DecodedEpoch, usize), AILLError> {
+    let data = &data[offset..];
+    if data.len() < 5 {
         return Err(AILLError::InvalidStructure(
             "Insufficient data for epoch header".into(),
         ));
     }
 
-    let seq_num = u16::from_be_bytes([data[offset], data[offset + 1]]);
-    let payload_len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+    let seq_num = u16::from_be_bytes([data[0], data[1]]);
+    let len_word = u16::from_be_bytes([data[2], data[3]]);
+    let payload_len = (len_word & !EXTENDED_EPOCH) as usize;
+
+    let (flags, timestamp_us, header_len) = if len_word & EXTENDED_EPOCH == 0 {
+        (None, None, 4)
+    } else {
+        let flags = data[4];
+        if flags & epoch_flags::TIMESTAMP == 0 {
+            (Some(flags), None, 5)
+        } else if data.len() < 13 {
+            return Err(AILLError::InvalidStructure(
+                "Insufficient data for epoch header".into(),
+            ));
+        } else {
+            let ts = i64::from_be_bytes(data[5..13].try_into().unwrap());
+            (Some(flags), Some(ts), 13)
+        }
+    };
 
-    if data.len() - offset < 4 + payload_len + 1 {
+    let scheme_id = flags.map_or(0, integrity::scheme_id);
+    let scheme = match expected {
+        Some(scheme) if scheme.id() == scheme_id => scheme,
+        Some(scheme) => {
+            return Err(AILLError::InvalidStructure(format!(
+                "Epoch integrity scheme {} where {} was expected",
+                scheme_id,
+                scheme.id()
+            )))
+        }
+        None => integrity::unkeyed(scheme_id).ok_or_else(|| {
+            AILLError::InvalidStructure(format!("Epoch integrity scheme {} needs a key", scheme_id))
+        })?,
+    };
+    let body_len = header_len + payload_len;
+    if data.len() < body_len + scheme.tag_len() {
         return Err(AILLError::InvalidStructure(format!(
             "Incomplete epoch payload (expected {} bytes)",
             payload_len
         )));
     }
 
-    let payload = data[offset + 4..offset + 4 + payload_len].to_vec();
-    let received_crc = data[offset + 4 + payload_len];
+    let payload = data[header_len..body_len].to_vec();
 
-    // Verify CRC over (seq + len + payload)
-    let computed_crc = crc8(&data[offset..offset + 4 + payload_len]);
-    let crc_ok = received_crc == computed_crc;
+    // Verify the check over (header + payload)
+    let crc_ok = scheme.verify(&data[..body_len], &data[body_len..body_len + scheme.tag_len()]);
+    #[cfg(feature = "tracing")]
+    if !crc_ok {
+        tracing::warn!(seq_num, scheme_id, "epoch integrity check failed");
+    }
 
-    let total_consumed = 4 + payload_len + 1;
+    let total_consumed = body_len + scheme.tag_len();
     Ok((
         DecodedEpoch {
             seq_num,
             payload,
             crc_ok,
+            flags,
+            timestamp_us,
         },
         total_consumed,
     ))
 }
-
-// ═══════════════════════════════════════════════════════════════════════
-// Pretty Printer
-// ═══════════════════════════════════════════════════════════════════════
-
-/// Produce a human-readable representation of a decoded AILL AST.
-pub fn pretty_print(node: &AstNode, indent: usize) -> String {
-    let prefix = "  ".repeat(indent);
-    let mut lines = Vec::new();
-
-    match node {
-        AstNode::Utterance { meta, body } => {
-            lines.push(format!("{}UTTERANCE:", prefix));
-            lines.push(pretty_print_meta(meta, indent + 1));
-            lines.push(format!("{}  BODY:", prefix));
-            for expr in body {
-                lines.push(pretty_print(expr, indent + 2));
-            }
-        }
-        AstNode::Literal { value_type, value } => {
-            let val_str = match value {
-                LiteralValue::Int8(v) => v.to_string(),
-                LiteralValue::Int16(v) => v.to_string(),
-                LiteralValue::Int32(v) => v.to_string(),
-                LiteralValue::Int64(v) => v.to_string(),
-                LiteralValue::Uint8(v) => v.to_string(),
-                LiteralValue::Uint16(v) => v.to_string(),
-                LiteralValue::Uint32(v) => v.to_string(),
-                LiteralValue::Uint64(v) => v.to_string(),
-                LiteralValue::Float16(v) => format!("{}", v),
-                LiteralValue::Float32(v) => format!("{}", v),
-                LiteralValue::Float64(v) => format!("{}", v),
-                LiteralValue::Bool(v) => v.to_string(),
-                LiteralValue::String(v) => v.clone(),
-                LiteralValue::Bytes(v) => format!("{:?}", v),
-                LiteralValue::Timestamp(v) => v.to_string(),
-                LiteralValue::Null => "None".to_string(),
-            };
-            lines.push(format!("{}{}: {}", prefix, value_type, val_str));
-        }
-        AstNode::Struct { fields } => {
-            lines.push(format!("{}STRUCT:", prefix));
-            for (fid, val) in fields {
-                lines.push(format!("{}  field_0x{:04X}:", prefix, fid));
-                lines.push(pretty_print(val, indent + 2));
-            }
-        }
-        AstNode::List { count, elements } => {
-            lines.push(format!("{}LIST[{}]:", prefix, count));
-            for elem in elements {
-                lines.push(pretty_print(elem, indent + 1));
-            }
-        }
-        AstNode::Map { count, pairs } => {
-            lines.push(format!("{}MAP[{}]:", prefix, count));
-            for (k, v) in pairs {
-                lines.push(format!("{}  key: {}", prefix, pretty_print(k, 0).trim()));
-                lines.push(format!("{}  val: {}", prefix, pretty_print(v, 0).trim()));
-            }
-        }
-        AstNode::Pragmatic { act, expression } => {
-            lines.push(format!("{}{}:", prefix, act));
-            lines.push(pretty_print(expression, indent + 1));
-        }
-        AstNode::Modal { modality, expression, extra } => {
-            let extra_str = match extra {
-                Some(v) => format!(" (horizon={}ms)", v),
-                None => String::new(),
-            };
-            lines.push(format!("{}[{}{}]:", prefix, modality, extra_str));
-            lines.push(pretty_print(expression, indent + 1));
-        }
-        AstNode::Temporal { modifier, expression } => {
-            lines.push(format!("{}<{}>:", prefix, modifier));
-            lines.push(pretty_print(expression, indent + 1));
-        }
-        AstNode::DomainRef { level, domain_code } => {
-            let level_name = match level {
-                1 => "L1",
-                2 => "L2",
-                3 => "L3",
-                _ => "?",
-            };
-            lines.push(format!("{}REF({}: DOMAIN_0x{:04X})", prefix, level_name, domain_code));
-        }
-        AstNode::ContextRef { sct_index } => {
-            lines.push(format!("{}SCT_REF[{}]", prefix, sct_index));
-        }
-        AstNode::Code { mnemonic, .. } => {
-            lines.push(format!("{}{}", prefix, mnemonic));
-        }
-        AstNode::Annotated { mnemonic, .. } => {
-            lines.push(format!("{}{}", prefix, mnemonic));
-        }
-    }
-
-    lines.join("\n")
-}
-
-fn pretty_print_meta(meta: &MetaHeader, indent: usize) -> String {
-    let prefix = "  ".repeat(indent);
-    let mut lines = Vec::new();
-    lines.push(format!(
-        "{}META: confidence={:.2} priority={} timestamp={}",
-        prefix, meta.confidence, meta.priority, meta.timestamp_us
-    ));
-    if let Some(ref dest) = meta.dest_agent {
-        let hex: String = dest.iter().map(|b| format!("{:02x}", b)).collect();
-        lines.push(format!("{}  dest_agent={}", prefix, hex));
-    }
-    if let Some(seq) = meta.seqnum {
-        lines.push(format!("{}  seqnum={}", prefix, seq));
-    }
-    lines.join("\n")
-}
@@ -0,0 +1,163 @@
+//! Duplicate suppression for received utterances.
+//!
+//! Relays flood broadcasts and senders retransmit, so the same utterance
+//! can reach an agent several times. A [`DedupCache`] remembers what was
+//! already delivered and tells the receive pipeline to drop repeats before
+//! they reach the application. Utterances are identified by their COMM-1
+//! MSG_ID when they carry one, and otherwise by the HASH_REF hash of their
+//! wire bytes.
+//!
+//! The cache holds at most `capacity` keys, evicting the least recently
+//! seen, and optionally forgets a key `ttl_us` after it was first seen so
+//! a deliberate resend much later is delivered again.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ast::AstNode;
+use crate::conversation::utterance_hash;
+use crate::correlator;
+use crate::decoder::AILLDecoder;
+use crate::error::AILLError;
+
+/// Default number of keys a [`DedupCache`] remembers.
+pub const DEFAULT_DEDUP_CAPACITY: usize = 256;
+
+/// What identifies an utterance for duplicate suppression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DedupKey {
+    /// COMM-1 MSG_ID tag.
+    MsgId(u64),
+    /// [`utterance_hash`] of the wire bytes, for utterances without a MSG_ID.
+    Hash(u32),
+}
+
+impl DedupKey {
+    /// Key of an already decoded utterance whose wire bytes are `wire`.
+    pub fn of(ast: &AstNode, wire: &[u8]) -> Self {
+        match correlator::msg_id(ast) {
+            Some(id) => DedupKey::MsgId(id),
+            None => DedupKey::Hash(utterance_hash(wire)),
+        }
+    }
+}
+
+struct Entry {
+    first_us: i64,
+    /// Position in the recency order.
+    tick: u64,
+}
+
+/// Bounded LRU set of recently delivered utterances.
+pub struct DedupCache {
+    capacity: usize,
+    ttl_us: Option<i64>,
+    entries: HashMap<DedupKey, Entry>,
+    /// Keys by last use, oldest first.
+    order: BTreeMap<u64, DedupKey>,
+    tick: u64,
+}
+
+impl Default for DedupCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEDUP_CAPACITY)
+    }
+}
+
+impl DedupCache {
+    /// Cache remembering up to `capacity` keys (at least one), with no TTL.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), ttl_us: None, entries: HashMap::new(), order: BTreeMap::new(), tick: 0 }
+    }
+
+    /// Forget keys `ttl_us` after they were first seen.
+    pub fn with_ttl(mut self, ttl_us: i64) -> Self {
+        self.ttl_us = Some(ttl_us);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Record `key` at `now_us`; returns `true` if it is new, `false` for a
+    /// duplicate. A duplicate becomes the most recently seen key.
+    pub fn insert(&mut self, key: DedupKey, now_us: i64) -> bool {
+        self.tick += 1;
+        let ttl = self.ttl_us;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.order.remove(&entry.tick);
+            self.order.insert(self.tick, key);
+            entry.tick = self.tick;
+            let expired = ttl.is_some_and(|ttl| now_us.saturating_sub(entry.first_us) >= ttl);
+            if expired {
+                entry.first_us = now_us;
+            }
+            return expired;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, Entry { first_us: now_us, tick: self.tick });
+        self.order.insert(self.tick, key);
+        true
+    }
+
+    /// Whether a decoded utterance should be delivered, recording it.
+    pub fn admit_decoded(&mut self, ast: &AstNode, wire: &[u8], now_us: i64) -> bool {
+        self.insert(DedupKey::of(ast, wire), now_us)
+    }
+
+    /// Decode `wire` and report whether it should be delivered.
+    pub fn admit(&mut self, wire: &[u8], now_us: i64) -> Result<bool, AILLError> {
+        let ast = AILLDecoder::new().decode_utterance(wire)?;
+        Ok(self.admit_decoded(&ast, wire, now_us))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::AILLEncoder;
+
+    fn utterance(msg_id: Option<u64>, value: u8) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance();
+        if let Some(id) = msg_id {
+            e.msg_id(id);
+        }
+        e.assert_().uint8(value);
+        e.end_utterance()
+    }
+
+    #[test]
+    fn suppresses_repeats_by_msg_id_or_hash() {
+        let mut cache = DedupCache::new(2).with_ttl(1_000);
+        assert!(cache.admit(&utterance(Some(7), 1), 0).unwrap());
+        // Same MSG_ID, different bytes: still a duplicate
+        assert!(!cache.admit(&utterance(Some(7), 2), 10).unwrap());
+        assert!(cache.admit(&utterance(None, 1), 20).unwrap());
+        assert!(!cache.admit(&utterance(None, 1), 30).unwrap());
+        assert_eq!(cache.len(), 2);
+
+        // The hash key was seen more recently, so MSG_ID 7 is evicted
+        assert!(cache.insert(DedupKey::MsgId(8), 40));
+        assert!(!cache.admit(&utterance(None, 1), 50).unwrap());
+        assert!(cache.insert(DedupKey::MsgId(7), 60));
+
+        // Past the TTL a key counts as new again
+        assert!(!cache.insert(DedupKey::MsgId(7), 1_059));
+        assert!(cache.insert(DedupKey::MsgId(7), 1_060));
+        assert!(!cache.insert(DedupKey::MsgId(7), 1_500));
+    }
+}
@@ -0,0 +1,355 @@
+//! Delta encoding between consecutive utterances.
+//!
+//! A [`DeltaEncoder`] compares each new state utterance against the last one
+//! it sent. When only literal values changed it emits a patch utterance:
+//!
+//! ```text
+//! UTTERANCE  CORRECT MAP[n] { LIST[k](uint16 path...) => new literal, ... }
+//! ```
+//!
+//! where each path addresses a literal in the baseline: the first step is
+//! the body index, then one step per struct field id, list index or map
+//! pair index. Pragmatic, modal and temporal wrappers consume no step.
+//! Structural changes, and every `refresh_interval`-th message, are sent as
+//! the full state instead. A [`DeltaDecoder`] applies either form and keeps
+//! the reconstructed state.
+
+use crate::ast::{AstNode, LiteralValue, MetaHeader};
+use crate::decoder::AILLDecoder;
+use crate::error::AILLError;
+use crate::templates::encode_ast;
+
+const CORRECT: &str = "CORRECT";
+
+/// One literal change: the path to it and its new value.
+pub type Patch = (Vec<u16>, AstNode);
+
+/// Sender side: turns successive state utterances into full or patch
+/// utterances.
+pub struct DeltaEncoder {
+    baseline: Option<AstNode>,
+    refresh_interval: u32,
+    since_refresh: u32,
+}
+
+impl DeltaEncoder {
+    /// Send the full state at least every `refresh_interval` messages
+    /// (0 or 1 disables patching).
+    pub fn new(refresh_interval: u32) -> Self {
+        Self { baseline: None, refresh_interval, since_refresh: 0 }
+    }
+
+    /// Force the next message to carry the full state.
+    pub fn reset(&mut self) {
+        self.baseline = None;
+    }
+
+    /// Encode `state`, returning the wire bytes and whether they carry a
+    /// patch (`true`) or the full state (`false`).
+    pub fn encode(&mut self, state: &AstNode) -> Result<(Vec<u8>, bool), AILLError> {
+        let full = encode_ast(state)?;
+        let refresh_due = self.since_refresh + 1 >= self.refresh_interval;
+
+        if let (Some(base), false) = (&self.baseline, refresh_due) {
+            if let Some(patches) = diff(base, state) {
+                let AstNode::Utterance { meta, .. } = state else { unreachable!() };
+                let patch = encode_ast(&patch_utterance(meta.clone(), patches))?;
+                if patch.len() < full.len() {
+                    self.baseline = Some(state.clone());
+                    self.since_refresh += 1;
+                    return Ok((patch, true));
+                }
+            }
+        }
+
+        self.baseline = Some(state.clone());
+        self.since_refresh = 0;
+        Ok((full, false))
+    }
+}
+
+/// Receiver side: reconstructs state from full and patch utterances.
+#[derive(Default)]
+pub struct DeltaDecoder {
+    state: Option<AstNode>,
+}
+
+impl DeltaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most recently reconstructed state.
+    pub fn state(&self) -> Option<&AstNode> {
+        self.state.as_ref()
+    }
+
+    /// Decode `data` and apply it, returning the updated state.
+    pub fn apply(&mut self, data: &[u8]) -> Result<&AstNode, AILLError> {
+        let ast = AILLDecoder::new().decode_utterance(data)?;
+        self.apply_ast(ast)
+    }
+
+    /// Apply an already decoded full or patch utterance.
+    pub fn apply_ast(&mut self, ast: AstNode) -> Result<&AstNode, AILLError> {
+        let AstNode::Utterance { meta, body } = ast else {
+            return Err(AILLError::InvalidStructure("delta input must be an utterance".into()));
+        };
+        match parse_patches(&body)? {
+            None => self.state = Some(AstNode::Utterance { meta, body }),
+            Some(patches) => {
+                let Some(AstNode::Utterance { body: cur_body, .. }) = self.state.as_ref() else {
+                    return Err(AILLError::InvalidStructure("delta patch received without a baseline".into()));
+                };
+                // All or nothing: a bad patch leaves the state as it was
+                let mut body = cur_body.clone();
+                for (path, value) in patches {
+                    apply_patch(&mut body, &path, value)?;
+                }
+                self.state = Some(AstNode::Utterance { meta, body });
+            }
+        }
+        Ok(self.state.as_ref().unwrap())
+    }
+}
+
+/// Literal-level differences from `base` to `new`, or `None` if the two
+/// utterances differ structurally.
+pub fn diff(base: &AstNode, new: &AstNode) -> Option<Vec<Patch>> {
+    let (AstNode::Utterance { body: a, .. }, AstNode::Utterance { body: b, .. }) = (base, new) else {
+        return None;
+    };
+    if a.len() != b.len() {
+        return None;
+    }
+    let mut out = Vec::new();
+    let mut path = Vec::new();
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        path.push(i as u16);
+        if !diff_node(x, y, &mut path, &mut out) {
+            return None;
+        }
+        path.pop();
+    }
+    Some(out)
+}
+
+fn diff_node(a: &AstNode, b: &AstNode, path: &mut Vec<u16>, out: &mut Vec<Patch>) -> bool {
+    match (a, b) {
+        (AstNode::Literal { .. }, AstNode::Literal { .. }) => {
+            if a != b {
+                out.push((path.clone(), b.clone()));
+            }
+            true
+        }
+        (AstNode::Struct { fields: fa }, AstNode::Struct { fields: fb }) => {
+            if !fa.keys().eq(fb.keys()) {
+                return false;
+            }
+            fa.iter().zip(fb.values()).all(|((fid, x), y)| {
+                path.push(*fid);
+                let ok = diff_node(x, y, path, out);
+                path.pop();
+                ok
+            })
+        }
+        (AstNode::List { count: ca, elements: ea }, AstNode::List { count: cb, elements: eb }) => {
+            ca == cb && ea.len() == eb.len() && ea.iter().zip(eb).enumerate().all(|(i, (x, y))| {
+                path.push(i as u16);
+                let ok = diff_node(x, y, path, out);
+                path.pop();
+                ok
+            })
+        }
+        (AstNode::Map { count: ca, pairs: pa }, AstNode::Map { count: cb, pairs: pb }) => {
+            ca == cb && pa.len() == pb.len() && pa.iter().zip(pb).enumerate().all(|(i, ((ka, x), (kb, y)))| {
+                if ka != kb {
+                    return false;
+                }
+                path.push(i as u16);
+                let ok = diff_node(x, y, path, out);
+                path.pop();
+                ok
+            })
+        }
+        (AstNode::Pragmatic { act: x, expression: ea }, AstNode::Pragmatic { act: y, expression: eb })
+        | (AstNode::Temporal { modifier: x, expression: ea }, AstNode::Temporal { modifier: y, expression: eb }) => {
+            x == y && diff_node(ea, eb, path, out)
+        }
+        (
+            AstNode::SchemaRef { schema_id: x, expression: ea },
+            AstNode::SchemaRef { schema_id: y, expression: eb },
+        ) => x == y && diff_node(ea, eb, path, out),
+        (
+            AstNode::Modal { modality: x, expression: ea, extra: xa },
+            AstNode::Modal { modality: y, expression: eb, extra: xb },
+        ) => x == y && xa == xb && diff_node(ea, eb, path, out),
+        _ => a == b,
+    }
+}
+
+fn patch_utterance(meta: MetaHeader, patches: Vec<Patch>) -> AstNode {
+    AstNode::Utterance {
+        meta,
+        body: vec![AstNode::Pragmatic {
+            act: CORRECT.into(),
+            expression: Box::new(patch_map(patches)),
+        }],
+    }
+}
+
+/// Build the `MAP { LIST(uint16 path) => literal }` patch expression.
+pub(crate) fn patch_map(patches: Vec<Patch>) -> AstNode {
+    let pairs: Vec<(AstNode, AstNode)> = patches
+        .into_iter()
+        .map(|(path, value)| {
+            let elements: Vec<AstNode> = path
+                .into_iter()
+                .map(|step| AstNode::Literal { value_type: "uint16".into(), value: LiteralValue::Uint16(step) })
+                .collect();
+            (AstNode::List { count: elements.len() as u16, elements }, value)
+        })
+        .collect();
+    AstNode::Map { count: pairs.len() as u16, pairs }
+}
+
+/// Recognise a patch body. Returns `None` for anything else, which is
+/// treated as a full state.
+fn parse_patches(body: &[AstNode]) -> Result<Option<Vec<Patch>>, AILLError> {
+    match body {
+        [AstNode::Pragmatic { act, expression }] if act == CORRECT => parse_patch_map(expression),
+        _ => Ok(None),
+    }
+}
+
+/// Parse a patch map built by [`patch_map`]. Returns `None` if `expr` is
+/// not shaped like one.
+pub(crate) fn parse_patch_map(expr: &AstNode) -> Result<Option<Vec<Patch>>, AILLError> {
+    let AstNode::Map { pairs, .. } = expr else {
+        return Ok(None);
+    };
+    let mut patches = Vec::with_capacity(pairs.len());
+    for (key, value) in pairs {
+        let AstNode::List { elements, .. } = key else {
+            return Ok(None);
+        };
+        let mut path = Vec::with_capacity(elements.len());
+        for step in elements {
+            match step {
+                AstNode::Literal { value: LiteralValue::Uint16(s), .. } => path.push(*s),
+                _ => return Ok(None),
+            }
+        }
+        if path.is_empty() || !matches!(value, AstNode::Literal { .. }) {
+            return Err(AILLError::InvalidStructure("malformed delta patch entry".into()));
+        }
+        patches.push((path, value.clone()));
+    }
+    Ok(Some(patches))
+}
+
+/// Replace the literal at `path` in an utterance body.
+pub(crate) fn apply_patch(body: &mut [AstNode], path: &[u16], value: AstNode) -> Result<(), AILLError> {
+    let bad_path = || AILLError::InvalidStructure(format!("delta path {:?} does not match baseline", path));
+    let mut node = body.get_mut(path[0] as usize).ok_or_else(bad_path)?;
+    for &step in &path[1..] {
+        node = unwrap_mut(node);
+        node = match node {
+            AstNode::Struct { fields } => fields.get_mut(&step),
+            AstNode::List { elements, .. } => elements.get_mut(step as usize),
+            AstNode::Map { pairs, .. } => pairs.get_mut(step as usize).map(|(_, v)| v),
+            _ => None,
+        }
+        .ok_or_else(bad_path)?;
+    }
+    let node = unwrap_mut(node);
+    if !matches!(node, AstNode::Literal { .. }) {
+        return Err(bad_path());
+    }
+    *node = value;
+    Ok(())
+}
+
+/// Descend through pragmatic/modal/temporal/schema wrappers.
+fn unwrap_mut(mut node: &mut AstNode) -> &mut AstNode {
+    while let AstNode::Pragmatic { expression, .. }
+    | AstNode::Modal { expression, .. }
+    | AstNode::Temporal { expression, .. }
+    | AstNode::SchemaRef { expression, .. } = node
+    {
+        node = expression;
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLEncoder;
+
+    fn telemetry(x: f32, y: f32, mode: &str) -> AstNode {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().l1_ref(0x0000);
+        e.begin_struct().field(0x0001).float32(x).field(0x0002).float32(y).field(0x0003).string(mode).end_struct();
+        AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+    }
+
+    #[test]
+    fn patches_track_sender_state() {
+        let mut enc = DeltaEncoder::new(10);
+        let mut dec = DeltaDecoder::new();
+
+        let (wire, patched) = enc.encode(&telemetry(1.0, 2.0, "cruise")).unwrap();
+        assert!(!patched);
+        dec.apply(&wire).unwrap();
+
+        let next = telemetry(1.5, 2.0, "cruise");
+        let (wire, patched) = enc.encode(&next).unwrap();
+        assert!(patched);
+        assert!(wire.len() < encode_ast(&next).unwrap().len());
+        assert_eq!(dec.apply(&wire).unwrap(), &next);
+    }
+
+    #[test]
+    fn structural_change_and_refresh_send_full_state() {
+        let mut enc = DeltaEncoder::new(3);
+        assert!(!enc.encode(&telemetry(0.0, 0.0, "a")).unwrap().1);
+        assert!(enc.encode(&telemetry(1.0, 0.0, "a")).unwrap().1);
+        assert!(enc.encode(&telemetry(2.0, 0.0, "a")).unwrap().1);
+        assert!(!enc.encode(&telemetry(3.0, 0.0, "a")).unwrap().1); // refresh due
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().float32(1.0);
+        let other = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        assert!(!enc.encode(&other).unwrap().1);
+    }
+
+    #[test]
+    fn patch_without_baseline_is_rejected() {
+        let mut enc = DeltaEncoder::new(10);
+        enc.encode(&telemetry(0.0, 0.0, "a")).unwrap();
+        let (patch, _) = enc.encode(&telemetry(0.0, 9.0, "a")).unwrap();
+        assert!(DeltaDecoder::new().apply(&patch).is_err());
+    }
+
+    #[test]
+    fn failed_patch_leaves_the_state_alone() {
+        let mut dec = DeltaDecoder::new();
+        let base = telemetry(1.0, 2.0, "cruise");
+        dec.apply_ast(base.clone()).unwrap();
+
+        let AstNode::Utterance { meta, .. } = telemetry(0.0, 0.0, "x") else { unreachable!() };
+        let value = |v| AstNode::Literal { value_type: "float32".into(), value: LiteralValue::Float32(v) };
+        let bad = patch_utterance(meta, vec![(vec![1, 0x0001], value(99.0)), (vec![7], value(5.0))]);
+        let err = dec.apply_ast(bad).unwrap_err();
+        assert!(err.to_string().contains("delta path [7] does not match baseline"), "{}", err);
+        assert_eq!(dec.state(), Some(&base));
+    }
+
+    #[test]
+    fn diff_paths_include_body_index_and_field() {
+        let patches = diff(&telemetry(0.0, 0.0, "a"), &telemetry(0.0, 5.0, "a")).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].0, vec![1, 0x0002]);
+    }
+}
@@ -1,35 +1,131 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::error::AILLError;
 use crate::codebook::base::{fc, ty, st, modal, pragma, meta, arith, rel, quant, esc};
-use crate::wire::ByteWriter;
-use crate::wire::crc8::crc8;
+use crate::agent::AgentId;
+use crate::ast::{AstNode, LiteralValue, MEASUREMENT_SCHEMA_ID};
+use crate::templates::{encode_ast, write_frame_control, write_literal};
+use crate::wire::{epoch_flags, encode_f16_slice, encode_float16_checked, ByteWriter, Float16Overflow, EXTENDED_EPOCH, LONG_STRINGS_VERSION};
+use crate::wire::integrity::{Crc8, IntegrityScheme};
 
 /// Maximum payload size per epoch.
 pub const MAX_EPOCH_PAYLOAD: usize = 8192;
 
+/// What [`AILLEncoder::float16`] does with a value it cannot represent
+/// within the tolerance set by [`AILLEncoder::with_float16_tolerance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Float16Fallback {
+    /// Encode as FLOAT16 anyway and record a [`Float16Warning`].
+    Warn,
+    /// Encode as FLOAT32 instead and record a [`Float16Warning`].
+    Upgrade,
+}
+
+/// A FLOAT16 literal whose rounding error exceeded the tolerance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Float16Warning {
+    pub value: f32,
+    /// `decoded - value` for the float16 encoding; infinite on overflow.
+    pub error: f32,
+    /// Whether the value was written as FLOAT32 instead.
+    pub upgraded: bool,
+}
+
+/// A struct, list or map being written, see [`AILLEncoder::try_end_utterance`].
+struct OpenContainer {
+    code: u8,
+    declared: u16,
+    items: usize,
+    /// Id and offset of each FIELD_ID of a struct.
+    fields: Vec<(u16, usize)>,
+    /// A CODEBOOK_REF was written inside.
+    rebinds: bool,
+}
+
 /// Fluent builder for encoding AILL utterances into wire format bytes.
 pub struct AILLEncoder {
     stream: ByteWriter,
-    _uuid: [u8; 16],
+    agent_id: AgentId,
     in_utterance: bool,
+    long_strings: bool,
+    open: Vec<OpenContainer>,
+    count_errors: Vec<AILLError>,
+    float16_check: Option<(f32, Float16Fallback)>,
+    float16_warnings: Vec<Float16Warning>,
+    canonical_fields: bool,
 }
 
 impl AILLEncoder {
     pub fn new() -> Self {
         Self {
             stream: ByteWriter::new(),
-            _uuid: [0u8; 16],
+            agent_id: AgentId::NIL,
             in_utterance: false,
+            long_strings: false,
+            open: Vec::new(),
+            count_errors: Vec::new(),
+            float16_check: None,
+            float16_warnings: Vec::new(),
+            canonical_fields: true,
         }
     }
 
-    pub fn with_uuid(uuid: [u8; 16]) -> Self {
+    pub fn with_uuid(uuid: impl Into<AgentId>) -> Self {
         Self {
             stream: ByteWriter::new(),
-            _uuid: uuid,
+            agent_id: uuid.into(),
             in_utterance: false,
+            long_strings: false,
+            open: Vec::new(),
+            count_errors: Vec::new(),
+            float16_check: None,
+            float16_warnings: Vec::new(),
+            canonical_fields: true,
         }
     }
 
+    /// Check FLOAT16 literals: a value whose relative rounding error
+    /// exceeds `tolerance`, or which overflows ±65504, is handled per
+    /// `fallback`.
+    pub fn with_float16_tolerance(mut self, tolerance: f32, fallback: Float16Fallback) -> Self {
+        self.float16_check = Some((tolerance, fallback));
+        self
+    }
+
+    /// Tag each utterance with [`LONG_STRINGS_VERSION`] and give its
+    /// strings varint length prefixes, so they may exceed 65535 bytes.
+    /// Only for peers that decode that version.
+    pub fn with_long_strings(mut self) -> Self {
+        self.long_strings = true;
+        self
+    }
+
+    /// Whether a struct's FIELD_ID fields are rewritten in ascending id
+    /// order when it closes, so equal structs encode to equal bytes however
+    /// the caller ordered them. On by default. Structs with positional
+    /// values are always left as written.
+    pub fn with_canonical_fields(mut self, canonical: bool) -> Self {
+        self.canonical_fields = canonical;
+        self
+    }
+
+    /// FLOAT16 literals that exceeded the tolerance so far.
+    pub fn float16_warnings(&self) -> &[Float16Warning] {
+        &self.float16_warnings
+    }
+
+    pub fn take_float16_warnings(&mut self) -> Vec<Float16Warning> {
+        std::mem::take(&mut self.float16_warnings)
+    }
+
+    /// The id given to [`with_uuid`](Self::with_uuid); nil by default.
+    pub fn agent_id(&self) -> AgentId {
+        self.agent_id
+    }
+
     fn code(&mut self, code: u8) -> &mut Self {
+        self.track(code);
         self.stream.write_u8(code);
         self
     }
@@ -45,7 +141,7 @@ impl AILLEncoder {
         confidence: f32,
         priority: u8,
         timestamp_us: Option<i64>,
-        dest_agent: Option<&[u8; 16]>,
+        dest_agent: Option<AgentId>,
         seqnum: Option<u32>,
     ) -> &mut Self {
         let ts = timestamp_us.unwrap_or(0);
@@ -63,12 +159,18 @@ impl AILLEncoder {
         // Optional meta fields
         if let Some(dest) = dest_agent {
             self.code(meta::DEST_AGENT);
-            self.stream.write_uuid(dest);
+            self.stream.write_uuid(dest.as_bytes());
         }
         if let Some(seq) = seqnum {
             self.code(meta::SEQNUM);
             self.stream.write_u32_be(seq);
         }
+        if self.long_strings {
+            let (major, minor) = LONG_STRINGS_VERSION;
+            self.version_tag(major, minor);
+        } else {
+            self.stream.set_long_strings(false);
+        }
 
         self.in_utterance = true;
         self
@@ -77,7 +179,10 @@ impl AILLEncoder {
     pub fn end_utterance(&mut self) -> Vec<u8> {
         self.code(fc::END_UTTERANCE);
         self.in_utterance = false;
-        self.stream.to_bytes()
+        let bytes = self.stream.to_bytes();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(bytes = bytes.len(), "utterance encoded");
+        bytes
     }
 
     // ── Pragmatic acts ──
@@ -120,6 +225,10 @@ impl AILLEncoder {
     pub fn end_struct(&mut self) -> &mut Self { self.code(st::END_STRUCT) }
 
     pub fn field(&mut self, field_code: u16) -> &mut Self {
+        let at = self.stream.len();
+        if let Some(c) = self.open.last_mut().filter(|c| c.code == st::BEGIN_STRUCT) {
+            c.fields.push((field_code, at));
+        }
         self.code(st::FIELD_ID);
         self.stream.write_u16_be(field_code);
         self
@@ -128,19 +237,52 @@ impl AILLEncoder {
     pub fn begin_list(&mut self, count: u16) -> &mut Self {
         self.code(st::BEGIN_LIST);
         self.stream.write_u16_be(count);
-        self
+        self.declare(count)
     }
 
+    /// Close a list. A mismatch with the count given to `begin_list` is
+    /// reported by [`try_end_utterance`](Self::try_end_utterance), or here
+    /// by [`try_end_list`](Self::try_end_list).
     pub fn end_list(&mut self) -> &mut Self { self.code(st::END_LIST) }
 
+    /// Close a list, failing with [`AILLError::CountMismatch`] if it holds
+    /// a different number of elements than `begin_list` declared. The list
+    /// is closed either way.
+    pub fn try_end_list(&mut self) -> Result<&mut Self, AILLError> {
+        self.try_close(st::END_LIST)
+    }
+
     pub fn begin_map(&mut self, count: u16) -> &mut Self {
         self.code(st::BEGIN_MAP);
         self.stream.write_u16_be(count);
-        self
+        self.declare(count)
     }
 
+    /// Close a map. Pairs stay in the order they were written.
     pub fn end_map(&mut self) -> &mut Self { self.code(st::END_MAP) }
 
+    /// Close a map like [`try_end_list`](Self::try_end_list), counting pairs.
+    pub fn try_end_map(&mut self) -> Result<&mut Self, AILLError> {
+        self.try_close(st::END_MAP)
+    }
+
+    fn try_close(&mut self, code: u8) -> Result<&mut Self, AILLError> {
+        let before = self.count_errors.len();
+        self.code(code);
+        match self.count_errors.get(before) {
+            Some(e) => Err(e.clone()),
+            None => Ok(self),
+        }
+    }
+
+    /// Emit SCHEMA_REF(0x2E) + u16. Follow with a struct of positional
+    /// values in schema order, see [`schema`](crate::schema).
+    pub fn schema_ref(&mut self, schema_id: u16) -> &mut Self {
+        self.code(st::SCHEMA_REF);
+        self.stream.write_u16_be(schema_id);
+        self
+    }
+
     // ── Typed values ──
 
     pub fn int8(&mut self, val: i8) -> &mut Self {
@@ -185,7 +327,24 @@ impl AILLEncoder {
         self
     }
 
+    pub fn uint64(&mut self, val: u64) -> &mut Self {
+        self.code(ty::TYPE_UINT64);
+        self.stream.write_u64_be(val);
+        self
+    }
+
     pub fn float16(&mut self, val: f32) -> &mut Self {
+        if let Some((tolerance, fallback)) = self.float16_check {
+            let (_, error) = encode_float16_checked(val, Float16Overflow::Infinity)
+                .expect("infinity policy never fails");
+            if error.abs() > tolerance * val.abs() {
+                let upgraded = fallback == Float16Fallback::Upgrade;
+                self.float16_warnings.push(Float16Warning { value: val, error, upgraded });
+                if upgraded {
+                    return self.float32(val);
+                }
+            }
+        }
         self.code(ty::TYPE_FLOAT16);
         self.stream.write_f16_be(val);
         self
@@ -197,6 +356,12 @@ impl AILLEncoder {
         self
     }
 
+    /// Emit `val ± sigma` as a [`MEASUREMENT_SCHEMA_ID`] struct of two
+    /// FLOAT32 values.
+    pub fn float32_with_sigma(&mut self, val: f32, sigma: f32) -> &mut Self {
+        self.schema_ref(MEASUREMENT_SCHEMA_ID).begin_struct().float32(val).float32(sigma).end_struct()
+    }
+
     pub fn float64(&mut self, val: f64) -> &mut Self {
         self.code(ty::TYPE_FLOAT64);
         self.stream.write_f64_be(val);
@@ -209,12 +374,42 @@ impl AILLEncoder {
         self
     }
 
+    /// Emit a string. One longer than [`max_string_len`](Self::max_string_len)
+    /// has its length prefix wrap, and
+    /// [`try_end_utterance`](Self::try_end_utterance) fails; use
+    /// [`try_string`](Self::try_string) to catch it here instead.
     pub fn string(&mut self, val: &str) -> &mut Self {
         self.code(ty::TYPE_STRING);
         self.stream.write_string(val);
         self
     }
 
+    /// Like [`string`](Self::string), but fails instead of emitting a
+    /// string whose length does not fit the prefix.
+    pub fn try_string(&mut self, val: &str) -> Result<&mut Self, AILLError> {
+        if val.len() > self.max_string_len() {
+            return Err(AILLError::EncoderError(format!(
+                "string of {} bytes exceeds the {}-byte limit",
+                val.len(),
+                self.max_string_len()
+            )));
+        }
+        Ok(self.string(val))
+    }
+
+    /// Longest string the current utterance can carry.
+    pub fn max_string_len(&self) -> usize {
+        self.stream.max_string_len()
+    }
+
+    /// Emit a byte value. One longer than 65535 bytes has its length prefix
+    /// wrap, and [`try_end_utterance`](Self::try_end_utterance) fails.
+    pub fn bytes(&mut self, val: &[u8]) -> &mut Self {
+        self.code(ty::TYPE_BYTES);
+        self.stream.write_bytes_val(val);
+        self
+    }
+
     pub fn null(&mut self) -> &mut Self {
         self.code(ty::TYPE_NULL)
     }
@@ -235,6 +430,25 @@ impl AILLEncoder {
         self.end_list()
     }
 
+    /// A list of float16 literals, converted in one batch unless a
+    /// [float16 tolerance](Self::with_float16_tolerance) is set.
+    pub fn list_of_float16(&mut self, values: &[f32]) -> &mut Self {
+        self.begin_list(values.len() as u16);
+        if self.float16_check.is_some() {
+            for &v in values {
+                self.float16(v);
+            }
+        } else {
+            let mut halves = Vec::with_capacity(values.len() * 2);
+            encode_f16_slice(values, &mut halves);
+            for half in halves.chunks_exact(2) {
+                self.code(ty::TYPE_FLOAT16);
+                self.stream.write_raw(half);
+            }
+        }
+        self.end_list()
+    }
+
     pub fn list_of_int32(&mut self, values: &[i32]) -> &mut Self {
         self.begin_list(values.len() as u16);
         for &v in values {
@@ -243,6 +457,32 @@ impl AILLEncoder {
         self.end_list()
     }
 
+    pub fn list_of_uint64(&mut self, values: &[u64]) -> &mut Self {
+        self.begin_list(values.len() as u16);
+        for &v in values {
+            self.uint64(v);
+        }
+        self.end_list()
+    }
+
+    // ── Convenience: string-keyed maps ──
+
+    pub fn map_from_str_pairs(&mut self, pairs: &[(&str, &str)]) -> &mut Self {
+        self.begin_map(pairs.len() as u16);
+        for &(k, v) in pairs {
+            self.string(k).string(v);
+        }
+        self.end_map()
+    }
+
+    pub fn map_from_str_f32(&mut self, pairs: &[(&str, f32)]) -> &mut Self {
+        self.begin_map(pairs.len() as u16);
+        for &(k, v) in pairs {
+            self.string(k).float32(v);
+        }
+        self.end_map()
+    }
+
     // ── Domain codebook references ──
 
     pub fn l1_ref(&mut self, code: u16) -> &mut Self {
@@ -263,6 +503,15 @@ impl AILLEncoder {
         self
     }
 
+    /// Bind escape `level` (1-3) to domain registry `registry_id` for the
+    /// rest of the utterance: `CODEBOOK_REF <uint8 level> <uint16 registry>`.
+    pub fn codebook_ref(&mut self, level: u8, registry_id: u8) -> &mut Self {
+        self.code(esc::CODEBOOK_REF);
+        self.stream.write_u8(level);
+        self.stream.write_u16_be(registry_id as u16);
+        self
+    }
+
     // ── Operators ──
 
     pub fn op(&mut self, opcode: u8) -> &mut Self { self.code(opcode) }
@@ -304,13 +553,9 @@ impl AILLEncoder {
     // ── Meta field helpers ──
 
     /// Emit SOURCE_AGENT(0x92) + 16 UUID bytes
-    pub fn source_agent(&mut self, uuid: &[u8]) -> &mut Self {
+    pub fn source_agent(&mut self, uuid: impl Into<AgentId>) -> &mut Self {
         self.code(meta::SOURCE_AGENT);
-        // Write exactly 16 bytes (pad or truncate)
-        let mut buf = [0u8; 16];
-        let len = uuid.len().min(16);
-        buf[..len].copy_from_slice(&uuid[..len]);
-        self.stream.write_uuid(&buf);
+        self.stream.write_uuid(uuid.into().as_bytes());
         self
     }
 
@@ -321,12 +566,77 @@ impl AILLEncoder {
         self
     }
 
+    /// Emit TRACE_ID(0x9C) + u64
+    pub fn trace_id(&mut self, trace_id: u64) -> &mut Self {
+        self.code(meta::TRACE_ID);
+        self.stream.write_u64_be(trace_id);
+        self
+    }
+
+    /// Emit VERSION_TAG(0x9B) + major + minor. From
+    /// [`LONG_STRINGS_VERSION`] on, later strings take varint lengths.
+    pub fn version_tag(&mut self, major: u16, minor: u16) -> &mut Self {
+        self.code(meta::VERSION_TAG);
+        self.stream.write_u16_be(major).write_u16_be(minor);
+        self.stream.set_long_strings((major, minor) >= LONG_STRINGS_VERSION);
+        self
+    }
+
+    /// Emit COST(0x9D) + f16
+    pub fn cost(&mut self, cost: f32) -> &mut Self {
+        self.code(meta::COST);
+        self.stream.write_f16_be(cost);
+        self
+    }
+
     // ── Negotiation pragmatic acts ──
 
     pub fn propose(&mut self) -> &mut Self { self.code(pragma::PROPOSE) }
     pub fn accept_pragma(&mut self) -> &mut Self { self.code(pragma::ACCEPT) }
     pub fn reject(&mut self) -> &mut Self { self.code(pragma::REJECT) }
 
+    // ── Frame control ──
+
+    fn frame_control(&mut self, code: u8, operands: &[u32]) -> &mut Self {
+        self.track(code);
+        write_frame_control(&mut self.stream, code, operands).expect("operands match the opcode");
+        self
+    }
+
+    pub fn pause(&mut self) -> &mut Self { self.frame_control(fc::PAUSE, &[]) }
+    pub fn resume(&mut self) -> &mut Self { self.frame_control(fc::RESUME, &[]) }
+    pub fn retransmit(&mut self, epoch_seq: u16) -> &mut Self { self.frame_control(fc::RETRANSMIT, &[epoch_seq as u32]) }
+    pub fn ack_epoch(&mut self, epoch_seq: u16) -> &mut Self { self.frame_control(fc::ACK_EPOCH, &[epoch_seq as u32]) }
+
+    /// NACK_EPOCH with a [`RejectReason`](crate::reliability::RejectReason) code.
+    pub fn nack_epoch(&mut self, epoch_seq: u16, reason: u8) -> &mut Self {
+        self.frame_control(fc::NACK_EPOCH, &[epoch_seq as u32, reason as u32])
+    }
+
+    /// SYNC_MARK carrying the low 32 bits of a microsecond timestamp.
+    pub fn sync_mark(&mut self, timestamp_us: i64) -> &mut Self {
+        self.frame_control(fc::SYNC_MARK, &[timestamp_us as u32])
+    }
+
+    pub fn fragment_start(&mut self, total_frags: u16) -> &mut Self {
+        self.frame_control(fc::FRAGMENT_START, &[total_frags as u32])
+    }
+    pub fn fragment_cont(&mut self, frag_index: u16) -> &mut Self {
+        self.frame_control(fc::FRAGMENT_CONT, &[frag_index as u32])
+    }
+    pub fn fragment_end(&mut self, crc32_full: u32) -> &mut Self { self.frame_control(fc::FRAGMENT_END, &[crc32_full]) }
+    pub fn echo_request(&mut self, nonce: u32) -> &mut Self { self.frame_control(fc::ECHO_REQUEST, &[nonce]) }
+    pub fn echo_reply(&mut self, nonce: u32) -> &mut Self { self.frame_control(fc::ECHO_REPLY, &[nonce]) }
+
+    // ── Utterance references ──
+
+    /// Emit HASH_REF(0x96) + u32
+    pub fn hash_ref(&mut self, hash: u32) -> &mut Self {
+        self.code(meta::HASH_REF);
+        self.stream.write_u32_be(hash);
+        self
+    }
+
     // ── Raw byte access ──
 
     pub fn raw(&mut self, data: &[u8]) -> &mut Self {
@@ -334,9 +644,122 @@ impl AILLEncoder {
         self
     }
 
+    pub(crate) fn literal(&mut self, value: &LiteralValue) -> &mut Self {
+        write_literal(&mut self.stream, value);
+        self.item();
+        self
+    }
+
     pub fn current_size(&self) -> usize {
         self.stream.len()
     }
+
+    /// Fill in the count of the innermost open list or map, written at
+    /// `at` by `begin_list(0)` or `begin_map(0)`.
+    pub(crate) fn patch_count(&mut self, at: usize, count: u16) -> &mut Self {
+        self.stream.patch_u16_be(at, count);
+        self.declare(count)
+    }
+
+    // ── Count tracking ──
+
+    /// Note that `code` was written, keeping count of the expressions in
+    /// each open container.
+    fn track(&mut self, code: u8) {
+        match code {
+            fc::START_UTTERANCE => {
+                self.open.clear();
+                self.count_errors.clear();
+                self.stream.take_length_error();
+            }
+            st::BEGIN_STRUCT | st::BEGIN_LIST | st::BEGIN_MAP => {
+                self.open.push(OpenContainer { code, declared: 0, items: 0, fields: Vec::new(), rebinds: false });
+            }
+            st::END_STRUCT | st::END_LIST | st::END_MAP => {
+                if let Some(c) = self.open.pop() {
+                    if code == st::END_STRUCT && c.code == st::BEGIN_STRUCT {
+                        self.sort_fields(&c);
+                    }
+                    let actual = if c.code == st::BEGIN_MAP { c.items.div_ceil(2) } else { c.items };
+                    if c.code != st::BEGIN_STRUCT && actual != c.declared as usize {
+                        self.count_errors.push(AILLError::CountMismatch { declared: c.declared, actual });
+                    }
+                }
+                self.item();
+            }
+            // Prefixes of the expression that follows
+            0x60..=0x8F | meta::CONFIDENCE | meta::LABEL | st::SCHEMA_REF | st::FIELD_ID | st::FIELD_SEP => {}
+            fc::END_UTTERANCE | esc::NOP | esc::COMMENT => {}
+            esc::CODEBOOK_REF => {
+                self.open.iter_mut().for_each(|c| c.rebinds = true);
+                self.item();
+            }
+            _ => self.item(),
+        }
+    }
+
+    /// Rewrite the fields of a struct about to be closed in ascending id
+    /// order. Moving named fields past positional ones could change which
+    /// value wins a clashing id, and moving a CODEBOOK_REF past domain
+    /// references which registry they resolve to, so structs with either
+    /// are left alone.
+    fn sort_fields(&mut self, c: &OpenContainer) {
+        let fields = &c.fields;
+        if !self.canonical_fields || c.rebinds || fields.len() != c.items || fields.is_sorted_by_key(|f| f.0) {
+            return;
+        }
+        let start = fields[0].1;
+        let tail = self.stream.take_from(start);
+        let ends = fields.iter().skip(1).map(|f| f.1).chain([start + tail.len()]);
+        let mut chunks: Vec<(u16, &[u8])> =
+            fields.iter().zip(ends).map(|(&(id, from), to)| (id, &tail[from - start..to - start])).collect();
+        // Stable, so the last of several equal ids still wins on decode
+        chunks.sort_by_key(|chunk| chunk.0);
+        for (_, chunk) in chunks {
+            self.stream.write_raw(chunk);
+        }
+    }
+
+    /// A complete expression was written.
+    fn item(&mut self) {
+        if let Some(c) = self.open.last_mut() {
+            c.items += 1;
+        }
+    }
+
+    fn declare(&mut self, count: u16) -> &mut Self {
+        if let Some(c) = self.open.last_mut() {
+            c.declared = count;
+        }
+        self
+    }
+
+    /// Like [`end_utterance`](Self::end_utterance), but fails if a string
+    /// or byte value written since `start_utterance` was too long for its
+    /// length prefix, or with [`AILLError::CountMismatch`] if a list or map
+    /// closed since then held a different number of elements than declared.
+    pub fn try_end_utterance(&mut self) -> Result<Vec<u8>, AILLError> {
+        let bytes = self.end_utterance();
+        if let Some(e) = self.stream.take_length_error() {
+            return Err(e);
+        }
+        match self.count_errors.first() {
+            Some(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %e, "utterance has a container count mismatch");
+                Err(e.clone())
+            }
+            None => Ok(bytes),
+        }
+    }
+
+    /// [`try_end_utterance`](Self::try_end_utterance), then empty the
+    /// stream so the next utterance comes out on its own.
+    pub(crate) fn take_utterance(&mut self) -> Result<Vec<u8>, AILLError> {
+        let bytes = self.try_end_utterance();
+        self.stream = ByteWriter::new();
+        bytes
+    }
 }
 
 impl Default for AILLEncoder {
@@ -350,6 +773,10 @@ pub struct EpochBuilder {
     seq: u16,
     epochs: Vec<Vec<u8>>,
     current_payload: ByteWriter,
+    /// Flags for the extended header, `None` for plain 5-byte headers.
+    flags: Option<u8>,
+    timestamp_us: Option<i64>,
+    integrity: Box<dyn IntegrityScheme>,
 }
 
 impl EpochBuilder {
@@ -358,9 +785,33 @@ impl EpochBuilder {
             seq: 0,
             epochs: Vec::new(),
             current_payload: ByteWriter::new(),
+            flags: None,
+            timestamp_us: None,
+            integrity: Box::new(Crc8),
         }
     }
 
+    /// Emit extended headers carrying `flags` (see [`epoch_flags`]).
+    /// The TIMESTAMP bit is managed by [`set_timestamp`](Self::set_timestamp)
+    /// and the INTEGRITY bits by [`with_integrity`](Self::with_integrity).
+    pub fn with_flags(mut self, flags: u8) -> Self {
+        self.flags = Some(flags & !(epoch_flags::TIMESTAMP | epoch_flags::INTEGRITY));
+        self
+    }
+
+    /// Close epochs with `scheme` instead of a CRC-8. Any other scheme
+    /// names itself in the extended header.
+    pub fn with_integrity(mut self, scheme: impl IntegrityScheme + 'static) -> Self {
+        self.integrity = Box::new(scheme);
+        self
+    }
+
+    /// Stamp the epochs flushed from now on with `timestamp_us`, or stop
+    /// stamping them. A timestamp implies the extended header.
+    pub fn set_timestamp(&mut self, timestamp_us: Option<i64>) {
+        self.timestamp_us = timestamp_us;
+    }
+
     pub fn write(&mut self, data: &[u8]) {
         if self.current_payload.len() + data.len() > MAX_EPOCH_PAYLOAD {
             self.flush();
@@ -368,24 +819,67 @@ impl EpochBuilder {
         self.current_payload.write_raw(data);
     }
 
+    /// Finish the utterance `encoder` is building and frame it, leaving the
+    /// encoder empty for the next one. Everything the encoder holds is
+    /// framed as one utterance, so start it fresh or reuse it only through
+    /// this method. Returns the sequence number of the epoch it went into,
+    /// see [`write_whole`](Self::write_whole).
+    pub fn write_utterance(&mut self, encoder: &mut AILLEncoder) -> Result<u16, AILLError> {
+        let wire = encoder.take_utterance()?;
+        self.write_whole(&wire)
+    }
+
+    /// Encode `node` and frame it like [`write_utterance`](Self::write_utterance).
+    pub fn write_ast(&mut self, node: &AstNode) -> Result<u16, AILLError> {
+        let wire = encode_ast(node)?;
+        self.write_whole(&wire)
+    }
+
+    /// Frame a whole utterance so a lost epoch costs as few others as
+    /// possible: it joins the open epoch only if it fits there entirely,
+    /// and starts a new one otherwise. Returns the sequence number of that
+    /// epoch, which may still be open. Expressions must not be split
+    /// across epochs, so an utterance longer than [`MAX_EPOCH_PAYLOAD`]
+    /// fails; split large data across utterances instead.
+    pub fn write_whole(&mut self, utterance: &[u8]) -> Result<u16, AILLError> {
+        if utterance.len() > MAX_EPOCH_PAYLOAD {
+            return Err(AILLError::EncoderError(format!(
+                "utterance of {} bytes does not fit a {}-byte epoch",
+                utterance.len(),
+                MAX_EPOCH_PAYLOAD
+            )));
+        }
+        if self.current_payload.len() + utterance.len() > MAX_EPOCH_PAYLOAD {
+            self.flush();
+        }
+        self.current_payload.write_raw(utterance);
+        Ok(self.seq)
+    }
+
     pub fn flush(&mut self) {
         if self.current_payload.is_empty() {
             return;
         }
         let payload = self.current_payload.to_bytes();
-        let mut epoch = ByteWriter::new();
-        epoch.write_u16_be(self.seq);
-        epoch.write_u16_be(payload.len() as u16);
-        epoch.write_raw(&payload);
-        // CRC-8 over (seq + length + payload)
-        let epoch_bytes = epoch.to_bytes();
-        let checksum = crc8(&epoch_bytes);
-        epoch.write_u8(checksum);
-        self.epochs.push(epoch.into_bytes());
-        self.seq += 1;
+        let flags = self.flags.or(self.timestamp_us.map(|_| 0));
+        #[cfg(feature = "tracing")]
+        tracing::trace!(seq = self.seq, payload = payload.len(), "epoch framed");
+        self.epochs.push(frame_epoch_with(self.seq, flags, self.timestamp_us, &payload, self.integrity.as_ref()));
+        self.seq = self.seq.wrapping_add(1);
         self.current_payload = ByteWriter::new();
     }
 
+    /// Continue numbering from `seq`, e.g. after restoring a session.
+    pub fn with_seq(mut self, seq: u16) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// Sequence number of the next epoch.
+    pub fn next_seq(&self) -> u16 {
+        self.seq
+    }
+
     pub fn get_epochs(&mut self) -> Vec<Vec<u8>> {
         self.flush();
         self.epochs.clone()
@@ -397,3 +891,586 @@ impl Default for EpochBuilder {
         Self::new()
     }
 }
+
+/// Wrap `payload` in an epoch header and CRC-8.
+pub(crate) fn frame_epoch(seq: u16, payload: &[u8]) -> Vec<u8> {
+    frame_epoch_with(seq, None, None, payload, &Crc8)
+}
+
+/// Wrap `payload` in a plain header, or an extended one if `flags` is set
+/// or `integrity` is not CRC-8, and close it with `integrity`'s check.
+fn frame_epoch_with(
+    seq: u16,
+    flags: Option<u8>,
+    timestamp_us: Option<i64>,
+    payload: &[u8],
+    integrity: &dyn IntegrityScheme,
+) -> Vec<u8> {
+    let scheme = (integrity.id() << epoch_flags::INTEGRITY.trailing_zeros()) & epoch_flags::INTEGRITY;
+    let flags = if scheme == 0 { flags } else { Some(flags.unwrap_or(0) | scheme) };
+    let mut epoch = ByteWriter::new();
+    epoch.write_u16_be(seq);
+    match (flags, timestamp_us) {
+        (None, _) => epoch.write_u16_be(payload.len() as u16),
+        (Some(flags), None) => epoch.write_u16_be(payload.len() as u16 | EXTENDED_EPOCH).write_u8(flags),
+        (Some(flags), Some(ts)) => epoch
+            .write_u16_be(payload.len() as u16 | EXTENDED_EPOCH)
+            .write_u8(flags | epoch_flags::TIMESTAMP)
+            .write_i64_be(ts),
+    };
+    epoch.write_raw(payload);
+    // Check over (header + payload)
+    let check = integrity.tag(&epoch.to_bytes());
+    epoch.write_raw(&check);
+    epoch.into_bytes()
+}
+
+/// Default cap on unacknowledged bytes for an [`EpochWriter`].
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4 * MAX_EPOCH_PAYLOAD;
+
+/// Writes epochs straight into a sink as their payloads fill up, instead
+/// of collecting them like [`EpochBuilder`].
+///
+/// Epochs count as in flight from the moment they are written until the
+/// application reports them delivered with [`acknowledge`](Self::acknowledge).
+/// An epoch that would exceed the in-flight limit, or that the sink
+/// refuses with `WouldBlock`, is held back and the congestion callback is
+/// invoked; held epochs go out as acknowledgements free up room. A sink
+/// that returns `WouldBlock` must do so before accepting any byte of the
+/// epoch.
+pub struct EpochWriter<W: Write> {
+    sink: W,
+    seq: u16,
+    payload: Vec<u8>,
+    max_payload: usize,
+    max_in_flight: usize,
+    in_flight: usize,
+    held: VecDeque<Vec<u8>>,
+    on_congestion: Option<Box<dyn FnMut(usize) + Send>>,
+}
+
+impl<W: Write> EpochWriter<W> {
+    pub fn new(sink: W) -> Self {
+        Self {
+            sink,
+            seq: 0,
+            payload: Vec::new(),
+            max_payload: MAX_EPOCH_PAYLOAD,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            in_flight: 0,
+            held: VecDeque::new(),
+            on_congestion: None,
+        }
+    }
+
+    /// Cap epoch payloads at `max_payload` bytes (at most [`MAX_EPOCH_PAYLOAD`]).
+    pub fn with_max_payload(mut self, max_payload: usize) -> Self {
+        self.max_payload = max_payload.clamp(1, MAX_EPOCH_PAYLOAD);
+        self
+    }
+
+    /// Hold epochs back while more than `max_in_flight` bytes are
+    /// unacknowledged. One epoch is always allowed through an idle link.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Call `callback` with the in-flight byte count whenever an epoch has
+    /// to be held back.
+    pub fn on_congestion(mut self, callback: impl FnMut(usize) + Send + 'static) -> Self {
+        self.on_congestion = Some(Box::new(callback));
+        self
+    }
+
+    /// Continue numbering from `seq`, e.g. after restoring a session.
+    pub fn with_seq(mut self, seq: u16) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// Sequence number of the next epoch.
+    pub fn next_seq(&self) -> u16 {
+        self.seq
+    }
+
+    /// Largest epoch payload written.
+    pub fn max_payload(&self) -> usize {
+        self.max_payload
+    }
+
+    /// Bytes written to the sink and not yet acknowledged.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Bytes of complete epochs waiting for room in the sink.
+    pub fn held(&self) -> usize {
+        self.held.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_congested(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Append payload bytes, emitting every epoch they fill.
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), AILLError> {
+        while !data.is_empty() {
+            let n = (self.max_payload - self.payload.len()).min(data.len());
+            self.payload.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.payload.len() == self.max_payload {
+                self.emit()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a complete utterance ahead of everything else, in an epoch of
+    /// its own flagged [`PRIORITY`](epoch_flags::PRIORITY). It skips the
+    /// held epochs and the in-flight limit, and the utterance being
+    /// fragmented continues afterwards. Fails if `utterance` does not fit
+    /// one epoch.
+    pub fn write_urgent(&mut self, utterance: &[u8]) -> Result<(), AILLError> {
+        if utterance.len() > self.max_payload {
+            return Err(AILLError::EncoderError(format!(
+                "urgent utterance of {} bytes does not fit a {}-byte epoch",
+                utterance.len(),
+                self.max_payload
+            )));
+        }
+        let epoch = frame_epoch_with(self.seq, Some(epoch_flags::PRIORITY), None, utterance, &Crc8);
+        self.seq = self.seq.wrapping_add(1);
+        match self.sink.write_all(&epoch) {
+            Ok(()) => self.in_flight += epoch.len(),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.held.push_front(epoch);
+                self.congested();
+            }
+            Err(e) => return Err(sink_error(e)),
+        }
+        Ok(())
+    }
+
+    /// Emit the partial epoch, if any, and flush the sink.
+    pub fn flush(&mut self) -> Result<(), AILLError> {
+        if self.payload.is_empty() {
+            self.drain()?;
+        } else {
+            self.emit()?;
+        }
+        self.sink.flush().map_err(sink_error)
+    }
+
+    /// Record `bytes` as delivered and send held epochs that now fit.
+    pub fn acknowledge(&mut self, bytes: usize) -> Result<(), AILLError> {
+        self.in_flight = self.in_flight.saturating_sub(bytes);
+        self.drain()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+    /// The sink, discarding held epochs and any partial payload.
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+
+    fn emit(&mut self) -> Result<(), AILLError> {
+        let payload = std::mem::take(&mut self.payload);
+        self.held.push_back(frame_epoch(self.seq, &payload));
+        self.seq = self.seq.wrapping_add(1);
+        self.drain()
+    }
+
+    /// Write held epochs in order until the in-flight limit is reached or
+    /// the sink would block.
+    fn drain(&mut self) -> Result<(), AILLError> {
+        while let Some(epoch) = self.held.front() {
+            let blocked = if self.in_flight > 0 && self.in_flight + epoch.len() > self.max_in_flight {
+                true
+            } else {
+                match self.sink.write_all(epoch) {
+                    Ok(()) => false,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => true,
+                    Err(e) => return Err(sink_error(e)),
+                }
+            };
+            if blocked {
+                self.congested();
+                return Ok(());
+            }
+            self.in_flight += epoch.len();
+            self.held.pop_front();
+        }
+        Ok(())
+    }
+
+    fn congested(&mut self) {
+        let in_flight = self.in_flight;
+        if let Some(callback) = self.on_congestion.as_mut() {
+            callback(in_flight);
+        }
+    }
+}
+
+fn sink_error(e: io::Error) -> AILLError {
+    AILLError::EncoderError(format!("epoch sink: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AstNode, LiteralValue};
+    use crate::AILLDecoder;
+
+    #[test]
+    fn long_strings_need_the_version_tag() {
+        let text = "x".repeat(70_000);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_();
+        assert!(e.try_string(&text).is_err());
+        assert!(e.try_string("short").is_ok());
+
+        let mut e = AILLEncoder::new().with_long_strings();
+        e.start_utterance().assert_().try_string(&text).unwrap();
+        let wire = e.end_utterance();
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        let AstNode::Utterance { body, .. } = &ast else { panic!() };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        assert!(matches!(expression.as_ref(), AstNode::Literal { value: LiteralValue::String(s), .. } if s.len() == 70_000));
+        assert_eq!(crate::templates::encode_ast(&ast).unwrap(), wire);
+
+        // Short strings get a one-byte prefix
+        let mut e = AILLEncoder::new().with_long_strings();
+        e.start_utterance().string("hi");
+        let long = e.end_utterance();
+        let mut e = AILLEncoder::new();
+        e.start_utterance().string("hi");
+        assert_eq!(long.len(), e.end_utterance().len() + 5 - 1);
+    }
+
+    #[test]
+    fn wrapped_length_prefixes_are_reported() {
+        let text = "x".repeat(70_000);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().string(&text);
+        let err = e.try_end_utterance().unwrap_err();
+        assert_eq!(err, AILLError::EncoderError("string of 70000 bytes exceeds the 65535-byte limit".into()));
+        e.start_utterance().assert_().bytes(&[0; 70_000]);
+        assert!(matches!(e.try_end_utterance(), Err(AILLError::EncoderError(m)) if m.starts_with("byte value of 70000")));
+        // The next utterance starts clean
+        e.start_utterance().assert_().string("short");
+        assert!(e.try_end_utterance().is_ok());
+
+        let ast = AstNode::Utterance {
+            meta: Default::default(),
+            body: vec![AstNode::Literal { value_type: "bytes".into(), value: LiteralValue::Bytes(vec![0; 70_000]) }],
+        };
+        assert!(crate::templates::encode_ast(&ast).is_err());
+    }
+
+    #[test]
+    fn string_keyed_maps_round_trip() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().begin_list(3);
+        e.map_from_str_pairs(&[("mode", "idle"), ("zone", "B")]);
+        e.map_from_str_f32(&[("x", 1.5), ("y", -2.0)]);
+        e.list_of_uint64(&[u64::MAX, 0]).end_list();
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+
+        let AstNode::Utterance { body, .. } = &ast else { panic!() };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        let AstNode::List { elements, .. } = expression.as_ref() else { panic!() };
+        let str_value = |node: Option<&AstNode>| match node {
+            Some(AstNode::Literal { value: LiteralValue::String(s), .. }) => s.clone(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(str_value(elements[0].get_str_key("zone")), "B");
+        assert!(elements[0].get_str_key("x").is_none());
+        assert!(matches!(
+            elements[1].get_str_key("y"),
+            Some(AstNode::Literal { value: LiteralValue::Float32(v), .. }) if *v == -2.0
+        ));
+        assert!(elements[2].get_str_key("x").is_none());
+        let AstNode::List { elements: ids, .. } = &elements[2] else { panic!() };
+        assert_eq!(ids[0], AstNode::Literal { value_type: "uint64".into(), value: LiteralValue::Uint64(u64::MAX) });
+    }
+
+    #[test]
+    fn epoch_writer_holds_epochs_while_congested() {
+        use std::sync::{Arc, Mutex};
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&calls);
+        let mut w = EpochWriter::new(Vec::new())
+            .with_max_payload(10)
+            .with_max_in_flight(20)
+            .on_congestion(move |in_flight| seen.lock().unwrap().push(in_flight));
+
+        w.write(&[7; 25]).unwrap();
+        assert_eq!((w.in_flight(), w.held()), (15, 15));
+        assert!(w.is_congested());
+        w.flush().unwrap();
+        assert_eq!(w.held(), 15 + 10);
+
+        w.acknowledge(15).unwrap();
+        assert_eq!((w.in_flight(), w.held()), (15, 10));
+        w.acknowledge(15).unwrap();
+        assert!(!w.is_congested());
+        assert_eq!(*calls.lock().unwrap(), vec![15, 15, 15]);
+
+        let wire = w.into_inner();
+        let mut b = EpochBuilder::new();
+        b.write(&[7; 10]);
+        b.flush();
+        b.write(&[7; 10]);
+        b.flush();
+        b.write(&[7; 5]);
+        assert_eq!(wire, b.get_epochs().concat());
+    }
+
+    #[test]
+    fn extended_epoch_headers_are_autodetected() {
+        use crate::decoder::decode_epoch;
+
+        let mut b = EpochBuilder::new().with_flags(epoch_flags::COMPRESSED | epoch_flags::PRIORITY);
+        b.write(b"first");
+        b.flush();
+        b.set_timestamp(Some(-42));
+        b.write(b"second");
+        let epochs = b.get_epochs();
+        assert_eq!(epochs[0].len(), 6 + 5);
+        assert_eq!(epochs[1].len(), 14 + 6);
+
+        let (first, used) = decode_epoch(&epochs[0], 0).unwrap();
+        assert_eq!((first.payload.as_slice(), first.flags, first.timestamp_us, used), (&b"first"[..], Some(0x09), None, 11));
+        let (second, _) = decode_epoch(&epochs[1], 0).unwrap();
+        assert!(second.crc_ok);
+        assert_eq!((second.flags, second.timestamp_us), (Some(0x89), Some(-42)));
+
+        // A timestamp alone switches to the extended header; plain stays 5 bytes
+        let mut b = EpochBuilder::new();
+        b.write(b"x");
+        b.flush();
+        b.set_timestamp(Some(1));
+        b.write(b"y");
+        let epochs = b.get_epochs();
+        assert_eq!((epochs[0].len(), epochs[1].len()), (6, 15));
+        let (plain, _) = decode_epoch(&epochs[0], 0).unwrap();
+        assert_eq!((plain.flags, plain.timestamp_us), (None, None));
+        assert_eq!(decode_epoch(&epochs[1], 0).unwrap().0.flags, Some(epoch_flags::TIMESTAMP));
+    }
+
+    #[test]
+    fn utterances_start_an_epoch_unless_they_fit() {
+        use crate::decoder::{decode_epoch, AILLDecoder};
+
+        let mut b = EpochBuilder::new().with_seq(9);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().bytes(&[1; 100]);
+        assert_eq!(b.write_utterance(&mut e), Ok(9));
+        e.start_utterance().assert_().bytes(&[2; MAX_EPOCH_PAYLOAD - 100]);
+        assert_eq!(b.write_utterance(&mut e), Ok(10));
+        // Too long for any epoch: refused rather than cut mid-expression
+        let long = AILLEncoder::new().start_utterance().assert_().bytes(&[3; MAX_EPOCH_PAYLOAD + 100]).end_utterance();
+        assert!(matches!(b.write_whole(&long), Err(AILLError::EncoderError(_))));
+        // Small enough to share the epoch holding the second one
+        let short = AILLEncoder::new().start_utterance().query().null().end_utterance();
+        let ast = AILLDecoder::new().decode_utterance(&short).unwrap();
+        assert_eq!(b.write_ast(&ast), Ok(10));
+        assert_eq!(b.next_seq(), 10);
+
+        let payloads: Vec<Vec<u8>> =
+            b.get_epochs().iter().map(|epoch| decode_epoch(epoch, 0).unwrap().0.payload).collect();
+        assert_eq!(payloads.len(), 2);
+        assert!(payloads[1].ends_with(&short));
+        assert_eq!(AILLDecoder::new().utterances(&payloads[1]).count(), 2);
+
+        e.start_utterance().begin_list(2).null().end_list();
+        assert!(b.write_utterance(&mut e).is_err());
+    }
+
+    #[test]
+    fn epochs_close_with_the_configured_integrity_scheme() {
+        use crate::decoder::{decode_epoch, decode_epoch_with};
+        use crate::wire::integrity::{scheme_id, Crc16};
+
+        let mut b = EpochBuilder::new().with_integrity(Crc16).with_flags(epoch_flags::PRIORITY);
+        b.write(&[1, 2, 3]);
+        let epoch = &b.get_epochs()[0];
+        assert_eq!(epoch.len(), 5 + 3 + 2);
+        let (decoded, used) = decode_epoch(epoch, 0).unwrap();
+        assert!(decoded.crc_ok && used == epoch.len());
+        assert_eq!(decoded.flags.map(scheme_id), Some(1));
+        assert_eq!(decoded.flags.unwrap() & epoch_flags::PRIORITY, epoch_flags::PRIORITY);
+
+        // A forger cannot fall back to a plain CRC-8 epoch
+        let mut b = EpochBuilder::new();
+        b.write(&[4, 5]);
+        let plain = &b.get_epochs()[0];
+        assert_eq!(plain.len(), 4 + 2 + 1);
+        assert!(decode_epoch_with(plain, 0, &Crc16).is_err());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn hmac_epochs_need_the_key() {
+        use crate::decoder::{decode_epoch, decode_epoch_with};
+        use crate::wire::integrity::{HmacSha256, HMAC_TAG_LEN};
+
+        let key = HmacSha256::new(b"shared secret");
+        let mut b = EpochBuilder::new().with_integrity(key.clone());
+        b.write(&[4, 5]);
+        let mut epoch = b.get_epochs()[0].clone();
+        assert_eq!(epoch.len(), 5 + 2 + HMAC_TAG_LEN);
+        assert!(decode_epoch(&epoch, 0).is_err());
+        assert!(decode_epoch_with(&epoch, 0, &key).unwrap().0.crc_ok);
+        assert!(!decode_epoch_with(&epoch, 0, &HmacSha256::new(b"guess")).unwrap().0.crc_ok);
+        epoch[5] ^= 1;
+        assert!(!decode_epoch_with(&epoch, 0, &key).unwrap().0.crc_ok);
+
+        // A forger cannot fall back to a plain CRC-8 epoch
+        let mut b = EpochBuilder::new();
+        b.write(&[4, 5]);
+        let plain = &b.get_epochs()[0];
+        assert_eq!(plain.len(), 4 + 2 + 1);
+        assert!(decode_epoch_with(plain, 0, &key).is_err());
+    }
+
+    #[test]
+    fn list_and_map_counts_are_verified() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().begin_list(2).string("a").eq().uint8(1).end_list();
+        assert_eq!(e.try_end_utterance(), Err(AILLError::CountMismatch { declared: 2, actual: 3 }));
+
+        e.start_utterance().begin_map(2).string("a").confidence(0.5).null().end_map();
+        assert_eq!(e.try_end_utterance(), Err(AILLError::CountMismatch { declared: 2, actual: 1 }));
+
+        e.start_utterance().begin_list(2).begin_struct().field(1).list_of_float32(&[1.0]).end_struct();
+        e.op(esc::NOP).query().null().end_list();
+        assert!(e.try_end_utterance().is_ok());
+
+        // Checked where the container closes
+        e.start_utterance().assert_().begin_list(1).null();
+        assert!(e.try_end_list().is_ok());
+        e.begin_list(3).null();
+        assert_eq!(e.try_end_list().err(), Some(AILLError::CountMismatch { declared: 3, actual: 1 }));
+        e.begin_map(1).string("a").null().string("b").null();
+        assert_eq!(e.try_end_map().err(), Some(AILLError::CountMismatch { declared: 1, actual: 2 }));
+        assert_eq!(e.try_end_utterance(), Err(AILLError::CountMismatch { declared: 3, actual: 1 }));
+    }
+
+    #[test]
+    fn list_of_float16_matches_single_literals() {
+        let values: Vec<f32> = (0..21).map(|i| i as f32 * 0.37).collect();
+        let mut batched = AILLEncoder::new();
+        batched.start_utterance().assert_().list_of_float16(&values);
+        let mut single = AILLEncoder::new();
+        single.start_utterance().assert_().begin_list(values.len() as u16);
+        for &v in &values {
+            single.float16(v);
+        }
+        single.end_list();
+        assert_eq!(batched.end_utterance(), single.end_utterance());
+    }
+
+    #[test]
+    fn float16_tolerance_warns_or_upgrades() {
+        let mut e = AILLEncoder::new().with_float16_tolerance(1e-4, Float16Fallback::Upgrade);
+        e.start_utterance().assert_().begin_list(3).float16(0.5).float16(1234.567).float16(1e6).end_list();
+        let warnings = e.take_float16_warnings();
+        assert_eq!(warnings.iter().map(|w| w.value).collect::<Vec<_>>(), vec![1234.567, 1e6]);
+        assert!(warnings[1].error.is_infinite() && warnings[1].upgraded);
+
+        let AstNode::Utterance { body, .. } = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap() else {
+            panic!()
+        };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        let AstNode::List { elements, .. } = expression.as_ref() else { panic!() };
+        let types: Vec<_> = elements
+            .iter()
+            .map(|el| match el {
+                AstNode::Literal { value: LiteralValue::Float16(_), .. } => "f16",
+                AstNode::Literal { value: LiteralValue::Float32(_), .. } => "f32",
+                _ => "?",
+            })
+            .collect();
+        assert_eq!(types, vec!["f16", "f32", "f32"]);
+
+        let mut e = AILLEncoder::new().with_float16_tolerance(1e-4, Float16Fallback::Warn);
+        e.start_utterance().float16(1234.567);
+        assert!(!e.float16_warnings()[0].upgraded);
+    }
+
+    /// A struct of fields 2, 1 and 3, field 3 holding a struct of 9 and 8.
+    fn scrambled(e: &mut AILLEncoder) -> Vec<u8> {
+        e.start_utterance().assert_().begin_struct();
+        e.field(2).string("b");
+        e.field(1).uint8(1);
+        e.field(3).begin_struct().field(9).null().field(8).bool_(true).end_struct();
+        e.end_struct();
+        e.end_utterance()
+    }
+
+    #[test]
+    fn struct_fields_are_written_in_id_order() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().begin_struct();
+        e.field(1).uint8(1);
+        e.field(2).string("b");
+        e.field(3).begin_struct().field(8).bool_(true).field(9).null().end_struct();
+        e.end_struct();
+        let sorted = e.end_utterance();
+
+        assert_eq!(scrambled(&mut AILLEncoder::new()), sorted);
+        let ast = AILLDecoder::new().decode_utterance(&sorted).unwrap();
+        assert_eq!(crate::templates::encode_ast(&ast).unwrap(), sorted);
+
+        let as_written = scrambled(&mut AILLEncoder::new().with_canonical_fields(false));
+        assert_ne!(as_written, sorted);
+        assert_eq!(AILLDecoder::new().decode_utterance(&as_written).unwrap(), ast);
+    }
+
+    #[test]
+    fn positional_structs_and_map_pairs_keep_their_order() {
+        let mixed = |canonical| {
+            let mut e = AILLEncoder::new().with_canonical_fields(canonical);
+            e.start_utterance().begin_struct().field(5).uint8(5).uint8(7).field(0).uint8(1).end_struct();
+            e.end_utterance()
+        };
+        assert_eq!(mixed(true), mixed(false));
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().map_from_str_pairs(&[("z", "1"), ("a", "2")]);
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        let AstNode::Utterance { body, .. } = ast else { panic!() };
+        let AstNode::Map { pairs, .. } = &body[0] else { panic!("{:?}", body) };
+        let keys: Vec<_> = pairs.iter().map(|(k, _)| k.clone()).collect();
+        let key = |s: &str| AstNode::Literal { value_type: "string".into(), value: LiteralValue::String(s.into()) };
+        assert_eq!(keys, vec![key("z"), key("a")]);
+    }
+
+    #[test]
+    fn structs_rebinding_escapes_keep_their_order() {
+        use crate::codebook::NAV1;
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().begin_struct();
+        e.field(2).begin_list(2).codebook_ref(1, NAV1.registry_id).l1_ref(0x0001).end_list();
+        e.field(1).l1_ref(0x0002);
+        e.end_struct();
+        let wire = e.end_utterance();
+        let mut e = AILLEncoder::new().with_canonical_fields(false);
+        e.start_utterance().begin_struct();
+        e.field(2).begin_list(2).codebook_ref(1, NAV1.registry_id).l1_ref(0x0001).end_list();
+        e.field(1).l1_ref(0x0002);
+        e.end_struct();
+        assert_eq!(wire, e.end_utterance());
+
+        let AstNode::Utterance { body, .. } = AILLDecoder::new().decode_utterance(&wire).unwrap() else { panic!() };
+        let AstNode::Struct { fields } = &body[0] else { panic!("{:?}", body) };
+        assert_eq!(fields[&1], AstNode::DomainRef { level: 1, domain_code: 0x0002, registry: Some(NAV1.registry_id) });
+    }
+}
@@ -10,6 +10,48 @@ pub enum AILLError {
     InvalidVarInt,
     Utf8Error(String),
     EncoderError(String),
+    Timeout(String),
+    /// A list or map holds a different number of elements (map: pairs)
+    /// than its header declares.
+    CountMismatch { declared: u16, actual: usize },
+    /// The utterance is addressed to other agents and was not decoded.
+    NotAddressed,
+    /// Expressions nest deeper than the decoder allows.
+    TooDeep { offset: usize, max_depth: usize },
+    /// A domain reference the decoder's codebooks do not define, refused
+    /// under [`UnknownDomainPolicy::Error`](crate::decoder::UnknownDomainPolicy::Error).
+    UnknownDomainRef { offset: usize, level: u8, domain_code: u16, registry: Option<u8> },
+}
+
+impl AILLError {
+    /// Stable snake_case name of the variant, e.g. `"crc_mismatch"`, for
+    /// reporting errors as data rather than text.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AILLError::InvalidOpCode(_) => "invalid_opcode",
+            AILLError::CrcMismatch { .. } => "crc_mismatch",
+            AILLError::UnexpectedEof { .. } => "unexpected_eof",
+            AILLError::InvalidStructure(_) => "invalid_structure",
+            AILLError::InvalidVarInt => "invalid_varint",
+            AILLError::Utf8Error(_) => "utf8_error",
+            AILLError::EncoderError(_) => "encoder_error",
+            AILLError::Timeout(_) => "timeout",
+            AILLError::CountMismatch { .. } => "count_mismatch",
+            AILLError::NotAddressed => "not_addressed",
+            AILLError::TooDeep { .. } => "too_deep",
+            AILLError::UnknownDomainRef { .. } => "unknown_domain_ref",
+        }
+    }
+
+    /// Byte offset the error names, for the variants that carry one.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            AILLError::UnexpectedEof { offset, .. }
+            | AILLError::TooDeep { offset, .. }
+            | AILLError::UnknownDomainRef { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for AILLError {
@@ -26,6 +68,22 @@ impl fmt::Display for AILLError {
             AILLError::InvalidVarInt => write!(f, "Invalid variable-length integer"),
             AILLError::Utf8Error(msg) => write!(f, "UTF-8 error: {}", msg),
             AILLError::EncoderError(msg) => write!(f, "Encoder error: {}", msg),
+            AILLError::Timeout(msg) => write!(f, "Timed out: {}", msg),
+            AILLError::CountMismatch { declared, actual } => {
+                write!(f, "Count mismatch: declared {}, found {}", declared, actual)
+            }
+            AILLError::NotAddressed => write!(f, "Utterance addressed to other agents"),
+            AILLError::TooDeep { offset, max_depth } => {
+                write!(f, "[offset {}] Nesting deeper than {} levels", offset, max_depth)
+            }
+            AILLError::UnknownDomainRef { offset, level, domain_code, registry: Some(registry) } => write!(
+                f,
+                "[offset {}] L{} code 0x{:04X} is not in registry 0x{:02X}",
+                offset, level, domain_code, registry
+            ),
+            AILLError::UnknownDomainRef { offset, level, domain_code, registry: None } => {
+                write!(f, "[offset {}] L{} code 0x{:04X} on an unbound escape level", offset, level, domain_code)
+            }
         }
     }
 }
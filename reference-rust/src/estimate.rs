@@ -0,0 +1,140 @@
+//! Transmitted size of an utterance, known before it is sent.
+//!
+//! The wire bytes of an utterance are split into epochs of at most
+//! [`MAX_EPOCH_PAYLOAD`] bytes, each adding a 5-byte header and CRC, and
+//! may then be expanded by a forward error correction code. A
+//! [`SizeEstimate`] totals these so a sender can decide up front whether
+//! a message fits one transmission or has to be fragmented.
+
+use crate::ast::AstNode;
+use crate::encoder::{AILLEncoder, MAX_EPOCH_PAYLOAD};
+use crate::error::AILLError;
+use crate::templates::encode_ast;
+
+/// Epoch sequence number, length and CRC-8.
+pub const EPOCH_OVERHEAD: usize = 5;
+
+/// Link parameters that add to the utterance's own size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overhead {
+    /// Largest epoch payload.
+    pub max_epoch_payload: usize,
+    /// FEC code rate: data bytes per transmitted byte, `1.0` without FEC
+    /// (a rate-1/2 code is `0.5`).
+    pub fec_rate: f32,
+}
+
+impl Default for Overhead {
+    fn default() -> Self {
+        Self { max_epoch_payload: MAX_EPOCH_PAYLOAD, fec_rate: 1.0 }
+    }
+}
+
+impl Overhead {
+    pub fn with_fec_rate(mut self, fec_rate: f32) -> Self {
+        self.fec_rate = fec_rate;
+        self
+    }
+
+    pub fn with_max_epoch_payload(mut self, max_epoch_payload: usize) -> Self {
+        self.max_epoch_payload = max_epoch_payload;
+        self
+    }
+
+    /// Estimate for an utterance of `utterance` wire bytes.
+    pub fn estimate(&self, utterance: usize) -> SizeEstimate {
+        let epochs = utterance.div_ceil(self.max_epoch_payload.max(1));
+        let framed = utterance + epochs * EPOCH_OVERHEAD;
+        let transmitted = if self.fec_rate > 0.0 && self.fec_rate < 1.0 {
+            (framed as f64 / self.fec_rate as f64).ceil() as usize
+        } else {
+            framed
+        };
+        SizeEstimate { utterance, epochs, framed, transmitted }
+    }
+}
+
+/// Sizes of an utterance at each stage of transmission, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// Utterance wire bytes, START to END_UTTERANCE.
+    pub utterance: usize,
+    /// Epochs needed to carry it.
+    pub epochs: usize,
+    /// With epoch headers and CRCs.
+    pub framed: usize,
+    /// After FEC.
+    pub transmitted: usize,
+}
+
+impl SizeEstimate {
+    /// Whether the transmitted bytes fit within `limit`.
+    pub fn fits(&self, limit: usize) -> bool {
+        self.transmitted <= limit
+    }
+}
+
+#[cfg(feature = "audio-core")]
+impl SizeEstimate {
+    /// Whether the message fits one acoustic transmission.
+    pub fn fits_acoustic(&self) -> bool {
+        self.fits(crate::audio::MAX_ENCODE_BYTES)
+    }
+
+    /// Acoustic airtime in seconds, including the sync and end chirps.
+    pub fn airtime(&self) -> f32 {
+        use crate::audio::{END_DURATION, FRAME_TIME, SYNC_DURATION};
+        SYNC_DURATION + self.transmitted as f32 * 2.0 * FRAME_TIME + END_DURATION
+    }
+}
+
+/// Size of `ast` (an utterance) on the link, without FEC.
+pub fn utterance_size(ast: &AstNode) -> Result<SizeEstimate, AILLError> {
+    utterance_size_with(ast, &Overhead::default())
+}
+
+/// Size of `ast` (an utterance) on the link with the given overhead.
+pub fn utterance_size_with(ast: &AstNode, overhead: &Overhead) -> Result<SizeEstimate, AILLError> {
+    Ok(overhead.estimate(encode_ast(ast)?.len()))
+}
+
+impl AILLEncoder {
+    /// Estimated link size of the utterance being built, counting the
+    /// END_UTTERANCE that [`end_utterance`](Self::end_utterance) will add.
+    pub fn projected_size(&self) -> SizeEstimate {
+        self.projected_size_with(&Overhead::default())
+    }
+
+    pub fn projected_size_with(&self, overhead: &Overhead) -> SizeEstimate {
+        overhead.estimate(self.current_size() + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLDecoder;
+
+    #[test]
+    fn projection_matches_the_encoded_utterance() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().string("hello").float32(1.5);
+        let projected = e.projected_size();
+        let wire = e.end_utterance();
+        assert_eq!(projected.utterance, wire.len());
+        assert_eq!(projected.epochs, 1);
+        assert_eq!(projected.framed, wire.len() + EPOCH_OVERHEAD);
+
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        assert_eq!(utterance_size(&ast).unwrap(), projected);
+    }
+
+    #[test]
+    fn epochs_and_fec_add_overhead() {
+        let overhead = Overhead::default().with_max_epoch_payload(100).with_fec_rate(0.5);
+        let est = overhead.estimate(250);
+        assert_eq!((est.epochs, est.framed, est.transmitted), (3, 265, 530));
+        assert!(est.fits(530) && !est.fits(529));
+        assert_eq!(Overhead::default().estimate(0).transmitted, 0);
+    }
+}
@@ -0,0 +1,404 @@
+//! Decoding to a flat stream of events instead of a tree.
+//!
+//! [`AILLDecoder::decode_events`] walks the wire bytes and yields one
+//! [`WireEvent`] at a time, keeping only a stack of the containers that
+//! are open. A consumer that folds or forwards values as they go by can
+//! process payloads far larger than it could hold as an [`AstNode`] tree.
+//!
+//! The events follow the tree decoder's reading of the wire:
+//!
+//! - a pragmatic act, modality, temporal modifier, SCHEMA_REF or inline
+//!   annotation is followed by the events of the one expression it
+//!   applies to;
+//! - each struct value is preceded by a [`WireEvent::Field`], unnamed
+//!   fields numbered from 0 as in [`AstNode::Struct`];
+//! - a missing END_UTTERANCE or closing structure code is tolerated, and
+//!   the matching end events are still emitted;
+//! - nesting deeper than [`DecoderConfig::max_depth`](crate::DecoderConfig::max_depth)
+//!   fails with the same [`AILLError::TooDeep`], so the stack stays bounded.
+//!
+//! COMM-1 tags that the tree decoder lifts into the meta header (multicast
+//! recipients, thread id) appear here as ordinary events.
+
+use crate::ast::{AstNode, LiteralValue, MetaHeader};
+use crate::codebook::base::{esc, fc, meta, modal, st};
+use crate::decoder::{AILLDecoder, Session};
+use crate::error::AILLError;
+
+/// One token of decoded wire input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireEvent {
+    /// START_UTTERANCE and the meta header.
+    StartUtterance(MetaHeader),
+    EndUtterance,
+    StartStruct,
+    /// The next value belongs to struct field `id`.
+    Field(u16),
+    EndStruct,
+    /// BEGIN_LIST with its declared element count.
+    StartList(u16),
+    EndList,
+    /// BEGIN_MAP with its declared pair count. Keys and values alternate.
+    StartMap(u16),
+    EndMap,
+    Literal(LiteralValue),
+    /// Pragmatic act opcode.
+    Pragmatic(u8),
+    /// Modality opcode, with the horizon of PREDICTED.
+    Modal(u8, Option<f64>),
+    Temporal(u8),
+    /// SCHEMA_REF with its schema id.
+    SchemaRef(u16),
+    /// Inline CONFIDENCE annotation.
+    Confidence(f32),
+    /// Inline LABEL annotation.
+    Label(String),
+    DomainRef { level: u8, domain_code: u16, registry: Option<u8> },
+    CodebookRef { level: u8, registry_id: u8 },
+    ContextRef(u32),
+    HashRef(u32),
+    Extension { code: u8, payload: Vec<u8> },
+    /// Wire bytes of the value of a field the decoder does not know, see
+    /// [`DecoderConfig::known_fields`](crate::DecoderConfig::known_fields).
+    Raw(Vec<u8>),
+    /// Operator or other opcode without operands.
+    Code(u8),
+    /// Frame-control opcode and its operands.
+    FrameControl { code: u8, operands: Vec<u32> },
+}
+
+/// What an open construct expects next.
+enum Frame {
+    Utterance,
+    Struct { positional: u16 },
+    List { declared: u16, remaining: u16 },
+    Map { declared: u16, remaining: u16, value_next: bool },
+    /// The expression a wrapper applies to.
+    Operand,
+}
+
+/// Iterator over the events of a buffer of back-to-back utterances, see
+/// [`AILLDecoder::decode_events`]. It stops after yielding the first error.
+pub struct Events<'d> {
+    session: Session<'d, 'd>,
+    bindings: [Option<u8>; 3],
+    /// Open constructs, each with the depth of the expressions inside it.
+    stack: Vec<(Frame, usize)>,
+    /// Struct field id awaiting its value, and whether it is positional.
+    field: Option<(u16, bool)>,
+    queued: Option<WireEvent>,
+    done: bool,
+}
+
+impl AILLDecoder {
+    /// Decode `data` as a stream of [`WireEvent`]s without building an AST.
+    pub fn decode_events<'d>(&'d self, data: &'d [u8]) -> Events<'d> {
+        Events {
+            session: Session::new(data, self.config(), None),
+            bindings: self.config().escape_bindings,
+            stack: Vec::new(),
+            field: None,
+            queued: None,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<WireEvent, AILLError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.queued.take() {
+            return Some(Ok(event));
+        }
+        if self.done {
+            return None;
+        }
+        match self.step() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Events<'_> {
+    /// Depth of open utterances, containers and wrappers.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn step(&mut self) -> Result<Option<WireEvent>, AILLError> {
+        loop {
+            let depth = self.stack.last().map_or(0, |(_, depth)| *depth);
+            let s = &mut self.session;
+            let next = if s.reader.is_empty() { None } else { Some(s.reader.peek()?) };
+            let substitute_null = match self.stack.last_mut().map(|(frame, _)| frame) {
+                None => {
+                    return match next {
+                        Some(_) => self.start_utterance().map(Some),
+                        None => Ok(None),
+                    };
+                }
+                Some(Frame::Utterance) => match next {
+                    None | Some(fc::END_UTTERANCE) => {
+                        if next.is_some() {
+                            s.opcode()?;
+                        }
+                        self.stack.pop();
+                        return Ok(Some(WireEvent::EndUtterance));
+                    }
+                    Some(_) => false,
+                },
+                Some(Frame::Struct { positional }) => match next {
+                    None | Some(st::END_STRUCT) => {
+                        if next.is_some() {
+                            s.opcode()?;
+                        }
+                        self.stack.pop();
+                        return Ok(Some(WireEvent::EndStruct));
+                    }
+                    Some(st::FIELD_SEP) => {
+                        s.opcode()?;
+                        continue;
+                    }
+                    Some(st::FIELD_ID) => {
+                        s.opcode()?;
+                        let id = s.reader.read_u16_be()?;
+                        if !s.is_known_field(id) {
+                            match s.raw_expression()? {
+                                Some(bytes) => {
+                                    self.queued = Some(WireEvent::Raw(bytes));
+                                    return Ok(Some(WireEvent::Field(id)));
+                                }
+                                None => continue,
+                            }
+                        }
+                        self.field = Some((id, false));
+                        false
+                    }
+                    Some(_) => {
+                        self.field = Some((*positional, true));
+                        false
+                    }
+                },
+                Some(Frame::List { declared, remaining }) => {
+                    if matches!(next, Some(esc::NOP | esc::COMMENT)) {
+                        s.decode_expression()?;
+                        continue;
+                    }
+                    if *remaining == 0 || s.at_container_end() {
+                        s.close_container(st::END_LIST, *declared, (*declared - *remaining) as usize)?;
+                        self.stack.pop();
+                        return Ok(Some(WireEvent::EndList));
+                    }
+                    *remaining -= 1;
+                    false
+                }
+                Some(Frame::Map { declared, remaining, value_next }) => {
+                    if *value_next && !s.at_container_end() {
+                        *value_next = false;
+                    } else if *value_next || *remaining == 0 || s.at_container_end() {
+                        // A key without its value is not a pair
+                        let actual = (*declared - *remaining) as usize - *value_next as usize;
+                        s.close_container(st::END_MAP, *declared, actual)?;
+                        self.stack.pop();
+                        return Ok(Some(WireEvent::EndMap));
+                    } else {
+                        *remaining -= 1;
+                        *value_next = true;
+                    }
+                    true
+                }
+                Some(Frame::Operand) => {
+                    self.stack.pop();
+                    true
+                }
+            };
+
+            // Like the tree decoder, a NOP or COMMENT in place of a
+            // wrapper operand or map entry stands for null.
+            let (event, frame) = match self.expression(depth)? {
+                Some(expr) => expr,
+                None if substitute_null => (WireEvent::Literal(LiteralValue::Null), None),
+                None => {
+                    self.field = None;
+                    continue;
+                }
+            };
+            let event = self.with_field(event);
+            self.stack.extend(frame.map(|frame| (frame, depth + 1)));
+            return Ok(Some(event));
+        }
+    }
+
+    fn start_utterance(&mut self) -> Result<WireEvent, AILLError> {
+        let code = self.session.opcode()?;
+        if code != fc::START_UTTERANCE {
+            return Err(AILLError::InvalidStructure(format!(
+                "Expected START_UTTERANCE (0x00), got 0x{:02X}",
+                code
+            )));
+        }
+        self.session.bindings = self.bindings;
+        self.session.reader.set_long_strings(false);
+        let meta = self.session.decode_meta_header()?;
+        self.stack.push((Frame::Utterance, 0));
+        Ok(WireEvent::StartUtterance(meta))
+    }
+
+    /// Read the head of an expression inside `depth` open ones: the whole
+    /// of a leaf, or the opening of a container or wrapper along with the
+    /// frame it opens.
+    fn expression(&mut self, depth: usize) -> Result<Option<(WireEvent, Option<Frame>)>, AILLError> {
+        let s = &mut self.session;
+        if s.reader.is_empty() {
+            return Ok(None);
+        }
+        s.check_depth(depth)?;
+        let code = s.reader.peek()?;
+        let event = match code {
+            0x60..=0x8F | meta::CONFIDENCE | meta::LABEL => {
+                s.opcode()?;
+                match code {
+                    0x80..=0x8F => WireEvent::Pragmatic(code),
+                    modal::PREDICTED => WireEvent::Modal(code, Some(s.reader.read_f16_be()? as f64)),
+                    modal::REPORTED => {
+                        s.reader.read_uuid()?;
+                        WireEvent::Modal(code, None)
+                    }
+                    0x70..=0x7F => WireEvent::Modal(code, None),
+                    meta::CONFIDENCE => WireEvent::Confidence(s.reader.read_f16_be()?),
+                    meta::LABEL => WireEvent::Label(s.reader.read_string()?),
+                    _ => WireEvent::Temporal(code),
+                }
+            }
+            st::SCHEMA_REF => {
+                s.opcode()?;
+                WireEvent::SchemaRef(s.reader.read_u16_be()?)
+            }
+            st::BEGIN_STRUCT => {
+                s.opcode()?;
+                return Ok(Some((WireEvent::StartStruct, Some(Frame::Struct { positional: 0 }))));
+            }
+            st::BEGIN_LIST => {
+                s.opcode()?;
+                let count = s.reader.read_u16_be()?;
+                return Ok(Some((WireEvent::StartList(count), Some(Frame::List { declared: count, remaining: count }))));
+            }
+            st::BEGIN_MAP => {
+                s.opcode()?;
+                let count = s.reader.read_u16_be()?;
+                let frame = Frame::Map { declared: count, remaining: count, value_next: false };
+                return Ok(Some((WireEvent::StartMap(count), Some(frame))));
+            }
+            _ => return Ok(s.decode_expression()?.map(|node| (leaf_event(node), None))),
+        };
+        Ok(Some((event, Some(Frame::Operand))))
+    }
+
+    /// Precede `event` with the pending struct field, if any.
+    fn with_field(&mut self, event: WireEvent) -> WireEvent {
+        let Some((id, positional)) = self.field.take() else {
+            return event;
+        };
+        if let (true, Some(Frame::Struct { positional: next })) = (positional, self.stack.last_mut().map(|(frame, _)| frame)) {
+            *next += 1;
+        }
+        self.queued = Some(event);
+        WireEvent::Field(id)
+    }
+}
+
+fn leaf_event(node: AstNode) -> WireEvent {
+    match node {
+        AstNode::Literal { value, .. } => WireEvent::Literal(value),
+        AstNode::DomainRef { level, domain_code, registry } => WireEvent::DomainRef { level, domain_code, registry },
+        AstNode::CodebookRef { level, registry_id } => WireEvent::CodebookRef { level, registry_id },
+        AstNode::ContextRef { sct_index } => WireEvent::ContextRef(sct_index),
+        AstNode::HashRef { hash } => WireEvent::HashRef(hash),
+        AstNode::Extension { code, payload, .. } => WireEvent::Extension { code, payload },
+        AstNode::Code { code, .. } => WireEvent::Code(code),
+        AstNode::FrameControl { code, operands, .. } => WireEvent::FrameControl { code, operands },
+        other => unreachable!("not a leaf expression: {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::base::{esc, pragma, temporal};
+    use crate::encoder::AILLEncoder;
+    use WireEvent::*;
+
+    fn events(wire: &[u8]) -> Vec<WireEvent> {
+        AILLDecoder::new().decode_events(wire).collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn flattens_nested_containers() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().temporal(temporal::PAST).begin_struct();
+        e.field(0x0007).begin_list(2).int8(1).null().end_list();
+        e.uint8(9);
+        e.begin_map(1).string("k").op(esc::NOP).end_map().end_struct();
+        let mut wire = e.end_utterance();
+        let mut e = AILLEncoder::new();
+        e.start_utterance().l1_ref(0x0010);
+        wire.extend(e.end_utterance());
+
+        let got = events(&wire);
+        assert!(matches!(got[0], StartUtterance(_)));
+        assert_eq!(
+            got[1..18],
+            [
+                Pragmatic(pragma::ASSERT),
+                Temporal(temporal::PAST),
+                StartStruct,
+                Field(7),
+                StartList(2),
+                Literal(LiteralValue::Int8(1)),
+                Literal(LiteralValue::Null),
+                EndList,
+                Field(0),
+                Literal(LiteralValue::Uint8(9)),
+                Field(1),
+                StartMap(1),
+                Literal(LiteralValue::String("k".into())),
+                Literal(LiteralValue::Null),
+                EndMap,
+                EndStruct,
+                EndUtterance,
+            ]
+        );
+        assert!(matches!(got[18], StartUtterance(_)));
+        assert_eq!(got[19..], [DomainRef { level: 1, domain_code: 0x0010, registry: None }, EndUtterance]);
+    }
+
+    #[test]
+    fn truncation_closes_open_constructs_and_errors_stop_the_stream() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().query().begin_list(1).int8(1);
+        let wire = e.end_utterance();
+        let got = events(&wire[..wire.len() - 1]);
+        assert_eq!(got[got.len() - 3..], [Literal(LiteralValue::Int8(1)), EndList, EndUtterance]);
+
+        // A list cut short of its count is still a mismatch
+        let mut e = AILLEncoder::new();
+        e.start_utterance().query().begin_list(3).int8(1);
+        let wire = e.end_utterance();
+        let last = AILLDecoder::new().decode_events(&wire[..wire.len() - 1]).last().unwrap();
+        assert_eq!(last, Err(AILLError::CountMismatch { declared: 3, actual: 1 }));
+
+        let decoder = AILLDecoder::new();
+        let mut it = decoder.decode_events(&[0x42]);
+        assert!(it.next().unwrap().is_err());
+        assert!(it.next().is_none());
+    }
+}
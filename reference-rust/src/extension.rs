@@ -0,0 +1,125 @@
+//! Application-defined opcodes in the reserved range 0xC0-0xEF.
+//!
+//! Each extension opcode is followed by a fixed-size payload agreed out of
+//! band. Applications declare theirs in an [`ExtensionRegistry`] and decode
+//! with [`AILLDecoder::with_extensions`](crate::AILLDecoder::with_extensions).
+//! Reserved opcodes that are not registered still decode, as
+//! [`AstNode::Extension`](crate::AstNode::Extension) nodes with an empty
+//! payload, so utterances from newer peers survive a round trip.
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+
+/// Opcodes available for extensions.
+pub const EXTENSION_RANGE: RangeInclusive<u8> = 0xC0..=0xEF;
+
+/// A registered extension opcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct ExtensionOpcode {
+    pub code: u8,
+    pub mnemonic: String,
+    /// Payload bytes following the opcode.
+    pub payload_len: usize,
+}
+
+/// Extension opcodes known to a decoder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct ExtensionRegistry {
+    opcodes: BTreeMap<u8, ExtensionOpcode>,
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare `code` with a fixed `payload_len`. Fails for codes outside
+    /// [`EXTENSION_RANGE`] or already registered.
+    pub fn register(&mut self, code: u8, mnemonic: &str, payload_len: usize) -> Result<(), AILLError> {
+        if !EXTENSION_RANGE.contains(&code) {
+            return Err(AILLError::InvalidStructure(format!(
+                "0x{:02X} is outside the extension range 0xC0-0xEF",
+                code
+            )));
+        }
+        if self.opcodes.contains_key(&code) {
+            return Err(AILLError::InvalidStructure(format!("extension opcode 0x{:02X} already registered", code)));
+        }
+        self.opcodes.insert(code, ExtensionOpcode { code, mnemonic: mnemonic.to_string(), payload_len });
+        Ok(())
+    }
+
+    pub fn get(&self, code: u8) -> Option<&ExtensionOpcode> {
+        self.opcodes.get(&code)
+    }
+
+    pub fn len(&self) -> usize {
+        self.opcodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.opcodes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ExtensionOpcode> {
+        self.opcodes.values()
+    }
+}
+
+impl AILLEncoder {
+    /// Emit an extension opcode followed by its payload. The payload length
+    /// must match what the receiving side registered.
+    pub fn extension(&mut self, code: u8, payload: &[u8]) -> &mut Self {
+        self.op(code).raw(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::AstNode;
+    use crate::templates::encode_ast;
+    use crate::AILLDecoder;
+
+    fn registry() -> ExtensionRegistry {
+        let mut reg = ExtensionRegistry::new();
+        reg.register(0xC1, "VENDOR_PING", 2).unwrap();
+        reg
+    }
+
+    #[test]
+    fn registration_is_range_checked() {
+        let mut reg = registry();
+        assert!(reg.register(0xC1, "AGAIN", 0).is_err());
+        assert!(reg.register(0xBF, "NOT_RESERVED", 0).is_err());
+        assert!(reg.register(0xF0, "NOT_RESERVED", 0).is_err());
+        assert_eq!(reg.get(0xC1).unwrap().payload_len, 2);
+        assert_eq!(reg.len(), 1);
+    }
+
+    #[test]
+    fn registered_payloads_decode_and_unknown_codes_are_preserved() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().begin_list(3).extension(0xC1, &[0xAB, 0xCD]).extension(0xE7, &[]).int8(5).end_list();
+        let wire = e.end_utterance();
+
+        let ast = AILLDecoder::with_extensions(registry()).decode_utterance(&wire).unwrap();
+        let AstNode::Utterance { body, .. } = &ast else { panic!() };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        let AstNode::List { elements, .. } = expression.as_ref() else { panic!() };
+        assert_eq!(
+            elements[0],
+            AstNode::Extension { code: 0xC1, mnemonic: "VENDOR_PING".into(), payload: vec![0xAB, 0xCD] }
+        );
+        assert_eq!(elements[1], AstNode::Extension { code: 0xE7, mnemonic: "RESERVED".into(), payload: vec![] });
+        assert_eq!(encode_ast(&ast).unwrap(), wire);
+    }
+}
@@ -0,0 +1,183 @@
+//! Fast path for WARN-level and emergency utterances.
+//!
+//! Urgent traffic must not wait behind bulk data. A [`FastPath`] treats an
+//! utterance as urgent when its PRIORITY is at or above a threshold, or
+//! when it references SAFETY-1. Along the way:
+//!
+//! - [`FastPath::write`] sends urgent utterances with
+//!   [`EpochWriter::write_urgent`], in an epoch of their own flagged
+//!   [`PRIORITY`](crate::wire::epoch_flags::PRIORITY) that goes out ahead
+//!   of held epochs and of the utterance still being fragmented;
+//! - [`EpochReorderBuffer`](crate::reorder::EpochReorderBuffer) delivers
+//!   such epochs on arrival instead of in sequence order;
+//! - [`StreamDecoder::push_urgent`](crate::stream::StreamDecoder::push_urgent)
+//!   decodes them without touching the partially received utterance.
+//!
+//! SAFETY-1 references are only recognised on an escape level bound to
+//! its registry, see [`DecoderConfig::bind_escape`](crate::DecoderConfig::bind_escape).
+
+use std::io::Write;
+
+use crate::ast::AstNode;
+use crate::codebook::SAFETY1;
+use crate::decoder::{AILLDecoder, DecodeObserver};
+use crate::encoder::EpochWriter;
+use crate::error::AILLError;
+
+/// Lowest priority sent on the fast path by default (0 lowest, 7 highest).
+pub const DEFAULT_URGENT_PRIORITY: u8 = 6;
+
+/// Decides which utterances skip the queue; see the [module docs](self).
+pub struct FastPath {
+    decoder: AILLDecoder,
+    threshold: u8,
+    safety_refs: bool,
+}
+
+impl Default for FastPath {
+    fn default() -> Self {
+        Self { decoder: AILLDecoder::default(), threshold: DEFAULT_URGENT_PRIORITY, safety_refs: true }
+    }
+}
+
+impl FastPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify with `decoder`'s escape bindings.
+    pub fn with_decoder(mut self, decoder: AILLDecoder) -> Self {
+        self.decoder = decoder;
+        self
+    }
+
+    /// Treat utterances with priority `threshold` or above as urgent.
+    pub fn with_threshold(mut self, threshold: u8) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Whether a SAFETY-1 reference makes an utterance urgent (default on).
+    pub fn with_safety_refs(mut self, safety_refs: bool) -> Self {
+        self.safety_refs = safety_refs;
+        self
+    }
+
+    /// Whether the utterance in `wire` takes the fast path.
+    pub fn is_urgent(&self, wire: &[u8]) -> Result<bool, AILLError> {
+        let mut refs = SafetyRefs(false);
+        let ast = self.decoder.decode_utterance_with_observer(wire, &mut refs)?;
+        let priority = match &ast {
+            AstNode::Utterance { meta, .. } => meta.priority,
+            _ => 0,
+        };
+        Ok(priority >= self.threshold || (self.safety_refs && refs.0))
+    }
+
+    /// Write one utterance to `writer`, urgently if it qualifies. Returns
+    /// whether it took the fast path.
+    pub fn write<W: Write>(&self, writer: &mut EpochWriter<W>, wire: &[u8]) -> Result<bool, AILLError> {
+        let urgent = self.is_urgent(wire)?;
+        match urgent {
+            true => writer.write_urgent(wire)?,
+            false => writer.write(wire)?,
+        }
+        Ok(urgent)
+    }
+}
+
+/// Notes whether any domain reference resolves to SAFETY-1.
+struct SafetyRefs(bool);
+
+impl DecodeObserver for SafetyRefs {
+    fn on_node_end(&mut self, _start: usize, _end: usize, node: &AstNode) {
+        if let AstNode::DomainRef { registry: Some(registry), .. } = node {
+            self.0 |= *registry == SAFETY1.registry_id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{decode_epoch, DecoderConfig};
+    use crate::encoder::AILLEncoder;
+    use crate::reorder::{EpochReorderBuffer, ReorderEvent};
+    use crate::stream::StreamDecoder;
+    use crate::wire::epoch_flags;
+
+    fn utterance(priority: u8, safety_ref: bool, fill: usize) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, priority, None, None, None).assert_();
+        if safety_ref {
+            e.l1_ref(0x0000);
+        }
+        e.bytes(&vec![0xAA; fill]);
+        e.end_utterance()
+    }
+
+    #[test]
+    fn classifies_by_priority_and_safety_refs() {
+        let fast = FastPath::new();
+        assert!(!fast.is_urgent(&utterance(3, false, 1)).unwrap());
+        assert!(fast.is_urgent(&utterance(DEFAULT_URGENT_PRIORITY, false, 1)).unwrap());
+        // Unbound escape level: the reference is not known to be SAFETY-1
+        assert!(!fast.is_urgent(&utterance(0, true, 1)).unwrap());
+
+        let bound = AILLDecoder::with_config(DecoderConfig::default().bind_escape(1, SAFETY1.registry_id));
+        let fast = FastPath::new().with_decoder(bound).with_threshold(8);
+        assert!(fast.is_urgent(&utterance(0, true, 1)).unwrap());
+        assert!(!fast.with_safety_refs(false).is_urgent(&utterance(7, true, 1)).unwrap());
+    }
+
+    #[test]
+    fn urgent_utterance_overtakes_fragmented_and_held_traffic() {
+        let fast = FastPath::new();
+        let bulk = utterance(1, false, 60);
+        let alarm = utterance(7, false, 1);
+        let full = (bulk.len() / 32) as u16;
+        assert!(full >= 2 && !bulk.len().is_multiple_of(32));
+        // The bulk utterance is mid-fragmentation: one epoch sent, the rest
+        // held or still being filled
+        let mut writer = EpochWriter::new(Vec::new()).with_max_payload(32).with_max_in_flight(32);
+        assert!(!fast.write(&mut writer, &bulk).unwrap());
+        assert!(writer.is_congested());
+        assert!(fast.write(&mut writer, &alarm).unwrap());
+        writer.flush().unwrap();
+        while writer.is_congested() {
+            writer.acknowledge(usize::MAX).unwrap();
+        }
+
+        // On the wire: the first bulk epoch, then the alarm ahead of the held one
+        let wire = writer.into_inner();
+        let mut epochs = Vec::new();
+        let mut pos = 0;
+        while pos < wire.len() {
+            let (epoch, used) = decode_epoch(&wire, pos).unwrap();
+            epochs.push(epoch);
+            pos += used;
+        }
+        assert_eq!(epochs[1].flags, Some(epoch_flags::PRIORITY));
+        assert_eq!(epochs[1].seq_num, full);
+
+        // The receiver delivers it as soon as it arrives, even out of order
+        let mut reorder = EpochReorderBuffer::new(1_000).with_next_seq(0);
+        let mut stream = StreamDecoder::new();
+        let mut got = Vec::new();
+        epochs.swap(0, 1);
+        for epoch in epochs {
+            for event in reorder.push(epoch, 0) {
+                let ReorderEvent::Deliver { seq, payload } = event else { panic!() };
+                let nodes = match seq == full {
+                    true => stream.push_urgent(&payload).unwrap(),
+                    false => stream.push(&payload).unwrap(),
+                };
+                got.extend(nodes.into_iter().map(|n| (seq, n)));
+            }
+        }
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0], (full, AILLDecoder::new().decode_utterance(&alarm).unwrap()));
+        assert_eq!(got[1].1, AILLDecoder::new().decode_utterance(&bulk).unwrap());
+        assert_eq!(reorder.next_seq(), Some(full + 2));
+    }
+}
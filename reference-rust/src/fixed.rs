@@ -0,0 +1,240 @@
+//! Allocation-free encoding into a fixed-size buffer.
+//!
+//! [`FixedEncoder`] has the same fluent API as [`AILLEncoder`](crate::AILLEncoder)
+//! for the common small messages (heartbeats, status, safety alerts) but
+//! writes into an inline `[u8; N]`, so it can live on the stack of a
+//! microcontroller. An opcode that does not fit, with its operands, marks
+//! the encoder as overflowed and is dropped along with everything after it;
+//! [`end_utterance`](FixedEncoder::end_utterance) then reports the error.
+
+use crate::agent::AgentId;
+use crate::codebook::base::{arith, esc, fc, meta, modal, pragma, rel, st, ty};
+use crate::error::AILLError;
+use crate::wire::encode_float16;
+
+/// Encoder writing into a `[u8; N]` buffer.
+pub struct FixedEncoder<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// Bytes the encoding needed once it overflowed.
+    overflow: Option<usize>,
+}
+
+impl<const N: usize> FixedEncoder<N> {
+    pub const fn new() -> Self {
+        Self { buf: [0; N], len: 0, overflow: None }
+    }
+
+    /// Write one opcode and its operands, or none of it.
+    fn put(&mut self, parts: &[&[u8]]) -> &mut Self {
+        let size: usize = parts.iter().map(|p| p.len()).sum();
+        match self.overflow {
+            Some(ref mut needed) => *needed += size,
+            None if self.len + size > N => self.overflow = Some(self.len + size),
+            None => {
+                for part in parts {
+                    self.buf[self.len..self.len + part.len()].copy_from_slice(part);
+                    self.len += part.len();
+                }
+            }
+        }
+        self
+    }
+
+    fn code(&mut self, code: u8) -> &mut Self {
+        self.put(&[&[code]])
+    }
+
+    fn coded(&mut self, code: u8, data: &[u8]) -> &mut Self {
+        self.put(&[&[code], data])
+    }
+
+    fn sized(&mut self, code: u8, data: &[u8]) -> &mut Self {
+        self.put(&[&[code], &(data.len() as u16).to_be_bytes(), data])
+    }
+
+    // ── Utterance framing ──
+
+    pub fn start_utterance(&mut self) -> &mut Self {
+        self.start_utterance_with(1.0, 3, None, None, None)
+    }
+
+    pub fn start_utterance_with(
+        &mut self,
+        confidence: f32,
+        priority: u8,
+        timestamp_us: Option<i64>,
+        dest_agent: Option<AgentId>,
+        seqnum: Option<u32>,
+    ) -> &mut Self {
+        self.code(fc::START_UTTERANCE);
+        self.coded(meta::CONFIDENCE, &encode_float16(confidence));
+        self.coded(meta::PRIORITY, &[priority]);
+        self.coded(meta::TIMESTAMP_META, &timestamp_us.unwrap_or(0).to_be_bytes());
+        if let Some(dest) = dest_agent {
+            self.coded(meta::DEST_AGENT, dest.as_bytes());
+        }
+        if let Some(seq) = seqnum {
+            self.coded(meta::SEQNUM, &seq.to_be_bytes());
+        }
+        self
+    }
+
+    /// Close the utterance and return its wire bytes, or an
+    /// [`AILLError::EncoderError`] if they did not fit in `N` bytes.
+    pub fn end_utterance(&mut self) -> Result<&[u8], AILLError> {
+        self.code(fc::END_UTTERANCE);
+        match self.overflow {
+            Some(needed) => Err(AILLError::EncoderError(format!(
+                "utterance needs {} bytes, buffer holds {}",
+                needed, N
+            ))),
+            None => Ok(self.as_bytes()),
+        }
+    }
+
+    /// Clear the buffer and the overflow flag for the next utterance.
+    pub fn reset(&mut self) -> &mut Self {
+        self.len = 0;
+        self.overflow = None;
+        self
+    }
+
+    // ── Pragmatic acts ──
+
+    pub fn pragma(&mut self, act: u8) -> &mut Self { self.code(act) }
+    pub fn query(&mut self) -> &mut Self { self.code(pragma::QUERY) }
+    pub fn assert_(&mut self) -> &mut Self { self.code(pragma::ASSERT) }
+    pub fn request(&mut self) -> &mut Self { self.code(pragma::REQUEST) }
+    pub fn command(&mut self) -> &mut Self { self.code(pragma::COMMAND) }
+    pub fn acknowledge(&mut self) -> &mut Self { self.code(pragma::ACKNOWLEDGE) }
+    pub fn warn(&mut self) -> &mut Self { self.code(pragma::WARN) }
+
+    // ── Modality and temporal ──
+
+    pub fn modality(&mut self, m: u8) -> &mut Self { self.code(m) }
+    pub fn observed(&mut self) -> &mut Self { self.code(modal::OBSERVED) }
+    pub fn inferred(&mut self) -> &mut Self { self.code(modal::INFERRED) }
+
+    pub fn predicted(&mut self, horizon_ms: f32) -> &mut Self {
+        self.coded(modal::PREDICTED, &encode_float16(horizon_ms))
+    }
+
+    pub fn temporal(&mut self, t: u8) -> &mut Self { self.code(t) }
+
+    // ── Structure ──
+
+    pub fn begin_struct(&mut self) -> &mut Self { self.code(st::BEGIN_STRUCT) }
+    pub fn end_struct(&mut self) -> &mut Self { self.code(st::END_STRUCT) }
+    pub fn field(&mut self, field_code: u16) -> &mut Self { self.coded(st::FIELD_ID, &field_code.to_be_bytes()) }
+    pub fn begin_list(&mut self, count: u16) -> &mut Self { self.coded(st::BEGIN_LIST, &count.to_be_bytes()) }
+    pub fn end_list(&mut self) -> &mut Self { self.code(st::END_LIST) }
+    pub fn begin_map(&mut self, count: u16) -> &mut Self { self.coded(st::BEGIN_MAP, &count.to_be_bytes()) }
+    pub fn end_map(&mut self) -> &mut Self { self.code(st::END_MAP) }
+
+    // ── Typed values ──
+
+    pub fn int8(&mut self, val: i8) -> &mut Self { self.coded(ty::TYPE_INT8, &val.to_be_bytes()) }
+    pub fn int16(&mut self, val: i16) -> &mut Self { self.coded(ty::TYPE_INT16, &val.to_be_bytes()) }
+    pub fn int32(&mut self, val: i32) -> &mut Self { self.coded(ty::TYPE_INT32, &val.to_be_bytes()) }
+    pub fn int64(&mut self, val: i64) -> &mut Self { self.coded(ty::TYPE_INT64, &val.to_be_bytes()) }
+    pub fn uint8(&mut self, val: u8) -> &mut Self { self.coded(ty::TYPE_UINT8, &[val]) }
+    pub fn uint16(&mut self, val: u16) -> &mut Self { self.coded(ty::TYPE_UINT16, &val.to_be_bytes()) }
+    pub fn uint32(&mut self, val: u32) -> &mut Self { self.coded(ty::TYPE_UINT32, &val.to_be_bytes()) }
+    pub fn uint64(&mut self, val: u64) -> &mut Self { self.coded(ty::TYPE_UINT64, &val.to_be_bytes()) }
+    pub fn float16(&mut self, val: f32) -> &mut Self { self.coded(ty::TYPE_FLOAT16, &encode_float16(val)) }
+    pub fn float32(&mut self, val: f32) -> &mut Self { self.coded(ty::TYPE_FLOAT32, &val.to_be_bytes()) }
+    pub fn float64(&mut self, val: f64) -> &mut Self { self.coded(ty::TYPE_FLOAT64, &val.to_be_bytes()) }
+    pub fn bool_(&mut self, val: bool) -> &mut Self { self.coded(ty::TYPE_BOOL, &[val as u8]) }
+    pub fn string(&mut self, val: &str) -> &mut Self { self.sized(ty::TYPE_STRING, val.as_bytes()) }
+    pub fn bytes(&mut self, val: &[u8]) -> &mut Self { self.sized(ty::TYPE_BYTES, val) }
+    pub fn null(&mut self) -> &mut Self { self.code(ty::TYPE_NULL) }
+    pub fn timestamp(&mut self, val: i64) -> &mut Self { self.coded(ty::TYPE_TIMESTAMP, &val.to_be_bytes()) }
+
+    pub fn list_of_float32(&mut self, values: &[f32]) -> &mut Self {
+        self.begin_list(values.len() as u16);
+        for &v in values {
+            self.float32(v);
+        }
+        self.end_list()
+    }
+
+    // ── Domain codebook references ──
+
+    pub fn l1_ref(&mut self, code: u16) -> &mut Self { self.coded(esc::ESCAPE_L1, &code.to_be_bytes()) }
+    pub fn l2_ref(&mut self, code: u16) -> &mut Self { self.coded(esc::ESCAPE_L2, &code.to_be_bytes()) }
+    pub fn l3_ref(&mut self, code: u16) -> &mut Self { self.coded(esc::ESCAPE_L3, &code.to_be_bytes()) }
+
+    // ── Operators and annotations ──
+
+    pub fn op(&mut self, opcode: u8) -> &mut Self { self.code(opcode) }
+    pub fn eq(&mut self) -> &mut Self { self.code(rel::EQ) }
+    pub fn lt(&mut self) -> &mut Self { self.code(rel::LT) }
+    pub fn gt(&mut self) -> &mut Self { self.code(rel::GT) }
+    pub fn distance(&mut self) -> &mut Self { self.code(arith::DISTANCE) }
+
+    pub fn confidence(&mut self, val: f32) -> &mut Self { self.coded(meta::CONFIDENCE, &encode_float16(val)) }
+    pub fn label(&mut self, text: &str) -> &mut Self { self.sized(meta::LABEL, text.as_bytes()) }
+
+    // ── Raw byte access ──
+
+    pub fn raw(&mut self, data: &[u8]) -> &mut Self {
+        self.put(&[data])
+    }
+
+    pub fn current_size(&self) -> usize {
+        self.len
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Whether a write has been dropped for lack of space.
+    pub fn is_overflowed(&self) -> bool {
+        self.overflow.is_some()
+    }
+
+    /// The bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<const N: usize> Default for FixedEncoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLEncoder;
+
+    #[test]
+    fn matches_the_heap_encoder() {
+        let mut fixed = FixedEncoder::<64>::new();
+        fixed.start_utterance_with(0.9, 1, Some(42), None, Some(7));
+        fixed.warn().observed().begin_struct().field(1).float16(0.5).field(2).string("hot").end_struct();
+        let wire = fixed.end_utterance().unwrap().to_vec();
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(0.9, 1, Some(42), None, Some(7));
+        e.warn().observed().begin_struct().field(1).float16(0.5).field(2).string("hot").end_struct();
+        assert_eq!(wire, e.end_utterance());
+    }
+
+    #[test]
+    fn overflow_is_reported_at_the_end() {
+        let mut fixed = FixedEncoder::<20>::new();
+        fixed.start_utterance().assert_().string("does not fit");
+        assert!(fixed.is_overflowed());
+        assert_eq!(fixed.current_size(), 16);
+        let err = fixed.end_utterance().unwrap_err();
+        assert_eq!(err, AILLError::EncoderError("utterance needs 32 bytes, buffer holds 20".into()));
+
+        fixed.reset().start_utterance().assert_().null();
+        assert_eq!(fixed.end_utterance().unwrap().len(), 18);
+    }
+}
@@ -0,0 +1,495 @@
+use crate::ast::{AstNode, LiteralValue, MetaHeader};
+use crate::codebook::base::BASE_CODEBOOK;
+use crate::codebook::{get_domain_codebook, DomainCodebook, DomainEntry};
+use crate::decoder::DEFAULT_MAX_DEPTH;
+
+/// Output style for [`Formatter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Indented multi-line tree, identical to [`pretty_print`].
+    Plain,
+    /// Single-line form, e.g. `ASSERT(STRUCT{0x0000: float32:3.5})`.
+    Compact,
+    /// Indented tree with ANSI colors per codebook category.
+    Ansi,
+    /// Indented tree as HTML, one `<span class="aill-<category>">` per token.
+    Html,
+}
+
+/// Configurable renderer for decoded AILL ASTs.
+///
+/// ```
+/// use aill::format::{Formatter, Style};
+/// # let node = aill::AstNode::ContextRef { sct_index: 1 };
+/// let html = Formatter::new(Style::Html).format(&node);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Formatter<'d> {
+    style: Style,
+    domain: Option<&'d DomainCodebook>,
+    max_depth: usize,
+}
+
+impl<'d> Formatter<'d> {
+    pub fn new(style: Style) -> Self {
+        Self { style, domain: None, max_depth: DEFAULT_MAX_DEPTH }
+    }
+
+    /// Resolve domain references, struct field ids and enumerated UINT8
+    /// values against `domain`.
+    pub fn with_domain(mut self, domain: &'d DomainCodebook) -> Self {
+        self.domain = Some(domain);
+        self
+    }
+
+    /// Elide nodes nested more than `max_depth` levels, e.g. to match a
+    /// decoder's [`DecoderConfig::max_depth`](crate::DecoderConfig::max_depth).
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// The codebook for a reference decoded against `registry`, falling
+    /// back to the one given to [`with_domain`](Self::with_domain).
+    fn codebook(&self, registry: Option<u8>) -> Option<&'d DomainCodebook> {
+        registry.and_then(get_domain_codebook).or(self.domain)
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Render `node` starting at nesting level 0.
+    pub fn format(&self, node: &AstNode) -> String {
+        self.format_indented(node, 0)
+    }
+
+    /// Render `node` starting at the given nesting level (ignored for
+    /// [`Style::Compact`]). Nodes nested more than the maximum depth,
+    /// [`DEFAULT_MAX_DEPTH`] unless set, below `node` are elided as `...`.
+    pub fn format_indented(&self, node: &AstNode, indent: usize) -> String {
+        if self.style == Style::Compact {
+            return self.compact(node, 0);
+        }
+        let mut lines = Vec::new();
+        self.tree(node, indent, 0, &mut lines);
+        let rendered: Vec<String> = lines.iter().map(|l| self.render_line(l)).collect();
+        match self.style {
+            Style::Html => format!("<pre class=\"aill-tree\">{}</pre>", rendered.join("\n")),
+            _ => rendered.join("\n"),
+        }
+    }
+
+    // ── Tree layout ──
+
+    fn tree(&self, node: &AstNode, indent: usize, depth: usize, lines: &mut Vec<Line>) {
+        if depth > self.max_depth {
+            lines.push(Line::new(indent).text("..."));
+            return;
+        }
+        match node {
+            AstNode::Utterance { meta, body } => {
+                lines.push(Line::new(indent).span("UTTERANCE", "frame_control").text(":"));
+                meta_lines(meta, indent + 1, lines);
+                lines.push(Line::new(indent + 1).span("BODY", "frame_control").text(":"));
+                // A UINT8 following a reference to an enumerated entry is its value
+                let mut pending: Option<&DomainEntry> = None;
+                for expr in body {
+                    if let Some(line) = pending.take().and_then(|e| enum_line(e, expr, indent + 2)) {
+                        lines.push(line);
+                        continue;
+                    }
+                    self.tree(expr, indent + 2, depth + 1, lines);
+                    pending = trailing_domain_ref(expr)
+                        .and_then(|(registry, code)| self.codebook(registry)?.lookup(code));
+                }
+            }
+            AstNode::Literal { value_type, value } => {
+                lines.push(
+                    Line::new(indent)
+                        .span(value_type, "type_marker")
+                        .text(": ")
+                        .span(&literal_text(value), "literal"),
+                );
+            }
+            AstNode::Struct { fields } => {
+                lines.push(Line::new(indent).span("STRUCT", "structure").text(":"));
+                for (fid, val) in fields {
+                    let entry = self.domain.and_then(|d| d.lookup(*fid));
+                    let mut line = Line::new(indent + 1).span(&format!("field_0x{:04X}", fid), "structure");
+                    if let Some(e) = entry {
+                        line = line.text(" (").span(e.mnemonic, "escape").text(")");
+                    }
+                    lines.push(line.text(":"));
+                    match entry.and_then(|e| enum_line(e, val, indent + 2)) {
+                        Some(line) => lines.push(line),
+                        None => self.tree(val, indent + 2, depth + 1, lines),
+                    }
+                }
+            }
+            AstNode::List { count, elements } => {
+                lines.push(Line::new(indent).span(&format!("LIST[{}]", count), "structure").text(":"));
+                for elem in elements {
+                    self.tree(elem, indent + 1, depth + 1, lines);
+                }
+            }
+            AstNode::Map { count, pairs } => {
+                lines.push(Line::new(indent).span(&format!("MAP[{}]", count), "structure").text(":"));
+                for (k, v) in pairs {
+                    self.labelled(indent + 1, "key: ", k, depth + 1, lines);
+                    self.labelled(indent + 1, "val: ", v, depth + 1, lines);
+                }
+            }
+            AstNode::Pragmatic { act, expression } => {
+                lines.push(Line::new(indent).span(act, "pragmatic").text(":"));
+                self.tree(expression, indent + 1, depth + 1, lines);
+            }
+            AstNode::Modal { modality, expression, extra } => {
+                let extra_str = match extra {
+                    Some(v) => format!(" (horizon={}ms)", v),
+                    None => String::new(),
+                };
+                lines.push(
+                    Line::new(indent)
+                        .text("[")
+                        .span(&format!("{}{}", modality, extra_str), "modality")
+                        .text("]:"),
+                );
+                self.tree(expression, indent + 1, depth + 1, lines);
+            }
+            AstNode::Temporal { modifier, expression } => {
+                lines.push(Line::new(indent).text("<").span(modifier, "temporal").text(">:"));
+                self.tree(expression, indent + 1, depth + 1, lines);
+            }
+            AstNode::SchemaRef { schema_id, expression } => {
+                lines.push(Line::new(indent).span(&format!("SCHEMA_REF(0x{:04X})", schema_id), "structure").text(":"));
+                self.tree(expression, indent + 1, depth + 1, lines);
+            }
+            AstNode::DomainRef { level, domain_code, registry } => {
+                let target = match self.codebook(*registry).and_then(|d| d.lookup(*domain_code).map(|e| (d.name, e))) {
+                    Some((name, e)) => format!("{}/{}", name, e.mnemonic),
+                    None => format!("DOMAIN_0x{:04X}", domain_code),
+                };
+                lines.push(
+                    Line::new(indent)
+                        .span(&format!("REF({}: {})", level_name(*level), target), "escape"),
+                );
+            }
+            AstNode::CodebookRef { level, registry_id } => {
+                let name = get_domain_codebook(*registry_id)
+                    .map(|d| d.name.to_string())
+                    .unwrap_or_else(|| format!("REGISTRY_0x{:02X}", registry_id));
+                lines.push(
+                    Line::new(indent).span(&format!("CODEBOOK_REF({} = {})", level_name(*level), name), "escape"),
+                );
+            }
+            AstNode::ContextRef { sct_index } => {
+                lines.push(Line::new(indent).span(&format!("SCT_REF[{}]", sct_index), "meta"));
+            }
+            AstNode::HashRef { hash } => {
+                lines.push(Line::new(indent).span(&format!("HASH_REF(0x{:08X})", hash), "meta"));
+            }
+            AstNode::Code { code, mnemonic } => {
+                lines.push(Line::new(indent).span(mnemonic, BASE_CODEBOOK[*code as usize].category));
+            }
+            AstNode::FrameControl { mnemonic, operands, .. } => {
+                let mut line = Line::new(indent).span(mnemonic, "frame_control");
+                if !operands.is_empty() {
+                    let operands: Vec<String> = operands.iter().map(|o| o.to_string()).collect();
+                    line = line.text(&format!("({})", operands.join(", ")));
+                }
+                lines.push(line);
+            }
+            AstNode::Annotated { mnemonic, .. } => {
+                lines.push(Line::new(indent).span(mnemonic, "meta"));
+            }
+            AstNode::Extension { mnemonic, payload, .. } => {
+                let mut line = Line::new(indent).span(mnemonic, "reserved");
+                if !payload.is_empty() {
+                    line = line.text(&format!("({})", hex(payload)));
+                }
+                lines.push(line);
+            }
+            AstNode::Raw { bytes } => {
+                lines.push(
+                    Line::new(indent).span(&format!("RAW[{}]", bytes.len()), "reserved").text(&format!("({})", hex(bytes))),
+                );
+            }
+            AstNode::Placeholder { name, value_type } => {
+                lines.push(Line::new(indent).span(value_type, "type_marker").text(": ?").text(name));
+            }
+        }
+    }
+
+    /// Lay out `node` with its first line prefixed by `label`. Continuation
+    /// lines keep the nesting they would have at level 0.
+    fn labelled(&self, indent: usize, label: &str, node: &AstNode, depth: usize, lines: &mut Vec<Line>) {
+        let mut inner = Vec::new();
+        self.tree(node, 0, depth, &mut inner);
+        let mut inner = inner.into_iter();
+        if let Some(first) = inner.next() {
+            let mut line = Line::new(indent).text(label);
+            line.spans.extend(first.spans);
+            lines.push(line);
+        }
+        lines.extend(inner);
+    }
+
+    fn render_line(&self, line: &Line) -> String {
+        let mut out = "  ".repeat(line.indent);
+        for span in &line.spans {
+            match (self.style, span.class) {
+                (Style::Ansi, Some(class)) => {
+                    out.push_str(&format!("\x1b[{}m{}\x1b[0m", ansi_color(class), span.text));
+                }
+                (Style::Html, Some(class)) => {
+                    out.push_str(&format!(
+                        "<span class=\"aill-{}\">{}</span>",
+                        class,
+                        html_escape(&span.text)
+                    ));
+                }
+                (Style::Html, None) => out.push_str(&html_escape(&span.text)),
+                _ => out.push_str(&span.text),
+            }
+        }
+        out
+    }
+
+    // ── Compact layout ──
+
+    fn compact(&self, node: &AstNode, depth: usize) -> String {
+        if depth > self.max_depth {
+            return "...".to_string();
+        }
+        match node {
+            AstNode::Utterance { meta, body } => {
+                let mut hdr = format!(
+                    "UTTERANCE(confidence={:.2} priority={} timestamp={}",
+                    meta.confidence, meta.priority, meta.timestamp_us
+                );
+                if let Some(ref dest) = meta.dest_agent {
+                    hdr.push_str(&format!(" dest={}", hex(dest.as_bytes())));
+                }
+                if let Some(seq) = meta.seqnum {
+                    hdr.push_str(&format!(" seqnum={}", seq));
+                }
+                let exprs: Vec<String> = body.iter().map(|e| self.compact(e, depth + 1)).collect();
+                format!("{}) {{ {} }}", hdr, exprs.join("; "))
+            }
+            AstNode::Literal { value_type, value } => match value {
+                LiteralValue::String(s) => format!("{}:{:?}", value_type, s),
+                _ => format!("{}:{}", value_type, literal_text(value)),
+            },
+            AstNode::Struct { fields } => {
+                let items: Vec<String> = fields
+                    .iter()
+                    .map(|(fid, v)| format!("0x{:04X}: {}", fid, self.compact(v, depth + 1)))
+                    .collect();
+                format!("STRUCT{{{}}}", items.join(", "))
+            }
+            AstNode::List { count, elements } => {
+                let items: Vec<String> = elements.iter().map(|e| self.compact(e, depth + 1)).collect();
+                format!("LIST[{}]({})", count, items.join(", "))
+            }
+            AstNode::Map { count, pairs } => {
+                let items: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{} => {}", self.compact(k, depth + 1), self.compact(v, depth + 1)))
+                    .collect();
+                format!("MAP[{}]({})", count, items.join(", "))
+            }
+            AstNode::Pragmatic { act, expression } => format!("{}({})", act, self.compact(expression, depth + 1)),
+            AstNode::Modal { modality, expression, extra } => match extra {
+                Some(v) => format!("[{} horizon={}ms]({})", modality, v, self.compact(expression, depth + 1)),
+                None => format!("[{}]({})", modality, self.compact(expression, depth + 1)),
+            },
+            AstNode::Temporal { modifier, expression } => {
+                format!("<{}>({})", modifier, self.compact(expression, depth + 1))
+            }
+            AstNode::SchemaRef { schema_id, expression } => {
+                format!("SCHEMA_REF(0x{:04X})({})", schema_id, self.compact(expression, depth + 1))
+            }
+            _ => {
+                let mut lines = Vec::new();
+                self.tree(node, 0, depth, &mut lines);
+                lines.iter().map(|l| self.render_line(l)).collect::<Vec<_>>().join(" ")
+            }
+        }
+    }
+}
+
+/// Produce a human-readable representation of a decoded AILL AST.
+pub fn pretty_print(node: &AstNode, indent: usize) -> String {
+    Formatter::new(Style::Plain).format_indented(node, indent)
+}
+
+/// Like [`pretty_print`], but resolves domain references and struct field ids
+/// against `domain`, and shows enumerated UINT8 state codes by name
+/// (e.g. `EMERGENCY_LEVEL=3 (danger)`).
+pub fn pretty_print_with_domain(node: &AstNode, indent: usize, domain: &DomainCodebook) -> String {
+    Formatter::new(Style::Plain).with_domain(domain).format_indented(node, indent)
+}
+
+/// ANSI SGR parameters for a codebook category.
+pub fn ansi_color(category: &str) -> &'static str {
+    match category {
+        "frame_control" => "1;90",
+        "type_marker" => "32",
+        "literal" => "92",
+        "structure" => "36",
+        "quantifier" => "35",
+        "logic" => "35",
+        "relational" => "33",
+        "arithmetic" => "33",
+        "temporal" => "34",
+        "modality" => "94",
+        "pragmatic" => "1;31",
+        "meta" => "90",
+        "escape" => "95",
+        _ => "2",
+    }
+}
+
+struct Span {
+    text: String,
+    class: Option<&'static str>,
+}
+
+struct Line {
+    indent: usize,
+    spans: Vec<Span>,
+}
+
+impl Line {
+    fn new(indent: usize) -> Self {
+        Self { indent, spans: Vec::new() }
+    }
+
+    fn span(mut self, text: &str, class: &'static str) -> Self {
+        self.spans.push(Span { text: text.to_string(), class: Some(class) });
+        self
+    }
+
+    fn text(mut self, text: &str) -> Self {
+        self.spans.push(Span { text: text.to_string(), class: None });
+        self
+    }
+}
+
+fn meta_lines(meta: &MetaHeader, indent: usize, lines: &mut Vec<Line>) {
+    lines.push(Line::new(indent).span("META", "meta").text(&format!(
+        ": confidence={:.2} priority={} timestamp={}",
+        meta.confidence, meta.priority, meta.timestamp_us
+    )));
+    if let Some(ref dest) = meta.dest_agent {
+        lines.push(Line::new(indent + 1).text(&format!("dest_agent={}", hex(dest.as_bytes()))));
+    }
+    if let Some(seq) = meta.seqnum {
+        lines.push(Line::new(indent + 1).text(&format!("seqnum={}", seq)));
+    }
+}
+
+pub(crate) fn literal_text(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::Int8(v) => v.to_string(),
+        LiteralValue::Int16(v) => v.to_string(),
+        LiteralValue::Int32(v) => v.to_string(),
+        LiteralValue::Int64(v) => v.to_string(),
+        LiteralValue::Uint8(v) => v.to_string(),
+        LiteralValue::Uint16(v) => v.to_string(),
+        LiteralValue::Uint32(v) => v.to_string(),
+        LiteralValue::Uint64(v) => v.to_string(),
+        LiteralValue::Float16(v) => format!("{}", v),
+        LiteralValue::Float32(v) => format!("{}", v),
+        LiteralValue::Float64(v) => format!("{}", v),
+        LiteralValue::Bool(v) => v.to_string(),
+        LiteralValue::String(v) => v.clone(),
+        LiteralValue::Bytes(v) => format!("{:?}", v),
+        LiteralValue::Timestamp(v) => v.to_string(),
+        LiteralValue::Null => "None".to_string(),
+        LiteralValue::Measurement { value, stddev } => format!("{} ± {}", value, stddev),
+    }
+}
+
+fn level_name(level: u8) -> &'static str {
+    match level {
+        1 => "L1",
+        2 => "L2",
+        3 => "L3",
+        _ => "?",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The registry and domain code referenced at the end of `node`, looking
+/// through pragmatic, modal and temporal wrappers.
+fn trailing_domain_ref(mut node: &AstNode) -> Option<(Option<u8>, u16)> {
+    loop {
+        match node {
+            AstNode::DomainRef { domain_code, registry, .. } => return Some((*registry, *domain_code)),
+            AstNode::Pragmatic { expression, .. }
+            | AstNode::Modal { expression, .. }
+            | AstNode::Temporal { expression, .. }
+            | AstNode::SchemaRef { expression, .. } => node = expression,
+            _ => return None,
+        }
+    }
+}
+
+/// Render a UINT8 literal as a named enum value of `entry`, if it is one.
+fn enum_line(entry: &DomainEntry, node: &AstNode, indent: usize) -> Option<Line> {
+    let AstNode::Literal { value: LiteralValue::Uint8(v), .. } = node else {
+        return None;
+    };
+    let label = entry.enum_label(*v)?;
+    Some(
+        Line::new(indent)
+            .span(entry.mnemonic, "escape")
+            .text("=")
+            .span(&v.to_string(), "literal")
+            .text(&format!(" ({})", label)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AILLDecoder, AILLEncoder};
+
+    fn sample() -> AstNode {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_();
+        e.begin_struct().field(0x0001).string("a<b").field(0x0002).float32(1.5).end_struct();
+        AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+    }
+
+    #[test]
+    fn compact_is_single_line() {
+        let out = Formatter::new(Style::Compact).format(&sample());
+        assert!(!out.contains('\n'));
+        assert!(out.contains("ASSERT(STRUCT{0x0001: string:\"a<b\", 0x0002: float32:1.5})"), "{}", out);
+    }
+
+    #[test]
+    fn ansi_colors_pragmatic_acts() {
+        let out = Formatter::new(Style::Ansi).format(&sample());
+        assert!(out.contains("\x1b[1;31mASSERT\x1b[0m"), "{}", out);
+    }
+
+    #[test]
+    fn html_escapes_and_tags_categories() {
+        let out = Formatter::new(Style::Html).format(&sample());
+        assert!(out.starts_with("<pre class=\"aill-tree\">"));
+        assert!(out.contains("<span class=\"aill-pragmatic\">ASSERT</span>"));
+        assert!(out.contains("a&lt;b"));
+        assert!(!out.contains("a<b"));
+    }
+}
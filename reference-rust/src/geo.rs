@@ -0,0 +1,291 @@
+//! Geodesy for NAV-1 coordinates and geofences.
+//!
+//! NAV-1 mixes WGS84 fixes (LATITUDE, LONGITUDE, ALTITUDE_MSL) with local
+//! positions in metres (POSITION_2D, GEOFENCE). A [`LocalFrame`] converts
+//! between the two through ECEF, as east/north/up offsets from an origin.
+//! [`haversine_m`] gives the great-circle distance between two fixes.
+//!
+//! A [`Geofence`] is a restricted polygon in local metres, encoded as
+//!
+//! ```text
+//! ESCAPE_L1 GEOFENCE        LIST[n](LIST[2](FLOAT32 east, FLOAT32 north))
+//! ESCAPE_L1 GEOFENCE_STATUS UINT8 status
+//! ```
+//!
+//! with GEOFENCE_STATUS values as in [`GeofenceStatus`].
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::encoder::AILLEncoder;
+
+/// NAV-1 GEOFENCE entry code.
+pub const GEOFENCE: u16 = 0x0039;
+/// NAV-1 GEOFENCE_STATUS entry code.
+pub const GEOFENCE_STATUS: u16 = 0x003A;
+
+/// WGS84 semi-major axis in metres.
+pub const WGS84_A: f64 = 6_378_137.0;
+/// WGS84 flattening.
+pub const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// Mean Earth radius used by [`haversine_m`], in metres.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// A WGS84 position; altitude is above the ellipsoid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wgs84 {
+    pub lat_deg: f64,
+    pub lon_deg: f64,
+    pub alt_m: f64,
+}
+
+/// Offsets in metres east, north and up of a [`LocalFrame`] origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Enu {
+    pub east_m: f64,
+    pub north_m: f64,
+    pub up_m: f64,
+}
+
+impl Wgs84 {
+    pub fn new(lat_deg: f64, lon_deg: f64, alt_m: f64) -> Self {
+        Self { lat_deg, lon_deg, alt_m }
+    }
+
+    /// Earth-centred, earth-fixed coordinates in metres.
+    pub fn to_ecef(&self) -> [f64; 3] {
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let (lat, lon) = (self.lat_deg.to_radians(), self.lon_deg.to_radians());
+        let n = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        [
+            (n + self.alt_m) * lat.cos() * lon.cos(),
+            (n + self.alt_m) * lat.cos() * lon.sin(),
+            (n * (1.0 - e2) + self.alt_m) * lat.sin(),
+        ]
+    }
+
+    /// Inverse of [`to_ecef`](Self::to_ecef) (Bowring's method).
+    pub fn from_ecef([x, y, z]: [f64; 3]) -> Self {
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let b = WGS84_A * (1.0 - WGS84_F);
+        let ep2 = (WGS84_A * WGS84_A - b * b) / (b * b);
+        let p = x.hypot(y);
+        let theta = (z * WGS84_A).atan2(p * b);
+        let lat = (z + ep2 * b * theta.sin().powi(3)).atan2(p - e2 * WGS84_A * theta.cos().powi(3));
+        let n = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        let alt = if lat.cos().abs() > 1e-9 { p / lat.cos() - n } else { z.abs() - b };
+        Self { lat_deg: lat.to_degrees(), lon_deg: y.atan2(x).to_degrees(), alt_m: alt }
+    }
+}
+
+/// Great-circle distance between two fixes on a spherical Earth, ignoring
+/// altitude.
+pub fn haversine_m(a: &Wgs84, b: &Wgs84) -> f64 {
+    let (lat1, lat2) = (a.lat_deg.to_radians(), b.lat_deg.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.lon_deg - a.lon_deg).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// East-north-up frame tangent to the ellipsoid at `origin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalFrame {
+    origin: Wgs84,
+    origin_ecef: [f64; 3],
+}
+
+impl LocalFrame {
+    pub fn new(origin: Wgs84) -> Self {
+        Self { origin, origin_ecef: origin.to_ecef() }
+    }
+
+    pub fn origin(&self) -> Wgs84 {
+        self.origin
+    }
+
+    pub fn to_enu(&self, point: &Wgs84) -> Enu {
+        let p = point.to_ecef();
+        let d = [p[0] - self.origin_ecef[0], p[1] - self.origin_ecef[1], p[2] - self.origin_ecef[2]];
+        let (sin_lat, cos_lat) = self.origin.lat_deg.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.origin.lon_deg.to_radians().sin_cos();
+        Enu {
+            east_m: -sin_lon * d[0] + cos_lon * d[1],
+            north_m: -sin_lat * cos_lon * d[0] - sin_lat * sin_lon * d[1] + cos_lat * d[2],
+            up_m: cos_lat * cos_lon * d[0] + cos_lat * sin_lon * d[1] + sin_lat * d[2],
+        }
+    }
+
+    pub fn to_wgs84(&self, enu: &Enu) -> Wgs84 {
+        let (sin_lat, cos_lat) = self.origin.lat_deg.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.origin.lon_deg.to_radians().sin_cos();
+        let Enu { east_m: e, north_m: n, up_m: u } = *enu;
+        Wgs84::from_ecef([
+            self.origin_ecef[0] - sin_lon * e - sin_lat * cos_lon * n + cos_lat * cos_lon * u,
+            self.origin_ecef[1] + cos_lon * e - sin_lat * sin_lon * n + cos_lat * sin_lon * u,
+            self.origin_ecef[2] + cos_lat * n + sin_lat * u,
+        ])
+    }
+}
+
+/// GEOFENCE_STATUS value: where an agent is relative to a restricted area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum GeofenceStatus {
+    /// Outside the fence and clear of its margin.
+    Clear = 0,
+    /// Outside, but within the warning margin of the boundary.
+    Approaching = 1,
+    /// Inside the restricted area.
+    Breach = 2,
+}
+
+impl GeofenceStatus {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(GeofenceStatus::Clear),
+            1 => Some(GeofenceStatus::Approaching),
+            2 => Some(GeofenceStatus::Breach),
+            _ => None,
+        }
+    }
+}
+
+/// A restricted polygon in local metres (east, north).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geofence {
+    pub vertices: Vec<(f64, f64)>,
+}
+
+impl Geofence {
+    pub fn new(vertices: Vec<(f64, f64)>) -> Self {
+        Self { vertices }
+    }
+
+    /// Whether `(east, north)` lies inside the polygon (even-odd rule).
+    pub fn contains(&self, east: f64, north: f64) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a.1 > north) != (b.1 > north) && east < a.0 + (north - a.1) * (b.0 - a.0) / (b.1 - a.1) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Distance from `(east, north)` to the nearest edge, in metres.
+    pub fn distance_to_boundary(&self, east: f64, north: f64) -> f64 {
+        self.edges()
+            .map(|(a, b)| {
+                let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+                let len2 = dx * dx + dy * dy;
+                let t = if len2 > 0.0 { (((east - a.0) * dx + (north - a.1) * dy) / len2).clamp(0.0, 1.0) } else { 0.0 };
+                (east - (a.0 + t * dx)).hypot(north - (a.1 + t * dy))
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Status of a position, warning within `margin_m` of the boundary.
+    pub fn status(&self, east: f64, north: f64, margin_m: f64) -> GeofenceStatus {
+        if self.contains(east, north) {
+            GeofenceStatus::Breach
+        } else if self.distance_to_boundary(east, north) <= margin_m {
+            GeofenceStatus::Approaching
+        } else {
+            GeofenceStatus::Clear
+        }
+    }
+
+    /// Status of a WGS84 fix, with the fence expressed in `frame`.
+    pub fn status_of_fix(&self, frame: &LocalFrame, fix: &Wgs84, margin_m: f64) -> GeofenceStatus {
+        let enu = frame.to_enu(fix);
+        self.status(enu.east_m, enu.north_m, margin_m)
+    }
+
+    /// Read the value of a GEOFENCE reference: a list of 2-element lists
+    /// of floats.
+    pub fn from_ast(node: &AstNode) -> Option<Self> {
+        let AstNode::List { elements, .. } = node else {
+            return None;
+        };
+        let vertex = |node: &AstNode| match node {
+            AstNode::List { elements, .. } if elements.len() == 2 => Some((float(&elements[0])?, float(&elements[1])?)),
+            _ => None,
+        };
+        elements.iter().map(vertex).collect::<Option<Vec<_>>>().map(Self::new)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+}
+
+fn float(node: &AstNode) -> Option<f64> {
+    match node {
+        AstNode::Literal { value: LiteralValue::Float16(v) | LiteralValue::Float32(v), .. } => Some(*v as f64),
+        AstNode::Literal { value: LiteralValue::Float64(v), .. } => Some(*v),
+        _ => None,
+    }
+}
+
+impl AILLEncoder {
+    /// Emit NAV-1 GEOFENCE with the fence's vertices as FLOAT32 pairs.
+    pub fn geofence(&mut self, fence: &Geofence) -> &mut Self {
+        self.l1_ref(GEOFENCE).begin_list(fence.vertices.len() as u16);
+        for &(east, north) in &fence.vertices {
+            self.begin_list(2).float32(east as f32).float32(north as f32).end_list();
+        }
+        self.end_list()
+    }
+
+    /// Emit NAV-1 GEOFENCE_STATUS.
+    pub fn geofence_status(&mut self, status: GeofenceStatus) -> &mut Self {
+        self.l1_ref(GEOFENCE_STATUS).uint8(status as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::AILLDecoder;
+
+    #[test]
+    fn enu_round_trip_and_distances() {
+        let origin = Wgs84::new(47.3977, 8.5456, 488.0);
+        let frame = LocalFrame::new(origin);
+        let p = Wgs84::new(47.3987, 8.5476, 500.0);
+        let enu = frame.to_enu(&p);
+        assert!((enu.east_m - 151.0).abs() < 1.0, "{:?}", enu);
+        assert!((enu.north_m - 111.2).abs() < 1.0, "{:?}", enu);
+        let back = frame.to_wgs84(&enu);
+        assert!((back.lat_deg - p.lat_deg).abs() < 1e-9 && (back.lon_deg - p.lon_deg).abs() < 1e-9);
+        assert!((back.alt_m - p.alt_m).abs() < 1e-3);
+
+        // One degree of latitude is about 111.2 km
+        let d = haversine_m(&Wgs84::new(0.0, 0.0, 0.0), &Wgs84::new(1.0, 0.0, 0.0));
+        assert!((d - 111_195.0).abs() < 1.0, "{}", d);
+        assert!((haversine_m(&origin, &p) - enu.east_m.hypot(enu.north_m)).abs() < 0.5);
+    }
+
+    #[test]
+    fn geofence_status_and_encoding() {
+        // An L-shaped restricted area
+        let fence = Geofence::new(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 10.0), (10.0, 10.0), (10.0, 20.0), (0.0, 20.0)]);
+        assert_eq!(fence.status(5.0, 15.0, 2.0), GeofenceStatus::Breach);
+        assert_eq!(fence.status(15.0, 11.0, 2.0), GeofenceStatus::Approaching);
+        assert_eq!(fence.status(15.0, 15.0, 2.0), GeofenceStatus::Clear);
+        assert!((fence.distance_to_boundary(15.0, 15.0) - 5.0).abs() < 1e-9);
+
+        let frame = LocalFrame::new(Wgs84::new(47.0, 8.0, 0.0));
+        let fix = frame.to_wgs84(&Enu { east_m: 5.0, north_m: 5.0, up_m: 0.0 });
+        assert_eq!(fence.status_of_fix(&frame, &fix, 0.0), GeofenceStatus::Breach);
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().geofence(&fence);
+        e.geofence_status(GeofenceStatus::Approaching);
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        let AstNode::Utterance { body, .. } = &ast else { panic!() };
+        assert_eq!(Geofence::from_ast(&body[1]), Some(fence));
+        assert!(matches!(&body[3], AstNode::Literal { value: LiteralValue::Uint8(1), .. }));
+        assert_eq!(GeofenceStatus::from_u8(1), Some(GeofenceStatus::Approaching));
+    }
+}
@@ -0,0 +1,384 @@
+//! Time intervals, Allen relations and deadlines.
+//!
+//! The temporal opcodes carry no operands of their own; these helpers fix
+//! the expressions that follow them:
+//!
+//! ```text
+//! DURATION INT64 micros
+//! STRUCT { 0x0000: TIMESTAMP start, 0x0001: TIMESTAMP end }             interval
+//! STRUCT { 0x0000: TIMESTAMP start, 0x0002: DURATION INT64 micros }     interval
+//! T_BEFORE | T_AFTER | T_DURING | ... LIST[2](interval a, interval b)   a <relation> b
+//! T_DEADLINE STRUCT { 0x0000: TIMESTAMP deadline, 0x0001: expression }
+//! ```
+//!
+//! A [`DeadlineScheduler`] orders pending work earliest deadline first, and
+//! takes the deadline and priority of an utterance straight from its AST.
+
+use std::collections::BTreeMap;
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::base::{temporal, BASE_CODEBOOK};
+use crate::encoder::AILLEncoder;
+
+/// Interval struct field carrying the start timestamp.
+pub const START_FIELD: u16 = 0x0000;
+/// Interval struct field carrying the end timestamp.
+pub const END_FIELD: u16 = 0x0001;
+/// Interval struct field carrying the length as a DURATION.
+pub const DURATION_FIELD: u16 = 0x0002;
+/// T_DEADLINE struct field carrying the deadline timestamp.
+pub const DEADLINE_FIELD: u16 = 0x0000;
+/// T_DEADLINE struct field carrying the time-sensitive expression.
+pub const PAYLOAD_FIELD: u16 = 0x0001;
+
+/// A span of time in microseconds, `start_us` inclusive, `end_us` exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    pub start_us: i64,
+    pub end_us: i64,
+}
+
+impl Interval {
+    /// Interval between two timestamps, in either order.
+    pub fn new(start_us: i64, end_us: i64) -> Self {
+        Self { start_us: start_us.min(end_us), end_us: start_us.max(end_us) }
+    }
+
+    pub fn from_duration(start_us: i64, duration_us: i64) -> Self {
+        Self::new(start_us, start_us.saturating_add(duration_us))
+    }
+
+    pub fn duration_us(&self) -> i64 {
+        self.end_us - self.start_us
+    }
+
+    pub fn contains(&self, t_us: i64) -> bool {
+        (self.start_us..self.end_us).contains(&t_us)
+    }
+
+    /// Which Allen relation `self` has to `other`. The opcodes only name
+    /// one of each inverse pair besides BEFORE / AFTER, so e.g. for "contains"
+    /// this is `None` and `other.relation(self)` is DURING.
+    pub fn relation(&self, other: &Interval) -> Option<AllenRelation> {
+        let (a, b) = (self, other);
+        Some(if a.end_us < b.start_us {
+            AllenRelation::Before
+        } else if b.end_us < a.start_us {
+            AllenRelation::After
+        } else if a.end_us == b.start_us {
+            AllenRelation::Meets
+        } else if a == b {
+            AllenRelation::Simultaneous
+        } else if a.start_us == b.start_us && a.end_us < b.end_us {
+            AllenRelation::Starts
+        } else if a.end_us == b.end_us && a.start_us > b.start_us {
+            AllenRelation::Finishes
+        } else if a.start_us > b.start_us && a.end_us < b.end_us {
+            AllenRelation::During
+        } else if a.start_us < b.start_us && a.end_us < b.end_us {
+            AllenRelation::Overlaps
+        } else {
+            return None;
+        })
+    }
+
+    /// Read an interval struct in either form.
+    pub fn from_ast(node: &AstNode) -> Option<Self> {
+        let AstNode::Struct { fields } = node else {
+            return None;
+        };
+        let start = timestamp(fields.get(&START_FIELD)?)?;
+        match (fields.get(&END_FIELD), fields.get(&DURATION_FIELD)) {
+            (Some(end), _) => Some(Self::new(start, timestamp(end)?)),
+            (None, Some(duration)) => Some(Self::from_duration(start, duration_of(duration)?)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The Allen interval relations that have an opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AllenRelation {
+    Before,
+    After,
+    During,
+    Simultaneous,
+    Starts,
+    Finishes,
+    Overlaps,
+    Meets,
+}
+
+impl AllenRelation {
+    pub fn code(self) -> u8 {
+        match self {
+            AllenRelation::Before => temporal::T_BEFORE,
+            AllenRelation::After => temporal::T_AFTER,
+            AllenRelation::During => temporal::T_DURING,
+            AllenRelation::Simultaneous => temporal::T_SIMULTANEOUS,
+            AllenRelation::Starts => temporal::T_STARTS,
+            AllenRelation::Finishes => temporal::T_FINISHES,
+            AllenRelation::Overlaps => temporal::T_OVERLAPS,
+            AllenRelation::Meets => temporal::T_MEETS,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        [
+            AllenRelation::Before,
+            AllenRelation::After,
+            AllenRelation::During,
+            AllenRelation::Simultaneous,
+            AllenRelation::Starts,
+            AllenRelation::Finishes,
+            AllenRelation::Overlaps,
+            AllenRelation::Meets,
+        ]
+        .into_iter()
+        .find(|r| r.code() == code)
+    }
+
+    fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        (temporal::T_BEFORE..=temporal::T_MEETS)
+            .find(|&code| BASE_CODEBOOK[code as usize].mnemonic == mnemonic)
+            .and_then(Self::from_code)
+    }
+}
+
+impl AILLEncoder {
+    /// Emit `DURATION INT64 <micros>`.
+    pub fn duration(&mut self, micros: i64) -> &mut Self {
+        self.temporal(temporal::DURATION).int64(micros)
+    }
+
+    /// Emit an interval as a start/end struct.
+    pub fn interval(&mut self, interval: &Interval) -> &mut Self {
+        self.begin_struct().field(START_FIELD).timestamp(interval.start_us);
+        self.field(END_FIELD).timestamp(interval.end_us).end_struct()
+    }
+
+    /// Emit an interval as a start/duration struct.
+    pub fn interval_with_duration(&mut self, interval: &Interval) -> &mut Self {
+        self.begin_struct().field(START_FIELD).timestamp(interval.start_us);
+        self.field(DURATION_FIELD).duration(interval.duration_us()).end_struct()
+    }
+
+    /// Emit `a <relation> b`.
+    pub fn allen(&mut self, relation: AllenRelation, a: &Interval, b: &Interval) -> &mut Self {
+        self.temporal(relation.code()).begin_list(2).interval(a).interval(b).end_list()
+    }
+
+    /// Begin T_DEADLINE { deadline, payload }. Encode the time-sensitive
+    /// expression next, then close with `end_struct()`.
+    pub fn deadline(&mut self, deadline_us: i64) -> &mut Self {
+        self.temporal(temporal::T_DEADLINE);
+        self.begin_struct().field(DEADLINE_FIELD).timestamp(deadline_us).field(PAYLOAD_FIELD)
+    }
+}
+
+/// The relation and intervals of an Allen-relation expression.
+pub fn allen(node: &AstNode) -> Option<(AllenRelation, Interval, Interval)> {
+    let AstNode::Temporal { modifier, expression } = node else {
+        return None;
+    };
+    let relation = AllenRelation::from_mnemonic(modifier)?;
+    match expression.as_ref() {
+        AstNode::List { elements, .. } if elements.len() == 2 => {
+            Some((relation, Interval::from_ast(&elements[0])?, Interval::from_ast(&elements[1])?))
+        }
+        _ => None,
+    }
+}
+
+/// The microseconds of a `DURATION INT64` expression.
+pub fn duration_of(node: &AstNode) -> Option<i64> {
+    match node {
+        AstNode::Temporal { modifier, expression } if modifier == "DURATION" => match expression.as_ref() {
+            AstNode::Literal { value: LiteralValue::Int64(us), .. } => Some(*us),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Deadline of an utterance: the first T_DEADLINE at body level or
+/// wrapped in an act, modality or temporal modifier there. Takes the
+/// earliest if an expression carries several.
+pub fn deadline(ast: &AstNode) -> Option<i64> {
+    match ast {
+        AstNode::Utterance { body, .. } => body.iter().filter_map(deadline).min(),
+        AstNode::Temporal { modifier, expression } if modifier == "T_DEADLINE" => {
+            let AstNode::Struct { fields } = expression.as_ref() else {
+                return None;
+            };
+            let own = fields.get(&DEADLINE_FIELD).and_then(timestamp);
+            let inner = fields.get(&PAYLOAD_FIELD).and_then(deadline);
+            own.into_iter().chain(inner).min()
+        }
+        AstNode::Pragmatic { expression, .. }
+        | AstNode::Modal { expression, .. }
+        | AstNode::Temporal { expression, .. } => deadline(expression),
+        _ => None,
+    }
+}
+
+/// Whether the utterance carries a deadline at or before `now_us`.
+pub fn is_past_deadline(ast: &AstNode, now_us: i64) -> bool {
+    deadline(ast).is_some_and(|d| d <= now_us)
+}
+
+fn timestamp(node: &AstNode) -> Option<i64> {
+    match node {
+        AstNode::Literal { value: LiteralValue::Timestamp(t), .. } => Some(*t),
+        _ => None,
+    }
+}
+
+/// Position of a task in the queue: deadlines first, earliest first, then
+/// higher priority, then arrival order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Rank {
+    no_deadline: bool,
+    deadline_us: i64,
+    inverse_priority: u8,
+    order: u64,
+}
+
+/// Earliest-deadline-first queue of pending work.
+pub struct DeadlineScheduler<T> {
+    tasks: BTreeMap<Rank, T>,
+    next_order: u64,
+}
+
+impl<T> Default for DeadlineScheduler<T> {
+    fn default() -> Self {
+        Self { tasks: BTreeMap::new(), next_order: 0 }
+    }
+}
+
+impl<T> DeadlineScheduler<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Queue `task` with a priority (0 lowest, 7 highest) and optional
+    /// deadline. Tasks without a deadline run after all that have one.
+    pub fn push(&mut self, task: T, priority: u8, deadline_us: Option<i64>) {
+        let rank = Rank {
+            no_deadline: deadline_us.is_none(),
+            deadline_us: deadline_us.unwrap_or(i64::MAX),
+            inverse_priority: u8::MAX - priority,
+            order: self.next_order,
+        };
+        self.next_order += 1;
+        self.tasks.insert(rank, task);
+    }
+
+    /// The next task to run.
+    pub fn pop(&mut self) -> Option<T> {
+        self.tasks.pop_first().map(|(_, task)| task)
+    }
+
+    /// Deadline of the next task, if it has one.
+    pub fn next_deadline(&self) -> Option<i64> {
+        self.tasks.keys().next().filter(|r| !r.no_deadline).map(|r| r.deadline_us)
+    }
+
+    /// Remove and return, in queue order, the tasks whose deadline is at
+    /// or before `now_us`.
+    pub fn expire(&mut self, now_us: i64) -> Vec<T> {
+        let mut expired = Vec::new();
+        while self.next_deadline().is_some_and(|d| d <= now_us) {
+            expired.extend(self.pop());
+        }
+        expired
+    }
+}
+
+impl DeadlineScheduler<AstNode> {
+    /// Queue an utterance under its PRIORITY and [`deadline`].
+    pub fn push_utterance(&mut self, ast: AstNode) {
+        let priority = match &ast {
+            AstNode::Utterance { meta, .. } => meta.priority,
+            _ => 0,
+        };
+        let deadline_us = deadline(&ast);
+        self.push(ast, priority, deadline_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::AILLDecoder;
+
+    #[test]
+    fn allen_relations_between_intervals() {
+        let a = Interval::new(0, 10);
+        assert_eq!(a.relation(&Interval::new(20, 30)), Some(AllenRelation::Before));
+        assert_eq!(a.relation(&Interval::new(-20, -10)), Some(AllenRelation::After));
+        assert_eq!(a.relation(&Interval::new(10, 30)), Some(AllenRelation::Meets));
+        assert_eq!(a.relation(&Interval::new(0, 10)), Some(AllenRelation::Simultaneous));
+        assert_eq!(a.relation(&Interval::new(0, 30)), Some(AllenRelation::Starts));
+        assert_eq!(a.relation(&Interval::new(-5, 10)), Some(AllenRelation::Finishes));
+        assert_eq!(a.relation(&Interval::new(-5, 15)), Some(AllenRelation::During));
+        assert_eq!(a.relation(&Interval::new(5, 15)), Some(AllenRelation::Overlaps));
+        // Contains is the inverse of DURING
+        assert_eq!(a.relation(&Interval::new(2, 8)), None);
+        assert!(a.contains(0) && !a.contains(10));
+    }
+
+    #[test]
+    fn intervals_and_deadlines_round_trip() {
+        let a = Interval::new(1_000, 5_000);
+        let b = Interval::from_duration(4_000, 2_000);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().allen(AllenRelation::Overlaps, &a, &b);
+        e.deadline(9_000).propose().interval_with_duration(&b).end_struct();
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+
+        let AstNode::Utterance { body, .. } = &ast else { panic!() };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        assert_eq!(allen(expression), Some((AllenRelation::Overlaps, a, b)));
+        assert_eq!(deadline(&ast), Some(9_000));
+        assert!(!is_past_deadline(&ast, 8_999));
+        assert!(is_past_deadline(&ast, 9_000));
+    }
+
+    #[test]
+    fn scheduler_runs_earliest_deadline_first() {
+        let mut s = DeadlineScheduler::new();
+        s.push("background", 7, None);
+        s.push("late", 1, Some(300));
+        s.push("soon", 1, Some(100));
+        s.push("soon, urgent", 5, Some(100));
+        assert_eq!(s.next_deadline(), Some(100));
+        assert_eq!(s.pop(), Some("soon, urgent"));
+        assert_eq!(s.expire(200), vec!["soon"]);
+        assert_eq!(s.pop(), Some("late"));
+        assert_eq!(s.pop(), Some("background"));
+        assert!(s.is_empty());
+
+        let utterance = |priority: u8, deadline_us: Option<i64>| {
+            let mut e = AILLEncoder::new();
+            e.start_utterance_with(1.0, priority, None, None, None);
+            match deadline_us {
+                Some(d) => e.deadline(d).assert_().uint8(priority).end_struct(),
+                None => e.assert_().uint8(priority),
+            };
+            AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+        };
+        let mut s = DeadlineScheduler::new();
+        s.push_utterance(utterance(7, None));
+        s.push_utterance(utterance(2, Some(50)));
+        assert_eq!(deadline(&s.pop().unwrap()), Some(50));
+    }
+}
@@ -1,22 +1,95 @@
+//! AILL reference implementation.
+//!
+//! Cargo features:
+//!
+//! | Feature       | Adds                                                         |
+//! |---------------|--------------------------------------------------------------|
+//! | `core`        | Wire format, codebooks, encoder and decoder (always built)   |
+//! | `ast-serde`   | Serde for the AST and state types, JSON helpers (default)    |
+//! | `sha256`      | Utterance hashes: `conversation`, `dedup`, `router`, and     |
+//! |               | HMAC-SHA256 epochs (default)                                 |
+//! | `audio-core`  | Float acoustic modem and profiles; implies `ast-serde`       |
+//! | `audio-fixed` | Q15 fixed-point acoustic decoder, without serde or FFT crate |
+//! | `audio`       | `audio-core` plus WAV files                                  |
+//! | `audio-live`  | `audio` plus microphone and speaker I/O                      |
+//! | `wasm`        | JS bindings; `wasm-audio` adds the acoustic modem            |
+//!
+//! `default-features = false` gives the minimal `core` build, which pulls
+//! in neither serde, sha2 nor any audio crate.
+
 pub mod error;
 pub mod wire;
+pub mod agent;
 pub mod codebook;
 pub mod ast;
 pub mod encoder;
+pub mod fixed;
 pub mod decoder;
+pub mod format;
+pub mod capture;
+pub mod templates;
+pub mod delta;
+#[cfg(feature = "sha256")]
+pub mod conversation;
+pub mod correlator;
+pub mod trace;
+pub mod cost;
+#[cfg(feature = "sha256")]
+pub mod router;
+#[cfg(feature = "sha256")]
+pub mod dedup;
+pub mod addressing;
+pub mod comm;
+pub mod extension;
+pub mod schema;
+pub mod thread;
+pub mod reliability;
+pub mod reorder;
+pub mod stream;
+pub mod attachment;
+pub mod batch;
+pub mod compress;
+pub mod fastpath;
+pub mod ratelimit;
+pub mod events;
+pub mod session;
+pub mod typed;
+pub mod macros;
+pub mod estimate;
+pub mod clock;
+pub mod timestamp;
+pub mod interval;
+pub mod geo;
+pub mod spatial;
+pub mod testing;
+#[cfg(feature = "ast-serde")]
+pub mod conformance;
+pub mod analysis;
+pub mod lint;
+
+#[cfg(feature = "decode-stats")]
+pub mod profile;
 
 #[cfg(feature = "wasm")]
 pub mod wasm;
 
-#[cfg(feature = "audio-core")]
+#[cfg(any(feature = "audio-core", feature = "audio-fixed"))]
 pub mod audio;
 
 // Re-exports for convenience
 pub use error::AILLError;
+pub use agent::AgentId;
 pub use ast::{AstNode, MetaHeader, LiteralValue, DecodedEpoch};
-pub use encoder::{AILLEncoder, EpochBuilder};
-pub use decoder::{AILLDecoder, decode_epoch, pretty_print};
-pub use wire::{crc8, encode_varint, decode_varint, encode_float16, decode_float16};
+pub use encoder::{AILLEncoder, EpochBuilder, EpochWriter, Float16Fallback, Float16Warning};
+pub use decoder::{AILLDecoder, DecoderConfig, DecodeObserver, NodeKind, UnknownDomainPolicy, UnknownDomainRef, Utterances, DEFAULT_MAX_DEPTH, decode_epoch, decode_epoch_with};
+pub use format::{pretty_print, pretty_print_with_domain};
+pub use capture::{pretty_print_capture, pretty_print_epoch};
+pub use events::{Events, WireEvent};
+pub use fixed::FixedEncoder;
+pub use wire::{
+    crc8, encode_varint, decode_varint, encode_float16, encode_float16_checked, decode_float16, encode_f16_slice,
+    decode_f16_slice, Float16Overflow,
+};
 pub use codebook::{
     base::{self, BASE_CODEBOOK, CodeEntry},
     DomainCodebook, DomainEntry,
@@ -0,0 +1,310 @@
+//! Pre-flight checks of a wire message.
+//!
+//! [`Linter::lint`] decodes one utterance strictly and reports what would
+//! trip up a receiver, each finding with the byte offset it points at:
+//! decode errors and trailing bytes, meta-header values out of range, and
+//! domain values whose shape does not match their codebook entry's
+//! `value_type`. Only references on an escape level bound to a registry
+//! (see [`DecoderConfig::bind_escape`](crate::DecoderConfig::bind_escape))
+//! are checked against a codebook.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ast::{AnnotationValue, AstNode, LiteralValue, MetaHeader};
+use crate::codebook::base::meta;
+use crate::codebook::types::{ScalarType, TypeExpr};
+use crate::codebook::{get_domain_codebook, DomainCodebook, DomainEntry};
+use crate::decoder::{AILLDecoder, DecodeObserver, NodeKind};
+use crate::error::AILLError;
+
+/// Largest priority the spec defines (0 lowest, 7 highest).
+pub const MAX_PRIORITY: u8 = 7;
+
+/// How far a timestamp may lie ahead of the linter's clock before it is
+/// reported, in microseconds.
+pub const FUTURE_TOLERANCE_US: i64 = 60_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The message is usable but probably not what the sender meant.
+    Warning,
+    /// A receiver will reject or misread the message.
+    Error,
+}
+
+/// One finding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    /// Byte offset into the message the finding points at.
+    pub offset: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "offset 0x{:04X}: {}: {}", self.offset, severity, self.message)
+    }
+}
+
+/// Checks wire messages; see the [module docs](self).
+#[derive(Default)]
+pub struct Linter {
+    decoder: AILLDecoder,
+    now_us: Option<i64>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode with `decoder`, e.g. one with escape levels bound.
+    pub fn with_decoder(mut self, decoder: AILLDecoder) -> Self {
+        self.decoder = decoder;
+        self
+    }
+
+    /// Report timestamps more than [`FUTURE_TOLERANCE_US`] ahead of `now_us`.
+    pub fn with_now(mut self, now_us: i64) -> Self {
+        self.now_us = Some(now_us);
+        self
+    }
+
+    /// All findings for the utterance in `wire`, in offset order.
+    pub fn lint(&self, wire: &[u8]) -> Vec<Lint> {
+        let mut rec = Recorder::default();
+        let result = self.decoder.decode_utterance_with_observer(wire, &mut rec);
+        let mut lints = Vec::new();
+        match result {
+            Ok(AstNode::Utterance { meta, .. }) => {
+                if rec.end < wire.len() {
+                    lints.push(warning(rec.end, format!("{} trailing bytes after END_UTTERANCE", wire.len() - rec.end)));
+                }
+                self.check_meta(&meta, &rec, &mut lints);
+                check_domain_values(&rec.nodes, &mut lints);
+            }
+            Ok(_) => lints.push(error(0, "not an utterance".into())),
+            Err(e) => {
+                let offset = match e {
+                    AILLError::UnexpectedEof { offset, .. } => offset,
+                    _ => rec.last_offset,
+                };
+                lints.push(error(offset, e.to_string()));
+            }
+        }
+        lints.sort_by_key(|l| l.offset);
+        lints
+    }
+
+    fn check_meta(&self, hdr: &MetaHeader, rec: &Recorder, lints: &mut Vec<Lint>) {
+        // Each check points at the annotation's payload, one past its opcode
+        let at = |code: u8| rec.meta_offsets.get(&code).map_or(0, |o| o + 1);
+        if !(0.0..=1.0).contains(&hdr.confidence) {
+            lints.push(error(at(meta::CONFIDENCE), format!("confidence {} is outside 0..=1", hdr.confidence)));
+        }
+        if hdr.priority > MAX_PRIORITY {
+            lints.push(warning(
+                at(meta::PRIORITY),
+                format!("priority {} is above the highest defined level {}", hdr.priority, MAX_PRIORITY),
+            ));
+        }
+        if let Some(now) = self.now_us {
+            if hdr.timestamp_us > now.saturating_add(FUTURE_TOLERANCE_US) {
+                let ahead = (hdr.timestamp_us - now) as f64 / 1e6;
+                lints.push(warning(at(meta::TIMESTAMP_META), format!("timestamp is {:.1} s in the future", ahead)));
+            }
+        }
+        if let Some(AnnotationValue::U16(ttl)) = hdr.annotations.get("ttl") {
+            if *ttl == 0 {
+                lints.push(warning(at(meta::TTL), "TTL 0 expires the message before it is delivered".into()));
+            } else if hdr.timestamp_us == 0 {
+                lints.push(warning(at(meta::TTL), "TTL without a timestamp; receivers cannot tell when it expires".into()));
+            }
+        }
+        if hdr.source_agent.is_some() && hdr.source_agent == hdr.dest_agent {
+            lints.push(warning(at(meta::DEST_AGENT), "message is addressed to its own sender".into()));
+        }
+    }
+}
+
+/// Findings for `wire` with the default decoder, see [`Linter`].
+pub fn lint(wire: &[u8]) -> Vec<Lint> {
+    Linter::new().lint(wire)
+}
+
+/// Offsets and spans gathered while decoding.
+#[derive(Default)]
+struct Recorder {
+    /// Offset of the first opcode of each meta annotation in the header.
+    meta_offsets: BTreeMap<u8, usize>,
+    in_meta: bool,
+    /// Offset of the last opcode or node the decoder reached.
+    last_offset: usize,
+    /// Decoded body nodes as `(start, end, node)`, innermost first.
+    nodes: Vec<(usize, usize, AstNode)>,
+    end: usize,
+}
+
+impl DecodeObserver for Recorder {
+    fn on_opcode(&mut self, offset: usize, code: u8) {
+        self.last_offset = offset;
+        if self.in_meta {
+            self.meta_offsets.entry(code).or_insert(offset);
+        }
+    }
+
+    fn on_node_start(&mut self, offset: usize, kind: NodeKind) {
+        self.last_offset = offset;
+        self.in_meta = kind == NodeKind::MetaHeader;
+    }
+
+    fn on_node_end(&mut self, start: usize, end: usize, node: &AstNode) {
+        match node {
+            AstNode::Utterance { .. } => self.end = end,
+            _ => self.nodes.push((start, end, node.clone())),
+        }
+    }
+}
+
+/// Check the value following each bound domain reference against the
+/// entry's type. The value is the outermost node starting where the
+/// reference ends.
+fn check_domain_values(nodes: &[(usize, usize, AstNode)], lints: &mut Vec<Lint>) {
+    for (start, end, node) in nodes {
+        let AstNode::DomainRef { domain_code, registry: Some(registry), .. } = node else {
+            continue;
+        };
+        let Some(codebook) = get_domain_codebook(*registry) else {
+            lints.push(warning(*start, format!("unknown registry 0x{:02X}", registry)));
+            continue;
+        };
+        let Some(entry) = codebook.lookup(*domain_code) else {
+            lints.push(warning(*start, format!("code 0x{:04X} is not in {}", domain_code, codebook.name)));
+            continue;
+        };
+        let value = nodes.iter().filter(|(s, _, _)| s == end).max_by_key(|(_, e, _)| *e);
+        let Some((value_start, _, value)) = value else {
+            continue;
+        };
+        let Some(ty) = entry_type(entry) else {
+            continue;
+        };
+        if let Some(problem) = mismatch(&ty, value, codebook) {
+            lints.push(warning(*value_start, format!("{}.{} ({}): {}", codebook.name, entry.mnemonic, ty, problem)));
+        }
+    }
+}
+
+/// An entry's type, with enumerated `UINT8` states as [`TypeExpr::Enum`].
+fn entry_type(entry: &DomainEntry) -> Option<TypeExpr> {
+    entry.enum_type().or_else(|| entry.type_expr().ok())
+}
+
+/// Why `node` is not a value of type `ty`, if it is not.
+fn mismatch(ty: &TypeExpr, node: &AstNode, codebook: &DomainCodebook) -> Option<String> {
+    match (ty, node) {
+        (TypeExpr::None, _) => None,
+        (TypeExpr::Scalar(ScalarType::Uint128), AstNode::Literal { value: LiteralValue::Bytes(b), .. }) if b.len() == 16 => {
+            None
+        }
+        (TypeExpr::Scalar(scalar), AstNode::Literal { value_type, .. }) => (!value_type.eq_ignore_ascii_case(scalar.name()))
+            .then(|| format!("expected {}, got {}", scalar.name(), value_type)),
+        (TypeExpr::Bytes(len), AstNode::Literal { value: LiteralValue::Bytes(b), .. }) => match len {
+            Some(n) if b.len() != *n => Some(format!("expected {} bytes, got {}", n, b.len())),
+            _ => None,
+        },
+        (TypeExpr::Array { element, len }, AstNode::List { elements, .. }) => match len {
+            Some(n) if elements.len() != *n => Some(format!("expected {} elements, got {}", n, elements.len())),
+            _ => elements_mismatch(element, elements, codebook),
+        },
+        (TypeExpr::List(element), AstNode::List { elements, .. }) => elements_mismatch(element, elements, codebook),
+        (TypeExpr::Struct(names), AstNode::Struct { fields }) => (!names.is_empty() && fields.len() > names.len())
+            .then(|| format!("expected at most {} fields, got {}", names.len(), fields.len())),
+        (TypeExpr::Enum(variants), AstNode::Literal { value: LiteralValue::Uint8(v), .. }) => ((*v as usize) >= variants.len())
+            .then(|| format!("{} is not one of the {} defined values", v, variants.len())),
+        (TypeExpr::Named(name), _) => {
+            let entry = codebook.entries().iter().find(|e| e.mnemonic == name)?;
+            mismatch(&entry_type(entry)?, node, codebook)
+        }
+        (_, other) => Some(format!("expected {}, got {}", ty, describe(other))),
+    }
+}
+
+fn elements_mismatch(element: &TypeExpr, elements: &[AstNode], codebook: &DomainCodebook) -> Option<String> {
+    elements
+        .iter()
+        .enumerate()
+        .find_map(|(i, el)| mismatch(element, el, codebook).map(|why| format!("element {}: {}", i, why)))
+}
+
+fn describe(node: &AstNode) -> String {
+    match node {
+        AstNode::Literal { value_type, .. } => value_type.clone(),
+        AstNode::Struct { .. } => "struct".into(),
+        AstNode::List { .. } => "list".into(),
+        AstNode::Map { .. } => "map".into(),
+        _ => "an expression".into(),
+    }
+}
+
+fn warning(offset: usize, message: String) -> Lint {
+    Lint { offset, severity: Severity::Warning, message }
+}
+
+fn error(offset: usize, message: String) -> Lint {
+    Lint { offset, severity: Severity::Error, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::nav::NAV1_REGISTRY_ID;
+    use crate::decoder::DecoderConfig;
+    use crate::encoder::AILLEncoder;
+
+    fn nav_linter() -> Linter {
+        Linter::new().with_decoder(AILLDecoder::with_config(DecoderConfig::default().bind_escape(1, NAV1_REGISTRY_ID)))
+    }
+
+    #[test]
+    fn clean_message_has_no_findings() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(0.9, 3, Some(1_000), None, None).assert_();
+        e.l1_ref(0x0002).float32(1.5);
+        e.l1_ref(0x0001).begin_list(2).float32(1.0).float32(2.0).end_list();
+        assert_eq!(nav_linter().with_now(1_000).lint(&e.end_utterance()), []);
+    }
+
+    #[test]
+    fn findings_point_at_offending_bytes() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(0.9, 9, Some(0), None, None).assert_();
+        e.l1_ref(0x0002).uint8(1);
+        e.l1_ref(0x0001).begin_list(3).float32(1.0).float32(2.0).float32(3.0).end_list();
+        e.l1_ref(0x0FFF).null();
+        let mut wire = e.end_utterance();
+        wire.push(0x00);
+
+        let lints = nav_linter().lint(&wire);
+        let messages: Vec<String> = lints.iter().map(|l| l.to_string()).collect();
+        assert_eq!(lints.len(), 5, "{:#?}", messages);
+        assert_eq!((lints[0].offset, lints[0].severity), (5, Severity::Warning));
+        assert!(messages[0].contains("priority 9"));
+        // START, CONFIDENCE, PRIORITY, TIMESTAMP, ASSERT, ESCAPE_L1
+        assert_eq!(lints[1].offset, 19);
+        assert!(messages[1].ends_with("NAV-1.HEADING (FLOAT32): expected FLOAT32, got uint8"), "{}", messages[1]);
+        assert!(messages[2].contains("expected 2 elements, got 3"));
+        assert!(messages[3].contains("code 0x0FFF is not in NAV-1"));
+        assert!(messages[4].contains("1 trailing bytes"));
+
+        let truncated = lint(&wire[..wire.len() - 4]);
+        assert_eq!(truncated.len(), 1);
+        assert_eq!(truncated[0].severity, Severity::Error);
+    }
+}
@@ -0,0 +1,289 @@
+//! The [`aill!`](crate::aill) macro and its support code.
+
+use crate::agent::AgentId;
+use crate::codebook::base::BASE_CODEBOOK;
+use crate::codebook::DomainEntry;
+use crate::encoder::AILLEncoder;
+
+/// Build an utterance from mnemonics and literals.
+///
+/// ```
+/// use aill::aill;
+///
+/// let wire = aill!(ASSERT OBSERVED nav::GOTO { 0x0000: [1.0f32, 2.0, 0.5] });
+/// let mut e = aill::AILLEncoder::new();
+/// e.start_utterance().assert_().observed().l1_ref(0x0090);
+/// e.begin_struct().field(0x0000).begin_list(3).float32(1.0).float64(2.0).float64(0.5).end_list().end_struct();
+/// assert_eq!(wire, e.end_utterance());
+/// ```
+///
+/// The input is a sequence of:
+///
+/// | syntax | encodes |
+/// |---|---|
+/// | `ASSERT`, `OBSERVED`, `PAST`, `ADD`, `TYPE_NULL`, ... | the base codebook opcode with that mnemonic |
+/// | `PREDICTED(ms)`, `CONFIDENCE(c)`, `LABEL(s)` | opcodes that take an argument |
+/// | `nav::GOTO`, `safety::EMERGENCY_LEVEL`, ... | `ESCAPE_L1` reference to a domain entry |
+/// | `1u8`, `-2i16`, `1.5f32`, `true`, `"text"` | a literal typed after the Rust literal |
+/// | `(expr)` | a literal from a runtime value |
+/// | `{ key: value, ... }` | a struct; keys are `u16` literals or domain entries |
+/// | `[a, b, ...]` | a list |
+/// | `map { key => value, ... }` | a map |
+///
+/// Mnemonics and domain entries are resolved at compile time, so a typo
+/// fails to compile:
+///
+/// ```compile_fail
+/// let wire = aill::aill!(ASERT nav::GOTO);
+/// ```
+///
+/// Unsuffixed literals follow Rust's defaults: `2.0` is a
+/// FLOAT64 and `7` an INT32.
+///
+/// `aill!(in encoder; ...)` writes the expressions into an existing
+/// `&mut AILLEncoder` instead, without utterance framing. Long inputs may
+/// need a higher `#![recursion_limit]`.
+#[macro_export]
+macro_rules! aill {
+    // ── Body items ──
+    (@items $e:ident;) => {};
+    (@items $e:ident; , $($rest:tt)*) => { $crate::aill!(@items $e; $($rest)*) };
+    (@items $e:ident; => $($rest:tt)*) => { $crate::aill!(@items $e; $($rest)*) };
+    (@items $e:ident; $key:literal : $($rest:tt)*) => {
+        $e.field($key);
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; $dom:ident :: $name:ident : $($rest:tt)*) => {
+        $e.field($crate::aill!(@domain $dom $name));
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; $dom:ident :: $name:ident $($rest:tt)*) => {
+        $e.l1_ref($crate::aill!(@domain $dom $name));
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; { $($body:tt)* } $($rest:tt)*) => {
+        $e.begin_struct();
+        $crate::aill!(@items $e; $($body)*);
+        $e.end_struct();
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; [] $($rest:tt)*) => {
+        $e.begin_list(0).end_list();
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; map {} $($rest:tt)*) => {
+        $e.begin_map(0).end_map();
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; [ $($body:tt)* ] $($rest:tt)*) => {
+        $crate::macros::list($e, |$e| {
+            let mut n = 0u16;
+            $crate::aill!(@split $e n []; $($body)*);
+            n
+        });
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; map { $($body:tt)* } $($rest:tt)*) => {
+        $crate::macros::map($e, |$e| {
+            let mut n = 0u16;
+            $crate::aill!(@split $e n []; $($body)*);
+            n
+        });
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; PREDICTED ( $horizon_ms:expr ) $($rest:tt)*) => {
+        $e.predicted($horizon_ms);
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; CONFIDENCE ( $c:expr ) $($rest:tt)*) => {
+        $e.confidence($c);
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; LABEL ( $s:expr ) $($rest:tt)*) => {
+        $e.label($s);
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; ( $value:expr ) $($rest:tt)*) => {
+        $crate::macros::Literal::write($value, &mut *$e);
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; $value:literal $($rest:tt)*) => {
+        $crate::macros::Literal::write($value, &mut *$e);
+        $crate::aill!(@items $e; $($rest)*)
+    };
+    (@items $e:ident; $mnemonic:ident $($rest:tt)*) => {
+        $e.op({
+            const CODE: u8 = $crate::macros::base_code(stringify!($mnemonic));
+            CODE
+        });
+        $crate::aill!(@items $e; $($rest)*)
+    };
+
+    // ── Comma-separated container elements, counted into $n ──
+    (@split $e:ident $n:ident [$($cur:tt)*]; , $($rest:tt)*) => {
+        $n += 1;
+        $crate::aill!(@items $e; $($cur)*);
+        $crate::aill!(@split $e $n []; $($rest)*)
+    };
+    (@split $e:ident $n:ident []; ) => {};
+    (@split $e:ident $n:ident [$($cur:tt)+]; ) => {
+        $n += 1;
+        $crate::aill!(@items $e; $($cur)+)
+    };
+    (@split $e:ident $n:ident [$($cur:tt)*]; $t:tt $($rest:tt)*) => {
+        $crate::aill!(@split $e $n [$($cur)* $t]; $($rest)*)
+    };
+
+    // ── Domain entry codes ──
+    (@domain $dom:ident $name:ident) => {{
+        const CODE: u16 = $crate::macros::domain_code($crate::aill!(@entries $dom), stringify!($name));
+        CODE
+    }};
+    (@entries nav) => { $crate::codebook::nav::NAV1_ENTRIES };
+    (@entries percept) => { $crate::codebook::percept::PERCEPT1_ENTRIES };
+    (@entries manip) => { $crate::codebook::manip::MANIP1_ENTRIES };
+    (@entries comm) => { $crate::codebook::comm::COMM1_ENTRIES };
+    (@entries diag) => { $crate::codebook::diag::DIAG1_ENTRIES };
+    (@entries plan) => { $crate::codebook::plan::PLAN1_ENTRIES };
+    (@entries safety) => { $crate::codebook::safety::SAFETY1_ENTRIES };
+
+    // ── Entry points ──
+    (in $enc:expr; $($body:tt)*) => {{
+        let e: &mut $crate::AILLEncoder = $enc;
+        $crate::aill!(@items e; $($body)*);
+    }};
+    ($($body:tt)*) => {{
+        let mut enc = $crate::AILLEncoder::new();
+        enc.start_utterance();
+        {
+            let e = &mut enc;
+            $crate::aill!(@items e; $($body)*);
+        }
+        enc.end_utterance()
+    }};
+}
+
+/// A Rust value that [`aill!`](crate::aill) can write as a typed literal.
+pub trait Literal {
+    fn write(self, e: &mut AILLEncoder);
+}
+
+macro_rules! literal_impl {
+    ($($ty:ty => $method:ident),* $(,)?) => {
+        $(
+            impl Literal for $ty {
+                fn write(self, e: &mut AILLEncoder) {
+                    e.$method(self);
+                }
+            }
+        )*
+    };
+}
+
+literal_impl! {
+    i8 => int8, i16 => int16, i32 => int32, i64 => int64,
+    u8 => uint8, u16 => uint16, u32 => uint32, u64 => uint64,
+    f32 => float32, f64 => float64, bool => bool_,
+    &str => string, &[u8] => bytes,
+}
+
+impl Literal for &String {
+    fn write(self, e: &mut AILLEncoder) {
+        e.string(self);
+    }
+}
+
+impl Literal for AgentId {
+    fn write(self, e: &mut AILLEncoder) {
+        e.bytes(self.as_bytes());
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Base codebook opcode for `mnemonic`; fails const evaluation if none.
+#[doc(hidden)]
+pub const fn base_code(mnemonic: &str) -> u8 {
+    let mut i = 0;
+    while i < BASE_CODEBOOK.len() {
+        if str_eq(BASE_CODEBOOK[i].mnemonic, mnemonic) {
+            return i as u8;
+        }
+        i += 1;
+    }
+    panic!("aill!: unknown base codebook mnemonic");
+}
+
+/// Code of the domain entry named `mnemonic`; fails const evaluation if none.
+#[doc(hidden)]
+pub const fn domain_code(entries: &[DomainEntry], mnemonic: &str) -> u16 {
+    let mut i = 0;
+    while i < entries.len() {
+        if str_eq(entries[i].mnemonic, mnemonic) {
+            return entries[i].code;
+        }
+        i += 1;
+    }
+    panic!("aill!: unknown domain entry");
+}
+
+/// A list whose element count is known once `body` has written it.
+#[doc(hidden)]
+pub fn list(e: &mut AILLEncoder, body: impl FnOnce(&mut AILLEncoder) -> u16) {
+    let at = e.begin_list(0).current_size() - 2;
+    let n = body(e);
+    e.patch_count(at, n).end_list();
+}
+
+/// A map whose entry count is known once `body` has written it.
+#[doc(hidden)]
+pub fn map(e: &mut AILLEncoder, body: impl FnOnce(&mut AILLEncoder) -> u16) {
+    let at = e.begin_map(0).current_size() - 2;
+    let n = body(e);
+    e.patch_count(at, n).end_map();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codebook::base::temporal;
+    use crate::AILLEncoder;
+
+    #[test]
+    fn expands_to_encoder_calls() {
+        let id = 7u32;
+        let wire = aill!(
+            comm::MSG_ID (9u64)
+            WARN PAST PREDICTED(500.0) CONFIDENCE(0.5) {
+                safety::EMERGENCY_LEVEL: 3u8,
+                0x0001: map { "a" => [], "b" => [-1i8, TYPE_NULL] },
+                0x0002: (id),
+            }
+        );
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().l1_ref(0x0027).uint64(9);
+        e.warn().temporal(temporal::PAST).predicted(500.0).confidence(0.5).begin_struct();
+        e.field(0x0000).uint8(3);
+        e.field(0x0001).begin_map(2).string("a").begin_list(0).end_list();
+        e.string("b").begin_list(2).int8(-1).null().end_list().end_map();
+        e.field(0x0002).uint32(7).end_struct();
+        assert_eq!(wire, e.end_utterance());
+
+        let mut e = AILLEncoder::new();
+        aill!(in &mut e; QUERY nav::GOTO);
+        assert_eq!(e.current_size(), 4);
+    }
+}
@@ -0,0 +1,227 @@
+//! Decode instrumentation for sizing heaps (`decode-stats` feature).
+//!
+//! [`AILLDecoder::decode_with_stats`] decodes an utterance and reports in a
+//! [`DecodeStats`] how many AST nodes of each kind it built and how deeply
+//! they nest. Heap figures need [`CountingAllocator`] installed as the
+//! global allocator; they are kept per thread, so decodes running on other
+//! threads do not disturb them.
+//!
+//! ```
+//! use aill::profile::CountingAllocator;
+//! use aill::{AILLDecoder, AILLEncoder};
+//!
+//! #[global_allocator]
+//! static ALLOC: CountingAllocator = CountingAllocator::new();
+//!
+//! let mut e = AILLEncoder::new();
+//! e.start_utterance().assert_().list_of_int32(&[1, 2, 3]);
+//! let (ast, stats) = AILLDecoder::new().decode_with_stats(&e.end_utterance());
+//! assert!(ast.is_ok());
+//! assert_eq!(stats.nodes, 6);
+//! assert!(stats.heap.unwrap().peak_bytes > 0);
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ast::AstNode;
+use crate::decoder::{AILLDecoder, DecodeObserver, NodeKind};
+use crate::error::AILLError;
+
+/// Heap use during one decode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Most bytes held at once beyond those held when decoding began.
+    pub peak_bytes: usize,
+    /// Bytes still held when decoding finished: the AST and what it owns.
+    pub retained_bytes: usize,
+    pub allocations: usize,
+}
+
+/// What a decode built; see the [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Wire bytes the utterance took.
+    pub bytes: usize,
+    /// AST nodes started, the utterance included.
+    pub nodes: usize,
+    pub nodes_by_kind: BTreeMap<NodeKind, usize>,
+    /// Deepest nesting of nodes below the utterance.
+    pub max_depth: usize,
+    /// `None` unless [`CountingAllocator`] is the global allocator.
+    pub heap: Option<HeapStats>,
+}
+
+impl DecodeStats {
+    /// Add the figures of another decode, keeping the larger peaks.
+    pub fn merge(&mut self, other: &DecodeStats) {
+        self.bytes += other.bytes;
+        self.nodes += other.nodes;
+        for (kind, n) in &other.nodes_by_kind {
+            *self.nodes_by_kind.entry(*kind).or_default() += n;
+        }
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.heap = match (self.heap, other.heap) {
+            (Some(a), Some(b)) => Some(HeapStats {
+                peak_bytes: a.peak_bytes.max(b.peak_bytes),
+                retained_bytes: a.retained_bytes + b.retained_bytes,
+                allocations: a.allocations + b.allocations,
+            }),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+/// Counts nodes as the decoder reports them.
+#[derive(Default)]
+struct Collector {
+    stats: DecodeStats,
+    depth: usize,
+}
+
+impl DecodeObserver for Collector {
+    fn on_node_start(&mut self, _offset: usize, kind: NodeKind) {
+        // The meta header is folded into the utterance and never ends
+        if kind == NodeKind::MetaHeader {
+            return;
+        }
+        self.stats.nodes += 1;
+        *self.stats.nodes_by_kind.entry(kind).or_default() += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth);
+        self.depth += 1;
+    }
+
+    fn on_node_end(&mut self, _start: usize, end: usize, node: &AstNode) {
+        self.depth = self.depth.saturating_sub(1);
+        if matches!(node, AstNode::Utterance { .. }) {
+            self.stats.bytes = end;
+        }
+    }
+}
+
+impl AILLDecoder {
+    /// Decode an utterance, measuring what the decode built.
+    pub fn decode_with_stats(&self, data: &[u8]) -> (Result<AstNode, AILLError>, DecodeStats) {
+        let mut collector = Collector::default();
+        let before = heap_snapshot();
+        let result = self.decode_utterance_with_observer(data, &mut collector);
+        collector.stats.heap = heap_since(before);
+        (result, collector.stats)
+    }
+}
+
+// ── Allocation counting ──
+
+/// Whether a [`CountingAllocator`] has served an allocation.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+struct Counters {
+    live: Cell<isize>,
+    peak: Cell<isize>,
+    allocations: Cell<usize>,
+}
+
+thread_local! {
+    static HEAP: Counters = const {
+        Counters { live: Cell::new(0), peak: Cell::new(0), allocations: Cell::new(0) }
+    };
+}
+
+fn record(grow: isize, allocation: bool) {
+    // Fails only while the thread is being torn down
+    let _ = HEAP.try_with(|h| {
+        let live = h.live.get() + grow;
+        h.live.set(live);
+        h.peak.set(h.peak.get().max(live));
+        h.allocations.set(h.allocations.get() + allocation as usize);
+    });
+}
+
+/// Live bytes and allocation count, after resetting the peak to the
+/// current level.
+fn heap_snapshot() -> (isize, usize) {
+    HEAP.with(|h| {
+        h.peak.set(h.live.get());
+        (h.live.get(), h.allocations.get())
+    })
+}
+
+fn heap_since((live, allocations): (isize, usize)) -> Option<HeapStats> {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    HEAP.with(|h| {
+        Some(HeapStats {
+            peak_bytes: (h.peak.get() - live).max(0) as usize,
+            retained_bytes: (h.live.get() - live).max(0) as usize,
+            allocations: h.allocations.get() - allocations,
+        })
+    })
+}
+
+/// A global allocator that counts the bytes each thread holds, wrapping
+/// `A` ([`System`] by default).
+pub struct CountingAllocator<A = System> {
+    inner: A,
+}
+
+impl CountingAllocator<System> {
+    pub const fn new() -> Self {
+        Self { inner: System }
+    }
+}
+
+impl Default for CountingAllocator<System> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> CountingAllocator<A> {
+    pub const fn with_inner(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+fn installed() {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        INSTALLED.store(true, Ordering::Relaxed);
+    }
+}
+
+// SAFETY: every call is forwarded to `inner` unchanged; the counters
+// touch no heap memory.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            installed();
+            record(layout.size() as isize, true);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            installed();
+            record(layout.size() as isize, true);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        record(-(layout.size() as isize), false);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = self.inner.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            record(new_size as isize - layout.size() as isize, true);
+        }
+        new
+    }
+}
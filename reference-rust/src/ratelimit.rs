@@ -0,0 +1,297 @@
+//! Per-topic and per-priority bandwidth budgets.
+//!
+//! An acoustic link carries around 100 bps, so a telemetry stream sending
+//! a few utterances a second would leave no room for commands. A
+//! [`RateLimiter`] sits between the application and the
+//! [`EpochWriter`](crate::EpochWriter) and holds each class of traffic to
+//! a [`Budget`] of utterances and bytes per second. An utterance's class
+//! is its TOPIC meta tag if a budget is configured for that topic, and
+//! otherwise its PRIORITY; classes without a budget are not limited.
+//!
+//! Budgets are token buckets that hold `burst_secs` worth of allowance. An
+//! utterance over budget is handled by the class's [`OverflowPolicy`]:
+//! dropped, queued until the budget refills, or merged so that only the
+//! latest pending utterance of the class is sent. Queued utterances go out
+//! in order, and are released by [`RateLimiter::poll`]. Time is passed in
+//! explicitly as microseconds.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::ast::{AnnotationValue, AstNode};
+use crate::decoder::AILLDecoder;
+use crate::error::AILLError;
+
+/// What happens to an utterance that exceeds its class's budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discard it.
+    #[default]
+    Drop,
+    /// Hold up to `limit` utterances until the budget allows them.
+    Queue { limit: usize },
+    /// Hold only the newest utterance, replacing any older pending one.
+    Merge,
+}
+
+/// Allowance for one class of traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Budget {
+    pub messages_per_sec: Option<f64>,
+    pub bytes_per_sec: Option<f64>,
+    /// Seconds of allowance that can build up while the class is idle.
+    pub burst_secs: f64,
+    pub policy: OverflowPolicy,
+}
+
+impl Budget {
+    /// Unlimited budget with a one-second burst, dropping on overflow.
+    pub fn new() -> Self {
+        Self { burst_secs: 1.0, ..Self::default() }
+    }
+
+    pub fn with_messages_per_sec(mut self, rate: f64) -> Self {
+        self.messages_per_sec = Some(rate);
+        self
+    }
+
+    pub fn with_bytes_per_sec(mut self, rate: f64) -> Self {
+        self.bytes_per_sec = Some(rate);
+        self
+    }
+
+    pub fn with_burst_secs(mut self, secs: f64) -> Self {
+        self.burst_secs = secs;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// How an utterance passed to [`RateLimiter::submit`] was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Within budget: send it now.
+    Send,
+    /// Held for a later [`RateLimiter::poll`].
+    Queued,
+    /// Held in place of an older pending utterance of the same class.
+    Merged,
+    Dropped,
+}
+
+/// Traffic class an utterance is budgeted under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RateClass {
+    Topic(u16),
+    Priority(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    rate_per_us: f64,
+    capacity: f64,
+    tokens: f64,
+    last_us: Option<i64>,
+}
+
+impl Bucket {
+    fn new(rate_per_sec: f64, burst_secs: f64) -> Self {
+        let capacity = (rate_per_sec * burst_secs).max(1.0);
+        Self { rate_per_us: rate_per_sec / 1e6, capacity, tokens: capacity, last_us: None }
+    }
+
+    fn refill(&mut self, now_us: i64) {
+        if let Some(last) = self.last_us {
+            let elapsed = now_us.saturating_sub(last).max(0) as f64;
+            self.tokens = (self.tokens + elapsed * self.rate_per_us).min(self.capacity);
+        }
+        self.last_us = Some(now_us);
+    }
+
+    /// Whether `cost` may be spent, allowing for rounding in the refills.
+    /// A full bucket admits anything, so a message larger than the burst
+    /// still gets through, on credit.
+    fn allows(&self, cost: f64) -> bool {
+        let tokens = self.tokens + 1e-9;
+        tokens >= cost || tokens >= self.capacity
+    }
+}
+
+struct Class {
+    budget: Budget,
+    messages: Option<Bucket>,
+    bytes: Option<Bucket>,
+    pending: VecDeque<(u8, Vec<u8>)>,
+}
+
+impl Class {
+    fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            messages: budget.messages_per_sec.map(|r| Bucket::new(r, budget.burst_secs)),
+            bytes: budget.bytes_per_sec.map(|r| Bucket::new(r, budget.burst_secs)),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Spend the allowance for `len` bytes if there is enough.
+    fn take(&mut self, len: usize, now_us: i64) -> bool {
+        let buckets = [self.messages.as_mut().map(|b| (b, 1.0)), self.bytes.as_mut().map(|b| (b, len as f64))];
+        let mut buckets: Vec<_> = buckets.into_iter().flatten().collect();
+        buckets.iter_mut().for_each(|(b, _)| b.refill(now_us));
+        if !buckets.iter().all(|(b, cost)| b.allows(*cost)) {
+            return false;
+        }
+        buckets.iter_mut().for_each(|(b, cost)| b.tokens -= *cost);
+        true
+    }
+}
+
+/// Enforces [`Budget`]s on outgoing utterances; see the [module docs](self).
+#[derive(Default)]
+pub struct RateLimiter {
+    decoder: AILLDecoder,
+    classes: BTreeMap<RateClass, Class>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Budget utterances tagged with `topic`.
+    pub fn with_topic(mut self, topic: u16, budget: Budget) -> Self {
+        self.classes.insert(RateClass::Topic(topic), Class::new(budget));
+        self
+    }
+
+    /// Budget untopiced utterances, or those of unbudgeted topics, sent at
+    /// `priority`.
+    pub fn with_priority(mut self, priority: u8, budget: Budget) -> Self {
+        self.classes.insert(RateClass::Priority(priority), Class::new(budget));
+        self
+    }
+
+    /// Class `ast` is budgeted under, if any.
+    pub fn class_of(&self, ast: &AstNode) -> Option<RateClass> {
+        let AstNode::Utterance { meta, .. } = ast else { return None };
+        let topic = match meta.annotations.get("topic") {
+            Some(AnnotationValue::U16(topic)) => Some(RateClass::Topic(*topic)),
+            _ => None,
+        };
+        topic
+            .filter(|class| self.classes.contains_key(class))
+            .or(Some(RateClass::Priority(meta.priority)).filter(|class| self.classes.contains_key(class)))
+    }
+
+    /// Decide what to do with the utterance in `wire`. Utterances that
+    /// are queued or merged are returned later by [`poll`](Self::poll).
+    pub fn submit(&mut self, wire: &[u8], now_us: i64) -> Result<Verdict, AILLError> {
+        let ast = self.decoder.decode_utterance(wire)?;
+        let priority = match &ast {
+            AstNode::Utterance { meta, .. } => meta.priority,
+            _ => 0,
+        };
+        let Some(class) = self.class_of(&ast).and_then(|c| self.classes.get_mut(&c)) else {
+            return Ok(Verdict::Send);
+        };
+        // Earlier utterances of the class go first
+        if class.pending.is_empty() && class.take(wire.len(), now_us) {
+            return Ok(Verdict::Send);
+        }
+        let verdict = match class.budget.policy {
+            OverflowPolicy::Drop => return Ok(Verdict::Dropped),
+            OverflowPolicy::Queue { limit } if class.pending.len() >= limit => return Ok(Verdict::Dropped),
+            OverflowPolicy::Queue { .. } => Verdict::Queued,
+            OverflowPolicy::Merge if class.pending.is_empty() => Verdict::Queued,
+            OverflowPolicy::Merge => {
+                class.pending.clear();
+                Verdict::Merged
+            }
+        };
+        class.pending.push_back((priority, wire.to_vec()));
+        Ok(verdict)
+    }
+
+    /// Held utterances the budgets now allow, highest priority first.
+    pub fn poll(&mut self, now_us: i64) -> Vec<Vec<u8>> {
+        let mut released = Vec::new();
+        for class in self.classes.values_mut() {
+            while let Some((_, wire)) = class.pending.front() {
+                if !class.take(wire.len(), now_us) {
+                    break;
+                }
+                released.extend(class.pending.pop_front());
+            }
+        }
+        released.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+        released.into_iter().map(|(_, wire)| wire).collect()
+    }
+
+    /// Number of utterances held back.
+    pub fn pending(&self) -> usize {
+        self.classes.values().map(|c| c.pending.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::AILLEncoder;
+
+    const TELEMETRY: u16 = 0x0200;
+
+    fn utterance(topic: Option<u16>, priority: u8, value: u8) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, priority, None, None, None);
+        if let Some(topic) = topic {
+            e.topic(topic);
+        }
+        e.assert_().uint8(value);
+        e.end_utterance()
+    }
+
+    #[test]
+    fn telemetry_budget_does_not_hold_back_commands() {
+        let mut limiter = RateLimiter::new()
+            .with_topic(TELEMETRY, Budget::new().with_messages_per_sec(1.0))
+            .with_priority(3, Budget::new().with_messages_per_sec(2.0).with_policy(OverflowPolicy::Queue { limit: 1 }));
+        assert_eq!(limiter.submit(&utterance(Some(TELEMETRY), 3, 1), 0).unwrap(), Verdict::Send);
+        assert_eq!(limiter.submit(&utterance(Some(TELEMETRY), 3, 2), 100_000).unwrap(), Verdict::Dropped);
+        // Commands at another priority have no budget
+        for t in 0..10 {
+            assert_eq!(limiter.submit(&utterance(None, 6, t), t as i64).unwrap(), Verdict::Send);
+        }
+        assert_eq!(limiter.submit(&utterance(Some(TELEMETRY), 3, 3), 1_000_000).unwrap(), Verdict::Send);
+
+        // Priority 3 without a topic: two a second, then a queue of one
+        let untagged: Vec<_> = (0..4).map(|v| utterance(None, 3, v)).collect();
+        let verdicts: Vec<_> = untagged.iter().map(|w| limiter.submit(w, 0).unwrap()).collect();
+        assert_eq!(verdicts, [Verdict::Send, Verdict::Send, Verdict::Queued, Verdict::Dropped]);
+        assert!(limiter.poll(100_000).is_empty());
+        assert_eq!(limiter.poll(500_000), vec![untagged[2].clone()]);
+        assert_eq!(limiter.pending(), 0);
+    }
+
+    #[test]
+    fn merge_keeps_the_latest_and_bytes_are_budgeted() {
+        // About 100 bps: 10 bytes a second, with a burst smaller than one reading
+        let budget = Budget::new().with_bytes_per_sec(10.0).with_burst_secs(2.0).with_policy(OverflowPolicy::Merge);
+        let mut limiter = RateLimiter::new().with_topic(TELEMETRY, budget);
+        let readings: Vec<_> = (0..3).map(|v| utterance(Some(TELEMETRY), 2, v)).collect();
+        let len = readings[0].len() as i64;
+        assert!(len > 20);
+        assert_eq!(limiter.submit(&readings[0], 0).unwrap(), Verdict::Send);
+        assert_eq!(limiter.submit(&readings[1], 0).unwrap(), Verdict::Queued);
+        assert_eq!(limiter.submit(&readings[2], 0).unwrap(), Verdict::Merged);
+        assert_eq!(limiter.pending(), 1);
+        // The first reading went out on credit, so the bucket refills for
+        // as long as a reading takes to send
+        let refilled = len * 100_000;
+        assert!(limiter.poll(refilled - 100_000).is_empty());
+        assert_eq!(limiter.poll(refilled + 1_000), vec![readings[2].clone()]);
+    }
+}
@@ -0,0 +1,1129 @@
+//! Epoch acknowledgement, retransmission and link statistics.
+//!
+//! A [`ReliableSender`] keeps every transmitted epoch until the peer's
+//! [`ReliableReceiver`] acknowledges it, retransmitting on NACK or timeout.
+//! Control frames are sent outside utterances:
+//!
+//! ```text
+//! ACK_EPOCH  <u16 seq> [<u8 n> <n bytes>]  epoch(s) received intact, see EpochAck
+//! NACK_EPOCH <u16 seq> [<u8 reason>]       epoch refused, see RejectReason
+//! PAUSE                                    hold new epochs and retransmissions
+//! RESUME                                   release held epochs
+//! ABORT      <u8 reason>                   drop everything not yet delivered, see abort_reason
+//! ```
+//!
+//! The optional SACK bitmap of an [`EpochAck`] acknowledges later epochs in
+//! the same frame: bit `i`, least significant first within each byte,
+//! stands for epoch `seq + 1 + i`. Setting the top bit of the length byte
+//! makes the ACK cumulative: it then also covers every epoch before `seq`
+//! (within half the sequence space). A receiver built
+//! [`with_selective_ack`](ReliableReceiver::with_selective_ack) replies
+//! with cumulative ACKs only every few epochs, or at once when it sees a
+//! gap, instead of acknowledging each epoch on its own.
+//!
+//! A NACK without a reason byte, as sent by older receivers, means a CRC
+//! failure; see [`EpochNack`]. The sender resends on CRC, waits out BUSY
+//! and gives up on the epoch for any other reason. The same reason codes
+//! can be sent in an utterance with [`AILLEncoder::reject_with`].
+//!
+//! Flow-control frames and refused epochs are reported to the application
+//! as [`FlowEvent`]s.
+//!
+//! The retransmission timeout is fixed unless the sender is built
+//! [`with_adaptive_timeout`](ReliableSender::with_adaptive_timeout); it
+//! then follows the measured round-trip time with an [`RttEstimator`],
+//! since acoustic links take seconds where UDP takes milliseconds.
+//!
+//! Both ends keep [`LinkStats`], which [`encode_link_report`] turns into
+//! DIAG-1 utterances for monitoring. Time is passed in explicitly as
+//! microseconds.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::base::{fc, mnemonic_for, pragma};
+use crate::codebook::diag::DIAG1_REGISTRY_ID;
+use crate::wire::{epoch_flags, IntegrityScheme, EXTENDED_EPOCH};
+use crate::decoder::{decode_epoch, decode_epoch_with};
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+
+/// DIAG-1 AILL_BER entry code.
+pub const AILL_BER: u16 = 0x0041;
+/// DIAG-1 AILL_THROUGHPUT entry code.
+pub const AILL_THROUGHPUT: u16 = 0x0042;
+/// DIAG-1 AILL_RETRANSMITS entry code.
+pub const AILL_RETRANSMITS: u16 = 0x0043;
+
+/// Retransmissions of one epoch before the sender gives up on it.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Number of recent sequence numbers a receiver remembers, so that a
+/// retransmission after a lost ACK is not delivered twice.
+const SEEN_WINDOW: usize = 64;
+
+/// Counters shared by both ends of a link.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct LinkStats {
+    /// Epochs transmitted for the first time.
+    pub epochs_sent: u64,
+    /// Epochs received, including those that failed their CRC.
+    pub epochs_received: u64,
+    /// Bytes received in epochs, headers and CRC included.
+    pub bytes_received: u64,
+    pub crc_failures: u64,
+    pub retransmits: u64,
+    /// Epochs abandoned after exhausting their retries.
+    pub failures: u64,
+    /// Payload bytes acknowledged (sender) or delivered (receiver).
+    pub bytes_delivered: u64,
+    pub first_us: Option<i64>,
+    pub last_us: Option<i64>,
+}
+
+impl LinkStats {
+    fn touch(&mut self, now_us: i64) {
+        self.first_us.get_or_insert(now_us);
+        self.last_us = Some(now_us);
+    }
+
+    /// Fraction of received epochs that failed their CRC.
+    pub fn epoch_error_rate(&self) -> f64 {
+        if self.epochs_received == 0 {
+            return 0.0;
+        }
+        self.crc_failures as f64 / self.epochs_received as f64
+    }
+
+    /// Bit error rate estimate, counting one bit error per failed epoch.
+    /// This is a lower bound: CRC-8 cannot tell how many bits flipped.
+    pub fn bit_error_rate(&self) -> f64 {
+        if self.bytes_received == 0 {
+            return 0.0;
+        }
+        self.crc_failures as f64 / (self.bytes_received * 8) as f64
+    }
+
+    /// Delivered payload bits per second between the first and last event.
+    pub fn throughput_bps(&self) -> f64 {
+        match (self.first_us, self.last_us) {
+            (Some(first), Some(last)) if last > first => {
+                (self.bytes_delivered * 8) as f64 / ((last - first) as f64 / 1e6)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Encode DIAG-1 AILL_BER, AILL_RETRANSMITS and AILL_THROUGHPUT reports,
+/// one ASSERT utterance each. Each binds ESCAPE_L1 to DIAG-1 first, since
+/// the codes would otherwise read as COMM-1 CHANNEL_CLEAR, TX_REQUEST and
+/// TX_GRANT.
+pub fn encode_link_report(stats: &LinkStats) -> Vec<Vec<u8>> {
+    let retransmits = stats.retransmits.min(u16::MAX as u64) as u16;
+    vec![
+        diag_report(AILL_BER, |e| e.float32(stats.bit_error_rate() as f32)),
+        diag_report(AILL_RETRANSMITS, |e| e.uint16(retransmits)),
+        diag_report(AILL_THROUGHPUT, |e| e.float32(stats.throughput_bps() as f32)),
+    ]
+}
+
+fn diag_report(code: u16, value: impl FnOnce(&mut AILLEncoder) -> &mut AILLEncoder) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    value(e.start_utterance().codebook_ref(1, DIAG1_REGISTRY_ID).assert_().l1_ref(code));
+    e.end_utterance()
+}
+
+/// Why a NACK_EPOCH or REJECT refused an epoch or request.
+///
+/// Codes 0x00 to 0x02 are the spec's NACK_EPOCH reasons (`crc_fail`,
+/// `type_error`, `codebook_miss`, the last also for reserved base codes
+/// 0xC0 to 0xEF). The spec assigns no others, so the remaining reasons
+/// of this implementation use codes from 0x80.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum RejectReason {
+    /// The epoch failed its CRC (0x00).
+    Crc,
+    /// A value does not have the type expected (0x01).
+    TypeError,
+    /// The content uses a codebook or code the receiver does not have
+    /// (0x02).
+    CodebookMiss,
+    /// The protocol version or an epoch header flag is not supported
+    /// (0x80).
+    UnsupportedVersion,
+    /// Refused by the receiver's policy (0x81).
+    Policy,
+    /// The receiver is overloaded; try again later (0x82).
+    Busy,
+    Other(u8),
+}
+
+impl RejectReason {
+    pub fn code(self) -> u8 {
+        match self {
+            RejectReason::Crc => 0x00,
+            RejectReason::TypeError => 0x01,
+            RejectReason::CodebookMiss => 0x02,
+            RejectReason::UnsupportedVersion => 0x80,
+            RejectReason::Policy => 0x81,
+            RejectReason::Busy => 0x82,
+            RejectReason::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => RejectReason::Crc,
+            0x01 => RejectReason::TypeError,
+            0x02 => RejectReason::CodebookMiss,
+            0x80 => RejectReason::UnsupportedVersion,
+            0x81 => RejectReason::Policy,
+            0x82 => RejectReason::Busy,
+            other => RejectReason::Other(other),
+        }
+    }
+}
+
+/// Field of a [`reject_with`](AILLEncoder::reject_with) struct holding the
+/// reason code.
+pub const REASON_FIELD: u16 = 0x0000;
+/// Field holding the optional human-readable detail.
+pub const DETAIL_FIELD: u16 = 0x0001;
+
+impl AILLEncoder {
+    /// REJECT with a structured payload:
+    /// `REJECT { 0x0000: UINT8 reason, 0x0001: STRING detail }`.
+    pub fn reject_with(&mut self, reason: RejectReason, detail: Option<&str>) -> &mut Self {
+        self.reject().begin_struct().field(REASON_FIELD).uint8(reason.code());
+        if let Some(detail) = detail {
+            self.field(DETAIL_FIELD).string(detail);
+        }
+        self.end_struct()
+    }
+}
+
+/// Reason and detail of the first REJECT in a decoded utterance written
+/// by [`AILLEncoder::reject_with`].
+pub fn rejection(ast: &AstNode) -> Option<(RejectReason, Option<String>)> {
+    let AstNode::Utterance { body, .. } = ast else {
+        return None;
+    };
+    body.iter().find_map(|node| {
+        let AstNode::Pragmatic { act, expression } = node else { return None };
+        let AstNode::Struct { fields } = expression.as_ref() else { return None };
+        if act != mnemonic_for(pragma::REJECT) {
+            return None;
+        }
+        let reason = match fields.get(&REASON_FIELD)? {
+            AstNode::Literal { value: LiteralValue::Uint8(code), .. } => RejectReason::from_code(*code),
+            _ => return None,
+        };
+        let detail = match fields.get(&DETAIL_FIELD) {
+            Some(AstNode::Literal { value: LiteralValue::String(s), .. }) => Some(s.clone()),
+            _ => None,
+        };
+        Some((reason, detail))
+    })
+}
+
+/// Longest SACK bitmap an ACK_EPOCH carries, covering 256 epochs.
+pub const MAX_SACK_BYTES: usize = 32;
+
+/// Bit of the SACK length byte marking a cumulative ACK.
+const CUMULATIVE: u8 = 0x80;
+
+/// ACK_EPOCH: an epoch received intact, and optionally later ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EpochAck {
+    pub seq: u16,
+    /// Also acknowledges every epoch before `seq`.
+    pub cumulative: bool,
+    /// SACK bitmap; bit `i` acknowledges epoch `seq + 1 + i`. Empty for a
+    /// plain ACK, at most [`MAX_SACK_BYTES`] long.
+    pub sack: Vec<u8>,
+}
+
+impl EpochAck {
+    pub fn new(seq: u16) -> Self {
+        Self { seq, cumulative: false, sack: Vec::new() }
+    }
+
+    /// Acknowledge `seq` and everything before it.
+    pub fn cumulative(mut self) -> Self {
+        self.cumulative = true;
+        self
+    }
+
+    /// Whether this acknowledges epoch `seq`.
+    pub fn covers(&self, seq: u16) -> bool {
+        let behind = self.seq.wrapping_sub(seq);
+        let ahead = seq.wrapping_sub(self.seq).wrapping_sub(1) as usize;
+        behind == 0
+            || (self.cumulative && behind < 0x8000)
+            || self.sack.get(ahead / 8).is_some_and(|b| b & (1 << (ahead % 8)) != 0)
+    }
+
+    /// Acknowledge `seq` together with those of `later` that fall within
+    /// the bitmap's reach after it. Others are left out.
+    pub fn with_selective(seq: u16, later: impl IntoIterator<Item = u16>) -> Self {
+        let mut sack = vec![0u8; MAX_SACK_BYTES];
+        for other in later {
+            let i = other.wrapping_sub(seq).wrapping_sub(1) as usize;
+            if i < MAX_SACK_BYTES * 8 {
+                sack[i / 8] |= 1 << (i % 8);
+            }
+        }
+        let used = sack.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+        sack.truncate(used);
+        Self { seq, cumulative: false, sack }
+    }
+
+    /// Every epoch acknowledged explicitly, `seq` first; a cumulative ACK
+    /// covers more, see [`covers`](Self::covers).
+    pub fn acked(&self) -> Vec<u16> {
+        let later = (0..self.sack.len() * 8)
+            .filter(|i| self.sack[i / 8] & (1 << (i % 8)) != 0)
+            .map(|i| self.seq.wrapping_add(1).wrapping_add(i as u16));
+        std::iter::once(self.seq).chain(later).collect()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut wire = vec![fc::ACK_EPOCH];
+        wire.extend_from_slice(&self.seq.to_be_bytes());
+        if self.cumulative || !self.sack.is_empty() {
+            wire.push(self.sack.len() as u8 | if self.cumulative { CUMULATIVE } else { 0 });
+            wire.extend_from_slice(&self.sack);
+        }
+        wire
+    }
+
+    pub fn decode(wire: &[u8]) -> Result<Self, AILLError> {
+        let [fc::ACK_EPOCH, hi, lo, ref rest @ ..] = *wire else {
+            return Err(AILLError::InvalidStructure("ACK_EPOCH needs a u16 sequence number".into()));
+        };
+        let (cumulative, sack) = match rest {
+            [] => (false, Vec::new()),
+            [n, bitmap @ ..] if bitmap.len() == (n & !CUMULATIVE) as usize && bitmap.len() <= MAX_SACK_BYTES => {
+                (n & CUMULATIVE != 0, bitmap.to_vec())
+            }
+            _ => {
+                return Err(AILLError::InvalidStructure(format!(
+                    "ACK_EPOCH SACK bitmap must be a length byte and up to {} bytes",
+                    MAX_SACK_BYTES
+                )))
+            }
+        };
+        Ok(Self { seq: u16::from_be_bytes([hi, lo]), cumulative, sack })
+    }
+}
+
+/// NACK_EPOCH: an epoch refused, and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochNack {
+    pub seq: u16,
+    pub reason: RejectReason,
+}
+
+impl EpochNack {
+    pub fn new(seq: u16, reason: RejectReason) -> Self {
+        Self { seq, reason }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let [hi, lo] = self.seq.to_be_bytes();
+        vec![fc::NACK_EPOCH, hi, lo, self.reason.code()]
+    }
+
+    /// A NACK without a reason byte is a CRC failure.
+    pub fn decode(wire: &[u8]) -> Result<Self, AILLError> {
+        let (seq, reason) = match *wire {
+            [fc::NACK_EPOCH, hi, lo] => (u16::from_be_bytes([hi, lo]), RejectReason::Crc),
+            [fc::NACK_EPOCH, hi, lo, reason] => (u16::from_be_bytes([hi, lo]), RejectReason::from_code(reason)),
+            _ => {
+                return Err(AILLError::InvalidStructure(format!(
+                    "NACK_EPOCH must be 3 or 4 bytes, got {}",
+                    wire.len()
+                )))
+            }
+        };
+        Ok(Self { seq, reason })
+    }
+}
+
+/// ABORT reason codes.
+pub mod abort_reason {
+    pub const UNSPECIFIED: u8 = 0x00;
+    pub const CHANNEL_DEGRADED: u8 = 0x01;
+    pub const SEMANTIC_ERROR: u8 = 0x02;
+    pub const TIMEOUT: u8 = 0x03;
+}
+
+/// A link control frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Control {
+    Ack(EpochAck),
+    Nack(EpochNack),
+    Pause,
+    Resume,
+    /// ABORT with its reason code, see [`abort_reason`].
+    Abort(u8),
+}
+
+impl Control {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Control::Ack(ack) => ack.encode(),
+            Control::Nack(nack) => nack.encode(),
+            Control::Pause => vec![fc::PAUSE],
+            Control::Resume => vec![fc::RESUME],
+            Control::Abort(reason) => vec![fc::ABORT, *reason],
+        }
+    }
+
+    pub fn decode(wire: &[u8]) -> Result<Self, AILLError> {
+        match *wire {
+            [fc::PAUSE] => Ok(Control::Pause),
+            [fc::RESUME] => Ok(Control::Resume),
+            [fc::ABORT, reason] => Ok(Control::Abort(reason)),
+            [fc::ACK_EPOCH, ..] => Ok(Control::Ack(EpochAck::decode(wire)?)),
+            [fc::NACK_EPOCH, ..] => Ok(Control::Nack(EpochNack::decode(wire)?)),
+            [other, ..] => Err(AILLError::InvalidOpCode(other)),
+            [] => Err(AILLError::InvalidStructure("empty control frame".into())),
+        }
+    }
+}
+
+/// A PAUSE, RESUME or ABORT acted upon, or an epoch the peer refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub enum FlowEvent {
+    Paused,
+    Resumed,
+    Aborted { reason: u8 },
+    /// A NACK other than for a CRC failure.
+    Rejected { seq: u16, reason: RejectReason },
+}
+
+fn epoch_seq(epoch: &[u8]) -> Result<u16, AILLError> {
+    match epoch {
+        [hi, lo, ..] if epoch.len() >= 5 => Ok(u16::from_be_bytes([*hi, *lo])),
+        _ => Err(AILLError::InvalidStructure("Insufficient data for epoch header".into())),
+    }
+}
+
+/// Smoothed round-trip time and retransmission timeout, as in RFC 6298:
+/// `RTO = SRTT + 4 * RTTVAR`, clamped, and doubled on every timeout until
+/// the next sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct RttEstimator {
+    srtt_us: Option<f64>,
+    rttvar_us: f64,
+    rto_us: i64,
+    min_rto_us: i64,
+    max_rto_us: i64,
+}
+
+impl RttEstimator {
+    /// Start at `initial_rto_us`, keeping the timeout within
+    /// `min_rto_us..=max_rto_us`.
+    pub fn new(initial_rto_us: i64, min_rto_us: i64, max_rto_us: i64) -> Self {
+        Self {
+            srtt_us: None,
+            rttvar_us: 0.0,
+            rto_us: initial_rto_us.clamp(min_rto_us, max_rto_us),
+            min_rto_us,
+            max_rto_us,
+        }
+    }
+
+    /// Fold in one round-trip measurement.
+    pub fn sample(&mut self, rtt_us: i64) {
+        let rtt = rtt_us.max(0) as f64;
+        match self.srtt_us {
+            None => {
+                self.srtt_us = Some(rtt);
+                self.rttvar_us = rtt / 2.0;
+            }
+            Some(srtt) => {
+                self.rttvar_us = 0.75 * self.rttvar_us + 0.25 * (srtt - rtt).abs();
+                self.srtt_us = Some(0.875 * srtt + 0.125 * rtt);
+            }
+        }
+        let rto = self.srtt_us.unwrap_or(rtt) + 4.0 * self.rttvar_us;
+        self.rto_us = (rto.round() as i64).clamp(self.min_rto_us, self.max_rto_us);
+    }
+
+    /// Double the timeout after it expired.
+    pub fn back_off(&mut self) {
+        self.rto_us = self.rto_us.saturating_mul(2).min(self.max_rto_us);
+    }
+
+    pub fn rto_us(&self) -> i64 {
+        self.rto_us
+    }
+
+    /// Smoothed round-trip time, once there is a sample.
+    pub fn srtt_us(&self) -> Option<i64> {
+        self.srtt_us.map(|s| s.round() as i64)
+    }
+}
+
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+struct InFlight {
+    epoch: Vec<u8>,
+    sent_us: i64,
+    retries: u32,
+}
+
+/// Sending end: tracks unacknowledged epochs and retransmits them.
+///
+/// While the peer has paused the link, new epochs are held back and
+/// nothing is retransmitted.
+///
+/// The sender serializes with serde, see [`SessionState`](crate::session::SessionState).
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct ReliableSender {
+    timeout_us: i64,
+    max_retries: u32,
+    in_flight: BTreeMap<u16, InFlight>,
+    paused: bool,
+    held: VecDeque<Vec<u8>>,
+    #[cfg_attr(feature = "ast-serde", serde(skip))]
+    events: Vec<FlowEvent>,
+    stats: LinkStats,
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    rtt: Option<RttEstimator>,
+}
+
+impl ReliableSender {
+    /// Retransmit epochs not acknowledged within `timeout_us`.
+    pub fn new(timeout_us: i64) -> Self {
+        Self {
+            timeout_us,
+            max_retries: DEFAULT_MAX_RETRIES,
+            in_flight: BTreeMap::new(),
+            paused: false,
+            held: VecDeque::new(),
+            events: Vec::new(),
+            stats: LinkStats::default(),
+            rtt: None,
+        }
+    }
+
+    /// Derive the timeout from measured round-trip times, starting from the
+    /// one given to [`new`](Self::new) and kept within
+    /// `min_us..=max_us`. ACKs of epochs sent once are measured; those of
+    /// retransmitted epochs are ambiguous and ignored.
+    pub fn with_adaptive_timeout(mut self, min_us: i64, max_us: i64) -> Self {
+        self.rtt = Some(RttEstimator::new(self.timeout_us, min_us, max_us));
+        self
+    }
+
+    /// Current retransmission timeout.
+    pub fn timeout_us(&self) -> i64 {
+        self.rtt.map_or(self.timeout_us, |rtt| rtt.rto_us())
+    }
+
+    /// The round-trip estimator, if the timeout is adaptive.
+    pub fn rtt(&self) -> Option<&RttEstimator> {
+        self.rtt.as_ref()
+    }
+
+    /// Feed a round-trip time measured outside the ACK stream, e.g. from
+    /// an ECHO_REQUEST / ECHO_REPLY exchange. Ignored unless the timeout
+    /// is adaptive.
+    pub fn record_rtt(&mut self, rtt_us: i64) {
+        if let Some(rtt) = self.rtt.as_mut() {
+            rtt.sample(rtt_us);
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    /// Number of epochs awaiting acknowledgement.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Record an epoch (as built by [`EpochBuilder`](crate::EpochBuilder))
+    /// as transmitted. Returns the bytes to put on the link, or `None` if
+    /// the link is paused and the epoch is held until RESUME.
+    pub fn send(&mut self, epoch: Vec<u8>, now_us: i64) -> Result<Option<Vec<u8>>, AILLError> {
+        epoch_seq(&epoch)?;
+        if self.paused {
+            self.held.push_back(epoch);
+            return Ok(None);
+        }
+        Ok(Some(self.transmit(epoch, now_us)))
+    }
+
+    fn transmit(&mut self, epoch: Vec<u8>, now_us: i64) -> Vec<u8> {
+        let seq = u16::from_be_bytes([epoch[0], epoch[1]]);
+        self.stats.epochs_sent += 1;
+        self.stats.touch(now_us);
+        self.in_flight.insert(seq, InFlight { epoch: epoch.clone(), sent_us: now_us, retries: 0 });
+        epoch
+    }
+
+    /// Give up on every held and unacknowledged epoch. Returns the ABORT
+    /// frame, with `reason` (see [`abort_reason`]), to send so the receiver
+    /// discards its partial utterance.
+    pub fn abort(&mut self, reason: u8) -> Vec<u8> {
+        self.held.clear();
+        self.in_flight.clear();
+        Control::Abort(reason).encode()
+    }
+
+    /// Flow-control events seen since the last call.
+    pub fn take_events(&mut self) -> Vec<FlowEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Handle a control frame from the receiver. Returns epochs to
+    /// (re)transmit.
+    pub fn handle_control(&mut self, wire: &[u8], now_us: i64) -> Result<Vec<Vec<u8>>, AILLError> {
+        match Control::decode(wire)? {
+            Control::Pause => {
+                self.paused = true;
+                self.events.push(FlowEvent::Paused);
+                Ok(Vec::new())
+            }
+            Control::Resume => {
+                self.paused = false;
+                self.events.push(FlowEvent::Resumed);
+                let held: Vec<Vec<u8>> = self.held.drain(..).collect();
+                Ok(held.into_iter().map(|epoch| self.transmit(epoch, now_us)).collect())
+            }
+            Control::Abort(reason) => {
+                self.abort(reason);
+                self.events.push(FlowEvent::Aborted { reason });
+                Ok(Vec::new())
+            }
+            Control::Ack(ack) => {
+                let acked: Vec<u16> = self.in_flight.keys().copied().filter(|&seq| ack.covers(seq)).collect();
+                for seq in acked {
+                    if let Some(f) = self.in_flight.remove(&seq) {
+                        if f.retries == 0 {
+                            self.record_rtt(now_us - f.sent_us);
+                        }
+                        self.stats.bytes_delivered += payload_len(&f.epoch) as u64;
+                        self.stats.touch(now_us);
+                    }
+                }
+                Ok(Vec::new())
+            }
+            // Left in flight; the timeout resends it after RESUME
+            Control::Nack(EpochNack { reason: RejectReason::Crc, .. }) if self.paused => Ok(Vec::new()),
+            Control::Nack(EpochNack { seq, reason: RejectReason::Crc }) => {
+                Ok(self.retransmit(seq, now_us).into_iter().collect())
+            }
+            Control::Nack(EpochNack { seq, reason }) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(seq, ?reason, "epoch rejected by receiver");
+                self.events.push(FlowEvent::Rejected { seq, reason });
+                // A busy receiver gets the epoch again after the timeout
+                if reason != RejectReason::Busy && self.in_flight.remove(&seq).is_some() {
+                    self.stats.failures += 1;
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Retransmit epochs whose timeout has expired, abandoning those that
+    /// have used up their retries.
+    pub fn poll(&mut self, now_us: i64) -> Vec<Vec<u8>> {
+        if self.paused {
+            return Vec::new();
+        }
+        let timeout_us = self.timeout_us();
+        let expired: Vec<u16> = self
+            .in_flight
+            .iter()
+            .filter(|(_, f)| now_us - f.sent_us >= timeout_us)
+            .map(|(seq, _)| *seq)
+            .collect();
+        if !expired.is_empty() {
+            if let Some(rtt) = self.rtt.as_mut() {
+                rtt.back_off();
+            }
+        }
+        expired.into_iter().filter_map(|seq| self.retransmit(seq, now_us)).collect()
+    }
+
+    fn retransmit(&mut self, seq: u16, now_us: i64) -> Option<Vec<u8>> {
+        let f = self.in_flight.get_mut(&seq)?;
+        if f.retries >= self.max_retries {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(seq, retries = f.retries, "epoch abandoned after its last retry");
+            self.in_flight.remove(&seq);
+            self.stats.failures += 1;
+            return None;
+        }
+        f.retries += 1;
+        f.sent_us = now_us;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(seq, attempt = f.retries, "retransmitting epoch");
+        self.stats.retransmits += 1;
+        Some(f.epoch.clone())
+    }
+}
+
+/// What a [`ReliableReceiver`] made of one received epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    /// The epoch payload, unless the epoch was corrupt or a duplicate.
+    pub payload: Option<Vec<u8>>,
+    /// ACK/NACK frame to send back; empty if a selective-ACK receiver
+    /// is holding its acknowledgement back.
+    pub reply: Vec<u8>,
+}
+
+/// Cumulative acknowledgement state of a [`ReliableReceiver`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+struct SackState {
+    /// Next epoch expected in order; every earlier one has arrived.
+    next: u16,
+    /// Epochs received past a gap.
+    ahead: HashSet<u16>,
+    /// In-order epochs per ACK.
+    every: u16,
+    /// In-order epochs since the last ACK.
+    unacked: u16,
+}
+
+impl SackState {
+    /// Note epoch `seq` as received. Returns whether to ACK now.
+    fn record(&mut self, seq: u16, duplicate: bool) -> bool {
+        let had_gap = !self.ahead.is_empty();
+        if seq == self.next {
+            self.next = self.next.wrapping_add(1);
+            while self.ahead.remove(&self.next) {
+                self.next = self.next.wrapping_add(1);
+            }
+            self.unacked += 1;
+        } else if (seq.wrapping_sub(self.next) as usize) < MAX_SACK_BYTES * 8 {
+            self.ahead.insert(seq);
+        }
+        // A gap, or a resend after a lost ACK, is reported at once
+        duplicate || had_gap || !self.ahead.is_empty() || self.unacked >= self.every
+    }
+
+    fn ack(&mut self) -> EpochAck {
+        self.unacked = 0;
+        let last = self.next.wrapping_sub(1);
+        let mut ack = EpochAck::with_selective(last, self.ahead.iter().copied());
+        ack.cumulative = true;
+        ack
+    }
+}
+
+/// Receiving end: verifies epochs and produces ACK/NACK replies.
+#[derive(Default)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct ReliableReceiver {
+    seen: HashSet<u16>,
+    seen_order: VecDeque<u16>,
+    stats: LinkStats,
+    /// Extended-header flags this end can handle; `None` accepts all.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    supported_flags: Option<u8>,
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    sack: Option<SackState>,
+    /// Scheme epochs must be closed with. Keys are not saved, so set it
+    /// again on a restored receiver.
+    #[cfg_attr(feature = "ast-serde", serde(skip))]
+    integrity: Option<Box<dyn IntegrityScheme>>,
+    /// [`id`](IntegrityScheme::id) of `integrity`, saved in its place so a
+    /// restored receiver knows it is missing.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    integrity_id: Option<u8>,
+}
+
+impl ReliableReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// NACK epochs carrying extended-header flags outside `flags` with
+    /// [`RejectReason::UnsupportedVersion`]. The TIMESTAMP flag is always
+    /// accepted.
+    pub fn with_supported_flags(mut self, flags: u8) -> Self {
+        self.supported_flags = Some(flags | epoch_flags::TIMESTAMP);
+        self
+    }
+
+    /// Acknowledge with cumulative ACKs carrying a SACK bitmap, starting
+    /// from epoch `first_seq`, and only once every `every` in-order epochs.
+    /// A gap or a duplicate is acknowledged at once; call
+    /// [`ack_now`](Self::ack_now) to flush at the end of a burst.
+    pub fn with_selective_ack(mut self, first_seq: u16, every: u16) -> Self {
+        self.sack = Some(SackState { next: first_seq, every: every.max(1), ..SackState::default() });
+        self
+    }
+
+    /// Verify epochs with `scheme`, e.g. an HMAC key shared with the
+    /// sender, NACKing those that fail it and refusing epochs closed with
+    /// any other scheme. Without it, only keyless schemes are accepted.
+    ///
+    /// The scheme, key included, is not serialized. A receiver restored
+    /// from a state that had one refuses every epoch until it is given the
+    /// scheme again, rather than falling back to accepting unkeyed epochs.
+    pub fn with_integrity(mut self, scheme: impl IntegrityScheme + 'static) -> Self {
+        self.set_integrity(scheme);
+        self
+    }
+
+    /// Like [`with_integrity`](Self::with_integrity), e.g. on the receiver
+    /// of a restored [`SessionState`](crate::session::SessionState).
+    pub fn set_integrity(&mut self, scheme: impl IntegrityScheme + 'static) {
+        self.integrity_id = Some(scheme.id());
+        self.integrity = Some(Box::new(scheme));
+    }
+
+    /// Whether the receiver was restored without the integrity scheme it
+    /// was saved with, and refuses epochs until it gets it back.
+    pub fn needs_integrity(&self) -> bool {
+        self.integrity.is_none() && self.integrity_id.is_some()
+    }
+
+    /// The cumulative ACK for everything received so far, if there is
+    /// anything held back to acknowledge.
+    pub fn ack_now(&mut self) -> Option<Vec<u8>> {
+        let sack = self.sack.as_mut()?;
+        (sack.unacked > 0 || !sack.ahead.is_empty()).then(|| sack.ack().encode())
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    /// Process one received epoch.
+    pub fn receive(&mut self, wire: &[u8], now_us: i64) -> Result<Delivery, AILLError> {
+        if let (None, Some(id)) = (&self.integrity, self.integrity_id) {
+            return Err(AILLError::InvalidStructure(format!(
+                "integrity scheme {} was not restored; set it again with set_integrity",
+                id
+            )));
+        }
+        let (epoch, consumed) = match &self.integrity {
+            Some(scheme) => decode_epoch_with(wire, 0, scheme.as_ref())?,
+            None => decode_epoch(wire, 0)?,
+        };
+        self.stats.epochs_received += 1;
+        self.stats.bytes_received += consumed as u64;
+        self.stats.touch(now_us);
+
+        if !epoch.crc_ok {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(seq = epoch.seq_num, "NACKing corrupt epoch");
+            self.stats.crc_failures += 1;
+            return Ok(Delivery { payload: None, reply: EpochNack::new(epoch.seq_num, RejectReason::Crc).encode() });
+        }
+        if let (Some(flags), Some(supported)) = (epoch.flags, self.supported_flags) {
+            if flags & !supported != 0 {
+                let reply = EpochNack::new(epoch.seq_num, RejectReason::UnsupportedVersion).encode();
+                return Ok(Delivery { payload: None, reply });
+            }
+        }
+        let duplicate = !self.seen.insert(epoch.seq_num);
+        let reply = match self.sack.as_mut() {
+            Some(sack) => match sack.record(epoch.seq_num, duplicate) {
+                true => sack.ack().encode(),
+                false => Vec::new(),
+            },
+            None => EpochAck::new(epoch.seq_num).encode(),
+        };
+        if duplicate {
+            return Ok(Delivery { payload: None, reply });
+        }
+        self.seen_order.push_back(epoch.seq_num);
+        if self.seen_order.len() > SEEN_WINDOW {
+            if let Some(old) = self.seen_order.pop_front() {
+                self.seen.remove(&old);
+            }
+        }
+        self.stats.bytes_delivered += epoch.payload.len() as u64;
+        Ok(Delivery { payload: Some(epoch.payload), reply })
+    }
+}
+
+/// Payload bytes of a framed epoch, from its length word.
+fn payload_len(epoch: &[u8]) -> usize {
+    (u16::from_be_bytes([epoch[2], epoch[3]]) & !EXTENDED_EPOCH) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AstNode, LiteralValue};
+    use crate::{AILLDecoder, EpochBuilder};
+
+    fn epochs(n: usize) -> Vec<Vec<u8>> {
+        let mut b = EpochBuilder::new();
+        for i in 0..n {
+            b.write(&[i as u8; 100]);
+            b.flush();
+        }
+        b.get_epochs()
+    }
+
+    #[test]
+    fn corrupt_epoch_is_nacked_and_resent() {
+        let mut tx = ReliableSender::new(1_000_000);
+        let mut rx = ReliableReceiver::new();
+        let epoch = tx.send(epochs(1).remove(0), 0).unwrap().unwrap();
+
+        let mut corrupt = epoch.clone();
+        corrupt[10] ^= 0x01;
+        let d = rx.receive(&corrupt, 100_000).unwrap();
+        assert_eq!(d.payload, None);
+        let resend = tx.handle_control(&d.reply, 200_000).unwrap();
+        assert_eq!(resend, vec![epoch.clone()]);
+
+        let d = rx.receive(&resend[0], 300_000).unwrap();
+        assert_eq!(d.payload, Some(vec![0; 100]));
+        tx.handle_control(&d.reply, 1_000_000).unwrap();
+        assert_eq!(tx.in_flight(), 0);
+        // A duplicate (e.g. after a lost ACK) is re-acked but not delivered.
+        assert_eq!(rx.receive(&epoch, 1_100_000).unwrap().payload, None);
+
+        let (tx, rx) = (tx.stats(), rx.stats());
+        assert_eq!((tx.epochs_sent, tx.retransmits, tx.bytes_delivered), (1, 1, 100));
+        assert_eq!(tx.throughput_bps(), 800.0);
+        assert_eq!((rx.epochs_received, rx.crc_failures), (3, 1));
+        assert_eq!(rx.epoch_error_rate(), 1.0 / 3.0);
+        assert_eq!(rx.bit_error_rate(), 1.0 / (3.0 * 105.0 * 8.0));
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn keyed_epochs_are_verified() {
+        use crate::wire::integrity::HmacSha256;
+
+        let key = HmacSha256::new(b"link key");
+        let mut b = EpochBuilder::new().with_integrity(key.clone());
+        b.set_timestamp(Some(7));
+        b.write(&[1; 50]);
+        let epoch = b.get_epochs().remove(0);
+        let mut tx = ReliableSender::new(1_000_000);
+        tx.send(epoch.clone(), 0).unwrap();
+
+        assert!(ReliableReceiver::new().receive(&epoch, 10).is_err());
+        let mut forged = ReliableReceiver::new().with_integrity(HmacSha256::new(b"guess"));
+        let d = forged.receive(&epoch, 10).unwrap();
+        assert_eq!((d.payload, forged.stats().crc_failures), (None, 1));
+        assert!(forged.receive(&epochs(1)[0], 20).is_err());
+
+        let mut rx = ReliableReceiver::new().with_integrity(key);
+        let d = rx.receive(&epoch, 10).unwrap();
+        assert_eq!(d.payload, Some(vec![1; 50]));
+        tx.handle_control(&d.reply, 20).unwrap();
+        assert_eq!((tx.in_flight(), tx.stats().bytes_delivered), (0, 50));
+    }
+
+    #[test]
+    fn timeouts_retry_then_give_up() {
+        let mut tx = ReliableSender::new(1_000).with_max_retries(2);
+        for e in epochs(2) {
+            tx.send(e, 0).unwrap().unwrap();
+        }
+        tx.handle_control(&EpochAck::new(1).encode(), 10).unwrap();
+        assert!(tx.poll(999).is_empty());
+        assert_eq!(tx.poll(1_000).len(), 1);
+        assert_eq!(tx.poll(2_000).len(), 1);
+        assert!(tx.poll(3_000).is_empty());
+        assert_eq!(tx.in_flight(), 0);
+        assert_eq!((tx.stats().retransmits, tx.stats().failures), (2, 1));
+    }
+
+    #[test]
+    fn adaptive_timeout_follows_round_trips() {
+        let mut tx = ReliableSender::new(3_000_000).with_adaptive_timeout(1_000, 10_000_000);
+        assert_eq!(tx.timeout_us(), 3_000_000);
+        let mut now = 0;
+        for e in epochs(8) {
+            let seq = u16::from_be_bytes([e[0], e[1]]);
+            tx.send(e, now).unwrap();
+            now += 20_000;
+            tx.handle_control(&EpochAck::new(seq).encode(), now).unwrap();
+        }
+        assert_eq!(tx.rtt().unwrap().srtt_us(), Some(20_000));
+        assert!(tx.timeout_us() < 40_000, "{}", tx.timeout_us());
+
+        // A timeout doubles it; the ACK of the resent epoch is not a sample
+        let rto = tx.timeout_us();
+        tx.send(epochs(1).remove(0), now).unwrap();
+        assert_eq!(tx.poll(now + rto).len(), 1);
+        assert_eq!(tx.timeout_us(), 2 * rto);
+        tx.handle_control(&EpochAck::new(0).encode(), now + rto + 5_000_000).unwrap();
+        assert_eq!(tx.timeout_us(), 2 * rto);
+
+        tx.record_rtt(2_000_000);
+        assert!(tx.timeout_us() > 1_000_000);
+        assert_eq!(ReliableSender::new(500).timeout_us(), 500);
+    }
+
+    #[test]
+    fn pause_holds_epochs_until_resume() {
+        let mut tx = ReliableSender::new(1_000);
+        let mut batch = epochs(3);
+        tx.send(batch.remove(0), 0).unwrap().unwrap();
+        tx.handle_control(&Control::Pause.encode(), 10).unwrap();
+        assert!(tx.is_paused());
+        assert_eq!(tx.send(batch.remove(0), 20).unwrap(), None);
+        assert!(tx.poll(5_000).is_empty());
+
+        let released = tx.handle_control(&Control::Resume.encode(), 6_000).unwrap();
+        assert_eq!(released.len(), 1);
+        assert_eq!(tx.in_flight(), 2);
+        assert_eq!(tx.poll(6_000).len(), 1);
+
+        assert_eq!(tx.abort(abort_reason::SEMANTIC_ERROR), vec![fc::ABORT, abort_reason::SEMANTIC_ERROR]);
+        assert_eq!(tx.in_flight(), 0);
+        tx.handle_control(&Control::Abort(abort_reason::TIMEOUT).encode(), 7_000).unwrap();
+        let aborted = FlowEvent::Aborted { reason: abort_reason::TIMEOUT };
+        assert_eq!(tx.take_events(), vec![FlowEvent::Paused, FlowEvent::Resumed, aborted]);
+        assert_eq!(Control::decode(&[fc::PAUSE]).unwrap(), Control::Pause);
+        assert_eq!(Control::decode(&[fc::ABORT, 0x03]).unwrap(), Control::Abort(abort_reason::TIMEOUT));
+        assert!(Control::decode(&[fc::ABORT]).is_err());
+    }
+
+    #[test]
+    fn nack_reasons_decide_whether_to_resend() {
+        let mut tx = ReliableSender::new(1_000);
+        let mut rx = ReliableReceiver::new().with_supported_flags(epoch_flags::PRIORITY);
+        let mut b = EpochBuilder::new().with_flags(epoch_flags::COMPRESSED);
+        b.write(&[1, 2, 3]);
+        let epoch = tx.send(b.get_epochs().remove(0), 0).unwrap().unwrap();
+        let d = rx.receive(&epoch, 10).unwrap();
+        assert_eq!(d.payload, None);
+        assert_eq!(EpochNack::decode(&d.reply).unwrap(), EpochNack::new(0, RejectReason::UnsupportedVersion));
+        assert!(tx.handle_control(&d.reply, 20).unwrap().is_empty());
+        assert_eq!((tx.in_flight(), tx.stats().failures), (0, 1));
+
+        // BUSY keeps the epoch for the timeout; a legacy 3-byte NACK is a CRC failure
+        for e in epochs(2) {
+            tx.send(e, 100).unwrap();
+        }
+        tx.handle_control(&EpochNack::new(0, RejectReason::Busy).encode(), 200).unwrap();
+        assert_eq!(tx.in_flight(), 2);
+        assert_eq!(tx.handle_control(&[fc::NACK_EPOCH, 0, 1], 300).unwrap().len(), 1);
+        assert_eq!(
+            tx.take_events(),
+            vec![
+                FlowEvent::Rejected { seq: 0, reason: RejectReason::UnsupportedVersion },
+                FlowEvent::Rejected { seq: 0, reason: RejectReason::Busy },
+            ]
+        );
+        assert_eq!(
+            Control::decode(&[fc::NACK_EPOCH, 0, 1, 0x42]).unwrap(),
+            Control::Nack(EpochNack::new(1, RejectReason::Other(0x42)))
+        );
+
+        // A conformant peer's type_error and codebook_miss give up on the epoch
+        assert_eq!(EpochNack::new(1, RejectReason::UnsupportedVersion).encode(), [fc::NACK_EPOCH, 0, 1, 0x80]);
+        for (code, reason) in [(0x01, RejectReason::TypeError), (0x02, RejectReason::CodebookMiss)] {
+            assert_eq!(reason.code(), code);
+            assert_eq!(Control::decode(&[fc::NACK_EPOCH, 0, 1, code]).unwrap(), Control::Nack(EpochNack::new(1, reason)));
+            tx.send(epochs(1).remove(0), 400).unwrap();
+            assert!(tx.handle_control(&[fc::NACK_EPOCH, 0, 0, code], 500).unwrap().is_empty());
+            assert_eq!(tx.take_events(), vec![FlowEvent::Rejected { seq: 0, reason }]);
+        }
+        assert_eq!(tx.stats().failures, 3);
+    }
+
+    #[test]
+    fn sack_bitmap_acknowledges_later_epochs() {
+        let ack = EpochAck::with_selective(0xFFFE, [0xFFFF, 2, 0xFFFE, 300]);
+        assert_eq!(ack.sack, [0b0000_1001]);
+        assert_eq!(ack.encode(), [fc::ACK_EPOCH, 0xFF, 0xFE, 1, 0b0000_1001]);
+        assert_eq!(EpochAck::decode(&ack.encode()).unwrap().acked(), [0xFFFE, 0xFFFF, 2]);
+        assert_eq!(EpochAck::new(5).encode(), [fc::ACK_EPOCH, 0, 5]);
+        assert!(EpochAck::decode(&[fc::ACK_EPOCH, 0, 5, 2, 0xFF]).is_err());
+
+        let mut tx = ReliableSender::new(1_000);
+        for e in epochs(4) {
+            tx.send(e, 0).unwrap();
+        }
+        tx.handle_control(&EpochAck::with_selective(0, [2, 3]).encode(), 10).unwrap();
+        assert_eq!(tx.in_flight(), 1);
+        assert_eq!(tx.poll(1_000), [epochs(4).remove(1)]);
+    }
+
+    #[test]
+    fn selective_ack_receiver_coalesces_replies() {
+        let batch = epochs(7);
+        let mut tx = ReliableSender::new(1_000);
+        let mut rx = ReliableReceiver::new().with_selective_ack(0, 4);
+        let mut replies = Vec::new();
+        for (i, e) in batch.iter().enumerate() {
+            tx.send(e.clone(), 0).unwrap();
+            // Epoch 4 is lost
+            if i != 4 {
+                replies.push(rx.receive(e, 10).unwrap().reply);
+            }
+        }
+        let sent: Vec<&Vec<u8>> = replies.iter().filter(|r| !r.is_empty()).collect();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(*sent[0], [fc::ACK_EPOCH, 0, 3, CUMULATIVE]);
+        assert_eq!(*sent[1], [fc::ACK_EPOCH, 0, 3, CUMULATIVE | 1, 0b010]);
+        assert_eq!(*sent[2], [fc::ACK_EPOCH, 0, 3, CUMULATIVE | 1, 0b110]);
+        for reply in sent {
+            tx.handle_control(reply, 20).unwrap();
+        }
+        assert_eq!(tx.in_flight(), 1);
+
+        let resent = tx.poll(1_000).remove(0);
+        let reply = rx.receive(&resent, 1_010).unwrap().reply;
+        assert_eq!(EpochAck::decode(&reply).unwrap(), EpochAck::new(6).cumulative());
+        tx.handle_control(&reply, 1_020).unwrap();
+        assert_eq!(tx.in_flight(), 0);
+        assert_eq!(rx.ack_now(), None);
+    }
+
+    #[test]
+    fn reject_carries_a_structured_reason() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().reject_with(RejectReason::CodebookMiss, Some("registry 0x40"));
+        let ast = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap();
+        assert_eq!(rejection(&ast), Some((RejectReason::CodebookMiss, Some("registry 0x40".into()))));
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().reject().null();
+        assert_eq!(rejection(&AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()), None);
+    }
+
+    #[test]
+    fn link_report_uses_diag_codes() {
+        let stats = LinkStats { retransmits: 7, ..Default::default() };
+        let reports = encode_link_report(&stats);
+        assert_eq!(reports.len(), 3);
+        let AstNode::Utterance { body, .. } = AILLDecoder::new().decode_utterance(&reports[1]).unwrap() else {
+            panic!()
+        };
+        assert_eq!(body[0], AstNode::CodebookRef { level: 1, registry_id: DIAG1_REGISTRY_ID });
+        let retransmits = AstNode::DomainRef { level: 1, domain_code: AILL_RETRANSMITS, registry: Some(DIAG1_REGISTRY_ID) };
+        assert!(matches!(&body[1], AstNode::Pragmatic { act, expression } if act == "ASSERT" && **expression == retransmits));
+        assert!(matches!(body[2], AstNode::Literal { value: LiteralValue::Uint16(7), .. }));
+    }
+}
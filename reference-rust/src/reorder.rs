@@ -0,0 +1,340 @@
+//! Reordering of epochs that arrive out of sequence.
+//!
+//! Over UDP or a lossy acoustic link epochs can arrive late, twice, or
+//! not at all. An [`EpochReorderBuffer`] holds early epochs until the ones
+//! before them arrive and hands payloads on in sequence order. A missing
+//! epoch holds up delivery until an epoch behind it has waited the
+//! timeout; the buffer then reports the gap and moves on. Sequence numbers
+//! wrap around at `u16::MAX`. Time is passed in explicitly as microseconds.
+//!
+//! Epochs flagged [`PRIORITY`](epoch_flags::PRIORITY) carry urgent
+//! utterances (see [`fastpath`](crate::fastpath)) and are delivered as
+//! soon as they arrive, ahead of anything held back.
+//!
+//! [`reassemble`] does the same in one go for a buffer holding a batch of
+//! epochs, e.g. a blob handed to a browser client.
+
+use std::collections::VecDeque;
+
+#[cfg(feature = "ast-serde")]
+use serde::Serialize;
+
+use crate::ast::DecodedEpoch;
+use crate::decoder::decode_epoch;
+use crate::error::AILLError;
+use crate::wire::epoch_flags;
+
+/// Default number of sequence numbers the buffer holds ahead of the next
+/// expected epoch.
+pub const DEFAULT_REORDER_WINDOW: u16 = 64;
+
+/// Output of an [`EpochReorderBuffer`], in sequence order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReorderEvent {
+    Deliver { seq: u16, payload: Vec<u8> },
+    /// `count` epochs starting at `first` were given up on.
+    Gap { first: u16, count: u16 },
+}
+
+struct Held {
+    /// `None` once delivered out of order on the fast path.
+    payload: Option<Vec<u8>>,
+    arrived_us: i64,
+}
+
+/// Puts received epochs back into sequence order.
+pub struct EpochReorderBuffer {
+    /// Sequence number of the next epoch to deliver; set by the first
+    /// epoch unless given up front.
+    next: Option<u16>,
+    /// Epochs from `next` onward, `None` where one has not arrived.
+    slots: VecDeque<Option<Held>>,
+    window: u16,
+    timeout_us: i64,
+}
+
+impl EpochReorderBuffer {
+    /// Buffer that skips a missing epoch once a later one has waited
+    /// `timeout_us`.
+    pub fn new(timeout_us: i64) -> Self {
+        Self { next: None, slots: VecDeque::new(), window: DEFAULT_REORDER_WINDOW, timeout_us }
+    }
+
+    /// Hold at most `window` sequence numbers (1..=32768). An epoch further
+    /// ahead forces the oldest missing ones to be skipped.
+    pub fn with_window(mut self, window: u16) -> Self {
+        self.window = window.clamp(1, 0x8000);
+        self
+    }
+
+    /// Expect `seq` first instead of taking it from the first epoch.
+    pub fn with_next_seq(mut self, seq: u16) -> Self {
+        self.next = Some(seq);
+        self
+    }
+
+    /// Sequence number of the next epoch to deliver.
+    pub fn next_seq(&self) -> Option<u16> {
+        self.next
+    }
+
+    /// Number of epochs held back waiting for earlier ones.
+    pub fn pending(&self) -> usize {
+        self.slots.iter().flatten().filter(|h| h.payload.is_some()).count()
+    }
+
+    /// Accept a decoded epoch. Epochs that failed their CRC are ignored, as
+    /// are duplicates and epochs behind the next expected one.
+    pub fn push(&mut self, epoch: DecodedEpoch, now_us: i64) -> Vec<ReorderEvent> {
+        if !epoch.crc_ok {
+            return Vec::new();
+        }
+        if epoch.flags.is_some_and(|flags| flags & epoch_flags::PRIORITY != 0) {
+            return self.insert_urgent(epoch.seq_num, epoch.payload, now_us);
+        }
+        self.insert(epoch.seq_num, epoch.payload, now_us)
+    }
+
+    /// Accept the payload of epoch `seq`.
+    pub fn insert(&mut self, seq: u16, payload: Vec<u8>, now_us: i64) -> Vec<ReorderEvent> {
+        let mut out = Vec::new();
+        self.place(seq, Held { payload: Some(payload), arrived_us: now_us }, &mut out);
+        self.release(&mut out);
+        out
+    }
+
+    /// Accept the payload of urgent epoch `seq` and deliver it at once,
+    /// before any epochs it releases. Its sequence number still counts
+    /// towards ordering, so it is not delivered again.
+    pub fn insert_urgent(&mut self, seq: u16, payload: Vec<u8>, now_us: i64) -> Vec<ReorderEvent> {
+        let mut out = Vec::new();
+        let fresh = self.place(seq, Held { payload: None, arrived_us: now_us }, &mut out);
+        if fresh {
+            out.insert(0, ReorderEvent::Deliver { seq, payload });
+        }
+        self.release(&mut out);
+        out
+    }
+
+    /// Store `held` in the slot for `seq`, first skipping ahead if it lies
+    /// past the window. Returns `false` for duplicates and late epochs.
+    fn place(&mut self, seq: u16, held: Held, out: &mut Vec<ReorderEvent>) -> bool {
+        let ahead = seq.wrapping_sub(*self.next.get_or_insert(seq));
+        if ahead >= 0x8000 {
+            return false;
+        }
+        if ahead >= self.window {
+            self.advance((ahead - self.window + 1) as usize, out);
+        }
+        let at = seq.wrapping_sub(self.next.unwrap_or(seq)) as usize;
+        if self.slots.len() <= at {
+            self.slots.resize_with(at + 1, || None);
+        }
+        if self.slots[at].is_some() {
+            return false;
+        }
+        self.slots[at] = Some(held);
+        true
+    }
+
+    /// Give up on missing epochs that held up a later one for the timeout.
+    pub fn poll(&mut self, now_us: i64) -> Vec<ReorderEvent> {
+        let mut out = Vec::new();
+        while let Some(oldest) = self.slots.iter().flatten().map(|h| h.arrived_us).min() {
+            if now_us - oldest < self.timeout_us {
+                break;
+            }
+            let missing = self.slots.iter().take_while(|s| s.is_none()).count();
+            self.advance(missing, &mut out);
+            self.release(&mut out);
+        }
+        out
+    }
+
+    /// Deliver the epochs at the front that are not waiting on a gap.
+    fn release(&mut self, out: &mut Vec<ReorderEvent>) {
+        let ready = self.slots.iter().take_while(|s| s.is_some()).count();
+        self.advance(ready, out);
+    }
+
+    /// Move `n` sequence numbers on, delivering held epochs and reporting
+    /// the rest as gaps.
+    fn advance(&mut self, n: usize, out: &mut Vec<ReorderEvent>) {
+        let Some(mut seq) = self.next else { return };
+        let mut gap: Option<(u16, u16)> = None;
+        for _ in 0..n {
+            match self.slots.pop_front().flatten() {
+                Some(held) => {
+                    if let Some((first, count)) = gap.take() {
+                        out.push(ReorderEvent::Gap { first, count });
+                    }
+                    if let Some(payload) = held.payload {
+                        out.push(ReorderEvent::Deliver { seq, payload });
+                    }
+                }
+                None => gap.get_or_insert((seq, 0)).1 += 1,
+            }
+            seq = seq.wrapping_add(1);
+        }
+        if let Some((first, count)) = gap {
+            out.push(ReorderEvent::Gap { first, count });
+        }
+        self.next = Some(seq);
+    }
+}
+
+/// One epoch found by [`reassemble`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize))]
+pub struct EpochStatus {
+    pub seq: u16,
+    /// Offset of the epoch in the buffer and its size, header and CRC included.
+    pub offset: usize,
+    pub len: usize,
+    pub crc_ok: bool,
+    pub flags: Option<u8>,
+    pub timestamp_us: Option<i64>,
+}
+
+/// Epochs of a buffer put back in sequence, see [`reassemble`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reassembly {
+    /// Every epoch in buffer order.
+    pub epochs: Vec<EpochStatus>,
+    /// Missing sequence numbers as `(first, count)`.
+    pub gaps: Vec<(u16, u16)>,
+    /// Payloads of the intact epochs in sequence order.
+    pub payload: Vec<u8>,
+    /// Why the end of the buffer could not be read as an epoch.
+    pub error: Option<AILLError>,
+}
+
+/// Split `data` into epochs, drop those failing their CRC and join the
+/// payloads of the rest in sequence order, starting from the lowest
+/// sequence number present (modulo wraparound).
+pub fn reassemble(data: &[u8]) -> Reassembly {
+    let mut epochs = Vec::new();
+    let mut intact = Vec::new();
+    let mut error = None;
+    let mut offset = 0;
+    while offset < data.len() {
+        match decode_epoch(data, offset) {
+            Ok((epoch, len)) => {
+                epochs.push(EpochStatus {
+                    seq: epoch.seq_num,
+                    offset,
+                    len,
+                    crc_ok: epoch.crc_ok,
+                    flags: epoch.flags,
+                    timestamp_us: epoch.timestamp_us,
+                });
+                if epoch.crc_ok {
+                    intact.push(epoch);
+                }
+                offset += len;
+            }
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+
+    let mut reassembly = Reassembly { epochs, gaps: Vec::new(), payload: Vec::new(), error };
+    let Some(first) = intact.first().map(|e| e.seq_num) else {
+        return reassembly;
+    };
+    let start = intact.iter().map(|e| e.seq_num.wrapping_sub(first) as i16).min().unwrap_or(0);
+    let mut buf = EpochReorderBuffer::new(0).with_window(0x8000).with_next_seq(first.wrapping_add(start as u16));
+    let mut events = Vec::new();
+    for epoch in intact {
+        events.extend(buf.push(epoch, 0));
+    }
+    events.extend(buf.poll(0));
+    for event in events {
+        match event {
+            ReorderEvent::Deliver { payload, .. } => reassembly.payload.extend(payload),
+            ReorderEvent::Gap { first, count } => reassembly.gaps.push((first, count)),
+        }
+    }
+    reassembly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivered(events: &[ReorderEvent]) -> Vec<u16> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                ReorderEvent::Deliver { seq, .. } => Some(*seq),
+                ReorderEvent::Gap { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reorders_across_wraparound() {
+        let mut buf = EpochReorderBuffer::new(1_000).with_next_seq(0xFFFE);
+        assert!(buf.insert(0xFFFF, vec![1], 0).is_empty());
+        assert!(buf.insert(1, vec![3], 10).is_empty());
+        assert_eq!(buf.pending(), 2);
+        assert_eq!(delivered(&buf.insert(0xFFFE, vec![0], 20)), vec![0xFFFE, 0xFFFF]);
+        let events = buf.insert(0, vec![2], 30);
+        assert_eq!(events[0], ReorderEvent::Deliver { seq: 0, payload: vec![2] });
+        assert_eq!(delivered(&events), vec![0, 1]);
+
+        // Duplicates and late arrivals are dropped
+        assert!(buf.insert(0xFFFF, vec![1], 40).is_empty());
+        assert_eq!((buf.next_seq(), buf.pending()), (Some(2), 0));
+    }
+
+    #[test]
+    fn gaps_are_reported_after_the_timeout() {
+        let mut buf = EpochReorderBuffer::new(1_000);
+        assert_eq!(delivered(&buf.insert(10, vec![], 0)), vec![10]);
+        buf.insert(13, vec![], 100);
+        buf.insert(12, vec![], 500);
+        assert!(buf.poll(1_099).is_empty());
+        assert_eq!(
+            buf.poll(1_100),
+            vec![
+                ReorderEvent::Gap { first: 11, count: 1 },
+                ReorderEvent::Deliver { seq: 12, payload: vec![] },
+                ReorderEvent::Deliver { seq: 13, payload: vec![] },
+            ]
+        );
+
+        // An epoch beyond the window pushes the oldest slots out
+        let mut buf = EpochReorderBuffer::new(1_000).with_window(4).with_next_seq(0);
+        buf.insert(1, vec![], 0);
+        let events = buf.insert(6, vec![], 0);
+        assert_eq!(events[0], ReorderEvent::Gap { first: 0, count: 1 });
+        assert_eq!(events[2], ReorderEvent::Gap { first: 2, count: 1 });
+        assert_eq!(delivered(&events), vec![1]);
+        assert_eq!(buf.next_seq(), Some(3));
+    }
+
+    #[test]
+    fn reassembles_a_batch_of_epochs() {
+        let mut b = crate::EpochBuilder::new().with_seq(0xFFFE);
+        for chunk in [&b"ab"[..], b"cd", b"ef", b"gh", b"ij"] {
+            b.write(chunk);
+            b.flush();
+        }
+        let mut epochs = b.get_epochs();
+        epochs.swap(0, 2);
+        epochs[3][5] ^= 0xFF;
+        let mut data = epochs.concat();
+        data.extend([0, 1]);
+
+        let r = reassemble(&data);
+        assert_eq!(r.epochs.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![0, 0xFFFF, 0xFFFE, 1, 2]);
+        assert!(!r.epochs[3].crc_ok);
+        assert_eq!(r.epochs[4].offset, 4 * 7);
+        assert_eq!(r.gaps, vec![(1, 1)]);
+        assert_eq!(r.payload, b"abcdefij");
+        assert!(r.error.is_some());
+    }
+}
@@ -0,0 +1,414 @@
+//! Relay/mesh forwarding (COMM-1 RELAY_REQUEST, RELAY_ACK, MESH_ROUTE,
+//! HOP_COUNT).
+//!
+//! Routing tags travel at body level, ahead of the pragmatic act:
+//!
+//! ```text
+//! ESCAPE_L1 MESH_ROUTE    LIST[n](BYTES(16) ...)     agents traversed so far
+//! ESCAPE_L1 HOP_COUNT     UINT8 hops
+//! ESCAPE_L1 RELAY_REQUEST STRUCT{0x0000: BYTES(16) dest, 0x0001: BYTES(16) via}
+//! ACKNOWLEDGE ESCAPE_L1 RELAY_ACK STRUCT{0x0000: UINT64 msg_id}
+//! ```
+//!
+//! A [`Router`] inspects each received utterance and decides whether to
+//! deliver it locally, forward it (appending itself to MESH_ROUTE and
+//! incrementing HOP_COUNT), or drop it.
+
+use crate::addressing;
+use crate::agent::AgentId;
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::comm::COMM1_REGISTRY_ID;
+use crate::correlator;
+use crate::dedup::{DedupCache, DedupKey};
+use crate::decoder::AILLDecoder;
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+use crate::templates::encode_ast;
+
+pub const RELAY_REQUEST: u16 = 0x0023;
+pub const RELAY_ACK: u16 = 0x0024;
+pub const MESH_ROUTE: u16 = 0x0025;
+pub const HOP_COUNT: u16 = 0x0026;
+
+/// Default hop limit for forwarded utterances.
+pub const DEFAULT_MAX_HOPS: u8 = 4;
+
+/// Why a [`Router`] dropped an utterance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The MSG_ID was already seen.
+    Duplicate,
+    /// HOP_COUNT reached the router's limit.
+    HopLimit,
+    /// This router already appears in MESH_ROUTE.
+    Loop,
+    /// A RELAY_REQUEST names a different relay.
+    NotRelay,
+}
+
+/// Routing decision for a received utterance.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Route {
+    /// Addressed to this agent.
+    Deliver,
+    /// Addressed elsewhere; retransmit `wire`. `ack` is a RELAY_ACK to send
+    /// back when the utterance explicitly requested this relay.
+    Forward { wire: Vec<u8>, ack: Option<Vec<u8>> },
+    /// Broadcast, or multicast including this agent: handle locally and
+    /// retransmit `wire`.
+    DeliverAndForward { wire: Vec<u8> },
+    Drop(DropReason),
+}
+
+/// Forwarding engine for one agent.
+pub struct Router {
+    self_id: AgentId,
+    max_hops: u8,
+    flood_broadcasts: bool,
+    seen: DedupCache,
+}
+
+impl Router {
+    pub fn new(self_id: impl Into<AgentId>) -> Self {
+        Self {
+            self_id: self_id.into(),
+            max_hops: DEFAULT_MAX_HOPS,
+            flood_broadcasts: true,
+            seen: DedupCache::default(),
+        }
+    }
+
+    /// Drop utterances that have already made `max_hops` hops.
+    pub fn with_max_hops(mut self, max_hops: u8) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Whether utterances without DEST_AGENT are re-broadcast (default on).
+    pub fn with_flood_broadcasts(mut self, flood: bool) -> Self {
+        self.flood_broadcasts = flood;
+        self
+    }
+
+    /// Decide what to do with a received utterance.
+    ///
+    /// Forwarding re-encodes the utterance, so it fails for inputs the AST
+    /// cannot represent exactly (inline annotations, REPORTED).
+    pub fn route(&mut self, wire: &[u8]) -> Result<Route, AILLError> {
+        let mut ast = AILLDecoder::new().decode_utterance(wire)?;
+        addressing::lift_tags(&mut ast);
+
+        if let Some(id) = correlator::msg_id(&ast) {
+            // Forwarded copies differ in MESH_ROUTE, so only MSG_ID identifies them
+            if !self.seen.insert(DedupKey::MsgId(id), 0) {
+                return Ok(Route::Drop(DropReason::Duplicate));
+            }
+        }
+
+        let AstNode::Utterance { meta, .. } = &ast else {
+            unreachable!("decode_utterance always returns an utterance");
+        };
+        let dest = meta.dest_agent;
+        if dest == Some(self.self_id) {
+            return Ok(Route::Deliver);
+        }
+        let multicast = !meta.dest_agents.is_empty();
+        let for_us = multicast && meta.is_addressed_to(&self.self_id);
+        if for_us && meta.dest_agents.len() == 1 {
+            return Ok(Route::Deliver);
+        }
+        if mesh_route(&ast).contains(&self.self_id) {
+            return Ok(Route::Drop(DropReason::Loop));
+        }
+
+        let relay = relay_request(&ast);
+        let hops = hop_count(&ast).unwrap_or(0);
+        if for_us {
+            // Other multicast recipients may still need the utterance.
+            return if hops >= self.max_hops {
+                Ok(Route::Deliver)
+            } else {
+                Ok(Route::DeliverAndForward { wire: self.forwarded(ast, hops)? })
+            };
+        }
+        let directed = multicast || dest.is_some();
+        match directed {
+            false if !self.flood_broadcasts => Ok(Route::Deliver),
+            false if hops >= self.max_hops => Ok(Route::Deliver),
+            false => Ok(Route::DeliverAndForward { wire: self.forwarded(ast, hops)? }),
+            true => {
+                let requested = match relay {
+                    Some((_, via)) if via != self.self_id => return Ok(Route::Drop(DropReason::NotRelay)),
+                    Some(_) => true,
+                    None => false,
+                };
+                if hops >= self.max_hops {
+                    return Ok(Route::Drop(DropReason::HopLimit));
+                }
+                let ack = match correlator::msg_id(&ast) {
+                    Some(id) if requested => Some(relay_ack(id)),
+                    _ => None,
+                };
+                Ok(Route::Forward { wire: self.forwarded(ast, hops)?, ack })
+            }
+        }
+    }
+
+    fn forwarded(&self, mut ast: AstNode, hops: u8) -> Result<Vec<u8>, AILLError> {
+        let AstNode::Utterance { body, .. } = &mut ast else {
+            unreachable!();
+        };
+        let mut path = mesh_route_of(body);
+        path.push(self.self_id);
+        let route = AstNode::List {
+            count: path.len() as u16,
+            elements: path
+                .iter()
+                .map(|id| AstNode::Literal { value_type: "bytes".into(), value: LiteralValue::Bytes(id.as_bytes().to_vec()) })
+                .collect(),
+        };
+        set_tag(body, MESH_ROUTE, route);
+        set_tag(
+            body,
+            HOP_COUNT,
+            AstNode::Literal { value_type: "uint8".into(), value: LiteralValue::Uint8(hops.saturating_add(1)) },
+        );
+        encode_ast(&ast)
+    }
+}
+
+/// Encode a RELAY_ACK confirming that `msg_id` was forwarded.
+pub fn relay_ack(msg_id: u64) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().acknowledge().l1_ref(RELAY_ACK);
+    e.begin_struct().field(0x0000).uint64(msg_id).end_struct();
+    e.end_utterance()
+}
+
+/// The MSG_ID confirmed by a RELAY_ACK utterance.
+pub fn relay_ack_id(ast: &AstNode) -> Option<u64> {
+    let AstNode::Utterance { body, .. } = ast else {
+        return None;
+    };
+    // The acknowledgement is the first act after the leading tags
+    let act = find_tag(body, RELAY_ACK).err()?;
+    let AstNode::Pragmatic { expression, .. } = body.get(act)? else {
+        return None;
+    };
+    if !is_comm1_ref(expression, RELAY_ACK) {
+        return None;
+    }
+    match body.get(act + 1)? {
+        AstNode::Struct { fields } => match fields.get(&0x0000)? {
+            AstNode::Literal { value: LiteralValue::Uint64(id), .. } => Some(*id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Agents listed in MESH_ROUTE, oldest hop first.
+pub fn mesh_route(ast: &AstNode) -> Vec<AgentId> {
+    match ast {
+        AstNode::Utterance { body, .. } => mesh_route_of(body),
+        _ => Vec::new(),
+    }
+}
+
+/// The HOP_COUNT tag, if present.
+pub fn hop_count(ast: &AstNode) -> Option<u8> {
+    match tag(ast, HOP_COUNT)? {
+        AstNode::Literal { value: LiteralValue::Uint8(n), .. } => Some(*n),
+        _ => None,
+    }
+}
+
+/// The `(dest, via)` of a RELAY_REQUEST tag, if present.
+pub fn relay_request(ast: &AstNode) -> Option<(AgentId, AgentId)> {
+    let AstNode::Struct { fields } = tag(ast, RELAY_REQUEST)? else {
+        return None;
+    };
+    Some((uuid(fields.get(&0x0000)?)?, uuid(fields.get(&0x0001)?)?))
+}
+
+impl AILLEncoder {
+    /// Emit MESH_ROUTE as a list of 16-byte agent ids.
+    pub fn mesh_route(&mut self, path: &[AgentId]) -> &mut Self {
+        self.l1_ref(MESH_ROUTE).begin_list(path.len() as u16);
+        for id in path {
+            self.bytes(id.as_bytes());
+        }
+        self.end_list()
+    }
+
+    /// Emit HOP_COUNT.
+    pub fn hop_count(&mut self, hops: u8) -> &mut Self {
+        self.l1_ref(HOP_COUNT).uint8(hops)
+    }
+
+    /// Emit RELAY_REQUEST asking `via` to forward to `dest`.
+    pub fn relay_request(&mut self, dest: impl Into<AgentId>, via: impl Into<AgentId>) -> &mut Self {
+        let (dest, via) = (dest.into(), via.into());
+        self.l1_ref(RELAY_REQUEST);
+        self.begin_struct().field(0x0000).bytes(dest.as_bytes()).field(0x0001).bytes(via.as_bytes()).end_struct()
+    }
+}
+
+/// The value following a COMM-1 `ESCAPE_L1 code` tag among the leading
+/// body items.
+fn tag(ast: &AstNode, code: u16) -> Option<&AstNode> {
+    let AstNode::Utterance { body, .. } = ast else {
+        return None;
+    };
+    find_tag(body, code).ok().map(|i| &body[i + 1])
+}
+
+/// Index of the COMM-1 tag `code` with a value among the leading body
+/// items, or the index of the first item after them.
+fn find_tag(body: &[AstNode], code: u16) -> Result<usize, usize> {
+    let mut i = 0;
+    while let Some(head @ AstNode::DomainRef { level: 1, .. }) = body.get(i) {
+        match body.get(i + 1) {
+            Some(AstNode::DomainRef { .. } | AstNode::Pragmatic { .. }) | None => i += 1,
+            Some(_) if is_comm1_ref(head, code) => return Ok(i),
+            Some(_) => i += 2,
+        }
+    }
+    Err(i)
+}
+
+fn is_comm1_ref(node: &AstNode, code: u16) -> bool {
+    matches!(node, AstNode::DomainRef { level: 1, domain_code, registry }
+        if *domain_code == code && registry.is_none_or(|r| r == COMM1_REGISTRY_ID))
+}
+
+/// Replace the value of tag `code`, inserting the tag at the front of the
+/// body if absent.
+fn set_tag(body: &mut Vec<AstNode>, code: u16, value: AstNode) {
+    match find_tag(body, code) {
+        Ok(i) => body[i + 1] = value,
+        Err(_) => {
+            body.insert(0, value);
+            body.insert(0, AstNode::DomainRef { level: 1, domain_code: code, registry: None });
+        }
+    }
+}
+
+fn mesh_route_of(body: &[AstNode]) -> Vec<AgentId> {
+    match find_tag(body, MESH_ROUTE).ok().map(|i| &body[i + 1]) {
+        Some(AstNode::List { elements, .. }) => elements.iter().filter_map(uuid).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn uuid(node: &AstNode) -> Option<AgentId> {
+    match node {
+        AstNode::Literal { value: LiteralValue::Bytes(b), .. } => AgentId::try_from(b.as_slice()).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: AgentId = AgentId::new([0xA; 16]);
+    const B: AgentId = AgentId::new([0xB; 16]);
+    const C: AgentId = AgentId::new([0xC; 16]);
+
+    fn directed(dest: AgentId, msg_id: u64) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(dest), None).msg_id(msg_id).assert_().float32(1.0);
+        e.end_utterance()
+    }
+
+    fn decode(wire: &[u8]) -> AstNode {
+        AILLDecoder::new().decode_utterance(wire).unwrap()
+    }
+
+    #[test]
+    fn deliver_forward_and_dedup() {
+        let mut b = Router::new(B);
+        assert_eq!(b.route(&directed(B, 1)).unwrap(), Route::Deliver);
+
+        let Route::Forward { wire, ack } = b.route(&directed(C, 2)).unwrap() else { panic!() };
+        assert!(ack.is_none());
+        let fwd = decode(&wire);
+        assert_eq!(mesh_route(&fwd), vec![B]);
+        assert_eq!(hop_count(&fwd), Some(1));
+        assert_eq!(correlator::msg_id(&fwd), Some(2));
+
+        assert_eq!(b.route(&directed(C, 2)).unwrap(), Route::Drop(DropReason::Duplicate));
+    }
+
+    #[test]
+    fn loops_and_hop_limit_are_dropped() {
+        let mut a = Router::new(A);
+        let mut b = Router::new(B).with_max_hops(1);
+
+        let Route::Forward { wire, .. } = a.route(&directed(C, 5)).unwrap() else { panic!() };
+        assert_eq!(b.route(&wire).unwrap(), Route::Drop(DropReason::HopLimit));
+
+        let mut a2 = Router::new(A);
+        assert_eq!(a2.route(&wire).unwrap(), Route::Drop(DropReason::Loop));
+    }
+
+    #[test]
+    fn relay_request_gets_ack() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(C), None).msg_id(9).relay_request(C, B).query().l1_ref(0x0000);
+        let wire = e.end_utterance();
+
+        assert_eq!(Router::new(A).route(&wire).unwrap(), Route::Drop(DropReason::NotRelay));
+        let Route::Forward { ack: Some(ack), wire: fwd } = Router::new(B).route(&wire).unwrap() else { panic!() };
+        assert_eq!(relay_ack_id(&decode(&ack)), Some(9));
+        assert_eq!(relay_request(&decode(&fwd)), Some((C, B)));
+    }
+
+    #[test]
+    fn broadcasts_are_delivered_and_flooded() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().msg_id(3).warn().l1_ref(0x0000);
+        let wire = e.end_utterance();
+        assert!(matches!(Router::new(A).route(&wire).unwrap(), Route::DeliverAndForward { .. }));
+        assert_eq!(Router::new(A).with_flood_broadcasts(false).route(&wire).unwrap(), Route::Deliver);
+    }
+
+    #[test]
+    fn multicast_delivers_to_members_and_forwards() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().dest_agents(&[A, C]).assert_().float32(1.0);
+        let wire = e.end_utterance();
+        assert!(matches!(Router::new(A).route(&wire).unwrap(), Route::DeliverAndForward { .. }));
+        assert!(matches!(Router::new(B).route(&wire).unwrap(), Route::Forward { .. }));
+    }
+
+    #[test]
+    fn colliding_codes_outside_the_tags_are_payload() {
+        // MANIP-1 JOINT_TRAJECTORY shares MESH_ROUTE's code
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(B), None).command().l1_ref(MESH_ROUTE);
+        e.begin_list(2).float32(1.0).float32(2.0).end_list();
+        let wire = e.end_utterance();
+        let Route::Forward { wire: fwd, .. } = Router::new(A).route(&wire).unwrap() else { panic!() };
+        let fwd = decode(&fwd);
+        assert_eq!(mesh_route(&fwd), vec![A]);
+        assert_eq!(hop_count(&fwd), Some(1));
+        let AstNode::Utterance { body, .. } = &fwd else { panic!() };
+        let AstNode::Utterance { body: sent, .. } = decode(&wire) else { panic!() };
+        assert_eq!(body[4..], sent[..]);
+
+        // and SAFETY-1 PROTECTIVE_STOP HOP_COUNT's
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(C), None).assert_().l1_ref(HOP_COUNT).uint8(9);
+        let wire = e.end_utterance();
+        assert_eq!(hop_count(&decode(&wire)), None);
+        assert!(matches!(Router::new(A).route(&wire).unwrap(), Route::Forward { .. }));
+
+        // Tags on L1 bound to another registry are not routing tags either
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(1.0, 3, None, Some(C), None).hop_count(9).assert_().float32(1.0);
+        let diag = AILLDecoder::with_config(crate::DecoderConfig::new().bind_escape(1, crate::codebook::DIAG1.registry_id));
+        assert_eq!(hop_count(&diag.decode_utterance(&e.end_utterance()).unwrap()), None);
+    }
+}
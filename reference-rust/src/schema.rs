@@ -0,0 +1,409 @@
+//! Named struct schemas and their compact SCHEMA_REF encoding.
+//!
+//! A [`Schema`] lists the fields of a struct type with their ids, names and
+//! types. Encoded against a schema, a struct is written as
+//! `SCHEMA_REF <uint16 schema_id>` followed by a struct of positional
+//! values in schema order, which saves the three-byte FIELD_ID of every
+//! field. The decoder reads SCHEMA_REF payloads unchecked;
+//! [`SchemaRegistry::check`] checks a decoded utterance against the
+//! registered schemas. The registry can also expand the values back to
+//! field ids or pair them with field names.
+//!
+//! Schemas are built in Rust or parsed from a small text format:
+//!
+//! ```text
+//! # Planar pose
+//! schema 0x0101 Pose
+//!     0x0000 x        float32
+//!     0x0001 y        float32
+//!     0x0002 heading  float16
+//! ```
+//!
+//! Field types are literal type names (`int8` ... `timestamp`, `null`,
+//! `measurement`), `struct`, `list`, `map`, or `any` to accept every
+//! expression.
+//!
+//! Schema [`MEASUREMENT_SCHEMA_ID`] is well known: a value and its standard
+//! deviation as two positional FLOAT32s, which every decoder reads as a
+//! [`LiteralValue::Measurement`].
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+pub use crate::ast::{measurement, MEASUREMENT_SCHEMA_ID};
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+use crate::templates::{literal_type_name, LITERAL_TYPES};
+
+/// One field of a [`Schema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct SchemaField {
+    pub id: u16,
+    pub name: String,
+    pub value_type: String,
+}
+
+/// A named struct type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct Schema {
+    pub id: u16,
+    pub name: String,
+    /// Fields in wire order.
+    pub fields: Vec<SchemaField>,
+}
+
+impl Schema {
+    pub fn new(id: u16, name: &str) -> Self {
+        Self { id, name: name.to_string(), fields: Vec::new() }
+    }
+
+    /// Append a field; it is encoded after the fields added before it.
+    pub fn with_field(mut self, id: u16, name: &str, value_type: &str) -> Self {
+        self.fields.push(SchemaField { id, name: name.to_string(), value_type: value_type.to_string() });
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&SchemaField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Check the payload of a SCHEMA_REF to this schema: a struct with one
+    /// positional value of the right type per field.
+    pub fn check(&self, payload: &AstNode) -> Result<(), AILLError> {
+        let AstNode::Struct { fields } = payload else {
+            return Err(AILLError::InvalidStructure(format!("schema {} payload is not a struct", self.name)));
+        };
+        if fields.len() != self.fields.len() || fields.keys().zip(0u16..).any(|(&k, i)| k != i) {
+            return Err(AILLError::InvalidStructure(format!(
+                "schema {} expects {} positional fields, got {}",
+                self.name,
+                self.fields.len(),
+                fields.len()
+            )));
+        }
+        for (field, value) in self.fields.iter().zip(fields.values()) {
+            if !matches_type(&field.value_type, value) {
+                return Err(AILLError::InvalidStructure(format!(
+                    "schema {} field {} expects {}, got {}",
+                    self.name,
+                    field.name,
+                    field.value_type,
+                    type_name(value)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn a struct keyed by field id into its compact SCHEMA_REF form.
+    pub fn compile(&self, node: &AstNode) -> Result<AstNode, AILLError> {
+        let AstNode::Struct { fields } = node else {
+            return Err(AILLError::EncoderError(format!("schema {} can only compile a struct", self.name)));
+        };
+        if let Some(id) = fields.keys().find(|id| !self.fields.iter().any(|f| f.id == **id)) {
+            return Err(AILLError::EncoderError(format!("field 0x{:04X} is not in schema {}", id, self.name)));
+        }
+        let mut positional = BTreeMap::new();
+        for (i, field) in (0u16..).zip(&self.fields) {
+            let value = fields.get(&field.id).ok_or_else(|| {
+                AILLError::EncoderError(format!("schema {} field {} is missing", self.name, field.name))
+            })?;
+            positional.insert(i, value.clone());
+        }
+        let payload = AstNode::Struct { fields: positional };
+        self.check(&payload).map_err(|e| AILLError::EncoderError(e.to_string()))?;
+        Ok(AstNode::SchemaRef { schema_id: self.id, expression: Box::new(payload) })
+    }
+
+    /// Write `values`, one per field in schema order, as a SCHEMA_REF
+    /// expression.
+    pub fn encode_values(&self, e: &mut AILLEncoder, values: &[LiteralValue]) -> Result<(), AILLError> {
+        if values.len() != self.fields.len() {
+            return Err(AILLError::EncoderError(format!(
+                "schema {} has {} fields, got {} values",
+                self.name,
+                self.fields.len(),
+                values.len()
+            )));
+        }
+        for (field, value) in self.fields.iter().zip(values) {
+            if field.value_type != "any" && field.value_type != literal_type_name(value) {
+                return Err(AILLError::EncoderError(format!(
+                    "schema {} field {} expects {}, got {}",
+                    self.name,
+                    field.name,
+                    field.value_type,
+                    literal_type_name(value)
+                )));
+            }
+        }
+        e.schema_ref(self.id).begin_struct();
+        for value in values {
+            e.literal(value);
+        }
+        e.end_struct();
+        Ok(())
+    }
+}
+
+fn type_name(node: &AstNode) -> &str {
+    match node {
+        AstNode::Literal { value_type, .. } => value_type,
+        AstNode::Struct { .. } | AstNode::SchemaRef { .. } => "struct",
+        AstNode::List { .. } => "list",
+        AstNode::Map { .. } => "map",
+        _ => "expression",
+    }
+}
+
+fn matches_type(value_type: &str, node: &AstNode) -> bool {
+    value_type == "any" || value_type == type_name(node)
+}
+
+/// Schemas known to an encoder or decoder, by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<u16, Schema>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the schemas in `text`, see the [module docs](self).
+    pub fn parse(text: &str) -> Result<Self, AILLError> {
+        let mut reg = Self::new();
+        let mut current: Option<Schema> = None;
+        for (n, line) in text.lines().enumerate() {
+            let err = |msg: &str| AILLError::InvalidStructure(format!("schema line {}: {}", n + 1, msg));
+            let line = line.split('#').next().unwrap_or("").trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["schema", id, name] => {
+                    if let Some(done) = current.take() {
+                        reg.register(done)?;
+                    }
+                    current = Some(Schema::new(parse_u16(id).ok_or_else(|| err("bad schema id"))?, name));
+                }
+                [id, name, value_type] => {
+                    let schema = current.take().ok_or_else(|| err("field outside a schema"))?;
+                    let id = parse_u16(id).ok_or_else(|| err("bad field id"))?;
+                    current = Some(schema.with_field(id, name, value_type));
+                }
+                _ => return Err(err("expected `schema <id> <name>` or `<id> <name> <type>`")),
+            }
+        }
+        if let Some(done) = current {
+            reg.register(done)?;
+        }
+        Ok(reg)
+    }
+
+    /// Add `schema`. Fails if its id is taken, a field id or name repeats,
+    /// or a field type is unknown.
+    pub fn register(&mut self, schema: Schema) -> Result<(), AILLError> {
+        if self.schemas.contains_key(&schema.id) {
+            return Err(AILLError::InvalidStructure(format!("schema 0x{:04X} already registered", schema.id)));
+        }
+        for (i, field) in schema.fields.iter().enumerate() {
+            let value_type = field.value_type.as_str();
+            if !LITERAL_TYPES.contains(&value_type) && !["struct", "list", "map", "any"].contains(&value_type) {
+                return Err(AILLError::InvalidStructure(format!(
+                    "schema {} field {} has unknown type '{}'",
+                    schema.name, field.name, field.value_type
+                )));
+            }
+            if schema.fields[..i].iter().any(|f| f.id == field.id || f.name == field.name) {
+                return Err(AILLError::InvalidStructure(format!(
+                    "schema {} repeats field {}",
+                    schema.name, field.name
+                )));
+            }
+        }
+        self.schemas.insert(schema.id, schema);
+        Ok(())
+    }
+
+    pub fn get(&self, id: u16) -> Option<&Schema> {
+        self.schemas.get(&id)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Schema> {
+        self.schemas.values().find(|s| s.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.schemas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Schema> {
+        self.schemas.values()
+    }
+
+    /// Check every SCHEMA_REF in `node` against its schema. References to
+    /// unregistered schemas are not checked.
+    pub fn check(&self, node: &AstNode) -> Result<(), AILLError> {
+        match node {
+            AstNode::SchemaRef { schema_id, expression } => {
+                if let Some(schema) = self.get(*schema_id) {
+                    schema.check(expression)?;
+                }
+                self.check(expression)
+            }
+            AstNode::Utterance { body, .. } => body.iter().try_for_each(|n| self.check(n)),
+            AstNode::Struct { fields } => fields.values().try_for_each(|n| self.check(n)),
+            AstNode::List { elements, .. } => elements.iter().try_for_each(|n| self.check(n)),
+            AstNode::Map { pairs, .. } => pairs.iter().try_for_each(|(k, v)| {
+                self.check(k)?;
+                self.check(v)
+            }),
+            AstNode::Pragmatic { expression, .. }
+            | AstNode::Modal { expression, .. }
+            | AstNode::Temporal { expression, .. } => self.check(expression),
+            _ => Ok(()),
+        }
+    }
+
+    /// The fields of a SCHEMA_REF node with their names, in schema order.
+    /// `None` for other nodes, unregistered schemas and payloads that do
+    /// not match.
+    pub fn named_fields<'s, 'n>(&'s self, node: &'n AstNode) -> Option<Vec<(&'s str, &'n AstNode)>> {
+        let AstNode::SchemaRef { schema_id, expression } = node else {
+            return None;
+        };
+        let schema = self.get(*schema_id)?;
+        schema.check(expression).ok()?;
+        let AstNode::Struct { fields } = expression.as_ref() else {
+            return None;
+        };
+        Some(schema.fields.iter().map(|f| f.name.as_str()).zip(fields.values()).collect())
+    }
+
+    /// Copy of `node` with every SCHEMA_REF to a registered schema replaced
+    /// by a plain struct keyed by field id.
+    pub fn expand(&self, node: &AstNode) -> AstNode {
+        let expand_box = |e: &AstNode| Box::new(self.expand(e));
+        match node {
+            AstNode::SchemaRef { schema_id, expression } => {
+                let inner = self.expand(expression);
+                match (self.get(*schema_id), &inner) {
+                    (Some(schema), AstNode::Struct { fields }) if schema.check(&inner).is_ok() => AstNode::Struct {
+                        fields: schema.fields.iter().map(|f| f.id).zip(fields.values().cloned()).collect(),
+                    },
+                    _ => AstNode::SchemaRef { schema_id: *schema_id, expression: Box::new(inner) },
+                }
+            }
+            AstNode::Utterance { meta, body } => {
+                AstNode::Utterance { meta: meta.clone(), body: body.iter().map(|n| self.expand(n)).collect() }
+            }
+            AstNode::Struct { fields } => {
+                AstNode::Struct { fields: fields.iter().map(|(k, v)| (*k, self.expand(v))).collect() }
+            }
+            AstNode::List { count, elements } => {
+                AstNode::List { count: *count, elements: elements.iter().map(|n| self.expand(n)).collect() }
+            }
+            AstNode::Map { count, pairs } => AstNode::Map {
+                count: *count,
+                pairs: pairs.iter().map(|(k, v)| (self.expand(k), self.expand(v))).collect(),
+            },
+            AstNode::Pragmatic { act, expression } => {
+                AstNode::Pragmatic { act: act.clone(), expression: expand_box(expression) }
+            }
+            AstNode::Modal { modality, expression, extra } => {
+                AstNode::Modal { modality: modality.clone(), expression: expand_box(expression), extra: *extra }
+            }
+            AstNode::Temporal { modifier, expression } => {
+                AstNode::Temporal { modifier: modifier.clone(), expression: expand_box(expression) }
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+fn parse_u16(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::encode_ast;
+    use crate::AILLDecoder;
+
+    const POSE: &str = "
+        # Planar pose
+        schema 0x0101 Pose
+            0x0000 x float32
+            0x0001 y float32
+            0x0010 frame string
+    ";
+
+    fn body(ast: &AstNode) -> &AstNode {
+        let AstNode::Utterance { body, .. } = ast else { panic!() };
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        expression
+    }
+
+    #[test]
+    fn compact_encoding_decodes_with_names() {
+        let schemas = SchemaRegistry::parse(POSE).unwrap();
+        let pose = schemas.get_by_name("Pose").unwrap();
+        let values = [LiteralValue::Float32(1.0), LiteralValue::Float32(2.0), LiteralValue::String("map".into())];
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_();
+        pose.encode_values(&mut e, &values).unwrap();
+        let wire = e.end_utterance();
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().begin_struct();
+        e.field(0x0000).float32(1.0).field(0x0001).float32(2.0).field(0x0010).string("map").end_struct();
+        let verbose = e.end_utterance();
+        assert_eq!(wire.len() + 3 * 3 - 3, verbose.len());
+
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        schemas.check(&ast).unwrap();
+        let names: Vec<&str> = schemas.named_fields(body(&ast)).unwrap().iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, ["x", "y", "frame"]);
+        assert_eq!(encode_ast(&ast).unwrap(), wire);
+
+        let plain = AILLDecoder::new().decode_utterance(&verbose).unwrap();
+        assert_eq!(schemas.expand(&ast), plain);
+        assert_eq!(pose.compile(body(&plain)).unwrap(), *body(&ast));
+    }
+
+    #[test]
+    fn mismatches_are_rejected() {
+        let schemas = SchemaRegistry::parse(POSE).unwrap();
+        let pose = schemas.get(0x0101).unwrap();
+        assert!(pose.encode_values(&mut AILLEncoder::new(), &[LiteralValue::Float32(1.0)]).is_err());
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().schema_ref(0x0101).begin_struct().float32(1.0).int8(2).string("s").end_struct();
+        let wire = e.end_utterance();
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        assert!(schemas.check(&ast).is_err());
+        // Unknown to this registry, so not checked
+        assert!(SchemaRegistry::new().check(&ast).is_ok());
+
+        assert!(SchemaRegistry::parse("schema 1 A\n 0 x float32\n 0 y float32").is_err());
+        assert!(SchemaRegistry::parse("schema 1 A\n 0 x complex").is_err());
+        assert!(SchemaRegistry::parse(" 0 x float32").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! Session state that survives a restart.
+//!
+//! [`SessionState`] gathers what an agent would otherwise have to
+//! renegotiate with its peer: escape-level codebook bindings and extension
+//! opcodes, the next outgoing epoch sequence number, both ends of the
+//! reliability layer including unacknowledged epochs, the header
+//! compression contexts and what the peer's capability report allows.
+//! With the `ast-serde` feature it serializes with serde, so it can be
+//! written to disk and restored after a reboot.
+//!
+//! Integrity keys are never written out. A receiver saved with a scheme
+//! refuses epochs after restoring until
+//! [`set_integrity`](ReliableReceiver::set_integrity) gives it the key
+//! again, and the builder from
+//! [`epoch_builder`](SessionState::epoch_builder) needs
+//! [`with_integrity`](EpochBuilder::with_integrity) as before the reboot.
+//!
+//! Link timestamps are stored as given. If the state has to survive a
+//! reboot, feed the sender and receiver a wall clock such as
+//! [`now_micros`](crate::timestamp::now_micros) rather than a monotonic one.
+
+#[cfg(feature = "ast-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::codebook::{compatibility, fallback_label, CompatReport, Degradation};
+use crate::comm::AgentCapabilities;
+use crate::compress::{HeaderCompressor, HeaderDecompressor};
+use crate::decoder::{AILLDecoder, DecodeObserver, DecoderConfig};
+use crate::encoder::EpochBuilder;
+use crate::error::AILLError;
+use crate::reliability::{ReliableReceiver, ReliableSender};
+use crate::templates::encode_ast;
+
+/// Resumable state of one AILL session.
+#[cfg_attr(feature = "ast-serde", derive(Serialize, Deserialize))]
+pub struct SessionState {
+    /// Codebook bindings and extensions in effect.
+    pub decoder: DecoderConfig,
+    /// Sequence number of the next outgoing epoch.
+    pub next_seq: u16,
+    pub sender: ReliableSender,
+    pub receiver: ReliableReceiver,
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub compressor: HeaderCompressor,
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub decompressor: HeaderDecompressor,
+    /// What the peer can decode, once its capabilities are known.
+    /// Outgoing utterances are checked against it.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub peer: Option<CompatReport>,
+    /// How [`encode_ast`](Self::encode_ast) handles references the peer
+    /// does not support.
+    #[cfg_attr(feature = "ast-serde", serde(default))]
+    pub degradation: Degradation,
+}
+
+impl SessionState {
+    /// A fresh session whose sender retransmits after `timeout_us`.
+    pub fn new(timeout_us: i64) -> Self {
+        Self {
+            decoder: DecoderConfig::default(),
+            next_seq: 0,
+            sender: ReliableSender::new(timeout_us),
+            receiver: ReliableReceiver::new(),
+            compressor: HeaderCompressor::new(),
+            decompressor: HeaderDecompressor::new(),
+            peer: None,
+            degradation: Degradation::Refuse,
+        }
+    }
+
+    /// Adopt the peer's capabilities, e.g. from its
+    /// [`CapabilityReport`](crate::comm::CapabilityReport).
+    pub fn negotiate(&mut self, ours: &AgentCapabilities, theirs: &AgentCapabilities) -> &CompatReport {
+        self.peer.insert(compatibility(ours, theirs))
+    }
+
+    /// Check an outgoing utterance against [`peer`](Self::peer): it must
+    /// fit the peer's max payload, and every domain reference, resolved
+    /// through the session's escape bindings, must be to a registry the
+    /// peer supports.
+    pub fn check_outgoing(&self, wire: &[u8]) -> Result<(), AILLError> {
+        let Some(peer) = &self.peer else {
+            return Ok(());
+        };
+        if wire.len() > peer.max_payload as usize {
+            return Err(AILLError::EncoderError(format!(
+                "utterance of {} bytes exceeds the peer's {} byte limit",
+                wire.len(),
+                peer.max_payload
+            )));
+        }
+        let mut refs = DomainRefs(Vec::new());
+        self.decoder().decode_utterance_with_observer(wire, &mut refs)?;
+        refs.0.into_iter().try_for_each(|(registry, code)| peer.check(registry, code))
+    }
+
+    /// Encode `ast`, substituting references the peer does not support
+    /// per [`degradation`](Self::degradation) and refusing what it still
+    /// could not decode.
+    pub fn encode_ast(&self, ast: &AstNode) -> Result<Vec<u8>, AILLError> {
+        let wire = if self.degradation == Degradation::Refuse {
+            encode_ast(ast)?
+        } else {
+            let mut ast = ast.clone();
+            self.degrade(&mut ast);
+            encode_ast(&ast)?
+        };
+        self.check_outgoing(&wire)?;
+        Ok(wire)
+    }
+
+    /// Replace the domain references in `ast` that the peer does not
+    /// support per [`degradation`](Self::degradation), returning how many
+    /// were replaced. References resolve through the session's escape
+    /// bindings and the CODEBOOK_REFs before them; once those are replaced,
+    /// CODEBOOK_REFs to registries the peer does not support are dropped.
+    pub fn degrade(&self, ast: &mut AstNode) -> usize {
+        let Some(peer) = &self.peer else {
+            return 0;
+        };
+        let mut bindings = self.decoder.escape_bindings;
+        degrade(ast, &mut bindings, peer, self.degradation)
+    }
+
+    /// A decoder with the session's bindings.
+    pub fn decoder(&self) -> AILLDecoder {
+        AILLDecoder::with_config(self.decoder.clone())
+    }
+
+    /// An epoch builder continuing the session's numbering. Store its
+    /// [`next_seq`](EpochBuilder::next_seq) back before saving.
+    pub fn epoch_builder(&self) -> EpochBuilder {
+        EpochBuilder::new().with_seq(self.next_seq)
+    }
+
+    #[cfg(feature = "ast-serde")]
+    pub fn to_json(&self) -> Result<String, AILLError> {
+        serde_json::to_string(self).map_err(|e| AILLError::EncoderError(format!("session state: {}", e)))
+    }
+
+    #[cfg(feature = "ast-serde")]
+    pub fn from_json(json: &str) -> Result<Self, AILLError> {
+        serde_json::from_str(json).map_err(|e| AILLError::InvalidStructure(format!("session state: {}", e)))
+    }
+}
+
+fn degrade(node: &mut AstNode, bindings: &mut [Option<u8>; 3], peer: &CompatReport, mode: Degradation) -> usize {
+    match node {
+        AstNode::DomainRef { level, domain_code, .. } => {
+            let registry = (*level as usize).checked_sub(1).and_then(|i| bindings.get(i).copied().flatten());
+            let Some(registry) = registry.filter(|&id| !peer.allows(id)) else {
+                return 0;
+            };
+            let label = AstNode::Literal {
+                value_type: "string".into(),
+                value: LiteralValue::String(fallback_label(registry, *domain_code)),
+            };
+            *node = match mode {
+                Degradation::Refuse => return 0,
+                Degradation::Label => label,
+                Degradation::Clarify => AstNode::Pragmatic { act: "CLARIFY".into(), expression: Box::new(label) },
+            };
+            1
+        }
+        AstNode::CodebookRef { level, registry_id } => {
+            if let Some(slot) = (*level as usize).checked_sub(1).and_then(|i| bindings.get_mut(i)) {
+                *slot = Some(*registry_id);
+            }
+            0
+        }
+        AstNode::Utterance { body, .. } => degrade_all(body, bindings, peer, mode).0,
+        AstNode::Struct { fields } => fields.values_mut().map(|n| degrade(n, bindings, peer, mode)).sum(),
+        AstNode::List { count, elements } => {
+            let (replaced, dropped) = degrade_all(elements, bindings, peer, mode);
+            *count = count.saturating_sub(dropped as u16);
+            replaced
+        }
+        AstNode::Map { pairs, .. } => {
+            pairs.iter_mut().map(|(k, v)| degrade(k, bindings, peer, mode) + degrade(v, bindings, peer, mode)).sum()
+        }
+        AstNode::Pragmatic { expression, .. }
+        | AstNode::Modal { expression, .. }
+        | AstNode::Temporal { expression, .. }
+        | AstNode::SchemaRef { expression, .. } => degrade(expression, bindings, peer, mode),
+        _ => 0,
+    }
+}
+
+/// [`degrade`] each of `nodes` in order, then drop the CODEBOOK_REFs to
+/// registries the peer does not support, whose references are all replaced
+/// unless refusing. Returns how many references were replaced and how many
+/// CODEBOOK_REFs dropped.
+fn degrade_all(
+    nodes: &mut Vec<AstNode>,
+    bindings: &mut [Option<u8>; 3],
+    peer: &CompatReport,
+    mode: Degradation,
+) -> (usize, usize) {
+    let replaced = nodes.iter_mut().map(|n| degrade(n, bindings, peer, mode)).sum();
+    if mode == Degradation::Refuse {
+        return (replaced, 0);
+    }
+    let before = nodes.len();
+    nodes.retain(|n| !matches!(n, AstNode::CodebookRef { registry_id, .. } if !peer.allows(*registry_id)));
+    (replaced, before - nodes.len())
+}
+
+/// Registry and code of every domain reference decoded.
+struct DomainRefs(Vec<(Option<u8>, u16)>);
+
+impl DecodeObserver for DomainRefs {
+    fn on_node_end(&mut self, _start: usize, _end: usize, node: &AstNode) {
+        if let AstNode::DomainRef { domain_code, registry, .. } = node {
+            self.0.push((*registry, *domain_code));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "ast-serde"))]
+mod tests {
+    use super::*;
+    use crate::codebook::NAV1;
+
+    #[test]
+    fn restored_session_resumes_where_it_left_off() {
+        let mut state = SessionState::new(1_000);
+        state.decoder = DecoderConfig::new().bind_escape(2, NAV1.registry_id);
+        state.decoder.extensions.register(0xC0, "VENDOR", 1).unwrap();
+        let mut epochs = state.epoch_builder();
+        epochs.write(&[1, 2, 3]);
+        let sent = epochs.get_epochs().remove(0);
+        state.next_seq = epochs.next_seq();
+        state.sender.send(sent.clone(), 0).unwrap();
+        state.receiver.receive(&sent, 10).unwrap();
+
+        let mut restored = SessionState::from_json(&state.to_json().unwrap()).unwrap();
+        assert_eq!(restored.decoder, state.decoder);
+        assert_eq!(restored.decoder().config().escape_binding(2), Some(NAV1.registry_id));
+        assert_eq!(restored.epoch_builder().next_seq(), 1);
+        assert_eq!(restored.receiver.stats(), state.receiver.stats());
+        // The unacknowledged epoch is still retransmitted, and the receiver
+        // still recognises it as a duplicate.
+        assert_eq!(restored.sender.poll(1_000), vec![sent.clone()]);
+        assert_eq!(restored.receiver.receive(&sent, 1_100).unwrap().payload, None);
+        assert!(SessionState::from_json("{}").is_err());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn restored_receiver_waits_for_its_key() {
+        use crate::wire::integrity::HmacSha256;
+
+        let key = HmacSha256::new(b"link key");
+        let mut state = SessionState::new(1_000);
+        state.receiver.set_integrity(key.clone());
+        let mut epochs = state.epoch_builder().with_integrity(key.clone());
+        epochs.write(&[1, 2, 3]);
+        let sent = epochs.get_epochs().remove(0);
+
+        let mut restored = SessionState::from_json(&state.to_json().unwrap()).unwrap();
+        assert!(restored.receiver.needs_integrity());
+        assert!(restored.receiver.receive(&sent, 10).is_err());
+        assert_eq!(restored.receiver.stats().epochs_received, 0);
+        restored.receiver.set_integrity(key);
+        assert!(!restored.receiver.needs_integrity());
+        assert_eq!(restored.receiver.receive(&sent, 20).unwrap().payload, Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn outgoing_utterances_respect_the_peer() {
+        use crate::codebook::SAFETY1;
+        use crate::AILLEncoder;
+
+        let mut state = SessionState::new(1_000);
+        let ours = AgentCapabilities::new(0, 0).with_builtin_domains();
+        let theirs = AgentCapabilities::new(0, 0).with_domain(NAV1.registry_id, 1).with_max_payload(64);
+        let utterance = |registry: u8| {
+            let mut e = AILLEncoder::new();
+            e.start_utterance().codebook_ref(2, registry).assert_().l2_ref(0x0001).uint8(1);
+            e.end_utterance()
+        };
+        assert!(state.check_outgoing(&utterance(SAFETY1.registry_id)).is_ok());
+
+        assert_eq!(state.negotiate(&ours, &theirs).unsupported.len(), ours.domains_supported.len() - 1);
+        assert!(state.check_outgoing(&utterance(NAV1.registry_id)).is_ok());
+        assert!(state.check_outgoing(&utterance(SAFETY1.registry_id)).is_err());
+        // Bindings carried over from earlier utterances count too
+        state.decoder = DecoderConfig::new().bind_escape(1, SAFETY1.registry_id);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().l1_ref(0x0001).uint8(1);
+        let wire = e.end_utterance();
+        let ast = state.decoder().decode_utterance(&wire).unwrap();
+        assert!(state.encode_ast(&ast).is_err());
+        state.decoder = DecoderConfig::new();
+        assert_eq!(state.encode_ast(&ast), Ok(wire));
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().string(&"x".repeat(64));
+        assert!(state.check_outgoing(&e.end_utterance()).is_err());
+        let restored = SessionState::from_json(&state.to_json().unwrap()).unwrap();
+        assert_eq!(restored.peer, state.peer);
+    }
+
+    #[test]
+    fn unsupported_references_degrade_instead_of_failing() {
+        use crate::codebook::{DIAG1, SAFETY1};
+        use crate::AILLEncoder;
+
+        let mut state = SessionState::new(1_000);
+        let ours = AgentCapabilities::new(0, 0).with_builtin_domains();
+        state.negotiate(&ours, &AgentCapabilities::new(0, 0).with_domain(NAV1.registry_id, 1));
+        let mut e = AILLEncoder::new();
+        e.start_utterance().codebook_ref(1, DIAG1.registry_id).assert_().l1_ref(0x0000).float16(80.0);
+        e.codebook_ref(1, NAV1.registry_id).query().l1_ref(0x0000).null();
+        e.codebook_ref(1, SAFETY1.registry_id).request().l1_ref(0x0001).null();
+        let ast = state.decoder().decode_utterance(&e.end_utterance()).unwrap();
+        assert!(state.encode_ast(&ast).is_err());
+
+        let decoded = |state: &SessionState| {
+            let wire = state.encode_ast(&ast).unwrap();
+            let AstNode::Utterance { body, .. } = state.decoder().decode_utterance(&wire).unwrap() else { unreachable!() };
+            body
+        };
+        let label = |text: String| AstNode::Literal { value_type: "string".into(), value: LiteralValue::String(text) };
+        let nav_only = |body: &[AstNode]| {
+            body.iter().all(|n| !matches!(n, AstNode::CodebookRef { registry_id, .. } if *registry_id != NAV1.registry_id))
+        };
+        state.degradation = Degradation::Label;
+        let body = decoded(&state);
+        assert!(nav_only(&body), "{:?}", body);
+        assert_eq!(body[0], AstNode::Pragmatic { act: "ASSERT".into(), expression: Box::new(label("DIAG-1.BATTERY_LEVEL".into())) });
+        assert_eq!(body[2], AstNode::CodebookRef { level: 1, registry_id: NAV1.registry_id });
+        assert_eq!(body[3], AstNode::Pragmatic { act: "QUERY".into(), expression: Box::new(AstNode::DomainRef { level: 1, domain_code: 0, registry: Some(NAV1.registry_id) }) });
+        assert_eq!(state.degrade(&mut ast.clone()), 2);
+
+        state.degradation = Degradation::Clarify;
+        let body = decoded(&state);
+        assert!(nav_only(&body), "{:?}", body);
+        let clarify = AstNode::Pragmatic { act: "CLARIFY".into(), expression: Box::new(label(fallback_label(SAFETY1.registry_id, 0x0001))) };
+        assert_eq!(body[5], AstNode::Pragmatic { act: "REQUEST".into(), expression: Box::new(clarify) });
+    }
+}
@@ -0,0 +1,355 @@
+//! Compact encodings for occupancy grids and point clouds.
+//!
+//! NAV-1 OCCUPANCY_GRID and PERCEPT-1 LIDAR_SCAN are too large to send as
+//! one literal per cell or coordinate. Here they pack their bulk data into
+//! a single BYTES value:
+//!
+//! ```text
+//! ESCAPE_L1 OCCUPANCY_GRID STRUCT {
+//!     0x0000: UINT16 width, 0x0001: UINT16 height,
+//!     0x0002: FLOAT32 resolution_m, 0x0003: LIST[2](FLOAT32 x, FLOAT32 y) origin,
+//!     0x0004: BYTES cells    row-major runs of (varint count, UINT8 value)
+//! }
+//! ESCAPE_L1 LIDAR_SCAN STRUCT {
+//!     0x0000: FLOAT32 scale_m, 0x0001: LIST[3](FLOAT32) origin,
+//!     0x0002: BYTES points   per point 3 x INT16 deltas from the previous
+//!                            point in units of scale_m
+//! }
+//! ```
+//!
+//! Cell values are occupancy percentages 0-100, or [`UNKNOWN_CELL`]. Point
+//! coordinates are quantized to `scale_m` relative to the origin before
+//! delta coding, so errors do not accumulate; a delta that does not fit
+//! INT16 is written as [`POINT_ESCAPE`] followed by the absolute 3 x INT32
+//! point. [`PointCloud::from_ast`] also reads LIDAR_SCAN's plain
+//! `LIST<ARRAY<FLOAT32,3>>` form.
+
+use std::collections::BTreeMap;
+
+use crate::ast::{AstNode, LiteralValue};
+use crate::encoder::AILLEncoder;
+use crate::error::AILLError;
+use crate::wire::{decode_varint, encode_varint};
+
+/// NAV-1 OCCUPANCY_GRID entry code.
+pub const OCCUPANCY_GRID: u16 = 0x0069;
+/// PERCEPT-1 LIDAR_SCAN entry code.
+pub const LIDAR_SCAN: u16 = 0x0070;
+
+/// Cell value for space that has not been observed.
+pub const UNKNOWN_CELL: u8 = 255;
+/// INT16 x-delta announcing an absolute point.
+pub const POINT_ESCAPE: i16 = i16::MIN;
+
+/// Row-major 2D occupancy grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccupancyGrid {
+    pub width: u16,
+    pub height: u16,
+    /// Cell edge length in metres.
+    pub resolution_m: f32,
+    /// Position of cell (0, 0)'s corner in the map frame.
+    pub origin: (f32, f32),
+    /// `width * height` values, rows first.
+    pub cells: Vec<u8>,
+}
+
+impl OccupancyGrid {
+    /// Grid of unknown cells.
+    pub fn new(width: u16, height: u16, resolution_m: f32) -> Self {
+        Self {
+            width,
+            height,
+            resolution_m,
+            origin: (0.0, 0.0),
+            cells: vec![UNKNOWN_CELL; width as usize * height as usize],
+        }
+    }
+
+    pub fn with_origin(mut self, x: f32, y: f32) -> Self {
+        self.origin = (x, y);
+        self
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<u8> {
+        (x < self.width && y < self.height).then(|| self.cells[y as usize * self.width as usize + x as usize])
+    }
+
+    pub fn set(&mut self, x: u16, y: u16, value: u8) {
+        if x < self.width && y < self.height {
+            self.cells[y as usize * self.width as usize + x as usize] = value;
+        }
+    }
+
+    /// Run-length encoding of the cells.
+    pub fn rle(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut cells = self.cells.iter().peekable();
+        while let Some(&value) = cells.next() {
+            let mut run = 1u32;
+            while cells.next_if_eq(&&value).is_some() {
+                run += 1;
+            }
+            out.extend(encode_varint(run));
+            out.push(value);
+        }
+        out
+    }
+
+    /// Emit `ESCAPE_L1 OCCUPANCY_GRID STRUCT{...}`. Fails if the cells
+    /// do not match the dimensions or compress to more than a BYTES value
+    /// holds.
+    pub fn encode(&self, e: &mut AILLEncoder) -> Result<(), AILLError> {
+        if self.cells.len() != self.width as usize * self.height as usize {
+            return Err(AILLError::EncoderError(format!(
+                "occupancy grid has {} cells for {}x{}",
+                self.cells.len(),
+                self.width,
+                self.height
+            )));
+        }
+        let rle = bytes_value(self.rle(), "occupancy grid")?;
+        e.l1_ref(OCCUPANCY_GRID).begin_struct();
+        e.field(0x0000).uint16(self.width).field(0x0001).uint16(self.height);
+        e.field(0x0002).float32(self.resolution_m);
+        e.field(0x0003).begin_list(2).float32(self.origin.0).float32(self.origin.1).end_list();
+        e.field(0x0004).bytes(&rle).end_struct();
+        Ok(())
+    }
+
+    /// Read the value of an OCCUPANCY_GRID reference.
+    pub fn from_ast(node: &AstNode) -> Result<Self, AILLError> {
+        let fields = struct_fields(node, "occupancy grid")?;
+        let width = match fields.get(&0x0000) {
+            Some(AstNode::Literal { value: LiteralValue::Uint16(v), .. }) => *v,
+            _ => return Err(invalid("occupancy grid width")),
+        };
+        let height = match fields.get(&0x0001) {
+            Some(AstNode::Literal { value: LiteralValue::Uint16(v), .. }) => *v,
+            _ => return Err(invalid("occupancy grid height")),
+        };
+        let resolution_m = fields.get(&0x0002).and_then(float).ok_or_else(|| invalid("occupancy grid resolution"))?;
+        let origin = match fields.get(&0x0003).map(floats) {
+            Some(Some(xy)) if xy.len() == 2 => (xy[0], xy[1]),
+            _ => return Err(invalid("occupancy grid origin")),
+        };
+        let rle = match fields.get(&0x0004) {
+            Some(AstNode::Literal { value: LiteralValue::Bytes(b), .. }) => b,
+            _ => return Err(invalid("occupancy grid cells")),
+        };
+        let size = width as usize * height as usize;
+        let mut cells = Vec::with_capacity(size);
+        let mut pos = 0;
+        while pos < rle.len() {
+            let (run, used) = decode_varint(rle, pos)?;
+            let value = *rle.get(pos + used).ok_or(AILLError::UnexpectedEof { offset: pos + used, needed: 1 })?;
+            if cells.len() + run as usize > size {
+                return Err(invalid("occupancy grid run past the last cell"));
+            }
+            cells.resize(cells.len() + run as usize, value);
+            pos += used + 1;
+        }
+        if cells.len() != size {
+            return Err(AILLError::InvalidStructure(format!("occupancy grid has {} of {} cells", cells.len(), size)));
+        }
+        Ok(Self { width, height, resolution_m, origin, cells })
+    }
+}
+
+/// A set of 3D points in metres.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCloud {
+    pub points: Vec<[f32; 3]>,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<[f32; 3]>) -> Self {
+        Self { points }
+    }
+
+    /// Emit `ESCAPE_L1 LIDAR_SCAN STRUCT{...}`, quantizing to `scale_m`.
+    pub fn encode(&self, e: &mut AILLEncoder, scale_m: f32) -> Result<(), AILLError> {
+        if scale_m.is_nan() || scale_m <= 0.0 {
+            return Err(AILLError::EncoderError(format!("point cloud scale must be positive, got {}", scale_m)));
+        }
+        let origin = self.points.first().copied().unwrap_or_default();
+        let mut packed = Vec::with_capacity(self.points.len() * 6);
+        let mut prev = [0i32; 3];
+        for p in &self.points {
+            let q: [i32; 3] = std::array::from_fn(|i| ((p[i] - origin[i]) / scale_m).round() as i32);
+            let delta: Vec<i16> = (0..3).filter_map(|i| i16::try_from(q[i] - prev[i]).ok()).collect();
+            if delta.len() == 3 && delta[0] != POINT_ESCAPE {
+                delta.iter().for_each(|d| packed.extend(d.to_be_bytes()));
+            } else {
+                packed.extend(POINT_ESCAPE.to_be_bytes());
+                q.iter().for_each(|v| packed.extend(v.to_be_bytes()));
+            }
+            prev = q;
+        }
+        let packed = bytes_value(packed, "point cloud")?;
+        e.l1_ref(LIDAR_SCAN).begin_struct().field(0x0000).float32(scale_m);
+        e.field(0x0001).begin_list(3).float32(origin[0]).float32(origin[1]).float32(origin[2]).end_list();
+        e.field(0x0002).bytes(&packed).end_struct();
+        Ok(())
+    }
+
+    /// Emit LIDAR_SCAN as its codebook type, `LIST<ARRAY<FLOAT32,3>>`.
+    pub fn encode_plain(&self, e: &mut AILLEncoder) -> Result<(), AILLError> {
+        let count = u16::try_from(self.points.len())
+            .map_err(|_| AILLError::EncoderError(format!("{} points do not fit one list", self.points.len())))?;
+        e.l1_ref(LIDAR_SCAN).begin_list(count);
+        for p in &self.points {
+            e.begin_list(3).float32(p[0]).float32(p[1]).float32(p[2]).end_list();
+        }
+        e.end_list();
+        Ok(())
+    }
+
+    /// Read the value of a LIDAR_SCAN reference in either form.
+    pub fn from_ast(node: &AstNode) -> Result<Self, AILLError> {
+        if let AstNode::List { elements, .. } = node {
+            let points = elements.iter().map(|el| match floats(el) {
+                Some(xyz) if xyz.len() == 3 => Ok([xyz[0], xyz[1], xyz[2]]),
+                _ => Err(invalid("point cloud point")),
+            });
+            return points.collect::<Result<_, _>>().map(Self::new);
+        }
+        let fields = struct_fields(node, "point cloud")?;
+        let scale = fields.get(&0x0000).and_then(float).ok_or_else(|| invalid("point cloud scale"))?;
+        let origin = match fields.get(&0x0001).map(floats) {
+            Some(Some(xyz)) if xyz.len() == 3 => xyz,
+            _ => return Err(invalid("point cloud origin")),
+        };
+        let packed = match fields.get(&0x0002) {
+            Some(AstNode::Literal { value: LiteralValue::Bytes(b), .. }) => b,
+            _ => return Err(invalid("point cloud points")),
+        };
+        let mut points = Vec::new();
+        let mut q = [0i32; 3];
+        let mut pos = 0;
+        let read = |pos: usize, n: usize| {
+            packed.get(pos..pos + n).ok_or(AILLError::UnexpectedEof { offset: pos, needed: n })
+        };
+        while pos < packed.len() {
+            let dx = i16::from_be_bytes(read(pos, 2)?.try_into().unwrap());
+            if dx == POINT_ESCAPE {
+                let abs = read(pos + 2, 12)?;
+                for (i, v) in q.iter_mut().enumerate() {
+                    *v = i32::from_be_bytes(abs[i * 4..i * 4 + 4].try_into().unwrap());
+                }
+                pos += 14;
+            } else {
+                let rest = read(pos + 2, 4)?;
+                q[0] += dx as i32;
+                q[1] += i16::from_be_bytes([rest[0], rest[1]]) as i32;
+                q[2] += i16::from_be_bytes([rest[2], rest[3]]) as i32;
+                pos += 6;
+            }
+            points.push(std::array::from_fn(|i| origin[i] + q[i] as f32 * scale));
+        }
+        Ok(Self::new(points))
+    }
+}
+
+fn bytes_value(bytes: Vec<u8>, what: &str) -> Result<Vec<u8>, AILLError> {
+    match bytes.len() > u16::MAX as usize {
+        true => Err(AILLError::EncoderError(format!("{} packs to {} bytes, more than one BYTES value", what, bytes.len()))),
+        false => Ok(bytes),
+    }
+}
+
+fn struct_fields<'a>(node: &'a AstNode, what: &str) -> Result<&'a BTreeMap<u16, AstNode>, AILLError> {
+    match node {
+        AstNode::Struct { fields } => Ok(fields),
+        _ => Err(AILLError::InvalidStructure(format!("{} is not a struct", what))),
+    }
+}
+
+fn invalid(what: &str) -> AILLError {
+    AILLError::InvalidStructure(format!("missing or malformed {}", what))
+}
+
+fn float(node: &AstNode) -> Option<f32> {
+    match node {
+        AstNode::Literal { value: LiteralValue::Float16(v) | LiteralValue::Float32(v), .. } => Some(*v),
+        AstNode::Literal { value: LiteralValue::Float64(v), .. } => Some(*v as f32),
+        _ => None,
+    }
+}
+
+fn floats(node: &AstNode) -> Option<Vec<f32>> {
+    match node {
+        AstNode::List { elements, .. } => elements.iter().map(float).collect(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::AILLDecoder;
+
+    /// Encode `write`'s output as an utterance and return the value after
+    /// the domain reference, with the utterance size.
+    fn round_trip(write: impl FnOnce(&mut AILLEncoder)) -> (AstNode, usize) {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_();
+        write(&mut e);
+        let wire = e.end_utterance();
+        let AstNode::Utterance { body, .. } = AILLDecoder::new().decode_utterance(&wire).unwrap() else { panic!() };
+        (body[1].clone(), wire.len())
+    }
+
+    #[test]
+    fn occupancy_grid_round_trips_and_beats_float_lists() {
+        // A 100 x 100 room: unknown outside, free inside, walls around it
+        let mut grid = OccupancyGrid::new(100, 100, 0.05).with_origin(-2.5, -2.5);
+        for y in 10..90 {
+            for x in 10..90 {
+                let wall = x == 10 || x == 89 || y == 10 || y == 89;
+                grid.set(x, y, if wall { 100 } else { 0 });
+            }
+        }
+        let (node, compact) = round_trip(|e| grid.encode(e).unwrap());
+        assert_eq!(OccupancyGrid::from_ast(&node).unwrap(), grid);
+
+        // One FLOAT32 literal per cell
+        let (_, naive) = round_trip(|e| {
+            e.l1_ref(OCCUPANCY_GRID).begin_list(10_000);
+            grid.cells.iter().for_each(|&c| {
+                e.float32(c as f32);
+            });
+            e.end_list();
+        });
+        assert!(compact * 50 < naive, "{} vs {} bytes", compact, naive);
+
+        let mut bad = grid.clone();
+        bad.cells.pop();
+        assert!(bad.encode(&mut AILLEncoder::new()).is_err());
+    }
+
+    #[test]
+    fn point_cloud_quantizes_without_drift_and_beats_float_lists() {
+        // A 2000-point scan of a 5 m circle, with one far return
+        let mut points: Vec<[f32; 3]> = (0..2000)
+            .map(|i| {
+                let a = i as f32 * std::f32::consts::TAU / 2000.0;
+                [5.0 * a.cos(), 5.0 * a.sin(), 0.3]
+            })
+            .collect();
+        points[1000] = [80.0, 0.0, 0.3];
+        let cloud = PointCloud::new(points);
+
+        let (node, compact) = round_trip(|e| cloud.encode(e, 0.001).unwrap());
+        let decoded = PointCloud::from_ast(&node).unwrap();
+        assert_eq!(decoded.points.len(), 2000);
+        for (a, b) in cloud.points.iter().zip(&decoded.points) {
+            assert!((0..3).all(|i| (a[i] - b[i]).abs() <= 0.0006), "{:?} vs {:?}", a, b);
+        }
+
+        let (plain, naive) = round_trip(|e| cloud.encode_plain(e).unwrap());
+        assert_eq!(PointCloud::from_ast(&plain).unwrap(), cloud);
+        assert!(compact * 3 < naive, "{} vs {} bytes", compact, naive);
+        assert!(cloud.encode(&mut AILLEncoder::new(), 0.0).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Incremental decoding of utterances that arrive in pieces.
+//!
+//! A [`StreamDecoder`] is fed epoch payloads as they are received and
+//! hands back each utterance once its END_UTTERANCE has arrived. It also
+//! acts on the frame-control opcodes:
+//!
+//! - ABORT and its reason code, in place of an expression or between
+//!   utterances, discard the partial utterance;
+//! - PAUSE and RESUME between utterances are passed on to the application.
+//!
+//! Both surface as [`FlowEvent`]s.
+//!
+//! Urgent utterances sent on the [`fastpath`](crate::fastpath) arrive in
+//! epochs of their own and go through [`push_urgent`](StreamDecoder::push_urgent),
+//! bypassing the partial utterance.
+
+use crate::ast::AstNode;
+use crate::codebook::base::fc;
+use crate::decoder::{AILLDecoder, Prefix};
+use crate::error::AILLError;
+use crate::reliability::FlowEvent;
+#[cfg(doc)]
+use crate::reliability::abort_reason;
+
+/// Reassembles utterances from a byte stream.
+#[derive(Default)]
+pub struct StreamDecoder {
+    decoder: AILLDecoder,
+    buf: Vec<u8>,
+    events: Vec<FlowEvent>,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode with `decoder`'s configuration.
+    pub fn with_decoder(decoder: AILLDecoder) -> Self {
+        Self { decoder, ..Self::default() }
+    }
+
+    /// Bytes of the utterance currently being received.
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Append received bytes and return the utterances they complete.
+    ///
+    /// On a decode error the buffered bytes are dropped, so the stream can
+    /// resynchronise on the next utterance.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<AstNode>, AILLError> {
+        self.buf.extend_from_slice(bytes);
+        let mut done = Vec::new();
+        while let Some(&code) = self.buf.first() {
+            let (event, len) = match (code, self.buf.get(1)) {
+                (fc::PAUSE, _) => (FlowEvent::Paused, 1),
+                (fc::RESUME, _) => (FlowEvent::Resumed, 1),
+                (fc::ABORT, Some(&reason)) => (FlowEvent::Aborted { reason }, 2),
+                // The reason code is still to come
+                (fc::ABORT, None) => break,
+                _ => match self.decoder.decode_prefix(&self.buf) {
+                    Ok(Prefix::Complete(node, used)) => {
+                        self.buf.drain(..used);
+                        done.push(node);
+                        continue;
+                    }
+                    Ok(Prefix::Aborted(reason, used)) => (FlowEvent::Aborted { reason }, used),
+                    Ok(Prefix::Truncated) => break,
+                    Err(e) => {
+                        self.buf.clear();
+                        return Err(e);
+                    }
+                },
+            };
+            self.buf.drain(..len);
+            self.events.push(event);
+        }
+        Ok(done)
+    }
+
+    /// Decode the payload of an urgent epoch, which holds whole utterances
+    /// of its own. The partial utterance being received is left alone.
+    pub fn push_urgent(&self, bytes: &[u8]) -> Result<Vec<AstNode>, AILLError> {
+        let mut done = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            match self.decoder.decode_prefix(rest)? {
+                Prefix::Complete(node, used) => {
+                    done.push(node);
+                    rest = &rest[used..];
+                }
+                Prefix::Aborted(_, used) => rest = &rest[used..],
+                Prefix::Truncated => {
+                    return Err(AILLError::InvalidStructure("urgent epoch ends inside an utterance".into()))
+                }
+            }
+        }
+        Ok(done)
+    }
+
+    /// Discard the partial utterance, e.g. on an ABORT received out of
+    /// band, reporting `reason` (see [`abort_reason`]). Returns the number
+    /// of bytes dropped.
+    pub fn abort(&mut self, reason: u8) -> usize {
+        let dropped = self.buf.len();
+        self.buf.clear();
+        self.events.push(FlowEvent::Aborted { reason });
+        dropped
+    }
+
+    /// Flow-control events seen since the last call.
+    pub fn take_events(&mut self) -> Vec<FlowEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::AILLEncoder;
+    use crate::reliability::abort_reason;
+
+    fn utterance(value: i32) -> Vec<u8> {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_().int32(value);
+        e.end_utterance()
+    }
+
+    #[test]
+    fn reassembles_utterances_split_across_pushes() {
+        let mut wire = utterance(1);
+        wire.push(fc::PAUSE);
+        wire.push(fc::RESUME);
+        wire.extend(utterance(2));
+
+        let mut stream = StreamDecoder::new();
+        let mut got = Vec::new();
+        for chunk in wire.chunks(5) {
+            got.extend(stream.push(chunk).unwrap());
+        }
+        assert_eq!(got.len(), 2);
+        assert_eq!(stream.pending(), 0);
+        assert_eq!(stream.take_events(), vec![FlowEvent::Paused, FlowEvent::Resumed]);
+    }
+
+    #[test]
+    fn abort_discards_the_partial_utterance() {
+        let full = utterance(7);
+        let mut stream = StreamDecoder::new();
+        assert!(stream.push(&full[..full.len() - 6]).unwrap().is_empty());
+        assert!(stream.pending() > 0);
+        assert!(stream.push(&[fc::ABORT]).unwrap().is_empty());
+        assert!(stream.push(&[abort_reason::TIMEOUT]).unwrap().is_empty());
+        assert_eq!(stream.pending(), 0);
+        assert_eq!(stream.take_events(), vec![FlowEvent::Aborted { reason: abort_reason::TIMEOUT }]);
+
+        // The decoder refuses an aborted utterance outright
+        let mut aborted = full[..full.len() - 6].to_vec();
+        aborted.extend([fc::ABORT, abort_reason::UNSPECIFIED]);
+        assert!(AILLDecoder::new().decode_utterance(&aborted).is_err());
+
+        assert_eq!(stream.push(&full).unwrap().len(), 1);
+        stream.push(&full[..4]).unwrap();
+        assert_eq!(stream.abort(abort_reason::CHANNEL_DEGRADED), 4);
+    }
+
+    #[test]
+    fn abort_between_utterances_keeps_the_next_one() {
+        // The reason code is not mistaken for START_UTTERANCE
+        let mut wire = vec![fc::ABORT, abort_reason::UNSPECIFIED];
+        wire.extend(utterance(1));
+        let mut stream = StreamDecoder::new();
+        assert_eq!(stream.push(&wire).unwrap().len(), 1);
+        assert_eq!(stream.pending(), 0);
+        assert_eq!(stream.take_events(), vec![FlowEvent::Aborted { reason: abort_reason::UNSPECIFIED }]);
+    }
+}
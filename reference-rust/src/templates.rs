@@ -0,0 +1,474 @@
+//! Parameterized utterance templates.
+//!
+//! A [`Template`] is compiled once from an utterance AST containing
+//! [`AstNode::Placeholder`] slots. Each [`Template::instantiate`] call then
+//! only copies the precompiled wire bytes and writes the substituted
+//! literals, which suits messages re-sent at a fixed rate with a few changing
+//! values.
+
+use crate::ast::{AnnotationValue, AstNode, LiteralValue, MetaHeader, MEASUREMENT_SCHEMA_ID};
+use crate::codebook::base::{esc, fc, meta, modal, st, ty, BASE_CODEBOOK};
+use crate::error::AILLError;
+use crate::wire::{ByteWriter, LONG_STRINGS_VERSION};
+
+/// Offset of the TIMESTAMP_META payload within an encoded utterance:
+/// START(1) + CONFIDENCE(1+2) + PRIORITY(1+1) + TIMESTAMP_META(1).
+const TIMESTAMP_OFFSET: usize = 7;
+
+/// Build a placeholder node for a literal of `value_type` (e.g. `"float32"`).
+pub fn placeholder(name: &str, value_type: &str) -> AstNode {
+    AstNode::Placeholder {
+        name: name.to_string(),
+        value_type: value_type.to_string(),
+    }
+}
+
+/// Literal type name of `value`, as used in [`AstNode::Literal::value_type`].
+pub fn literal_type_name(value: &LiteralValue) -> &'static str {
+    match value {
+        LiteralValue::Int8(_) => "int8",
+        LiteralValue::Int16(_) => "int16",
+        LiteralValue::Int32(_) => "int32",
+        LiteralValue::Int64(_) => "int64",
+        LiteralValue::Uint8(_) => "uint8",
+        LiteralValue::Uint16(_) => "uint16",
+        LiteralValue::Uint32(_) => "uint32",
+        LiteralValue::Uint64(_) => "uint64",
+        LiteralValue::Float16(_) => "float16",
+        LiteralValue::Float32(_) => "float32",
+        LiteralValue::Float64(_) => "float64",
+        LiteralValue::Bool(_) => "bool",
+        LiteralValue::String(_) => "string",
+        LiteralValue::Bytes(_) => "bytes",
+        LiteralValue::Timestamp(_) => "timestamp",
+        LiteralValue::Null => "null",
+        LiteralValue::Measurement { .. } => "measurement",
+    }
+}
+
+pub(crate) const LITERAL_TYPES: [&str; 17] = [
+    "int8", "int16", "int32", "int64", "uint8", "uint16", "uint32", "uint64",
+    "float16", "float32", "float64", "bool", "string", "bytes", "timestamp", "null", "measurement",
+];
+
+/// Encode an AST to wire bytes. The AST must not contain placeholders.
+pub fn encode_ast(node: &AstNode) -> Result<Vec<u8>, AILLError> {
+    let mut c = Compiler { w: ByteWriter::new(), chunks: Vec::new(), slots: Vec::new() };
+    c.node(node)?;
+    if let Some(slot) = c.slots.first() {
+        return Err(AILLError::EncoderError(format!("unfilled placeholder '{}'", slot.name)));
+    }
+    if let Some(e) = c.w.take_length_error() {
+        return Err(e);
+    }
+    Ok(c.w.into_bytes())
+}
+
+struct Slot {
+    name: String,
+    value_type: String,
+}
+
+/// A precompiled utterance with named literal slots.
+///
+/// ```
+/// use aill::{AILLDecoder, AstNode, LiteralValue, MetaHeader};
+/// use aill::templates::{placeholder, Template};
+///
+/// let ast = AstNode::Utterance {
+///     meta: MetaHeader::default(),
+///     body: vec![placeholder("speed", "float32")],
+/// };
+/// let tpl = Template::new(&ast).unwrap();
+/// let wire = tpl.instantiate(&[LiteralValue::Float32(1.5)]).unwrap();
+/// assert!(AILLDecoder::new().decode_utterance(&wire).is_ok());
+/// ```
+pub struct Template {
+    /// Wire bytes between slots; always `slots.len() + 1` entries.
+    chunks: Vec<Vec<u8>>,
+    slots: Vec<Slot>,
+    long_strings: bool,
+}
+
+impl Template {
+    /// Compile an utterance AST. Placeholders are numbered in document order.
+    ///
+    /// Fails if `ast` is not an utterance, names an unknown literal type,
+    /// or contains nodes whose payload the AST does not retain
+    /// (`Annotated`, `REPORTED`).
+    pub fn new(ast: &AstNode) -> Result<Template, AILLError> {
+        if !matches!(ast, AstNode::Utterance { .. }) {
+            return Err(AILLError::EncoderError("template root must be an utterance".into()));
+        }
+        let mut c = Compiler { w: ByteWriter::new(), chunks: Vec::new(), slots: Vec::new() };
+        c.node(ast)?;
+        if let Some(e) = c.w.take_length_error() {
+            return Err(e);
+        }
+        let long_strings = c.w.long_strings();
+        c.chunks.push(c.w.into_bytes());
+        Ok(Template { chunks: c.chunks, slots: c.slots, long_strings })
+    }
+
+    /// Placeholder `(name, value_type)` pairs in substitution order.
+    pub fn placeholders(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.slots.iter().map(|s| (s.name.as_str(), s.value_type.as_str()))
+    }
+
+    /// Encode the utterance with `values` substituted for the placeholders,
+    /// in the order returned by [`placeholders`](Self::placeholders).
+    pub fn instantiate(&self, values: &[LiteralValue]) -> Result<Vec<u8>, AILLError> {
+        if values.len() != self.slots.len() {
+            return Err(AILLError::EncoderError(format!(
+                "template expects {} values, got {}",
+                self.slots.len(),
+                values.len()
+            )));
+        }
+        let mut w = ByteWriter::new();
+        w.set_long_strings(self.long_strings).write_raw(&self.chunks[0]);
+        for ((slot, value), chunk) in self.slots.iter().zip(values).zip(&self.chunks[1..]) {
+            let actual = literal_type_name(value);
+            if actual != slot.value_type {
+                return Err(AILLError::EncoderError(format!(
+                    "placeholder '{}' expects {}, got {}",
+                    slot.name, slot.value_type, actual
+                )));
+            }
+            write_literal(&mut w, value);
+            w.write_raw(chunk);
+        }
+        if let Some(e) = w.take_length_error() {
+            return Err(e);
+        }
+        Ok(w.into_bytes())
+    }
+
+    /// Like [`instantiate`](Self::instantiate), also replacing the
+    /// utterance timestamp.
+    pub fn instantiate_at(&self, timestamp_us: i64, values: &[LiteralValue]) -> Result<Vec<u8>, AILLError> {
+        let mut bytes = self.instantiate(values)?;
+        bytes[TIMESTAMP_OFFSET..TIMESTAMP_OFFSET + 8].copy_from_slice(&timestamp_us.to_be_bytes());
+        Ok(bytes)
+    }
+}
+
+struct Compiler {
+    w: ByteWriter,
+    chunks: Vec<Vec<u8>>,
+    slots: Vec<Slot>,
+}
+
+impl Compiler {
+    fn node(&mut self, node: &AstNode) -> Result<(), AILLError> {
+        match node {
+            AstNode::Utterance { meta, body } => {
+                self.w.write_u8(fc::START_UTTERANCE);
+                self.meta_header(meta)?;
+                for expr in body {
+                    self.node(expr)?;
+                }
+                self.w.write_u8(fc::END_UTTERANCE);
+            }
+            AstNode::Literal { value, .. } => write_literal(&mut self.w, value),
+            AstNode::Struct { fields } => {
+                self.w.write_u8(st::BEGIN_STRUCT);
+                for (fid, val) in fields {
+                    self.w.write_u8(st::FIELD_ID).write_u16_be(*fid);
+                    self.node(val)?;
+                }
+                self.w.write_u8(st::END_STRUCT);
+            }
+            AstNode::List { count, elements } => {
+                self.w.write_u8(st::BEGIN_LIST).write_u16_be(*count);
+                for elem in elements {
+                    self.node(elem)?;
+                }
+                self.w.write_u8(st::END_LIST);
+            }
+            AstNode::Map { count, pairs } => {
+                self.w.write_u8(st::BEGIN_MAP).write_u16_be(*count);
+                for (k, v) in pairs {
+                    self.node(k)?;
+                    self.node(v)?;
+                }
+                self.w.write_u8(st::END_MAP);
+            }
+            AstNode::Pragmatic { act, expression } => {
+                self.w.write_u8(opcode(act)?);
+                self.node(expression)?;
+            }
+            AstNode::Modal { modality, expression, extra } => {
+                let code = opcode(modality)?;
+                self.w.write_u8(code);
+                match code {
+                    modal::PREDICTED => {
+                        self.w.write_f16_be(extra.unwrap_or(0.0) as f32);
+                    }
+                    modal::REPORTED => {
+                        return Err(AILLError::EncoderError(
+                            "REPORTED source UUID is not retained in the AST".into(),
+                        ));
+                    }
+                    _ => {}
+                }
+                self.node(expression)?;
+            }
+            AstNode::Temporal { modifier, expression } => {
+                self.w.write_u8(opcode(modifier)?);
+                self.node(expression)?;
+            }
+            AstNode::SchemaRef { schema_id, expression } => {
+                self.w.write_u8(st::SCHEMA_REF).write_u16_be(*schema_id);
+                match expression.as_ref() {
+                    // Positional fields, as the decoder numbers them
+                    AstNode::Struct { fields } if fields.keys().copied().eq(0..fields.len() as u16) => {
+                        self.w.write_u8(st::BEGIN_STRUCT);
+                        for val in fields.values() {
+                            self.node(val)?;
+                        }
+                        self.w.write_u8(st::END_STRUCT);
+                    }
+                    other => self.node(other)?,
+                }
+            }
+            AstNode::DomainRef { level, domain_code, .. } => {
+                let code = match level {
+                    1 => esc::ESCAPE_L1,
+                    2 => esc::ESCAPE_L2,
+                    3 => esc::ESCAPE_L3,
+                    _ => {
+                        return Err(AILLError::EncoderError(format!("invalid escape level {}", level)));
+                    }
+                };
+                self.w.write_u8(code).write_u16_be(*domain_code);
+            }
+            AstNode::CodebookRef { level, registry_id } => {
+                self.w.write_u8(esc::CODEBOOK_REF).write_u8(*level).write_u16_be(*registry_id as u16);
+            }
+            AstNode::ContextRef { sct_index } => {
+                self.w.write_u8(meta::CONTEXT_REF).write_varint(*sct_index);
+            }
+            AstNode::HashRef { hash } => {
+                self.w.write_u8(meta::HASH_REF).write_u32_be(*hash);
+            }
+            AstNode::Code { code, .. } => {
+                self.w.write_u8(*code);
+            }
+            AstNode::FrameControl { code, operands, .. } => {
+                write_frame_control(&mut self.w, *code, operands)?;
+            }
+            AstNode::Extension { code, payload, .. } => {
+                self.w.write_u8(*code).write_raw(payload);
+            }
+            AstNode::Raw { bytes } => {
+                self.w.write_raw(bytes);
+            }
+            AstNode::Annotated { mnemonic, .. } => {
+                return Err(AILLError::EncoderError(format!(
+                    "annotation {} cannot be re-encoded from the AST",
+                    mnemonic
+                )));
+            }
+            AstNode::Placeholder { name, value_type } => {
+                if !LITERAL_TYPES.contains(&value_type.as_str()) {
+                    return Err(AILLError::EncoderError(format!(
+                        "placeholder '{}' has unknown type '{}'",
+                        name, value_type
+                    )));
+                }
+                if let Some(e) = self.w.take_length_error() {
+                    return Err(e);
+                }
+                let long_strings = self.w.long_strings();
+                let chunk = std::mem::take(&mut self.w).into_bytes();
+                self.w.set_long_strings(long_strings);
+                self.chunks.push(chunk);
+                self.slots.push(Slot { name: name.clone(), value_type: value_type.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    fn meta_header(&mut self, hdr: &MetaHeader) -> Result<(), AILLError> {
+        let w = &mut self.w;
+        w.write_u8(meta::CONFIDENCE).write_f16_be(hdr.confidence);
+        w.write_u8(meta::PRIORITY).write_u8(hdr.priority);
+        w.write_u8(meta::TIMESTAMP_META).write_i64_be(hdr.timestamp_us);
+        if let Some(ref uuid) = hdr.source_agent {
+            w.write_u8(meta::SOURCE_AGENT).write_uuid(uuid.as_bytes());
+        }
+        if let Some(ref uuid) = hdr.dest_agent {
+            w.write_u8(meta::DEST_AGENT).write_uuid(uuid.as_bytes());
+        }
+        if let Some(seq) = hdr.seqnum {
+            w.write_u8(meta::SEQNUM).write_u32_be(seq);
+        }
+        for (key, value) in &hdr.annotations {
+            match (key.as_str(), value) {
+                ("trace_id", AnnotationValue::U64(v)) => {
+                    w.write_u8(meta::TRACE_ID).write_u64_be(*v);
+                }
+                ("ttl", AnnotationValue::U16(v)) => {
+                    w.write_u8(meta::TTL).write_u16_be(*v);
+                }
+                ("topic", AnnotationValue::U16(v)) => {
+                    w.write_u8(meta::TOPIC).write_u16_be(*v);
+                }
+                ("version", AnnotationValue::Pair(major, minor)) => {
+                    w.write_u8(meta::VERSION_TAG).write_u16_be(*major).write_u16_be(*minor);
+                    w.set_long_strings((*major, *minor) >= LONG_STRINGS_VERSION);
+                }
+                ("cost", AnnotationValue::F32(c)) => {
+                    w.write_u8(meta::COST).write_f16_be(*c);
+                }
+                _ => {
+                    return Err(AILLError::EncoderError(format!(
+                        "unsupported meta annotation '{}'",
+                        key
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Base codebook code for `mnemonic`.
+fn opcode(mnemonic: &str) -> Result<u8, AILLError> {
+    BASE_CODEBOOK
+        .iter()
+        .find(|e| e.mnemonic == mnemonic)
+        .map(|e| e.code)
+        .ok_or_else(|| AILLError::EncoderError(format!("unknown mnemonic '{}'", mnemonic)))
+}
+
+/// Write frame-control `code` and its operands, which must match
+/// [`fc::operand_widths`] in number and fit their widths.
+pub(crate) fn write_frame_control(w: &mut ByteWriter, code: u8, operands: &[u32]) -> Result<(), AILLError> {
+    let mnemonic = BASE_CODEBOOK[code as usize].mnemonic;
+    let widths = fc::operand_widths(code)
+        .ok_or_else(|| AILLError::EncoderError(format!("{} is not a body frame-control code", mnemonic)))?;
+    if widths.len() != operands.len() {
+        return Err(AILLError::EncoderError(format!(
+            "{} takes {} operands, got {}",
+            mnemonic,
+            widths.len(),
+            operands.len()
+        )));
+    }
+    if let Some((width, operand)) = widths.iter().zip(operands).find(|(w, o)| **w < 4 && **o >> (**w * 8) != 0) {
+        return Err(AILLError::EncoderError(format!("{} operand {} does not fit {} bytes", mnemonic, operand, width)));
+    }
+    w.write_u8(code);
+    for (width, operand) in widths.iter().zip(operands) {
+        match width {
+            1 => w.write_u8(*operand as u8),
+            2 => w.write_u16_be(*operand as u16),
+            _ => w.write_u32_be(*operand),
+        };
+    }
+    Ok(())
+}
+
+pub(crate) fn write_literal(w: &mut ByteWriter, value: &LiteralValue) {
+    match value {
+        LiteralValue::Int8(v) => w.write_u8(ty::TYPE_INT8).write_i8(*v),
+        LiteralValue::Int16(v) => w.write_u8(ty::TYPE_INT16).write_i16_be(*v),
+        LiteralValue::Int32(v) => w.write_u8(ty::TYPE_INT32).write_i32_be(*v),
+        LiteralValue::Int64(v) => w.write_u8(ty::TYPE_INT64).write_i64_be(*v),
+        LiteralValue::Uint8(v) => w.write_u8(ty::TYPE_UINT8).write_u8(*v),
+        LiteralValue::Uint16(v) => w.write_u8(ty::TYPE_UINT16).write_u16_be(*v),
+        LiteralValue::Uint32(v) => w.write_u8(ty::TYPE_UINT32).write_u32_be(*v),
+        LiteralValue::Uint64(v) => w.write_u8(ty::TYPE_UINT64).write_u64_be(*v),
+        LiteralValue::Float16(v) => w.write_u8(ty::TYPE_FLOAT16).write_f16_be(*v),
+        LiteralValue::Float32(v) => w.write_u8(ty::TYPE_FLOAT32).write_f32_be(*v),
+        LiteralValue::Float64(v) => w.write_u8(ty::TYPE_FLOAT64).write_f64_be(*v),
+        LiteralValue::Bool(v) => w.write_u8(ty::TYPE_BOOL).write_u8(*v as u8),
+        LiteralValue::String(v) => w.write_u8(ty::TYPE_STRING).write_string(v),
+        LiteralValue::Bytes(v) => w.write_u8(ty::TYPE_BYTES).write_bytes_val(v),
+        LiteralValue::Timestamp(v) => w.write_u8(ty::TYPE_TIMESTAMP).write_i64_be(*v),
+        LiteralValue::Null => w.write_u8(ty::TYPE_NULL),
+        LiteralValue::Measurement { value, stddev } => w
+            .write_u8(st::SCHEMA_REF)
+            .write_u16_be(MEASUREMENT_SCHEMA_ID)
+            .write_u8(st::BEGIN_STRUCT)
+            .write_u8(ty::TYPE_FLOAT32)
+            .write_f32_be(*value)
+            .write_u8(ty::TYPE_FLOAT32)
+            .write_f32_be(*stddev)
+            .write_u8(st::END_STRUCT),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AILLDecoder, AILLEncoder};
+    use std::collections::BTreeMap;
+
+    fn pose_template() -> Template {
+        let mut fields = BTreeMap::new();
+        fields.insert(0x0000, placeholder("x", "float32"));
+        fields.insert(0x0001, placeholder("label", "string"));
+        let ast = AstNode::Utterance {
+            meta: MetaHeader::default(),
+            body: vec![AstNode::Pragmatic {
+                act: "ASSERT".into(),
+                expression: Box::new(AstNode::Struct { fields }),
+            }],
+        };
+        Template::new(&ast).unwrap()
+    }
+
+    #[test]
+    fn instantiate_matches_encoder_output() {
+        let tpl = pose_template();
+        let names: Vec<_> = tpl.placeholders().map(|(n, _)| n).collect();
+        assert_eq!(names, ["x", "label"]);
+
+        let wire = tpl
+            .instantiate(&[LiteralValue::Float32(2.5), LiteralValue::String("arm".into())])
+            .unwrap();
+        let mut e = AILLEncoder::new();
+        e.start_utterance().assert_();
+        e.begin_struct().field(0x0000).float32(2.5).field(0x0001).string("arm").end_struct();
+        assert_eq!(wire, e.end_utterance());
+    }
+
+    #[test]
+    fn instantiate_at_rewrites_timestamp() {
+        let wire = pose_template()
+            .instantiate_at(123_456, &[LiteralValue::Float32(0.0), LiteralValue::String(String::new())])
+            .unwrap();
+        match AILLDecoder::new().decode_utterance(&wire).unwrap() {
+            AstNode::Utterance { meta, .. } => assert_eq!(meta.timestamp_us, 123_456),
+            other => panic!("expected utterance, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decoded_ast_roundtrips_as_template() {
+        let mut e = AILLEncoder::new();
+        e.start_utterance_with(0.5, 6, Some(42), Some([7; 16].into()), Some(9));
+        e.command().l1_ref(0x0010).predicted(500.0).list_of_int32(&[1, 2]);
+        let wire = e.end_utterance();
+        let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+        assert_eq!(Template::new(&ast).unwrap().instantiate(&[]).unwrap(), wire);
+    }
+
+    #[test]
+    fn type_and_arity_errors() {
+        let tpl = pose_template();
+        assert!(tpl.instantiate(&[LiteralValue::Float32(1.0)]).is_err());
+        assert!(tpl
+            .instantiate(&[LiteralValue::Int32(1), LiteralValue::String("a".into())])
+            .is_err());
+        let bad = AstNode::Utterance {
+            meta: MetaHeader::default(),
+            body: vec![placeholder("v", "float128")],
+        };
+        assert!(Template::new(&bad).is_err());
+        assert!(Template::new(&placeholder("v", "int8")).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+//! In-memory links and a two-agent protocol harness.
+//!
+//! A [`LoopbackTransport`] is one end of a datagram link held in memory.
+//! Frames sent at one end arrive at the other after the configured
+//! latency plus a random jitter, which lets later frames overtake earlier
+//! ones, or not at all. Randomness comes from a seed, so a failing run
+//! can be replayed exactly. Time is passed in explicitly as microseconds.
+//!
+//! A [`LoopbackHarness`] connects two [`LoopbackAgent`]s, each a full
+//! stack of epoch framing, [`ReliableSender`] and [`ReliableReceiver`],
+//! [`EpochReorderBuffer`] and [`StreamDecoder`], and drives both from a
+//! [`MockClock`]. Epochs and ACK/NACK frames travel over separate
+//! transports with the same conditions.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use super::Rng;
+use crate::ast::AstNode;
+use crate::clock::{MockClock, TimeSource};
+use crate::decoder::decode_epoch;
+use crate::encoder::EpochBuilder;
+use crate::error::AILLError;
+use crate::reliability::{ReliableReceiver, ReliableSender, DEFAULT_MAX_RETRIES};
+use crate::reorder::{EpochReorderBuffer, ReorderEvent};
+use crate::stream::StreamDecoder;
+
+/// Loss, delay and reordering applied by a [`LoopbackTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LinkConditions {
+    /// Percentage of frames dropped.
+    pub loss_percent: u8,
+    pub latency_us: i64,
+    /// Extra delay per frame, uniform in `0..=jitter_us`.
+    pub jitter_us: i64,
+    pub seed: u64,
+}
+
+impl LinkConditions {
+    /// A perfect link: no loss, no delay.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_loss_percent(mut self, loss_percent: u8) -> Self {
+        self.loss_percent = loss_percent.min(100);
+        self
+    }
+
+    pub fn with_latency_us(mut self, latency_us: i64) -> Self {
+        self.latency_us = latency_us;
+        self
+    }
+
+    pub fn with_jitter_us(mut self, jitter_us: i64) -> Self {
+        self.jitter_us = jitter_us;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// One direction of a link.
+struct Channel {
+    conditions: LinkConditions,
+    rng: Rng,
+    /// Frames by arrival time, then send order.
+    in_transit: BTreeMap<(i64, u64), Vec<u8>>,
+    sent: u64,
+    dropped: u64,
+}
+
+impl Channel {
+    fn new(conditions: LinkConditions, seed: u64) -> Arc<Mutex<Self>> {
+        let channel = Self { conditions, rng: Rng(seed), in_transit: BTreeMap::new(), sent: 0, dropped: 0 };
+        Arc::new(Mutex::new(channel))
+    }
+}
+
+fn lock(channel: &Mutex<Channel>) -> std::sync::MutexGuard<'_, Channel> {
+    channel.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// One end of an in-memory datagram link; see the [module docs](self).
+#[derive(Clone)]
+pub struct LoopbackTransport {
+    outgoing: Arc<Mutex<Channel>>,
+    incoming: Arc<Mutex<Channel>>,
+}
+
+impl LoopbackTransport {
+    /// Both ends of a link with `conditions` in each direction.
+    pub fn pair(conditions: LinkConditions) -> (Self, Self) {
+        let ab = Channel::new(conditions, conditions.seed);
+        let ba = Channel::new(conditions, conditions.seed ^ 0xA5A5_A5A5_A5A5_A5A5);
+        (Self { outgoing: ab.clone(), incoming: ba.clone() }, Self { outgoing: ba, incoming: ab })
+    }
+
+    /// Put `frame` on the link at `now_us`.
+    pub fn send(&self, frame: &[u8], now_us: i64) {
+        let mut guard = lock(&self.outgoing);
+        let ch = &mut *guard;
+        ch.sent += 1;
+        if ch.rng.chance(ch.conditions.loss_percent as u64) {
+            ch.dropped += 1;
+            return;
+        }
+        let jitter = match ch.conditions.jitter_us {
+            j if j > 0 => ch.rng.below(j as u64 + 1) as i64,
+            _ => 0,
+        };
+        let arrival = now_us.saturating_add(ch.conditions.latency_us + jitter);
+        let order = ch.sent;
+        ch.in_transit.insert((arrival, order), frame.to_vec());
+    }
+
+    /// Frames that have arrived by `now_us`, in arrival order.
+    pub fn recv(&self, now_us: i64) -> Vec<Vec<u8>> {
+        let mut ch = lock(&self.incoming);
+        let later = ch.in_transit.split_off(&(now_us.saturating_add(1), 0));
+        std::mem::replace(&mut ch.in_transit, later).into_values().collect()
+    }
+
+    /// Frames sent from this end that have not arrived yet.
+    pub fn in_transit(&self) -> usize {
+        lock(&self.outgoing).in_transit.len()
+    }
+
+    /// Frames sent from this end, and how many of them were lost.
+    pub fn sent(&self) -> (u64, u64) {
+        let ch = lock(&self.outgoing);
+        (ch.sent, ch.dropped)
+    }
+}
+
+/// One side of a [`LoopbackHarness`].
+pub struct LoopbackAgent {
+    data: LoopbackTransport,
+    control: LoopbackTransport,
+    next_seq: u16,
+    sender: ReliableSender,
+    receiver: ReliableReceiver,
+    reorder: EpochReorderBuffer,
+    stream: StreamDecoder,
+    delivered: Vec<AstNode>,
+    gaps: u32,
+}
+
+impl LoopbackAgent {
+    fn new(data: LoopbackTransport, control: LoopbackTransport, timeout_us: i64) -> Self {
+        Self {
+            data,
+            control,
+            next_seq: 0,
+            sender: ReliableSender::new(timeout_us),
+            receiver: ReliableReceiver::new(),
+            // Long enough for the sender to use up its retries
+            reorder: EpochReorderBuffer::new(timeout_us * (DEFAULT_MAX_RETRIES as i64 + 1)).with_next_seq(0),
+            stream: StreamDecoder::new(),
+            delivered: Vec::new(),
+            gaps: 0,
+        }
+    }
+
+    /// Frame a complete utterance into epochs and send them.
+    pub fn send(&mut self, utterance: &[u8], now_us: i64) -> Result<(), AILLError> {
+        let mut epochs = EpochBuilder::new().with_seq(self.next_seq);
+        epochs.write(utterance);
+        let framed = epochs.get_epochs();
+        self.next_seq = epochs.next_seq();
+        for epoch in framed {
+            if let Some(wire) = self.sender.send(epoch, now_us)? {
+                self.data.send(&wire, now_us);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle everything that has arrived by `now_us` and retransmit what
+    /// has timed out.
+    pub fn step(&mut self, now_us: i64) -> Result<(), AILLError> {
+        for frame in self.control.recv(now_us) {
+            for epoch in self.sender.handle_control(&frame, now_us)? {
+                self.data.send(&epoch, now_us);
+            }
+        }
+        for epoch in self.sender.poll(now_us) {
+            self.data.send(&epoch, now_us);
+        }
+        for wire in self.data.recv(now_us) {
+            let delivery = self.receiver.receive(&wire, now_us)?;
+            if !delivery.reply.is_empty() {
+                self.control.send(&delivery.reply, now_us);
+            }
+            if delivery.payload.is_some() {
+                let (epoch, _) = decode_epoch(&wire, 0)?;
+                let events = self.reorder.push(epoch, now_us);
+                self.deliver(events)?;
+            }
+        }
+        let events = self.reorder.poll(now_us);
+        self.deliver(events)
+    }
+
+    fn deliver(&mut self, events: Vec<ReorderEvent>) -> Result<(), AILLError> {
+        for event in events {
+            match event {
+                ReorderEvent::Deliver { payload, .. } => self.delivered.extend(self.stream.push(&payload)?),
+                ReorderEvent::Gap { count, .. } => self.gaps += count as u32,
+            }
+        }
+        Ok(())
+    }
+
+    /// Utterances received so far, in order.
+    pub fn delivered(&self) -> &[AstNode] {
+        &self.delivered
+    }
+
+    pub fn take_delivered(&mut self) -> Vec<AstNode> {
+        std::mem::take(&mut self.delivered)
+    }
+
+    /// Epochs the reorder buffer gave up on.
+    pub fn gaps(&self) -> u32 {
+        self.gaps
+    }
+
+    pub fn sender(&self) -> &ReliableSender {
+        &self.sender
+    }
+
+    pub fn receiver(&self) -> &ReliableReceiver {
+        &self.receiver
+    }
+
+    /// The data and control transports.
+    pub fn transports(&self) -> (&LoopbackTransport, &LoopbackTransport) {
+        (&self.data, &self.control)
+    }
+
+    /// Nothing unacknowledged and nothing of ours on the wire.
+    pub fn is_idle(&self) -> bool {
+        self.sender.in_flight() == 0 && self.data.in_transit() == 0 && self.control.in_transit() == 0
+    }
+}
+
+/// Two agents connected by loopback transports; see the [module docs](self).
+pub struct LoopbackHarness {
+    pub a: LoopbackAgent,
+    pub b: LoopbackAgent,
+    pub clock: MockClock,
+    step_us: i64,
+}
+
+impl LoopbackHarness {
+    /// Agents whose senders retransmit after `timeout_us`, over links with
+    /// `conditions`.
+    pub fn new(conditions: LinkConditions, timeout_us: i64) -> Self {
+        let (data_a, data_b) = LoopbackTransport::pair(conditions);
+        let (control_a, control_b) = LoopbackTransport::pair(conditions.with_seed(!conditions.seed));
+        Self {
+            a: LoopbackAgent::new(data_a, control_a, timeout_us),
+            b: LoopbackAgent::new(data_b, control_b, timeout_us),
+            clock: MockClock::new(0),
+            step_us: (timeout_us / 10).max(1),
+        }
+    }
+
+    /// Advance the clock by `step_us` per [`step`](Self::step).
+    pub fn with_step_us(mut self, step_us: i64) -> Self {
+        self.step_us = step_us.max(1);
+        self
+    }
+
+    pub fn now_us(&self) -> i64 {
+        self.clock.now_us()
+    }
+
+    /// Advance the clock one step and let both agents act.
+    pub fn step(&mut self) -> Result<(), AILLError> {
+        let now = self.clock.advance(self.step_us);
+        self.a.step(now)?;
+        self.b.step(now)
+    }
+
+    /// Step until both agents are idle or `max_us` of simulated time has
+    /// passed. Returns whether the link settled.
+    pub fn run_until_idle(&mut self, max_us: i64) -> Result<bool, AILLError> {
+        let deadline = self.now_us().saturating_add(max_us);
+        while !(self.a.is_idle() && self.b.is_idle()) {
+            if self.now_us() >= deadline {
+                return Ok(false);
+            }
+            self.step()?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_delays_reorders_and_drops_reproducibly() {
+        let conditions = LinkConditions::new().with_loss_percent(20).with_latency_us(1_000).with_jitter_us(5_000);
+        let run = || {
+            let (a, b) = LoopbackTransport::pair(conditions.with_seed(7));
+            for i in 0..200u8 {
+                a.send(&[i], i as i64 * 100);
+            }
+            assert!(b.recv(999).is_empty());
+            let got: Vec<u8> = b.recv(i64::MAX).into_iter().map(|f| f[0]).collect();
+            assert_eq!(a.in_transit(), 0);
+            (got, a.sent())
+        };
+        let (got, (sent, dropped)) = run();
+        assert_eq!(run().0, got);
+        assert_eq!(sent, 200);
+        assert_eq!(got.len() as u64, sent - dropped);
+        assert!((20..60).contains(&dropped), "{}", dropped);
+        assert!(got.windows(2).any(|w| w[0] > w[1]));
+    }
+}
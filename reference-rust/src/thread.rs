@@ -0,0 +1,230 @@
+//! Conversation threads (COMM-1 THREAD_ID).
+//!
+//! A negotiation spans several utterances (a PROPOSE, a counter-PROPOSE,
+//! an ACCEPT) that share a body-level tag:
+//!
+//! ```text
+//! ESCAPE_L1 THREAD_ID TYPE_UINT64 <id>
+//! ```
+//!
+//! The decoder leaves the tag in the body; [`lift_thread_id`] copies it into
+//! [`MetaHeader::thread_id`]. A [`ThreadStore`] groups decoded utterances by
+//! it so a dialogue can be reconstructed in order.
+//!
+//! [`MetaHeader::thread_id`]: crate::ast::MetaHeader::thread_id
+
+use std::collections::HashMap;
+
+use crate::agent::AgentId;
+use crate::ast::AstNode;
+use crate::correlator::uint64_tag;
+use crate::encoder::AILLEncoder;
+use crate::trace::new_trace_id;
+
+/// COMM-1 THREAD_ID entry code.
+pub const THREAD_ID: u16 = 0x0029;
+
+/// Generate a random, non-zero thread id.
+pub fn new_thread_id() -> u64 {
+    new_trace_id()
+}
+
+impl AILLEncoder {
+    /// Emit COMM-1 THREAD_ID as `ESCAPE_L1 0x0029 TYPE_UINT64 <id>`.
+    pub fn thread_id(&mut self, id: u64) -> &mut Self {
+        self.l1_ref(THREAD_ID).uint64(id)
+    }
+}
+
+/// The THREAD_ID of a decoded utterance, if present.
+pub fn thread_id(ast: &AstNode) -> Option<u64> {
+    match ast {
+        AstNode::Utterance { meta, body } => meta.thread_id.or_else(|| uint64_tag(body, THREAD_ID)),
+        _ => None,
+    }
+}
+
+/// Copy the THREAD_ID tag of a decoded utterance into its meta header.
+pub fn lift_thread_id(ast: &mut AstNode) {
+    if let AstNode::Utterance { meta, body } = ast {
+        meta.thread_id = uint64_tag(body, THREAD_ID);
+    }
+}
+
+/// The pragmatic act of an utterance (`"PROPOSE"`, `"ACCEPT"`, ...).
+fn act_of(ast: &AstNode) -> Option<&str> {
+    let AstNode::Utterance { body, .. } = ast else {
+        return None;
+    };
+    body.iter().find_map(|node| match node {
+        AstNode::Pragmatic { act, .. } => Some(act.as_str()),
+        _ => None,
+    })
+}
+
+/// Where a thread's negotiation stands, judged by its latest act.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Open,
+    Accepted,
+    Rejected,
+}
+
+/// The utterances of one thread, in arrival order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thread {
+    id: u64,
+    utterances: Vec<AstNode>,
+    last_update: u64,
+}
+
+impl Thread {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn utterances(&self) -> &[AstNode] {
+        &self.utterances
+    }
+
+    /// Pragmatic acts in arrival order, e.g. `["PROPOSE", "PROPOSE", "ACCEPT"]`.
+    pub fn acts(&self) -> Vec<&str> {
+        self.utterances.iter().filter_map(act_of).collect()
+    }
+
+    pub fn state(&self) -> ThreadState {
+        match self.acts().last() {
+            Some(&"ACCEPT") => ThreadState::Accepted,
+            Some(&"REJECT") => ThreadState::Rejected,
+            _ => ThreadState::Open,
+        }
+    }
+
+    /// SOURCE_AGENTs that spoke in the thread, in order of first utterance.
+    pub fn participants(&self) -> Vec<AgentId> {
+        let mut agents = Vec::new();
+        for ast in &self.utterances {
+            if let AstNode::Utterance { meta, .. } = ast {
+                if let Some(agent) = meta.source_agent {
+                    if !agents.contains(&agent) {
+                        agents.push(agent);
+                    }
+                }
+            }
+        }
+        agents
+    }
+}
+
+/// Groups decoded utterances by THREAD_ID.
+pub struct ThreadStore {
+    threads: HashMap<u64, Thread>,
+    capacity: usize,
+    clock: u64,
+}
+
+impl ThreadStore {
+    /// Keep at most `capacity` threads; the least recently updated thread
+    /// is dropped first.
+    pub fn new(capacity: usize) -> Self {
+        Self { threads: HashMap::new(), capacity, clock: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Add an utterance to its thread. Returns the thread id, or `None`
+    /// (and stores nothing) if the utterance has no THREAD_ID.
+    pub fn insert(&mut self, ast: AstNode) -> Option<u64> {
+        let id = thread_id(&ast)?;
+        if self.capacity == 0 {
+            return Some(id);
+        }
+        if !self.threads.contains_key(&id) && self.threads.len() == self.capacity {
+            let oldest = self.threads.values().min_by_key(|t| t.last_update).map(|t| t.id);
+            if let Some(oldest) = oldest {
+                self.threads.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        let thread = self
+            .threads
+            .entry(id)
+            .or_insert_with(|| Thread { id, utterances: Vec::new(), last_update: 0 });
+        thread.utterances.push(ast);
+        thread.last_update = self.clock;
+        Some(id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Thread> {
+        self.threads.get(&id)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Thread> {
+        self.threads.remove(&id)
+    }
+
+    /// All threads, most recently updated first.
+    pub fn threads(&self) -> Vec<&Thread> {
+        let mut threads: Vec<&Thread> = self.threads.values().collect();
+        threads.sort_by_key(|t| std::cmp::Reverse(t.last_update));
+        threads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLDecoder;
+
+    const A: AgentId = AgentId::new([0xA; 16]);
+    const B: AgentId = AgentId::new([0xB; 16]);
+
+    fn say(agent: AgentId, thread: Option<u64>, act: u8) -> AstNode {
+        let mut e = AILLEncoder::new();
+        e.start_utterance().source_agent(agent);
+        if let Some(id) = thread {
+            e.thread_id(id);
+        }
+        e.pragma(act).l1_ref(0x0012);
+        AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+    }
+
+    #[test]
+    fn thread_id_read_and_lifted() {
+        let mut ast = say(A, Some(77), 0x88);
+        assert_eq!(thread_id(&ast), Some(77));
+        lift_thread_id(&mut ast);
+        let AstNode::Utterance { meta, .. } = &ast else { panic!() };
+        assert_eq!(meta.thread_id, Some(77));
+        assert_eq!(thread_id(&say(A, None, 0x88)), None);
+        assert_ne!(new_thread_id(), 0);
+    }
+
+    #[test]
+    fn store_reconstructs_negotiation() {
+        let mut store = ThreadStore::new(2);
+        assert_eq!(store.insert(say(A, None, 0x81)), None);
+        store.insert(say(A, Some(1), 0x88));
+        store.insert(say(B, Some(1), 0x88));
+        assert_eq!(store.get(1).unwrap().state(), ThreadState::Open);
+        store.insert(say(A, Some(1), 0x89));
+
+        let thread = store.get(1).unwrap();
+        assert_eq!(thread.acts(), vec!["PROPOSE", "PROPOSE", "ACCEPT"]);
+        assert_eq!(thread.state(), ThreadState::Accepted);
+        assert_eq!(thread.participants(), vec![A, B]);
+
+        store.insert(say(B, Some(2), 0x85));
+        store.insert(say(B, Some(3), 0x88));
+        assert_eq!(store.len(), 2);
+        assert!(store.get(1).is_none());
+        assert_eq!(store.threads()[0].id(), 3);
+        assert_eq!(store.get(2).unwrap().state(), ThreadState::Rejected);
+    }
+}
@@ -0,0 +1,109 @@
+//! TYPE_TIMESTAMP and TIMESTAMP_META conversions.
+//!
+//! Both carry signed microseconds since the Unix epoch. These helpers
+//! convert to and from [`SystemTime`] and [`Duration`]; out-of-range
+//! values saturate rather than panic.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::ast::{LiteralValue, MetaHeader};
+use crate::encoder::AILLEncoder;
+
+/// Microseconds since the Unix epoch for `t`; negative before 1970.
+pub fn to_micros(t: SystemTime) -> i64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => duration_micros(d),
+        Err(e) => duration_micros(e.duration()).saturating_neg(),
+    }
+}
+
+/// The [`SystemTime`] `micros` microseconds after (or before) the epoch.
+pub fn from_micros(micros: i64) -> SystemTime {
+    let offset = Duration::from_micros(micros.unsigned_abs());
+    let t = if micros >= 0 { UNIX_EPOCH.checked_add(offset) } else { UNIX_EPOCH.checked_sub(offset) };
+    t.unwrap_or(UNIX_EPOCH)
+}
+
+/// `d` in whole microseconds, saturating at `i64::MAX`.
+pub fn duration_micros(d: Duration) -> i64 {
+    i64::try_from(d.as_micros()).unwrap_or(i64::MAX)
+}
+
+/// The current time in microseconds since the epoch.
+pub fn now_micros() -> i64 {
+    to_micros(SystemTime::now())
+}
+
+impl AILLEncoder {
+    /// Emit TYPE_TIMESTAMP for `t`.
+    pub fn timestamp_at(&mut self, t: SystemTime) -> &mut Self {
+        self.timestamp(to_micros(t))
+    }
+
+    /// Emit TYPE_TIMESTAMP for the current time.
+    pub fn timestamp_now(&mut self) -> &mut Self {
+        self.timestamp(now_micros())
+    }
+
+    /// `start_utterance()` with TIMESTAMP_META set to the current time.
+    pub fn start_utterance_now(&mut self) -> &mut Self {
+        self.start_utterance_with(1.0, 3, Some(now_micros()), None, None)
+    }
+}
+
+impl LiteralValue {
+    /// The time of a `Timestamp` literal.
+    pub fn as_system_time(&self) -> Option<SystemTime> {
+        match self {
+            LiteralValue::Timestamp(us) => Some(from_micros(*us)),
+            _ => None,
+        }
+    }
+}
+
+impl MetaHeader {
+    /// TIMESTAMP_META as a [`SystemTime`].
+    pub fn time(&self) -> SystemTime {
+        from_micros(self.timestamp_us)
+    }
+
+    /// How long before `now` the utterance was stamped, or `None` if the
+    /// timestamp lies in the future.
+    pub fn age(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(self.time()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::AstNode;
+    use crate::AILLDecoder;
+
+    #[test]
+    fn conversions_roundtrip_and_saturate() {
+        let t = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        assert_eq!(to_micros(t), 1_700_000_000_123_456);
+        assert_eq!(from_micros(to_micros(t)), t);
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(to_micros(before), -1_000_000);
+        assert_eq!(from_micros(-1_000_000), before);
+        assert_eq!(duration_micros(Duration::MAX), i64::MAX);
+    }
+
+    #[test]
+    fn encoder_and_decoder_helpers() {
+        let t = UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut e = AILLEncoder::new();
+        e.start_utterance_now().assert_().timestamp_at(t);
+        let AstNode::Utterance { meta, body } = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap()
+        else {
+            panic!()
+        };
+        assert!(meta.age(SystemTime::now() + Duration::from_secs(1)).unwrap() < Duration::from_secs(60));
+        assert_eq!(meta.age(meta.time() - Duration::from_secs(1)), None);
+        let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+        let AstNode::Literal { value, .. } = expression.as_ref() else { panic!() };
+        assert_eq!(value.as_system_time(), Some(t));
+    }
+}
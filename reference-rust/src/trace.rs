@@ -0,0 +1,143 @@
+//! TRACE_ID propagation across request chains.
+//!
+//! A [`TraceContext`] remembers the trace id of the utterance currently
+//! being handled and stamps it onto every outgoing utterance, so a chain of
+//! requests across several agents shares one id. When nothing is being
+//! traced, the first outgoing utterance starts a new trace.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ast::AstNode;
+use crate::encoder::AILLEncoder;
+
+/// Generate a random, non-zero 64-bit trace id.
+pub fn new_trace_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let mut h = RandomState::new().build_hasher();
+    h.write_u64(nanos);
+    h.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    h.finish().max(1)
+}
+
+/// The TRACE_ID of a decoded utterance, if present.
+pub fn trace_id(ast: &AstNode) -> Option<u64> {
+    match ast {
+        AstNode::Utterance { meta, .. } => meta.trace_id(),
+        _ => None,
+    }
+}
+
+/// Carries the active trace id from incoming to outgoing utterances.
+#[derive(Debug, Clone, Default)]
+pub struct TraceContext {
+    current: Option<u64>,
+}
+
+impl TraceContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continue an existing trace.
+    pub fn with_trace_id(trace_id: u64) -> Self {
+        Self { current: Some(trace_id) }
+    }
+
+    /// The active trace id, if any.
+    pub fn current(&self) -> Option<u64> {
+        self.current
+    }
+
+    /// Adopt the trace id of an incoming utterance. Utterances without a
+    /// TRACE_ID leave the context unchanged.
+    pub fn observe(&mut self, ast: &AstNode) -> Option<u64> {
+        if let Some(id) = trace_id(ast) {
+            self.current = Some(id);
+        }
+        self.current
+    }
+
+    /// The trace id for an outgoing utterance, starting a new trace if none
+    /// is active.
+    pub fn outgoing(&mut self) -> u64 {
+        *self.current.get_or_insert_with(new_trace_id)
+    }
+
+    /// End the active trace.
+    pub fn clear(&mut self) {
+        self.current = None;
+    }
+
+    /// `start_utterance()` followed by this context's TRACE_ID.
+    pub fn start_utterance<'e>(&mut self, enc: &'e mut AILLEncoder) -> &'e mut AILLEncoder {
+        let id = self.outgoing();
+        enc.start_utterance().trace_id(id)
+    }
+
+    /// A `tracing` span for handling `ast`, carrying its trace id, priority
+    /// and seqnum.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self, ast: &AstNode) -> tracing::Span {
+        let trace = trace_id(ast).or(self.current).map(|id| format!("{:016x}", id));
+        match ast {
+            AstNode::Utterance { meta, .. } => tracing::info_span!(
+                "aill.utterance",
+                trace_id = trace.as_deref().unwrap_or(""),
+                priority = meta.priority,
+                seqnum = meta.seqnum,
+            ),
+            _ => tracing::info_span!("aill.utterance", trace_id = trace.as_deref().unwrap_or("")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AILLDecoder;
+
+    fn decode(wire: &[u8]) -> AstNode {
+        AILLDecoder::new().decode_utterance(wire).unwrap()
+    }
+
+    #[test]
+    fn ids_are_nonzero_and_distinct() {
+        let a = new_trace_id();
+        let b = new_trace_id();
+        assert!(a != 0 && b != 0);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn trace_propagates_from_request_to_reply() {
+        let mut sender = TraceContext::new();
+        let mut e = AILLEncoder::new();
+        sender.start_utterance(&mut e).query().l1_ref(0x0000);
+        let request = decode(&e.end_utterance());
+        let id = trace_id(&request).unwrap();
+        assert_eq!(sender.current(), Some(id));
+
+        let mut receiver = TraceContext::new();
+        receiver.observe(&request);
+        let mut e = AILLEncoder::new();
+        receiver.start_utterance(&mut e).assert_().float32(1.0);
+        assert_eq!(trace_id(&decode(&e.end_utterance())), Some(id));
+    }
+
+    #[test]
+    fn untraced_input_keeps_context() {
+        let mut ctx = TraceContext::with_trace_id(7);
+        let mut e = AILLEncoder::new();
+        e.start_utterance().null();
+        assert_eq!(ctx.observe(&decode(&e.end_utterance())), Some(7));
+        ctx.clear();
+        assert_eq!(ctx.current(), None);
+    }
+}
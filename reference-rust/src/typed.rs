@@ -0,0 +1,316 @@
+//! Type-state utterance builder.
+//!
+//! [`AILLEncoder`] accepts any sequence of calls and leaves well-formedness
+//! to the caller. The builders here wrap it so that the usual mistakes do
+//! not compile: each state only offers the calls that are legal in it.
+//!
+//! ```
+//! use aill::typed::UtteranceBuilder;
+//!
+//! let wire = UtteranceBuilder::new()
+//!     .assert_()
+//!     .begin_struct()
+//!     .field(0x0000).float32(1.5)
+//!     .field(0x0001).list().item().uint8(1).item().uint8(2).end()
+//!     .end_struct()
+//!     .finish();
+//! assert!(aill::AILLDecoder::new().decode_utterance(&wire).is_ok());
+//! ```
+//!
+//! ```compile_fail
+//! // A pragmatic act must be followed by an expression, not another act.
+//! aill::typed::UtteranceBuilder::new().assert_().query();
+//! ```
+//!
+//! ```compile_fail
+//! // FIELD_ID is only available inside a struct.
+//! aill::typed::UtteranceBuilder::new().assert_().field(0x0000);
+//! ```
+
+use crate::codebook::base::{modal, pragma};
+use crate::encoder::AILLEncoder;
+
+mod private {
+    use crate::encoder::AILLEncoder;
+
+    pub trait Sealed {
+        fn encoder(&mut self) -> &mut AILLEncoder;
+    }
+}
+
+use private::Sealed;
+
+/// A state an [`Expr`] returns to once its expression is written.
+pub trait Parent: Sealed {}
+
+impl<T: Sealed> Parent for T {}
+
+/// An utterance body: a sequence of expressions, each optionally
+/// introduced by a pragmatic act.
+pub struct UtteranceBuilder {
+    enc: AILLEncoder,
+}
+
+impl UtteranceBuilder {
+    /// Start an utterance with the default header.
+    pub fn new() -> Self {
+        let mut enc = AILLEncoder::new();
+        enc.start_utterance();
+        Self { enc }
+    }
+
+    /// Start an utterance on `enc`, which keeps its agent id and FLOAT16
+    /// settings, with the given confidence and priority.
+    pub fn with_encoder(mut enc: AILLEncoder, confidence: f32, priority: u8) -> Self {
+        enc.start_utterance_with(confidence, priority, None, None, None);
+        Self { enc }
+    }
+
+    /// Pragmatic act `act`, followed by exactly one expression.
+    pub fn act(mut self, act: u8) -> Expr<Self> {
+        self.enc.pragma(act);
+        Expr { parent: self }
+    }
+
+    pub fn assert_(self) -> Expr<Self> { self.act(pragma::ASSERT) }
+    pub fn query(self) -> Expr<Self> { self.act(pragma::QUERY) }
+    pub fn request(self) -> Expr<Self> { self.act(pragma::REQUEST) }
+    pub fn command(self) -> Expr<Self> { self.act(pragma::COMMAND) }
+    pub fn acknowledge(self) -> Expr<Self> { self.act(pragma::ACKNOWLEDGE) }
+    pub fn warn(self) -> Expr<Self> { self.act(pragma::WARN) }
+
+    /// A body expression without a pragmatic act, such as a domain tag.
+    pub fn expr(self) -> Expr<Self> {
+        Expr { parent: self }
+    }
+
+    /// Write END_UTTERANCE and return the wire bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.enc.end_utterance()
+    }
+}
+
+impl Default for UtteranceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sealed for UtteranceBuilder {
+    fn encoder(&mut self) -> &mut AILLEncoder {
+        &mut self.enc
+    }
+}
+
+/// A slot that must be filled by exactly one expression, after which
+/// building continues in `P`.
+#[must_use = "an expression slot must be filled"]
+pub struct Expr<P: Parent> {
+    parent: P,
+}
+
+impl<P: Parent> Sealed for Expr<P> {
+    fn encoder(&mut self) -> &mut AILLEncoder {
+        self.parent.encoder()
+    }
+}
+
+macro_rules! leaf {
+    ($($(#[$doc:meta])* $name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $name(mut self, $($arg: $ty),*) -> P {
+                self.parent.encoder().$name($($arg),*);
+                self.parent
+            }
+        )*
+    };
+}
+
+impl<P: Parent> Expr<P> {
+    leaf! {
+        null();
+        bool_(val: bool);
+        int8(val: i8);
+        int16(val: i16);
+        int32(val: i32);
+        int64(val: i64);
+        uint8(val: u8);
+        uint16(val: u16);
+        uint32(val: u32);
+        uint64(val: u64);
+        float16(val: f32);
+        float32(val: f32);
+        float64(val: f64);
+        string(val: &str);
+        bytes(val: &[u8]);
+        timestamp(val: i64);
+        l1_ref(code: u16);
+        l2_ref(code: u16);
+        l3_ref(code: u16);
+        context_ref(sct_index: u32);
+        hash_ref(hash: u32);
+    }
+
+    /// Modality `m` applied to the expression that follows.
+    /// Use [`predicted`](Self::predicted) for PREDICTED.
+    pub fn modality(mut self, m: u8) -> Self {
+        self.parent.encoder().modality(m);
+        self
+    }
+
+    pub fn observed(self) -> Self { self.modality(modal::OBSERVED) }
+    pub fn inferred(self) -> Self { self.modality(modal::INFERRED) }
+
+    /// PREDICTED with a horizon, applied to the expression that follows.
+    pub fn predicted(mut self, horizon_ms: f32) -> Self {
+        self.parent.encoder().predicted(horizon_ms);
+        self
+    }
+
+    /// Temporal modifier `t` applied to the expression that follows.
+    pub fn temporal(mut self, t: u8) -> Self {
+        self.parent.encoder().temporal(t);
+        self
+    }
+
+    pub fn begin_struct(mut self) -> StructBuilder<P> {
+        self.parent.encoder().begin_struct();
+        StructBuilder { parent: self.parent }
+    }
+
+    /// A list whose element count is filled in by [`ListBuilder::end`].
+    pub fn list(mut self) -> ListBuilder<P> {
+        let count_at = self.parent.encoder().begin_list(0).current_size() - 2;
+        ListBuilder { parent: self.parent, count_at, count: 0 }
+    }
+
+    /// A map whose entry count is filled in by [`MapBuilder::end`].
+    pub fn map(mut self) -> MapBuilder<P> {
+        let count_at = self.parent.encoder().begin_map(0).current_size() - 2;
+        MapBuilder { parent: self.parent, count_at, count: 0 }
+    }
+}
+
+/// Fields of a struct.
+#[must_use = "a struct must be closed with end_struct"]
+pub struct StructBuilder<P: Parent> {
+    parent: P,
+}
+
+impl<P: Parent> Sealed for StructBuilder<P> {
+    fn encoder(&mut self) -> &mut AILLEncoder {
+        self.parent.encoder()
+    }
+}
+
+impl<P: Parent> StructBuilder<P> {
+    pub fn field(mut self, field_code: u16) -> Expr<Self> {
+        self.parent.encoder().field(field_code);
+        Expr { parent: self }
+    }
+
+    pub fn end_struct(mut self) -> P {
+        self.parent.encoder().end_struct();
+        self.parent
+    }
+}
+
+/// Elements of a list.
+#[must_use = "a list must be closed with end"]
+pub struct ListBuilder<P: Parent> {
+    parent: P,
+    count_at: usize,
+    count: u16,
+}
+
+impl<P: Parent> Sealed for ListBuilder<P> {
+    fn encoder(&mut self) -> &mut AILLEncoder {
+        self.parent.encoder()
+    }
+}
+
+impl<P: Parent> ListBuilder<P> {
+    /// Add an element.
+    ///
+    /// # Panics
+    /// On the 65536th element.
+    pub fn item(mut self) -> Expr<Self> {
+        self.count = self.count.checked_add(1).expect("list exceeds 65535 elements");
+        Expr { parent: self }
+    }
+
+    pub fn end(mut self) -> P {
+        let (at, count) = (self.count_at, self.count);
+        self.parent.encoder().patch_count(at, count).end_list();
+        self.parent
+    }
+}
+
+/// Entries of a map.
+#[must_use = "a map must be closed with end"]
+pub struct MapBuilder<P: Parent> {
+    parent: P,
+    count_at: usize,
+    count: u16,
+}
+
+impl<P: Parent> Sealed for MapBuilder<P> {
+    fn encoder(&mut self) -> &mut AILLEncoder {
+        self.parent.encoder()
+    }
+}
+
+impl<P: Parent> MapBuilder<P> {
+    /// Add an entry: the key expression, then the value expression.
+    ///
+    /// # Panics
+    /// On the 65536th entry.
+    pub fn entry(mut self) -> Expr<Expr<Self>> {
+        self.count = self.count.checked_add(1).expect("map exceeds 65535 entries");
+        Expr { parent: Expr { parent: self } }
+    }
+
+    pub fn end(mut self) -> P {
+        let (at, count) = (self.count_at, self.count);
+        self.parent.encoder().patch_count(at, count).end_map();
+        self.parent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::base::temporal;
+
+    #[test]
+    fn matches_the_runtime_encoder() {
+        let typed = UtteranceBuilder::new()
+            .expr()
+            .l1_ref(0x0027)
+            .query()
+            .observed()
+            .temporal(temporal::PAST)
+            .begin_struct()
+            .field(0x0000)
+            .map()
+            .entry()
+            .string("a")
+            .list()
+            .item()
+            .int8(-1)
+            .end()
+            .end()
+            .field(0x0001)
+            .predicted(250.0)
+            .bool_(true)
+            .end_struct()
+            .finish();
+
+        let mut e = AILLEncoder::new();
+        e.start_utterance().l1_ref(0x0027).query().observed().temporal(temporal::PAST);
+        e.begin_struct().field(0x0000).begin_map(1).string("a").begin_list(1).int8(-1).end_list().end_map();
+        e.field(0x0001).predicted(250.0).bool_(true).end_struct();
+        assert_eq!(typed, e.end_utterance());
+    }
+}
@@ -1,9 +1,12 @@
 use wasm_bindgen::prelude::*;
+use crate::agent::AgentId;
 use crate::codebook::base::{self, fc, ty, st, pragma, BASE_CODEBOOK};
 use crate::encoder::AILLEncoder;
-use crate::decoder::AILLDecoder;
+use crate::decoder::{AILLDecoder, DecodeObserver};
 use crate::pretty_print as pp;
+use crate::format::{Formatter, Style};
 use crate::wire::crc8::crc8 as compute_crc8;
+use crate::{AILLError, AstNode};
 
 // ═══════════════════════════════════════════════════════════════════════
 // Encoding functions
@@ -65,7 +68,7 @@ pub fn encode_content(content_type: &str, content: &str) -> Vec<u8> {
 pub fn encode_pragmatic(act: u8, topic_id: u16, content: &str, agent_id: &[u8]) -> Vec<u8> {
     let mut enc = AILLEncoder::new();
     enc.start_utterance();
-    enc.source_agent(agent_id);
+    enc.source_agent(padded_agent_id(agent_id));
     enc.topic(topic_id);
     enc.pragma(act);
     enc.string(content);
@@ -80,7 +83,7 @@ pub fn encode_pragmatic(act: u8, topic_id: u16, content: &str, agent_id: &[u8])
 pub fn encode_task_allocation(task_id: u32, role: &str, agent_id: &[u8]) -> Vec<u8> {
     let mut enc = AILLEncoder::new();
     enc.start_utterance();
-    enc.source_agent(agent_id);
+    enc.source_agent(padded_agent_id(agent_id));
     enc.topic(0x0101); // role_claim topic
     enc.propose();
     // PLAN-1 ALLOCATE_TASK struct: ESCAPE_L1 + 0x000D + struct{task_id, role}
@@ -94,6 +97,28 @@ pub fn encode_task_allocation(task_id: u32, role: &str, agent_id: &[u8]) -> Vec<
     enc.end_utterance()
 }
 
+/// JS callers may pass shorter ids; pad with zeros (or truncate) to 16 bytes.
+fn padded_agent_id(bytes: &[u8]) -> AgentId {
+    let mut buf = [0u8; 16];
+    let len = bytes.len().min(16);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    AgentId::new(buf)
+}
+
+/// Parse a UUID string (hyphens optional) into 16 agent id bytes.
+#[wasm_bindgen]
+pub fn parse_agent_id(s: &str) -> Result<Vec<u8>, JsError> {
+    let id: AgentId = s.parse().map_err(|e: crate::AILLError| JsError::new(&e.to_string()))?;
+    Ok(id.as_bytes().to_vec())
+}
+
+/// Format 16 agent id bytes as a hyphenated UUID string.
+#[wasm_bindgen]
+pub fn format_agent_id(bytes: &[u8]) -> Result<String, JsError> {
+    let id = AgentId::try_from(bytes).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(id.to_string())
+}
+
 /// Decode a pragmatic message, returning { act, topic, content, agent } or null.
 #[wasm_bindgen]
 pub fn decode_pragmatic_simple(data: &[u8]) -> JsValue {
@@ -116,7 +141,7 @@ fn decode_pragmatic_inner(bytes: &[u8]) -> Option<(String, u16, String, String)>
         return None;
     }
     let mut pos: usize = 1;
-    let mut agent_bytes: Option<[u8; 16]> = None;
+    let mut agent: Option<AgentId> = None;
     let mut topic_id: u16 = 0;
 
     // Parse meta header, extracting SOURCE_AGENT and TOPIC
@@ -129,9 +154,7 @@ fn decode_pragmatic_inner(bytes: &[u8]) -> Option<(String, u16, String, String)>
             0x92 => { // SOURCE_AGENT + 16 bytes
                 pos += 1;
                 if pos + 16 > bytes.len() { return None; }
-                let mut uuid = [0u8; 16];
-                uuid.copy_from_slice(&bytes[pos..pos + 16]);
-                agent_bytes = Some(uuid);
+                agent = AgentId::try_from(&bytes[pos..pos + 16]).ok();
                 pos += 16;
             }
             0x93 => { // DEST_AGENT + 16 bytes
@@ -186,10 +209,7 @@ fn decode_pragmatic_inner(bytes: &[u8]) -> Option<(String, u16, String, String)>
         String::new()
     };
 
-    let agent_hex = match agent_bytes {
-        Some(uuid) => uuid.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
-        None => String::new(),
-    };
+    let agent_hex = agent.map(|id| id.to_hex()).unwrap_or_default();
 
     Some((act_name.to_string(), topic_id, content, agent_hex))
 }
@@ -198,6 +218,72 @@ fn decode_pragmatic_inner(bytes: &[u8]) -> Option<(String, u16, String, String)>
 // Decoding functions
 // ═══════════════════════════════════════════════════════════════════════
 
+/// Error thrown by the decoding functions, so the inspector can point at
+/// the failing byte: `{ kind, offset, expected, got, message }`. `kind` is
+/// [`AILLError::kind`]; `offset` is the byte the decoder stopped at, or
+/// null if unknown; `expected` and `got` are the values the error
+/// compares (CRCs, counts, or the byte found at `offset`), or null.
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct ErrorInfo {
+    kind: &'static str,
+    offset: Option<usize>,
+    expected: Option<u64>,
+    got: Option<u64>,
+    message: String,
+}
+
+impl ErrorInfo {
+    /// `error` with what it says about itself; `context` prefixes the message.
+    fn new(context: &str, error: &AILLError) -> Self {
+        let (expected, got) = match *error {
+            AILLError::InvalidOpCode(code) => (None, Some(code as u64)),
+            AILLError::CrcMismatch { expected, actual } => (Some(expected as u64), Some(actual as u64)),
+            AILLError::UnexpectedEof { needed, .. } => (Some(needed as u64), None),
+            AILLError::CountMismatch { declared, actual } => (Some(declared as u64), Some(actual as u64)),
+            AILLError::TooDeep { max_depth, .. } => (Some(max_depth as u64), None),
+            _ => (None, None),
+        };
+        ErrorInfo {
+            kind: error.kind(),
+            offset: error.offset(),
+            expected,
+            got,
+            message: format!("{}: {}", context, error),
+        }
+    }
+
+    /// Fill in an offset the error did not carry, and the byte found there.
+    fn located(mut self, data: &[u8], offset: Option<usize>) -> Self {
+        self.offset = self.offset.or(offset);
+        if self.got.is_none() {
+            self.got = self.offset.and_then(|i| data.get(i)).map(|&b| b as u64);
+        }
+        self
+    }
+
+    fn into_js(self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self).unwrap_or_else(|_| JsValue::from_str(&self.message))
+    }
+}
+
+/// Remembers where the last opcode was read, to locate decode errors.
+#[derive(Default)]
+struct LastOpcode(Option<usize>);
+
+impl DecodeObserver for LastOpcode {
+    fn on_opcode(&mut self, offset: usize, _code: u8) {
+        self.0 = Some(offset);
+    }
+}
+
+/// Decode one utterance, locating a failure at the opcode it happened in.
+fn decode_located(data: &[u8]) -> Result<AstNode, ErrorInfo> {
+    let mut last = LastOpcode::default();
+    AILLDecoder::new()
+        .decode_utterance_with_observer(data, &mut last)
+        .map_err(|e| ErrorInfo::new("Decode error", &e).located(data, last.0))
+}
+
 /// Simple decoder that returns {type, content} or null.
 /// Matches the demo's `AILL.decode(wire)` behavior.
 #[wasm_bindgen]
@@ -296,24 +382,29 @@ fn simple_decode(bytes: &[u8]) -> Option<(String, String)> {
 }
 
 /// Full AST decode — returns the AST as a JS value (serde-serialized).
+/// Throws an error object as described at [`ErrorInfo`].
 #[wasm_bindgen]
-pub fn decode_ast(data: &[u8]) -> Result<JsValue, JsError> {
-    let decoder = AILLDecoder::new();
-    let node = decoder.decode_utterance(data)
-        .map_err(|e| JsError::new(&format!("Decode error: {}", e)))?;
+pub fn decode_ast(data: &[u8]) -> Result<JsValue, JsValue> {
+    let node = decode_located(data).map_err(ErrorInfo::into_js)?;
     serde_wasm_bindgen::to_value(&node)
-        .map_err(|e| JsError::new(&format!("Serialization error: {}", e)))
+        .map_err(|e| JsError::new(&format!("Serialization error: {}", e)).into())
 }
 
 /// Pretty-print AILL wire-format bytes as a human-readable tree.
 #[wasm_bindgen]
-pub fn pretty_print_bytes(data: &[u8]) -> Result<String, JsError> {
-    let decoder = AILLDecoder::new();
-    let node = decoder.decode_utterance(data)
-        .map_err(|e| JsError::new(&format!("Decode error: {}", e)))?;
+pub fn pretty_print_bytes(data: &[u8]) -> Result<String, JsValue> {
+    let node = decode_located(data).map_err(ErrorInfo::into_js)?;
     Ok(pp(&node, 0))
 }
 
+/// Render AILL wire-format bytes as an HTML tree with one
+/// `<span class="aill-<category>">` per token, for the web inspector.
+#[wasm_bindgen]
+pub fn pretty_print_html(data: &[u8]) -> Result<String, JsValue> {
+    let node = decode_located(data).map_err(ErrorInfo::into_js)?;
+    Ok(Formatter::new(Style::Html).format(&node))
+}
+
 // ═══════════════════════════════════════════════════════════════════════
 // Utility functions
 // ═══════════════════════════════════════════════════════════════════════
@@ -412,12 +503,11 @@ pub fn acoustic_encode(wire_bytes: &[u8], sample_rate: u32) -> Result<Vec<f32>,
 /// If sample_rate is 0, defaults to 48000 Hz.
 #[cfg(feature = "audio-core")]
 #[wasm_bindgen]
-pub fn acoustic_decode(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>, JsError> {
+pub fn acoustic_decode(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>, JsValue> {
     let sr = if sample_rate == 0 { constants::DEFAULT_SAMPLE_RATE } else { sample_rate };
-    let decoder = AcousticDecoder::with_sample_rate(sr)
-        .map_err(|e| JsError::new(&format!("Acoustic decode error: {}", e)))?;
-    let bytes = decoder.decode(samples)
-        .map_err(|e| JsError::new(&format!("Acoustic decode error: {}", e)))?;
+    let error = |e: AILLError| ErrorInfo::new("Acoustic decode error", &e).into_js();
+    let decoder = AcousticDecoder::with_sample_rate(sr).map_err(error)?;
+    let bytes = decoder.decode(samples).map_err(error)?;
     Ok(bytes)
 }
 
@@ -428,6 +518,160 @@ pub fn acoustic_duration(num_bytes: usize) -> f32 {
     constants::SYNC_DURATION + (num_bytes as f32 * 2.0 * constants::FRAME_TIME) + constants::END_DURATION
 }
 
+/// Seconds of new audio between decoding passes of [`WebAudioModem`].
+#[cfg(feature = "audio-core")]
+const MODEM_ANALYSIS_INTERVAL: f32 = 0.25;
+
+/// Live acoustic modem for the browser. Feed it the microphone's sample
+/// frames from an AudioWorklet with `pushSamples`; it returns the decoded
+/// utterance of every transmission completed so far. `renderMessage`
+/// gives the samples to play for a frame. Both sides use the same DSP as
+/// native builds.
+#[cfg(feature = "audio-core")]
+#[wasm_bindgen]
+pub struct WebAudioModem {
+    encoder: AcousticEncoder,
+    decoder: AcousticDecoder,
+    /// Audio not yet known to be free of a transmission.
+    buffer: Vec<f32>,
+    /// Samples pushed since the last decoding pass.
+    unanalyzed: usize,
+}
+
+#[cfg(feature = "audio-core")]
+#[wasm_bindgen]
+impl WebAudioModem {
+    /// A modem at `sample_rate` (the AudioContext's), or 48000 Hz if 0.
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: u32) -> Result<WebAudioModem, JsError> {
+        let sr = if sample_rate == 0 { constants::DEFAULT_SAMPLE_RATE } else { sample_rate };
+        let error = |e: crate::AILLError| JsError::new(&format!("Acoustic modem error: {}", e));
+        Ok(WebAudioModem {
+            encoder: AcousticEncoder::with_sample_rate(sr).map_err(error)?,
+            decoder: AcousticDecoder::with_sample_rate(sr).map_err(error)?,
+            buffer: Vec::new(),
+            unanalyzed: 0,
+        })
+    }
+
+    #[wasm_bindgen(getter, js_name = sampleRate)]
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    /// Append microphone samples. Returns an array with the decoded
+    /// utterance of each transmission completed by them, as `decode_ast`
+    /// gives it, or the [`ErrorInfo`] object of one that did not decode.
+    #[wasm_bindgen(js_name = pushSamples)]
+    pub fn push_samples(&mut self, samples: &[f32]) -> Result<js_sys::Array, JsError> {
+        self.receive(samples)
+            .iter()
+            .map(|frame| match decode_located(frame) {
+                Ok(node) => serde_wasm_bindgen::to_value(&node)
+                    .map_err(|e| JsError::new(&format!("Serialization error: {}", e))),
+                Err(error) => Ok(error.into_js()),
+            })
+            .collect()
+    }
+
+    /// Render wire bytes as PCM samples (Float32Array) for playback.
+    #[wasm_bindgen(js_name = renderMessage)]
+    pub fn render_message(&self, wire_bytes: &[u8]) -> Result<Vec<f32>, JsError> {
+        let audio = self.encoder.encode(wire_bytes)
+            .map_err(|e| JsError::new(&format!("Acoustic encode error: {}", e)))?;
+        Ok(audio.samples)
+    }
+
+    /// Drop buffered audio, e.g. after the modem's own playback.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.unanalyzed = 0;
+    }
+}
+
+#[cfg(feature = "audio-core")]
+impl WebAudioModem {
+    /// [`push_samples`](Self::push_samples) for Rust callers: the frames of
+    /// the transmissions completed by `samples`. Frames that fail to
+    /// decode are dropped.
+    pub fn receive(&mut self, samples: &[f32]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(samples);
+        self.unanalyzed += samples.len();
+        let sr = self.sample_rate() as f32;
+        if (self.unanalyzed as f32) < MODEM_ANALYSIS_INTERVAL * sr {
+            return Vec::new();
+        }
+        self.unanalyzed = 0;
+
+        let mut frames = Vec::new();
+        // Start of the audio still needed
+        let mut keep = 0;
+        while self.buffer.len() - keep >= constants::FFT_SIZE {
+            let rest = &self.buffer[keep..];
+            let Ok(found) = self.decoder.decode_detailed(rest) else {
+                // No sync chirp: keep only enough for one that has begun
+                let sync = (constants::SYNC_DURATION * sr) as usize + constants::FFT_SIZE;
+                keep = keep.max(self.buffer.len().saturating_sub(sync));
+                break;
+            };
+            // Without the end chirp and some audio after it, the
+            // transmission may still be arriving
+            if found.end + constants::FFT_SIZE > rest.len() {
+                keep += found.start;
+                break;
+            }
+            if let Ok(bytes) = found.bytes {
+                frames.push(bytes);
+            }
+            keep += found.end;
+        }
+        self.buffer.drain(..keep);
+
+        // Give up on a transmission longer than the decoder reads
+        let longest = constants::SYNC_DURATION
+            + constants::MAX_DECODE_FRAMES as f32 * constants::FRAME_TIME
+            + constants::END_DURATION;
+        let excess = self.buffer.len().saturating_sub((longest * sr) as usize);
+        self.buffer.drain(..excess);
+        frames
+    }
+}
+
+/// Result of [`decode_epochs`].
+#[derive(serde::Serialize)]
+struct DecodedEpochs {
+    epochs: Vec<crate::reorder::EpochStatus>,
+    gaps: Vec<(u16, u16)>,
+    utterances: Vec<crate::AstNode>,
+    error: Option<ErrorInfo>,
+}
+
+/// Decode a blob of back-to-back epochs: split them, CRC-check them,
+/// reorder by sequence number, concatenate the payloads and decode every
+/// utterance. Returns `{ epochs: [{seq, offset, len, crc_ok, flags,
+/// timestamp_us}], gaps: [[first, count]], utterances: [ast], error }`,
+/// where `error` is an [`ErrorInfo`] object describing a truncated epoch,
+/// located at its start in `data`, or an undecodable payload.
+#[wasm_bindgen]
+pub fn decode_epochs(data: &[u8]) -> Result<JsValue, JsError> {
+    let r = crate::reorder::reassemble(data);
+    let failed_epoch = r.epochs.last().map_or(0, |e| e.offset + e.len);
+    let mut error = r.error.map(|e| ErrorInfo::new("Epoch error", &e).located(data, Some(failed_epoch)));
+    let mut utterances = Vec::new();
+    for result in AILLDecoder::new().utterances(&r.payload) {
+        match result {
+            Ok((node, _)) => utterances.push(node),
+            Err(e) => {
+                // Payload offsets do not point into `data`
+                error.get_or_insert_with(|| ErrorInfo { offset: None, got: None, ..ErrorInfo::new("Decode error", &e) });
+            }
+        }
+    }
+    let result = DecodedEpochs { epochs: r.epochs, gaps: r.gaps, utterances, error };
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsError::new(&format!("Serialization error: {}", e)))
+}
+
 /// Validate CRC of wire-format bytes (epoch format).
 #[wasm_bindgen]
 pub fn validate_epoch(data: &[u8]) -> bool {
@@ -439,3 +683,44 @@ pub fn validate_epoch(data: &[u8]) -> bool {
         Err(_) => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_errors_point_at_the_failing_byte() {
+        // A meta header not starting with CONFIDENCE
+        let mut wire = encode_string("hello");
+        wire[1] = 0x42;
+        let error = decode_located(&wire).unwrap_err();
+        assert_eq!((error.kind, error.offset, error.got), ("invalid_structure", Some(1), Some(0x42)));
+
+        let wire = encode_string("hello");
+        let error = decode_located(&wire[..wire.len() - 3]).unwrap_err();
+        assert_eq!((error.kind, error.expected), ("unexpected_eof", Some(5)));
+        assert!(error.offset.unwrap() < wire.len() && error.message.starts_with("Decode error: "));
+
+        let r = crate::reorder::reassemble(&[0, 1, 0, 9, 0]);
+        let error = ErrorInfo::new("Epoch error", &r.error.unwrap()).located(&[0, 1, 0, 9, 0], Some(0));
+        assert_eq!((error.kind, error.offset, error.got), ("invalid_structure", Some(0), Some(0)));
+    }
+
+    #[cfg(feature = "audio-core")]
+    #[test]
+    fn test_modem_hears_worklet_frames() {
+        let mut modem = WebAudioModem::new(0).unwrap();
+        let messages = [encode_string("hello"), encode_string("over")];
+        let quiet = vec![0.0f32; modem.sample_rate() as usize / 2];
+        let mut samples = quiet.clone();
+        for message in &messages {
+            samples.extend(modem.render_message(message).unwrap());
+            samples.extend(&quiet);
+        }
+
+        // An AudioWorklet hands over 128 samples at a time
+        let heard: Vec<Vec<u8>> = samples.chunks(128).flat_map(|block| modem.receive(block)).collect();
+        assert_eq!(heard, messages);
+        assert!(modem.buffer.len() < modem.sample_rate() as usize);
+    }
+}
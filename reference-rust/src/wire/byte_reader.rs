@@ -3,20 +3,32 @@ use crate::wire::float16::decode_float16;
 use crate::wire::varint::decode_varint;
 
 /// A cursor for reading AILL wire-format bytes.
+#[derive(Clone)]
 pub struct ByteReader<'a> {
     data: &'a [u8],
     pos: usize,
+    long_strings: bool,
 }
 
 impl<'a> ByteReader<'a> {
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data, pos: 0 }
+        Self { data, pos: 0, long_strings: false }
+    }
+
+    /// Read string lengths as varints, see [`LONG_STRINGS_VERSION`](crate::wire::LONG_STRINGS_VERSION).
+    pub fn set_long_strings(&mut self, on: bool) {
+        self.long_strings = on;
     }
 
     pub fn pos(&self) -> usize {
         self.pos
     }
 
+    /// The bytes read since position `start`.
+    pub fn since(&self, start: usize) -> &'a [u8] {
+        &self.data[start..self.pos]
+    }
+
     pub fn remaining(&self) -> usize {
         self.data.len().saturating_sub(self.pos)
     }
@@ -115,8 +127,8 @@ impl<'a> ByteReader<'a> {
     }
 
     pub fn read_string(&mut self) -> Result<String, AILLError> {
-        let length = self.read_u16_be()? as usize;
-        let bytes = self.read_bytes(length)?;
+        let length = if self.long_strings { self.read_varint()? } else { self.read_u16_be()? as u32 };
+        let bytes = self.read_bytes(length as usize)?;
         String::from_utf8(bytes.to_vec())
             .map_err(|e| AILLError::Utf8Error(e.to_string()))
     }
@@ -1,14 +1,33 @@
+use crate::error::AILLError;
 use crate::wire::float16::encode_float16;
 use crate::wire::varint::encode_varint;
 
 /// A buffer for building AILL wire-format byte sequences.
 pub struct ByteWriter {
     buf: Vec<u8>,
+    long_strings: bool,
+    /// The first string or byte value too long for its length prefix.
+    length_error: Option<AILLError>,
 }
 
 impl ByteWriter {
     pub fn new() -> Self {
-        Self { buf: Vec::new() }
+        Self { buf: Vec::new(), long_strings: false, length_error: None }
+    }
+
+    /// Write string lengths as varints, see [`LONG_STRINGS_VERSION`](crate::wire::LONG_STRINGS_VERSION).
+    pub fn set_long_strings(&mut self, on: bool) -> &mut Self {
+        self.long_strings = on;
+        self
+    }
+
+    pub fn long_strings(&self) -> bool {
+        self.long_strings
+    }
+
+    /// Longest string [`write_string`](Self::write_string) encodes intact.
+    pub fn max_string_len(&self) -> usize {
+        if self.long_strings { u32::MAX as usize } else { u16::MAX as usize }
     }
 
     pub fn write_u8(&mut self, val: u8) -> &mut Self {
@@ -66,14 +85,26 @@ impl ByteWriter {
         self
     }
 
+    /// Write a length-prefixed string. Beyond
+    /// [`max_string_len`](Self::max_string_len) the length wraps, which
+    /// [`take_length_error`](Self::take_length_error) reports.
     pub fn write_string(&mut self, val: &str) -> &mut Self {
         let bytes = val.as_bytes();
-        self.write_u16_be(bytes.len() as u16);
+        self.check_length("string", bytes.len(), self.max_string_len());
+        if self.long_strings {
+            self.write_varint(bytes.len() as u32);
+        } else {
+            self.write_u16_be(bytes.len() as u16);
+        }
         self.buf.extend_from_slice(bytes);
         self
     }
 
+    /// Write a u16-length-prefixed byte value. Beyond 65535 bytes the
+    /// length wraps, which [`take_length_error`](Self::take_length_error)
+    /// reports.
     pub fn write_bytes_val(&mut self, val: &[u8]) -> &mut Self {
+        self.check_length("byte value", val.len(), u16::MAX as usize);
         self.write_u16_be(val.len() as u16);
         self.buf.extend_from_slice(val);
         self
@@ -94,6 +125,36 @@ impl ByteWriter {
         self
     }
 
+    /// Overwrite two bytes at `at` with `val`, big-endian.
+    ///
+    /// # Panics
+    /// If `at + 2` exceeds the bytes written.
+    pub fn patch_u16_be(&mut self, at: usize, val: u16) -> &mut Self {
+        self.buf[at..at + 2].copy_from_slice(&val.to_be_bytes());
+        self
+    }
+
+    /// Remove and return the bytes from `at` on.
+    ///
+    /// # Panics
+    /// If `at` exceeds the bytes written.
+    pub fn take_from(&mut self, at: usize) -> Vec<u8> {
+        self.buf.split_off(at)
+    }
+
+    /// The first string or byte value written since the last call whose
+    /// length did not fit its prefix, leaving the output corrupt.
+    pub fn take_length_error(&mut self) -> Option<AILLError> {
+        self.length_error.take()
+    }
+
+    fn check_length(&mut self, what: &str, len: usize, max: usize) {
+        if len > max && self.length_error.is_none() {
+            self.length_error =
+                Some(AILLError::EncoderError(format!("{} of {} bytes exceeds the {}-byte limit", what, len, max)));
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.buf.clone()
     }
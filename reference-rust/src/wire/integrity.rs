@@ -0,0 +1,162 @@
+//! Integrity checks closing an epoch.
+//!
+//! Epochs end in a CRC-8 unless their extended header names another
+//! [`IntegrityScheme`] in the [`INTEGRITY`](super::epoch_flags::INTEGRITY)
+//! bits of its flags. CRC-16 is built in for links that need a stronger
+//! check, HMAC-SHA256 (feature `sha256`) for ones that must reject forged
+//! epochs; integrators
+//! can implement the trait for anything else their certification asks for.
+
+#[cfg(feature = "sha256")]
+use sha2::{Digest, Sha256};
+
+use super::epoch_flags::INTEGRITY;
+use super::{crc16, crc8};
+
+/// Bytes of the truncated HMAC-SHA256 tag.
+pub const HMAC_TAG_LEN: usize = 16;
+
+/// A check value over an epoch's header and payload, written after the
+/// payload.
+pub trait IntegrityScheme: Send + Sync {
+    /// Id carried in the INTEGRITY flag bits, 0-7. 0 is CRC-8, 1 CRC-16
+    /// and 2 HMAC-SHA256; the rest are free for private schemes.
+    fn id(&self) -> u8;
+
+    /// Bytes the check value takes.
+    fn tag_len(&self) -> usize;
+
+    /// Check value over `data`.
+    fn tag(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Whether `tag` is the check value of `data`.
+    fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        self.tag(data) == tag
+    }
+}
+
+/// CRC-8/CCITT, the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc8;
+
+impl IntegrityScheme for Crc8 {
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn tag_len(&self) -> usize {
+        1
+    }
+
+    fn tag(&self, data: &[u8]) -> Vec<u8> {
+        vec![crc8(data)]
+    }
+}
+
+/// CRC-16/CCITT-FALSE, big-endian.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc16;
+
+impl IntegrityScheme for Crc16 {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn tag_len(&self) -> usize {
+        2
+    }
+
+    fn tag(&self, data: &[u8]) -> Vec<u8> {
+        crc16(data).to_be_bytes().to_vec()
+    }
+}
+
+/// HMAC-SHA256 with a shared key, truncated to [`HMAC_TAG_LEN`] bytes.
+#[cfg(feature = "sha256")]
+#[derive(Clone)]
+pub struct HmacSha256 {
+    /// The key padded, or hashed and padded, to the SHA-256 block size.
+    block: [u8; 64],
+}
+
+#[cfg(feature = "sha256")]
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; 64];
+        if key.len() > block.len() {
+            block[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        Self { block }
+    }
+
+    /// The full 32-byte HMAC of `data`.
+    pub fn mac(&self, data: &[u8]) -> [u8; 32] {
+        let inner = Sha256::new().chain_update(self.block.map(|b| b ^ 0x36)).chain_update(data).finalize();
+        Sha256::new().chain_update(self.block.map(|b| b ^ 0x5C)).chain_update(inner).finalize().into()
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl IntegrityScheme for HmacSha256 {
+    fn id(&self) -> u8 {
+        2
+    }
+
+    fn tag_len(&self) -> usize {
+        HMAC_TAG_LEN
+    }
+
+    fn tag(&self, data: &[u8]) -> Vec<u8> {
+        self.mac(data)[..HMAC_TAG_LEN].to_vec()
+    }
+
+    /// Compares in constant time, so the timing leaks nothing about the tag.
+    fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        tag.len() == HMAC_TAG_LEN && self.tag(data).iter().zip(tag).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl std::fmt::Debug for HmacSha256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HmacSha256 { .. }")
+    }
+}
+
+/// Scheme id named by an extended header's flags.
+pub fn scheme_id(flags: u8) -> u8 {
+    (flags & INTEGRITY) >> INTEGRITY.trailing_zeros()
+}
+
+/// The built-in scheme with `id` that needs no key.
+pub fn unkeyed(id: u8) -> Option<&'static dyn IntegrityScheme> {
+    match id {
+        0 => Some(&Crc8),
+        1 => Some(&Crc16),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "sha256"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let hmac = HmacSha256::new(b"Jefe");
+        let mac = hmac.mac(b"what do ya want for nothing?");
+        assert_eq!(hex(&mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        // Case 6: a key longer than the block is hashed first
+        let mac = HmacSha256::new(&[0xAA; 131]).mac(b"Test Using Larger Than Block-Size Key - Hash Key First");
+        assert_eq!(hex(&mac), "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+
+        let tag = hmac.tag(b"epoch");
+        assert!(hmac.verify(b"epoch", &tag));
+        assert!(!hmac.verify(b"epocH", &tag));
+        assert!(!HmacSha256::new(b"other").verify(b"epoch", &tag));
+        assert!(!hmac.verify(b"epoch", &tag[..8]));
+    }
+}
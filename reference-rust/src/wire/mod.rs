@@ -1,11 +1,45 @@
 pub mod crc8;
+pub mod crc16;
+pub mod crc32;
+pub mod integrity;
 pub mod varint;
 pub mod float16;
 pub mod byte_writer;
 pub mod byte_reader;
 
 pub use crc8::crc8;
+pub use crc16::crc16;
+pub use crc32::crc32;
+pub use integrity::IntegrityScheme;
 pub use varint::{encode_varint, decode_varint};
-pub use float16::{encode_float16, decode_float16};
+pub use float16::{
+    encode_float16, encode_float16_checked, decode_float16, encode_f16_slice, decode_f16_slice, Float16Overflow,
+    FLOAT16_MAX,
+};
 pub use byte_writer::ByteWriter;
 pub use byte_reader::ByteReader;
+
+/// VERSION_TAG from which an utterance's strings (TYPE_STRING, LABEL,
+/// COMMENT) carry a varint length prefix instead of a u16, lifting the
+/// 65535-byte limit. Utterances without the tag, or with an older one,
+/// keep the u16 prefix.
+pub const LONG_STRINGS_VERSION: (u16, u16) = (1, 2);
+
+/// Set in the length word of an epoch header to mark the extended format:
+/// `seq:u16 | 0x8000|len:u16 | flags:u8 | [timestamp:i64] | payload | check`,
+/// the check being a CRC-8 unless the flags name another integrity scheme.
+/// Payloads are at most 8192 bytes, so plain headers never have it set.
+pub const EXTENDED_EPOCH: u16 = 0x8000;
+
+/// Flag bits of an extended epoch header.
+pub mod epoch_flags {
+    pub const COMPRESSED: u8 = 0x01;
+    pub const FEC: u8 = 0x02;
+    pub const ENCRYPTED: u8 = 0x04;
+    pub const PRIORITY: u8 = 0x08;
+    /// Id of the [`IntegrityScheme`](super::IntegrityScheme) closing the
+    /// epoch; 0 for CRC-8.
+    pub const INTEGRITY: u8 = 0x70;
+    /// A 64-bit timestamp in microseconds follows the flags byte.
+    pub const TIMESTAMP: u8 = 0x80;
+}
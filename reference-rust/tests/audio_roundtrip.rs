@@ -1,10 +1,10 @@
 #![cfg(feature = "audio-core")]
 
 use aill::audio::{
-    AcousticDecoder, AcousticEncoder,
+    AcousticDecoder, AcousticEncoder, AcousticProfile, LatencyProfile,
     constants::*,
 };
-use aill::{AILLEncoder, EpochBuilder};
+use aill::{AILLEncoder, AILLError, AgentId, EpochBuilder};
 
 /// Helper: encode wire bytes → PCM → decode back to wire bytes.
 fn roundtrip(wire_bytes: &[u8]) -> Vec<u8> {
@@ -69,6 +69,17 @@ fn test_44100hz_sample_rate() {
     );
 }
 
+#[test]
+fn test_shaped_audio_roundtrip() {
+    let original: Vec<u8> = (0..=255).step_by(7).collect();
+    for sample_rate in [DEFAULT_SAMPLE_RATE, 44100] {
+        let encoder = AcousticEncoder::with_sample_rate(sample_rate).unwrap().with_peak_level(0.95);
+        let audio = encoder.encode(&original).unwrap();
+        let decoder = AcousticDecoder::with_sample_rate(sample_rate).unwrap();
+        assert_eq!(decoder.decode(&audio.samples).unwrap(), original, "at {} Hz", sample_rate);
+    }
+}
+
 #[test]
 fn test_duration_formula() {
     let encoder = AcousticEncoder::new();
@@ -106,12 +117,170 @@ fn test_epoch_wrapped_roundtrip() {
     );
 }
 
+#[test]
+fn test_address_filter_skips_other_agents() {
+    let (me, other) = (AgentId::new([0xA; 16]), AgentId::new([0xB; 16]));
+    let mut enc = AILLEncoder::new();
+    enc.start_utterance_with(1.0, 3, None, Some(other), None)
+        .assert_()
+        .string("not for you");
+    let wire = enc.end_utterance();
+    let audio = AcousticEncoder::new().encode(&wire).unwrap();
+
+    let filtered = AcousticDecoder::new().with_address(me);
+    assert_eq!(filtered.decode(&audio.samples), Err(AILLError::NotAddressed));
+    let sniffer = AcousticDecoder::new().with_address(me).with_promiscuous(true);
+    assert_eq!(sniffer.decode(&audio.samples).unwrap(), wire);
+    let recipient = AcousticDecoder::new().with_address(other);
+    assert_eq!(recipient.decode(&audio.samples).unwrap(), wire);
+}
+
+#[test]
+fn test_learned_noise_rejects_hum_on_a_carrier() {
+    // A steady hum on one lo-band carrier, as from a fan
+    let hum = |n: usize| -> Vec<f32> {
+        let step = 2.0 * std::f32::consts::PI * CARRIER_FREQS[1] / DEFAULT_SAMPLE_RATE as f32;
+        (0..n).map(|i| 0.15 * (step * i as f32).sin()).collect()
+    };
+    let original = vec![0x42, 0x13, 0xAB, 0x80, 0x01];
+    let mut samples = AcousticEncoder::new().encode(&original).unwrap().samples;
+    let noise = hum(samples.len());
+    for (s, h) in samples.iter_mut().zip(noise) {
+        *s += h;
+    }
+    assert_ne!(AcousticDecoder::new().decode(&samples).ok(), Some(original.clone()));
+
+    let mut decoder = AcousticDecoder::new();
+    decoder.learn_noise(&hum(DEFAULT_SAMPLE_RATE as usize)).unwrap();
+    assert_eq!(decoder.decode(&samples).unwrap(), original);
+}
+
+#[test]
+fn test_decode_all_finds_each_message_in_a_capture() {
+    let messages = [vec![0x42, 0x13, 0x01], vec![0xFF, 0x0F, 0xA5, 0x5A], vec![0x00, 0x90, 0x01]];
+    let gap = vec![0.0f32; (0.3 * DEFAULT_SAMPLE_RATE as f32) as usize];
+    let mut capture = gap.clone();
+    for (i, msg) in messages.iter().enumerate() {
+        let audio = AcousticEncoder::new().encode(msg).unwrap();
+        let gain = if i == 1 { 0.5 } else { 1.0 };
+        capture.extend(audio.samples.iter().map(|s| s * gain));
+        capture.extend(&gap);
+    }
+
+    let results = AcousticDecoder::new().decode_all(&capture);
+    assert_eq!(results.len(), messages.len(), "{:?}", results);
+    for (result, msg) in results.iter().zip(&messages) {
+        assert_eq!(result.bytes.as_ref().unwrap(), msg);
+        assert!(result.start < result.end && result.quality > 1.0);
+    }
+    assert!(results.windows(2).all(|w| w[0].end <= w[1].start));
+}
+
+#[test]
+fn test_interference_in_one_slot_is_an_erasure() {
+    let original = vec![0xFF, 0x0F, 0xA5, 0x5A];
+    let mut samples = AcousticEncoder::new().encode(&original).unwrap().samples;
+    let clean = AcousticDecoder::new().decode_detailed(&samples).unwrap();
+    assert!(clean.erasures.is_empty(), "{:?}", clean.confidence);
+
+    // A lo-band tone over the hi nibble of byte 1, as from chirp leakage
+    let sr = DEFAULT_SAMPLE_RATE as f32;
+    let slot = ((SYNC_DURATION + 2.0 * FRAME_TIME) * sr) as usize;
+    let step = 2.0 * std::f32::consts::PI * CARRIER_FREQS[1] / sr;
+    for i in 0..(SYMBOL_DURATION * sr) as usize {
+        samples[slot + i] += 0.12 * (step * i as f32).sin();
+    }
+    let noisy = AcousticDecoder::new().decode_detailed(&samples).unwrap();
+    assert_eq!(noisy.bytes.unwrap(), original);
+    assert_eq!(noisy.erasures, vec![1]);
+}
+
+#[test]
+fn test_station_id_tail_names_the_sender() {
+    let alice: AgentId = "6ba7b810-9dad-11d1-80b4-00c04fd430c8".parse().unwrap();
+    let bob = AgentId::new([0xA5; 16]);
+    let original = vec![0x42, 0x13, 0xAB, 0x01];
+    let tailed = |id| {
+        let profile = AcousticProfile { station_id: Some(id) };
+        AcousticEncoder::new().with_profile(profile).encode(&original).unwrap()
+    };
+    let audio = tailed(alice);
+    let plain = AcousticEncoder::new().encode(&original).unwrap();
+    let tail = STATION_GAP + STATION_ID_BYTES as f32 * STATION_FRAME_TIME;
+    assert!((audio.duration - plain.duration - tail).abs() < 1e-5);
+
+    let decoder = AcousticDecoder::new();
+    assert_eq!(decoder.station_id(&audio.samples).unwrap(), alice);
+    let result = decoder.decode_detailed(&audio.samples).unwrap();
+    assert_eq!((result.bytes.unwrap(), result.station), (original.clone(), Some(alice)));
+    assert_eq!(decoder.decode_detailed(&plain.samples).unwrap().station, None);
+    assert!(decoder.station_id(&plain.samples).is_err());
+
+    // Transmissions back to back are told apart by their tails
+    let mut capture = audio.samples.clone();
+    capture.extend(vec![0.0; 4800]);
+    capture.extend(tailed(bob).samples);
+    let results = decoder.decode_all(&capture);
+    let stations: Vec<_> = results.iter().map(|r| r.station).collect();
+    assert_eq!(stations, vec![Some(alice), Some(bob)]);
+    assert!(results.iter().all(|r| r.bytes.as_ref() == Ok(&original)));
+
+    // A tail that fails its checksum names nobody
+    let sr = DEFAULT_SAMPLE_RATE as f32;
+    let third = plain.samples.len() + ((STATION_GAP + 2.0 * STATION_FRAME_TIME) * sr) as usize;
+    let mut damaged = audio.samples.clone();
+    damaged[third..third + (STATION_FRAME_TIME * sr) as usize].fill(0.0);
+    assert!(decoder.station_id(&damaged).is_err());
+}
+
+/// A small speaker: second-order high-pass at 2 kHz that leaves the
+/// lowest carrier under a quarter of the level of the highest.
+fn tinny_speaker(samples: &[f32]) -> Vec<f32> {
+    let w = 2.0 * std::f32::consts::PI * 2000.0 / DEFAULT_SAMPLE_RATE as f32;
+    let alpha = w.sin() / std::f32::consts::SQRT_2;
+    let a0 = 1.0 + alpha;
+    let b = [(1.0 + w.cos()) / 2.0 / a0, -(1.0 + w.cos()) / a0, (1.0 + w.cos()) / 2.0 / a0];
+    let a = [-2.0 * w.cos() / a0, (1.0 - alpha) / a0];
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    samples
+        .iter()
+        .map(|&x| {
+            let y = b[0] * x + b[1] * x1 + b[2] * x2 - a[0] * y1 - a[1] * y2;
+            (x2, x1, y2, y1) = (x1, x, y1, y);
+            y
+        })
+        .collect()
+}
+
+#[test]
+fn test_calibrated_response_compensation() {
+    let chirp = AcousticEncoder::new().sync_chirp();
+    let mut recording = vec![0.0; DEFAULT_SAMPLE_RATE as usize / 2];
+    recording.extend(tinny_speaker(&chirp));
+    recording.resize(DEFAULT_SAMPLE_RATE as usize, 0.0);
+    let profile = LatencyProfile::measure(&chirp, &recording, DEFAULT_SAMPLE_RATE, 0.5, 1.0).unwrap();
+    let r = profile.carrier_response;
+    assert!(r[0] < 0.25 * r[7], "{:?}", r);
+
+    let original: Vec<u8> = (0..=255).step_by(5).collect();
+    let audio = AcousticEncoder::new().encode(&original).unwrap();
+    let played = tinny_speaker(&audio.samples);
+    assert_ne!(AcousticDecoder::new().decode(&played).ok(), Some(original.clone()));
+
+    // Either end can make up for the speaker
+    let decoder = AcousticDecoder::new().with_carrier_weights(profile.carrier_weights());
+    assert_eq!(decoder.decode(&played).unwrap(), original);
+    let encoder = AcousticEncoder::new().with_carrier_gains(profile.carrier_gains());
+    let compensated = tinny_speaker(&encoder.encode(&original).unwrap().samples);
+    assert_eq!(AcousticDecoder::new().decode(&compensated).unwrap(), original);
+}
+
 // WAV tests require the full `audio` feature (hound dependency)
 #[cfg(feature = "audio")]
 mod wav_tests {
     use aill::audio::{
-        AcousticDecoder, AcousticEncoder,
-        read_wav, write_wav,
+        AcousticDecoder, AcousticEncoder, WavMetadata,
+        read_wav, write_wav_with_metadata,
     };
 
     #[test]
@@ -122,8 +291,9 @@ mod wav_tests {
         let encoder = AcousticEncoder::new();
         let audio = encoder.encode(&original).unwrap();
 
-        write_wav(path, &audio.samples, audio.sample_rate).unwrap();
-        let (samples, sr) = read_wav(path).unwrap();
+        let metadata = WavMetadata::for_payload(&original);
+        write_wav_with_metadata(path, &audio.samples, audio.sample_rate, &metadata).unwrap();
+        let (samples, sr, read_metadata) = read_wav(path).unwrap();
         assert_eq!(sr, audio.sample_rate);
 
         let decoder = AcousticDecoder::new();
@@ -133,7 +303,27 @@ mod wav_tests {
             "WAV round-trip failed:\n  original:  {:02X?}\n  recovered: {:02X?}",
             original, recovered
         );
+        assert!(read_metadata.unwrap().verify(&recovered));
+
+        std::fs::remove_file(path).ok();
+    }
+}
+
+// Compressed and resampled captures require the `audio-formats` feature
+#[cfg(feature = "audio-formats")]
+mod file_tests {
+    use aill::audio::{write_wav, AcousticDecoder, AcousticEncoder};
+
+    #[test]
+    fn test_decode_file_resamples_to_decoder_rate() {
+        let path = "/tmp/aill_audio_file_44100_test.wav";
+        let original = vec![0x42, 0x13, 0xAB, 0xFF, 0x01];
+        let audio = AcousticEncoder::with_sample_rate(44_100).unwrap().encode(&original).unwrap();
+        write_wav(path, &audio.samples, audio.sample_rate).unwrap();
 
+        let results = AcousticDecoder::new().decode_file(path).unwrap();
         std::fs::remove_file(path).ok();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].bytes.as_ref().unwrap(), &original);
     }
 }
@@ -0,0 +1,89 @@
+//! Runs two `aill-bridge` gateways against each other without audio
+//! devices or sockets: one hears an agent through the acoustic modem, the
+//! other plays what arrives over a lossy in-memory network.
+#![cfg(feature = "audio-core")]
+
+#[path = "../src/bin/aill-bridge/gateway.rs"]
+mod gateway;
+
+use aill::audio::{AcousticDecoder, AcousticEncoder};
+use aill::testing::{LinkConditions, LoopbackTransport};
+use aill::{AILLEncoder, AgentId};
+
+use gateway::{Gateway, RETRANSMIT_TIMEOUT_US};
+
+/// Simulated time between steps.
+const STEP_US: i64 = RETRANSMIT_TIMEOUT_US / 4;
+
+fn utterance(n: u32) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().source_agent(AgentId::new([7; 16]));
+    e.uint32(n);
+    e.end_utterance()
+}
+
+/// What `frame` sounds like after the modem: encoded, played, decoded.
+fn over_the_air(frame: &[u8]) -> Vec<u8> {
+    let audio = AcousticEncoder::new().encode(frame).unwrap();
+    AcousticDecoder::new().decode(&audio.samples).unwrap()
+}
+
+/// Exchange datagrams between the gateways until both are idle. Returns
+/// the utterances each played, in order.
+fn run(bridges: &mut [Gateway; 2], net: &[LoopbackTransport; 2]) -> [Vec<Vec<u8>>; 2] {
+    let mut played = [Vec::new(), Vec::new()];
+    let mut now = 0;
+    for _ in 0..200 {
+        now += STEP_US;
+        for side in 0..2 {
+            for datagram in net[side].recv(now) {
+                bridges[side].received(&datagram, now).unwrap();
+            }
+            for datagram in bridges[side].poll_net(now).unwrap() {
+                net[side].send(&datagram, now);
+            }
+            played[side].extend(bridges[side].poll_air());
+        }
+        if bridges.iter().all(Gateway::is_idle) && net.iter().all(|t| t.in_transit() == 0) {
+            return played;
+        }
+    }
+    panic!("bridges did not settle");
+}
+
+#[test]
+fn utterances_cross_the_network_both_ways() {
+    let conditions = LinkConditions::new().with_loss_percent(30).with_latency_us(STEP_US).with_seed(7);
+    let (a, b) = LoopbackTransport::pair(conditions);
+    let net = [a, b];
+    let mut bridges = [Gateway::new(), Gateway::new()];
+
+    let sent: Vec<Vec<u8>> = (0..5).map(utterance).collect();
+    for (i, frame) in sent.iter().enumerate() {
+        bridges[i % 2].heard(&over_the_air(frame), 0).unwrap();
+    }
+    let played = run(&mut bridges, &net);
+
+    let expected = |side: usize| -> Vec<Vec<u8>> { sent.iter().skip(1 - side).step_by(2).cloned().collect() };
+    assert_eq!(played[1], expected(1));
+    assert_eq!(played[0], expected(0));
+    for frame in played.iter().flatten() {
+        assert_eq!(&over_the_air(frame), frame);
+    }
+    assert!(bridges[0].link_stats().0.retransmits + bridges[1].link_stats().0.retransmits > 0);
+}
+
+#[test]
+fn misheard_frames_stay_off_the_network() {
+    let mut bridge = Gateway::new();
+    let whole = utterance(1);
+    assert!(bridge.heard(&whole[..whole.len() - 1], 0).is_err());
+    assert!(bridge.heard(&[0xEE, 0x42], 0).is_err());
+    assert_eq!(bridge.rejected(), 2);
+    assert!(bridge.poll_net(0).unwrap().is_empty());
+
+    let mut two = utterance(2);
+    two.extend(utterance(3));
+    bridge.heard(&two, 0).unwrap();
+    assert_eq!(bridge.poll_net(0).unwrap().len(), 1);
+}
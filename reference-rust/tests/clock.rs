@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use aill::clock::{MockClock, TimeSource};
+use aill::correlator::Correlator;
+use aill::interval::DeadlineScheduler;
+use aill::reliability::{ReliableReceiver, ReliableSender};
+use aill::*;
+
+/// Epochs the simulated link loses, by transmission count.
+fn lossy(n: usize) -> bool {
+    n % 3 == 1
+}
+
+#[test]
+fn retransmissions_over_a_lossy_link_run_on_simulated_time() {
+    let clock = MockClock::new(1_700_000_000_000_000);
+    let start = clock.now_us();
+    let mut tx = ReliableSender::new(2_000_000).with_max_retries(10);
+    let mut rx = ReliableReceiver::new();
+    let mut epochs = EpochBuilder::new();
+    for i in 0..6u8 {
+        epochs.write(&[i; 4]);
+        epochs.flush();
+    }
+
+    let mut queue: Vec<Vec<u8>> = Vec::new();
+    for epoch in epochs.get_epochs() {
+        queue.extend(tx.send(epoch, clock.now_us()).unwrap());
+    }
+    let (mut sent, mut delivered) = (0, Vec::new());
+    // A minute of link time, in 100 ms steps
+    for _ in 0..600 {
+        for epoch in std::mem::take(&mut queue) {
+            sent += 1;
+            if lossy(sent) {
+                continue;
+            }
+            clock.advance(150_000);
+            let delivery = rx.receive(&epoch, clock.now_us()).unwrap();
+            delivered.extend(delivery.payload);
+            queue.extend(tx.handle_control(&delivery.reply, clock.now_us()).unwrap());
+        }
+        clock.advance_by(Duration::from_millis(100));
+        queue.extend(tx.poll(clock.now_us()));
+        if tx.in_flight() == 0 {
+            break;
+        }
+    }
+    assert_eq!(tx.in_flight(), 0);
+    assert_eq!(delivered.len(), 6);
+    assert!(tx.stats().retransmits >= 2, "{:?}", tx.stats());
+    assert!(clock.now_us() - start >= 2_000_000);
+}
+
+#[test]
+fn timeouts_and_ttls_follow_the_injected_clock() {
+    let clock = MockClock::new(0);
+    let shared = clock.clone();
+
+    let mut corr = Correlator::new();
+    let id = corr.register(shared.now_us(), 500_000);
+    clock.advance(499_999);
+    assert!(corr.poll_timeouts(shared.now_us()).is_empty());
+    clock.advance(1);
+    assert_eq!(corr.poll_timeouts(shared.now_us()), vec![id]);
+
+    clock.advance_by(Duration::from_secs(11));
+
+    let mut deadlines = DeadlineScheduler::new();
+    deadlines.push("stop", 3, Some(shared.now_us() + 1_000));
+    deadlines.push("report", 3, Some(shared.now_us() + 5_000));
+    clock.advance(2_000);
+    assert_eq!(deadlines.expire(shared.now_us()), vec!["stop"]);
+    assert_eq!(timestamp::to_micros(shared.now()), 11_000_000 + 500_000 + 2_000);
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn dedup_ttls_follow_the_injected_clock() {
+    use aill::dedup::{DedupCache, DedupKey};
+
+    let clock = MockClock::new(0);
+    let mut seen = DedupCache::new(8).with_ttl(10_000_000);
+    assert!(seen.insert(DedupKey::MsgId(1), clock.now_us()));
+    clock.advance_by(Duration::from_secs(9));
+    assert!(!seen.insert(DedupKey::MsgId(1), clock.now_us()));
+    clock.advance_by(Duration::from_secs(2));
+    assert!(seen.insert(DedupKey::MsgId(1), clock.now_us()));
+}
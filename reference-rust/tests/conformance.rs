@@ -335,7 +335,7 @@ fn tg_ex_006_l1_domain_ref() {
     let utt = AILLDecoder::new().decode_utterance(&wire).unwrap();
     let ref_node = inner_expression(body_expr(&utt, 0));
     match ref_node {
-        AstNode::DomainRef { level, domain_code } => {
+        AstNode::DomainRef { level, domain_code, .. } => {
             assert_eq!(*level, 1);
             assert_eq!(*domain_code, 0x0090);
         }
@@ -364,12 +364,12 @@ fn tg_mt_001_confidence_priority_timestamp() {
 fn tg_mt_002_dest_agent_seqnum() {
     let dest: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
     let mut e = AILLEncoder::new();
-    e.start_utterance_with(1.0, 3, Some(0), Some(&dest), Some(42));
+    e.start_utterance_with(1.0, 3, Some(0), Some(dest.into()), Some(42));
     e.assert_().null();
     let wire = e.end_utterance();
     let utt = AILLDecoder::new().decode_utterance(&wire).unwrap();
     let m = get_meta(&utt);
-    assert_eq!(m.dest_agent.as_deref(), Some(dest.as_slice()));
+    assert_eq!(m.dest_agent.map(|id| *id.as_bytes()), Some(dest));
     assert_eq!(m.seqnum, Some(42));
 }
 
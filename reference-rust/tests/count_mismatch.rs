@@ -0,0 +1,122 @@
+use aill::*;
+
+/// A list declaring `declared` elements followed by `actual` UINT8s.
+fn list(declared: u16, actual: u8) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().begin_list(declared);
+    for i in 0..actual {
+        e.uint8(i);
+    }
+    e.end_list().end_utterance()
+}
+
+#[test]
+fn decoder_reports_count_mismatch() {
+    let decoder = AILLDecoder::new();
+    assert!(decoder.decode_utterance(&list(3, 3)).is_ok());
+    assert_eq!(decoder.decode_utterance(&list(3, 2)), Err(AILLError::CountMismatch { declared: 3, actual: 2 }));
+    assert_eq!(decoder.decode_utterance(&list(1, 3)), Err(AILLError::CountMismatch { declared: 1, actual: 3 }));
+
+    let mut e = AILLEncoder::new();
+    e.start_utterance().begin_map(1).string("a").uint8(1).string("b").uint8(2).end_map();
+    let wire = e.end_utterance();
+    assert_eq!(decoder.decode_utterance(&wire), Err(AILLError::CountMismatch { declared: 1, actual: 2 }));
+
+    // Without an END_LIST the count alone delimits the list
+    let mut e = AILLEncoder::new();
+    e.start_utterance().begin_list(1).uint8(1).uint8(2);
+    assert!(decoder.decode_utterance(&e.end_utterance()).is_ok());
+}
+
+#[test]
+fn event_stream_reports_count_mismatch() {
+    let decoder = AILLDecoder::new();
+    let last = |wire: &[u8]| decoder.decode_events(wire).last().unwrap();
+    assert_eq!(last(&list(2, 2)), Ok(WireEvent::EndUtterance));
+    assert_eq!(last(&list(2, 1)), Err(AILLError::CountMismatch { declared: 2, actual: 1 }));
+    assert_eq!(last(&list(0, 2)), Err(AILLError::CountMismatch { declared: 0, actual: 2 }));
+}
+
+// Regressions from fuzzing: counts far beyond the elements present.
+
+#[test]
+fn many_unclosed_siblings_decode_in_linear_time() {
+    // Each list's count is used up before its next sibling, so each one
+    // looks ahead for an END_LIST that never comes
+    let mut e = AILLEncoder::new();
+    e.start_utterance();
+    for _ in 0..20_000 {
+        e.begin_list(1).uint8(1);
+    }
+    let wire = e.end_utterance();
+    let started = std::time::Instant::now();
+    let AstNode::Utterance { body, .. } = AILLDecoder::new().decode_utterance(&wire).unwrap() else {
+        panic!("not an utterance");
+    };
+    assert_eq!(body.len(), 20_000);
+    assert_eq!(AILLDecoder::new().decode_events(&wire).last().unwrap(), Ok(WireEvent::EndUtterance));
+    assert!(started.elapsed().as_secs() < 5, "took {:?}", started.elapsed());
+
+    // A closer after many siblings still counts them against the first list
+    let mut e = AILLEncoder::new();
+    e.start_utterance().begin_list(1).uint8(1);
+    for _ in 0..20_000 {
+        e.begin_list(1).uint8(1);
+    }
+    e.end_list().end_list();
+    let wire = e.end_utterance();
+    assert_eq!(AILLDecoder::new().decode_utterance(&wire), Err(AILLError::CountMismatch { declared: 1, actual: 20_001 }));
+}
+
+#[test]
+fn oversized_count_stops_at_end_of_utterance() {
+    let decoder = AILLDecoder::new();
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().begin_list(65535).uint8(1).uint8(2).uint8(3);
+    let wire = e.end_utterance();
+    let mismatch = AILLError::CountMismatch { declared: 65535, actual: 3 };
+    assert_eq!(decoder.decode_utterance(&wire).unwrap_err(), mismatch);
+    assert_eq!(decoder.decode_events(&wire).last().unwrap().unwrap_err(), mismatch);
+
+    // A map cut off between a key and its value
+    let mut e = AILLEncoder::new();
+    e.start_utterance().begin_map(65535).string("a").uint8(1).string("b");
+    let wire = e.end_utterance();
+    let mismatch = AILLError::CountMismatch { declared: 65535, actual: 1 };
+    assert_eq!(decoder.decode_utterance(&wire).unwrap_err(), mismatch);
+    assert_eq!(decoder.decode_events(&wire).last().unwrap().unwrap_err(), mismatch);
+}
+
+#[test]
+fn oversized_count_stops_at_end_of_input() {
+    // No END_UTTERANCE either, so the input ends inside the list
+    let mut wire = list(3, 2);
+    wire.truncate(wire.len() - 2);
+    let mismatch = AILLError::CountMismatch { declared: 3, actual: 2 };
+    assert_eq!(AILLDecoder::new().decode_utterance(&wire).unwrap_err(), mismatch);
+    assert_eq!(AILLDecoder::new().decode_events(&wire).last().unwrap().unwrap_err(), mismatch);
+}
+
+#[test]
+fn oversized_count_stops_at_enclosing_closer() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().begin_struct().field(1).begin_list(40_000).uint8(1).end_struct().uint8(2);
+    let wire = e.end_utterance();
+    let mismatch = AILLError::CountMismatch { declared: 40_000, actual: 1 };
+    assert_eq!(AILLDecoder::new().decode_utterance(&wire).unwrap_err(), mismatch);
+    assert_eq!(AILLDecoder::new().decode_events(&wire).last().unwrap().unwrap_err(), mismatch);
+}
+
+#[test]
+fn chained_containers_decode_in_linear_time() {
+    // Each list's count runs out before the next one starts, so every
+    // close looks ahead over all the lists that follow.
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_();
+    for _ in 0..64 {
+        e.begin_list(1).uint8(0).begin_map(1).uint8(0).uint8(0);
+    }
+    let wire = e.end_utterance();
+    assert!(AILLDecoder::new().decode_utterance(&wire).is_ok());
+    assert_eq!(AILLDecoder::new().decode_events(&wire).last().unwrap(), Ok(WireEvent::EndUtterance));
+}
@@ -0,0 +1,34 @@
+use aill::*;
+
+fn utterance(value: u8) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().uint8(value);
+    e.end_utterance()
+}
+
+#[test]
+fn back_to_back_utterances_decode_with_ranges() {
+    let (a, b) = (utterance(1), utterance(2));
+    let capture = [a.as_slice(), b.as_slice()].concat();
+    let decoder = AILLDecoder::new();
+
+    let all = decoder.decode_all(&capture).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].1, 0..a.len());
+    assert_eq!(all[1].1, a.len()..capture.len());
+    assert_eq!(all[1].0, decoder.decode_utterance(&b).unwrap());
+    assert!(decoder.decode_all(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn iterator_stops_after_the_first_error() {
+    let mut capture = utterance(1);
+    capture.push(0x42);
+    capture.extend(utterance(2));
+
+    let results: Vec<_> = AILLDecoder::new().utterances(&capture).collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(AILLDecoder::new().decode_all(&capture).is_err());
+}
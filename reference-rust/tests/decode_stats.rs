@@ -0,0 +1,51 @@
+#![cfg(feature = "decode-stats")]
+
+use aill::decoder::NodeKind;
+use aill::profile::{CountingAllocator, DecodeStats};
+use aill::testing::generate_utterance;
+use aill::*;
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator::new();
+
+#[test]
+fn counts_nodes_depth_and_heap() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().begin_struct();
+    e.field(1).list_of_float32(&[1.0; 100]);
+    e.field(2).string("label");
+    e.end_struct();
+    let wire = e.end_utterance();
+
+    let (ast, stats) = AILLDecoder::new().decode_with_stats(&wire);
+    assert!(ast.is_ok());
+    assert_eq!(stats.bytes, wire.len());
+    assert_eq!(stats.nodes, 105);
+    assert_eq!(stats.nodes_by_kind[&NodeKind::Literal], 101);
+    assert_eq!(stats.max_depth, 4);
+    let heap = stats.heap.unwrap();
+    assert!(heap.allocations > 0);
+    assert!(heap.retained_bytes > 100 * std::mem::size_of::<AstNode>(), "{:?}", heap);
+    assert!(heap.peak_bytes >= heap.retained_bytes);
+
+    // Dropping the AST gives its memory back
+    drop(ast);
+    let (_, again) = AILLDecoder::new().decode_with_stats(&wire);
+    assert_eq!(again.heap.unwrap().retained_bytes, heap.retained_bytes);
+}
+
+#[test]
+fn stats_merge_across_a_corpus() {
+    let mut total = DecodeStats::default();
+    let mut deepest = 0;
+    for seed in 0..50 {
+        let (ast, stats) = AILLDecoder::new().decode_with_stats(&generate_utterance(seed, 4));
+        assert!(ast.is_ok());
+        deepest = deepest.max(stats.max_depth);
+        total.merge(&stats);
+    }
+    assert_eq!(total.max_depth, deepest);
+    assert_eq!(total.nodes, total.nodes_by_kind.values().sum::<usize>());
+    assert!(total.nodes_by_kind[&NodeKind::Utterance] == 50);
+    assert!(total.heap.unwrap().peak_bytes > 0);
+}
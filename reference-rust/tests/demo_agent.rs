@@ -0,0 +1,96 @@
+//! Runs the `aill-demo` agents against each other without audio devices:
+//! frames go through the acoustic modem into a simulated capture, or
+//! straight across.
+#![cfg(feature = "audio")]
+
+#[path = "../src/bin/aill-demo/agent.rs"]
+mod agent;
+
+use aill::audio::constants::DEFAULT_SAMPLE_RATE;
+use aill::audio::{AcousticDecoder, AcousticEncoder};
+use aill::AgentId;
+
+use agent::{DemoAgent, Outcome, SilencePeriod, RETRANSMIT_TIMEOUT_US};
+
+/// Simulated time between turns.
+const TURN_US: i64 = RETRANSMIT_TIMEOUT_US / 3;
+
+/// Alternate turns until both agents have an outcome and nothing left to
+/// send. `link` carries one turn's frames across.
+fn run(agents: &mut [DemoAgent; 2], mut link: impl FnMut(Vec<Vec<u8>>) -> Vec<Vec<u8>>) {
+    let mut now = 0;
+    for _ in 0..60 {
+        for from in 0..2 {
+            let frames = agents[from].poll(now).unwrap();
+            for frame in link(frames) {
+                agents[1 - from].receive(&frame, now).unwrap();
+            }
+        }
+        if agents.iter().all(|a| a.outcome().is_some() && a.is_idle()) {
+            return;
+        }
+        now += TURN_US;
+    }
+    panic!("negotiation did not finish: {:?}", agents.each_ref().map(|a| a.outcome()));
+}
+
+/// Sends each frame through the acoustic modem, with silence either side.
+fn acoustic(frames: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let gap = vec![0.0f32; (0.2 * DEFAULT_SAMPLE_RATE as f32) as usize];
+    let decoder = AcousticDecoder::new();
+    frames
+        .iter()
+        .filter_map(|frame| {
+            let mut capture = gap.clone();
+            capture.extend(AcousticEncoder::new().encode(frame).unwrap().samples);
+            capture.extend(&gap);
+            decoder.decode(&capture).ok()
+        })
+        .collect()
+}
+
+fn agents(proposer: (u32, u32), responder: (u32, u32)) -> [DemoAgent; 2] {
+    [
+        DemoAgent::new(AgentId::new([1; 16]), proposer.0, proposer.1),
+        DemoAgent::new(AgentId::new([2; 16]), responder.0, responder.1),
+    ]
+}
+
+#[test]
+fn test_counter_proposal_agreed_over_audio_despite_lost_frame() {
+    let mut agents = agents((100, 2_000), (200, 1_000));
+    let asked = SilencePeriod { start_us: 1_700_000_000_000_000, duration_ms: 5_000 };
+    agents[0].propose(asked, 0).unwrap();
+
+    // The first frame on the air is lost once
+    let mut dropped = false;
+    run(&mut agents, |mut frames| {
+        if !dropped && !frames.is_empty() {
+            frames.remove(0);
+            dropped = true;
+        }
+        acoustic(&frames)
+    });
+
+    let agreed = Outcome::Agreed(SilencePeriod { duration_ms: 1_000, ..asked });
+    assert_eq!(agents[0].outcome(), Some(agreed));
+    assert_eq!(agents[1].outcome(), Some(agreed));
+    assert!(agents[0].link_stats().retransmits >= 1);
+    // PROPOSE, counter-PROPOSE, ACCEPT on both sides
+    for agent in &agents {
+        let transcript = agent.transcript();
+        assert_eq!(transcript.len(), 3, "{:#?}", transcript);
+        assert!(transcript[2].contains("ACCEPT"));
+    }
+}
+
+#[test]
+fn test_disjoint_ranges_end_in_rejection() {
+    let mut agents = agents((100, 200), (500, 900));
+    agents[0].propose(SilencePeriod { start_us: 0, duration_ms: 150 }, 0).unwrap();
+    run(&mut agents, |frames| frames);
+
+    assert_eq!(agents[0].outcome(), Some(Outcome::Refused));
+    assert_eq!(agents[1].outcome(), Some(Outcome::Refused));
+    assert!(agents[0].transcript().last().unwrap().contains("REJECT"));
+}
@@ -0,0 +1,91 @@
+use aill::codebook::base::pragma;
+use aill::*;
+
+fn nested_lists(depth: usize) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance();
+    for _ in 0..depth {
+        e.begin_list(1);
+    }
+    e.uint8(7);
+    e.end_utterance()
+}
+
+#[test]
+fn adversarial_nesting_fails_without_recursing() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance();
+    let mut acts = e.end_utterance();
+    acts.pop();
+    acts.extend([pragma::ASSERT; 200_000]);
+
+    let decoder = AILLDecoder::new();
+    for wire in [nested_lists(100_000), acts] {
+        let err = decoder.decode_utterance(&wire).unwrap_err();
+        assert!(matches!(err, AILLError::TooDeep { max_depth: DEFAULT_MAX_DEPTH, .. }), "{:?}", err);
+    }
+}
+
+#[test]
+fn depth_limit_is_configurable() {
+    let wire = nested_lists(DEFAULT_MAX_DEPTH - 1);
+    assert!(AILLDecoder::new().decode_utterance(&wire).is_ok());
+
+    let strict = AILLDecoder::with_config(DecoderConfig::new().with_max_depth(4));
+    assert!(strict.decode_utterance(&nested_lists(3)).is_ok());
+    assert_eq!(strict.decode_utterance(&nested_lists(4)).unwrap_err(), AILLError::TooDeep { offset: 27, max_depth: 4 });
+    // The event decoder stops at the same depth
+    assert_eq!(strict.decode_events(&nested_lists(3)).last().unwrap(), Ok(WireEvent::EndUtterance));
+    assert_eq!(strict.decode_events(&nested_lists(4)).last().unwrap(), Err(AILLError::TooDeep { offset: 27, max_depth: 4 }));
+}
+
+#[test]
+fn event_decoding_is_bounded_by_the_depth_limit() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().temporal(aill::codebook::base::temporal::PAST).uint8(1);
+    let wrapped = e.end_utterance();
+    let decoder = AILLDecoder::with_config(DecoderConfig::new().with_max_depth(2));
+    assert_eq!(decoder.decode_events(&wrapped).last(), decoder.decode_utterance(&wrapped).err().map(Err));
+
+    let decoder = AILLDecoder::new();
+    let wire = nested_lists(200_000);
+    let mut events = decoder.decode_events(&wire);
+    let mut count = 0;
+    let err = loop {
+        match events.next().unwrap() {
+            Ok(_) => count += 1,
+            Err(e) => break e,
+        }
+        assert!(events.depth() <= DEFAULT_MAX_DEPTH + 1);
+    };
+    assert!(matches!(err, AILLError::TooDeep { max_depth: DEFAULT_MAX_DEPTH, .. }), "{:?}", err);
+    assert_eq!(count, DEFAULT_MAX_DEPTH + 1);
+    assert_eq!(err, decoder.decode_utterance(&wire).unwrap_err());
+}
+
+#[test]
+fn pretty_print_elides_nodes_beyond_the_depth_limit() {
+    let mut node = AstNode::ContextRef { sct_index: 1 };
+    for _ in 0..2 * DEFAULT_MAX_DEPTH {
+        node = AstNode::List { count: 1, elements: vec![node] };
+    }
+    let out = pretty_print(&node, 0);
+    assert_eq!(out.lines().count(), DEFAULT_MAX_DEPTH + 2);
+    assert!(out.ends_with("..."));
+    assert!(!out.contains("SCT_REF"));
+    assert!(format::Formatter::new(format::Style::Compact).format(&node).contains("(...)"));
+}
+
+#[test]
+fn formatters_elide_at_a_configured_depth() {
+    let wire = nested_lists(3);
+    let node = AILLDecoder::new().decode_utterance(&wire).unwrap();
+    let full = format::Formatter::new(format::Style::Plain).format(&node);
+    assert!(full.contains("uint8"), "{}", full);
+
+    let config = DecoderConfig::new().with_max_depth(3);
+    let shallow = format::Formatter::new(format::Style::Plain).with_max_depth(config.max_depth).format(&node);
+    assert!(shallow.ends_with("...") && !shallow.contains("uint8"), "{}", shallow);
+    let compact = format::Formatter::new(format::Style::Compact).with_max_depth(3).format(&node);
+    assert!(compact.contains("...") && !compact.contains("uint8"), "{}", compact);
+}
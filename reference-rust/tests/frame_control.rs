@@ -0,0 +1,45 @@
+use aill::*;
+use aill::codebook::base::fc;
+use aill::templates::encode_ast;
+
+fn control(code: u8, mnemonic: &str, operands: &[u32]) -> AstNode {
+    AstNode::FrameControl { code, mnemonic: mnemonic.into(), operands: operands.to_vec() }
+}
+
+#[test]
+fn frame_control_inside_a_body_decodes_with_operands() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().sync_mark(0x1_2345_6789).assert_().uint8(1);
+    e.ack_epoch(7).nack_epoch(8, 0x02).echo_request(0xCAFE).pause();
+    let wire = e.end_utterance();
+
+    let ast = AILLDecoder::new().decode_utterance(&wire).unwrap();
+    let AstNode::Utterance { body, .. } = &ast else { panic!() };
+    assert_eq!(body[0], control(fc::SYNC_MARK, "SYNC_MARK", &[0x2345_6789]));
+    assert!(matches!(body[1], AstNode::Pragmatic { .. }));
+    assert_eq!(body[2..], [
+        control(fc::ACK_EPOCH, "ACK_EPOCH", &[7]),
+        control(fc::NACK_EPOCH, "NACK_EPOCH", &[8, 0x02]),
+        control(fc::ECHO_REQUEST, "ECHO_REQUEST", &[0xCAFE]),
+        control(fc::PAUSE, "PAUSE", &[]),
+    ]);
+    assert_eq!(encode_ast(&ast).unwrap(), wire);
+    assert!(pretty_print(&ast, 0).contains("NACK_EPOCH(8, 2)"));
+
+    let events: Vec<WireEvent> = AILLDecoder::new().decode_events(&wire).collect::<Result<_, _>>().unwrap();
+    assert!(events.contains(&WireEvent::FrameControl { code: fc::ACK_EPOCH, operands: vec![7] }));
+}
+
+#[test]
+fn frame_control_operands_are_checked_on_encode() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().uint8(1);
+    let AstNode::Utterance { meta, mut body } = AILLDecoder::new().decode_utterance(&e.end_utterance()).unwrap() else {
+        panic!()
+    };
+    body.push(control(fc::ACK_EPOCH, "ACK_EPOCH", &[0x1_0000]));
+    assert!(encode_ast(&AstNode::Utterance { meta: meta.clone(), body: body.clone() }).is_err());
+    body.pop();
+    body.push(control(fc::NACK_EPOCH, "NACK_EPOCH", &[1]));
+    assert!(encode_ast(&AstNode::Utterance { meta, body }).is_err());
+}
@@ -0,0 +1,33 @@
+//! Runs an `aill gen-corpus` corpus through the conformance checks.
+#![cfg(feature = "ast-serde")]
+
+#[path = "../src/bin/aill/corpus.rs"]
+mod corpus;
+
+use aill::conformance::{load_vectors, run, Expected};
+use aill::AILLDecoder;
+
+use corpus::write_corpus;
+
+#[test]
+fn test_corpus_vectors_pass_conformance() {
+    let dir = std::env::temp_dir().join("aill_gen_corpus_vectors");
+    std::fs::remove_dir_all(&dir).ok();
+    let stats = write_corpus(&dir, 1, 50, 3).unwrap();
+    let bins = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension().is_some_and(|x| x == "bin"))
+        .count();
+    let vectors = load_vectors(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!((stats.messages, bins), (50, 50));
+    assert_eq!(vectors.len(), stats.vectors);
+    assert!(stats.vectors > 25, "only {} of 50 messages became vectors", stats.vectors);
+    assert!(vectors.iter().any(|v| matches!(&v.expected, Expected::Ast(j) if j.to_string().contains("\"Struct\""))));
+
+    let report = run(&vectors, &AILLDecoder::new());
+    let failures: Vec<_> = report.failures().collect();
+    assert!(failures.is_empty(), "{:?}", failures);
+    assert_eq!(report.passed(), 2 * stats.vectors);
+}
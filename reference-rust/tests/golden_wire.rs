@@ -0,0 +1,232 @@
+//! Wire compatibility suite: representative utterances and epochs pinned to
+//! hand-checked bytes. A failure here means the wire format changed, which
+//! breaks every other implementation; update the bytes only together with
+//! the specification.
+
+use aill::codebook::base::temporal;
+use aill::templates::encode_ast;
+use aill::*;
+
+fn decode(wire: &[u8]) -> (MetaHeader, Vec<AstNode>) {
+    match AILLDecoder::new().decode_utterance(wire).unwrap() {
+        AstNode::Utterance { meta, body } => (meta, body),
+        other => panic!("expected an utterance, got {:?}", other),
+    }
+}
+
+fn literal(value_type: &str, value: LiteralValue) -> AstNode {
+    AstNode::Literal { value_type: value_type.into(), value }
+}
+
+const MINIMAL: &[u8] = &[
+    0x00, //                                        START_UTTERANCE
+    0x90, 0x3C, 0x00, //                            CONFIDENCE f16 1.0
+    0x91, 0x03, //                                  PRIORITY 3
+    0x94, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // TIMESTAMP i64 0
+    0x81, //                                        ASSERT
+    0x12, 0xFF, 0xFF, 0xFF, 0xFE, //                INT32 -2
+    0x01, //                                        END_UTTERANCE
+];
+
+const FULL_META: &[u8] = &[
+    0x00,
+    0x90, 0x3A, 0x00, //                            CONFIDENCE f16 0.75
+    0x91, 0x05, //                                  PRIORITY 5
+    0x94, 0x00, 0x06, 0x0A, 0x24, 0x18, 0x1E, 0x40, 0x00, // TIMESTAMP 1_700_000_000_000_000
+    0x93, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, // DEST_AGENT
+    0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+    0x95, 0x00, 0x00, 0x00, 0x2A, //                SEQNUM u32 42
+    0x92, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, // SOURCE_AGENT
+    0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22,
+    0x97, 0x01, 0x02, //                            TOPIC u16
+    0x9C, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // TRACE_ID u64
+    0x9D, 0x41, 0x00, //                            COST f16 2.5
+    0x80, //                                        QUERY
+    0xF0, 0x00, 0x01, //                            ESCAPE_L1 u16
+    0x01,
+];
+
+const TYPES: &[u8] = &[
+    0x00, 0x90, 0x3C, 0x00, 0x91, 0x03, 0x94, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x81,
+    0x23, 0x00, 0x0E, //                            BEGIN_LIST u16 count
+    0x10, 0xFF, //                                  INT8 -1
+    0x11, 0xFF, 0xFE, //                            INT16 -2
+    0x12, 0xFF, 0xFF, 0xFF, 0xFD, //                INT32 -3
+    0x13, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFC, // INT64 -4
+    0x14, 0x05, //                                  UINT8 5
+    0x15, 0x00, 0x06, //                            UINT16 6
+    0x16, 0x00, 0x00, 0x00, 0x07, //                UINT32 7
+    0x17, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, // UINT64 8
+    0x18, 0x3E, 0x00, //                            FLOAT16 1.5
+    0x19, 0xBE, 0x80, 0x00, 0x00, //                FLOAT32 -0.25
+    0x1A, 0x40, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // FLOAT64 3.0
+    0x1B, 0x01, //                                  BOOL true
+    0x1C, 0x00, 0x02, b'h', b'i', //                STRING u16 length + UTF-8
+    0x1D, 0x00, 0x02, 0xAB, 0xCD, //                BYTES u16 length
+    0x24, //                                        END_LIST
+    0x01,
+];
+
+const STRUCT_AND_MAP: &[u8] = &[
+    0x00, 0x90, 0x3C, 0x00, 0x91, 0x03, 0x94, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x81,
+    0x20, //                                        BEGIN_STRUCT
+    0x29, 0x00, 0x01, 0x14, 0x01, //                FIELD_ID u16, UINT8 1
+    0x29, 0x00, 0x02, //                            FIELD_ID u16
+    0x25, 0x00, 0x01, //                            BEGIN_MAP u16 pair count
+    0x1C, 0x00, 0x01, b'k', 0x1F, //                STRING "k" => NULL
+    0x26, //                                        END_MAP
+    0x21, //                                        END_STRUCT
+    0x01,
+];
+
+const WRAPPERS: &[u8] = &[
+    0x00, 0x90, 0x3C, 0x00, 0x91, 0x03, 0x94, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x83, //                                        COMMAND
+    0x7B, //                                        OBSERVED
+    0x62, //                                        FUTURE
+    0x7D, 0x5F, 0xD0, //                            PREDICTED f16 horizon 500 ms
+    0x90, 0x38, 0x00, //                            CONFIDENCE f16 0.5
+    0x9A, 0x00, 0x01, b'x', //                      LABEL u16 length
+    0x2E, 0x00, 0x10, //                            SCHEMA_REF u16
+    0x20, 0x19, 0x3F, 0x80, 0x00, 0x00, 0x21, //    STRUCT { FLOAT32 1.0 }
+    0x01,
+];
+
+const CONTROL_AND_REFS: &[u8] = &[
+    0x00, 0x90, 0x3C, 0x00, 0x91, 0x03, 0x94, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x06, 0x00, 0x03, //                            ACK_EPOCH u16
+    0x07, 0x00, 0x04, 0x02, //                      NACK_EPOCH u16 seq, u8 reason
+    0x98, 0x81, 0x2C, //                            CONTEXT_REF varint 300
+    0x96, 0xDE, 0xAD, 0xBE, 0xEF, //                HASH_REF u32
+    0x01,
+];
+
+/// Plain epoch: seq u16, length u16, payload, CRC-8 over all of it.
+const PLAIN_EPOCH: &[u8] = &[0x00, 0x07, 0x00, 0x02, 0x00, 0x01, 0xF8];
+
+/// Extended epoch: the top bit of the length word announces a flags byte,
+/// whose TIMESTAMP bit announces an i64 timestamp.
+const EXTENDED_EPOCH: &[u8] = &[
+    0xFF, 0xFF, //                                  seq
+    0x80, 0x01, //                                  EXTENDED | length 1
+    0x88, //                                        TIMESTAMP | PRIORITY
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // timestamp 1 us
+    0xAA, //                                        payload
+    0x38, //                                        CRC-8
+];
+
+/// Inline annotations such as those in [`WRAPPERS`] do not survive in the AST.
+const REENCODABLE: [&[u8]; 5] = [MINIMAL, FULL_META, TYPES, STRUCT_AND_MAP, CONTROL_AND_REFS];
+
+#[test]
+fn minimal_utterance() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().int32(-2);
+    assert_eq!(e.end_utterance(), MINIMAL);
+
+    let (meta, body) = decode(MINIMAL);
+    assert_eq!((meta.confidence, meta.priority, meta.timestamp_us), (1.0, 3, 0));
+    assert_eq!(body, vec![AstNode::Pragmatic {
+        act: "ASSERT".into(),
+        expression: Box::new(literal("int32", LiteralValue::Int32(-2))),
+    }]);
+}
+
+#[test]
+fn meta_header_field_order() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance_with(0.75, 5, Some(1_700_000_000_000_000), Some(AgentId::from([0x11; 16])), Some(42))
+        .source_agent(AgentId::from([0x22; 16]))
+        .topic(0x0102)
+        .trace_id(0x0102_0304_0506_0708)
+        .cost(2.5)
+        .query()
+        .l1_ref(0x0001);
+    assert_eq!(e.end_utterance(), FULL_META);
+
+    let (meta, _) = decode(FULL_META);
+    assert_eq!(meta.timestamp_us, 1_700_000_000_000_000);
+    assert_eq!(meta.dest_agent, Some(AgentId::from([0x11; 16])));
+    assert_eq!(meta.source_agent, Some(AgentId::from([0x22; 16])));
+    assert_eq!(meta.seqnum, Some(42));
+}
+
+#[test]
+fn every_literal_type() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().begin_list(14);
+    e.int8(-1).int16(-2).int32(-3).int64(-4);
+    e.uint8(5).uint16(6).uint32(7).uint64(8);
+    e.float16(1.5).float32(-0.25).float64(3.0);
+    e.bool_(true).string("hi").bytes(&[0xAB, 0xCD]).end_list();
+    assert_eq!(e.end_utterance(), TYPES);
+
+    let (_, body) = decode(TYPES);
+    let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!("{:?}", body) };
+    let AstNode::List { count: 14, elements } = &**expression else { panic!("{:?}", expression) };
+    assert_eq!(elements[8], literal("float16", LiteralValue::Float16(1.5)));
+    assert_eq!(elements[12], literal("string", LiteralValue::String("hi".into())));
+}
+
+#[test]
+fn containers_and_field_ids() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().begin_struct();
+    e.field(0x0001).uint8(1);
+    e.field(0x0002).begin_map(1).string("k").null().end_map();
+    e.end_struct();
+    assert_eq!(e.end_utterance(), STRUCT_AND_MAP);
+}
+
+#[test]
+fn wrappers_annotations_and_schema_refs() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().command().observed().temporal(temporal::FUTURE).predicted(500.0);
+    e.confidence(0.5).label("x").schema_ref(0x0010).begin_struct().float32(1.0).end_struct();
+    assert_eq!(e.end_utterance(), WRAPPERS);
+}
+
+#[test]
+fn frame_control_and_references() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().ack_epoch(3).nack_epoch(4, 2).context_ref(300).hash_ref(0xDEAD_BEEF);
+    assert_eq!(e.end_utterance(), CONTROL_AND_REFS);
+
+    let (_, body) = decode(CONTROL_AND_REFS);
+    assert_eq!(body[2], AstNode::ContextRef { sct_index: 300 });
+    assert_eq!(body[3], AstNode::HashRef { hash: 0xDEAD_BEEF });
+}
+
+#[test]
+fn epoch_headers() {
+    let mut epochs = EpochBuilder::new().with_seq(7);
+    epochs.write(&[0x00, 0x01]);
+    assert_eq!(epochs.get_epochs(), vec![PLAIN_EPOCH.to_vec()]);
+
+    let mut epochs = EpochBuilder::new().with_seq(0xFFFF).with_flags(wire::epoch_flags::PRIORITY);
+    epochs.set_timestamp(Some(1));
+    epochs.write(&[0xAA]);
+    assert_eq!(epochs.get_epochs(), vec![EXTENDED_EPOCH.to_vec()]);
+
+    let (epoch, used) = decode_epoch(EXTENDED_EPOCH, 0).unwrap();
+    assert_eq!(used, EXTENDED_EPOCH.len());
+    assert!(epoch.crc_ok);
+    assert_eq!((epoch.seq_num, epoch.flags, epoch.timestamp_us), (0xFFFF, Some(0x88), Some(1)));
+    assert_eq!(epoch.payload, [0xAA]);
+}
+
+#[test]
+fn golden_bytes_survive_decode_and_reencode() {
+    for golden in REENCODABLE {
+        let ast = AILLDecoder::new().decode_utterance(golden).unwrap();
+        let reencoded = encode_ast(&ast).unwrap();
+        assert_eq!(AILLDecoder::new().decode_utterance(&reencoded).unwrap(), ast);
+        // The AST encoder writes optional meta fields in a fixed order of
+        // its own, not the order they were received in
+        if golden != FULL_META {
+            assert_eq!(reencoded, golden, "{}", pretty_print(&ast, 0));
+        }
+    }
+}
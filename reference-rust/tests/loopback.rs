@@ -0,0 +1,41 @@
+use aill::testing::{generate_utterance, LinkConditions, LoopbackHarness};
+use aill::*;
+
+fn decode(wire: &[u8]) -> AstNode {
+    AILLDecoder::new().decode_utterance(wire).unwrap()
+}
+
+#[test]
+fn both_agents_deliver_everything_in_order_over_a_bad_link() {
+    let conditions = LinkConditions::new().with_loss_percent(15).with_latency_us(40_000).with_jitter_us(60_000).with_seed(3);
+    let mut link = LoopbackHarness::new(conditions, 250_000);
+    let from_a: Vec<_> = (0..30).map(|seed| generate_utterance(seed, 2)).collect();
+    let from_b: Vec<_> = (100..110).map(|seed| generate_utterance(seed, 4)).collect();
+    for (i, wire) in from_a.iter().enumerate() {
+        let now = link.now_us();
+        link.a.send(wire, now).unwrap();
+        if let Some(wire) = from_b.get(i) {
+            link.b.send(wire, now).unwrap();
+        }
+        link.step().unwrap();
+    }
+    assert!(link.run_until_idle(60_000_000).unwrap());
+
+    assert_eq!(link.b.delivered(), from_a.iter().map(|w| decode(w)).collect::<Vec<_>>());
+    assert_eq!(link.a.delivered(), from_b.iter().map(|w| decode(w)).collect::<Vec<_>>());
+    assert_eq!((link.a.gaps(), link.b.gaps()), (0, 0));
+    let (data, _) = link.a.transports();
+    assert!(data.sent().1 > 0);
+    assert!(link.a.sender().stats().retransmits > 0);
+}
+
+#[test]
+fn perfect_link_settles_without_retransmits() {
+    let mut link = LoopbackHarness::new(LinkConditions::new().with_latency_us(10_000), 100_000);
+    let wire = generate_utterance(1, 3);
+    link.a.send(&wire, 0).unwrap();
+    assert!(link.run_until_idle(1_000_000).unwrap());
+    assert_eq!(link.b.take_delivered(), vec![decode(&wire)]);
+    assert_eq!(link.a.sender().stats().retransmits, 0);
+    assert!(link.b.delivered().is_empty());
+}
@@ -0,0 +1,82 @@
+//! DecodeObserver hook tests.
+
+use aill::{AILLDecoder, AILLEncoder, AstNode, DecodeObserver, NodeKind};
+
+#[derive(Default)]
+struct Recorder {
+    opcodes: Vec<(usize, u8)>,
+    starts: Vec<(usize, NodeKind)>,
+    ends: Vec<(usize, usize, String)>,
+}
+
+impl DecodeObserver for Recorder {
+    fn on_opcode(&mut self, offset: usize, code: u8) {
+        self.opcodes.push((offset, code));
+    }
+
+    fn on_node_start(&mut self, offset: usize, kind: NodeKind) {
+        self.starts.push((offset, kind));
+    }
+
+    fn on_node_end(&mut self, start: usize, end: usize, node: &AstNode) {
+        let name = match node {
+            AstNode::Utterance { .. } => "utterance",
+            AstNode::Pragmatic { .. } => "pragmatic",
+            AstNode::Struct { .. } => "struct",
+            _ => "literal",
+        };
+        self.ends.push((start, end, name.to_string()));
+    }
+}
+
+fn sample() -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_();
+    e.begin_struct().field(0x0001).uint8(7).end_struct();
+    e.end_utterance()
+}
+
+#[test]
+fn observer_sees_every_node_with_byte_ranges() {
+    let data = sample();
+    let mut rec = Recorder::default();
+    let ast = AILLDecoder::new()
+        .decode_utterance_with_observer(&data, &mut rec)
+        .unwrap();
+    assert_eq!(ast, AILLDecoder::new().decode_utterance(&data).unwrap());
+
+    // Utterance spans the whole buffer and closes last.
+    assert_eq!(rec.starts[0], (0, NodeKind::Utterance));
+    assert_eq!(rec.ends.last().unwrap(), &(0, data.len(), "utterance".to_string()));
+
+    // Children close before their parents and lie within them.
+    let names: Vec<&str> = rec.ends.iter().map(|(_, _, n)| n.as_str()).collect();
+    assert_eq!(names, ["literal", "struct", "pragmatic", "utterance"]);
+    for (start, end, _) in &rec.ends {
+        assert!(start < end && *end <= data.len());
+    }
+
+    // The literal's range covers its type marker and one payload byte.
+    let (ls, le, _) = &rec.ends[0];
+    assert_eq!(le - ls, 2);
+    assert_eq!(&data[*ls..*le], &[0x14, 7]);
+}
+
+#[test]
+fn observer_opcodes_match_wire_bytes() {
+    let data = sample();
+    let mut rec = Recorder::default();
+    AILLDecoder::new()
+        .decode_utterance_with_observer(&data, &mut rec)
+        .unwrap();
+
+    assert_eq!(rec.opcodes.first(), Some(&(0, 0x00)));
+    assert_eq!(rec.opcodes.last(), Some(&(data.len() - 1, 0x01)));
+    for (offset, code) in &rec.opcodes {
+        assert_eq!(data[*offset], *code);
+    }
+    let codes: Vec<u8> = rec.opcodes.iter().map(|(_, c)| *c).collect();
+    assert!(codes.contains(&0x81)); // ASSERT
+    assert!(codes.contains(&0x20)); // BEGIN_STRUCT
+    assert!(codes.contains(&0x21)); // END_STRUCT
+}
@@ -0,0 +1,81 @@
+//! Drives `aill pipe` in both directions without stdin/stdout.
+#![cfg(feature = "ast-serde")]
+
+#[path = "../src/bin/aill/pipe.rs"]
+mod pipe;
+
+use std::io::BufReader;
+
+use aill::testing::generate_corpus;
+use aill::AILLDecoder;
+
+use pipe::{decode, encode, Framing, PipeStats};
+
+/// The first `count` seeded utterances as the JSON lines `decode` writes.
+fn json_lines(count: usize) -> String {
+    let decoder = AILLDecoder::new();
+    generate_corpus(11, count, 3)
+        .iter()
+        .map(|wire| serde_json::to_string(&decoder.decode_utterance(wire).unwrap()).unwrap() + "\n")
+        .collect()
+}
+
+#[test]
+fn test_json_survives_encode_and_decode() {
+    let json = json_lines(80);
+    assert!(json.contains(r#""node_type":"Struct""#));
+    for framing in [Framing::Raw, Framing::Hex] {
+        let (mut epochs, mut errors) = (Vec::new(), Vec::new());
+        let stats = encode(json.as_bytes(), &mut epochs, &mut errors, framing).unwrap();
+        // The AST keeps only the mnemonic of an annotation opcode and not
+        // the UUID of a REPORTED source, so the encoder refuses those; every
+        // other line must get through
+        let skipped: Vec<usize> = String::from_utf8(errors)
+            .unwrap()
+            .lines()
+            .map(|e| {
+                assert!(e.contains("cannot be re-encoded") || e.contains("is not retained"), "{}", e);
+                e["line ".len()..e.find(':').unwrap()].parse().unwrap()
+            })
+            .collect();
+        assert_eq!(stats, PipeStats { messages: 80 - skipped.len(), errors: skipped.len() });
+        let expected: String = json
+            .lines()
+            .enumerate()
+            .filter(|(i, _)| !skipped.contains(&(i + 1)))
+            .map(|(_, l)| format!("{}\n", l))
+            .collect();
+        assert!(expected.contains(r#""node_type":"Struct""#));
+
+        // Raw epochs arrive a few bytes at a time, as from a pipe
+        let mut out = Vec::new();
+        let input = BufReader::with_capacity(3, epochs.as_slice());
+        let stats = decode(input, &mut out, std::io::sink(), framing).unwrap();
+        assert_eq!(stats, PipeStats { messages: 80 - skipped.len(), errors: 0 });
+        assert_eq!(String::from_utf8(out).unwrap(), expected, "{:?}", framing);
+    }
+}
+
+#[test]
+fn test_bad_input_is_reported_and_skipped() {
+    let json = json_lines(3);
+    let mut hex = Vec::new();
+    encode(json.as_bytes(), &mut hex, std::io::sink(), Framing::Hex).unwrap();
+    let mut lines: Vec<String> = String::from_utf8(hex).unwrap().lines().map(String::from).collect();
+    // Flip the CRC of the second epoch and add a line that is not hex
+    let last = lines[1].pop().unwrap();
+    lines[1].push(if last == '0' { '1' } else { '0' });
+    lines.insert(2, "not an epoch".into());
+
+    let (mut out, mut errors) = (Vec::new(), Vec::new());
+    let stats = decode(lines.join("\n").as_bytes(), &mut out, &mut errors, Framing::Hex).unwrap();
+    assert_eq!(stats, PipeStats { messages: 2, errors: 2 });
+    let expected: Vec<&str> = json.lines().enumerate().filter(|&(i, _)| i != 1).map(|(_, l)| l).collect();
+    assert_eq!(String::from_utf8(out).unwrap().lines().collect::<Vec<_>>(), expected);
+    let errors = String::from_utf8(errors).unwrap();
+    assert!(errors.contains("epoch 1: CRC mismatch") && errors.contains("not hex"), "{}", errors);
+
+    let mut epochs = Vec::new();
+    let stats = encode("{\"bogus\": 1}\n\n".as_bytes(), &mut epochs, std::io::sink(), Framing::Raw).unwrap();
+    assert_eq!((stats, epochs.len()), (PipeStats { messages: 0, errors: 1 }, 0));
+}
@@ -0,0 +1,101 @@
+use aill::*;
+
+fn decode(wire: &[u8]) -> AstNode {
+    AILLDecoder::new().decode_utterance(wire).unwrap()
+}
+
+#[test]
+fn enum_value_after_domain_ref_is_named() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().warn().l1_ref(0x0000).uint8(3);
+    let utt = decode(&e.end_utterance());
+
+    let out = pretty_print_with_domain(&utt, 0, &SAFETY1);
+    assert!(out.contains("REF(L1: SAFETY-1/EMERGENCY_LEVEL)"), "{}", out);
+    assert!(out.contains("EMERGENCY_LEVEL=3 (danger)"), "{}", out);
+
+    // Without a domain the output is unchanged
+    let plain = pretty_print(&utt, 0);
+    assert!(plain.contains("REF(L1: DOMAIN_0x0000)"));
+    assert!(plain.contains("uint8: 3"));
+}
+
+#[test]
+fn enum_struct_fields_are_named() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_();
+    e.begin_struct().field(0x0000).uint8(4).field(0x0001).float32(0.5).end_struct();
+    let utt = decode(&e.end_utterance());
+
+    let out = pretty_print_with_domain(&utt, 0, &MANIP1);
+    assert!(out.contains("field_0x0000 (GRIPPER_STATE):"), "{}", out);
+    assert!(out.contains("GRIPPER_STATE=4 (holding)"), "{}", out);
+    assert!(out.contains("float32: 0.5"), "{}", out);
+}
+
+#[test]
+fn out_of_range_enum_value_falls_back_to_number() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().l1_ref(0x0000).uint8(42);
+    let out = pretty_print_with_domain(&decode(&e.end_utterance()), 0, &SAFETY1);
+    assert!(out.contains("uint8: 42"), "{}", out);
+}
+
+#[test]
+fn bound_escape_levels_resolve_against_their_registry() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().warn().l1_ref(0x0000).uint8(3).l2_ref(0x0000);
+    let config = DecoderConfig::new().bind_escape(1, SAFETY1.registry_id).bind_escape(2, NAV1.registry_id);
+    let utt = AILLDecoder::with_config(config).decode_utterance(&e.end_utterance()).unwrap();
+
+    let out = pretty_print(&utt, 0);
+    assert!(out.contains("REF(L1: SAFETY-1/EMERGENCY_LEVEL)"), "{}", out);
+    assert!(out.contains("EMERGENCY_LEVEL=3 (danger)"), "{}", out);
+    assert!(out.contains("REF(L2: NAV-1/POSITION_3D)"), "{}", out);
+
+    // The binding wins over the codebook passed to the formatter
+    assert!(pretty_print_with_domain(&utt, 0, &MANIP1).contains("SAFETY-1/EMERGENCY_LEVEL"));
+}
+
+#[test]
+fn codebook_ref_binds_levels_for_the_rest_of_the_utterance() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().warn().l2_ref(0x0000).codebook_ref(2, SAFETY1.registry_id).l2_ref(0x0000).uint8(3);
+    let wire = e.end_utterance();
+    let mut decoder = AILLDecoder::new();
+    let utt = decoder.decode_utterance(&wire).unwrap();
+    assert_eq!(templates::encode_ast(&utt).unwrap(), wire);
+
+    let out = pretty_print(&utt, 0);
+    assert!(out.contains("REF(L2: DOMAIN_0x0000)"), "{}", out);
+    assert!(out.contains("CODEBOOK_REF(L2 = SAFETY-1)"), "{}", out);
+    assert!(out.contains("REF(L2: SAFETY-1/EMERGENCY_LEVEL)"), "{}", out);
+    assert!(out.contains("EMERGENCY_LEVEL=3 (danger)"), "{}", out);
+
+    // Bindings only outlive the utterance once applied to the config
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().l2_ref(0x0001);
+    let next = e.end_utterance();
+    assert!(pretty_print(&decoder.decode_utterance(&next).unwrap(), 0).contains("DOMAIN_0x0001"));
+    decoder.config_mut().apply_codebook_refs(&utt);
+    assert_eq!(decoder.config().escape_binding(2), Some(SAFETY1.registry_id));
+    assert!(pretty_print(&decoder.decode_utterance(&next).unwrap(), 0).contains("SAFETY-1/"));
+}
+
+#[test]
+fn measurement_prints_with_its_error_bar() {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().l1_ref(0x0010).float32_with_sigma(1.52, 0.03);
+    let wire = e.end_utterance();
+    let utt = decode(&wire);
+
+    let out = pretty_print(&utt, 0);
+    assert!(out.contains("measurement: 1.52 ± 0.03"), "{}", out);
+    assert_eq!(templates::encode_ast(&utt).unwrap(), wire);
+
+    // Any other payload under the well-known id stays a SCHEMA_REF
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().schema_ref(schema::MEASUREMENT_SCHEMA_ID);
+    e.begin_struct().float32(1.0).float64(0.1).end_struct();
+    assert!(pretty_print(&decode(&e.end_utterance()), 0).contains("SCHEMA_REF(0x0001)"));
+}
@@ -0,0 +1,51 @@
+//! Drives the `aill repl` command interpreter without a terminal.
+
+#[path = "../src/bin/aill/repl.rs"]
+mod repl;
+
+use aill::codebook::base::modal;
+use aill::AILLEncoder;
+
+use repl::{Output, Repl};
+
+fn run(repl: &mut Repl, lines: &[&str]) {
+    for line in lines {
+        assert_eq!(repl.execute(line), Ok(Output::None), "{}", line);
+    }
+}
+
+#[test]
+fn test_lines_build_the_same_wire_as_the_encoder() {
+    let mut repl = Repl::new();
+    run(&mut repl, &["new 0.5 6", "propose", "struct", "field 0x0001", "f32 3.5", "field 2", "str two words"]);
+    assert!(repl.execute("hex").unwrap_err().contains("still open"));
+    run(&mut repl, &["end", "certain", "l1 0x0048", "u8 9"]);
+
+    let mut e = AILLEncoder::new();
+    e.start_utterance_with(0.5, 6, None, None, None).propose().begin_struct();
+    e.field(1).float32(3.5).field(2).string("two words").end_struct();
+    e.modality(modal::CERTAIN).l1_ref(0x0048).uint8(9);
+    let hex: String = e.end_utterance().iter().map(|b| format!("{:02X}", b)).collect();
+    assert_eq!(repl.execute("hex"), Ok(Output::Text(hex)));
+
+    let Ok(Output::Text(tree)) = repl.execute("show") else { panic!() };
+    assert!(tree.contains("PROPOSE") && tree.contains("two words"), "{}", tree);
+
+    run(&mut repl, &["undo", "u16 9"]);
+    let Ok(Output::Play(wire)) = repl.execute("play") else { panic!() };
+    assert_eq!(&wire[wire.len() - 4..], &[0x15, 0x00, 0x09, 0x01]);
+    assert!(repl.execute("frobnicate").is_err());
+    assert!(repl.execute("end").is_err());
+    assert_eq!(repl.execute("quit"), Ok(Output::Quit));
+}
+
+#[test]
+fn test_completion_over_commands_and_mnemonics() {
+    let repl = Repl::new();
+    assert_eq!(repl.complete("prop"), ["proportion", "propose"]);
+    assert_eq!(repl.complete("F1"), ["f16"]);
+    assert!(repl.complete("").contains(&"assert".to_string()));
+    assert_eq!(repl.complete("bool t"), ["true"]);
+    assert!(repl.complete("u8 ").is_empty());
+    assert_eq!(repl.complete("asert"), ["assert"]);
+}
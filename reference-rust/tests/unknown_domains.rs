@@ -0,0 +1,61 @@
+use aill::codebook::base::esc;
+use aill::codebook::dynamic::DynamicCodebook;
+use aill::codebook::DIAG1;
+use aill::*;
+
+/// An assertion of `code` on L1, with L1 bound to `registry` if given.
+fn l1_assertion(registry: Option<u8>, code: u16) -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance();
+    if let Some(registry) = registry {
+        e.codebook_ref(1, registry);
+    }
+    e.assert_().l1_ref(code).uint8(1);
+    e.end_utterance()
+}
+
+#[test]
+fn unknown_codes_decode_by_default() {
+    let wire = l1_assertion(Some(DIAG1.registry_id), 0xFFFF);
+    let (_, warnings) = AILLDecoder::new().decode_utterance_with_warnings(&wire).unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn strict_decoders_refuse_unknown_codes() {
+    let known = DIAG1.entries()[0].code;
+    let strict = AILLDecoder::with_config(DecoderConfig::new().with_unknown_domains(UnknownDomainPolicy::Error));
+    assert!(strict.decode_utterance(&l1_assertion(Some(DIAG1.registry_id), known)).is_ok());
+
+    for (registry, code) in [(Some(DIAG1.registry_id), 0xFFFF), (Some(0xEE), known), (None, known)] {
+        let wire = l1_assertion(registry, code);
+        let err = strict.decode_utterance(&wire).unwrap_err();
+        assert!(
+            matches!(err, AILLError::UnknownDomainRef { level: 1, domain_code, registry: r, .. } if domain_code == code && r == registry),
+            "{:?}",
+            err
+        );
+        assert_eq!(wire[err.offset().unwrap()], esc::ESCAPE_L1);
+    }
+}
+
+#[test]
+fn warnings_are_collected_and_custom_codebooks_resolve() {
+    let config = DecoderConfig::new().with_unknown_domains(UnknownDomainPolicy::Warn);
+    let wire = l1_assertion(Some(0xEE), 0x0042);
+    let (ast, warnings) = AILLDecoder::with_config(config.clone()).decode_utterance_with_warnings(&wire).unwrap();
+    assert_eq!(ast, AILLDecoder::new().decode_utterance(&wire).unwrap());
+    assert_eq!(
+        warnings,
+        [UnknownDomainRef { offset: wire.len() - 6, level: 1, domain_code: 0x0042, registry: Some(0xEE) }]
+    );
+
+    let custom = DynamicCodebook::new(0xEE).with_entry(0x0042, "UINT8", "gripper_mode");
+    let decoder = AILLDecoder::with_config(config.with_codebook(custom));
+    assert!(decoder.decode_utterance_with_warnings(&wire).unwrap().1.is_empty());
+    // A custom codebook replaces the built-in one with its id
+    let custom = DynamicCodebook::new(DIAG1.registry_id);
+    let strict = DecoderConfig::new().with_unknown_domains(UnknownDomainPolicy::Error).with_codebook(custom);
+    let wire = l1_assertion(Some(DIAG1.registry_id), DIAG1.entries()[0].code);
+    assert!(AILLDecoder::with_config(strict).decode_utterance(&wire).is_err());
+}
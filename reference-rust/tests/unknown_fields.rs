@@ -0,0 +1,48 @@
+use aill::*;
+use aill::templates::encode_ast;
+
+/// A status report from a newer peer: fields 0 and 1 are known to every
+/// receiver, 0x0040 was added later.
+fn newer_report() -> Vec<u8> {
+    let mut e = AILLEncoder::new();
+    e.start_utterance().assert_().begin_struct();
+    e.field(0x0000).uint8(2).field(0x0001).float32(0.75);
+    e.field(0x0040).begin_struct().field(0x0000).string("lidar").field(0x0001).list_of_float32(&[1.0, 2.0]).end_struct();
+    e.end_struct();
+    e.end_utterance()
+}
+
+fn fields(ast: &AstNode) -> &std::collections::BTreeMap<u16, AstNode> {
+    let AstNode::Utterance { body, .. } = ast else { panic!() };
+    let AstNode::Pragmatic { expression, .. } = &body[0] else { panic!() };
+    let AstNode::Struct { fields } = expression.as_ref() else { panic!() };
+    fields
+}
+
+#[test]
+fn unknown_fields_survive_a_round_trip_as_raw_bytes() {
+    let wire = newer_report();
+    let decoder = AILLDecoder::with_config(DecoderConfig::new().with_known_fields([0x0000, 0x0001]));
+    let ast = decoder.decode_utterance(&wire).unwrap();
+
+    let fields = fields(&ast);
+    assert!(matches!(fields[&0x0001], AstNode::Literal { .. }));
+    let AstNode::Raw { bytes } = &fields[&0x0040] else { panic!("{:?}", fields[&0x0040]) };
+    assert_eq!(bytes.first(), Some(&codebook::base::st::BEGIN_STRUCT));
+    assert_eq!(encode_ast(&ast).unwrap(), wire);
+    assert!(pretty_print(&ast, 0).contains("RAW["));
+
+    // Without a known-field set everything is decoded
+    let full = AILLDecoder::new().decode_utterance(&wire).unwrap();
+    assert!(matches!(self::fields(&full)[&0x0040], AstNode::Struct { .. }));
+}
+
+#[test]
+fn event_stream_passes_unknown_fields_through() {
+    let wire = newer_report();
+    let decoder = AILLDecoder::with_config(DecoderConfig::new().with_known_fields([0x0000, 0x0001]));
+    let events: Vec<WireEvent> = decoder.decode_events(&wire).collect::<Result<_, _>>().unwrap();
+    let at = events.iter().position(|e| *e == WireEvent::Field(0x0040)).unwrap();
+    assert!(matches!(events[at + 1], WireEvent::Raw(_)));
+    assert_eq!(events[at + 2..], [WireEvent::EndStruct, WireEvent::EndUtterance]);
+}